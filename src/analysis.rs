@@ -0,0 +1,922 @@
+//! Static analysis of statements in pending revisions, picking out the
+//! operations, tables, and locks involved well enough to warn about them,
+//! without needing a full SQL parser.
+use crate::statements::{strip_comments, Statement};
+
+/// Operations that lock a table for as long as they take to run, which
+/// grows with the size of the table.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TableOperation {
+    AlterTable,
+    /// Building an index without `CONCURRENTLY`, which blocks writes
+    CreateIndex,
+}
+
+impl TableOperation {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::AlterTable => "ALTER TABLE",
+            Self::CreateIndex => "CREATE INDEX",
+        }
+    }
+
+    /// How the operation could avoid locking the table for long.
+    pub fn suggestion(&self) -> &'static str {
+        match self {
+            Self::AlterTable => {
+                "consider adding constraints as `NOT VALID` and validating separately, \
+                 and backfilling data in batches rather than rewriting the table"
+            }
+            Self::CreateIndex => "consider `CREATE INDEX CONCURRENTLY` in a revision without a transaction",
+        }
+    }
+
+    /// The operation and table, as written, if the statement is one that
+    /// locks a table for its duration.
+    pub fn of(statement: &Statement) -> Option<(Self, String)> {
+        let parsed = Tokens::new(&statement.sql);
+
+        match (parsed.keyword(0), parsed.keyword(1), parsed.keyword(2)) {
+            (Some("alter"), Some("table"), _) => Some((Self::AlterTable, parsed.name(2)?)),
+            (Some("create"), Some("index"), _) | (Some("create"), Some("unique"), Some("index")) => {
+                if parsed.position("concurrently").is_some() {
+                    return None;
+                }
+
+                Some((Self::CreateIndex, parsed.name(parsed.position("on")? + 1)?))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// What a destructive statement destroys data in, as written in it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Destroyed {
+    Table(String),
+    /// A schema dropped with `CASCADE`, along with all of its tables
+    Schema(String),
+}
+
+impl Destroyed {
+    /// The tables and schemas whose data the statement destroys, for those
+    /// that `Statement::destructive_operation` describes.
+    pub fn all_for(statement: &Statement) -> Vec<Self> {
+        if statement.destructive_operation().is_none() {
+            return vec![];
+        }
+
+        let parsed = Tokens::new(&statement.sql);
+        let tables = |names: Vec<String>| names.into_iter().map(Self::Table).collect();
+
+        match (parsed.keyword(0), parsed.keyword(1)) {
+            (Some("drop"), Some("table")) => tables(parsed.names(2)),
+            (Some("drop"), Some("schema")) => parsed.names(2).into_iter().map(Self::Schema).collect(),
+            (Some("truncate"), _) => tables(parsed.names(parsed.skip(1, &["table"]))),
+            (Some("delete"), Some("from")) => tables(parsed.name(2).into_iter().collect()),
+            (Some("update"), _) => tables(parsed.name(1).into_iter().collect()),
+            _ => vec![],
+        }
+    }
+}
+
+/// The table an `UPDATE` or `DELETE` changes rows in, as written, along with
+/// the alias it's given, if any.
+pub fn changed_table(statement: &Statement) -> Option<(String, Option<String>)> {
+    let parsed = Tokens::new(&statement.sql);
+
+    let i = match (parsed.keyword(0), parsed.keyword(1)) {
+        (Some("update"), _) => parsed.skip(1, &["only"]),
+        (Some("delete"), Some("from")) => parsed.skip(2, &["only"]),
+        _ => return None,
+    };
+    let table = parsed.tokens.get(i)?.trim_end_matches('*').to_string();
+
+    let alias = match parsed.keyword(i + 1) {
+        Some("as") => parsed.tokens.get(i + 2).cloned(),
+        Some("set" | "using" | "where" | "returning") | None => None,
+        Some(_) => parsed.tokens.get(i + 1).cloned(),
+    };
+
+    Some((table, alias))
+}
+
+/// The table an `INSERT`, `UPDATE`, `DELETE`, or `MERGE` changes rows in, as
+/// written.
+pub fn modified_table(statement: &Statement) -> Option<String> {
+    let parsed = Tokens::new(&statement.sql);
+
+    match (parsed.keyword(0), parsed.keyword(1)) {
+        (Some("insert" | "merge"), Some("into")) => parsed.name(parsed.skip(2, &["only"])),
+        _ => changed_table(statement).map(|(table, _)| table),
+    }
+}
+
+/// The table a `CREATE TABLE` statement creates, as written, unless it's
+/// a partition of another, which has its parent's constraints.
+pub fn created_table(statement: &Statement) -> Option<String> {
+    let parsed = Tokens::new(&statement.sql);
+
+    if parsed.keyword(0) != Some("create") {
+        return None;
+    }
+
+    let i = parsed.skip(1, &["global", "local", "temp", "temporary", "unlogged"]);
+
+    if parsed.keyword(i) != Some("table") {
+        return None;
+    }
+
+    let partition = parsed.position("partition").is_some_and(|p| parsed.keyword(p + 1) == Some("of"));
+
+    parsed.name(i + 1).filter(|_| !partition)
+}
+
+/// The table and the name of the constraint that an `ALTER TABLE` adding a
+/// single named foreign key or check constraint adds, as written, or why the
+/// statement isn't one.
+pub fn added_constraint(statement: &Statement) -> Result<(String, String), String> {
+    let parsed = Tokens::new(&statement.sql);
+
+    let table = match (parsed.keyword(0), parsed.keyword(1)) {
+        (Some("alter"), Some("table")) => parsed.name(2),
+        _ => None,
+    };
+    let adds: Vec<_> = (0..parsed.keywords.len()).filter(|&i| parsed.keyword(i) == Some("add")).collect();
+
+    let (table, add) = match (table, adds.as_slice()) {
+        (Some(table), [add]) => (table, *add),
+        _ => return Err("expected an `ALTER TABLE` adding a single constraint".to_string()),
+    };
+
+    if parsed.keyword(add + 1) != Some("constraint") {
+        return Err("the constraint must be named to validate it by name".to_string());
+    }
+    if !matches!(parsed.keyword(add + 3), Some("foreign" | "check")) {
+        return Err("only foreign key and check constraints can be added as `NOT VALID`".to_string());
+    }
+    if parsed.position("valid").is_some_and(|i| i > 0 && parsed.keyword(i - 1) == Some("not")) {
+        return Err("the constraint is already added as `NOT VALID`".to_string());
+    }
+
+    let constraint = parsed.tokens.get(add + 2).cloned().ok_or("expected the constraint's name")?;
+
+    Ok((table, constraint))
+}
+
+/// What a statement does to an object in the schema.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Change {
+    Creates,
+    Alters,
+    Drops,
+}
+
+impl Change {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Creates => "creates",
+            Self::Alters => "alters",
+            Self::Drops => "drops",
+        }
+    }
+}
+
+/// Kinds of object whose creation, alteration, and removal are summarized,
+/// leaving out statements like `ALTER DEFAULT PRIVILEGES`.
+const IMPACTED_OBJECTS: &[&str] = &[
+    "domain",
+    "extension",
+    "function",
+    "index",
+    "policy",
+    "procedure",
+    "schema",
+    "sequence",
+    "table",
+    "trigger",
+    "type",
+    "view",
+];
+
+/// An object that a revision creates, alters, or drops, as written, and how
+/// many of its statements do so.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Impact {
+    pub change: Change,
+    /// The kind of object, eg. `materialized view`
+    pub object: String,
+    pub name: String,
+    pub statements: usize,
+}
+
+impl Impact {
+    /// The objects the statements create, alter, or drop, in the order each
+    /// is first changed in each way.
+    pub fn all_for(statements: &[Statement]) -> Vec<Self> {
+        let mut impacts: Vec<Self> = vec![];
+
+        for (change, object, name) in statements.iter().flat_map(changed_objects) {
+            let same = |impact: &&mut Self| {
+                impact.change == change && impact.object == object && same_name(&impact.name, &name)
+            };
+
+            match impacts.iter_mut().find(same) {
+                Some(impact) => impact.statements += 1,
+                None => impacts.push(Self { change, object, name, statements: 1 }),
+            }
+        }
+
+        impacts
+    }
+
+    /// A summary of what the statements change, eg. `creates table orders,
+    /// alters table users (2 statements)`, if they change anything.
+    pub fn summary(statements: &[Statement]) -> Option<String> {
+        let impacts = Self::all_for(statements);
+
+        if impacts.is_empty() {
+            return None;
+        }
+
+        Some(impacts.iter().map(Self::describe).collect::<Vec<_>>().join(", "))
+    }
+
+    fn describe(&self) -> String {
+        let described = format!("{} {} {}", self.change.as_str(), self.object, self.name);
+
+        match self.statements {
+            1 => described,
+            n => format!("{} ({} statements)", described, n),
+        }
+    }
+}
+
+/// The kind and name of each object the statement creates, alters, or drops.
+/// Indexes created without a name are described by their table instead.
+fn changed_objects(statement: &Statement) -> Vec<(Change, String, String)> {
+    let parsed = Tokens::new(&statement.sql);
+
+    let (change, i) = match parsed.keyword(0) {
+        Some("create") => (
+            Change::Creates,
+            parsed.skip(1, &["or", "replace", "unique", "global", "local", "temp", "temporary", "unlogged", "recursive"]),
+        ),
+        Some("alter") => (Change::Alters, 1),
+        Some("drop") => (Change::Drops, 1),
+        _ => return vec![],
+    };
+
+    let (object, i) = match (parsed.keyword(i), parsed.keyword(i + 1)) {
+        (Some(kind @ ("materialized" | "foreign")), Some(object)) if IMPACTED_OBJECTS.contains(&object) => {
+            (format!("{} {}", kind, object), i + 2)
+        }
+        (Some(object), _) if IMPACTED_OBJECTS.contains(&object) => (object.to_string(), i + 1),
+        _ => return vec![],
+    };
+
+    let i = parsed.skip(i, &["concurrently"]);
+
+    let names = match (change, parsed.keyword(i)) {
+        (Change::Creates, Some("on")) => parsed.name(i + 1).map(|table| format!("on {}", table)).into_iter().collect(),
+        (Change::Drops, _) => parsed.names(i),
+        _ => parsed.name(i).into_iter().collect(),
+    };
+
+    names.into_iter().map(|name| (change, object.clone(), name)).collect()
+}
+
+/// Whether the names are the same, where unquoted names differ only in case.
+fn same_name(a: &str, b: &str) -> bool {
+    let folded = |name: &str| if name.contains('"') { name.to_string() } else { name.to_lowercase() };
+
+    folded(a) == folded(b)
+}
+
+/// Estimates of a table's size from the database's statistics.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TableSize {
+    /// The estimated number of rows, if the table has ever been analyzed
+    pub rows: Option<i64>,
+    /// The size on disk including indexes and TOAST
+    pub bytes: i64,
+}
+
+/// A statement in a pending revision that takes a lock on a large table.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LargeTable {
+    pub line: usize,
+    pub operation: TableOperation,
+    pub table: String,
+    pub size: TableSize,
+}
+
+impl LargeTable {
+    pub fn message(&self) -> String {
+        let rows = self.size.rows.map_or_else(String::new, |rows| format!("~{} rows, ", rows));
+
+        format!(
+            "Line {}: {} on `{}` ({}{}) locks it for its duration - {}",
+            self.line,
+            self.operation.as_str(),
+            self.table,
+            rows,
+            format_bytes(self.size.bytes),
+            self.operation.suggestion(),
+        )
+    }
+}
+
+/// An object that a statement drops, as written.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Dropped {
+    /// The kind of object, eg. `materialized view`, or `column` or
+    /// `constraint` for those dropped from a table by `ALTER TABLE`
+    pub object: String,
+    /// The name of the object, or of the table a column or constraint is
+    /// dropped from
+    pub name: String,
+    /// The name of the column or constraint dropped from the table
+    pub part: Option<String>,
+    /// Whether it's dropped with `CASCADE`, along with whatever depends on it
+    pub cascade: bool,
+}
+
+impl Dropped {
+    /// The objects the statement drops, including columns and constraints
+    /// dropped from tables.
+    pub fn all_for(statement: &Statement) -> Vec<Self> {
+        let parsed = Tokens::new(&statement.sql);
+        let cascade = parsed.position("cascade").is_some();
+
+        if let (Some("alter"), Some("table"), Some(table)) = (parsed.keyword(0), parsed.keyword(1), parsed.name(2)) {
+            return (0..parsed.keywords.len())
+                .filter(|&i| parsed.keyword(i) == Some("drop"))
+                .filter_map(|i| {
+                    let (object, i) = match parsed.keyword(i + 1)? {
+                        "constraint" => ("constraint", i + 2),
+                        "column" => ("column", i + 2),
+                        // Dropped from a column rather than the table
+                        "default" | "not" | "expression" | "identity" => return None,
+                        _ => ("column", i + 1),
+                    };
+
+                    Some(Self {
+                        object: object.to_string(),
+                        name: table.clone(),
+                        part: Some(parsed.name(i)?),
+                        cascade,
+                    })
+                })
+                .collect();
+        }
+
+        changed_objects(statement)
+            .into_iter()
+            .filter(|(change, _, _)| *change == Change::Drops)
+            .map(|(_, object, name)| Self { object, name, part: None, cascade })
+            .collect()
+    }
+
+    pub fn describe(&self) -> String {
+        match &self.part {
+            Some(part) => format!("{} `{}` of `{}`", self.object, part, self.name),
+            None => format!("{} `{}`", self.object, self.name),
+        }
+    }
+}
+
+/// An object in the database that depends on another, by its catalog and
+/// `oid`, as described by PostgreSQL, eg. `view public.active_users`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Dependent {
+    pub key: String,
+    pub description: String,
+}
+
+/// A statement in a pending revision that drops an object others depend on,
+/// which either fails or, with `CASCADE`, drops them too.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DroppedDependents {
+    pub line: usize,
+    pub dropped: Dropped,
+    /// Descriptions of the objects that depend on it, directly or otherwise
+    pub dependents: Vec<String>,
+}
+
+impl DroppedDependents {
+    pub fn message(&self) -> String {
+        if self.dropped.cascade {
+            format!(
+                "Line {}: dropping {} with CASCADE also drops {}",
+                self.line,
+                self.dropped.describe(),
+                self.dependents.join(", "),
+            )
+        } else {
+            format!(
+                "Line {}: dropping {} fails without CASCADE, as {} depend(s) on it",
+                self.line,
+                self.dropped.describe(),
+                self.dependents.join(", "),
+            )
+        }
+    }
+}
+
+fn format_bytes(bytes: i64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+
+    let mut size = bytes as f64;
+    let mut unit = "B";
+
+    for next in UNITS {
+        if size < 1024.0 {
+            break;
+        }
+
+        size /= 1024.0;
+        unit = next;
+    }
+
+    if unit == "B" {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", size, unit)
+    }
+}
+
+/// Table-level lock modes, from weakest to strongest.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LockMode {
+    AccessShare,
+    RowShare,
+    RowExclusive,
+    ShareUpdateExclusive,
+    Share,
+    ShareRowExclusive,
+    Exclusive,
+    AccessExclusive,
+}
+
+impl LockMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::AccessShare => "ACCESS SHARE",
+            Self::RowShare => "ROW SHARE",
+            Self::RowExclusive => "ROW EXCLUSIVE",
+            Self::ShareUpdateExclusive => "SHARE UPDATE EXCLUSIVE",
+            Self::Share => "SHARE",
+            Self::ShareRowExclusive => "SHARE ROW EXCLUSIVE",
+            Self::Exclusive => "EXCLUSIVE",
+            Self::AccessExclusive => "ACCESS EXCLUSIVE",
+        }
+    }
+
+    /// What the lock keeps other sessions from doing to the relation while held.
+    pub fn blocks(&self) -> &'static str {
+        match self {
+            Self::AccessExclusive => "reads and writes",
+            Self::Exclusive | Self::ShareRowExclusive | Self::Share => "writes",
+            Self::ShareUpdateExclusive | Self::RowExclusive => "schema changes",
+            Self::RowShare | Self::AccessShare => "exclusive locks",
+        }
+    }
+
+    fn from_keywords(keywords: &[String]) -> Option<Self> {
+        let mode = keywords.join(" ");
+
+        [
+            Self::AccessShare,
+            Self::RowShare,
+            Self::RowExclusive,
+            Self::ShareUpdateExclusive,
+            Self::Share,
+            Self::ShareRowExclusive,
+            Self::Exclusive,
+            Self::AccessExclusive,
+        ]
+        .into_iter()
+        .find(|m| m.as_str().eq_ignore_ascii_case(&mode))
+    }
+}
+
+/// A lock that a statement takes on a relation, as written in the statement.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Lock {
+    pub mode: LockMode,
+    pub relation: String,
+}
+
+impl Lock {
+    fn on(names: impl IntoIterator<Item = String>, mode: LockMode) -> Vec<Self> {
+        names.into_iter().map(|relation| Self { mode, relation }).collect()
+    }
+
+    /// The locks the statement takes on relations it names, as documented for
+    /// each command. This covers the usual DDL and DML found in revisions on a
+    /// best-effort basis, so commands it doesn't recognize take no locks here.
+    pub fn all_for(statement: &Statement) -> Vec<Self> {
+        use LockMode::*;
+
+        let parsed = Tokens::new(&statement.sql);
+        let keyword = |i| parsed.keyword(i);
+        let concurrently = parsed.position("concurrently").is_some();
+
+        let mut locks = match (keyword(0), keyword(1)) {
+            (Some("insert"), Some("into")) | (Some("merge"), Some("into")) => Self::on(parsed.names(2), RowExclusive),
+            (Some("update"), _) => Self::on(parsed.name(1), RowExclusive),
+            (Some("delete"), Some("from")) => Self::on(parsed.name(2), RowExclusive),
+            (Some("truncate"), _) => Self::on(parsed.names(parsed.skip(1, &["table"])), AccessExclusive),
+            (Some("drop"), Some("table" | "view")) => Self::on(parsed.names(2), AccessExclusive),
+            (Some("drop"), Some("materialized")) => Self::on(parsed.names(parsed.skip(2, &["view"])), AccessExclusive),
+            (Some("drop"), Some("index")) => {
+                let mode = if concurrently { ShareUpdateExclusive } else { AccessExclusive };
+                Self::on(parsed.names(parsed.skip(2, &["concurrently"])), mode)
+            }
+            (Some("alter"), Some("table")) => Self::on(parsed.name(2), alter_table_mode(&parsed.keywords[2..])),
+            (Some("create"), _) if parsed.keywords.iter().take(3).any(|k| k == "index") => {
+                let mode = if concurrently { ShareUpdateExclusive } else { Share };
+                Self::on(parsed.position("on").and_then(|i| parsed.name(i + 1)), mode)
+            }
+            (Some("create"), _) if parsed.keywords.iter().take(4).any(|k| k == "trigger") => {
+                Self::on(parsed.position("on").and_then(|i| parsed.name(i + 1)), ShareRowExclusive)
+            }
+            (Some("refresh"), Some("materialized")) => {
+                let mode = if concurrently { Exclusive } else { AccessExclusive };
+                Self::on(parsed.name(parsed.skip(2, &["view", "concurrently"])), mode)
+            }
+            (Some("lock"), _) => {
+                let mode = parsed
+                    .position("in")
+                    .and_then(|i| {
+                        let end = parsed.position("mode")?;
+                        LockMode::from_keywords(parsed.keywords.get(i + 1..end)?)
+                    })
+                    .unwrap_or(AccessExclusive);
+                Self::on(parsed.names(parsed.skip(1, &["table"])), mode)
+            }
+            _ => vec![],
+        };
+
+        // Adding foreign keys also locks the referenced table
+        if matches!(keyword(0), Some("create" | "alter")) && keyword(1) == Some("table") {
+            for (i, _) in parsed.keywords.iter().enumerate().filter(|(_, k)| *k == "references") {
+                locks.extend(Self::on(parsed.name(i + 1), ShareRowExclusive));
+            }
+        }
+
+        locks
+    }
+}
+
+/// The strongest lock taken by the subcommands of an `ALTER TABLE`, which
+/// mostly take `ACCESS EXCLUSIVE` but with some notable exceptions.
+fn alter_table_mode(keywords: &[String]) -> LockMode {
+    use LockMode::*;
+
+    // Commas also separate column lists within subcommands, so only those
+    // starting with a subcommand's keyword begin a new one
+    let starts = ["add", "alter", "attach", "cluster", "detach", "disable", "drop", "enable", "set", "validate"];
+    let mut subcommands: Vec<Vec<&str>> = vec![];
+
+    for (i, keyword) in keywords.iter().enumerate() {
+        let after_comma = i > 0 && keywords[i - 1] == ",";
+
+        if keyword == "," {
+            continue;
+        }
+        if after_comma && starts.contains(&keyword.as_str()) || subcommands.is_empty() {
+            subcommands.push(vec![]);
+        }
+        subcommands.last_mut().unwrap().push(keyword);
+    }
+
+    subcommands
+        .iter()
+        .map(|words| {
+            let has = |word: &str| words.contains(&word);
+            // The first word is the table name for the first subcommand
+            let first = words.iter().position(|w| starts.contains(w)).map(|i| words[i]);
+
+            match first {
+                Some("validate") => ShareUpdateExclusive,
+                Some("alter") if has("statistics") => ShareUpdateExclusive,
+                Some("cluster") => ShareUpdateExclusive,
+                Some("set") if has("without") && has("cluster") => ShareUpdateExclusive,
+                Some("attach") => ShareUpdateExclusive,
+                Some("detach") if has("concurrently") => ShareUpdateExclusive,
+                Some("add") if has("foreign") || has("references") => ShareRowExclusive,
+                Some("enable" | "disable") if has("trigger") => ShareRowExclusive,
+                _ => AccessExclusive,
+            }
+        })
+        .max()
+        .unwrap_or(AccessExclusive)
+}
+
+/// The tokens of a statement alongside their lowercased forms for matching
+/// keywords.
+struct Tokens {
+    tokens: Vec<String>,
+    keywords: Vec<String>,
+}
+
+impl Tokens {
+    fn new(sql: &str) -> Self {
+        let tokens = tokens(sql);
+        let keywords = tokens.iter().map(|t| t.to_lowercase()).collect();
+
+        Self { tokens, keywords }
+    }
+
+    fn keyword(&self, i: usize) -> Option<&str> {
+        self.keywords.get(i).map(String::as_str)
+    }
+
+    fn position(&self, keyword: &str) -> Option<usize> {
+        self.keywords.iter().position(|k| k == keyword)
+    }
+
+    /// The index following any of the optional keywords from `i`.
+    fn skip(&self, mut i: usize, optional: &[&str]) -> usize {
+        while self.keyword(i).is_some_and(|k| optional.contains(&k)) {
+            i += 1;
+        }
+
+        i
+    }
+
+    /// The comma-separated relation names starting from `i`, skipping
+    /// `IF EXISTS` and `ONLY` along the way.
+    fn names(&self, mut i: usize) -> Vec<String> {
+        let mut names = vec![];
+
+        loop {
+            i = self.skip(i, &["if", "not", "exists", "only"]);
+
+            match self.tokens.get(i) {
+                Some(name) if name != "," => names.push(name.trim_end_matches('*').to_string()),
+                _ => break,
+            }
+
+            if self.keyword(i + 1) != Some(",") {
+                break;
+            }
+            i += 2;
+        }
+
+        names
+    }
+
+    /// The single relation name starting from `i`, if any.
+    fn name(&self, i: usize) -> Option<String> {
+        self.names(i).into_iter().next()
+    }
+}
+
+/// Splits the SQL into whitespace- and punctuation-separated tokens, with
+/// comments removed but quoted identifiers kept intact including their quotes,
+/// so that names like `public."Users"` come out whole. Commas are tokens
+/// themselves, while parentheses and semicolons are dropped.
+fn tokens(sql: &str) -> Vec<String> {
+    let mut tokens = vec![];
+    let mut token = String::new();
+    let mut quoted = false;
+
+    for c in strip_comments(sql).chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                token.push(c);
+            }
+            c if !quoted && (c.is_whitespace() || matches!(c, '(' | ')' | ',' | ';')) => {
+                if !token.is_empty() {
+                    tokens.push(std::mem::take(&mut token));
+                }
+                // Commas are kept to tell lists of tables apart
+                if c == ',' {
+                    tokens.push(c.to_string());
+                }
+            }
+            c => token.push(c),
+        }
+    }
+
+    if !token.is_empty() {
+        tokens.push(token);
+    }
+
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::statements::split;
+
+    #[test]
+    fn modified_table_covers_inserts_and_merges() {
+        let modified: Vec<_> = split(
+            "insert into public.users(id) values (1);\n\
+             merge into only users u using staged s on u.id = s.id when matched then delete;\n\
+             delete from events;\n\
+             select * from users;",
+        )
+        .iter()
+        .map(modified_table)
+        .collect();
+
+        assert_eq!(
+            modified,
+            vec![Some("public.users".to_string()), Some("users".to_string()), Some("events".to_string()), None]
+        );
+    }
+
+    #[test]
+    fn changed_table_finds_table_and_alias() {
+        let changed: Vec<_> = split(
+            "update only public.users* set name = 'x';\n\
+             update users as u set name = 'x';\n\
+             delete from \"Orders\" o using users u where o.user_id = u.id;\n\
+             delete from events;\n\
+             insert into users values (1);",
+        )
+        .iter()
+        .map(changed_table)
+        .collect();
+
+        assert_eq!(
+            changed,
+            vec![
+                Some(("public.users".to_string(), None)),
+                Some(("users".to_string(), Some("u".to_string()))),
+                Some(("\"Orders\"".to_string(), Some("o".to_string()))),
+                Some(("events".to_string(), None)),
+                None,
+            ]
+        );
+    }
+
+    #[test]
+    fn destroyed_finds_tables_and_schemas() {
+        use Destroyed::*;
+
+        let destroyed: Vec<_> = split(
+            "drop table if exists a, public.\"B\" cascade;\n\
+             truncate table only c, d;\n\
+             delete from e;\n\
+             update only f set x = 1;\n\
+             drop schema g cascade;\n\
+             delete from h where id = 1;",
+        )
+        .iter()
+        .map(Destroyed::all_for)
+        .collect();
+
+        assert_eq!(
+            destroyed,
+            vec![
+                vec![Table("a".to_string()), Table("public.\"B\"".to_string())],
+                vec![Table("c".to_string()), Table("d".to_string())],
+                vec![Table("e".to_string())],
+                vec![Table("f".to_string())],
+                vec![Schema("g".to_string())],
+                vec![],
+            ]
+        );
+    }
+
+    #[test]
+    fn dropped_finds_objects_columns_and_constraints() {
+        let dropped: Vec<_> = split(
+            "drop view if exists a, b cascade;\n\
+             drop materialized view c;\n\
+             alter table only d drop column if exists e, drop f, drop constraint g, alter column h drop not null;\n\
+             alter table i alter column j drop default;\n\
+             create table k ();",
+        )
+        .iter()
+        .map(|statement| Dropped::all_for(statement).iter().map(Dropped::describe).collect::<Vec<_>>())
+        .collect();
+
+        assert_eq!(
+            dropped,
+            vec![
+                vec!["view `a`", "view `b`"],
+                vec!["materialized view `c`"],
+                vec!["column `e` of `d`", "column `f` of `d`", "constraint `g` of `d`"],
+                vec![],
+                vec![],
+            ]
+        );
+        assert!(Dropped::all_for(&split("drop view a cascade;")[0])[0].cascade);
+    }
+
+    fn operations(sql: &str) -> Vec<Option<(TableOperation, String)>> {
+        split(sql).iter().map(TableOperation::of).collect()
+    }
+
+    #[test]
+    fn of_finds_locking_operations_and_tables() {
+        use TableOperation::*;
+
+        assert_eq!(
+            operations(
+                "ALTER TABLE IF EXISTS ONLY public.\"Users\" ADD COLUMN age int;\n\
+                 create unique index users_email on users (email);\n\
+                 create index if not exists users_name on only app.users using btree (name);\n\
+                 create index concurrently users_age on users (age);\n\
+                 create table things ();",
+            ),
+            vec![
+                Some((AlterTable, "public.\"Users\"".to_string())),
+                Some((CreateIndex, "users".to_string())),
+                Some((CreateIndex, "app.users".to_string())),
+                None,
+                None,
+            ],
+        );
+    }
+
+    fn locks(sql: &str) -> Vec<(&'static str, String)> {
+        split(sql)
+            .iter()
+            .flat_map(Lock::all_for)
+            .map(|lock| (lock.mode.as_str(), lock.relation))
+            .collect()
+    }
+
+    #[test]
+    fn all_for_finds_dml_and_ddl_locks() {
+        assert_eq!(
+            locks(
+                "insert into users (id, name) values (1, 'a');\n\
+                 update only app.users set name = 'b' where id = 1;\n\
+                 truncate table a, b restart identity;\n\
+                 drop table if exists c, d cascade;\n\
+                 create index concurrently users_name on users (name);\n\
+                 lock table e in share row exclusive mode;\n\
+                 select * from users;",
+            ),
+            vec![
+                ("ROW EXCLUSIVE", "users".to_string()),
+                ("ROW EXCLUSIVE", "app.users".to_string()),
+                ("ACCESS EXCLUSIVE", "a".to_string()),
+                ("ACCESS EXCLUSIVE", "b".to_string()),
+                ("ACCESS EXCLUSIVE", "c".to_string()),
+                ("ACCESS EXCLUSIVE", "d".to_string()),
+                ("SHARE UPDATE EXCLUSIVE", "users".to_string()),
+                ("SHARE ROW EXCLUSIVE", "e".to_string()),
+            ],
+        );
+    }
+
+    #[test]
+    fn all_for_uses_strongest_alter_table_subcommand() {
+        assert_eq!(
+            locks(
+                "alter table users validate constraint users_age;\n\
+                 alter table orders add constraint orders_user foreign key (user_id, tenant) references users (id, tenant);\n\
+                 alter table users alter column age set statistics 100, add column flag bool;",
+            ),
+            vec![
+                ("SHARE UPDATE EXCLUSIVE", "users".to_string()),
+                ("SHARE ROW EXCLUSIVE", "orders".to_string()),
+                ("SHARE ROW EXCLUSIVE", "users".to_string()),
+                ("ACCESS EXCLUSIVE", "users".to_string()),
+            ],
+        );
+    }
+
+    #[test]
+    fn impact_summarizes_changed_objects() {
+        let statements = split(
+            "create table orders (id int primary key);\n\
+             alter table users add column name text;\n\
+             ALTER TABLE Users ADD COLUMN email text;\n\
+             create unique index concurrently if not exists orders_user on orders (user_id);\n\
+             create index on users (email);\n\
+             create or replace function app.total(a int) returns int as $$ select a $$ language sql;\n\
+             create materialized view totals as select 1;\n\
+             drop index idx_tmp, idx_old;\n\
+             alter default privileges grant select on tables to app;\n\
+             insert into orders values (1);",
+        );
+
+        assert_eq!(
+            Impact::summary(&statements).unwrap(),
+            "creates table orders, alters table users (2 statements), creates index orders_user, \
+             creates index on users, creates function app.total, creates materialized view totals, \
+             drops index idx_tmp, drops index idx_old",
+        );
+        assert_eq!(Impact::summary(&split("insert into orders values (1);")), None);
+    }
+
+    #[test]
+    fn format_bytes_picks_units() {
+        assert_eq!(format_bytes(512), "512 B");
+        assert_eq!(format_bytes(1536), "1.5 KB");
+        assert_eq!(format_bytes(3 * 1024 * 1024 * 1024), "3.0 GB");
+    }
+}
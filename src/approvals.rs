@@ -0,0 +1,130 @@
+//! Sign-off on pending revisions for lightweight change control, checked by
+//! `jrny verify --require-approval-file [path]`.
+//!
+//! Each approval names the revision and the checksum of the contents that
+//! were approved, so that changing a revision afterwards needs approving
+//! again:
+//!
+//! ```toml
+//! [[approvals]]
+//! revision = "004.1618370298.add-orders-index.sql"
+//! checksum = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08"
+//! approved_by = "@dba-team"
+//! ```
+//!
+//! Revisions with owners, whether from their frontmatter or an `OWNERS` file
+//! in their directory, must be approved by one of them.
+use std::{fs, path::Path};
+
+use serde::Deserialize;
+
+use crate::{Error, Result};
+
+/// Lists the owners of the revisions in the directory, one per line
+pub const OWNERS_FILE: &str = "OWNERS";
+
+#[derive(Debug, Deserialize)]
+pub struct Approval {
+    /// The name of the revision file, relative to the revisions directory
+    pub revision: String,
+    pub checksum: String,
+    pub approved_by: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct Approvals {
+    #[serde(default)]
+    pub approvals: Vec<Approval>,
+}
+
+impl Approvals {
+    pub fn from_path(path: &Path) -> Result<Self> {
+        if !path.is_file() {
+            return Err(Error::FileNotValid(path.display().to_string()));
+        }
+
+        let contents = fs::read_to_string(path)?;
+
+        toml::from_str(&contents).map_err(|e| Error::TomlInvalid(e, path.display().to_string()))
+    }
+
+    /// What's missing for the revision with the given contents to count as
+    /// approved, if anything.
+    pub fn check(&self, filename: &str, checksum: &str, owners: &[String]) -> Option<String> {
+        let approvals: Vec<_> = self.approvals.iter().filter(|a| a.revision == filename).collect();
+
+        if approvals.is_empty() {
+            return Some(format!("Not approved (checksum {})", checksum));
+        }
+
+        let current: Vec<_> = approvals.iter().filter(|a| a.checksum == checksum).collect();
+
+        if current.is_empty() {
+            return Some(format!("Changed since approved (checksum now {})", checksum));
+        }
+
+        if !owners.is_empty() && !current.iter().any(|a| owners.contains(&a.approved_by)) {
+            return Some(format!("Not approved by an owner: {}", owners.join(", ")));
+        }
+
+        None
+    }
+}
+
+/// The owners of the revision, given by its path relative to the revisions
+/// directory, listed in the nearest `OWNERS` file, looking from the
+/// revision's own directory up to the revisions directory.
+pub fn directory_owners(revisions: &Path, path: &Path) -> Result<Vec<String>> {
+    let mut dir = revisions.join(path);
+
+    while dir.pop() && dir.starts_with(revisions) {
+        let path = dir.join(OWNERS_FILE);
+
+        if path.is_file() {
+            return Ok(parse_owners(&fs::read_to_string(path)?));
+        }
+    }
+
+    Ok(vec![])
+}
+
+/// The owners listed in an `OWNERS` file, ignoring blank lines and `#` comments.
+fn parse_owners(contents: &str) -> Vec<String> {
+    contents
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or_default().trim())
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn approval(revision: &str, checksum: &str, approved_by: &str) -> Approval {
+        Approval {
+            revision: revision.to_string(),
+            checksum: checksum.to_string(),
+            approved_by: approved_by.to_string(),
+        }
+    }
+
+    #[test]
+    fn approvals_must_match_checksum_and_owners() {
+        let approvals = Approvals {
+            approvals: vec![approval("001.a.sql", "old", "@dba"), approval("001.a.sql", "new", "@dev")],
+        };
+        let owners = vec!["@dba".to_string()];
+
+        assert_eq!(approvals.check("001.a.sql", "new", &[]), None);
+        assert!(approvals.check("001.a.sql", "new", &owners).unwrap().contains("owner"));
+        assert!(approvals.check("001.a.sql", "newer", &[]).unwrap().starts_with("Changed"));
+        assert!(approvals.check("002.b.sql", "new", &[]).unwrap().starts_with("Not approved"));
+    }
+
+    #[test]
+    fn owners_ignore_comments_and_blanks() {
+        assert_eq!(parse_owners("# DBAs\n@dba-team\n\n@jane # on call\n"), vec!["@dba-team", "@jane"]);
+    }
+}
@@ -0,0 +1,148 @@
+//! Assertions about the schema and data as revisions leave them, written as
+//! SQL files in which every query must return either no rows or a single
+//! `true`:
+//!
+//! ```sql
+//! -- Every user has an email address
+//! SELECT id, name FROM users WHERE email IS NULL;
+//!
+//! SELECT count(*) = 3 FROM roles;
+//! ```
+//!
+//! Statements that return nothing, such as `SET` or `CREATE TEMP TABLE`,
+//! pass as a matter of course, so they can set up what the queries need.
+use std::{fs, path::Path};
+
+use postgres::{GenericClient, SimpleQueryMessage};
+
+use crate::{
+    paths,
+    revisions::sorted_entries,
+    statements::{self, Statement},
+    Error,
+    Result,
+};
+
+/// The most columns of a failing row shown, beyond which they're elided.
+const MAX_COLUMNS: usize = 5;
+
+#[derive(Debug)]
+pub struct AssertionFile {
+    /// The name of the file, relative to the directory it's found in
+    pub filename: String,
+    pub statements: Vec<Statement>,
+}
+
+impl AssertionFile {
+    pub fn new(filename: &str, contents: &str) -> Self {
+        Self {
+            filename: filename.to_string(),
+            statements: statements::split(contents),
+        }
+    }
+
+    /// Every `.sql` file in the directory, at any depth, in order of path,
+    /// or none should there be no such directory.
+    pub fn all_from_disk(dir: &Path) -> Result<Vec<Self>> {
+        let mut files = vec![];
+
+        if dir.is_dir() {
+            Self::load_dir(dir, dir, &mut files)?;
+        }
+
+        Ok(files)
+    }
+
+    fn load_dir(root: &Path, dir: &Path, files: &mut Vec<Self>) -> Result<()> {
+        for entry in sorted_entries(dir)? {
+            if entry.is_dir() {
+                Self::load_dir(root, &entry, files)?;
+                continue;
+            }
+            if entry.extension().and_then(|ext| ext.to_str()) != Some("sql") {
+                continue;
+            }
+
+            let filename = paths::to_slash(entry.strip_prefix(root).unwrap_or(&entry))
+                .ok_or_else(|| Error::FileNotValid(entry.display().to_string()))?;
+
+            files.push(Self::new(&filename, &fs::read_to_string(&entry)?));
+        }
+
+        Ok(())
+    }
+}
+
+/// Runs the statement, returning why what it returned fails it, if it does.
+pub fn check(client: &mut impl GenericClient, statement: &Statement) -> std::result::Result<Option<String>, postgres::Error> {
+    let rows: Vec<_> = client
+        .simple_query(&statement.sql)?
+        .iter()
+        .filter_map(|message| match message {
+            SimpleQueryMessage::Row(row) => Some(
+                row.columns()
+                    .iter()
+                    .enumerate()
+                    .map(|(i, column)| (column.name().to_string(), row.get(i).map(ToString::to_string)))
+                    .collect(),
+            ),
+            _ => None,
+        })
+        .collect();
+
+    Ok(failure(&rows))
+}
+
+/// Why the rows a query returned, each as the name and text of its columns
+/// as Postgres gives them, fail the assertion, if they do.
+pub fn failure(rows: &[Vec<(String, Option<String>)>]) -> Option<String> {
+    match rows {
+        [] => None,
+        [row] if row.len() == 1 => match row[0].1.as_deref() {
+            Some("t") => None,
+            Some("f") => Some("returned false".to_string()),
+            None => Some("returned null".to_string()),
+            Some(_) => Some(format!("returned 1 row ({})", describe(row))),
+        },
+        [row] => Some(format!("returned 1 row ({})", describe(row))),
+        [row, ..] => Some(format!("returned {} row(s), the first being ({})", rows.len(), describe(row))),
+    }
+}
+
+/// The columns of the row as `name = value`, eg. `id = 4, email = null`.
+fn describe(row: &[(String, Option<String>)]) -> String {
+    let mut columns: Vec<_> = row
+        .iter()
+        .take(MAX_COLUMNS)
+        .map(|(name, value)| format!("{} = {}", name, value.as_deref().unwrap_or("null")))
+        .collect();
+
+    if row.len() > MAX_COLUMNS {
+        columns.push("...".to_string());
+    }
+
+    columns.join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(columns: &[(&str, Option<&str>)]) -> Vec<(String, Option<String>)> {
+        columns.iter().map(|(name, value)| (name.to_string(), value.map(ToString::to_string))).collect()
+    }
+
+    #[test]
+    fn queries_pass_with_no_rows_or_true() {
+        assert_eq!(failure(&[]), None);
+        assert_eq!(failure(&[row(&[("?column?", Some("t"))])]), None);
+        assert_eq!(failure(&[row(&[("?column?", Some("f"))])]).unwrap(), "returned false");
+        assert_eq!(failure(&[row(&[("every", None)])]).unwrap(), "returned null");
+        assert_eq!(failure(&[row(&[("id", Some("4"))])]).unwrap(), "returned 1 row (id = 4)");
+        assert_eq!(failure(&[row(&[("id", Some("4")), ("email", None)])]).unwrap(), "returned 1 row (id = 4, email = null)");
+        assert_eq!(
+            failure(&[row(&[("id", Some("4")), ("email", None)]), row(&[("id", Some("9")), ("email", None)])]).unwrap(),
+            "returned 2 row(s), the first being (id = 4, email = null)",
+        );
+    }
+}
@@ -0,0 +1,181 @@
+//! Running a single large `UPDATE` or `DELETE` in batches, each in its own
+//! transaction, as marked by a directive comment directly before it:
+//!
+//! ```sql
+//! -- jrny:batch size=10000 key=id
+//! update users set email = lower(email);
+//! ```
+//!
+//! Batches are ranges of the key, found by paging through the table in key
+//! order, so that each only locks and rewrites a bounded number of rows.
+use crate::{
+    analysis,
+    meta::directive_settings,
+    statements::{find_top_level_keyword, strip_comments, Statement},
+};
+
+/// Names the directive, which is followed by its settings
+pub const DIRECTIVE: &str = "batch";
+
+const DEFAULT_SIZE: u64 = 10000;
+const DEFAULT_KEY: &str = "id";
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Batch {
+    /// The most rows of the table covered by each batch
+    pub size: u64,
+    /// A unique, non-null column to page through the table by
+    pub key: String,
+}
+
+impl Batch {
+    /// The batching asked for by a directive in the statement's comments,
+    /// if any.
+    pub fn of(statement: &Statement) -> Result<Option<Self>, String> {
+        directive_settings(&statement.sql, DIRECTIVE).map(Self::parse).transpose()
+    }
+
+    /// Parses settings such as `size=10000 key=id`, either of which may be
+    /// left out for its default.
+    fn parse(settings: &str) -> Result<Self, String> {
+        let mut batch = Self {
+            size: DEFAULT_SIZE,
+            key: DEFAULT_KEY.to_string(),
+        };
+
+        for setting in settings.split_whitespace() {
+            match setting.split_once('=') {
+                Some(("size", size)) => {
+                    batch.size = size
+                        .parse()
+                        .ok()
+                        .filter(|size| *size > 0)
+                        .ok_or(format!("expected a positive number of rows, found `{}`", size))?;
+                }
+                Some(("key", key)) if !key.is_empty() => batch.key = key.to_string(),
+                _ => return Err(format!("unexpected `{}`, expected `size=[rows]` or `key=[column]`", setting)),
+            }
+        }
+
+        Ok(batch)
+    }
+}
+
+/// A statement rewritten to change one range of keys at a time.
+#[derive(Debug)]
+pub struct Batched {
+    /// The table as written in the statement
+    table: String,
+    /// The key qualified by the table's alias or name, as the statement
+    /// refers to it
+    qualified_key: String,
+    batch: Batch,
+    /// The statement up to its `WHERE` clause
+    head: String,
+    /// The statement's own condition, if any
+    condition: Option<String>,
+}
+
+impl Batched {
+    pub fn new(statement: &Statement, batch: Batch) -> Result<Self, String> {
+        let (table, alias) = analysis::changed_table(statement)
+            .ok_or_else(|| "only single `UPDATE` or `DELETE` statements can be batched".to_string())?;
+
+        let sql = strip_comments(&statement.sql).trim().to_string();
+
+        if find_top_level_keyword(&sql, "returning").is_some() {
+            return Err("batched statements can't have a `RETURNING` clause".to_string());
+        }
+
+        let (head, condition) = match find_top_level_keyword(&sql, "where") {
+            Some(i) => (sql[..i].trim_end().to_string(), Some(sql[i + "where".len()..].trim().to_string())),
+            None => (sql, None),
+        };
+
+        Ok(Self {
+            qualified_key: format!("{}.{}", alias.as_ref().unwrap_or(&table), batch.key),
+            table,
+            batch,
+            head,
+            condition,
+        })
+    }
+
+    /// A query for the last key of the batch following the given key, or the
+    /// first batch without one, as a literal (or null once there are no more).
+    pub fn next_bounds(&self, after: Option<&str>) -> String {
+        let key = &self.batch.key;
+        let after = after.map(|after| format!(" WHERE {} > {}", key, after)).unwrap_or_default();
+
+        format!(
+            "SELECT quote_literal(max({key})::TEXT) AS last FROM (SELECT {key} FROM {}{} ORDER BY {key} LIMIT {}) batch",
+            self.table,
+            after,
+            self.batch.size,
+            key = key,
+        )
+    }
+
+    /// The statement limited to the keys after `after` up to and including
+    /// `last`, given as literals.
+    pub fn statement(&self, after: Option<&str>, last: &str) -> String {
+        let key = &self.qualified_key;
+        let range = match after {
+            Some(after) => format!("{} > {} AND {} <= {}", key, after, key, last),
+            None => format!("{} <= {}", key, last),
+        };
+
+        match &self.condition {
+            Some(condition) => format!("{} WHERE ({}) AND ({})", self.head, range, condition),
+            None => format!("{} WHERE {}", self.head, range),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::statements::split;
+
+    fn statement(sql: &str) -> Statement {
+        split(sql).remove(0)
+    }
+
+    #[test]
+    fn batch_parses_directive_settings() {
+        assert_eq!(Batch::of(&statement("update t set x = 1")), Ok(None));
+        assert_eq!(
+            Batch::of(&statement("-- jrny:batch size=500 key=user_id\nupdate t set x = 1")),
+            Ok(Some(Batch { size: 500, key: "user_id".to_string() })),
+        );
+        assert_eq!(
+            Batch::of(&statement("-- jrny:batch\ndelete from t")),
+            Ok(Some(Batch { size: DEFAULT_SIZE, key: DEFAULT_KEY.to_string() })),
+        );
+        assert!(Batch::of(&statement("-- jrny:batch size=0\ndelete from t")).is_err());
+        assert!(Batch::of(&statement("-- jrny:batch rows=5\ndelete from t")).is_err());
+        assert_eq!(Batch::of(&statement("-- jrny:batches\ndelete from t")), Ok(None));
+    }
+
+    #[test]
+    fn batched_limits_statement_to_key_range() {
+        let batch = || Batch { size: 100, key: "id".to_string() };
+        let update = statement("-- jrny:batch\nupdate users u set name = lower(name) where u.name <> lower(u.name)");
+        let batched = Batched::new(&update, batch()).unwrap();
+
+        assert_eq!(
+            batched.next_bounds(Some("'5'")),
+            "SELECT quote_literal(max(id)::TEXT) AS last FROM (SELECT id FROM users WHERE id > '5' ORDER BY id LIMIT 100) batch",
+        );
+        assert_eq!(
+            batched.statement(Some("'5'"), "'105'"),
+            "update users u set name = lower(name) WHERE (u.id > '5' AND u.id <= '105') AND (u.name <> lower(u.name))",
+        );
+
+        let delete = Batched::new(&statement("delete from events"), batch()).unwrap();
+
+        assert_eq!(delete.statement(None, "'100'"), "delete from events WHERE events.id <= '100'");
+        assert!(Batched::new(&statement("delete from t returning id"), batch()).is_err());
+        assert!(Batched::new(&statement("insert into t values (1)"), batch()).is_err());
+    }
+}
@@ -0,0 +1,260 @@
+//! Portable bundles of the revision files along with an environment's history
+//! of applying them, written by `jrny export` and read back by `jrny import
+//! --bundle`, eg. to stand up an air-gapped environment or to archive what was
+//! applied for auditing.
+use std::path::{Component, Path};
+
+use chrono::{DateTime, Utc};
+
+use crate::{
+    git::Provenance,
+    json::Json,
+    revisions::{to_checksum, RepeatableRecord, RevisionRecord},
+};
+
+/// Identifies a JSON file as a bundle
+const FORMAT: &str = "jrny-bundle";
+
+/// Incremented whenever bundles change in a way older versions can't read
+const VERSION: i64 = 1;
+
+/// A file from the revisions directory, whether a revision or repeatable.
+#[derive(Debug, PartialEq, Eq)]
+pub struct BundledFile {
+    /// The name of the file, relative to the revisions directory
+    pub filename: String,
+    pub contents: String,
+}
+
+#[derive(Debug)]
+pub struct Bundle {
+    pub exported_at: DateTime<Utc>,
+    /// The name of the environment the history was exported from, if any
+    pub environment: Option<String>,
+    pub files: Vec<BundledFile>,
+    pub revisions: Vec<RevisionRecord>,
+    pub repeatables: Vec<RepeatableRecord>,
+}
+
+impl Bundle {
+    pub fn to_json(&self) -> Json {
+        let files = self.files.iter().map(|file| {
+            Json::object([
+                ("filename", Json::from(file.filename.as_str())),
+                ("checksum", Json::from(to_checksum(&file.contents))),
+                ("contents", Json::from(file.contents.as_str())),
+            ])
+        });
+
+        let revisions = self.revisions.iter().map(|record| {
+            Json::object([
+                ("id", Json::from(record.id)),
+                ("filename", Json::from(record.filename.as_str())),
+                ("name", Json::from(record.name.as_str())),
+                ("checksum", Json::from(record.checksum.as_str())),
+                ("created_at", Json::from(record.created_at.to_rfc3339())),
+                ("applied_on", Json::from(record.applied_on.to_rfc3339())),
+                ("git_commit", Json::from(record.provenance.commit.clone())),
+                ("git_branch", Json::from(record.provenance.branch.clone())),
+                ("git_author", Json::from(record.provenance.author.clone())),
+                ("release_group", Json::from(record.release_group.clone())),
+                ("impact", Json::from(record.impact.clone())),
+                ("archived", Json::from(record.archived.clone())),
+            ])
+        });
+
+        let repeatables = self.repeatables.iter().map(|record| {
+            Json::object([
+                ("filename", Json::from(record.filename.as_str())),
+                ("checksum", Json::from(record.checksum.as_str())),
+                ("applied_on", Json::from(record.applied_on.to_rfc3339())),
+            ])
+        });
+
+        Json::object([
+            ("format", Json::from(FORMAT)),
+            ("version", Json::from(VERSION)),
+            ("exported_at", Json::from(self.exported_at.to_rfc3339())),
+            ("environment", Json::from(self.environment.clone())),
+            ("files", Json::Array(files.collect())),
+            ("revisions", Json::Array(revisions.collect())),
+            ("repeatables", Json::Array(repeatables.collect())),
+        ])
+    }
+
+    /// Reads a bundle from its JSON, describing what was wrong if it isn't
+    /// one, including files whose contents don't match their checksum.
+    pub fn from_json(json: &Json) -> Result<Self, String> {
+        if json.get("format").and_then(Json::as_str) != Some(FORMAT) {
+            return Err(format!("expected `format` to be `{}`", FORMAT));
+        }
+
+        match json.get("version").and_then(Json::as_i64) {
+            Some(VERSION) => {}
+            Some(version) => return Err(format!("unsupported version {}, expected {}", version, VERSION)),
+            None => return Err("missing `version`".to_string()),
+        }
+
+        let files = array(json, "files")?
+            .iter()
+            .map(|file| {
+                let filename = string(file, "filename")?;
+                let contents = string(file, "contents")?;
+
+                if !is_relative(&filename) {
+                    return Err(format!("`{}` is not a path within the revisions directory", filename));
+                }
+
+                if string(file, "checksum")? != to_checksum(&contents) {
+                    return Err(format!("the contents of `{}` don't match its checksum", filename));
+                }
+
+                Ok(BundledFile { filename, contents })
+            })
+            .collect::<Result<_, String>>()?;
+
+        let revisions = array(json, "revisions")?
+            .iter()
+            .map(|record| {
+                let id = record
+                    .get("id")
+                    .and_then(Json::as_i64)
+                    .and_then(|id| i32::try_from(id).ok())
+                    .ok_or("expected each revision to have an `id`")?;
+
+                Ok(RevisionRecord {
+                    id,
+                    applied_on: timestamp(record, "applied_on")?,
+                    checksum: string(record, "checksum")?,
+                    created_at: timestamp(record, "created_at")?,
+                    filename: string(record, "filename")?,
+                    name: string(record, "name")?,
+                    provenance: Provenance {
+                        commit: optional_string(record, "git_commit"),
+                        branch: optional_string(record, "git_branch"),
+                        author: optional_string(record, "git_author"),
+                    },
+                    release_group: optional_string(record, "release_group"),
+                    impact: optional_string(record, "impact"),
+                    archived: optional_string(record, "archived"),
+                })
+            })
+            .collect::<Result<_, String>>()?;
+
+        let repeatables = array(json, "repeatables")?
+            .iter()
+            .map(|record| {
+                Ok(RepeatableRecord {
+                    applied_on: timestamp(record, "applied_on")?,
+                    checksum: string(record, "checksum")?,
+                    filename: string(record, "filename")?,
+                })
+            })
+            .collect::<Result<_, String>>()?;
+
+        Ok(Self {
+            exported_at: timestamp(json, "exported_at")?,
+            environment: optional_string(json, "environment"),
+            files,
+            revisions,
+            repeatables,
+        })
+    }
+}
+
+fn array<'a>(json: &'a Json, key: &str) -> Result<&'a [Json], String> {
+    json.get(key).and_then(Json::as_array).ok_or(format!("expected `{}` to be an array", key))
+}
+
+fn string(json: &Json, key: &str) -> Result<String, String> {
+    optional_string(json, key).ok_or(format!("expected `{}` to be a string", key))
+}
+
+fn optional_string(json: &Json, key: &str) -> Option<String> {
+    json.get(key).and_then(Json::as_str).map(str::to_string)
+}
+
+fn timestamp(json: &Json, key: &str) -> Result<DateTime<Utc>, String> {
+    json.get(key)
+        .and_then(Json::as_str)
+        .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
+        .map(|dt| dt.with_timezone(&Utc))
+        .ok_or(format!("expected `{}` to be an RFC 3339 timestamp", key))
+}
+
+/// Whether the filename stays within the directory it's relative to, since
+/// bundles can come from anywhere.
+fn is_relative(filename: &str) -> bool {
+    let mut components = Path::new(filename).components().peekable();
+
+    components.peek().is_some() && components.all(|c| matches!(c, Component::Normal(_)))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    #[test]
+    fn bundle_round_trips_through_json() {
+        let at = Utc.timestamp(1_618_370_298, 0);
+        let bundle = Bundle {
+            exported_at: at,
+            environment: Some("staging".to_string()),
+            files: vec![BundledFile {
+                filename: "001.1618370298.first.sql".to_string(),
+                contents: "CREATE TABLE a ();\n".to_string(),
+            }],
+            revisions: vec![RevisionRecord {
+                id: 1,
+                applied_on: at,
+                checksum: to_checksum("CREATE TABLE a ();\n"),
+                created_at: at,
+                filename: "001.1618370298.first.sql".to_string(),
+                name: "first".to_string(),
+                provenance: Provenance { commit: Some("abc".to_string()), branch: None, author: None },
+                release_group: Some("2021-04-release".to_string()),
+                impact: Some("creates table a".to_string()),
+                archived: Some("CREATE TABLE a ();\n".to_string()),
+            }],
+            repeatables: vec![],
+        };
+
+        let read = Bundle::from_json(&Json::parse(&bundle.to_json().to_string()).unwrap()).unwrap();
+
+        assert_eq!(read.exported_at, at);
+        assert_eq!(read.environment.as_deref(), Some("staging"));
+        assert_eq!(read.files, bundle.files);
+        assert_eq!(read.revisions[0].provenance.commit.as_deref(), Some("abc"));
+        assert_eq!(read.revisions[0].release_group, bundle.revisions[0].release_group);
+        assert_eq!(read.revisions[0].checksum, bundle.revisions[0].checksum);
+        assert_eq!(read.revisions[0].archived, bundle.revisions[0].archived);
+    }
+
+    #[test]
+    fn bundle_rejects_tampered_or_escaping_files() {
+        let bundle = |filename: &str, checksum: &str| {
+            Json::object([
+                ("format", Json::from(FORMAT)),
+                ("version", Json::from(VERSION)),
+                ("exported_at", Json::from("2021-04-14T03:18:18+00:00")),
+                (
+                    "files",
+                    Json::Array(vec![Json::object([
+                        ("filename", Json::from(filename)),
+                        ("checksum", Json::from(checksum)),
+                        ("contents", Json::from("SELECT 1;")),
+                    ])]),
+                ),
+                ("revisions", Json::Array(vec![])),
+                ("repeatables", Json::Array(vec![])),
+            ])
+        };
+
+        assert!(Bundle::from_json(&bundle("a.sql", &to_checksum("SELECT 1;"))).is_ok());
+        assert!(Bundle::from_json(&bundle("a.sql", &to_checksum("SELECT 2;"))).is_err());
+        assert!(Bundle::from_json(&bundle("../a.sql", &to_checksum("SELECT 1;"))).is_err());
+        assert!(Bundle::from_json(&bundle("/tmp/a.sql", &to_checksum("SELECT 1;"))).is_err());
+    }
+}
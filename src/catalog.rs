@@ -0,0 +1,100 @@
+//! Replacement text for the errors jrny reports and their hints, keyed by the
+//! errors' stable ids, so that wrappers around jrny can word them their own
+//! way or translate them. Only errors are covered, not the progress, review,
+//! or summary output of commands. The catalog is a TOML file given by
+//! `JRNY_MESSAGES`:
+//!
+//! ```toml
+//! [errors]
+//! config-not-found = "Keine Konfiguration gefunden ({message})"
+//!
+//! [hints]
+//! database-error = "Läuft die Datenbank?"
+//! ```
+//!
+//! Within the text, `{message}` is replaced with jrny's own message, or its
+//! hint, and `{id}` with the id. Those are the only parameters, so text that
+//! needs the specifics of an error, eg. the path, has to include jrny's own
+//! message to get them. Errors that aren't in the catalog are reported as they
+//! otherwise would be.
+use std::{collections::HashMap, fs, path::Path};
+
+use serde::Deserialize;
+
+use crate::{error::render_report, settings::Problem, Error, Result};
+
+/// The environment variable giving the path of the catalog.
+pub const MESSAGES: &str = "JRNY_MESSAGES";
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Catalog {
+    /// The text for each error, by its id
+    #[serde(default)]
+    pub errors: HashMap<String, String>,
+    /// The text for the hint given with each error, by the error's id
+    #[serde(default)]
+    pub hints: HashMap<String, String>,
+}
+
+impl Catalog {
+    pub fn from_filepath(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)?;
+        let settings: toml::Value =
+            toml::from_str(&contents).map_err(|e| Error::TomlInvalid(e, path.display().to_string()))?;
+
+        settings.try_into().map_err(|e| Problem::from_toml(&e).into_error(path, &[(path, &contents)]))
+    }
+
+    /// The error's message, as the catalog words it if it does.
+    pub fn message(&self, err: &Error) -> String {
+        let message = err.to_string();
+
+        match self.errors.get(err.id()) {
+            Some(text) => fill(text, err.id(), &message),
+            None => message,
+        }
+    }
+
+    /// The hint for the error, as the catalog words it if it does, even for
+    /// errors that jrny has no hint for.
+    pub fn hint(&self, err: &Error) -> Option<String> {
+        let hint = err.hint();
+
+        match self.hints.get(err.id()) {
+            Some(text) => Some(fill(text, err.id(), hint.as_deref().unwrap_or_default())),
+            None => hint,
+        }
+    }
+
+    /// As with `Error::report`, with the message and hint worded by the
+    /// catalog.
+    pub fn report(&self, err: &Error) -> String {
+        render_report(self.message(err), &err.causes(), self.hint(err))
+    }
+}
+
+fn fill(text: &str, id: &str, message: &str) -> String {
+    text.replace("{id}", id).replace("{message}", message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn errors_are_worded_by_the_catalog_if_listed() {
+        let catalog: Catalog = toml::from_str(
+            "[errors]\nconfig-not-found = \"[{id}] Keine Konfiguration: {message}\"\n\
+             [hints]\nrevisions-pending = \"Erst `jrny embark`\"",
+        )
+        .unwrap();
+        let not_found = Error::ConfigNotFound("jrny.toml".to_string());
+        let pending = Error::RevisionsPending(2);
+
+        assert_eq!(catalog.message(&not_found), format!("[config-not-found] Keine Konfiguration: {}", not_found));
+        assert_eq!(catalog.message(&pending), pending.to_string());
+        assert_eq!(catalog.hint(&pending).as_deref(), Some("Erst `jrny embark`"));
+        assert_eq!(catalog.report(&Error::EnvNotFound), Error::EnvNotFound.report());
+    }
+}
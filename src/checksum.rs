@@ -0,0 +1,228 @@
+//! How the contents of revisions are checksummed, which projects can choose
+//! in the config file:
+//!
+//! ```toml
+//! [revisions.checksum]
+//! algorithm = "sha512"
+//! normalize = ["line-endings", "whitespace", "comments"]
+//! ```
+//!
+//! Normalizing first means that, say, an editor converting line endings
+//! doesn't make an applied revision look as though it's changed. Each
+//! checksum is recorded along with the scheme it was made with, as in
+//! `sha512+line-endings:4f2a...`, and recorded checksums are compared to
+//! files checksummed by the same scheme, so changing the scheme never makes
+//! applied revisions look changed on its own. Checksums from the default
+//! scheme, being SHA-256 of the contents as they are, are recorded without
+//! one as they always have been.
+use serde::Deserialize;
+use sha2::{Digest, Sha256, Sha512};
+
+use crate::statements::strip_comments;
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Algorithm {
+    #[default]
+    Sha256,
+    Sha512,
+}
+
+impl Algorithm {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Sha256 => "sha256",
+            Self::Sha512 => "sha512",
+        }
+    }
+
+    fn named(s: &str) -> Option<Self> {
+        [Self::Sha256, Self::Sha512].into_iter().find(|algorithm| algorithm.as_str() == s)
+    }
+}
+
+/// Differences in contents that don't change their checksum, applied in
+/// the order listed.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "kebab-case")]
+pub enum Normalization {
+    /// `\r\n` line endings are treated as `\n`
+    LineEndings,
+    /// Line and block comments are removed, along with blank lines
+    Comments,
+    /// Whitespace at the end of each line and blank lines at the end of the
+    /// file are removed
+    Whitespace,
+}
+
+impl Normalization {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::LineEndings => "line-endings",
+            Self::Comments => "comments",
+            Self::Whitespace => "whitespace",
+        }
+    }
+
+    fn named(s: &str) -> Option<Self> {
+        [Self::LineEndings, Self::Comments, Self::Whitespace]
+            .into_iter()
+            .find(|normalization| normalization.as_str() == s)
+    }
+
+    fn apply(&self, contents: &str) -> String {
+        match self {
+            Self::LineEndings => contents.replace("\r\n", "\n"),
+            Self::Comments => strip_comments(contents)
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                .map(|line| format!("{}\n", line))
+                .collect(),
+            Self::Whitespace => {
+                let trimmed: Vec<_> = contents.lines().map(str::trim_end).collect();
+                let end = trimmed.iter().rposition(|line| !line.is_empty()).map_or(0, |last| last + 1);
+
+                trimmed[..end].iter().map(|line| format!("{}\n", line)).collect()
+            }
+        }
+    }
+}
+
+/// The checksum scheme for newly applied revisions, being SHA-256 of their
+/// contents as they are unless given.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct ChecksumSettings {
+    #[serde(default)]
+    pub algorithm: Algorithm,
+    /// Differences in contents to ignore, by normalizing them away first
+    #[serde(default)]
+    pub normalize: Vec<Normalization>,
+}
+
+impl ChecksumSettings {
+    /// Checksums the contents, prefixed with the scheme unless it's the
+    /// default one.
+    pub fn checksum(&self, contents: &str) -> String {
+        let normalize = self.normalizations();
+        let normalized = self.normalized(contents);
+
+        let hash = match self.algorithm {
+            Algorithm::Sha256 => format!("{:x}", Sha256::digest(normalized.as_bytes())),
+            Algorithm::Sha512 => format!("{:x}", Sha512::digest(normalized.as_bytes())),
+        };
+
+        if self.algorithm == Algorithm::default() && normalize.is_empty() {
+            return hash;
+        }
+
+        let scheme: Vec<_> = std::iter::once(self.algorithm.as_str())
+            .chain(normalize.iter().map(Normalization::as_str))
+            .collect();
+
+        format!("{}:{}", scheme.join("+"), hash)
+    }
+
+    /// The contents with the differences the scheme ignores normalized away,
+    /// as they're checksummed.
+    pub fn normalized(&self, contents: &str) -> String {
+        self.normalizations()
+            .iter()
+            .fold(contents.to_string(), |contents, normalization| normalization.apply(&contents))
+    }
+
+    fn normalizations(&self) -> Vec<Normalization> {
+        let mut normalize = self.normalize.clone();
+        normalize.sort();
+        normalize.dedup();
+
+        normalize
+    }
+
+    /// The scheme a recorded checksum was made with, if it's known.
+    fn of(recorded: &str) -> Option<Self> {
+        let scheme = match recorded.split_once(':') {
+            Some((scheme, _)) => scheme,
+            None => return Some(Self::default()),
+        };
+
+        let mut parts = scheme.split('+');
+        let algorithm = parts.next().and_then(Algorithm::named)?;
+        let normalize = parts.map(Normalization::named).collect::<Option<_>>()?;
+
+        Some(Self { algorithm, normalize })
+    }
+}
+
+/// Whether the contents match a recorded checksum, as checksummed by the
+/// scheme it was made with rather than the one currently configured.
+pub fn matches(contents: &str, recorded: &str) -> bool {
+    match ChecksumSettings::of(recorded) {
+        Some(scheme) => scheme.checksum(contents) == recorded,
+        None => false,
+    }
+}
+
+/// The contents normalized by the scheme the recorded checksum was made
+/// with, so that comparing them leaves out differences it ignores, or as
+/// they are if the scheme isn't known.
+pub fn normalized_as(contents: &str, recorded: &str) -> String {
+    match ChecksumSettings::of(recorded) {
+        Some(scheme) => scheme.normalized(contents),
+        None => contents.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scheme(algorithm: Algorithm, normalize: &[Normalization]) -> ChecksumSettings {
+        ChecksumSettings { algorithm, normalize: normalize.to_vec() }
+    }
+
+    #[test]
+    fn default_scheme_is_unprefixed_sha256() {
+        assert_eq!(
+            ChecksumSettings::default().checksum("SELECT 1;"),
+            format!("{:x}", Sha256::digest(b"SELECT 1;")),
+        );
+        assert!(scheme(Algorithm::Sha512, &[]).checksum("SELECT 1;").starts_with("sha512:"));
+        assert!(scheme(Algorithm::Sha256, &[Normalization::Whitespace, Normalization::LineEndings])
+            .checksum("SELECT 1;")
+            .starts_with("sha256+line-endings+whitespace:"));
+    }
+
+    #[test]
+    fn normalization_ignores_differences() {
+        let all = scheme(
+            Algorithm::Sha256,
+            &[Normalization::LineEndings, Normalization::Whitespace, Normalization::Comments],
+        );
+
+        let original = "-- Revision: users\nCREATE TABLE users ();\n";
+        let edited = "-- Revision: users\r\n\r\n-- Holds users  \r\nCREATE TABLE users (); /* none yet */  \r\n\r\n";
+
+        assert_eq!(all.checksum(original), all.checksum(edited));
+        assert_ne!(all.checksum(original), all.checksum("CREATE TABLE users (id INT);\n"));
+        assert_ne!(
+            scheme(Algorithm::Sha256, &[Normalization::Whitespace]).checksum(original),
+            scheme(Algorithm::Sha256, &[Normalization::Whitespace]).checksum(edited),
+        );
+    }
+
+    #[test]
+    fn recorded_checksums_match_by_their_own_scheme() {
+        let contents = "CREATE TABLE users ();\n";
+        let crlf = "CREATE TABLE users ();\r\n";
+
+        let legacy = ChecksumSettings::default().checksum(contents);
+        let normalized = scheme(Algorithm::Sha512, &[Normalization::LineEndings]).checksum(contents);
+
+        assert!(matches(contents, &legacy));
+        assert!(!matches(crlf, &legacy));
+        assert!(matches(contents, &normalized));
+        assert!(matches(crlf, &normalized));
+        assert!(!matches(contents, "md5:0cc175b9c0f1b6a831c399e269772661"));
+    }
+}
@@ -4,10 +4,12 @@ use std::{
     time::Duration,
 };
 
-use postgres::{config::Config, NoTls};
+#[cfg(not(feature = "aws-auth"))]
+use postgres::NoTls;
+use postgres::config::Config;
 pub use postgres::Client;
 
-use crate::Environment;
+use crate::{environment::DatabaseAuth, secrets, Environment};
 
 impl TryFrom<&Environment> for Client {
     type Error = crate::Error;
@@ -17,12 +19,76 @@ impl TryFrom<&Environment> for Client {
 
         config.application_name("jrny");
 
+        // Tokens expire within minutes, so a new one is needed for each connection
+        if env.database.auth == DatabaseAuth::AwsIam {
+            config.password(secrets::rds_auth_token(&config, env.database.aws_region.as_deref())?);
+        } else if let Some(password) = &env.database.password {
+            config.password(password);
+        } else if let Some(reference) = &env.database.password_ref {
+            config.password(secrets::resolve(reference)?);
+        }
+
         if config.get_connect_timeout().is_none() {
             config.connect_timeout(Duration::new(30, 0));
         }
 
+        // Long statements leave connections idle for as long as they run, which
+        // firewalls and load balancers may drop well before the default of two
+        // hours without a keepalive
+        if config.get_keepalives_idle() == Config::new().get_keepalives_idle() {
+            config.keepalives_idle(Duration::new(60, 0));
+        }
+
+        #[cfg(feature = "aws-auth")]
+        let client = {
+            use postgres::config::SslMode;
+
+            // Prefering TLS would fall back to sending the token in the clear
+            if env.database.auth == DatabaseAuth::AwsIam && config.get_ssl_mode() == SslMode::Prefer {
+                config.ssl_mode(SslMode::Require);
+            }
+
+            // A token sent to a server that isn't verified could be used again
+            // by whoever it is
+            let verify = env.database.auth == DatabaseAuth::AwsIam;
+
+            config.connect(tls::connector(env.database.ssl_root_cert.as_deref(), verify)?)?
+        };
+        #[cfg(not(feature = "aws-auth"))]
         let client = config.connect(NoTls)?;
 
         Ok(client)
     }
 }
+
+/// TLS for connections as the URL's `sslmode` asks, which is built along with
+/// IAM authentication since RDS only accepts its tokens over TLS.
+#[cfg(feature = "aws-auth")]
+mod tls {
+    use std::path::Path;
+
+    use openssl::ssl::{SslConnector, SslMethod, SslVerifyMode};
+    use postgres_openssl::MakeTlsConnector;
+
+    use crate::{Error, Result};
+
+    /// Verifies the server's certificate and host name against the root
+    /// certificates if given, or the system's if the server must be verified
+    /// regardless, or else, like libpq's `sslmode=require`, only encrypts the
+    /// connection.
+    pub fn connector(root_cert: Option<&Path>, verify: bool) -> Result<MakeTlsConnector> {
+        let failed = |e: openssl::error::ErrorStack| Error::TlsNotConfigured(e.to_string());
+        let mut builder = SslConnector::builder(SslMethod::tls()).map_err(failed)?;
+
+        match root_cert {
+            Some(path) => builder.set_ca_file(path).map_err(|e| {
+                Error::TlsNotConfigured(format!("could not load `{}`: {}", path.display(), e))
+            })?,
+            // The connector already trusts the system's root certificates
+            None if verify => {}
+            None => builder.set_verify(SslVerifyMode::NONE),
+        }
+
+        Ok(MakeTlsConnector::new(builder.build()))
+    }
+}
@@ -0,0 +1,66 @@
+use std::{convert::TryFrom, str::FromStr};
+
+use log::info;
+use postgres::Client;
+
+use super::testing::quote_identifier;
+use crate::{config::BootstrapSettings, environment, executor::Executor, Environment, Error, Result};
+
+/// The database connected to on the environment's server to create its own,
+/// which every Postgres server has.
+const MAINTENANCE_DB: &str = "postgres";
+
+const DATABASE_EXISTS: &str = "SELECT EXISTS (SELECT 1 FROM pg_database WHERE datname = $1)";
+const ROLE_EXISTS: &str = "SELECT EXISTS (SELECT 1 FROM pg_roles WHERE rolname = $1)";
+
+/// Creates the environment's database, along with the role to own it, should
+/// either not exist yet, returning whether the database was created.
+pub(super) fn create_database(env: &Environment, settings: &BootstrapSettings) -> Result<bool> {
+    let url = postgres::Config::from_str(&env.database.url)?;
+    // As with `psql`, the database is named after the user unless named itself
+    let name = url
+        .get_dbname()
+        .or_else(|| url.get_user())
+        .ok_or(Error::DatabaseNameMissing)?
+        .to_string();
+
+    let mut maintenance = env.clone();
+    maintenance.database.url = environment::with_dbname(&env.database.url, MAINTENANCE_DB);
+    let mut client = Client::try_from(&maintenance)?;
+
+    if let Some(role) = &settings.role {
+        if !client.query_one(ROLE_EXISTS, &[role])?.get::<_, bool>(0) {
+            client.batch_execute(&format!("CREATE ROLE {}", quote_identifier(role)))?;
+            info!("Created role {}", role);
+        }
+    }
+
+    if client.query_one(DATABASE_EXISTS, &[&name])?.get::<_, bool>(0) {
+        info!("Database {} already exists", name);
+        return Ok(false);
+    }
+
+    let mut create = format!("CREATE DATABASE {}", quote_identifier(&name));
+
+    if let Some(role) = &settings.role {
+        create.push_str(&format!(" OWNER {}", quote_identifier(role)));
+    }
+    if let Some(template) = &settings.template {
+        create.push_str(&format!(" TEMPLATE {}", quote_identifier(template)));
+    }
+
+    client.batch_execute(&create)?;
+    info!("Created database {}", name);
+
+    Ok(true)
+}
+
+/// Creates each of the extensions that the database lacks.
+pub(super) fn create_extensions(exec: &mut Executor, extensions: &[String]) -> Result<()> {
+    for extension in exec.missing_extensions(extensions)? {
+        exec.run_unrecorded(&format!("CREATE EXTENSION {}", quote_identifier(&extension)))?;
+        info!("Created extension {}", extension);
+    }
+
+    Ok(())
+}
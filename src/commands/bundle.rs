@@ -0,0 +1,125 @@
+use std::{collections::HashMap, fs};
+
+use chrono::Utc;
+
+use crate::{
+    bundle::{Bundle, BundledFile},
+    executor::Executor,
+    revisions::{ReappliedKind, RepeatableFile, RevisionFile},
+    Config,
+    Environment,
+    Error,
+    Result,
+};
+
+/// Bundles every file in the revisions directory along with what the
+/// environment has recorded as applied.
+pub(super) fn export(cfg: &Config, env: &Environment, exec: &mut Executor) -> Result<Bundle> {
+    let revisions = &cfg.revisions.directory;
+    let (repeatables, invalid) = RepeatableFile::partition_from_disk(revisions, &cfg.revisions.checksum)?;
+
+    if let Some(invalid) = invalid.into_iter().next() {
+        return Err(invalid.error);
+    }
+
+    let files = RevisionFile::all_from_disk(&cfg.revisions)?
+        .into_iter()
+        .map(|file| BundledFile { filename: file.path_name(), contents: file.contents })
+        .chain(
+            repeatables
+                .into_iter()
+                .map(|file| BundledFile { filename: file.filename, contents: file.contents }),
+        )
+        .collect();
+
+    exec.ensure_table_exists()?;
+
+    Ok(Bundle { files, ..state(env, exec)? })
+}
+
+/// Only what the environment has recorded as applied, without any files,
+/// for reviewing against later without access to the database.
+pub(super) fn state(env: &Environment, exec: &mut Executor) -> Result<Bundle> {
+    Ok(Bundle {
+        exported_at: Utc::now(),
+        environment: env.name.clone(),
+        files: vec![],
+        revisions: exec.load_revisions()?,
+        repeatables: exec.load_reapplied(ReappliedKind::Repeatable)?,
+    })
+}
+
+/// Writes the bundled files missing from the revisions directory, returning
+/// their filenames. Nothing is written if any file already on disk differs
+/// from the bundle's.
+pub(super) fn restore(cfg: &Config, bundle: &Bundle) -> Result<Vec<String>> {
+    let revisions = &cfg.revisions.directory;
+    let mut missing = vec![];
+
+    for file in &bundle.files {
+        let path = revisions.join(&file.filename);
+
+        if !path.exists() {
+            missing.push(file);
+        } else if fs::read_to_string(&path)? != file.contents {
+            return Err(Error::BundleConflict(
+                file.filename.clone(),
+                "already exists with different contents".to_string(),
+            ));
+        }
+    }
+
+    for file in &missing {
+        let path = revisions.join(&file.filename);
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        fs::write(&path, &file.contents)?;
+    }
+
+    Ok(missing.into_iter().map(|file| file.filename.clone()).collect())
+}
+
+/// Records the bundled history that the environment doesn't already have,
+/// returning the filenames of those recorded.
+pub(super) fn record(exec: &mut Executor, bundle: &Bundle) -> Result<Vec<String>> {
+    exec.ensure_table_exists()?;
+
+    let recorded: HashMap<_, _> = exec.load_revisions()?.into_iter().map(|r| (r.id, r.filename)).collect();
+    let recorded_repeatables: Vec<_> = exec
+        .load_reapplied(ReappliedKind::Repeatable)?
+        .into_iter()
+        .map(|r| r.filename)
+        .collect();
+
+    let mut revisions = vec![];
+
+    for record in &bundle.revisions {
+        match recorded.get(&record.id) {
+            Some(filename) if *filename == record.filename => continue,
+            Some(filename) => {
+                return Err(Error::BundleConflict(
+                    record.filename.clone(),
+                    format!("id {} is already recorded as applied for `{}`", record.id, filename),
+                ))
+            }
+            None => revisions.push(record),
+        }
+    }
+
+    let repeatables: Vec<_> = bundle
+        .repeatables
+        .iter()
+        .filter(|record| !recorded_repeatables.contains(&record.filename))
+        .collect();
+
+    exec.record_bundled_history(&revisions, &repeatables)?;
+
+    Ok(revisions
+        .iter()
+        .map(|r| r.filename.clone())
+        .chain(repeatables.iter().map(|r| r.filename.clone()))
+        .collect())
+}
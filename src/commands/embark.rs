@@ -1,85 +1,996 @@
-use log::info;
+use std::{
+    fs,
+    ops::{Deref, DerefMut},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Utc};
+use log::{warn, Level};
 
-use super::Review;
+use super::{review::Review, script::Script};
 use crate::{
-    executor::Executor,
-    revisions::AnnotatedRevision,
+    analysis::{modified_table, Destroyed},
+    assertions::AssertionFile,
+    batch::{Batch, Batched},
+    checksum::ChecksumSettings,
+    config::MaintenanceSettings,
+    environment::{BackupSettings, MaxDurationPolicy},
+    events::EventStream,
+    executor::{Executor, StatementObserver},
+    freeze::{FreezeScope, FreezeWindow},
+    git::Repository,
+    heartbeat::Heartbeat,
+    meta::{RevisionMeta, ALLOW_DESTRUCTIVE, NO_TRANSACTION},
+    pacing::PacingSettings,
+    progress::{format_duration, Progress},
+    report::{RevisionOutcome, RevisionStatus, RunReport, StatementError, StatementOutcome},
+    retry::LockRetry,
+    revisions::{self, AnnotatedRepeatable, AnnotatedRevision, VERIFY_SUFFIX},
+    rewrite::{ExplainAnalyze, Rewriter, Rewrites},
+    statements::{self, Statement},
+    validation::SeparateValidation,
     Config,
+    Environment,
     Error,
     Result,
 };
 
 pub struct Embark {
     pub to_apply: Vec<AnnotatedRevision>,
+    /// Repeatable revisions that are new or changed, applied after all others
+    pub repeatables_to_apply: Vec<AnnotatedRepeatable>,
+    /// An ad-hoc script to run without recording it, eg. for `jrny exec`
+    script: Option<Script>,
+    /// The repository containing the revisions, if any
+    repo: Option<Repository>,
+    /// Where to back up tables before destructive statements, if at all
+    backup: Option<BackupSettings>,
+    /// What happens to revisions taking longer than their `max_duration`
+    on_max_duration: MaxDurationPolicy,
+    /// How statements are rewritten before they're executed
+    rewrites: Rewrites,
+    /// What's run before the first revision and after the last
+    maintenance: MaintenanceSettings,
+    /// Where to save the plans of statements changing data, if they're to
+    /// be explained as they're run
+    explain: Option<PathBuf>,
+    /// How many rows of a table a revision changes before it's analyzed
+    analyze_after_rows: Option<u64>,
+    /// The environment to watch long-running statements from, over a second
+    /// connection, if heartbeats are enabled
+    monitor: Option<Environment>,
+    /// Whether to terminate sessions found blocking statements from taking locks
+    terminate_blockers: bool,
+    /// Whether revisions marked idempotent may create what already exists
+    allow_idempotent: bool,
+    /// Whether every statement executed is recorded in the audit table
+    audit: bool,
+    /// How long to pause between statements and revisions
+    pacing: PacingSettings,
+    /// The name to prepare the transaction that every revision is applied
+    /// in under, for a two-phase commit, if they're to be
+    prepared: Option<String>,
+}
+
+/// Either kind of revision, or a script, as far as applying them is concerned.
+#[derive(Clone, Copy)]
+enum Pending<'a> {
+    Revision(&'a AnnotatedRevision),
+    Repeatable(&'a AnnotatedRepeatable),
+    Script(&'a Script),
+}
+
+impl<'a> Pending<'a> {
+    fn filename(&self) -> &str {
+        match self {
+            Self::Revision(revision) => &revision.filename,
+            Self::Repeatable(repeatable) => &repeatable.filename,
+            Self::Script(script) => &script.filename,
+        }
+    }
+
+    fn contents(&self) -> &str {
+        match self {
+            Self::Revision(revision) => revision
+                .contents
+                .as_ref()
+                .unwrap_or_else(|| panic!("No content for {}", revision.filename)),
+            Self::Repeatable(repeatable) => &repeatable.contents,
+            Self::Script(script) => &script.contents,
+        }
+    }
+
+    fn meta(&self) -> Option<&RevisionMeta> {
+        match self {
+            Self::Revision(revision) => revision.meta.as_ref(),
+            Self::Repeatable(repeatable) => Some(&repeatable.meta),
+            Self::Script(script) => Some(&script.meta),
+        }
+    }
+
+    /// The release group of the revision, as only versioned revisions have.
+    fn group(&self) -> Option<&'a str> {
+        match self {
+            Self::Revision(revision) => revision.group.as_deref(),
+            Self::Repeatable(_) | Self::Script(_) => None,
+        }
+    }
 }
 
 impl Embark {
-    pub fn prepare(cfg: &Config, exec: &mut Executor) -> Result<Self> {
-        let Review { mut revisions, .. } = Review::annotated_revisions(
-            exec,
-            &cfg.revisions.directory,
-        )?;
+    pub fn prepare(cfg: &Config, env: &Environment, exec: &mut Executor) -> Result<Self> {
+        Self::from_review(cfg, env, Review::annotated_revisions(exec, &cfg.revisions, env)?)
+    }
+
+    /// As with `prepare`, but from revisions already reviewed against the
+    /// environment's history.
+    pub(super) fn from_review(cfg: &Config, env: &Environment, review: Review) -> Result<Self> {
+        review.check()?;
+
+        let Review { mut revisions, mut repeatables, .. } = review;
+        let mut to_apply: Vec<_> = revisions
+            .drain(..)
+            .filter(|anno| anno.applied_on.is_none() && anno.applicable)
+            .collect();
+        let repeatables_to_apply: Vec<_> = repeatables
+            .drain(..)
+            .filter(|anno| anno.is_pending() && anno.applicable && !anno.is_draft())
+            .collect();
+
+        // Applying revisions after a draft would leave it out of order once
+        // it's finished, so they wait for it
+        if let Some(i) = to_apply.iter().position(AnnotatedRevision::is_draft) {
+            let held = to_apply.split_off(i);
+
+            warn!(
+                "Stopping before draft revision {}, leaving it and {} later revision(s) pending",
+                held[0].filename,
+                held.len() - 1,
+            );
+        }
+
+        let embark = Self {
+            to_apply,
+            repeatables_to_apply,
+            script: None,
+            repo: Repository::discover(&cfg.revisions.directory),
+            backup: env.backup.clone().filter(|backup| backup.before_destructive),
+            on_max_duration: env.on_max_duration,
+            rewrites: Rewrites::from_settings(&cfg.rewrite),
+            maintenance: cfg.maintenance.clone(),
+            explain: None,
+            analyze_after_rows: env.analyze_after_rows,
+            monitor: Some(env.clone()).filter(|env| env.heartbeat.enabled),
+            terminate_blockers: false,
+            allow_idempotent: cfg.revisions.allow_idempotent,
+            audit: cfg.table.audit,
+            pacing: env.pacing.clone(),
+            prepared: None,
+        };
+
+        if env.protected {
+            embark.check_destructive()?;
+        }
+        embark.check_batches()?;
+        embark.check_lock_retries()?;
+        embark.check_validations()?;
+        embark.check_groups()?;
+
+        Ok(embark)
+    }
+
+    /// Applies only the given repeatable revisions or seeds, eg. for `jrny seed`.
+    pub fn reapply(cfg: &Config, repeatables: Vec<AnnotatedRepeatable>) -> Self {
+        Self {
+            to_apply: vec![],
+            repeatables_to_apply: repeatables,
+            script: None,
+            repo: None,
+            backup: None,
+            on_max_duration: MaxDurationPolicy::Warn,
+            rewrites: Rewrites::from_settings(&cfg.rewrite),
+            maintenance: MaintenanceSettings::default(),
+            explain: None,
+            analyze_after_rows: None,
+            monitor: None,
+            terminate_blockers: false,
+            allow_idempotent: cfg.revisions.allow_idempotent,
+            audit: cfg.table.audit,
+            pacing: PacingSettings::default(),
+            prepared: None,
+        }
+    }
+
+    /// Runs only the given script, eg. for `jrny exec`, with the same checks
+    /// and settings as the environment's revisions.
+    pub(super) fn script(cfg: &Config, env: &Environment, script: Script) -> Result<Self> {
+        let embark = Self {
+            to_apply: vec![],
+            repeatables_to_apply: vec![],
+            script: Some(script),
+            repo: None,
+            backup: env.backup.clone().filter(|backup| backup.before_destructive),
+            on_max_duration: env.on_max_duration,
+            rewrites: Rewrites::from_settings(&cfg.rewrite),
+            maintenance: MaintenanceSettings::default(),
+            explain: None,
+            analyze_after_rows: env.analyze_after_rows,
+            monitor: Some(env.clone()).filter(|env| env.heartbeat.enabled),
+            terminate_blockers: false,
+            allow_idempotent: cfg.revisions.allow_idempotent,
+            audit: cfg.table.audit,
+            pacing: env.pacing.clone(),
+            prepared: None,
+        };
+
+        if env.protected {
+            embark.check_destructive()?;
+        }
+        embark.check_batches()?;
+        embark.check_lock_retries()?;
+        embark.check_validations()?;
+
+        Ok(embark)
+    }
+
+    /// Also applies the given rewriters to statements, after those configured.
+    pub fn with_rewriters(mut self, rewriters: &[Arc<dyn Rewriter>]) -> Self {
+        self.rewrites.extend(rewriters);
+        self
+    }
+
+    /// Also terminates sessions found blocking statements from taking locks,
+    /// provided heartbeats are enabled to find them.
+    pub fn terminating_blockers(mut self) -> Self {
+        self.terminate_blockers = true;
+        self
+    }
+
+    /// Pauses whenever replicas lag further behind than given, rather than as
+    /// far as the environment allows, if at all.
+    pub fn limiting_replication_lag(mut self, max: Duration) -> Self {
+        self.pacing.max_replication_lag = Some(max);
+        self
+    }
+
+    /// Applies every pending revision in a single transaction, which is
+    /// prepared for a two-phase commit under the name rather than committed,
+    /// failing unless each of them can be applied within a transaction.
+    pub fn preparing(mut self, gid: &str) -> Result<Self> {
+        for revision in self.pending() {
+            if let Some((line, reason)) = outside_transaction(revision.contents()) {
+                return Err(Error::PreparedTransactionInvalid(format!("{}:{}", revision.filename(), line), reason.to_string()));
+            }
+        }
+
+        self.prepared = Some(gid.to_string());
+        Ok(self)
+    }
+
+    /// Also runs statements changing data with `EXPLAIN (ANALYZE, BUFFERS)`,
+    /// saving each plan to the directory, eg. when rehearsing against a copy
+    /// of production. Their rows affected aren't known as a result.
+    pub fn explaining(mut self, dir: &Path) -> Self {
+        self.rewrites.extend(&[Arc::new(ExplainAnalyze)]);
+        self.explain = Some(dir.to_path_buf());
+        self
+    }
+
+    /// The first of the environment's freeze windows in effect at the moment
+    /// that holds back any of the pending revisions, and when it ends.
+    pub fn frozen_by<'e>(&self, env: &'e Environment, now: DateTime<Utc>) -> Option<(&'e FreezeWindow, DateTime<Utc>)> {
+        let destructive = || {
+            self.pending().any(|revision| {
+                statements::split(revision.contents()).iter().any(|statement| statement.destructive_operation().is_some())
+            })
+        };
+
+        env.freeze
+            .iter()
+            .filter_map(|window| Some((window, window.in_effect(now)?)))
+            .find(|(window, _)| window.applies_to == FreezeScope::All || destructive())
+    }
+
+    /// The number of revisions of either kind, or scripts, to apply.
+    pub fn len(&self) -> usize {
+        self.to_apply.len() + self.repeatables_to_apply.len() + self.script.iter().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The transaction the revision is applied in along with others, by name,
+    /// which is the prepared transaction for every revision if there's to be
+    /// one, or else the revision's release group.
+    fn group_of<'a>(&'a self, revision: Pending<'a>) -> Option<&'a str> {
+        self.prepared.as_deref().or_else(|| revision.group())
+    }
+
+    /// Revisions of either kind, and any script, in the order they are applied.
+    fn pending(&self) -> impl Iterator<Item = Pending<'_>> {
+        self.to_apply
+            .iter()
+            .map(Pending::Revision)
+            .chain(self.repeatables_to_apply.iter().map(Pending::Repeatable))
+            .chain(self.script.iter().map(Pending::Script))
+    }
+
+    /// Fails if any statement to be applied is destructive without its revision
+    /// acknowledging as much with a directive.
+    fn check_destructive(&self) -> Result<()> {
+        let unacknowledged: Vec<_> = self
+            .pending()
+            .filter(|revision| !revision.meta().is_some_and(|meta| meta.has_directive(ALLOW_DESTRUCTIVE)))
+            .flat_map(|revision| {
+                statements::split(revision.contents())
+                    .into_iter()
+                    .filter_map(move |statement| {
+                        statement
+                            .destructive_operation()
+                            .map(|operation| format!("{}:{}  {}", revision.filename(), statement.line, operation))
+                    })
+            })
+            .collect();
+
+        if !unacknowledged.is_empty() {
+            return Err(Error::DestructiveStatementsFound(unacknowledged));
+        }
+
+        Ok(())
+    }
 
-        // TODO this is copypasta
-        let mut last_applied_index = -1;
+    /// Fails if any batch directive is invalid, before anything is applied
+    /// rather than once its revision is reached.
+    fn check_batches(&self) -> Result<()> {
+        for revision in self.pending() {
+            for statement in statements::split(revision.contents()) {
+                let invalid = |reason| Error::BatchInvalid(format!("{}:{}", revision.filename(), statement.line), reason);
 
-        for (i, revision) in revisions.iter().enumerate() {
-            if revision.applied_on.is_some() {
-                last_applied_index = i as isize;
+                if let Some(batch) = Batch::of(&statement).map_err(invalid)? {
+                    Batched::new(&statement, batch).map_err(invalid)?;
+                }
             }
         }
 
-        // This has gotten pretty inelegant, so refactor at some point,
-        // and there's more copy-pasta with the "previous id" thing
-        let (mut changed, mut duplicate_ids, mut missing, mut predate_applied) = (0, 0, 0, 0);
-        let mut previous_id = None;
+        Ok(())
+    }
+
+    /// Fails if any lock-retry directive is invalid, or is given where its
+    /// statement couldn't be tried again, as when batched or in a revision
+    /// managing its own transactions.
+    fn check_lock_retries(&self) -> Result<()> {
+        for revision in self.pending() {
+            let statements = statements::split(revision.contents());
+            let managed = statements.iter().any(Statement::is_transaction_command);
+
+            for statement in &statements {
+                let invalid = |reason| Error::LockRetryInvalid(format!("{}:{}", revision.filename(), statement.line), reason);
 
-        for (i, revision) in revisions.iter().enumerate() {
-            // If checksum comparison is missing, it hasn't been applied so ignore it
-            if !revision.checksums_match.unwrap_or(true) {
-                changed += 1;
+                if LockRetry::of(statement).map_err(invalid)?.is_none() {
+                    continue;
+                }
+                if matches!(Batch::of(statement), Ok(Some(_))) {
+                    return Err(invalid("batched statements can't also be retried".to_string()));
+                }
+                if managed {
+                    return Err(invalid("revisions managing their own transactions can't retry statements".to_string()));
+                }
             }
+        }
 
-            if !revision.on_disk {
-                missing += 1;
+        Ok(())
+    }
+
+    /// Fails if any validate-separately directive is invalid, or is given in
+    /// a revision managing its own transactions, which it would be validated
+    /// within.
+    fn check_validations(&self) -> Result<()> {
+        for revision in self.pending() {
+            let statements = statements::split(revision.contents());
+            let managed = statements.iter().any(Statement::is_transaction_command);
+
+            for statement in &statements {
+                let invalid = |reason| Error::ValidationInvalid(format!("{}:{}", revision.filename(), statement.line), reason);
+
+                if SeparateValidation::of(statement).map_err(invalid)?.is_some() && managed {
+                    return Err(invalid("revisions managing their own transactions can't validate separately".to_string()));
+                }
             }
+        }
+
+        Ok(())
+    }
 
-            if revision.applied_on.is_none() && (i as isize) < last_applied_index {
-                predate_applied += 1;
+    /// Fails if any release group can't be applied in a single transaction,
+    /// either as its pending revisions aren't consecutive or as one of them
+    /// can't be applied within a transaction.
+    fn check_groups(&self) -> Result<()> {
+        let (mut seen, mut previous) = (vec![], None);
+
+        for revision in self.pending() {
+            let group = match revision.group() {
+                Some(group) if previous == Some(group) => group,
+                Some(group) if seen.contains(&group) => {
+                    return Err(Error::ReleaseGroupInvalid(
+                        group.to_string(),
+                        format!("`{}` comes after revisions outside of the group", revision.filename()),
+                    ));
+                }
+                Some(group) => {
+                    seen.push(group);
+                    group
+                }
+                None => {
+                    previous = None;
+                    continue;
+                }
+            };
+            previous = Some(group);
+
+            if let Some((line, reason)) = outside_transaction(revision.contents()) {
+                return Err(Error::ReleaseGroupInvalid(
+                    group.to_string(),
+                    format!("`{}:{}` {}", revision.filename(), line, reason),
+                ));
             }
+        }
+
+        Ok(())
+    }
+
+    /// Backs up the tables that the revision's destructive statements affect,
+    /// if configured to, before it's applied.
+    fn back_up(&self, exec: &mut Executor, progress: &Progress, filename: &str, statements: &[Statement]) -> Result<()> {
+        let settings = match &self.backup {
+            Some(settings) => settings,
+            None => return Ok(()),
+        };
+        let destroyed: Vec<_> = statements.iter().flat_map(Destroyed::all_for).collect();
 
-            if previous_id == Some(revision.id) {
-                duplicate_ids += 1;
+        if destroyed.is_empty() {
+            return Ok(());
+        }
+
+        for (source, location) in exec.back_up(filename, &destroyed, settings)? {
+            progress.log(Level::Info, &format!("    backed up {} to {}", source, location));
+        }
+
+        Ok(())
+    }
+
+    /// Starts watching the executor's statements for heartbeats, if enabled,
+    /// warning rather than failing should it not be possible.
+    fn heartbeat(&self, exec: &mut Executor, progress: &Progress) -> Option<Heartbeat> {
+        let env = self.monitor.as_ref()?;
+
+        match exec.backend_pid() {
+            Ok(pid) => Some(Heartbeat::start(env, pid, self.terminate_blockers, progress.log_handle())),
+            Err(e) => {
+                progress.log(Level::Warn, &format!("  Heartbeats disabled, could not find the connection's process: {}", e));
+                None
+            }
+        }
+    }
+
+    /// Analyzes each table the revision changed more rows of than allowed,
+    /// warning rather than failing should any not be, since the revision has
+    /// already been applied.
+    /// Records the statements of the revisions in the audit table, if it's
+    /// enabled, as only a warning should that fail.
+    fn audit(&self, exec: &mut Executor, progress: &Progress, outcomes: &[RevisionOutcome]) {
+        if !self.audit || outcomes.iter().all(|outcome| outcome.statements.is_empty()) {
+            return;
+        }
+
+        if let Err(e) = exec.audit(outcomes) {
+            progress.log(Level::Warn, &format!("  Could not record statements in the audit table: {}", e));
+        }
+    }
+
+    fn analyze(&self, exec: &mut Executor, progress: &Progress, changed: &[(String, u64)]) {
+        let threshold = match self.analyze_after_rows {
+            Some(threshold) => threshold,
+            None => return,
+        };
+
+        for (table, rows) in changed.iter().filter(|(_, rows)| *rows > threshold) {
+            progress.log(Level::Info, &format!("    analyzing {} after {} row(s) changed", table, rows));
+
+            if let Err(e) = exec.analyze(table) {
+                progress.log(Level::Warn, &format!("    Could not analyze {}: {}", table, e));
             }
+        }
+    }
 
-            previous_id = Some(revision.id);
+    /// The SQL that applying each pending revision in order would execute,
+    /// for running by hand instead. Backups aren't included, as they're
+    /// taken by jrny itself.
+    pub fn emit(&self, exec: &Executor) -> Result<String> {
+        let mut sql = String::new();
+        let mut group = None;
+
+        if let Some(enable) = &self.maintenance.enable_sql {
+            sql.push_str(&format!("\n-- Enable maintenance mode\n{}\n", enable.trim_end()));
         }
 
-        if changed + duplicate_ids + missing + predate_applied > 0 {
-            return Err(Error::RevisionsFailedReview {
-                changed,
-                duplicate_ids,
-                missing,
-                predate_applied,
+        for revision in self.pending() {
+            let filename = revision.filename();
+
+            if group.is_some() && revision.group() != group {
+                sql.push_str(&exec.commit_group_sql());
+                group = None;
+            }
+            if let Some(name) = revision.group().filter(|_| group.is_none()) {
+                sql.push_str(&format!("\n-- Release group {}, applied in one transaction\n", name));
+                sql.push_str(&exec.begin_group_sql());
+                group = revision.group();
+            }
+
+            let statements = self.rewrites.apply(statements::split(revision.contents()));
+
+            if self.backup.is_some() && statements.iter().any(|statement| !Destroyed::all_for(statement).is_empty()) {
+                warn!("{} is destructive, but the emitted SQL won't back up any tables first", filename);
+            }
+
+            let max_duration = revision.meta().and_then(|meta| meta.max_duration);
+            let timeout = max_duration.filter(|_| self.on_max_duration == MaxDurationPolicy::Cancel);
+
+            if revision.meta().is_some_and(|meta| !meta.timeout.is_empty()) {
+                return Err(Error::SqlNotEmittable(format!(
+                    "`{}` has escalating timeouts, which needs jrny to try each statement again",
+                    filename,
+                )));
+            }
+            if matches!(revision, Pending::Revision(revision) if revision.verify.is_some()) {
+                return Err(Error::SqlNotEmittable(format!(
+                    "`{}` has a `{}` file, which needs jrny to check what its queries return",
+                    filename, VERIFY_SUFFIX,
+                )));
+            }
+
+            sql.push_str(&format!("\n-- {}\n", filename));
+            sql.push_str(&match revision {
+                Pending::Revision(revision) => {
+                    let provenance = self.repo.as_ref().map(|r| r.provenance(filename)).unwrap_or_default();
+                    exec.revision_sql(revision, &provenance, &statements, timeout, group.is_some())?
+                }
+                Pending::Repeatable(repeatable) => exec.repeatable_sql(repeatable, &statements, timeout)?,
+                Pending::Script(_) => unreachable!("scripts are only ever run"),
             });
         }
 
-        let to_apply: Vec<_> = revisions
-            .drain(..)
-            .filter(|anno| anno.applied_on.is_none())
-            .collect();
+        if group.is_some() {
+            sql.push_str(&exec.commit_group_sql());
+        }
+
+        // Without jrny to guarantee it's run, it's only as reliable as whatever runs the SQL
+        if let Some(disable) = &self.maintenance.disable_sql {
+            sql.push_str(&format!("\n-- Disable maintenance mode\n{}\n", disable.trim_end()));
+        }
 
-        Ok(Self { to_apply })
+        Ok(sql)
     }
 
-    pub fn apply(self, exec: &mut Executor) -> Result<()> {
-        info!("Applying {} revision(s)\n", self.to_apply.len());
+    /// Applies each pending revision in order, stopping at the first failure.
+    /// The report covers the whole run, including revisions skipped due to
+    /// an earlier failure.
+    pub fn apply(&self, exec: &mut Executor, events: EventStream) -> (RunReport, Result<()>) {
+        events.run_start(self.len());
 
-        for revision in &self.to_apply {
-            info!("  {}", revision.filename);
-            exec.run_revision(&revision)?;
+        let started_at = Utc::now();
+        let progress = Progress::start(self.len());
+        let mut outcomes = vec![];
+        let heartbeat = self.heartbeat(exec, &progress);
+        exec.pace(self.pacing.clone());
+        let mut exec = MaintenanceMode::new(exec, &self.maintenance, &progress);
+        let mut result = exec.enable();
+        // The release group whose transaction is open, if any, and where its
+        // revisions' outcomes start, with every revision in the one group
+        // when preparing a transaction
+        let mut group: Option<(&str, usize)> = None;
+        let prepared = self.prepared.is_some();
+        // Statements are audited between transactions, so that the records
+        // of those rolled back are kept, up to the revisions audited so far
+        let mut audited = 0;
+
+        for revision in self.pending() {
+            let filename = revision.filename();
+
+            if let Some((name, start)) = group.filter(|(name, _)| result.is_err() || self.group_of(revision) != Some(*name)) {
+                end_group(&mut exec, &progress, name, prepared, &mut outcomes[start..], &mut result);
+                group = None;
+            }
+            if group.is_none() {
+                self.audit(&mut exec, &progress, &outcomes[audited..]);
+                audited = outcomes.len();
+            }
+            if result.is_ok() {
+                result = exec.pause_before_revision(outcomes.is_empty(), &mut Pauses { progress: &progress });
+            }
+
+            if result.is_err() {
+                let outcome = RevisionOutcome {
+                    filename: filename.to_string(),
+                    status: RevisionStatus::Skipped,
+                    started_at: Utc::now(),
+                    elapsed: Duration::default(),
+                    statements: vec![],
+                };
+
+                events.revision_done(&outcome);
+                outcomes.push(outcome);
+                continue;
+            }
+
+            if let Some(name) = self.group_of(revision).filter(|_| group.is_none()) {
+                match prepared {
+                    true => progress.log(Level::Info, &format!("  Applying every revision in one transaction, prepared as {}", name)),
+                    false => progress.log(Level::Info, &format!("  Applying release group {} in one transaction", name)),
+                }
+                group = Some((name, outcomes.len()));
+                result = exec.begin_group();
+            }
+
+            let statements = self.rewrites.apply(statements::split(revision.contents()));
+
+            progress.revision(filename, statements.len());
+            events.revision_start(filename, statements.len());
+
+            let started_at = Utc::now();
+            let started = Instant::now();
+            let mut observer = Observer {
+                progress: &progress,
+                events,
+                filename,
+                started_at,
+                outcomes: vec![],
+                explain: self.explain.as_deref(),
+                explained: false,
+                changed: vec![],
+                heartbeat: heartbeat.as_ref(),
+            };
+
+            let max_duration = revision.meta().and_then(|meta| meta.max_duration);
+            let timeout = max_duration.filter(|_| self.on_max_duration == MaxDurationPolicy::Cancel);
+
+            exec.tolerate_existing(self.allow_idempotent && revision.meta().is_some_and(|meta| meta.idempotent));
+            exec.escalate_timeouts(revision.meta().map_or(&[], |meta| meta.timeout.as_slice()));
+
+            result = result.and_then(|_| self.back_up(&mut exec, &progress, filename, &statements)).and_then(|_| match revision {
+                Pending::Revision(revision) => {
+                    let provenance = self.repo.as_ref().map(|r| r.provenance(filename)).unwrap_or_default();
+                    exec.assert_after(revision.verify.as_deref().map(|verify| {
+                        AssertionFile::new(&revisions::companion_name(filename, VERIFY_SUFFIX), verify)
+                    }));
+                    exec.run_revision(revision, &provenance, &statements, &mut observer, timeout)
+                }
+                Pending::Repeatable(repeatable) => exec.run_repeatable(repeatable, &statements, &mut observer, timeout),
+                Pending::Script(script) => exec.run_script(&script.filename, &statements, &mut observer, timeout),
+            });
+
+            if result.is_ok() {
+                self.analyze(&mut exec, &progress, &observer.changed);
+            }
+
+            let outcome = RevisionOutcome {
+                filename: filename.to_string(),
+                status: if result.is_ok() { RevisionStatus::Applied } else { RevisionStatus::Failed },
+                started_at,
+                elapsed: started.elapsed(),
+                statements: observer.outcomes,
+            };
+
+            // Revisions that were cancelled have already failed
+            if let Some(max) = max_duration.filter(|max| result.is_ok() && outcome.elapsed > *max) {
+                let too_slow = Error::RevisionTooSlow(filename.to_string(), outcome.elapsed, max);
+
+                match self.on_max_duration {
+                    MaxDurationPolicy::Fail => result = Err(too_slow),
+                    _ => progress.log(Level::Warn, &format!("    {}", too_slow)),
+                }
+            }
+
+            events.revision_done(&outcome);
+            outcomes.push(outcome);
         }
 
-        Ok(())
+        if let Some((name, start)) = group {
+            end_group(&mut exec, &progress, name, prepared, &mut outcomes[start..], &mut result);
+        }
+        self.audit(&mut exec, &progress, &outcomes[audited..]);
+
+        // A failure to disable maintenance mode only fails an otherwise successful run
+        let disabled = exec.disable();
+        result = result.and(disabled);
+
+        let elapsed = progress.elapsed();
+        drop(exec);
+        drop(heartbeat);
+        drop(progress);
+
+        let report = RunReport {
+            started_at,
+            elapsed,
+            revisions: outcomes,
+            error: result.as_ref().err().map(ToString::to_string),
+            error_code: result.as_ref().err().map(Error::id),
+            tests: vec![],
+        };
+
+        events.run_done(&report);
+
+        (report, result)
+    }
+}
+
+/// Runs the SQL enabling maintenance mode, if any, and guarantees that the
+/// SQL disabling it is run once done, even should applying revisions fail
+/// or panic, by running it when dropped unless already disabled.
+struct MaintenanceMode<'a> {
+    exec: &'a mut Executor,
+    settings: &'a MaintenanceSettings,
+    progress: &'a Progress,
+    disabled: bool,
+}
+
+impl<'a> MaintenanceMode<'a> {
+    fn new(exec: &'a mut Executor, settings: &'a MaintenanceSettings, progress: &'a Progress) -> Self {
+        Self { exec, settings, progress, disabled: false }
+    }
+
+    fn enable(&mut self) -> Result<()> {
+        let sql = match &self.settings.enable_sql {
+            Some(sql) => sql,
+            None => return Ok(()),
+        };
+
+        self.progress.log(Level::Info, "  Enabling maintenance mode");
+        self.exec
+            .run_unrecorded(sql)
+            .map_err(|e| Error::MaintenanceSqlFailed("enable".to_string(), e.to_string()))
+    }
+
+    fn disable(&mut self) -> Result<()> {
+        self.disabled = true;
+
+        let sql = match &self.settings.disable_sql {
+            Some(sql) => sql,
+            None => return Ok(()),
+        };
+
+        self.progress.log(Level::Info, "  Disabling maintenance mode");
+        self.exec
+            .run_unrecorded(sql)
+            .map_err(|e| Error::MaintenanceSqlFailed("disable".to_string(), e.to_string()))
+    }
+}
+
+impl Deref for MaintenanceMode<'_> {
+    type Target = Executor;
+
+    fn deref(&self) -> &Executor {
+        self.exec
+    }
+}
+
+impl DerefMut for MaintenanceMode<'_> {
+    fn deref_mut(&mut self) -> &mut Executor {
+        self.exec
+    }
+}
+
+impl Drop for MaintenanceMode<'_> {
+    fn drop(&mut self) {
+        if !self.disabled {
+            if let Err(e) = self.disable() {
+                self.progress.log(Level::Warn, &format!("  {}", e));
+            }
+        }
+    }
+}
+
+/// Commits the release group's transaction if all of its revisions were
+/// applied, or prepares it under the group's name if it's to be, or otherwise
+/// rolls it back, along with those that were.
+fn end_group(
+    exec: &mut Executor,
+    progress: &Progress,
+    name: &str,
+    prepared: bool,
+    outcomes: &mut [RevisionOutcome],
+    result: &mut Result<()>,
+) {
+    let what = match prepared {
+        true => format!("transaction {}", name),
+        false => format!("release group {}", name),
+    };
+
+    if result.is_ok() {
+        *result = if prepared { exec.prepare_group(name) } else { exec.commit_group() };
+    } else if let Err(e) = exec.rollback_group() {
+        progress.log(Level::Warn, &format!("  Could not roll back {}: {}", what, e));
+    }
+
+    if result.is_err() {
+        let applied = outcomes.iter_mut().filter(|outcome| outcome.status == RevisionStatus::Applied);
+        let count = applied.map(|outcome| outcome.status = RevisionStatus::RolledBack).count();
+
+        progress.log(Level::Warn, &format!("  Rolled back {} ({} revision(s) undone)", what, count));
+    }
+}
+
+/// The line of the first statement in the revision that can't be applied
+/// within a transaction, if any, and why.
+fn outside_transaction(contents: &str) -> Option<(usize, &'static str)> {
+    statements::split(contents).iter().find_map(|statement| {
+        let reason = if statement.is_transaction_command() {
+            "manages its own transactions"
+        } else if statement.has_directive(NO_TRANSACTION) {
+            "runs without a transaction"
+        } else if Batch::of(statement).ok().flatten().is_some() {
+            "is batched"
+        } else if SeparateValidation::of(statement).ok().flatten().is_some() {
+            "validates a constraint separately"
+        } else {
+            return None;
+        };
+
+        Some((statement.line, reason))
+    })
+}
+
+/// Logs pauses for replicas to catch up.
+struct Pauses<'a> {
+    progress: &'a Progress,
+}
+
+impl StatementObserver for Pauses<'_> {
+    fn lagging(&mut self, lag: Duration, max: Duration) {
+        self.progress.log(
+            Level::Warn,
+            &format!(
+                "    Replicas are {} behind, beyond the {} allowed, pausing until they catch up",
+                format_duration(lag),
+                format_duration(max),
+            ),
+        );
+    }
+
+    fn caught_up(&mut self, waited: Duration) {
+        self.progress.log(Level::Info, &format!("    Replicas caught up after {}", format_duration(waited)));
+    }
+}
+
+/// Updates progress and records outcomes as statements are executed.
+struct Observer<'a> {
+    progress: &'a Progress,
+    events: EventStream,
+    /// The revision the statements belong to
+    filename: &'a str,
+    /// When the current statement started
+    started_at: DateTime<Utc>,
+    outcomes: Vec<StatementOutcome>,
+    /// Where to save the plans of explained statements, if at all
+    explain: Option<&'a Path>,
+    /// Whether the current statement was explained, so didn't count its rows
+    explained: bool,
+    /// The rows changed in each table, as named by the statements
+    changed: Vec<(String, u64)>,
+    heartbeat: Option<&'a Heartbeat>,
+}
+
+impl Observer<'_> {
+    fn record(&mut self, statement: &Statement, elapsed: Duration, rows: Option<u64>, error: Option<StatementError>) {
+        if let Some(heartbeat) = self.heartbeat {
+            heartbeat.done();
+        }
+
+        self.outcomes.push(StatementOutcome {
+            line: statement.line,
+            summary: statement.summary(),
+            checksum: ChecksumSettings::default().checksum(&statement.sql),
+            started_at: self.started_at,
+            elapsed,
+            rows,
+            error,
+        });
+
+        self.events.statement_done(self.filename, self.outcomes.last().unwrap());
+    }
+}
+
+impl StatementObserver for Observer<'_> {
+    fn before(&mut self, statement: &Statement) {
+        self.started_at = Utc::now();
+        self.progress.statement();
+
+        if let Some(heartbeat) = self.heartbeat {
+            heartbeat.statement(statement);
+        }
+    }
+
+    fn after(&mut self, statement: &Statement, elapsed: Duration, rows: u64) {
+        let rows = Some(rows).filter(|_| !self.explained);
+
+        if let Some((table, rows)) = modified_table(statement).zip(rows.filter(|rows| *rows > 0)) {
+            match self.changed.iter_mut().find(|(changed, _)| *changed == table) {
+                Some((_, total)) => *total += rows,
+                None => self.changed.push((table, rows)),
+            }
+        }
+
+        self.explained = false;
+        self.record(statement, elapsed, rows, None);
+    }
+
+    fn batch(&mut self, _statement: &Statement, batches: usize, rows: u64) {
+        self.progress.batch(batches, rows);
+    }
+
+    fn plan(&mut self, statement: &Statement, plan: &str) {
+        let dir = match self.explain {
+            Some(dir) => dir,
+            None => return,
+        };
+
+        self.explained = true;
+
+        let stem = Path::new(self.filename).file_stem().unwrap_or_default().to_string_lossy();
+        let path = dir.join(format!("{}.{}.txt", stem, statement.line));
+        let contents = format!("-- {}:{}\n-- {}\n\n{}\n", self.filename, statement.line, statement.summary(), plan);
+
+        if let Err(e) = fs::write(&path, contents) {
+            self.progress.log(Level::Warn, &format!("    Could not save plan to {}: {}", path.display(), e));
+        }
+    }
+
+    fn retrying(&mut self, statement: &Statement, attempt: u32, wait: Duration) {
+        self.progress.log(
+            Level::Warn,
+            &format!(
+                "    line {} could not take its lock in time (attempt {}), trying again in {}",
+                statement.line,
+                attempt,
+                format_duration(wait),
+            ),
+        );
+    }
+
+    fn lagging(&mut self, lag: Duration, max: Duration) {
+        Pauses { progress: self.progress }.lagging(lag, max);
+    }
+
+    fn caught_up(&mut self, waited: Duration) {
+        Pauses { progress: self.progress }.caught_up(waited);
+    }
+
+    fn timed_out(&mut self, statement: &Statement, timeout: Duration, next: Duration) {
+        self.progress.log(
+            Level::Warn,
+            &format!(
+                "    line {} timed out after {}, trying again with {}",
+                statement.line,
+                format_duration(timeout),
+                format_duration(next),
+            ),
+        );
+    }
+
+    fn existing(&mut self, statement: &Statement, error: &postgres::Error) {
+        let reason = error.as_db_error().map_or(error.to_string(), |e| e.message().to_string());
+
+        self.progress.log(Level::Warn, &format!("    line {} skipped, as the revision is idempotent: {}", statement.line, reason));
+    }
+
+    fn failed(&mut self, statement: &Statement, elapsed: Duration, error: &postgres::Error) {
+        let error = StatementError {
+            code: error.code().map(|code| code.code().to_string()),
+            message: error.to_string(),
+        };
+
+        self.record(statement, elapsed, None, Some(error));
     }
 }
@@ -0,0 +1,126 @@
+use std::{
+    fs,
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+};
+
+use super::{begin::ENV_STUB_TEMPLATE, EnvAddOptions};
+use crate::{environment, Environment, Error, Result};
+
+/// An environment file in the project's directory, along with the
+/// environment it gives, local file and all, or why it can't be read.
+pub(super) struct EnvironmentFile {
+    pub path: PathBuf,
+    /// The name in its filename, which is `None` for `jrny-env.toml`
+    pub name: Option<String>,
+    pub env: Result<Environment>,
+    /// Whether it has a local file whose settings take precedence
+    pub has_local: bool,
+}
+
+impl EnvironmentFile {
+    /// Every environment file in the directory, with `jrny-env.toml` first
+    /// and the rest by name.
+    pub fn find(dir: &Path) -> Result<Vec<Self>> {
+        let mut files = vec![];
+
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+
+            if !path.is_file() {
+                continue;
+            }
+
+            if let Some(name) = environment::name_from_filepath(&path) {
+                files.push(Self {
+                    env: Environment::from_filepath(&path),
+                    has_local: environment::local_filepath(&path).is_file(),
+                    name,
+                    path,
+                });
+            }
+        }
+
+        files.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Ok(files)
+    }
+}
+
+/// The environment file at the path for the new environment, checked to be
+/// valid.
+pub(super) fn render(path: &Path, opts: &EnvAddOptions) -> Result<String> {
+    let string = |s: &str| toml::Value::String(s.to_string()).to_string();
+    let name = format!("name = {}\n", string(&opts.name));
+    let mut contents = ENV_STUB_TEMPLATE
+        .replace("name = \"{{ name }}\"\n", &name)
+        .replace("{{ name }}", &opts.name)
+        .replace("url = \"\"\n", &format!("url = {}\n", string(&opts.url)));
+
+    if opts.protected {
+        contents = contents.replace(
+            &name,
+            &format!("{}\n# Guards the environment against commands only meant for development.\nprotected = true\n", name),
+        );
+    }
+
+    if let Some(password_ref) = &opts.password_ref {
+        contents.push_str(&format!(
+            "\n# Where to fetch the password from instead of the URL.\npassword_ref = {}\n",
+            string(password_ref),
+        ));
+    }
+
+    let invalid = |e| Error::TomlInvalid(e, path.display().to_string());
+    let settings: toml::Value = toml::from_str(&contents).map_err(invalid)?;
+    settings.try_into::<Environment>().map_err(invalid)?;
+
+    Ok(contents)
+}
+
+/// Writes the file, failing rather than overwriting it should it exist, and
+/// removing it again should writing fail part way.
+pub(super) fn create(path: &Path, contents: &str) -> Result<()> {
+    let mut file = fs::OpenOptions::new().write(true).create_new(true).open(path).map_err(|e| match e.kind() {
+        ErrorKind::AlreadyExists => Error::PathAlreadyExists(path.display().to_string()),
+        _ => e.into(),
+    })?;
+
+    if let Err(e) = file.write_all(contents.as_bytes()).and_then(|_| file.sync_all()) {
+        let _ = fs::remove_file(path);
+
+        return Err(e.into());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn new_environments_have_their_settings() {
+        let opts = EnvAddOptions {
+            name: "staging".to_string(),
+            url: "postgresql://app@db.staging/app".to_string(),
+            protected: true,
+            password_ref: Some("vault:secret/data/db#\"password\"".to_string()),
+        };
+        let path = Path::new("jrny-env.staging.toml");
+        let contents = render(path, &opts).unwrap();
+        let env: Environment = toml::from_str(&contents).unwrap();
+
+        assert!(contents.contains("`-e jrny-env.staging.toml`"));
+        assert_eq!(env.name.as_deref(), Some("staging"));
+        assert!(env.protected);
+        assert_eq!(env.database.url, opts.url);
+        assert_eq!(env.database.password_ref, opts.password_ref);
+
+        let opts = EnvAddOptions { protected: false, password_ref: None, ..opts };
+        let env: Environment = toml::from_str(&render(path, &opts).unwrap()).unwrap();
+
+        assert!(!env.protected);
+        assert_eq!(env.database.password_ref, None);
+    }
+}
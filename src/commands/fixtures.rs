@@ -0,0 +1,36 @@
+use super::embark::Embark;
+use crate::{executor::Executor, fixtures::Fixture, Config, Environment, Error, Result};
+
+/// Determines which fixtures to load, provided that the environment allows
+/// them and is otherwise up to date.
+pub(super) struct Fixtures {
+    pub to_load: Vec<Fixture>,
+}
+
+impl Fixtures {
+    pub fn prepare(cfg: &Config, env: &Environment, exec: &mut Executor) -> Result<Self> {
+        if env.protected {
+            return Err(Error::EnvironmentProtected(env.name.clone()));
+        }
+
+        let allowed = env
+            .name
+            .as_ref()
+            .is_some_and(|name| cfg.fixtures.environments.contains(name));
+
+        if !allowed {
+            return Err(Error::FixturesNotAllowed(env.name.clone()));
+        }
+
+        // Fixtures are written against the latest schema
+        let pending = Embark::prepare(cfg, env, exec)?.len();
+
+        if pending > 0 {
+            return Err(Error::RevisionsPending(pending));
+        }
+
+        let to_load = Fixture::all_from_disk(&cfg.fixtures.directory, env.name.as_deref())?;
+
+        Ok(Self { to_load })
+    }
+}
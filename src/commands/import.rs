@@ -0,0 +1,118 @@
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Utc};
+
+use crate::{
+    executor::Executor,
+    import::{History, MigrationTool},
+    revisions::{ReappliedKind, RepeatableFile, RevisionFile, REPEATABLE_PREFIX},
+    Config,
+    Error,
+    Result,
+};
+
+/// Converts the other tool's revisions into the revisions directory, which
+/// must not have any revisions yet, returning the paths written.
+pub(super) fn convert(cfg: &Config, tool: MigrationTool, source: &Path) -> Result<Vec<PathBuf>> {
+    let revisions = &cfg.revisions.directory;
+    let sources = tool.read_sources(source)?;
+
+    if revisions.is_dir() {
+        let (files, invalid) = RevisionFile::partition_from_disk(&cfg.revisions)?;
+        let (repeatables, _) = RepeatableFile::partition_from_disk(revisions, &cfg.revisions.checksum)?;
+
+        if !files.is_empty() || !invalid.is_empty() || !repeatables.is_empty() {
+            return Err(Error::PathNotEmptyDirectory(revisions.display().to_string()));
+        }
+    } else {
+        fs::create_dir_all(revisions)?;
+    }
+
+    let now = Utc::now();
+    let mut id = 0;
+    let mut written = vec![];
+
+    for source in sources {
+        // Names become part of the filename, so mustn't point elsewhere
+        let name = source.name.replace(['/', '\\'], "-");
+
+        let filename = if source.repeatable {
+            format!("{}{}.sql", REPEATABLE_PREFIX, name)
+        } else {
+            id += 1;
+            format!("{:03}.{}.{}.sql", id, source.created_at.unwrap_or(now).timestamp(), name)
+        };
+
+        let path = revisions.join(filename);
+        let contents = format!("{}\n\n{}", tool.marker(&source.original), source.contents);
+
+        fs::write(&path, contents)?;
+        written.push(path);
+    }
+
+    Ok(written)
+}
+
+/// Records each revision converted from the other tool as applied, provided
+/// its history says so and it isn't already recorded, returning the filenames
+/// of those recorded. Nothing is recorded if there's no history to go by.
+pub(super) fn record(
+    cfg: &Config,
+    exec: &mut Executor,
+    tool: MigrationTool,
+    history_table: &str,
+) -> Result<Option<Vec<String>>> {
+    let history = match exec.load_foreign_history(tool, history_table)? {
+        Some(history) => history,
+        None => return Ok(None),
+    };
+
+    exec.ensure_table_exists()?;
+
+    let recorded: HashSet<_> = exec.load_revisions()?.into_iter().map(|r| r.filename).collect();
+    let recorded_repeatables: HashSet<_> = exec
+        .load_reapplied(ReappliedKind::Repeatable)?
+        .into_iter()
+        .map(|r| r.filename)
+        .collect();
+
+    let mut newly_recorded = vec![];
+
+    for file in RevisionFile::all_from_disk(&cfg.revisions)? {
+        if recorded.contains(&file.filename) {
+            continue;
+        }
+
+        if let Some(at) = applied_on(tool, &history, &file.contents) {
+            exec.record_imported_revision(&file, at)?;
+            newly_recorded.push(file.filename);
+        }
+    }
+
+    let (repeatables, _) = RepeatableFile::partition_from_disk(&cfg.revisions.directory, &cfg.revisions.checksum)?;
+
+    for file in repeatables {
+        if recorded_repeatables.contains(&file.filename) {
+            continue;
+        }
+
+        if let Some(at) = applied_on(tool, &history, &file.contents) {
+            exec.record_imported_repeatable(&file, at)?;
+            newly_recorded.push(file.filename);
+        }
+    }
+
+    Ok(Some(newly_recorded))
+}
+
+/// Whether, and if known when, the other tool applied what the revision was
+/// converted from.
+fn applied_on(tool: MigrationTool, history: &History, contents: &str) -> Option<Option<DateTime<Utc>>> {
+    let original = tool.original(contents)?;
+
+    history.applied(&tool.key(original)?)
+}
@@ -1,27 +1,88 @@
 use std::{
+    collections::{BTreeMap, HashMap, HashSet},
     fs,
-    io::Write,
-    path::PathBuf,
+    io::{self, IsTerminal, Write},
+    path::{Path, PathBuf},
+    process::Command,
+    str::FromStr,
+    sync::Arc,
+    thread,
+    time::Duration,
 };
 
-use chrono::{DateTime, Local, Utc};
+use chrono::{DateTime, Local, SecondsFormat, Utc};
 use log::{info, warn};
+use termcolor::Color;
 
 use crate::{
+    analysis::{Lock, LockMode},
+    assertions::AssertionFile,
+    approvals::{self, Approvals},
+    bundle::Bundle,
+    checksum,
+    events::EventStream,
     executor::Executor,
-    revisions::RevisionFile,
+    generators::{GeneratorFlags, Generator},
+    grants::{self, Change, Cluster, Roles},
+    json::Json,
+    junit::{TestCase, TestSuite},
+    environment,
+    logger::{format_time, paint, time_width},
+    meta::RevisionMeta,
+    metrics,
+    notify,
+    ownership,
+    policy::Severity,
+    prepared::{PreparedTransaction, Resolution, TransactionId},
+    progress::format_duration,
+    report::{ReportFormat, RevisionStatus, RunReport},
+    revisions::{AnnotatedRepeatable, AnnotatedRevision, RepeatableFile, RevisionFile},
+    rewrite::Rewriter,
+    schema::{self, Difference},
+    signing::{self, Manifest, SigningTool, MANIFEST},
+    statements,
+    telemetry,
+    template,
+    workspace::{Pending, Workspace},
     Config,
     Environment,
+    Error,
+    MigrationTool,
     Result,
+    CONF,
+    ENV,
+    ROLES,
 };
 
 mod begin;
+mod bootstrap;
+mod bundle;
 mod embark;
+mod environments;
+mod fixtures;
+mod import;
+mod rename;
+mod run;
 mod review;
+mod script;
+mod seed;
+mod split;
+mod status;
+mod testing;
+mod tombstone;
 
 use begin::Begin;
 use embark::Embark;
-use review::Review;
+use environments::EnvironmentFile;
+use fixtures::Fixtures;
+use rename::Resequence;
+use review::{Review, ReviewFilter};
+use script::Script;
+use seed::Seed;
+use split::Split;
+use status::Status;
+use testing::ScratchDatabase;
+use tombstone::Tombstone;
 
 /// Accepts a path string targeting a directory to set up project files:
 /// The directory will be created if it does not exist or will fail if
@@ -29,13 +90,65 @@ use review::Review;
 /// that there is an empty `revisions` directory nested within it or
 /// create it if not already present. If any error occurs, any changes
 /// to the file system will be attempted to be reversed.
-pub fn begin(dirpath: &PathBuf) -> Result<()> {
-    let mut cmd = Begin::new(dirpath)?;
+/// How a new project is laid out and what's stubbed out for it.
+#[derive(Clone, Debug)]
+pub struct BeginOptions {
+    /// The revisions directory, relative to the project directory
+    pub revisions_dir: PathBuf,
+    /// Whether to also create directories for repeatable revisions, seeds,
+    /// and fixtures, rather than only for revisions
+    pub grouped: bool,
+    pub config_format: ConfigFormat,
+    /// Names of environments to create environment files for, which are
+    /// also listed as those revisions can be limited to
+    pub environments: Vec<String>,
+    /// Whether to keep environment files out of version control in the
+    /// project's `.gitignore`
+    pub gitignore: bool,
+}
+
+impl Default for BeginOptions {
+    fn default() -> Self {
+        Self {
+            revisions_dir: PathBuf::from("revisions"),
+            grouped: false,
+            config_format: ConfigFormat::default(),
+            environments: vec![],
+            gitignore: false,
+        }
+    }
+}
+
+/// How much of the config file `jrny begin` writes out.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ConfigFormat {
+    /// Every option, with those that are optional commented out
+    #[default]
+    Commented,
+    /// Only the options that are required
+    Minimal,
+}
+
+impl FromStr for ConfigFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "commented" => Ok(Self::Commented),
+            "minimal" => Ok(Self::Minimal),
+            _ => Err(Error::ConfigFormatInvalid(s.to_string())),
+        }
+    }
+}
+
+pub fn begin(dirpath: &Path, opts: &BeginOptions) -> Result<()> {
+    let mut cmd = Begin::new(dirpath, opts)?;
 
     cmd.create_root()
         .and_then(|_| cmd.create_revisions())
-        .and_then(|_| cmd.create_conf())
-        .and_then(|_| cmd.create_env())
+        .and_then(|_| cmd.create_conf(opts))
+        .and_then(|_| cmd.create_env(opts))
+        .and_then(|_| if opts.gitignore { cmd.create_gitignore().map(|_| ()) } else { Ok(()) })
         .map_err(|e| match cmd.revert() {
             Ok(_) => e,
             // TODO there should really be better error handling, since this
@@ -47,29 +160,28 @@ pub fn begin(dirpath: &PathBuf) -> Result<()> {
 
     // Only some paths could have been created by the command, so those
     // need to be dynamically labeled as such.
-    log_path("  ",     &cmd.paths.root_dir,      cmd.created_root);
-    log_path("  ├── ", &cmd.paths.revisions_dir, cmd.created_revisions);
-    log_path("  └── ", &cmd.paths.conf_file,     true);
-    log_path("  └── ", &cmd.paths.env_file,      true);
-    log_path("  └── ", &cmd.paths.env_ex_file,   true);
+    let paths = &cmd.paths;
+
+    log_path("  ",     &paths.root_dir,      cmd.created(&paths.root_dir));
+    log_path("  ├── ", &paths.revisions_dir, cmd.created(&paths.revisions_dir));
+    for dir in &paths.kind_dirs {
+        log_path("  ├── ", dir, cmd.created(dir));
+    }
+    log_path("  └── ", &paths.conf_file,     true);
+    log_path("  └── ", &paths.env_file,      true);
+    log_path("  └── ", &paths.env_ex_file,   true);
+    for file in &paths.env_stub_files {
+        log_path("  └── ", file, true);
+    }
+    if opts.gitignore {
+        log_path("  └── ", &paths.gitignore_file, cmd.created(&paths.gitignore_file));
+    }
 
     Ok(())
 }
 
-/// Generates a new empty revision file with the given name in the
-/// revisions directory specified by the provided config.
-pub fn plan(cfg: &Config, name: &str) -> Result<()> {
-    let timestamp = Utc::now().timestamp();
-    let next_id = RevisionFile::all_from_disk(&cfg.revisions.directory)?
-        .iter()
-        .reduce(|rf1, rf2| if rf1.id > rf2.id { rf1 } else { rf2 })
-        .map_or(0, |rf| rf.id as i32)
-        + 1;
-
-    let new_filename = format!("{:03}.{}.{}.sql", next_id, timestamp, name);
-    let new_path = cfg.revisions.directory.join(&new_filename);
-
-    let contents = format!("-- Revision: {name}
+/// What ordinary revisions are planned from, unless the config gives a template.
+const REVISION_TEMPLATE: &str = "-- Revision: {{ name }}
 --
 -- Add description here
 
@@ -78,7 +190,81 @@ begin;
 -- Add SQL here
 
 commit;
-", name=name);
+";
+
+// Data changes are best batched, which can't be within a transaction
+const DATA_REVISION_TEMPLATE: &str = "-- +++
+-- kind = \"data\"
+-- +++
+-- Revision: {{ name }}
+--
+-- Add description here
+
+-- Add UPDATE or DELETE statements here, each with a WHERE clause and
+-- ideally batched, eg.
+--
+-- -- jrny:batch size=10000 key=id
+-- update users set email = lower(email) where email <> lower(email);
+";
+
+/// Options for `plan` beyond the config and name.
+#[derive(Clone, Debug, Default)]
+pub struct PlanOptions {
+    /// Whether the revision changes data rather than the schema
+    pub data: bool,
+    /// Arguments for generating revisions, taking the name as the generator
+    pub args: Vec<String>,
+    /// Options for generators that take them
+    pub flags: GeneratorFlags,
+}
+
+/// Generates a new revision file with the given name in the revisions
+/// directory specified by the provided config, from its template if any.
+pub fn plan(cfg: &Config, name: &str, opts: &PlanOptions) -> Result<()> {
+    if !opts.args.is_empty() {
+        return plan_generated(cfg, name, opts);
+    }
+
+    let violations = cfg.revisions.naming.violations(name);
+
+    if !violations.is_empty() {
+        return Err(Error::RevisionNameViolatesRules(name.to_string(), violations));
+    }
+
+    let now = Utc::now();
+    let next_id = next_revision_id(cfg)?;
+
+    let new_filename = format!("{:03}.{}.{}.sql", next_id, now.timestamp(), name);
+    let new_path = cfg.revisions.directory.join(&new_filename);
+
+    let templates = &cfg.templates;
+    let (template_path, builtin) = if opts.data {
+        (templates.data.as_ref(), DATA_REVISION_TEMPLATE)
+    } else {
+        (templates.revision.as_ref(), REVISION_TEMPLATE)
+    };
+
+    let template = match template_path {
+        Some(path) if !path.is_file() => return Err(Error::FileNotValid(path.display().to_string())),
+        Some(path) => fs::read_to_string(path)?,
+        None => builtin.to_string(),
+    };
+
+    // Built-in variables take precedence over any of the same name in the config
+    let mut context = template::Context::new(now);
+
+    for (variable, value) in &templates.variables {
+        context.set(variable, value);
+    }
+    context
+        .set("name", name)
+        .set("id", &format!("{:03}", next_id))
+        .set("filename", &new_filename);
+
+    let contents = context.render(&template).map_err(|reason| {
+        let template = template_path.map_or_else(|| "built-in".to_string(), |path| path.display().to_string());
+        Error::TemplateInvalid(template, reason)
+    })?;
 
     fs::File::create(&new_path)?.write_all(contents.as_bytes())?;
 
@@ -87,101 +273,2789 @@ commit;
     Ok(())
 }
 
+/// Generates the revisions for a common operation, eg. `add-column`, with
+/// consecutive ids, checking all of their names before creating any.
+fn plan_generated(cfg: &Config, generator: &str, opts: &PlanOptions) -> Result<()> {
+    let revisions = Generator::parse(generator, &opts.args, &opts.flags)
+        .map_err(|reason| Error::GeneratorInvalid(generator.to_string(), reason))?
+        .revisions();
+    let names: Vec<_> = revisions.iter().map(|revision| cfg.revisions.naming.join(&revision.words)).collect();
+
+    for name in &names {
+        let violations = cfg.revisions.naming.violations(name);
+
+        if !violations.is_empty() {
+            return Err(Error::RevisionNameViolatesRules(name.to_string(), violations));
+        }
+    }
+
+    let timestamp = Utc::now().timestamp();
+    let next_id = next_revision_id(cfg)?;
+
+    for (i, (revision, name)) in revisions.iter().zip(&names).enumerate() {
+        let new_path = cfg.revisions.directory.join(format!("{:03}.{}.{}.sql", next_id + i as i32, timestamp, name));
+
+        let requires: Vec<_> = revision.requires.iter().map(|&required| names[required].as_str()).collect();
+
+        fs::File::create(&new_path)?.write_all(revision.contents(name, &requires).as_bytes())?;
+
+        info!("Created {}", new_path.display());
+    }
+
+    Ok(())
+}
+
+/// The id following that of the latest revision on disk.
+fn next_revision_id(cfg: &Config) -> Result<i32> {
+    let latest = RevisionFile::all_from_disk(&cfg.revisions)?
+        .iter()
+        .reduce(|rf1, rf2| if rf1.id > rf2.id { rf1 } else { rf2 })
+        .map_or(0, |rf| rf.id);
+
+    Ok(latest + 1)
+}
+
+/// The formats in which review results can be output.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    Junit,
+}
+
+impl FromStr for OutputFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "text" => Ok(Self::Text),
+            "junit" => Ok(Self::Junit),
+            _ => Err(Error::OutputFormatInvalid(s.to_string())),
+        }
+    }
+}
+
+/// What to do upon finding revisions already being applied elsewhere, eg. by
+/// another replica starting up at the same time.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum IfAlreadyRunning {
+    /// Wait for the other run to finish, then apply anything it didn't
+    #[default]
+    Wait,
+    /// Leave applying revisions to the other run
+    Skip,
+}
+
+impl FromStr for IfAlreadyRunning {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "wait" => Ok(Self::Wait),
+            "skip" => Ok(Self::Skip),
+            _ => Err(Error::IfAlreadyRunningInvalid(s.to_string())),
+        }
+    }
+}
+
+/// Options for `review` beyond the config and environment.
+#[derive(Clone, Debug, Default)]
+pub struct ReviewOptions {
+    pub format: OutputFormat,
+    /// Whether to list the locks taken by each pending statement
+    pub locks: bool,
+    /// A state file written by `jrny embark --write-state` to review against
+    /// instead of the database, which then isn't connected to at all
+    pub state: Option<PathBuf>,
+    /// A git ref to review only the revisions added or changed since, as
+    /// for a pull request, failing if any of them don't pass
+    pub against: Option<String>,
+    /// Whether to list only revisions yet to be applied
+    pub pending_only: bool,
+    /// Whether to list only revisions changed since they were applied
+    pub changed_only: bool,
+    /// Text or a `*` pattern to list only the revisions whose names or paths
+    /// it matches
+    pub revision: Option<String>,
+    /// Whether to list revisions under the release group they're in
+    pub group_by_release: bool,
+}
+
 /// Reviews the status of all revisions specified by the config as well as
-/// their status in the database.
-pub fn review(cfg: &Config, env: &Environment) -> Result<()> {
-    let mut exec = Executor::new(&cfg, &env)?;
-    let cmd = Review::annotated_revisions(&mut exec, &cfg.revisions.directory)?;
+/// their status in the database, or as recorded in a state file.
+pub fn review(cfg: &Config, env: &Environment, opts: &ReviewOptions) -> Result<()> {
+    // Only connected to without a state file to review against instead
+    let mut exec = None;
+    let junit = opts.format == OutputFormat::Junit;
+
+    let cmd = match &opts.state {
+        Some(path) if junit => Review::lenient_recorded_revisions(read_bundle(path)?, &cfg.revisions)?,
+        Some(path) => Review::recorded_revisions(read_bundle(path)?, &cfg.revisions)?,
+        None if junit => Review::lenient_annotated_revisions(exec.insert(Executor::new(cfg, env)?), &cfg.revisions, env)?,
+        None => Review::annotated_revisions(exec.insert(Executor::new(cfg, env)?), &cfg.revisions, env)?,
+    };
+    let cmd = match &opts.against {
+        Some(reference) => cmd.against(reference)?,
+        None => cmd,
+    };
+
+    if junit {
+        println!("{}", to_junit("jrny review", &cmd));
+        return Ok(());
+    }
+
+    if let Some(reference) = &opts.against {
+        let changed = cmd.revisions.iter().any(|revision| cmd.is_reviewed(&revision.path()))
+            || cmd.repeatables.iter().any(|repeatable| cmd.is_reviewed(Path::new(&repeatable.filename)));
+
+        if !changed {
+            info!("No revisions changed since `{}`", reference);
+            return Ok(());
+        }
 
-    if cmd.revisions.is_empty() {
+        info!("Revisions changed since `{}`\n", reference);
+    } else if cmd.revisions.is_empty() && cmd.repeatables.is_empty() {
         info!("No revisions found. Create your first revision with `jrny plan <some-name>`.");
         return Ok(());
+    } else {
+        info!("The journey thus far\n");
     }
 
-    info!("The journey thus far\n");
+    let filter = ReviewFilter {
+        pending_only: opts.pending_only,
+        changed_only: opts.changed_only,
+        pattern: opts.revision.clone(),
+    };
+    let listed = |revision: &AnnotatedRevision| cmd.is_reviewed(&revision.path()) && filter.revision(revision);
+    let listed_repeatable =
+        |repeatable: &AnnotatedRepeatable| cmd.is_reviewed(Path::new(&repeatable.filename)) && filter.repeatable(repeatable);
+
+    if !cmd.revisions.iter().any(listed) && !cmd.repeatables.iter().any(listed_repeatable) {
+        info!("  No revisions match");
+        return Ok(());
+    }
+
+    let width = time_width();
+
     info!(
-        "  {:3}  {:43}{:25}{:25}",
+        "  {:3}  {:43}{:width$}{:width$}",
         "Id", "Revision", "Created", "Applied"
     );
 
-    let format_local = |dt: DateTime<Utc>| DateTime::<Local>::from(dt)
-        .format("%v %X")
-        .to_string();
+    // Neither table sizes nor dependencies can be known offline
+    let (large_tables, dependents) = match &mut exec {
+        Some(exec) => (cmd.large_tables(exec, &cfg.review)?, cmd.dependents(exec)?),
+        None => (cmd.revisions.iter().map(|_| vec![]).collect(), cmd.revisions.iter().map(|_| vec![]).collect()),
+    };
+
+    let mut rows: Vec<_> = cmd
+        .revisions
+        .iter()
+        .zip(cmd.problems())
+        .zip(large_tables)
+        .zip(dependents)
+        .filter(|(((revision, _), _), _)| listed(revision))
+        .collect();
 
-    let mut last_applied_index = -1;
+    // Groups are listed in the order they first appear, with the revisions
+    // outside any group first
+    let mut groups: Vec<Option<&str>> = vec![];
 
-    for (i, revision) in cmd.revisions.iter().enumerate() {
-        if revision.applied_on.is_some() {
-            last_applied_index = i as isize;
+    if opts.group_by_release {
+        for (((revision, _), _), _) in &rows {
+            if !groups.contains(&revision.group.as_deref()) {
+                groups.push(revision.group.as_deref());
+            }
         }
+        groups.sort_by_key(Option::is_some);
+        rows.sort_by_key(|(((revision, _), _), _)| groups.iter().position(|group| *group == revision.group.as_deref()));
     }
 
-    // TODO clean up? this isn't elegant
-    let mut previous_id = None;
+    let mut group = None;
+
+    for (((revision, problems), large_tables), dependents) in rows {
+        if opts.group_by_release && group != Some(revision.group.as_deref()) {
+            group = Some(revision.group.as_deref());
+
+            match revision.group.as_deref() {
+                Some(name) => info!("\n  Release group {}", name),
+                None => info!("\n  Ungrouped"),
+            }
+        }
 
-    for (i, revision) in cmd.revisions.iter().enumerate() {
         let applied_on = match revision.applied_on {
-            Some(a) => format_local(a),
+            Some(a) => format_time(a),
             _ => "--".to_string(),
         };
 
-        let error = if let Some(false) = revision.checksums_match {
-            Some("The file has changed after being applied")
-        } else if !revision.on_disk {
-            Some("No corresponding file could not be found")
-        } else if revision.applied_on.is_none() && (i as isize) < last_applied_index {
-            Some("Later revisions have already been applied")
-        } else if previous_id == Some(revision.id) {
-            Some("Revision has duplicate id")
-        } else {
-            None
-        };
-
-        match error {
-            Some(error) => warn!(
-                "  {:3}  {:43}{:25}{:25}{}",
-                revision.id,
-                revision.name,
-                format_local(revision.created_at),
-                applied_on,
-                error,
-            ),
-            None => info!(
-                "  {:3}  {:43}{:25}{:25}",
+        match problems.first() {
+            Some(problem) => warn!(
+                "  {:3}  {:43}{:width$}{:width$}{}",
                 revision.id,
                 revision.name,
-                format_local(revision.created_at),
+                format_time(revision.created_at),
                 applied_on,
+                problem.message(),
             ),
+            None => {
+                let row = format!(
+                    "  {:3}  {:43}{:width$}{:width$}",
+                    revision.id,
+                    revision.name,
+                    format_time(revision.created_at),
+                    applied_on,
+                );
+
+                if revision.applied_on.is_none() && !revision.applicable {
+                    info!("{}{}", row, not_applied(&cmd, revision));
+                } else if revision.applied_on.is_none() && revision.is_draft() {
+                    info!("{}{}", paint(&row, Color::Yellow), DRAFT);
+                } else {
+                    let color = if revision.applied_on.is_some() { Color::Green } else { Color::Yellow };
+                    info!("{}", paint(&row, color));
+                }
+            }
+        }
+
+        log_meta(revision.meta.as_ref());
+
+        if let Some(impact) = review::impact(revision) {
+            info!("       Impact: {}", impact);
+        }
+
+        for large_table in large_tables {
+            info!("       {}", paint(&large_table.message(), Color::Yellow));
+        }
+
+        for dropped in dependents {
+            let color = if dropped.dropped.cascade { Color::Yellow } else { Color::Red };
+            info!("       {}", paint(&dropped.message(), color));
+        }
+
+        for hint in review::batching_hints(revision) {
+            info!("       {}", paint(&hint, Color::Yellow));
+        }
+
+        // Rules set as errors are already given as problems
+        for violation in cmd.policy_violations(revision).iter().filter(|v| v.severity == Severity::Warning) {
+            info!("       {}", paint(&violation.message(), Color::Yellow));
+        }
+
+        if let Some(warning) = review::deadline_warning(revision) {
+            info!("       {}", paint(&warning, Color::Yellow));
+        }
+
+        if revision.checksums_match == Some(false) {
+            log_changes(&cmd, revision);
+        }
+    }
+
+    if opts.group_by_release && cmd.repeatables.iter().any(listed_repeatable) {
+        info!("\n  Repeatable");
+    }
+
+    for repeatable in cmd.repeatables.iter().filter(|repeatable| listed_repeatable(repeatable)) {
+        let applied_on = match repeatable.applied_on {
+            Some(a) => format_time(a),
+            _ => "--".to_string(),
+        };
+        let mut row = format!(
+            "  {:3}  {:43}{:width$}{:width$}",
+            "R",
+            repeatable.name,
+            "--",
+            applied_on,
+        );
+
+        if !repeatable.applicable {
+            info!("{}{}", row, not_applicable(&repeatable.environments));
+            log_meta(Some(&repeatable.meta));
+            continue;
+        }
+
+        if repeatable.is_pending() && repeatable.is_draft() {
+            row.push_str(DRAFT);
+        } else if repeatable.checksums_match == Some(false) {
+            row.push_str("Changed since last applied, will be applied again");
         }
 
-        previous_id = Some(revision.id);
+        let color = if repeatable.is_pending() { Color::Yellow } else { Color::Green };
+
+        info!("{}", paint(&row, color));
+        log_meta(Some(&repeatable.meta));
+    }
+
+    if opts.locks {
+        log_locks(&cmd, &filter);
+    }
+
+    if opts.against.is_some() {
+        cmd.check()?;
+        info!("\nAll changed revisions passed review");
     }
 
     Ok(())
 }
 
-/// Applies all pending revisions specified by the given config to the
-/// database specified by the environment.
-pub fn embark(cfg: &Config, env: &Environment) -> Result<()> {
-    let mut exec = Executor::new(&cfg, &env)?;
+/// Shows how the applied revision's file has changed since, beneath it.
+fn log_changes(review: &Review, revision: &AnnotatedRevision) {
+    let changes = revision.contents.as_ref().and_then(|contents| review.changes(revision, contents));
+    let changes = match changes {
+        Some(changes) => changes,
+        None => {
+            info!("       {}", paint(CHANGES_UNKNOWN, Color::Yellow));
+            return;
+        }
+    };
 
-    let cmd = Embark::prepare(&cfg, &mut exec)?;
+    for line in changes.lines() {
+        let color = match line.chars().next() {
+            Some('-') => Some(Color::Red),
+            Some('+') => Some(Color::Green),
+            Some('@') => Some(Color::Cyan),
+            _ => None,
+        };
 
-    if cmd.to_apply.is_empty() {
-        info!("No revisions to apply");
+        match color {
+            Some(color) => info!("       {}", paint(line, color)),
+            None => info!("       {}", line),
+        }
+    }
+}
+
+/// Lists the locks that each statement of the pending revisions would take,
+/// highlighting those that block other sessions' reads or writes.
+fn log_locks(review: &Review, filter: &ReviewFilter) {
+    let revisions = review
+        .revisions
+        .iter()
+        .filter(|revision| revision.applied_on.is_none() && revision.applicable && review.is_reviewed(&revision.path()))
+        .filter(|revision| filter.revision(revision))
+        .filter_map(|revision| Some((&revision.filename, revision.contents.as_ref()?)));
+    let repeatables = review
+        .repeatables
+        .iter()
+        .filter(|repeatable| repeatable.is_pending() && repeatable.applicable)
+        .filter(|repeatable| review.is_reviewed(Path::new(&repeatable.filename)) && filter.repeatable(repeatable))
+        .map(|repeatable| (&repeatable.filename, &repeatable.contents));
+
+    info!("\nLocks taken by pending revisions");
+
+    let mut any = false;
+
+    for (filename, contents) in revisions.chain(repeatables) {
+        let locks: Vec<_> = statements::split(contents)
+            .iter()
+            .flat_map(|statement| Lock::all_for(statement).into_iter().map(move |lock| (statement.line, lock)))
+            .collect();
+
+        if locks.is_empty() {
+            continue;
+        }
+
+        any = true;
+        info!("\n  {}", filename);
+
+        for (line, lock) in locks {
+            let row = format!(
+                "    Line {:<5}{:25}{:30}blocks {}",
+                line,
+                lock.mode.as_str(),
+                lock.relation,
+                lock.mode.blocks(),
+            );
+
+            match lock.mode {
+                LockMode::AccessExclusive => info!("{}", paint(&row, Color::Red)),
+                LockMode::Exclusive | LockMode::ShareRowExclusive | LockMode::Share => {
+                    info!("{}", paint(&row, Color::Yellow))
+                }
+                _ => info!("{}", row),
+            }
+        }
+    }
+
+    if !any {
+        info!("\n  None that could be recognized");
+    }
+}
+
+/// Noted against pending drafts in listings.
+const DRAFT: &str = "Draft, not applied by embark";
+
+/// Shown for changed revisions when the file as applied can't be found.
+const CHANGES_UNKNOWN: &str = "How the file has changed can't be shown, as it's neither archived nor in git as it was applied";
+
+/// Explains why a revision limited to other environments won't be applied.
+fn not_applicable(environments: &[String]) -> String {
+    format!("Not applicable (only for {})", environments.join(", "))
+}
+
+/// Explains why a pending revision won't be applied, whether it's a
+/// tombstone, superseded by one already applied, or for other environments.
+fn not_applied(review: &Review, revision: &AnnotatedRevision) -> String {
+    if let Some(id) = revision.superseded_by() {
+        return format!("Superseded by revision {}, not applied", id);
+    }
+
+    match review.applied_instead(revision) {
+        Some(id) => format!("Not applied, as revision {}, which it supersedes, was", id),
+        None => not_applicable(&revision.environments),
+    }
+}
+
+/// Logs the metadata summary for a revision beneath its row in the review.
+fn log_meta(meta: Option<&RevisionMeta>) {
+    if let Some(summary) = meta.and_then(RevisionMeta::summary) {
+        info!("       {}", summary);
+    }
+}
+
+/// Options for `verify` beyond the config and environment.
+#[derive(Clone, Debug, Default)]
+pub struct VerifyOptions {
+    pub format: OutputFormat,
+    /// Approvals that every pending revision must have, if required
+    pub approvals: Option<PathBuf>,
+    /// Whether applied revisions must also have been applied in order
+    pub strict_order: bool,
+}
+
+/// Performs the same checks as `review` but only reports revisions that fail,
+/// including any files in the revisions directory that are not valid revisions,
+/// and fails if there are any.
+pub fn verify(cfg: &Config, env: &Environment, opts: &VerifyOptions) -> Result<()> {
+    let mut exec = Executor::new(cfg, env)?;
+    let mut cmd = Review::lenient_annotated_revisions(&mut exec, &cfg.revisions, env)?;
+
+    if opts.strict_order {
+        cmd = cmd.strict_order();
+    }
+
+    match opts.format {
+        OutputFormat::Junit => println!("{}", to_junit("jrny verify", &cmd)),
+        OutputFormat::Text => {
+            info!(
+                "Verifying {} revision(s)\n",
+                cmd.revisions.len() + cmd.repeatables.len() + cmd.invalid.len(),
+            );
+
+            for invalid in &cmd.invalid {
+                warn!("  {}  {}", invalid.filename, invalid.error);
+            }
+
+            for (revision, problems) in cmd.revisions.iter().zip(cmd.problems()) {
+                for problem in problems {
+                    warn!("  {}  {}", revision.filename, problem.message());
+                }
+
+                if let Some(warning) = review::deadline_warning(revision) {
+                    warn!("  {}  {}", revision.filename, warning);
+                }
+
+                if revision.checksums_match == Some(false) {
+                    log_changes(&cmd, revision);
+                }
+            }
+        }
+    }
+
+    cmd.check()?;
+
+    if opts.format == OutputFormat::Text {
+        info!("All revisions passed review");
+    }
+
+    if let Some(path) = &opts.approvals {
+        verify_approvals(cfg, &cmd, &Approvals::from_path(path)?)?;
+
+        if opts.format == OutputFormat::Text {
+            info!("All pending revisions are approved");
+        }
+    }
+
+    Ok(())
+}
+
+/// Fails unless every pending revision is approved as it is now, and by one
+/// of its owners if it has any.
+fn verify_approvals(cfg: &Config, cmd: &Review, approvals: &Approvals) -> Result<()> {
+    let revisions = cmd
+        .revisions
+        .iter()
+        .filter(|revision| revision.applied_on.is_none() && revision.applicable)
+        .take_while(|revision| !revision.is_draft())
+        .filter_map(|revision| Some((&revision.filename, revision.path(), revision.checksum.as_ref()?, revision.meta.as_ref()?)));
+    let repeatables = cmd
+        .repeatables
+        .iter()
+        .filter(|repeatable| repeatable.is_pending() && repeatable.applicable && !repeatable.is_draft())
+        .map(|repeatable| (&repeatable.filename, PathBuf::from(&repeatable.filename), &repeatable.checksum, &repeatable.meta));
+
+    let mut unapproved = 0;
+
+    for (filename, path, checksum, meta) in revisions.chain(repeatables) {
+        let owners = if meta.owners.is_empty() {
+            approvals::directory_owners(&cfg.revisions.directory, &path)?
+        } else {
+            meta.owners.clone()
+        };
+
+        if let Some(problem) = approvals.check(filename, checksum, &owners) {
+            warn!("  {}  {}", filename, problem);
+            unapproved += 1;
+        }
+    }
+
+    match unapproved {
+        0 => Ok(()),
+        n => Err(Error::RevisionsNotApproved(n)),
+    }
+}
+
+/// Summarizes how many revisions are applied and pending for the environment,
+/// and how many would fail review.
+pub fn status(cfg: &Config, env: &Environment) -> Result<()> {
+    let status = Status::of(cfg, env)?;
+    info!("{}", paint(&status.to_string(), status.color()));
+
+    Ok(())
+}
+
+/// As with `status`, for every project in the workspace, each with the config
+/// and environment files in its directory. Projects that can't be summarized,
+/// eg. as their database can't be connected to, are listed with the reason,
+/// failing once the rest have been.
+pub fn status_all_projects(workspace: &Workspace) -> Result<()> {
+    info!("Projects in {}\n", workspace.filepath().display());
+
+    let mut failed = 0;
+
+    for project in &workspace.projects {
+        let row = format!("  {:43}", project.display());
+
+        match Status::of_project(&workspace.root.join(project)) {
+            Ok(status) => info!("{}{}", row, paint(&status.to_string(), status.color())),
+            Err(e) => {
+                warn!("{}{}", row, e);
+                failed += 1;
+            }
+        }
+    }
+
+    match failed {
+        0 => Ok(()),
+        n => Err(Error::ProjectsFailed(n)),
+    }
+}
+
+/// Lists applied revisions in the order they were applied, along with
+/// the git commit, branch, and author recorded for each, if any.
+pub fn history(cfg: &Config, env: &Environment) -> Result<()> {
+    let mut exec = Executor::new(cfg, env)?;
+    exec.ensure_table_exists()?;
+
+    let mut records = exec.load_revisions()?;
+    records.sort_by_key(|record| record.applied_on);
+
+    if records.is_empty() {
+        info!("No revisions have been applied");
         return Ok(());
     }
 
-    cmd.apply(&mut exec)?;
+    // Files are only needed for metadata, so any that can't be loaded are ignored
+    let (files, _) = RevisionFile::partition_from_disk(&cfg.revisions)?;
+    let files: HashMap<_, _> = files.into_iter().map(|file| (file.filename.clone(), file)).collect();
+
+    let width = time_width();
+    let or_blank = |value: &Option<String>| value.clone().unwrap_or_else(|| "--".to_string());
+
+    info!("The journey thus far\n");
+    info!(
+        "  {:3}  {:43}{:width$}{:10}{:20}{}",
+        "Id", "Revision", "Applied", "Commit", "Branch", "Author"
+    );
+
+    for record in &records {
+        let provenance = &record.provenance;
+
+        info!(
+            "  {:3}  {:43}{:width$}{:10}{:20}{}",
+            record.id,
+            record.name,
+            format_time(record.applied_on),
+            provenance.commit.as_ref().map_or_else(|| "--".to_string(), |c| c.chars().take(8).collect()),
+            or_blank(&provenance.branch),
+            or_blank(&provenance.author),
+        );
+
+        log_meta(files.get(&record.filename).map(|file| &file.meta));
+    }
 
     Ok(())
 }
 
+/// Lists revisions whose files or recorded impact mention the term, ignoring
+/// case, along with when each was applied to the environment and the lines
+/// mentioning it.
+pub fn search(cfg: &Config, env: &Environment, term: &str) -> Result<()> {
+    let mut exec = Executor::new(cfg, env)?;
+    exec.ensure_table_exists()?;
+
+    let records: HashMap<_, _> = exec
+        .load_revisions()?
+        .into_iter()
+        .map(|record| (record.filename.clone(), record))
+        .collect();
+
+    // As with history, files that can't be loaded are ignored
+    let (files, _) = RevisionFile::partition_from_disk(&cfg.revisions)?;
+    let files: HashMap<_, _> = files.into_iter().map(|file| (file.filename.clone(), file)).collect();
+
+    let mut revisions: Vec<(i32, &str, &str)> = records
+        .values()
+        .map(|record| (record.id, record.filename.as_str(), record.name.as_str()))
+        .chain(files.values().filter(|file| !records.contains_key(&file.filename)).map(|file| {
+            (file.id, file.filename.as_str(), file.name.as_str())
+        }))
+        .collect();
+    revisions.sort();
+
+    let term_lower = term.to_lowercase();
+    let mentions = |text: &str| text.to_lowercase().contains(&term_lower);
+
+    let width = time_width();
+
+    let mut found = 0;
+
+    for (id, filename, name) in revisions {
+        let record = records.get(filename);
+        let impact = record.and_then(|record| record.impact.as_deref()).filter(|impact| mentions(impact));
+        let lines: Vec<_> = files
+            .get(filename)
+            .map(|file| {
+                file.contents
+                    .lines()
+                    .enumerate()
+                    .filter(|(_, line)| mentions(line))
+                    .map(|(i, line)| format!("Line {}: {}", i + 1, line.trim()))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        if impact.is_none() && lines.is_empty() {
+            continue;
+        }
+
+        if found == 0 {
+            info!("Revisions mentioning `{}`
+", term);
+            info!("  {:3}  {:43}{:width$}", "Id", "Revision", "Applied");
+        }
+        found += 1;
+
+        let applied_on = record.map_or_else(|| "--".to_string(), |record| format_time(record.applied_on));
+        let row = format!("  {:3}  {:43}{:width$}", id, name, applied_on);
+
+        match record {
+            Some(_) if !files.contains_key(filename) => info!("{}{}", row, paint("Not on disk", Color::Yellow)),
+            Some(_) => info!("{}", paint(&row, Color::Green)),
+            None => info!("{}", paint(&row, Color::Yellow)),
+        }
+
+        if let Some(impact) = impact {
+            info!("       Impact: {}", impact);
+        }
+        for line in lines {
+            info!("       {}", line);
+        }
+    }
+
+    if found == 0 {
+        info!("No revisions mention `{}`", term);
+    }
+
+    Ok(())
+}
+
+/// Options for `seed` beyond the config and environment.
+#[derive(Clone, Debug, Default)]
+pub struct SeedOptions {
+    /// Whether to apply all seeds, not just those new or changed since last applied
+    pub force: bool,
+    /// Whether applying to a protected environment has already been confirmed
+    pub confirmed: bool,
+}
+
+/// Applies seeds for reference data that are new or have changed since they were
+/// last applied, including those specific to the environment.
+pub fn seed(cfg: &Config, env: &Environment, opts: &SeedOptions) -> Result<()> {
+    let mut exec = Executor::new(cfg, env)?;
+    let cmd = Seed::prepare(cfg, env, &mut exec, opts.force)?;
+
+    if cmd.to_apply.is_empty() {
+        info!("No seeds to apply ({} unchanged)", cmd.unchanged);
+        return Ok(());
+    }
+
+    confirm_protected(env, opts.confirmed, &format!("apply {} seed(s)", cmd.to_apply.len()))?;
+
+    info!("Applying {} seed(s)\n", cmd.to_apply.len());
+
+    let (report, result) = Embark::reapply(cfg, cmd.to_apply).apply(&mut exec, EventStream::new(false));
+    report.log_summary();
+
+    result
+}
+
+/// Options for `exec` beyond the config and environment.
+#[derive(Clone, Debug)]
+pub struct ExecOptions {
+    /// The SQL file to run, or `-` to read it from standard input
+    pub path: String,
+    /// Whether running against a protected environment has already been confirmed
+    pub confirmed: bool,
+}
+
+/// Runs an ad-hoc SQL file against the database as a revision would be
+/// applied, in a transaction unless it manages its own and with the same
+/// checks and logging, but without recording it.
+pub fn exec(cfg: &Config, env: &Environment, opts: &ExecOptions) -> Result<()> {
+    let cmd = Embark::script(cfg, env, Script::read(&opts.path)?)?;
+    let mut exec = Executor::new(cfg, env)?;
+
+    // Scripts read from standard input can't be confirmed interactively
+    confirm_protected(env, opts.confirmed, &format!("apply script `{}`", opts.path))?;
+
+    let (report, result) = cmd.apply(&mut exec, EventStream::new(false));
+    report.log_summary();
+
+    result
+}
+
+/// Loads every fixture, as well as those specific to the environment, provided
+/// that it is one of those designated for fixtures, is not protected, and has
+/// no pending revisions.
+pub fn fixtures(cfg: &Config, env: &Environment) -> Result<()> {
+    let mut exec = Executor::new(cfg, env)?;
+    let cmd = Fixtures::prepare(cfg, env, &mut exec)?;
+
+    if cmd.to_load.is_empty() {
+        info!("No fixtures to load");
+        return Ok(());
+    }
+
+    info!("Loading {} fixture(s)\n", cmd.to_load.len());
+
+    let rows = exec.load_fixtures(&cmd.to_load)?;
+
+    for (fixture, rows) in cmd.to_load.iter().zip(rows) {
+        info!("  {} ({} rows)", fixture.filename, rows);
+    }
+
+    Ok(())
+}
+
+/// Options for `import` beyond the config and environment.
+#[derive(Clone, Debug)]
+pub struct ImportOptions {
+    /// The tool to import from, unless importing a bundle
+    pub from: Option<MigrationTool>,
+    /// Where the tool's revisions are, if they're yet to be converted
+    pub source: Option<PathBuf>,
+    /// The tool's history table, if not the tool's default
+    pub history_table: Option<String>,
+    /// A bundle written by `jrny export` to import instead
+    pub bundle: Option<PathBuf>,
+    /// Whether to also record the bundle's history for the environment
+    pub with_history: bool,
+}
+
+/// Converts the revisions of another tool into revisions, if given their
+/// source, and then records those that the tool's history says are applied
+/// to the environment as such, so that they aren't applied again.
+///
+/// Given a bundle instead, writes whichever of its revisions are missing
+/// and, optionally, records its history for the environment.
+pub fn import(cfg: &Config, env: &Environment, opts: &ImportOptions) -> Result<()> {
+    if let Some(path) = &opts.bundle {
+        return import_bundle(cfg, env, path, opts.with_history);
+    }
+
+    let tool = match opts.from {
+        Some(tool) => tool,
+        None => return Ok(()),
+    };
+
+    if let Some(source) = &opts.source {
+        let written = import::convert(cfg, tool, source)?;
+
+        info!("Imported {} revision(s) from {}\n", written.len(), tool.as_str());
+
+        for path in &written {
+            info!("  {}", file_name(path));
+        }
+
+        info!("");
+    }
+
+    let mut exec = Executor::new(cfg, env)?;
+    let table = opts.history_table.as_deref().unwrap_or_else(|| tool.default_history_table());
+
+    match import::record(cfg, &mut exec, tool, table)? {
+        None => info!("No {} history found in `{}`, so nothing is recorded as applied", tool.as_str(), table),
+        Some(recorded) if recorded.is_empty() => info!("No further revisions are recorded as applied by {}", tool.as_str()),
+        Some(recorded) => {
+            info!("Recorded {} revision(s) as already applied by {}\n", recorded.len(), tool.as_str());
+
+            for filename in &recorded {
+                info!("  {}", filename);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn import_bundle(cfg: &Config, env: &Environment, path: &Path, with_history: bool) -> Result<()> {
+    let bundle = read_bundle(path)?;
+
+    let written = bundle::restore(cfg, &bundle)?;
+
+    if written.is_empty() {
+        info!("All {} bundled file(s) are already in the revisions directory", bundle.files.len());
+    } else {
+        info!("Wrote {} of {} bundled file(s)\n", written.len(), bundle.files.len());
+
+        for filename in &written {
+            info!("  {}", filename);
+        }
+    }
+
+    if !with_history {
+        return Ok(());
+    }
+
+    let mut exec = Executor::new(cfg, env)?;
+    let recorded = bundle::record(&mut exec, &bundle)?;
+    let origin = match &bundle.environment {
+        Some(name) => format!("`{}`", name),
+        None => "the exported environment".to_string(),
+    };
+
+    info!("");
+
+    if recorded.is_empty() {
+        info!("No further revisions are recorded as applied to {}", origin);
+    } else {
+        info!("Recorded {} revision(s) as applied to {}\n", recorded.len(), origin);
+
+        for filename in &recorded {
+            info!("  {}", filename);
+        }
+    }
+
+    Ok(())
+}
+
+pub struct ExportOptions {
+    /// Where to write the bundle
+    pub output: PathBuf,
+}
+
+/// Reads a bundle, or a state file as a bundle without files, from its JSON.
+fn read_bundle(path: &Path) -> Result<Bundle> {
+    let pathstr = path.display().to_string();
+    let json = Json::parse(&fs::read_to_string(path)?).map_err(|reason| Error::BundleInvalid(pathstr.clone(), reason))?;
+
+    Bundle::from_json(&json).map_err(|reason| Error::BundleInvalid(pathstr, reason))
+}
+
+/// Writes a bundle of the revision files along with the environment's history
+/// of applying them, to be read back with `jrny import --bundle`.
+pub fn export(cfg: &Config, env: &Environment, opts: &ExportOptions) -> Result<()> {
+    let mut exec = Executor::new(cfg, env)?;
+    let bundle = bundle::export(cfg, env, &mut exec)?;
+
+    fs::write(&opts.output, bundle.to_json().to_string())?;
+
+    info!(
+        "Exported {} file(s) and {} applied revision(s) to {}",
+        bundle.files.len(),
+        bundle.revisions.len() + bundle.repeatables.len(),
+        opts.output.display(),
+    );
+
+    Ok(())
+}
+
+pub struct SignOptions {
+    /// The private key to sign with, or for `gpg` its id
+    pub key: PathBuf,
+    pub tool: SigningTool,
+    /// Where to write the manifest, with its signature alongside it
+    pub manifest: PathBuf,
+    /// Whether to sign each revision file yet to be signed instead
+    pub files: bool,
+}
+
+/// Writes a manifest of the checksum of every file in the revisions
+/// directory and signs it, for embarking to require, or signs each file
+/// without a signature of its own.
+pub fn sign(cfg: &Config, opts: &SignOptions) -> Result<()> {
+    let revisions = &cfg.revisions.directory;
+    let (repeatables, invalid) = RepeatableFile::partition_from_disk(revisions, &cfg.revisions.checksum)?;
+
+    if let Some(invalid) = invalid.into_iter().next() {
+        return Err(invalid.error);
+    }
+
+    let files = RevisionFile::all_from_disk(&cfg.revisions)?
+        .into_iter()
+        .map(|file| (file.path_name(), file.checksum))
+        .chain(repeatables.into_iter().map(|file| (file.filename, file.checksum)))
+        .collect();
+    let manifest = Manifest { files };
+
+    if opts.files {
+        let unsigned: Vec<_> = manifest
+            .files
+            .iter()
+            .map(|(filename, _)| cfg.revisions.directory.join(filename))
+            .filter(|path| !signing::signature_path(path).exists())
+            .collect();
+
+        for path in &unsigned {
+            signing::sign(path, &opts.key, opts.tool)?;
+            info!("  {}", signing::signature_path(path).display());
+        }
+
+        info!("Signed {} file(s) yet to be signed", unsigned.len());
+
+        return Ok(());
+    }
+
+    fs::write(&opts.manifest, manifest.render())?;
+
+    let signature = signing::sign(&opts.manifest, &opts.key, opts.tool)?;
+
+    info!(
+        "Signed {} file(s) in {}, with the signature in {}",
+        manifest.files.len(),
+        opts.manifest.display(),
+        signature.display(),
+    );
+
+    Ok(())
+}
+
+pub struct DiffOptions {
+    /// The database to compare from, as a URL or an environment file
+    pub from: String,
+    /// The database to compare to, as a URL or an environment file
+    pub to: String,
+}
+
+/// Compares the schemas of two databases, listing each object that's only
+/// in one of them or is defined differently in each, and fails if any are.
+pub fn diff(opts: &DiffOptions) -> Result<()> {
+    let (from, from_label) = database(&opts.from)?;
+    let (to, to_label) = database(&opts.to)?;
+
+    let from_objects = schema::objects(&mut postgres::Client::try_from(&from)?)?;
+    let to_objects = schema::objects(&mut postgres::Client::try_from(&to)?)?;
+    let differences = schema::diff(&from_objects, &to_objects);
+
+    info!("Comparing {} to {}\n", from_label, to_label);
+    log_differences(&differences);
+
+    match differences.len() {
+        0 => {
+            info!("No differences");
+            Ok(())
+        }
+        n => Err(Error::SchemasDiffer(n)),
+    }
+}
+
+/// Lists each object that's only in one schema or the other, or defined
+/// differently in each, along with the lines of its definition that differ.
+fn log_differences(differences: &[Difference]) {
+    for difference in differences {
+        match difference {
+            Difference::OnlyInFrom(object) => {
+                info!("{}", paint(&format!("  - {} {}", object.kind, object.name), Color::Red));
+            }
+            Difference::OnlyInTo(object) => {
+                info!("{}", paint(&format!("  + {} {}", object.kind, object.name), Color::Green));
+            }
+            Difference::Changed(from, to) => {
+                info!("{}", paint(&format!("  ~ {} {}", from.kind, from.name), Color::Yellow));
+
+                let (removed, added) = schema::changed_lines(&from.definition, &to.definition);
+
+                for line in removed {
+                    info!("{}", paint(&format!("      - {}", line), Color::Red));
+                }
+                for line in added {
+                    info!("{}", paint(&format!("      + {}", line), Color::Green));
+                }
+            }
+        }
+    }
+}
+
+pub struct GrantsOptions {
+    /// The file declaring roles and grants, or `roles.toml` alongside the
+    /// config file if not given
+    pub roles: Option<PathBuf>,
+    /// Whether changing a protected environment's roles is already confirmed
+    pub confirmed: bool,
+}
+
+/// Lists each change that applying the declared roles and grants would make
+/// to the environment's cluster, and fails if there are any.
+pub fn grants_diff(cfg: &Config, env: &Environment, opts: &GrantsOptions) -> Result<()> {
+    let path = opts.roles.clone().unwrap_or_else(|| cfg.root.join(ROLES));
+    let mut client = postgres::Client::try_from(env)?;
+    let changes = grant_changes(&path, env, &mut client)?;
+
+    log_grant_changes(&changes);
+
+    match changes.len() {
+        0 => {
+            info!("Roles and grants match {}", path.display());
+            Ok(())
+        }
+        n => Err(Error::GrantsDiffer(n, path.display().to_string())),
+    }
+}
+
+/// Makes the environment's cluster match the declared roles and grants, in
+/// a single transaction, once confirmed for protected environments.
+pub fn grants_apply(cfg: &Config, env: &Environment, opts: &GrantsOptions) -> Result<()> {
+    let path = opts.roles.clone().unwrap_or_else(|| cfg.root.join(ROLES));
+    let mut client = postgres::Client::try_from(env)?;
+    let changes = grant_changes(&path, env, &mut client)?;
+
+    if changes.is_empty() {
+        info!("Roles and grants already match {}", path.display());
+        return Ok(());
+    }
+
+    confirm_protected(env, opts.confirmed, &format!("apply {} role and grant change(s)", changes.len()))?;
+
+    let mut tx = client.transaction()?;
+
+    for change in &changes {
+        tx.batch_execute(change.sql())?;
+    }
+
+    tx.commit()?;
+    log_grant_changes(&changes);
+    info!("Made {} change(s) to match {}", changes.len(), path.display());
+
+    Ok(())
+}
+
+/// The changes making the cluster match the roles and grants declared for
+/// the environment in the file.
+fn grant_changes(path: &Path, env: &Environment, client: &mut postgres::Client) -> Result<Vec<Change>> {
+    let declared = Roles::from_path(path)?.for_environment(env.name.as_deref());
+
+    Ok(grants::changes(&declared, &Cluster::load(client)?))
+}
+
+fn log_grant_changes(changes: &[Change]) {
+    for change in changes {
+        let (sign, color) = match change {
+            Change::Add(_) => ('+', Color::Green),
+            Change::Alter(_) => ('~', Color::Yellow),
+            Change::Remove(_) => ('-', Color::Red),
+        };
+
+        info!("{}", paint(&format!("  {} {}", sign, change.sql()), color));
+    }
+}
+
+pub struct SnapshotOptions {
+    /// Where the snapshot is written, or read from when checking it
+    pub path: PathBuf,
+    /// Whether to check that the snapshot is up to date rather than write it
+    pub check: bool,
+}
+
+/// Writes a snapshot of the environment's schema, other than jrny's own
+/// tables and any backups, or checks that the one already written is up to
+/// date, failing if it's not.
+pub fn snapshot(cfg: &Config, env: &Environment, opts: &SnapshotOptions) -> Result<()> {
+    let objects = schema::objects(&mut postgres::Client::try_from(env)?)?;
+    let mut objects = schema::without_tracking_tables(objects, &cfg.table.schema, &cfg.table.name);
+
+    if let Some(backup) = &env.backup {
+        let prefix = format!("{}.", backup.schema);
+        objects.retain(|object| object.name != backup.schema && !object.name.starts_with(&prefix));
+    }
+
+    let path = opts.path.display().to_string();
+
+    if !opts.check {
+        fs::write(&opts.path, schema::render_snapshot(&objects))?;
+        info!("Wrote a snapshot of {} object(s) to {}", objects.len(), path);
+
+        return Ok(());
+    }
+
+    let contents = fs::read_to_string(&opts.path).map_err(|e| Error::SnapshotInvalid(path.clone(), e.to_string()))?;
+    let snapshot = schema::parse_snapshot(&contents).map_err(|reason| Error::SnapshotInvalid(path.clone(), reason))?;
+    let differences = schema::diff(&snapshot, &objects);
+
+    if differences.is_empty() {
+        info!("The snapshot {} is up to date", path);
+        return Ok(());
+    }
+
+    info!("The snapshot {} differs from the database\n", path);
+    log_differences(&differences);
+
+    Err(Error::SnapshotStale(path, differences.len()))
+}
+
+/// Lists the environment files alongside the config file, each with the
+/// database it's for, along with any environments the config lists that
+/// don't have one.
+pub fn env_list(cfg: &Config) -> Result<()> {
+    let files = EnvironmentFile::find(&cfg.root)?;
+
+    info!("Environments in {}\n", cfg.root.display());
+    info!("  {:16}{:32}{}", "Name", "File", "Database");
+
+    for file in &files {
+        let name = file.name.as_deref().unwrap_or("(default)");
+        let row = format!("  {:16}{:32}", name, file_name(&file.path));
+
+        let env = match &file.env {
+            Ok(env) => env,
+            Err(e) => {
+                info!("{}{}", row, paint(&e.to_string(), Color::Red));
+                continue;
+            }
+        };
+
+        let mut details = vec![];
+
+        if env.protected {
+            details.push("protected".to_string());
+        }
+        if file.has_local {
+            details.push(format!("overridden by {}", file_name(&environment::local_filepath(&file.path))));
+        }
+        if let Some(named) = env.name.as_deref().filter(|named| Some(*named) != file.name.as_deref()) {
+            details.push(format!("named `{}`", named));
+        }
+
+        let database = match env.database.url.as_str() {
+            "" => paint("no database url", Color::Yellow),
+            url => database_label(url).unwrap_or_else(|e| paint(&e.to_string(), Color::Red)),
+        };
+        let details = match details.is_empty() {
+            true => String::new(),
+            false => format!(" ({})", details.join(", ")),
+        };
+
+        info!("{}{}{}", row, database, details);
+    }
+
+    for name in &cfg.revisions.environments {
+        if !files.iter().any(|file| file.name.as_ref() == Some(name)) {
+            info!("  {:16}{:32}{}", name, "--", paint("No environment file", Color::Yellow));
+        }
+    }
+
+    Ok(())
+}
+
+/// The settings for an environment added with `jrny env add`.
+#[derive(Clone, Debug)]
+pub struct EnvAddOptions {
+    pub name: String,
+    pub url: String,
+    /// Whether the environment must be guarded against commands only meant
+    /// for development
+    pub protected: bool,
+    /// Where to fetch the password from instead of the URL
+    pub password_ref: Option<String>,
+}
+
+/// Writes the environment file for a new environment alongside the config
+/// file, having checked its settings, without ever overwriting one.
+pub fn env_add(cfg: &Config, opts: &EnvAddOptions) -> Result<()> {
+    if !environment::is_valid_name(&opts.name) {
+        return Err(Error::EnvironmentNameInvalid(opts.name.clone()));
+    }
+
+    database_label(&opts.url)?;
+
+    let path = environment::named_filepath(&cfg.root, &opts.name);
+    let contents = environments::render(&path, opts)?;
+
+    environments::create(&path, &contents)?;
+
+    info!("Added environment `{}`", opts.name);
+    log_path("  ", &path, true);
+
+    if !cfg.revisions.environments.is_empty() && !cfg.revisions.environments.contains(&opts.name) {
+        warn!(
+            "\n`{}` isn't listed in `revisions.environments` of the config, so revisions can't be limited to it",
+            opts.name,
+        );
+    }
+
+    info!("\nCheck that it can connect with `jrny env test {}`", opts.name);
+
+    Ok(())
+}
+
+/// Checks that the environment file, given by name or else `jrny-env.toml`,
+/// is valid along with its local file, and that its database can be
+/// connected to, summarizing its revisions as `status` does. Extensions the
+/// revisions need must be installed, unless the environment creates them.
+pub fn env_test(cfg: &Config, name: Option<&str>) -> Result<()> {
+    let (path, env) = environment_file(cfg, name)?;
+    info!("{} is valid", path.display());
+
+    let label = database_label(&env.database.url)?;
+    let status = Status::of(cfg, &env)?;
+
+    info!("Connected to {}", label);
+    info!("{}", paint(&status.to_string(), status.color()));
+
+    let missing = Executor::new(cfg, &env)?.missing_extensions(&cfg.revisions.extensions)?;
+
+    if !missing.is_empty() && !env.create_extensions {
+        return Err(Error::ExtensionsMissing(missing));
+    }
+    for extension in &missing {
+        info!("Extension {} is missing, and will be created by `jrny embark`", extension);
+    }
+
+    Ok(())
+}
+
+/// The environment file, given by name or else `jrny-env.toml`, along with
+/// the environment it gives, local file and all.
+fn environment_file(cfg: &Config, name: Option<&str>) -> Result<(PathBuf, Environment)> {
+    let path = match name {
+        Some(name) => environment::named_filepath(&cfg.root, name),
+        None => cfg.root.join(ENV),
+    };
+
+    if !path.is_file() && !environment::local_filepath(&path).is_file() {
+        return Err(Error::EnvironmentNotFound(name.unwrap_or("default").to_string(), path.display().to_string()));
+    }
+
+    let env = Environment::from_filepath(&path)?;
+
+    Ok((path, env))
+}
+
+/// Options for `clone-state` beyond the config.
+#[derive(Clone, Debug)]
+pub struct CloneStateOptions {
+    /// The environment whose history is copied, by name
+    pub from: String,
+    /// The environment it's copied to, by name
+    pub to: String,
+    /// Whether copying to a protected environment is already confirmed
+    pub confirmed: bool,
+}
+
+/// Records the revisions applied to one environment as applied to another,
+/// without running any of them or copying any data, eg. for a database
+/// restored from a dump of the other that left out the tracking tables.
+pub fn clone_state(cfg: &Config, opts: &CloneStateOptions) -> Result<()> {
+    let (_, from) = environment_file(cfg, Some(&opts.from))?;
+    let (_, mut to) = environment_file(cfg, Some(&opts.to))?;
+
+    // Protected environments are confirmed by the name they're given here
+    to.name.get_or_insert_with(|| opts.to.clone());
+
+    let mut from_exec = Executor::new(cfg, &from)?;
+
+    if !from_exec.tracking_table_exists()? {
+        info!("No revisions are recorded as applied to `{}`, so there's nothing to copy", opts.from);
+        return Ok(());
+    }
+
+    let state = bundle::state(&from, &mut from_exec)?;
+
+    confirm_protected(&to, opts.confirmed, &format!("copy the state of `{}`", opts.from))?;
+
+    let mut to_exec = Executor::new(cfg, &to)?;
+    let recorded = bundle::record(&mut to_exec, &state)?;
+
+    if recorded.is_empty() {
+        info!("`{}` already has every revision applied to `{}` recorded as applied", opts.to, opts.from);
+        return Ok(());
+    }
+
+    info!("Recorded {} revision(s) applied to `{}` as applied to `{}`\n", recorded.len(), opts.from, opts.to);
+
+    for filename in &recorded {
+        info!("  {}", filename);
+    }
+
+    Ok(())
+}
+
+/// Options for `revert` beyond the config and environment.
+#[derive(Clone, Debug)]
+pub struct RevertOptions {
+    /// The release group whose revisions are reverted, by its directory's name
+    pub group: String,
+    /// Whether reverting in a protected environment is already confirmed
+    pub confirmed: bool,
+}
+
+/// Reverts every revision applied as part of the release group, most
+/// recently applied first, with the `.revert.sql` file alongside each, in a
+/// single transaction just as the group was applied, so that either the
+/// whole group is pending again or none of it is.
+pub fn revert(cfg: &Config, env: &Environment, opts: &RevertOptions) -> Result<()> {
+    let mut exec = Executor::new(cfg, env)?;
+
+    if !exec.tracking_table_exists()? {
+        return Err(Error::ReleaseGroupNotApplied(opts.group.clone()));
+    }
+
+    // Embarking meanwhile could apply revisions after the group's
+    exec.lock()?;
+
+    let mut records = exec.load_revisions()?;
+    records.sort_by_key(|record| (record.applied_on, record.id));
+
+    let first = records
+        .iter()
+        .position(|record| record.release_group.as_deref() == Some(opts.group.as_str()))
+        .ok_or_else(|| Error::ReleaseGroupNotApplied(opts.group.clone()))?;
+    let (grouped, later): (Vec<_>, Vec<_>) = records
+        .drain(first..)
+        .partition(|record| record.release_group.as_deref() == Some(opts.group.as_str()));
+
+    let reverts = testing::revert_files(cfg)?;
+
+    if let Some(missing) = grouped.iter().find(|record| !reverts.contains_key(&record.filename)) {
+        return Err(Error::RevertMissing(missing.filename.clone()));
+    }
+
+    if !later.is_empty() {
+        warn!("{} revision(s) outside the group were applied after it and are left as they are\n", later.len());
+    }
+
+    confirm_protected(env, opts.confirmed, &format!("revert the {} revision(s) of `{}`", grouped.len(), opts.group))?;
+
+    let statements: Vec<_> = grouped
+        .iter()
+        .rev()
+        .map(|record| (record.filename.as_str(), statements::split(&reverts[&record.filename])))
+        .collect();
+    let reverting: Vec<_> = statements.iter().map(|(filename, statements)| (*filename, statements.as_slice())).collect();
+
+    exec.revert_revisions(&reverting)?;
+
+    info!("Reverted {} revision(s) of release group `{}`\n", reverting.len(), opts.group);
+
+    for (filename, _) in &reverting {
+        info!("  {}", filename);
+    }
+
+    Ok(())
+}
+
+/// The environment for a database given as a URL or an environment file,
+/// along with how to refer to it without giving away any password.
+fn database(given: &str) -> Result<(Environment, String)> {
+    if Path::new(given).is_file() {
+        let env = Environment::from_filepath(Path::new(given))?;
+        let label = match &env.name {
+            Some(name) => format!("{} ({})", name, given),
+            None => given.to_string(),
+        };
+
+        return Ok((env, label));
+    }
+
+    Ok((Environment::from_database_url(given), database_label(given)?))
+}
+
+/// How to refer to the database at the URL without giving away any password.
+fn database_label(url: &str) -> Result<String> {
+    let config = postgres::Config::from_str(url)?;
+    let host = match config.get_hosts().first() {
+        Some(postgres::config::Host::Tcp(host)) => host.clone(),
+        #[cfg(unix)]
+        Some(postgres::config::Host::Unix(path)) => path.display().to_string(),
+        None => "localhost".to_string(),
+    };
+
+    Ok(format!("{} on {}", config.get_dbname().unwrap_or_default(), host))
+}
+
+/// Renumbers pending revision files so that each has a unique id following
+/// all applied revisions, changing as few ids as possible.
+pub fn resequence(cfg: &Config, env: &Environment) -> Result<()> {
+    let mut exec = Executor::new(cfg, env)?;
+    let cmd = Resequence::prepare(cfg, env, &mut exec)?;
+
+    if cmd.renames.is_empty() {
+        info!("No revisions need to be renumbered");
+        return Ok(());
+    }
+
+    cmd.apply()?;
+
+    info!("Renumbered {} revision(s)\n", cmd.renames.len());
+
+    for rename in &cmd.renames {
+        info!("  {} -> {}", file_name(&rename.from), file_name(&rename.to));
+    }
+
+    Ok(())
+}
+
+pub struct SplitOptions {
+    /// The filename, id, or name of the revision to split
+    pub revision: String,
+    /// The statements to split before, numbered from 1, or none to choose
+    /// them interactively
+    pub at: Vec<usize>,
+}
+
+pub struct TombstoneOptions {
+    /// The filename, id, or name of the revision to replace
+    pub revision: String,
+    /// The id of the revision superseding it
+    pub superseded_by: i32,
+}
+
+/// Replaces a revision with a tombstone superseded by another, so that
+/// environments that already applied it keep it as applied, while the rest
+/// skip it and apply the replacement instead.
+pub fn tombstone(cfg: &Config, opts: &TombstoneOptions) -> Result<()> {
+    let cmd = Tombstone::prepare(cfg, &opts.revision, opts.superseded_by)?;
+
+    cmd.apply()?;
+
+    info!("Replaced {} with a tombstone superseded by {}", cmd.filename, cmd.superseded_by.1);
+
+    Ok(())
+}
+
+pub fn split(cfg: &Config, env: &Environment, opts: &SplitOptions) -> Result<()> {
+    let mut exec = Executor::new(cfg, env)?;
+    let cmd = Split::prepare(cfg, env, &mut exec, &opts.revision)?;
+
+    let at = if opts.at.is_empty() { choose_split_points(&cmd)? } else { opts.at.clone() };
+    let parts = cmd.parts(&at)?;
+    let renames = cmd.renames(parts.len());
+
+    cmd.apply(&parts, &renames)?;
+
+    info!("Split {} into {} revisions\n", file_name(&cmd.path), parts.len());
+
+    for part in &parts {
+        info!("  {}", file_name(&part.path));
+    }
+
+    if !renames.is_empty() {
+        info!("\nRenumbered {} revision(s)\n", renames.len());
+
+        for rename in &renames {
+            info!("  {} -> {}", file_name(&rename.from), file_name(&rename.to));
+        }
+    }
+
+    info!("\nThe original was moved to {}", cmd.original.display());
+
+    for filename in &cmd.required_by {
+        warn!("`{}` requires the revision split by name, so should require one of its parts instead", filename);
+    }
+
+    Ok(())
+}
+
+/// Lists the statements that the revision can be split between and asks
+/// which to split before, if there's a terminal to ask.
+fn choose_split_points(cmd: &Split) -> Result<Vec<usize>> {
+    if !io::stdin().is_terminal() {
+        return Err(Error::SplitPointsInvalid("none given - use `--at` to give them without a terminal".to_string()));
+    }
+
+    info!("Statements of {}\n", file_name(&cmd.path));
+
+    for (i, statement) in cmd.statements().iter().enumerate() {
+        info!("  {:>3}  {}", i + 1, statement.summary());
+    }
+
+    eprint!("\nSplit before statements, eg. `3` or `2,5`: ");
+
+    let mut typed = String::new();
+    io::stdin().read_line(&mut typed)?;
+
+    typed
+        .split(',')
+        .map(str::trim)
+        .filter(|point| !point.is_empty())
+        .map(|point| point.parse().map_err(|_| Error::SplitPointsInvalid(format!("`{}` is not a statement number", point))))
+        .collect()
+}
+
+/// Guards against acting on the wrong environment by requiring the name of a
+/// protected one to be typed in, unless already confirmed. Without a terminal
+/// to ask, or a name to check against, confirmation must be given upfront.
+fn confirm_protected(env: &Environment, confirmed: bool, action: &str) -> Result<()> {
+    if !env.protected || confirmed {
+        return Ok(());
+    }
+
+    let name = match &env.name {
+        Some(name) if io::stdin().is_terminal() => name,
+        _ => return Err(Error::ConfirmationRequired(env.name.clone())),
+    };
+
+    // Prompt via stderr, since stdout may be reserved for events
+    eprint!("About to {} to protected environment `{}` - type its name to continue: ", action, name);
+
+    let mut typed = String::new();
+    io::stdin().read_line(&mut typed)?;
+
+    if typed.trim() != name {
+        return Err(Error::ConfirmationFailed(name.clone()));
+    }
+
+    eprintln!();
+
+    Ok(())
+}
+
+/// Offers to resolve each applied revision whose file has changed since,
+/// given a terminal to ask, rather than leaving embark to fail its review.
+/// The changes are shown and can then be accepted, recording the file's
+/// checksum anew, or the file opened to undo them, or embark stopped.
+fn resolve_changed(cfg: &Config, env: &Environment, exec: &mut Executor, confirmed: bool) -> Result<()> {
+    if !io::stdin().is_terminal() || !exec.tracking_table_exists()? {
+        return Ok(());
+    }
+
+    let review = Review::existing_annotated_revisions(exec, &cfg.revisions, env)?;
+    let changed = review.revisions.iter().filter(|revision| revision.checksums_match == Some(false));
+
+    for revision in changed {
+        let record = match review.record(&revision.filename) {
+            Some(record) => record,
+            None => continue,
+        };
+        let path = cfg.revisions.directory.join(revision.path());
+        let name = revision.path_name();
+
+        warn!("Revision {} has changed since it was applied\n", name);
+
+        let mut shown = None;
+
+        loop {
+            let contents = fs::read_to_string(&path)?;
+
+            if checksum::matches(&contents, &record.checksum) {
+                info!("Revision {} is back as it was applied\n", name);
+                break;
+            }
+
+            // Shown again only once changed again, eg. in the editor
+            if shown.as_ref() != Some(&contents) {
+                match review.changes(revision, &contents) {
+                    Some(changes) => info!("{}", changes),
+                    None => info!("{}\n", CHANGES_UNKNOWN),
+                }
+                shown = Some(contents.clone());
+            }
+
+            // Prompt via stderr, as for confirmation
+            eprint!("[a]ccept the changes, [o]pen the file to undo them, or a[b]ort (the default): ");
+
+            let mut typed = String::new();
+            io::stdin().read_line(&mut typed)?;
+            eprintln!();
+
+            match typed.trim() {
+                "a" | "accept" => {
+                    confirm_protected(env, confirmed, &format!("accept the changes to {} as applied", name))?;
+                    exec.accept_checksum(&revision.filename, &cfg.revisions.checksum.checksum(&contents))?;
+                    info!("Accepted the changes to {}\n", name);
+                    break;
+                }
+                "o" | "open" => edit(&path)?,
+                "b" | "abort" | "" => return Err(Error::RevisionChangeRejected(name)),
+                _ => {}
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Opens the file in the editor given by `VISUAL` or `EDITOR`, or else `vi`,
+/// waiting for it to be closed.
+fn edit(path: &Path) -> Result<()> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .ok()
+        .filter(|editor| !editor.trim().is_empty())
+        .unwrap_or_else(|| "vi".to_string());
+    // The editor may be given with arguments, eg. `code --wait`
+    let mut args = editor.split_whitespace();
+    let program = args.next().unwrap_or("vi");
+
+    let status = Command::new(program)
+        .args(args)
+        .arg(path)
+        .status()
+        .map_err(|err| Error::CommandFailed(program.to_string(), err))?;
+
+    if !status.success() {
+        warn!("`{}` exited with {}", editor, status);
+    }
+
+    Ok(())
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().map_or_else(|| path.display().to_string(), |f| f.to_string_lossy().to_string())
+}
+
+/// Converts the review into a JUnit test suite with a test case per revision.
+fn to_junit(name: &str, review: &Review) -> TestSuite {
+    let invalid = review.invalid.iter().filter(|invalid| review.is_reviewed(Path::new(&invalid.filename))).map(|invalid| TestCase {
+        name: invalid.filename.clone(),
+        failures: vec![("invalid".to_string(), invalid.error.to_string())],
+    });
+
+    let revisions = review.revisions.iter().zip(review.problems());
+    let revisions = revisions.filter(|(revision, _)| review.is_reviewed(&revision.path())).map(|(revision, problems)| TestCase {
+        name: revision.filename.clone(),
+        failures: problems
+            .iter()
+            .map(|p| (p.kind().to_string(), p.message()))
+            .collect(),
+    });
+
+    // Repeatable revisions have no checks that could fail, but are included
+    // so that the suite covers every file
+    let repeatables = review.repeatables.iter().filter(|repeatable| review.is_reviewed(Path::new(&repeatable.filename)));
+    let repeatables = repeatables.map(|repeatable| TestCase {
+        name: repeatable.filename.clone(),
+        failures: vec![],
+    });
+
+    TestSuite {
+        name: name.to_string(),
+        cases: invalid.chain(revisions).chain(repeatables).collect(),
+    }
+}
+
+/// Options for `test` beyond the config and environment.
+#[derive(Clone, Debug, Default)]
+pub struct TestOptions {
+    /// The database to copy as the scratch database, rather than starting
+    /// from an empty one
+    pub template: Option<String>,
+    /// Whether to keep the scratch database afterwards, eg. to look into
+    /// a failure
+    pub keep: bool,
+    /// Whether to revert the revisions once applied, checking that this
+    /// leaves the schema as it was, and then apply them again
+    pub round_trip: bool,
+}
+
+/// Applies every revision to a scratch database created on the server of
+/// the environment, from nothing or a copy of a template, and then checks
+/// the assertions in the tests directory against it before dropping it, so
+/// that CI can tell that the revisions apply cleanly from zero.
+///
+/// Making a round trip of it, the revisions applied are then reverted, most
+/// recent first, by the revert files alongside them, and applied once more,
+/// to check that the revert files work before they're needed.
+pub fn test(cfg: &Config, env: &Environment, opts: &TestOptions) -> Result<()> {
+    if env.protected {
+        return Err(Error::TestProtected(env.name.clone()));
+    }
+
+    // Read beforehand so that a missing file doesn't waste a run
+    let files = AssertionFile::all_from_disk(&cfg.tests.directory)?;
+    let scratch = ScratchDatabase::create(env, opts.template.as_deref(), opts.keep)?;
+
+    match &opts.template {
+        Some(template) => info!("Created scratch database {} from {}\n", scratch.name, template),
+        None => info!("Created scratch database {}\n", scratch.name),
+    }
+
+    let embark_opts = EmbarkOptions {
+        confirmed: true,
+        ..Default::default()
+    };
+    let before = match opts.round_trip {
+        true => Some((testing::applied(cfg, &scratch.env)?, testing::objects(cfg, &scratch.env)?)),
+        false => None,
+    };
+
+    embark(cfg, &scratch.env, &embark_opts)?;
+
+    if let Some((applied, objects)) = before {
+        let reverted = testing::revert(cfg, &scratch.env, &applied)?;
+        let reverted_objects = testing::objects(cfg, &scratch.env)?;
+        let differences = schema::diff(&objects, &reverted_objects);
+
+        if !differences.is_empty() {
+            info!("\nThe schema differs from before they were applied\n");
+            log_differences(&differences);
+
+            return Err(Error::RevertIncomplete(differences.len()));
+        }
+
+        info!("\nReverted {} revision(s), leaving the schema as it was, and applying them again\n", reverted.len());
+        embark(cfg, &scratch.env, &embark_opts)?;
+    }
+
+    if files.is_empty() {
+        return Ok(());
+    }
+
+    info!("\nChecking {} test file(s)\n", files.len());
+
+    let failures = testing::check(&scratch.env, &files)?;
+
+    if !failures.is_empty() {
+        info!("");
+    }
+    for failure in &failures {
+        warn!("  {}:{}  {}", failure.filename, failure.line, failure.reason);
+    }
+
+    match failures.len() {
+        0 => Ok(()),
+        n => Err(Error::TestsFailed(n)),
+    }
+}
+
+/// Options for `bootstrap` beyond the config and environment.
+#[derive(Clone, Debug, Default)]
+pub struct BootstrapOptions {
+    /// Whether to create the database, and the role to own it, should they
+    /// not exist yet
+    pub create_db: bool,
+    /// Whether bootstrapping a protected environment is already confirmed
+    pub confirmed: bool,
+}
+
+/// Creates what the config says the environment needs before applying any
+/// revisions, should it not exist yet, which is the database itself with
+/// `create_db`, and then applies every pending revision as `embark` would,
+/// eg. for a preview environment spun up for a pull request.
+pub fn bootstrap(cfg: &Config, env: &Environment, opts: &BootstrapOptions) -> Result<()> {
+    confirm_protected(env, opts.confirmed, "bootstrap the database and apply revisions")?;
+
+    if opts.create_db {
+        bootstrap::create_database(env, &cfg.bootstrap)?;
+    }
+
+    let extensions: Vec<_> = cfg.bootstrap.extensions.iter().chain(&cfg.revisions.extensions).cloned().collect();
+    bootstrap::create_extensions(&mut Executor::new(cfg, env)?, &extensions)?;
+
+    let embark_opts = EmbarkOptions {
+        confirmed: true,
+        ..Default::default()
+    };
+
+    embark(cfg, env, &embark_opts)
+}
+
+/// Options for `embark` beyond the config and environment.
+#[derive(Clone, Debug, Default)]
+pub struct EmbarkOptions {
+    /// Where to write the run report, as either `.json` or `.md`
+    pub report: Option<PathBuf>,
+    /// Whether to stream NDJSON lifecycle events to standard output
+    pub events: bool,
+    /// Whether applying to a protected environment has already been confirmed
+    pub confirmed: bool,
+    /// What to do if revisions are already being applied elsewhere
+    pub if_already_running: IfAlreadyRunning,
+    /// Where to write the applied revisions afterwards, for `jrny review --state`
+    pub state: Option<PathBuf>,
+    /// Where to write the SQL that applying the revisions would execute,
+    /// rather than applying them
+    pub emit_sql: Option<PathBuf>,
+    /// Whether to apply revisions during a freeze window regardless
+    pub override_freeze: bool,
+    /// When to start, waiting until then before connecting to the database
+    pub not_before: Option<DateTime<Utc>>,
+    /// Whether this is a rehearsal against a copy of another environment,
+    /// which mustn't itself be protected
+    pub rehearse: bool,
+    /// Where to save the plans of statements changing data while rehearsing,
+    /// if they're to be explained
+    pub explain: Option<PathBuf>,
+    /// Whether to terminate sessions found blocking statements from taking
+    /// locks, which isn't allowed in protected environments
+    pub terminate_blockers: bool,
+    /// Rewriters to apply to statements after those configured, when using
+    /// jrny as a library
+    pub rewriters: Vec<Arc<dyn Rewriter>>,
+    /// Whether the revisions must be in a signed manifest, as they also must
+    /// should the environment require it
+    pub require_signature: bool,
+    /// Where the signed manifest is, defaulting to `jrny-manifest.txt`
+    pub manifest: Option<PathBuf>,
+    /// How far replicas may lag behind before pausing until they catch up,
+    /// rather than as far as the environment allows
+    pub max_replication_lag: Option<Duration>,
+    /// Whether the revisions of every project are committed together with a
+    /// two-phase commit, when applying those of every project in a workspace
+    pub two_phase: bool,
+}
+
+/// Applies all pending revisions specified by the given config to the
+/// database specified by the environment, summarizing the run afterwards.
+///
+/// Revisions are only applied while holding a lock on the database, so that
+/// runs started at once, eg. by several replicas, don't apply them twice.
+pub fn embark(cfg: &Config, env: &Environment, opts: &EmbarkOptions) -> Result<()> {
+    if opts.rehearse && env.protected {
+        return Err(Error::RehearsalProtected(env.name.clone()));
+    }
+    if opts.terminate_blockers && env.protected {
+        return Err(Error::TerminateBlockersProtected(env.name.clone()));
+    }
+
+    // Fail before applying anything rather than after
+    if let Some(path) = &opts.report {
+        ReportFormat::from_path(path)?;
+    }
+
+    // Confirmed before waiting, rather than whenever the run starts
+    let confirmed = match opts.not_before {
+        Some(start) => {
+            confirm_protected(env, opts.confirmed, &format!("apply revisions at {}", local_time(start)))?;
+            wait_until(start);
+            true
+        }
+        None => opts.confirmed,
+    };
+
+    let mut exec = Executor::new(cfg, env)?;
+
+    if let Some(path) = &opts.emit_sql {
+        return emit_sql(cfg, env, &mut exec, path, &opts.rewriters);
+    }
+
+    let events = EventStream::new(opts.events);
+
+    let nothing_to_apply = |exec: &mut Executor, events: &EventStream| {
+        info!("No revisions to apply");
+        events.run_start(0);
+        events.run_done(&RunReport::empty());
+
+        match &opts.state {
+            Some(path) => write_state(env, exec, path),
+            None => Ok(()),
+        }
+    };
+
+    // Whatever's reading the events isn't there to answer
+    if !opts.events {
+        resolve_changed(cfg, env, &mut exec, confirmed)?;
+    }
+
+    // Usually there's nothing to apply, which is safe to find without the
+    // lock, provided the tracking tables needn't be created
+    if exec.tracking_table_exists()? && Embark::prepare(cfg, env, &mut exec)?.is_empty() {
+        return nothing_to_apply(&mut exec, &events);
+    }
+
+    if !exec.try_lock()? {
+        match opts.if_already_running {
+            IfAlreadyRunning::Wait => {
+                info!("Revisions are already being applied elsewhere, waiting for them to finish");
+                exec.lock()?;
+            }
+            IfAlreadyRunning::Skip => {
+                info!("Revisions are already being applied elsewhere, skipping");
+                events.run_start(0);
+                events.run_done(&RunReport::empty());
+                return Ok(());
+            }
+        }
+    }
+
+    // Another run may have applied some or all of them in the meantime
+    let mut cmd = Embark::prepare(cfg, env, &mut exec)?.with_rewriters(&opts.rewriters);
+
+    if cmd.is_empty() {
+        return nothing_to_apply(&mut exec, &events);
+    }
+
+    check_after_projects(cfg, &cmd)?;
+    check_signature(cfg, env, &cmd, opts.require_signature, opts.manifest.as_deref().unwrap_or(Path::new(MANIFEST)))?;
+
+    if opts.terminate_blockers {
+        cmd = cmd.terminating_blockers();
+    }
+    if let Some(max) = opts.max_replication_lag {
+        cmd = cmd.limiting_replication_lag(max);
+    }
+    if let Some(dir) = opts.explain.as_deref().filter(|_| opts.rehearse) {
+        fs::create_dir_all(dir)?;
+        cmd = cmd.explaining(dir);
+    }
+
+    if let Some((window, until)) = cmd.frozen_by(env, Utc::now()) {
+        if !opts.override_freeze {
+            return Err(Error::EnvironmentFrozen(env.name.clone(), window.describe(until)));
+        }
+
+        warn!("Overriding the freeze{}", window.describe(until));
+    }
+
+    confirm_protected(env, confirmed, &format!("apply {} revision(s)", cmd.len()))?;
+    check_extensions(cfg, env, &mut exec)?;
+
+    if opts.rehearse {
+        info!("Rehearsing {} revision(s)\n", cmd.len());
+    } else {
+        info!("Applying {} revision(s)\n", cmd.len());
+    }
+    let before = if opts.rehearse { None } else { objects_before(env, &mut exec)? };
+    let (mut report, result) = cmd.apply(&mut exec, events);
+    let result = run_pgtap(cfg, env, events, &mut report, result);
+
+    report.log_summary();
+
+    let result = result.and(check_ownership(env, &mut exec, before));
+
+    if let Some(path) = &opts.report {
+        report.write(path)?;
+    }
+
+    // Whatever was applied before any failure is recorded all the same
+    if let Some(path) = &opts.state {
+        write_state(env, &mut exec, path)?;
+    }
+
+    publish(env, &report);
+
+    result
+}
+
+/// Runs the project's pgTAP files should every one of its revisions have been
+/// applied, as they test the schema as a whole, recording their outcomes and
+/// any failure in the report.
+fn run_pgtap(cfg: &Config, env: &Environment, events: EventStream, report: &mut RunReport, result: Result<()>) -> Result<()> {
+    let result = result.and_then(|_| {
+        report.tests = testing::pgtap(cfg, env, events)?;
+
+        match report.tests.iter().filter(|tests| !tests.is_ok()).count() {
+            0 => Ok(()),
+            n => Err(Error::PgTapFailed(n)),
+        }
+    });
+    if let (Err(e), None) = (&result, &report.error) {
+        report.error = Some(e.to_string());
+        report.error_code = Some(e.id());
+    }
+
+    result
+}
+
+/// Fails before applying anything should the database lack extensions that
+/// the revisions need, unless the environment has them created instead.
+fn check_extensions(cfg: &Config, env: &Environment, exec: &mut Executor) -> Result<()> {
+    let missing = exec.missing_extensions(&cfg.revisions.extensions)?;
+
+    if !missing.is_empty() && !env.create_extensions {
+        return Err(Error::ExtensionsMissing(missing));
+    }
+
+    bootstrap::create_extensions(exec, &missing)
+}
+
+/// The moment in local time, for messages about waiting until then.
+fn local_time(moment: DateTime<Utc>) -> String {
+    moment.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S %:z").to_string()
+}
+
+/// Sleeps until the moment given, should it be yet to come, without any
+/// connection to the database open in the meantime. The clock is checked
+/// every minute, should it be changed while waiting.
+fn wait_until(start: DateTime<Utc>) {
+    let remaining = || (start - Utc::now()).to_std().ok().filter(|remaining| !remaining.is_zero());
+
+    if let Some(remaining) = remaining() {
+        info!("Waiting until {} ({} from now) to start", local_time(start), format_duration(remaining));
+    }
+
+    while let Some(remaining) = remaining() {
+        thread::sleep(remaining.min(Duration::from_secs(60)));
+    }
+}
+
+/// Fails unless the manifest is signed by a trusted key and has every pending
+/// revision in it as it is, should a signature be required either by the
+/// option or by the environment, and unless every pending revision file is
+/// signed by one, should the environment require that.
+fn check_signature(cfg: &Config, env: &Environment, cmd: &Embark, required: bool, manifest: &Path) -> Result<()> {
+    let settings = match &env.signing {
+        Some(settings) => settings,
+        None if required => return Err(Error::SigningNotConfigured),
+        None => return Ok(()),
+    };
+
+    let manifest = if required || settings.required {
+        Some(signing::verify(manifest, settings)?)
+    } else {
+        None
+    };
+    let revisions = cmd
+        .to_apply
+        .iter()
+        .map(|revision| (revision.path_name(), revision.contents.as_deref().unwrap_or_default()));
+    let repeatables = cmd
+        .repeatables_to_apply
+        .iter()
+        .map(|repeatable| (repeatable.filename.clone(), repeatable.contents.as_str()));
+
+    let mut unsigned = 0;
+
+    for (filename, contents) in revisions.chain(repeatables) {
+        let problem = manifest.as_ref().and_then(|manifest| manifest.check(&filename, contents));
+        let problem = problem.or_else(|| {
+            let path = cfg.revisions.directory.join(&filename);
+            let unsigned = settings.files.then(|| signing::verify_contents(contents.as_bytes(), &path, settings).err()).flatten();
+
+            unsigned.map(|reason| format!("isn't signed by a trusted key: {}", reason))
+        });
+
+        if let Some(problem) = problem {
+            warn!("  {}  {}", filename, problem);
+            unsigned += 1;
+        }
+    }
+
+    match unsigned {
+        0 => Ok(()),
+        n => Err(Error::RevisionsNotSigned(n)),
+    }
+}
+
+/// Applies the pending revisions of every project in the workspace, each with
+/// the config and environment files in its directory, ordered so that those
+/// to be applied after revisions of other projects are. Consecutive revisions
+/// of the same project are applied together, as with `embark`, stopping at
+/// the first to fail.
+pub fn embark_all_projects(workspace: &Workspace, opts: &EmbarkOptions) -> Result<()> {
+    // Each of these is of a single project's run
+    let single = [
+        ("--report", opts.report.is_some()),
+        ("--events", opts.events),
+        ("--write-state", opts.state.is_some()),
+        ("--emit-sql", opts.emit_sql.is_some()),
+        ("--rehearse", opts.rehearse),
+        ("--manifest", opts.manifest.is_some()),
+    ];
+
+    if let Some((option, _)) = single.iter().find(|(_, given)| *given) {
+        return Err(Error::OptionsConflict(option.to_string(), "--all-projects".to_string()));
+    }
+
+    let mut loaded = vec![];
+
+    for project in &workspace.projects {
+        let (cfg, env) = load_project(&workspace.root.join(project))?;
+
+        if opts.terminate_blockers && env.protected {
+            return Err(Error::TerminateBlockersProtected(env.name.clone()));
+        }
+
+        loaded.push((cfg, env));
+    }
+
+    let confirmed = match opts.not_before {
+        Some(start) => {
+            for (project, (_, env)) in workspace.projects.iter().zip(&loaded) {
+                let action = format!("apply revisions of {} at {}", project.display(), local_time(start));
+                confirm_protected(env, opts.confirmed, &action)?;
+            }
+            wait_until(start);
+            true
+        }
+        None => opts.confirmed,
+    };
+
+    let mut projects = vec![];
+
+    for (cfg, env) in loaded {
+        let exec = Executor::new(&cfg, &env)?;
+        projects.push((cfg, env, exec));
+    }
+
+    // Every project is locked before any is prepared, so that what's applied
+    // elsewhere can't change the order in the meantime
+    for (_, _, exec) in projects.iter_mut() {
+        if !exec.try_lock()? {
+            match opts.if_already_running {
+                IfAlreadyRunning::Wait => {
+                    info!("Revisions are already being applied elsewhere, waiting for them to finish");
+                    exec.lock()?;
+                }
+                IfAlreadyRunning::Skip => {
+                    info!("Revisions are already being applied elsewhere, skipping");
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    let (mut embarks, mut applied) = (vec![], vec![]);
+
+    for (project, (cfg, env, exec)) in workspace.projects.iter().zip(projects.iter_mut()) {
+        let review = Review::annotated_revisions(exec, &cfg.revisions, env)?;
+        let names = review.revisions.iter().filter(|revision| revision.applied_on.is_some()).map(|revision| revision.name.clone());
+        applied.push(names.collect::<HashSet<_>>());
+
+        let embark = Embark::from_review(cfg, env, review)?.with_rewriters(&opts.rewriters);
+        check_signature(cfg, env, &embark, opts.require_signature, &workspace.root.join(project).join(MANIFEST))?;
+
+        let embark = if opts.terminate_blockers { embark.terminating_blockers() } else { embark };
+
+        embarks.push(match opts.max_replication_lag {
+            Some(max) => embark.limiting_replication_lag(max),
+            None => embark,
+        });
+    }
+
+    let pending: Vec<Pending> = embarks
+        .iter()
+        .map(|embark| {
+            embark
+                .to_apply
+                .iter()
+                .map(|revision| (revision.name.as_str(), revision.meta.as_ref().map_or(&[][..], |meta| &meta.after[..])))
+                .collect()
+        })
+        .collect();
+    let order = workspace.order(&pending, &applied).map_err(Error::ProjectOrderInvalid)?;
+
+    let total: usize = embarks.iter().map(Embark::len).sum();
+    let with_pending = embarks.iter().filter(|embark| !embark.is_empty()).count();
+
+    if total == 0 {
+        info!("No revisions to apply");
+        return Ok(());
+    }
+
+    for ((project, (_, env, _)), embark) in workspace.projects.iter().zip(&projects).zip(&embarks) {
+        if embark.is_empty() {
+            continue;
+        }
+
+        if let Some((window, until)) = embark.frozen_by(env, Utc::now()) {
+            if !opts.override_freeze {
+                return Err(Error::EnvironmentFrozen(env.name.clone(), window.describe(until)));
+            }
+
+            warn!("Overriding the freeze for {}{}", project.display(), window.describe(until));
+        }
+
+        confirm_protected(env, confirmed, &format!("apply {} revision(s) of {}", embark.len(), project.display()))?;
+    }
+
+    for ((cfg, env, exec), embark) in projects.iter_mut().zip(&embarks) {
+        if !embark.is_empty() {
+            check_extensions(cfg, env, exec)?;
+        }
+    }
+
+    if opts.two_phase {
+        return embark_two_phase(workspace, &mut projects, embarks, &order);
+    }
+
+    // Each step applies consecutive revisions of one project, with its
+    // repeatable revisions applied by its last
+    let mut queues: Vec<_> = embarks.iter_mut().map(|embark| std::mem::take(&mut embark.to_apply).into_iter()).collect();
+    let mut steps: Vec<(usize, Vec<AnnotatedRevision>, Vec<AnnotatedRepeatable>)> = vec![];
+
+    for p in order {
+        let revision = queues[p].next().unwrap();
+
+        match steps.last_mut() {
+            Some((last, revisions, _)) if *last == p => revisions.push(revision),
+            _ => steps.push((p, vec![revision], vec![])),
+        }
+    }
+
+    for (p, embark) in embarks.iter_mut().enumerate().filter(|(_, embark)| !embark.repeatables_to_apply.is_empty()) {
+        let repeatables = std::mem::take(&mut embark.repeatables_to_apply);
+
+        match steps.iter_mut().rev().find(|(last, _, _)| *last == p) {
+            Some((_, _, step_repeatables)) => *step_repeatables = repeatables,
+            None => steps.push((p, vec![], repeatables)),
+        }
+    }
+
+    info!("Applying {} revision(s) across {} project(s)\n", total, with_pending);
+
+    for (p, revisions, repeatables) in &steps {
+        let filenames = revisions.iter().map(|revision| &revision.filename).chain(repeatables.iter().map(|repeatable| &repeatable.filename));
+
+        for filename in filenames {
+            info!("  {:43}{}", workspace.projects[*p].display(), filename);
+        }
+    }
+
+    // A project's pgTAP files are run once its last step is applied
+    let last_steps: Vec<_> = (0..steps.len()).filter(|&i| steps[i + 1..].iter().all(|(q, _, _)| *q != steps[i].0)).collect();
+
+    for (i, (p, revisions, repeatables)) in steps.into_iter().enumerate() {
+        let (cfg, env, exec) = &mut projects[p];
+        let embark = &mut embarks[p];
+        embark.to_apply = revisions;
+        embark.repeatables_to_apply = repeatables;
+
+        info!("\nApplying {} revision(s) of {}\n", embark.len(), workspace.projects[p].display());
+
+        let before = objects_before(env, exec)?;
+        let (mut report, result) = embark.apply(exec, EventStream::new(false));
+        let result = match last_steps.contains(&i) {
+            true => run_pgtap(cfg, env, EventStream::new(false), &mut report, result),
+            false => result,
+        };
+        report.log_summary();
+        publish(env, &report);
+
+        result.and(check_ownership(env, exec, before))?;
+    }
+
+    Ok(())
+}
+
+/// Applies the pending revisions of each project with any in a transaction
+/// of its own, in the order the projects first come in, preparing each for a
+/// two-phase commit rather than committing it. Only once every one is
+/// prepared are they committed, in the same order, or should any fail, those
+/// prepared are rolled back, most recently prepared first. Transactions that
+/// can't be committed or rolled back are left for `jrny prepared resolve`.
+fn embark_two_phase(
+    workspace: &Workspace,
+    projects: &mut [(Config, Environment, Executor)],
+    embarks: Vec<Embark>,
+    order: &[usize],
+) -> Result<()> {
+    let mut participants: Vec<usize> = vec![];
+
+    // Each project's revisions are applied at once, so can't be ordered
+    // around another's
+    for &p in order {
+        if participants.last() == Some(&p) {
+            continue;
+        }
+        if participants.contains(&p) {
+            return Err(Error::ProjectOrderInvalid(format!(
+                "{} has revisions both before and after those of another project, so they can't be committed together",
+                workspace.projects[p].display(),
+            )));
+        }
+        participants.push(p);
+    }
+
+    // Projects with only repeatable revisions to apply come last
+    for (p, embark) in embarks.iter().enumerate() {
+        if !embark.is_empty() && !participants.contains(&p) {
+            participants.push(p);
+        }
+    }
+
+    // Revisions left prepared by an earlier run would be applied again, or
+    // wait on those prepared for as long as they're left
+    let mut unresolved = HashSet::new();
+
+    for (project, (_, _, exec)) in workspace.projects.iter().zip(projects.iter_mut()) {
+        for transaction in exec.prepared_transactions()? {
+            if transaction.id.project == project.display().to_string() {
+                unresolved.insert(transaction.id.to_string());
+            }
+        }
+    }
+
+    if !unresolved.is_empty() {
+        return Err(Error::PreparedTransactionsUnresolved(unresolved.len()));
+    }
+
+    let run = TransactionId::run(Utc::now());
+    let total = embarks.iter().map(Embark::len).sum::<usize>();
+    let mut embarks: Vec<_> = embarks.into_iter().map(Some).collect();
+    let mut preparing = vec![];
+
+    // Everything that could stop a transaction from being prepared is
+    // checked before any are
+    for (i, &p) in participants.iter().enumerate() {
+        let project = workspace.projects[p].display().to_string();
+        let id = TransactionId { run: run.clone(), position: i + 1, total: participants.len(), project: project.clone() };
+        let gid = id.gid().ok_or_else(|| {
+            Error::PreparedTransactionInvalid(project.clone(), "has too long a path to name its transaction".to_string())
+        })?;
+
+        if !projects[p].2.allows_prepared_transactions()? {
+            return Err(Error::PreparedTransactionsDisabled(project));
+        }
+
+        let (_, env, exec) = &mut projects[p];
+        let before = objects_before(env, exec)?;
+
+        preparing.push((p, embarks[p].take().unwrap().preparing(&gid)?, gid, before));
+    }
+
+    info!("Applying {} revision(s) across {} project(s), committing them together\n", total, participants.len());
+
+    for (p, embark, _, _) in &preparing {
+        let repeatables = embark.repeatables_to_apply.iter().map(|repeatable| &repeatable.filename);
+
+        for filename in embark.to_apply.iter().map(|revision| &revision.filename).chain(repeatables) {
+            info!("  {:43}{}", workspace.projects[*p].display(), filename);
+        }
+    }
+
+    let mut prepared: Vec<(usize, String, RunReport, _)> = vec![];
+
+    for (p, embark, gid, before) in preparing {
+        let (_, env, exec) = &mut projects[p];
+
+        info!("\nApplying {} revision(s) of {}\n", embark.len(), workspace.projects[p].display());
+
+        let (report, result) = embark.apply(exec, EventStream::new(false));
+
+        if let Err(e) = result {
+            report.log_summary();
+            publish(env, &report);
+
+            for (p, gid, mut report, _) in prepared.into_iter().rev() {
+                let (_, env, exec) = &mut projects[p];
+
+                match exec.rollback_prepared(&gid) {
+                    Ok(()) => {
+                        let applied = report.revisions.iter_mut().filter(|r| r.status == RevisionStatus::Applied);
+                        let count = applied.map(|r| r.status = RevisionStatus::RolledBack).count();
+                        warn!("Rolled back {} ({} revision(s) undone)", gid, count);
+                    }
+                    Err(e) => warn!("Could not roll back {}: {}", gid, e),
+                }
+                publish(env, &report);
+            }
+
+            return Err(e);
+        }
+
+        prepared.push((p, gid, report, before));
+    }
+
+    let mut left = 0;
+    let mut checked = Ok(());
+
+    // Once any is committed, the rest must be, so a failure leaves them all
+    // prepared for resolving later rather than rolling any back
+    for (p, gid, mut report, before) in prepared {
+        let (cfg, env, exec) = &mut projects[p];
+
+        if left == 0 {
+            match exec.commit_prepared(&gid) {
+                Ok(()) => {
+                    info!("\nCommitted {}", gid);
+                    let tested = run_pgtap(cfg, env, EventStream::new(false), &mut report, Ok(()));
+                    report.log_summary();
+                    publish(env, &report);
+                    checked = checked.and(tested).and(check_ownership(env, exec, before));
+                    continue;
+                }
+                Err(e) => warn!("Could not commit {}: {}", gid, e),
+            }
+        }
+
+        left += 1;
+    }
+
+    match left {
+        0 => checked,
+        n => Err(Error::PreparedTransactionsLeft(n)),
+    }
+}
+
+/// Lists the transactions left prepared by two-phase runs that didn't finish
+/// on the servers of every project in the workspace, by run, along with how
+/// `jrny prepared resolve` would resolve each run.
+pub fn prepared_list(workspace: &Workspace) -> Result<()> {
+    let (_, runs) = orphaned_transactions(workspace)?;
+
+    if runs.is_empty() {
+        info!("No transactions are left prepared");
+        return Ok(());
+    }
+
+    info!("Transactions left prepared by {} run(s)", runs.len());
+
+    for (run, transactions) in &runs {
+        info!("\n  Run {}, {}\n", run, resolution(transactions).describe());
+
+        for (transaction, _) in transactions {
+            info!(
+                "    {:>5}  {:43}{:width$}{} in {}",
+                format!("{}/{}", transaction.id.position, transaction.id.total),
+                transaction.id.project,
+                format_time(transaction.prepared),
+                transaction.owner,
+                transaction.database,
+                width = time_width(),
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Options for `prepared resolve` beyond the workspace.
+#[derive(Clone, Debug, Default)]
+pub struct PreparedOptions {
+    /// Whether resolving transactions in protected environments is already
+    /// confirmed
+    pub confirmed: bool,
+}
+
+/// Commits or rolls back the transactions left prepared by each two-phase
+/// run that didn't finish, as far as the run got: they're rolled back should
+/// it have stopped before every project's transaction was prepared, and
+/// otherwise committed. Every project is locked first, so that a run still
+/// going is waited for rather than resolved.
+pub fn prepared_resolve(workspace: &Workspace, opts: &PreparedOptions) -> Result<()> {
+    let (mut projects, runs) = orphaned_transactions(workspace)?;
+
+    if runs.is_empty() {
+        info!("No transactions are left prepared");
+        return Ok(());
+    }
+
+    for (project, (_, env, _)) in workspace.projects.iter().zip(&projects) {
+        if runs.values().flatten().any(|(_, p)| workspace.projects[*p] == *project) {
+            confirm_protected(env, opts.confirmed, &format!("resolve the prepared transactions of {}", project.display()))?;
+        }
+    }
+
+    for (_, _, exec) in projects.iter_mut() {
+        if !exec.try_lock()? {
+            info!("Revisions are already being applied elsewhere, waiting for them to finish");
+            exec.lock()?;
+        }
+    }
+
+    // A run may have finished while waiting for it
+    let mut left = HashSet::new();
+
+    for (_, _, exec) in projects.iter_mut() {
+        left.extend(exec.prepared_transactions()?.into_iter().map(|transaction| transaction.id.to_string()));
+    }
+
+    for (run, mut transactions) in runs {
+        transactions.retain(|(transaction, _)| left.contains(&transaction.id.to_string()));
+
+        if transactions.is_empty() {
+            continue;
+        }
+
+        let resolution = resolution(&transactions);
+
+        info!("\nResolving run {}, {}\n", run, resolution.describe());
+
+        // Rolled back in reverse, as the run itself would have
+        if resolution == Resolution::RollBack {
+            transactions.reverse();
+        }
+
+        for (transaction, p) in transactions {
+            let (_, _, exec) = &mut projects[p];
+            let gid = transaction.id.to_string();
+
+            match resolution {
+                Resolution::Commit => exec.commit_prepared(&gid)?,
+                Resolution::RollBack => exec.rollback_prepared(&gid)?,
+            }
+
+            info!("  {:43}{}", transaction.id.project, gid);
+        }
+    }
+
+    Ok(())
+}
+
+/// How the run's transactions left prepared are resolved.
+fn resolution(transactions: &[(PreparedTransaction, usize)]) -> Resolution {
+    let left: Vec<_> = transactions.iter().map(|(transaction, _)| transaction.id.position).collect();
+
+    Resolution::of(transactions[0].0.id.total, &left)
+}
+
+/// Every project in the workspace, connected to, along with the transactions
+/// left prepared on their servers by jrny, each once with the project it's of,
+/// by run and in order. Those of projects no longer in the workspace are only
+/// warned about.
+#[allow(clippy::type_complexity)]
+fn orphaned_transactions(
+    workspace: &Workspace,
+) -> Result<(Vec<(Config, Environment, Executor)>, BTreeMap<String, Vec<(PreparedTransaction, usize)>>)> {
+    let mut projects = vec![];
+    let mut runs: BTreeMap<String, Vec<(PreparedTransaction, usize)>> = BTreeMap::new();
+    let mut seen = HashSet::new();
+
+    for project in &workspace.projects {
+        let (cfg, env) = load_project(&workspace.root.join(project))?;
+        let mut exec = Executor::new(&cfg, &env)?;
+
+        for transaction in exec.prepared_transactions()? {
+            if !seen.insert(transaction.id.to_string()) {
+                continue;
+            }
+
+            match workspace.projects.iter().position(|project| project.display().to_string() == transaction.id.project) {
+                Some(p) => runs.entry(transaction.id.run.clone()).or_default().push((transaction, p)),
+                None => warn!("{} is of a project not in the workspace, so is left as it is", transaction.id),
+            }
+        }
+
+        projects.push((cfg, env, exec));
+    }
+
+    for transactions in runs.values_mut() {
+        transactions.sort_by_key(|(transaction, _)| transaction.id.position);
+    }
+
+    Ok((projects, runs))
+}
+
+/// What's in the database before revisions are applied, for finding what they
+/// create, if the environment checks who owns it.
+fn objects_before(env: &Environment, exec: &mut Executor) -> Result<Option<HashSet<String>>> {
+    match &env.ownership {
+        Some(settings) => Ok(Some(exec.objects(&settings.owner)?.into_iter().map(|object| object.key).collect())),
+        None => Ok(None),
+    }
+}
+
+/// Warns about objects created since those found before that aren't owned by
+/// the environment's owner, or lack its default privileges, failing should
+/// the environment say to. A failure to check at all is only warned about.
+fn check_ownership(env: &Environment, exec: &mut Executor, before: Option<HashSet<String>>) -> Result<()> {
+    let (settings, before) = match (&env.ownership, before) {
+        (Some(settings), Some(before)) => (settings, before),
+        _ => return Ok(()),
+    };
+
+    let after = match exec.objects(&settings.owner) {
+        Ok(after) => after,
+        Err(e) => {
+            warn!("Could not check who owns the objects created: {}", e);
+            return Ok(());
+        }
+    };
+
+    let misowned = ownership::misowned(&before, &after, &settings.owner);
+
+    for (object, problems) in &misowned {
+        warn!("The {} {} is {}", object.kind, object.name, problems.join(", and "));
+    }
+
+    if settings.fail && !misowned.is_empty() {
+        return Err(Error::ObjectsMisowned(misowned.len(), settings.owner.clone()));
+    }
+
+    Ok(())
+}
+
+/// Publishes the outcome of a run wherever the environment says to, with
+/// failures only warned about so as not to obscure the outcome itself.
+fn publish(env: &Environment, report: &RunReport) {
+    if let Some(settings) = &env.metrics {
+        if let Err(e) = metrics::publish(settings, report) {
+            warn!("Could not publish metrics: {}", e);
+        }
+    }
+
+    if let Some(settings) = &env.tracing {
+        if let Err(e) = telemetry::export(settings, report) {
+            warn!("Could not export trace: {}", e);
+        }
+    }
+
+    if let Some(settings) = &env.notifications {
+        if let Err(e) = notify::send(settings, env.name.as_deref(), report) {
+            warn!("Could not send notification: {}", e);
+        }
+    }
+}
+
+/// Loads the config file in the project's directory, along with the
+/// environment file where it would be found by default.
+fn load_project(dir: &Path) -> Result<(Config, Environment)> {
+    let cfg = Config::from_filepath(&dir.join(CONF))?;
+    let env = Environment::from_filepath(&cfg.revisions.directory.parent().unwrap().join(ENV))?;
+
+    Ok((cfg, env))
+}
+
+/// The names of the revisions applied to the project's environment, taking
+/// none to be if its tracking table doesn't exist yet.
+fn applied_revisions(dir: &Path) -> Result<HashSet<String>> {
+    let (cfg, env) = load_project(dir)?;
+    let mut exec = Executor::new(&cfg, &env)?;
+
+    if !exec.tracking_table_exists()? {
+        return Ok(HashSet::new());
+    }
+
+    Ok(exec.load_revisions()?.into_iter().map(|record| record.name).collect())
+}
+
+/// Fails unless the revisions of other projects that pending revisions must be
+/// applied after have been already, as found through the workspace file.
+fn check_after_projects(cfg: &Config, cmd: &Embark) -> Result<()> {
+    let afters: Vec<_> = cmd.to_apply.iter().filter_map(|revision| revision.meta.as_ref()).flat_map(|meta| &meta.after).collect();
+
+    if afters.is_empty() {
+        return Ok(());
+    }
+
+    let root = std::env::current_dir()?.join(cfg.revisions.directory.parent().unwrap());
+    let workspace = Workspace::find(&root)?.ok_or_else(|| Error::WorkspaceNotFound(root.display().to_string()))?;
+    let mut applied: HashMap<PathBuf, HashSet<String>> = HashMap::new();
+
+    for after in afters {
+        let (project, name) = after
+            .rsplit_once(':')
+            .ok_or_else(|| Error::ProjectOrderInvalid(format!("expected `project:name`, found `{}`", after)))?;
+        let dir = workspace.project_dir(Path::new(project)).ok_or_else(|| {
+            Error::ProjectNotInWorkspace(project.to_string(), workspace.filepath().display().to_string())
+        })?;
+
+        if !applied.contains_key(&dir) {
+            applied.insert(dir.clone(), applied_revisions(&dir)?);
+        }
+
+        if !applied[&dir].contains(name) {
+            return Err(Error::ProjectOrderInvalid(format!(
+                "`{}` must be applied first, eg. with `jrny embark --all-projects`",
+                after
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes the SQL that applying the pending revisions would execute, down to
+/// creating the tracking tables and recording each revision, so that it can
+/// be reviewed and run without jrny, eg. with `psql -v ON_ERROR_STOP=1 -f`.
+/// Nothing is written to the database.
+fn emit_sql(cfg: &Config, env: &Environment, exec: &mut Executor, path: &Path, rewriters: &[Arc<dyn Rewriter>]) -> Result<()> {
+    let setup = exec.tracking_tables_sql()?;
+    let review = Review::existing_annotated_revisions(exec, &cfg.revisions, env)?;
+    let cmd = Embark::from_review(cfg, env, review)?.with_rewriters(rewriters);
+
+    let mut sql = format!(
+        "-- {} revision(s) to apply{}, as of {}\n\
+         -- Run with `psql -v ON_ERROR_STOP=1 -f`, so that it stops at the first error\n\n",
+        cmd.len(),
+        env.name.as_ref().map(|name| format!(" to {}", name)).unwrap_or_default(),
+        Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+    );
+
+    // Kept until the session ends, as when jrny applies revisions itself
+    sql.push_str(&exec.lock_sql());
+
+    if let Some(setup) = setup {
+        sql.push_str("\n-- Tracking tables\n");
+        sql.push_str(&setup);
+    }
+
+    sql.push_str(&cmd.emit(exec)?);
+    fs::write(path, sql)?;
+
+    info!("Wrote SQL applying {} revision(s) to {}", cmd.len(), path.display());
+
+    Ok(())
+}
+
+/// Writes what the environment has recorded as applied to the state file.
+fn write_state(env: &Environment, exec: &mut Executor, path: &Path) -> Result<()> {
+    let state = bundle::state(env, exec)?;
+
+    fs::write(path, state.to_json().to_string())?;
+    info!("Wrote {} applied revision(s) to {}", state.revisions.len() + state.repeatables.len(), path.display());
+
+    Ok(())
+}
+
+pub struct RunOptions {
+    /// How long to wait for the database to accept connections, if at all
+    pub wait_for_db: Option<Duration>,
+    /// Whether applying to a protected environment has already been confirmed
+    pub confirmed: bool,
+    /// What to do if revisions are already being applied elsewhere
+    pub if_already_running: IfAlreadyRunning,
+    /// Whether to apply revisions during a freeze window regardless
+    pub override_freeze: bool,
+    /// The program to run once revisions are applied, followed by its arguments
+    pub command: Vec<String>,
+}
+
+/// Applies all pending revisions once the database is available and then
+/// becomes the given command, eg. as the entrypoint of a container.
+pub fn run(cfg: &Config, env: &Environment, opts: &RunOptions) -> Result<()> {
+    let (program, args) = opts.command.split_first().ok_or(Error::CommandMissing)?;
+
+    if let Some(timeout) = opts.wait_for_db {
+        run::wait_for_database(env, timeout)?;
+    }
+
+    let embark_opts = EmbarkOptions {
+        report: None,
+        events: false,
+        confirmed: opts.confirmed,
+        if_already_running: opts.if_already_running,
+        state: None,
+        emit_sql: None,
+        override_freeze: opts.override_freeze,
+        not_before: None,
+        rehearse: false,
+        explain: None,
+        terminate_blockers: false,
+        rewriters: vec![],
+        require_signature: false,
+        manifest: None,
+        max_replication_lag: None,
+        two_phase: false,
+    };
+
+    embark(cfg, env, &embark_opts)?;
+
+    run::exec(program, args)
+}
 
 /// Logs the path string with optional prefix and "[created]" suffix if the created
 /// condition is true.
-fn log_path(prefix: &str, path: &PathBuf, created: bool) {
+fn log_path(prefix: &str, path: &Path, created: bool) {
     info!(
         "{}{}{}",
         prefix,
@@ -0,0 +1,103 @@
+use std::{fs, path::PathBuf};
+
+use super::review::Review;
+use crate::{executor::Executor, Config, Environment, Error, Result};
+
+/// A pending revision file to be renamed to a new id.
+#[derive(Debug)]
+pub(super) struct Rename {
+    pub from: PathBuf,
+    pub to: PathBuf,
+}
+
+/// Renumbers pending revisions so that each has a unique id following all
+/// applied revisions, eg. after two branches each added a revision with
+/// the same id.
+pub(super) struct Resequence {
+    pub renames: Vec<Rename>,
+}
+
+impl Resequence {
+    pub fn prepare(cfg: &Config, env: &Environment, exec: &mut Executor) -> Result<Self> {
+        let review = Review::annotated_revisions(exec, &cfg.revisions, env)?;
+
+        let last_applied = review
+            .revisions
+            .iter()
+            .filter(|revision| revision.applied_on.is_some())
+            .map(|revision| revision.id)
+            .max();
+
+        // Revisions sharing an id keep their relative order of creation, while
+        // those for other environments are left alone since they may well
+        // have been applied there
+        let mut pending: Vec<_> = review
+            .revisions
+            .iter()
+            .filter(|revision| revision.applied_on.is_none() && revision.on_disk && revision.applicable)
+            .collect();
+        pending.sort_by_key(|revision| (revision.id, revision.created_at, revision.filename.clone()));
+
+        let ids = resequenced_ids(last_applied, &pending.iter().map(|r| r.id).collect::<Vec<_>>());
+
+        let renames = pending
+            .iter()
+            .zip(ids)
+            .filter(|(revision, id)| revision.id != *id)
+            .map(|(revision, id)| Rename {
+                from: cfg.revisions.directory.join(revision.path()),
+                to: cfg.revisions.directory.join(revision.path()).with_file_name(format!(
+                    "{:03}.{}.{}.sql",
+                    id,
+                    revision.created_at.timestamp(),
+                    revision.name,
+                )),
+            })
+            .collect();
+
+        Ok(Self { renames })
+    }
+
+    pub fn apply(&self) -> Result<()> {
+        for rename in &self.renames {
+            if rename.to.exists() {
+                return Err(Error::PathAlreadyExists(rename.to.display().to_string()));
+            }
+
+            fs::rename(&rename.from, &rename.to)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Assigns each pending id, in order, the lowest id that is both no lower than
+/// its current one and higher than every id before it, so that ids are only
+/// changed where necessary.
+pub(super) fn resequenced_ids(last_applied: Option<i32>, pending: &[i32]) -> Vec<i32> {
+    let mut previous = last_applied.unwrap_or(0);
+
+    pending
+        .iter()
+        .map(|&id| {
+            previous = id.max(previous + 1);
+            previous
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resequenced_ids_bumps_duplicates() {
+        assert_eq!(resequenced_ids(Some(3), &[4, 4, 5]), vec![4, 5, 6]);
+    }
+
+    #[test]
+    fn resequenced_ids_moves_pending_after_applied() {
+        assert_eq!(resequenced_ids(Some(10), &[2, 11, 15]), vec![11, 12, 15]);
+        assert_eq!(resequenced_ids(None, &[1, 1, 3]), vec![1, 2, 3]);
+    }
+}
@@ -1,33 +1,765 @@
 use std::{
-    collections::HashMap,
-    path::PathBuf,
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
     rc::Rc,
 };
 
+use chrono::{NaiveDate, Utc};
+
 use crate::{
+    analysis::{changed_table, Dropped, DroppedDependents, Impact, LargeTable, TableOperation},
+    batch::Batch,
+    bundle::Bundle,
+    checksum,
+    config::{ReviewSettings, RevisionsSettings},
+    diff,
     executor::Executor,
-    revisions::{AnnotatedRevision, RevisionFile, RevisionRecord},
+    git,
+    meta::{Deadline, RevisionKind, RevisionMeta},
+    paths,
+    pg_version::PgVersion,
+    policy::{Severity, Violation},
+    revisions::{
+        is_applicable,
+        AnnotatedRepeatable,
+        AnnotatedRevision,
+        InvalidRevision,
+        ReappliedKind,
+        RepeatableFile,
+        RepeatableRecord,
+        RevisionFile,
+        RevisionRecord,
+    },
+    statements,
+    Environment,
+    Error,
     Result,
 };
 
+/// The file as the revision was applied, as archived along with its record
+/// or else read from git at the commit it was applied from, provided it was
+/// committed by then.
+fn applied_contents(dir: &Path, name: &str, record: &RevisionRecord) -> Option<String> {
+    let from_git = || git::show(dir, record.provenance.commit.as_deref()?, name);
+
+    record
+        .archived
+        .clone()
+        .filter(|archived| checksum::matches(archived, &record.checksum))
+        .or_else(|| from_git().filter(|applied| checksum::matches(applied, &record.checksum)))
+}
+
+/// The contents of the revision, if it's a pending data revision.
+fn pending_data(revision: &AnnotatedRevision) -> Option<&str> {
+    match (&revision.contents, &revision.meta) {
+        (Some(contents), Some(meta)) if meta.kind == RevisionKind::Data && revision.applied_on.is_none() => Some(contents),
+        _ => None,
+    }
+}
+
+/// The lines of the statements in a pending data revision that update or
+/// delete every row of a table, which is almost never intended.
+fn unbounded_changes(revision: &AnnotatedRevision) -> Vec<usize> {
+    let contents = match pending_data(revision) {
+        Some(contents) => contents,
+        None => return vec![],
+    };
+
+    // Of updates and deletes, only those without a top-level `WHERE` are
+    // destructive, rather than one in a string or subquery
+    statements::split(contents)
+        .iter()
+        .filter(|statement| changed_table(statement).is_some() && statement.destructive_operation().is_some())
+        .map(|statement| statement.line)
+        .collect()
+}
+
+/// A summary of the objects a pending revision creates, alters, or drops,
+/// if any.
+pub(super) fn impact(revision: &AnnotatedRevision) -> Option<String> {
+    let contents = revision.contents.as_deref().filter(|_| revision.applied_on.is_none())?;
+
+    Impact::summary(&statements::split(contents))
+}
+
+/// Suggestions to batch the updates and deletes in a pending data revision
+/// that aren't already, so as not to lock or rewrite a whole table at once.
+pub(super) fn batching_hints(revision: &AnnotatedRevision) -> Vec<String> {
+    let contents = match pending_data(revision) {
+        Some(contents) => contents,
+        None => return vec![],
+    };
+
+    statements::split(contents)
+        .iter()
+        .filter(|statement| matches!(Batch::of(statement), Ok(None)))
+        .filter_map(|statement| {
+            let (table, _) = changed_table(statement)?;
+
+            Some(format!(
+                "Line {}: consider batching the change to `{}` with a `-- jrny:batch` comment before it",
+                statement.line, table,
+            ))
+        })
+        .collect()
+}
+
+/// The `apply_before` date of a pending revision and how it stands against
+/// it today, if it's near or past.
+pub(super) fn deadline(revision: &AnnotatedRevision) -> Option<(NaiveDate, Deadline)> {
+    let meta = revision.meta.as_ref().filter(|_| revision.applied_on.is_none() && revision.applicable)?;
+
+    Some((meta.apply_before?, meta.deadline(Utc::today().naive_utc())?))
+}
+
+/// A warning for a pending revision whose `apply_before` date is near.
+pub(super) fn deadline_warning(revision: &AnnotatedRevision) -> Option<String> {
+    match deadline(revision)? {
+        (date, Deadline::Approaching(days)) => Some(format!("Must be applied before {}, in {} day(s)", date, days)),
+        (_, Deadline::Passed) => None,
+    }
+}
+
+/// Which revisions review lists, for focusing on a few in a large project.
+#[derive(Debug, Default)]
+pub(super) struct ReviewFilter {
+    /// Whether to list only revisions yet to be applied, or repeatable
+    /// revisions that are new or changed
+    pub pending_only: bool,
+    /// Whether to list only revisions whose files changed since they were applied
+    pub changed_only: bool,
+    /// Text the revision's name or path contains, or a pattern matching its
+    /// filename or path, with `*` for any characters and `?` for any one
+    pub pattern: Option<String>,
+}
+
+impl ReviewFilter {
+    pub(super) fn revision(&self, revision: &AnnotatedRevision) -> bool {
+        (!self.pending_only || revision.applied_on.is_none())
+            && (!self.changed_only || revision.checksums_match == Some(false))
+            && self.names(&[&revision.name, &revision.filename, &revision.path_name()])
+    }
+
+    pub(super) fn repeatable(&self, repeatable: &AnnotatedRepeatable) -> bool {
+        (!self.pending_only || repeatable.is_pending())
+            && (!self.changed_only || repeatable.checksums_match == Some(false))
+            && self.names(&[&repeatable.name, &repeatable.filename])
+    }
+
+    fn names(&self, names: &[&str]) -> bool {
+        // Paths are matched as separated by `/`, however they're given
+        match self.pattern.as_deref().map(paths::normalize).as_ref() {
+            Some(pattern) if pattern.contains(['*', '?']) => names.iter().any(|name| glob_matches(pattern, name)),
+            Some(pattern) => names.iter().any(|name| name.contains(pattern.as_str())),
+            None => true,
+        }
+    }
+}
+
+/// Whether the whole of the text matches the pattern, where `*` matches any
+/// characters and `?` any one.
+fn glob_matches(pattern: &str, text: &str) -> bool {
+    let (pattern, text): (Vec<_>, Vec<_>) = (pattern.chars().collect(), text.chars().collect());
+    // Where to resume from after the last `*`, should what follows it not match
+    let (mut p, mut t, mut star) = (0, 0, None);
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((star_p, star_t)) => {
+                    star = Some((star_p, star_t + 1));
+                    p = star_p + 1;
+                    t = star_t + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Reasons that an individual revision fails review.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(super) enum Problem {
+    Changed,
+    NotOnDisk,
+    PredatesApplied,
+    DuplicateId,
+    /// A pending revision has the same name as another, ignoring case and separators
+    DuplicateName,
+    /// The name of a pending revision breaks the configured naming rules
+    Misnamed(Vec<String>),
+    /// A pending data revision updates or deletes every row of a table, on
+    /// each of the given lines
+    Unbounded(Vec<usize>),
+    /// A pending revision breaks policy rules set as errors
+    PolicyViolated(Vec<Violation>),
+    /// A pending revision requires the given revisions, which are neither
+    /// applied nor pending before it
+    UnmetRequirement(Vec<String>),
+    /// An applied revision's file differs from that of the git ref being
+    /// reviewed against
+    ChangedSinceRef(String),
+    /// An applied revision was applied after later revisions were, which is
+    /// only checked for when reviewing in strict order
+    AppliedOutOfOrder,
+    /// A pending revision was to be applied before the given date, which has
+    /// come
+    Expired(NaiveDate),
+    /// A tombstone is superseded by the revision with the given id, which
+    /// can't be found
+    ReplacementMissing(i32),
+    /// A pending revision needs the given version of Postgres, which is
+    /// newer than the server's
+    ServerTooOld(PgVersion, PgVersion),
+}
+
+impl Problem {
+    pub(super) fn message(&self) -> String {
+        match self {
+            Self::Changed => "The file has changed after being applied".to_string(),
+            Self::NotOnDisk => "No corresponding file could not be found".to_string(),
+            Self::PredatesApplied => "Later revisions have already been applied".to_string(),
+            Self::DuplicateId => "Revision has duplicate id".to_string(),
+            Self::DuplicateName => "Another revision has the same name".to_string(),
+            Self::Misnamed(violations) => format!("Name {}", violations.join(", ")),
+            Self::Unbounded(lines) => format!(
+                "Data revision changes every row without a WHERE clause on line(s) {}",
+                lines.iter().map(ToString::to_string).collect::<Vec<_>>().join(", "),
+            ),
+            Self::PolicyViolated(violations) => format!(
+                "Breaks policy: {}",
+                violations.iter().map(Violation::message).collect::<Vec<_>>().join("; "),
+            ),
+            Self::UnmetRequirement(names) => format!("Requires revisions not applied before it: {}", names.join(", ")),
+            Self::ChangedSinceRef(reference) => format!("Already applied, but changed since `{}`", reference),
+            Self::AppliedOutOfOrder => "Applied after later revisions".to_string(),
+            Self::Expired(date) => format!("Was to be applied before {}", date),
+            Self::ReplacementMissing(id) => format!("Superseded by revision {}, which can't be found", id),
+            Self::ServerTooOld(required, server) => format!("Needs Postgres {} or later, but the server is {}", required, server),
+        }
+    }
+
+    /// A short, stable identifier for machine-readable output
+    pub(super) fn kind(&self) -> &'static str {
+        match self {
+            Self::Changed => "changed",
+            Self::NotOnDisk => "missing",
+            Self::PredatesApplied => "predates-applied",
+            Self::DuplicateId => "duplicate-id",
+            Self::DuplicateName => "duplicate-name",
+            Self::Misnamed(_) => "misnamed",
+            Self::Unbounded(_) => "unbounded-change",
+            Self::PolicyViolated(_) => "policy",
+            Self::UnmetRequirement(_) => "unmet-requirement",
+            Self::ChangedSinceRef(_) => "changed-since-ref",
+            Self::AppliedOutOfOrder => "applied-out-of-order",
+            Self::Expired(_) => "expired",
+            Self::ReplacementMissing(_) => "replacement-missing",
+            Self::ServerTooOld(..) => "server-too-old",
+        }
+    }
+}
+
 pub(super) struct Review {
     pub(super) revisions: Vec<AnnotatedRevision>,
+    /// Repeatable revisions on disk, in the order they would be applied
+    pub(super) repeatables: Vec<AnnotatedRepeatable>,
+    /// Entries in the revisions directory that could not be loaded, which
+    /// is only ever populated by `lenient_annotated_revisions`
+    pub(super) invalid: Vec<InvalidRevision>,
+    settings: RevisionsSettings,
+    /// The name of the environment being reviewed, if it has one
+    env_name: Option<String>,
     files: Vec<Rc<RevisionFile>>,
     records: Vec<Rc<RevisionRecord>>,
     files_map: HashMap<String, Rc<RevisionFile>>,
     records_map: HashMap<String, Rc<RevisionRecord>>,
+    repeatable_files: Vec<RepeatableFile>,
+    repeatable_records: HashMap<String, RepeatableRecord>,
+    /// The git ref being reviewed against, if any, and the paths changed since
+    against: Option<(String, HashSet<PathBuf>)>,
+    /// Whether applied revisions must also have been applied in order
+    strict_order: bool,
+    /// The id of the tombstone applied in place of each revision superseding
+    /// it, directly or through other tombstones, by the superseding revision
+    replaced: HashMap<i32, i32>,
+    /// The version of the database's server, unless reviewing without one
+    server_version: Option<PgVersion>,
 }
 
 impl Review {
-    pub(super) fn annotated_revisions(exec: &mut Executor, revision_dir: &PathBuf) -> Result<Self> {
-        Ok(Self::new(exec, revision_dir)?.annotate())
+    pub(super) fn annotated_revisions(exec: &mut Executor, settings: &RevisionsSettings, env: &Environment) -> Result<Self> {
+        Self::new(exec, settings, env)?.strict()
+    }
+
+    /// Like `annotated_revisions` except that entries that cannot be loaded
+    /// as revisions are collected rather than failing the whole review.
+    pub(super) fn lenient_annotated_revisions(
+        exec: &mut Executor,
+        settings: &RevisionsSettings,
+        env: &Environment,
+    ) -> Result<Self> {
+        Ok(Self::new(exec, settings, env)?.annotate())
+    }
+
+    /// Like `annotated_revisions` but without creating the tracking tables,
+    /// taking nothing as recorded in those that don't exist yet.
+    pub(super) fn existing_annotated_revisions(
+        exec: &mut Executor,
+        settings: &RevisionsSettings,
+        env: &Environment,
+    ) -> Result<Self> {
+        let records = if exec.tracking_table_exists()? { exec.load_revisions()? } else { vec![] };
+        let repeatable_records = if exec.reapplied_table_exists(ReappliedKind::Repeatable)? {
+            exec.load_reapplied(ReappliedKind::Repeatable)?
+        } else {
+            vec![]
+        };
+
+        Self {
+            server_version: Some(exec.server_version()?),
+            ..Self::with_records(settings, env.name.clone(), records, repeatable_records)?
+        }
+        .strict()
+    }
+
+    /// Like `annotated_revisions` except that the applied revisions are those
+    /// recorded in a state file, rather than loaded from the database.
+    pub(super) fn recorded_revisions(state: Bundle, settings: &RevisionsSettings) -> Result<Self> {
+        Self::with_records(settings, state.environment, state.revisions, state.repeatables)?.strict()
+    }
+
+    /// Like `lenient_annotated_revisions` except that the applied revisions
+    /// are those recorded in a state file.
+    pub(super) fn lenient_recorded_revisions(state: Bundle, settings: &RevisionsSettings) -> Result<Self> {
+        Ok(Self::with_records(settings, state.environment, state.revisions, state.repeatables)?.annotate())
+    }
+
+    /// Fails with a count of each kind of problem found, if there are any.
+    pub(super) fn check(&self) -> Result<()> {
+        let (mut changed, mut duplicate_ids, mut duplicate_names) = (0, 0, 0);
+        let (mut misnamed, mut missing, mut out_of_order, mut predate_applied) = (0, 0, 0, 0);
+        let (mut expired, mut policy, mut unbounded, mut unmet, mut unreplaced) = (0, 0, 0, 0, 0);
+        let mut unsupported = 0;
+        let invalid = self.invalid.iter().filter(|invalid| self.is_reviewed(Path::new(&invalid.filename))).count();
+        let reviewed = self.revisions.iter().zip(self.problems()).filter(|(revision, _)| self.is_reviewed(&revision.path()));
+
+        for problem in reviewed.flat_map(|(_, problems)| problems) {
+            match problem {
+                Problem::Changed | Problem::ChangedSinceRef(_) => changed += 1,
+                Problem::DuplicateId => duplicate_ids += 1,
+                Problem::DuplicateName => duplicate_names += 1,
+                Problem::Misnamed(_) => misnamed += 1,
+                Problem::NotOnDisk => missing += 1,
+                Problem::PredatesApplied => predate_applied += 1,
+                Problem::Unbounded(_) => unbounded += 1,
+                Problem::PolicyViolated(_) => policy += 1,
+                Problem::UnmetRequirement(_) => unmet += 1,
+                Problem::AppliedOutOfOrder => out_of_order += 1,
+                Problem::Expired(_) => expired += 1,
+                Problem::ReplacementMissing(_) => unreplaced += 1,
+                Problem::ServerTooOld(..) => unsupported += 1,
+            }
+        }
+
+        let failed = changed
+            + duplicate_ids
+            + duplicate_names
+            + expired
+            + invalid
+            + misnamed
+            + missing
+            + out_of_order
+            + policy
+            + unreplaced
+            + unsupported;
+
+        if failed + predate_applied + unbounded + unmet > 0 {
+            return Err(Error::RevisionsFailedReview {
+                changed,
+                duplicate_ids,
+                duplicate_names,
+                expired,
+                invalid,
+                misnamed,
+                missing,
+                out_of_order,
+                policy,
+                predate_applied,
+                unbounded,
+                unmet,
+                unreplaced,
+                unsupported,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Determines which problems, if any, each revision has, in order of
+    /// importance and in the same order as `revisions`.
+    pub(super) fn problems(&self) -> Vec<Vec<Problem>> {
+        let last_applied_index = self
+            .revisions
+            .iter()
+            .rposition(|revision| revision.applied_on.is_some());
+
+        // The earliest that any revision after each was applied, if any were
+        let mut applied_later_on = vec![None; self.revisions.len()];
+        let mut earliest = None;
+
+        for (i, revision) in self.revisions.iter().enumerate().rev() {
+            applied_later_on[i] = earliest;
+            earliest = earliest.into_iter().chain(revision.applied_on).min();
+        }
+
+        let mut seen_ids = HashSet::new();
+
+        let mut name_counts: HashMap<String, usize> = HashMap::new();
+        for revision in &self.revisions {
+            *name_counts.entry(normalize_name(&revision.name)).or_default() += 1;
+        }
+
+        self.revisions
+            .iter()
+            .enumerate()
+            .map(|(i, revision)| {
+                let mut problems = vec![];
+
+                // If checksum comparison is missing, it hasn't been applied so ignore it
+                if let Some(false) = revision.checksums_match {
+                    problems.push(Problem::Changed);
+                }
+                if !revision.on_disk {
+                    problems.push(Problem::NotOnDisk);
+                }
+                if revision.applied_on.is_none()
+                    && revision.applicable
+                    && last_applied_index.is_some_and(|last| i < last)
+                {
+                    problems.push(Problem::PredatesApplied);
+                }
+                if self.strict_order
+                    && revision.applied_on.zip(applied_later_on[i]).is_some_and(|(applied_on, later)| later < applied_on)
+                {
+                    problems.push(Problem::AppliedOutOfOrder);
+                }
+                if !seen_ids.insert(revision.id) {
+                    problems.push(Problem::DuplicateId);
+                }
+                // Only pending revisions can still be renamed
+                if revision.applied_on.is_none() && name_counts[&normalize_name(&revision.name)] > 1 {
+                    problems.push(Problem::DuplicateName);
+                }
+
+                // Applied revisions can't be renamed, so the rules only apply going forward
+                if revision.applied_on.is_none() && revision.on_disk {
+                    let name = environment_suffix(&revision.name, &self.settings.environments)
+                        .map_or(revision.name.as_str(), |(name, _)| name);
+                    let violations = self.settings.naming.violations(name);
+
+                    if !violations.is_empty() {
+                        problems.push(Problem::Misnamed(violations));
+                    }
+                }
+
+                let unbounded = unbounded_changes(revision);
+
+                if !unbounded.is_empty() {
+                    problems.push(Problem::Unbounded(unbounded));
+                }
+
+                let violated: Vec<_> = self
+                    .policy_violations(revision)
+                    .into_iter()
+                    .filter(|violation| violation.severity == Severity::Error)
+                    .collect();
+
+                if !violated.is_empty() {
+                    problems.push(Problem::PolicyViolated(violated));
+                }
+
+                if let Some((reference, paths)) = &self.against {
+                    if revision.applied_on.is_some() && revision.checksums_match != Some(false) && paths.contains(&revision.path()) {
+                        problems.push(Problem::ChangedSinceRef(reference.clone()));
+                    }
+                }
+
+                if let Some((date, Deadline::Passed)) = deadline(revision) {
+                    problems.push(Problem::Expired(date));
+                }
+
+                if let Some(id) = revision.superseded_by() {
+                    if id == revision.id || !self.revisions.iter().any(|replacement| replacement.id == id) {
+                        problems.push(Problem::ReplacementMissing(id));
+                    }
+                }
+
+                let required = revision.meta.as_ref().and_then(|meta| meta.requires_pg).max(self.settings.requires_pg);
+
+                if let Some((required, server)) = required.zip(self.server_version).filter(|(required, server)| required > server) {
+                    if revision.applied_on.is_none() && revision.applicable {
+                        problems.push(Problem::ServerTooOld(required, server));
+                    }
+                }
+
+                let unmet = self.unmet_requirements(i);
+
+                if !unmet.is_empty() {
+                    problems.push(Problem::UnmetRequirement(unmet));
+                }
+
+                problems
+            })
+            .collect()
     }
 
-    fn new(exec: &mut Executor, revision_dir: &PathBuf) -> Result<Self> {
+    /// Limits review to the revisions added or changed since the git ref, as
+    /// with `git::changed_since`, flagging any of them already applied.
+    pub(super) fn against(self, reference: &str) -> Result<Self> {
+        let dir = &self.settings.directory;
+        let changed = git::changed_since(dir, reference)
+            .ok_or_else(|| Error::GitRefInvalid(reference.to_string(), dir.display().to_string()))?;
+
+        Ok(Self { against: Some((reference.to_string(), changed.into_iter().collect())), ..self })
+    }
+
+    /// Also requires applied revisions to have been applied in the order
+    /// they're in on disk, flagging any applied after later revisions were.
+    pub(super) fn strict_order(self) -> Self {
+        Self { strict_order: true, ..self }
+    }
+
+    /// The id of the tombstone that was applied in place of the revision,
+    /// which then isn't applied itself, if one was.
+    pub(super) fn applied_instead(&self, revision: &AnnotatedRevision) -> Option<i32> {
+        self.replaced.get(&revision.id).copied()
+    }
+
+    /// Tombstones only stay applied where they already were, while revisions
+    /// superseding them are only applied where what they supersede, directly
+    /// or through other tombstones, wasn't.
+    fn settle_tombstones(&mut self) {
+        for revision in &self.revisions {
+            let mut superseded = vec![revision.id];
+            let mut seen = HashSet::new();
+
+            while let Some(id) = superseded.pop() {
+                for tombstone in self.revisions.iter().filter(|r| r.superseded_by() == Some(id)) {
+                    if !seen.insert(tombstone.id) {
+                        continue;
+                    }
+                    if tombstone.applied_on.is_some() {
+                        self.replaced.insert(revision.id, tombstone.id);
+                    }
+
+                    superseded.push(tombstone.id);
+                }
+            }
+        }
+
+        for revision in self.revisions.iter_mut().filter(|revision| revision.applied_on.is_none()) {
+            if revision.superseded_by().is_some() || self.replaced.contains_key(&revision.id) {
+                revision.applicable = false;
+            }
+        }
+    }
+
+    /// The record of the revision by its filename, if it's been applied.
+    pub(super) fn record(&self, filename: &str) -> Option<&RevisionRecord> {
+        self.records_map.get(filename).map(|record| record.as_ref())
+    }
+
+    /// How the applied revision's file differs from the contents given, as a
+    /// unified diff of both as normalized by the scheme of its recorded
+    /// checksum, provided the file as it was applied can be found.
+    pub(super) fn changes(&self, revision: &AnnotatedRevision, contents: &str) -> Option<String> {
+        let record = self.record(&revision.filename)?;
+        let name = revision.path_name();
+        let applied = applied_contents(&self.settings.directory, &name, record)?;
+
+        diff::unified(
+            &checksum::normalized_as(&applied, &record.checksum),
+            &checksum::normalized_as(contents, &record.checksum),
+            &format!("{} (applied)", name),
+            &format!("{} (on disk)", name),
+        )
+    }
+
+    /// Whether the file at the path, relative to the revisions directory, is
+    /// being reviewed, which is every file unless reviewing against a ref.
+    pub(super) fn is_reviewed(&self, path: &Path) -> bool {
+        self.against.as_ref().is_none_or(|(_, changed)| changed.contains(path))
+    }
+
+    /// The names of the revisions that the pending revision at the index
+    /// requires, but which are neither applied nor applied before it.
+    fn unmet_requirements(&self, i: usize) -> Vec<String> {
+        let revision = &self.revisions[i];
+        let requires = match &revision.meta {
+            Some(meta) if revision.applied_on.is_none() && revision.applicable => &meta.requires,
+            _ => return vec![],
+        };
+
+        requires
+            .iter()
+            .filter(|name| {
+                !self.revisions.iter().enumerate().any(|(j, required)| {
+                    &required.name == *name && (required.applied_on.is_some() || (j < i && required.applicable))
+                })
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// The ways in which the revision breaks policy rules that aren't off
+    /// for the environment, if it's yet to be applied there.
+    pub(super) fn policy_violations(&self, revision: &AnnotatedRevision) -> Vec<Violation> {
+        match &revision.contents {
+            Some(contents) if revision.applied_on.is_none() && revision.applicable => {
+                self.settings.policy.violations(contents, self.env_name.as_deref())
+            }
+            _ => vec![],
+        }
+    }
+
+    /// Finds statements in each pending revision that lock a table exceeding
+    /// either threshold, in the same order as `revisions`. Tables that don't
+    /// exist yet, eg. as they're created by an earlier revision, are ignored.
+    pub(super) fn large_tables(&self, exec: &mut Executor, settings: &ReviewSettings) -> Result<Vec<Vec<LargeTable>>> {
+        let mut sizes = HashMap::new();
+        let mut all = vec![];
+
+        for revision in &self.revisions {
+            let mut large = vec![];
+            let contents = match &revision.contents {
+                Some(contents) if revision.applied_on.is_none() && revision.applicable => contents,
+                _ => {
+                    all.push(large);
+                    continue;
+                }
+            };
+
+            for statement in statements::split(contents) {
+                let (operation, table) = match TableOperation::of(&statement) {
+                    Some(found) => found,
+                    None => continue,
+                };
+
+                if !sizes.contains_key(&table) {
+                    sizes.insert(table.clone(), exec.table_size(&table)?);
+                }
+
+                let size = match sizes[&table] {
+                    Some(size) => size,
+                    None => continue,
+                };
+
+                let exceeds_rows = size.rows.is_some_and(|rows| rows > settings.large_table_rows);
+                let exceeds_bytes = size.bytes > settings.large_table_megabytes * 1024 * 1024;
+
+                if exceeds_rows || exceeds_bytes {
+                    large.push(LargeTable {
+                        line: statement.line,
+                        operation,
+                        table,
+                        size,
+                    });
+                }
+            }
+
+            all.push(large);
+        }
+
+        Ok(all)
+    }
+
+    /// Finds statements in each pending revision that drop an object others
+    /// depend on, in the same order as `revisions`. Dependents dropped by an
+    /// earlier statement are left out, as are objects that don't exist yet.
+    pub(super) fn dependents(&self, exec: &mut Executor) -> Result<Vec<Vec<DroppedDependents>>> {
+        let mut dropped_before = HashSet::new();
+        let mut all = vec![];
+
+        for revision in &self.revisions {
+            let mut found = vec![];
+            let contents = match &revision.contents {
+                Some(contents) if revision.applied_on.is_none() && revision.applicable => contents,
+                _ => {
+                    all.push(found);
+                    continue;
+                }
+            };
+
+            for statement in statements::split(contents) {
+                for dropped in Dropped::all_for(&statement) {
+                    let (key, dependents) = match exec.dependents(&dropped)? {
+                        Some(existing) => existing,
+                        None => continue,
+                    };
+                    let dependents: Vec<_> = dependents
+                        .into_iter()
+                        .filter(|dependent| !dropped_before.contains(&dependent.key))
+                        .map(|dependent| dependent.description)
+                        .collect();
+
+                    dropped_before.insert(key);
+
+                    if !dependents.is_empty() {
+                        found.push(DroppedDependents {
+                            line: statement.line,
+                            dropped,
+                            dependents,
+                        });
+                    }
+                }
+            }
+
+            all.push(found);
+        }
+
+        Ok(all)
+    }
+
+    fn new(exec: &mut Executor, settings: &RevisionsSettings, env: &Environment) -> Result<Self> {
         exec.ensure_table_exists()?;
 
-        let mut files = RevisionFile::all_from_disk(revision_dir)?;
-        let mut records = exec.load_revisions()?;
+        let records = exec.load_revisions()?;
+        let repeatable_records = exec.load_reapplied(ReappliedKind::Repeatable)?;
+
+        Ok(Self {
+            server_version: Some(exec.server_version()?),
+            ..Self::with_records(settings, env.name.clone(), records, repeatable_records)?
+        })
+    }
+
+    fn with_records(
+        settings: &RevisionsSettings,
+        env_name: Option<String>,
+        mut records: Vec<RevisionRecord>,
+        repeatable_records: Vec<RepeatableRecord>,
+    ) -> Result<Self> {
+        let revision_dir = &settings.directory;
+
+        let (mut files, mut invalid) = RevisionFile::partition_from_disk(settings)?;
+
+        let (repeatable_files, invalid_repeatables) = RepeatableFile::partition_from_disk(revision_dir, &settings.checksum)?;
+        invalid.extend(invalid_repeatables);
+
+        let repeatable_records = repeatable_records
+            .into_iter()
+            .map(|record| (record.filename.clone(), record))
+            .collect();
 
         let files: Vec<Rc<RevisionFile>> = files.drain(..).map(Rc::new).collect();
 
@@ -45,19 +777,44 @@ impl Review {
 
         Ok(Self {
             revisions: vec![],
+            repeatables: vec![],
+            invalid,
+            settings: settings.clone(),
+            env_name,
             files,
             files_map,
             records,
             records_map,
+            repeatable_files,
+            repeatable_records,
+            against: None,
+            strict_order: false,
+            replaced: HashMap::new(),
+            server_version: None,
         })
     }
 
+    /// Fails with the first entry that could not be loaded as a revision, if
+    /// any, annotating the rest otherwise.
+    fn strict(self) -> Result<Self> {
+        match self.invalid.into_iter().next() {
+            Some(invalid) => Err(invalid.error),
+            None => Ok(Self { invalid: vec![], ..self }.annotate()),
+        }
+    }
+
     /// Builds a list that represents all revisions files and records, matching each
     /// to determine which files have been applied and, for those that do, whether or
     /// not the checksums still match. Additionally, this verifies that all records
     /// continue to have corresponding files.
+    ///
+    /// Repeatable revisions are matched in the same way, though only to determine
+    /// whether they need to be applied again.
     fn annotate(mut self) -> Self {
         for file in self.files.iter() {
+            let environments = self.environments(&file.name, &file.meta);
+            let applicable = is_applicable(&environments, self.env_name.as_deref());
+
             let mut anno = AnnotatedRevision {
                 id: file.id,
                 applied_on: None,
@@ -68,18 +825,29 @@ impl Review {
                 filename: file.filename.clone(),
                 name: file.name.clone(),
                 on_disk: true,
+                meta: Some(file.meta.clone()),
+                environments,
+                applicable,
+                group: file.group.clone(),
+                directory: file.directory.clone(),
+                verify: file.verify.clone(),
             };
 
             if let Some(record) = self.records_map.get(&file.filename) {
                 anno.applied_on = Some(record.applied_on);
-                anno.checksums_match = Some(file.checksum == record.checksum);
+
+                // A tombstone's contents replace whatever was applied, so
+                // aren't compared with it
+                if file.meta.superseded_by.is_none() {
+                    anno.checksums_match = Some(checksum::matches(&file.contents, &record.checksum));
+                }
             }
 
             self.revisions.push(anno);
         }
 
         for record in self.records.iter() {
-            if self.files_map.get(&record.filename).is_some() {
+            if self.files_map.contains_key(&record.filename) {
                 continue;
             }
 
@@ -93,12 +861,123 @@ impl Review {
                 filename: record.filename.clone(),
                 name: record.name.clone(),
                 on_disk: false,
+                meta: None,
+                environments: vec![],
+                applicable: true,
+                group: None,
+                directory: None,
+                verify: None,
             };
 
             self.revisions.push(anno);
         }
 
-        self.revisions.sort();
+        self.settings.order.sort(&mut self.revisions);
+        self.settle_tombstones();
+
+        for file in std::mem::take(&mut self.repeatable_files) {
+            let record = self.repeatable_records.get(&file.filename);
+            let environments = self.environments(&file.name, &file.meta);
+            let applicable = is_applicable(&environments, self.env_name.as_deref());
+
+            self.repeatables.push(AnnotatedRepeatable {
+                applied_on: record.map(|r| r.applied_on),
+                checksums_match: record.map(|r| checksum::matches(&file.contents, &r.checksum)),
+                checksum: file.checksum,
+                contents: file.contents,
+                filename: file.filename,
+                name: file.name,
+                kind: file.kind,
+                meta: file.meta,
+                environments,
+                applicable,
+            });
+        }
+
         self
     }
+
+    /// The environments a revision is limited to, combining those from its
+    /// frontmatter and any filename suffix naming a known environment.
+    fn environments(&self, name: &str, meta: &RevisionMeta) -> Vec<String> {
+        let mut environments = meta.environments.clone();
+
+        if let Some((_, suffix)) = environment_suffix(name, &self.settings.environments) {
+            if !environments.iter().any(|env| env == suffix) {
+                environments.push(suffix.to_string());
+            }
+        }
+
+        environments
+    }
+}
+
+/// Splits a revision name like `seed-users.production` into the name proper
+/// and the environment it is limited to, if the suffix is a known environment.
+fn environment_suffix<'a>(name: &'a str, known: &[String]) -> Option<(&'a str, &'a str)> {
+    name.rsplit_once('.')
+        .filter(|(_, suffix)| known.iter().any(|env| env == suffix))
+}
+
+/// Lowercases the name and collapses anything other than letters and digits
+/// into single separators, so that eg. `Add Users` and `add_users` match.
+fn normalize_name(name: &str) -> String {
+    name.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn globs_match_whole_names() {
+        assert!(glob_matches("*users*", "002.1618449763.add-users-index.sql"));
+        assert!(glob_matches("2021-04/*", "2021-04/002.1618449763.add-orders.sql"));
+        assert!(glob_matches("00?.*.sql", "003.1618450954.backfill.sql"));
+        assert!(glob_matches("*a*b", "aXbXb"));
+        assert!(!glob_matches("*users", "002.1618449763.add-users.sql"));
+        assert!(!glob_matches("00?.*", "0103.1618450954.backfill.sql"));
+    }
+
+    fn revision(filename: &str, group: Option<&str>, applied: bool, checksums_match: Option<bool>) -> AnnotatedRevision {
+        AnnotatedRevision {
+            id: 1,
+            applied_on: Some(chrono::Utc::now()).filter(|_| applied),
+            checksum: None,
+            checksums_match,
+            contents: None,
+            created_at: chrono::Utc::now(),
+            filename: filename.to_string(),
+            name: filename.split('.').nth(2).unwrap_or_default().to_string(),
+            on_disk: true,
+            meta: None,
+            environments: vec![],
+            applicable: true,
+            group: group.map(ToString::to_string),
+            directory: group.map(ToString::to_string),
+            verify: None,
+        }
+    }
+
+    #[test]
+    fn filter_limits_by_status_and_name() {
+        let pending = revision("002.1618449763.add-orders.sql", Some("2021-04"), false, None);
+        let changed = revision("001.1618370298.add-users.sql", None, true, Some(false));
+
+        let filter = ReviewFilter { pending_only: true, ..Default::default() };
+        assert!(filter.revision(&pending) && !filter.revision(&changed));
+
+        let filter = ReviewFilter { changed_only: true, ..Default::default() };
+        assert!(!filter.revision(&pending) && filter.revision(&changed));
+
+        let filter = ReviewFilter { pattern: Some("users".to_string()), ..Default::default() };
+        assert!(!filter.revision(&pending) && filter.revision(&changed));
+
+        let filter = ReviewFilter { pattern: Some("2021-04/*".to_string()), ..Default::default() };
+        assert!(filter.revision(&pending) && !filter.revision(&changed));
+    }
 }
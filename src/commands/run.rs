@@ -0,0 +1,73 @@
+use std::{
+    convert::TryFrom,
+    process::Command,
+    str::FromStr,
+    thread,
+    time::{Duration, Instant},
+};
+
+use log::info;
+use postgres::{error::SqlState, Client};
+
+use crate::{Environment, Error, Result};
+
+const RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Retries connecting to the environment's database until it accepts
+/// connections or the timeout passes, whichever is first.
+pub(super) fn wait_for_database(env: &Environment, timeout: Duration) -> Result<()> {
+    // An invalid URL won't become valid by waiting
+    postgres::Config::from_str(&env.database.url)?;
+
+    let started = Instant::now();
+    let mut waiting = false;
+
+    loop {
+        let err = match Client::try_from(env) {
+            Ok(_) => return Ok(()),
+            Err(Error::DatabaseError(err)) => err,
+            Err(err) => return Err(err),
+        };
+
+        // Errors from the server itself, eg. for authentication, are final
+        // except for when it's still starting up
+        let retryable = err
+            .as_db_error()
+            .is_none_or(|db| *db.code() == SqlState::CANNOT_CONNECT_NOW);
+
+        if !retryable || started.elapsed() + RETRY_INTERVAL > timeout {
+            return Err(Error::DatabaseUnavailable(timeout, err));
+        }
+
+        if !waiting {
+            info!("Waiting up to {}s for the database to accept connections", timeout.as_secs());
+            waiting = true;
+        }
+
+        thread::sleep(RETRY_INTERVAL);
+    }
+}
+
+/// Replaces this process with the command, so that it receives signals sent
+/// to this one and its exit code is this one's, as a container entrypoint.
+#[cfg(unix)]
+pub(super) fn exec(program: &str, args: &[String]) -> Result<()> {
+    use std::os::unix::process::CommandExt;
+
+    // Only ever returns upon failing to execute the command
+    let err = Command::new(program).args(args).exec();
+
+    Err(Error::CommandFailed(program.to_string(), err))
+}
+
+/// Without `exec`, runs the command to completion and exits with its code,
+/// leaving the console to pass signals to it as well.
+#[cfg(not(unix))]
+pub(super) fn exec(program: &str, args: &[String]) -> Result<()> {
+    let status = Command::new(program)
+        .args(args)
+        .status()
+        .map_err(|err| Error::CommandFailed(program.to_string(), err))?;
+
+    std::process::exit(status.code().unwrap_or(1))
+}
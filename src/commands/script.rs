@@ -0,0 +1,40 @@
+use std::{
+    fs,
+    io::{self, Read},
+    path::Path,
+};
+
+use crate::{meta::RevisionMeta, Error, Result};
+
+/// What `jrny exec` reads from standard input instead of a file.
+const STDIN: &str = "-";
+
+/// An ad-hoc SQL file, run as a revision would be but never recorded.
+pub(super) struct Script {
+    /// The path the script was read from, or `stdin`
+    pub filename: String,
+    pub contents: String,
+    pub meta: RevisionMeta,
+}
+
+impl Script {
+    /// Reads the script from the given path, or from standard input if `-`.
+    pub fn read(path: &str) -> Result<Self> {
+        let (filename, contents) = if path == STDIN {
+            let mut contents = String::new();
+            io::stdin().read_to_string(&mut contents)?;
+
+            ("stdin".to_string(), contents)
+        } else {
+            if !Path::new(path).is_file() {
+                return Err(Error::FileNotValid(path.to_string()));
+            }
+
+            (path.to_string(), fs::read_to_string(path)?)
+        };
+
+        let meta = RevisionMeta::parse(&filename, &contents)?;
+
+        Ok(Self { filename, contents, meta })
+    }
+}
@@ -0,0 +1,65 @@
+use std::collections::HashMap;
+
+use crate::{
+    checksum,
+    executor::Executor,
+    revisions::{is_applicable, AnnotatedRepeatable, ReappliedKind, RepeatableFile},
+    Config,
+    Environment,
+    Result,
+};
+
+/// Determines which seeds need to be applied to the environment.
+pub(super) struct Seed {
+    pub to_apply: Vec<AnnotatedRepeatable>,
+    /// The number of seeds that are unchanged since last applied
+    pub unchanged: usize,
+}
+
+impl Seed {
+    /// Finds seeds that are new or changed since last applied, or all of
+    /// them if forced.
+    pub fn prepare(cfg: &Config, env: &Environment, exec: &mut Executor, force: bool) -> Result<Self> {
+        exec.ensure_seed_table_exists()?;
+
+        let files = RepeatableFile::seeds_from_disk(&cfg.seeds.directory, env.name.as_deref(), &cfg.revisions.checksum)?;
+        let records: HashMap<_, _> = exec
+            .load_reapplied(ReappliedKind::Seed)?
+            .into_iter()
+            .map(|record| (record.filename.clone(), record))
+            .collect();
+
+        let (mut to_apply, mut unchanged) = (vec![], 0);
+
+        for file in files {
+            let record = records.get(&file.filename);
+            let environments = file.meta.environments.clone();
+            let applicable = is_applicable(&environments, env.name.as_deref());
+
+            let seed = AnnotatedRepeatable {
+                applied_on: record.map(|r| r.applied_on),
+                checksums_match: record.map(|r| checksum::matches(&file.contents, &r.checksum)),
+                checksum: file.checksum,
+                contents: file.contents,
+                filename: file.filename,
+                name: file.name,
+                kind: file.kind,
+                meta: file.meta,
+                environments,
+                applicable,
+            };
+
+            if !seed.applicable {
+                continue;
+            }
+
+            if force || seed.is_pending() {
+                to_apply.push(seed);
+            } else {
+                unchanged += 1;
+            }
+        }
+
+        Ok(Self { to_apply, unchanged })
+    }
+}
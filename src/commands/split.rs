@@ -0,0 +1,376 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Utc};
+
+use super::{
+    rename::{resequenced_ids, Rename},
+    review::Review,
+};
+use crate::{
+    executor::Executor,
+    meta::frontmatter,
+    naming::NameStyle,
+    statements::{split_with_ends, Statement},
+    Config,
+    Environment,
+    Error,
+    Result,
+};
+
+/// Where the originals of split revisions are kept, alongside the revisions
+/// directory rather than in it so that they're never applied.
+const SPLIT_DIR: &str = "split";
+
+/// One of the revisions a revision is split into.
+#[derive(Debug, PartialEq, Eq)]
+pub(super) struct Part {
+    pub path: PathBuf,
+    pub contents: String,
+}
+
+/// A pending revision to be split into several at statement boundaries, each
+/// numbered in turn, with later pending revisions renumbered after them
+/// where need be.
+pub(super) struct Split {
+    /// The revision file being split
+    pub path: PathBuf,
+    /// Where the original is kept once it's been split
+    pub original: PathBuf,
+    id: i32,
+    created_at: DateTime<Utc>,
+    name: String,
+    contents: String,
+    /// Separates the name of the revision from the number of each part
+    separator: char,
+    /// The statements of the revision, each with the byte offset of its end
+    statements: Vec<(Statement, usize)>,
+    /// Pending revisions after it, by path, id, and name, in order
+    later: Vec<(PathBuf, i32, DateTime<Utc>, String)>,
+    /// Pending revisions that require the revision by name
+    pub required_by: Vec<String>,
+}
+
+impl Split {
+    /// Finds the pending revision by its filename, id, or name.
+    pub fn prepare(cfg: &Config, env: &Environment, exec: &mut Executor, revision: &str) -> Result<Self> {
+        let review = Review::annotated_revisions(exec, &cfg.revisions, env)?;
+
+        let matching: Vec<_> = review
+            .revisions
+            .iter()
+            .filter(|r| r.on_disk)
+            .filter(|r| {
+                r.filename == revision
+                    || r.path() == Path::new(revision)
+                    || r.name == revision
+                    || revision.parse::<i32>().ok() == Some(r.id)
+            })
+            .collect();
+
+        let found = match matching.as_slice() {
+            [found] => found,
+            _ => return Err(Error::RevisionNotFound(revision.to_string(), matching.len())),
+        };
+
+        if found.applied_on.is_some() {
+            return Err(Error::RevisionNotPending(found.filename.clone()));
+        }
+
+        let contents = found.contents.clone().unwrap_or_default();
+        let statements = split_with_ends(&contents);
+
+        if statements.iter().filter(|(statement, _)| !statement.is_transaction_command()).count() < 2 {
+            return Err(Error::SplitPointsInvalid(format!("`{}` has only one statement", found.filename)));
+        }
+
+        let mut pending: Vec<_> = review
+            .revisions
+            .iter()
+            .filter(|r| r.applied_on.is_none() && r.on_disk && r.applicable)
+            .collect();
+        pending.sort_by_key(|r| (r.id, r.created_at, r.filename.clone()));
+
+        let later = pending
+            .iter()
+            .skip_while(|r| r.filename != found.filename || r.directory != found.directory)
+            .skip(1)
+            .map(|r| (cfg.revisions.directory.join(r.path()), r.id, r.created_at, r.name.clone()))
+            .collect();
+
+        let required_by = pending
+            .iter()
+            .filter(|r| r.meta.as_ref().is_some_and(|meta| meta.requires.contains(&found.name)))
+            .map(|r| r.filename.clone())
+            .collect();
+
+        let separator = match cfg.revisions.naming.style {
+            Some(style) => style.separator(),
+            None if found.name.contains('-') && !found.name.contains('_') => NameStyle::KebabCase.separator(),
+            None => NameStyle::LowerSnakeCase.separator(),
+        };
+
+        let original = match cfg.revisions.directory.parent() {
+            Some(parent) => parent.join(SPLIT_DIR).join(found.path()),
+            None => PathBuf::from(SPLIT_DIR).join(found.path()),
+        };
+
+        Ok(Self {
+            path: cfg.revisions.directory.join(found.path()),
+            original,
+            id: found.id,
+            created_at: found.created_at,
+            name: found.name.clone(),
+            contents,
+            separator,
+            statements,
+            later,
+            required_by,
+        })
+    }
+
+    /// The statements that can be split between, being all but those that
+    /// manage transactions, in order.
+    pub fn statements(&self) -> Vec<&Statement> {
+        self.statements
+            .iter()
+            .map(|(statement, _)| statement)
+            .filter(|statement| !statement.is_transaction_command())
+            .collect()
+    }
+
+    /// The revisions the revision is split into, split before each of the
+    /// statements given, numbered from 1 as in `statements`.
+    pub fn parts(&self, at: &[usize]) -> Result<Vec<Part>> {
+        let contents = split_contents(&self.contents, &self.statements, at)?;
+        let (base, suffix) = match self.name.split_once('.') {
+            Some((base, suffix)) => (base, format!(".{}", suffix)),
+            None => (self.name.as_str(), String::new()),
+        };
+
+        Ok(contents
+            .into_iter()
+            .enumerate()
+            .map(|(i, contents)| Part {
+                path: self.path.with_file_name(format!(
+                    "{:03}.{}.{}{}{}{}.sql",
+                    self.id + i as i32,
+                    self.created_at.timestamp(),
+                    base,
+                    self.separator,
+                    i + 1,
+                    suffix,
+                )),
+                contents,
+            })
+            .collect())
+    }
+
+    /// The pending revisions after this one that must be renumbered to
+    /// follow the given number of parts.
+    pub fn renames(&self, parts: usize) -> Vec<Rename> {
+        let ids = resequenced_ids(Some(self.id + parts as i32 - 1), &self.later.iter().map(|(_, id, _, _)| *id).collect::<Vec<_>>());
+
+        self.later
+            .iter()
+            .zip(ids)
+            .filter(|((_, id, _, _), new_id)| id != new_id)
+            .map(|((path, _, created_at, name), id)| Rename {
+                from: path.clone(),
+                to: path.with_file_name(format!("{:03}.{}.{}.sql", id, created_at.timestamp(), name)),
+            })
+            .collect()
+    }
+
+    /// Moves the original out of the revisions directory, renumbers later
+    /// revisions, and writes the parts in its place.
+    pub fn apply(&self, parts: &[Part], renames: &[Rename]) -> Result<()> {
+        let targets = std::iter::once(&self.original)
+            .chain(renames.iter().map(|rename| &rename.to))
+            .chain(parts.iter().map(|part| &part.path));
+
+        for target in targets {
+            if target.exists() && !renames.iter().any(|rename| rename.from == *target) {
+                return Err(Error::PathAlreadyExists(target.display().to_string()));
+            }
+        }
+
+        if let Some(dir) = self.original.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::rename(&self.path, &self.original)?;
+
+        // Renumbered from last to first, since each may take the id of the next
+        for rename in renames.iter().rev() {
+            fs::rename(&rename.from, &rename.to)?;
+        }
+
+        for part in parts {
+            fs::write(&part.path, &part.contents)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Whether the statement starts a transaction block, ends one, or neither.
+fn transaction_change(statement: &Statement) -> Option<bool> {
+    match statement.leading_keyword().as_deref() {
+        Some("begin" | "start") => Some(true),
+        Some("commit" | "end" | "rollback" | "abort") => Some(false),
+        _ => None,
+    }
+}
+
+/// Splits the contents before each of the statements given, numbered from 1
+/// among those that don't manage transactions. Each part between statements
+/// in a transaction block has the block ended and begun again, and every part
+/// after the first starts with a copy of any frontmatter.
+fn split_contents(contents: &str, statements: &[(Statement, usize)], at: &[usize]) -> Result<Vec<String>> {
+    // The index of each statement that can be split before, along with the
+    // last block ended since the statement before it and whether it's in one
+    let mut splittable = vec![];
+    let mut in_block = false;
+    let mut last_outside = None;
+
+    for (i, (statement, _)) in statements.iter().enumerate() {
+        match transaction_change(statement) {
+            Some(begins) => {
+                in_block = begins;
+                if !begins {
+                    last_outside = Some(i);
+                }
+            }
+            None => {
+                splittable.push((i, last_outside, in_block));
+                last_outside = None;
+            }
+        }
+    }
+
+    let mut at = at.to_vec();
+    at.sort_unstable();
+    at.dedup();
+
+    if at.is_empty() {
+        return Err(Error::SplitPointsInvalid("none given".to_string()));
+    }
+
+    let mut cuts = vec![];
+
+    for &point in &at {
+        if point < 2 || point > splittable.len() {
+            return Err(Error::SplitPointsInvalid(format!(
+                "expected statements from 2 to {}, found {}",
+                splittable.len(),
+                point,
+            )));
+        }
+
+        let (_, closed, _) = splittable[point - 1];
+        let (previous, _, previous_in_block) = splittable[point - 2];
+
+        // Splitting after the end of a block between the statements is best,
+        // and otherwise just after the statement before, ending the block it's
+        // in should it be in one
+        let (after, wrap) = match closed {
+            Some(closed) => (closed, false),
+            None => (previous, previous_in_block),
+        };
+
+        cuts.push((line_end(contents, statements[after].1), wrap));
+    }
+
+    let frontmatter = frontmatter(contents).map(|block| format!("{}\n", block));
+    let mut parts = vec![];
+    let mut start = 0;
+    let mut reopen = false;
+
+    for (end, wrap) in cuts.iter().copied().chain(std::iter::once((contents.len(), false))) {
+        let mut part = String::new();
+
+        if start > 0 {
+            part.push_str(frontmatter.as_deref().unwrap_or_default());
+        }
+        if reopen {
+            part.push_str("begin;\n\n");
+        }
+
+        part.push_str(contents[start..end].trim_start_matches(['\r', '\n']));
+
+        if wrap {
+            part.push_str("\ncommit;\n");
+        }
+
+        parts.push(part);
+        start = end;
+        reopen = wrap;
+    }
+
+    Ok(parts)
+}
+
+/// The offset just past the end of the line the offset is on, if the rest of
+/// it is only whitespace, or else the offset itself.
+fn line_end(contents: &str, offset: usize) -> usize {
+    let rest = &contents[offset..];
+
+    match rest.find('\n') {
+        Some(newline) if rest[..newline].trim().is_empty() => offset + newline + 1,
+        None if rest.trim().is_empty() => contents.len(),
+        _ => offset,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn split(contents: &str, at: &[usize]) -> Vec<String> {
+        split_contents(contents, &split_with_ends(contents), at).unwrap()
+    }
+
+    #[test]
+    fn splits_at_statement_boundaries() {
+        let contents = "-- +++\n-- author = \"x\"\n-- +++\n-- Revision: big\n\ncreate table a ();\n\n-- Adds b\ncreate table b ();\ncreate table c ();\n";
+
+        assert_eq!(
+            split(contents, &[2, 3]),
+            vec![
+                "-- +++\n-- author = \"x\"\n-- +++\n-- Revision: big\n\ncreate table a ();\n",
+                "-- +++\n-- author = \"x\"\n-- +++\n\n-- Adds b\ncreate table b ();\n",
+                "-- +++\n-- author = \"x\"\n-- +++\n\ncreate table c ();\n",
+            ],
+        );
+    }
+
+    #[test]
+    fn splits_within_transaction_blocks() {
+        let contents = "begin;\n\ncreate table a ();\ncreate table b ();\n\ncommit;\n";
+
+        assert_eq!(
+            split(contents, &[2]),
+            vec!["begin;\n\ncreate table a ();\n\ncommit;\n", "begin;\n\ncreate table b ();\n\ncommit;\n"],
+        );
+
+        let contents = "begin;\ncreate table a ();\ncommit;\nbegin;\ncreate table b ();\ncommit;\n";
+
+        assert_eq!(
+            split(contents, &[2]),
+            vec!["begin;\ncreate table a ();\ncommit;\n", "begin;\ncreate table b ();\ncommit;\n"],
+        );
+    }
+
+    #[test]
+    fn split_points_must_be_between_statements() {
+        let contents = "create table a ();\ncreate table b ();\n";
+        let statements = split_with_ends(contents);
+
+        assert!(split_contents(contents, &statements, &[]).is_err());
+        assert!(split_contents(contents, &statements, &[1]).is_err());
+        assert!(split_contents(contents, &statements, &[3]).is_err());
+        assert_eq!(split(contents, &[2, 2]).len(), 2);
+    }
+}
@@ -0,0 +1,86 @@
+use std::{fmt, path::Path};
+
+use chrono::{DateTime, Utc};
+use termcolor::Color;
+
+use super::{load_project, review::Review};
+use crate::{executor::Executor, logger::format_time, Config, Environment, Result};
+
+/// How far along the revisions are in an environment, found without
+/// creating the tracking tables should they not exist yet.
+pub(super) struct Status {
+    pub applied: usize,
+    /// Revisions for the environment that are yet to be applied
+    pub pending: usize,
+    /// Repeatable revisions for the environment that are new or have changed
+    /// since they were last applied
+    pub repeatables_pending: usize,
+    /// Revisions that review would fail
+    pub failing: usize,
+    /// When the revision applied last was applied, if any have been
+    pub last_applied: Option<DateTime<Utc>>,
+}
+
+impl Status {
+    pub fn of(cfg: &Config, env: &Environment) -> Result<Self> {
+        let mut exec = Executor::new(cfg, env)?;
+        let review = Review::existing_annotated_revisions(&mut exec, &cfg.revisions, env)?;
+
+        Ok(Self {
+            applied: review.revisions.iter().filter(|revision| revision.applied_on.is_some()).count(),
+            pending: review
+                .revisions
+                .iter()
+                .filter(|revision| revision.applied_on.is_none() && revision.applicable)
+                .count(),
+            repeatables_pending: review
+                .repeatables
+                .iter()
+                .filter(|repeatable| repeatable.is_pending() && repeatable.applicable)
+                .count(),
+            failing: review.problems().iter().filter(|problems| !problems.is_empty()).count(),
+            last_applied: review.revisions.iter().filter_map(|revision| revision.applied_on).max(),
+        })
+    }
+
+    /// As with `of`, for the project with its config file in the directory.
+    pub fn of_project(dir: &Path) -> Result<Self> {
+        let (cfg, env) = load_project(dir)?;
+
+        Self::of(&cfg, &env)
+    }
+
+    pub fn color(&self) -> Color {
+        if self.failing > 0 {
+            Color::Red
+        } else if self.pending + self.repeatables_pending > 0 {
+            Color::Yellow
+        } else {
+            Color::Green
+        }
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.pending + self.repeatables_pending > 0 {
+            write!(f, "{} pending, {} applied", self.pending, self.applied)?;
+        } else {
+            write!(f, "Up to date, {} applied", self.applied)?;
+        }
+
+        if self.repeatables_pending > 0 {
+            write!(f, ", {} repeatable pending", self.repeatables_pending)?;
+        }
+
+        if self.failing > 0 {
+            write!(f, ", {} failing review", self.failing)?;
+        }
+
+        if let Some(last_applied) = self.last_applied {
+            write!(f, ", last applied {}", format_time(last_applied))?;
+        }
+
+        Ok(())
+    }
+}
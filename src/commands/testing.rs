@@ -0,0 +1,219 @@
+use std::{collections::HashMap, convert::TryFrom};
+
+use log::{info, warn};
+use postgres::Client;
+
+use crate::{
+    assertions::{self, AssertionFile},
+    environment,
+    events::EventStream,
+    executor::Executor,
+    pgtap::{self, TapOutcome},
+    revisions::RevisionFile,
+    schema::{self, SchemaObject},
+    statements,
+    telemetry::random_hex,
+    Config,
+    Environment,
+    Error,
+    Result,
+};
+
+/// A database created for the length of a test on the environment's server,
+/// and dropped again once done with unless it's to be kept.
+pub(super) struct ScratchDatabase {
+    pub name: String,
+    /// The environment as it is, but for the scratch database, and without
+    /// anything reaching beyond it such as notifications or metrics
+    pub env: Environment,
+    /// The connection to the environment's own database it was created from
+    admin: Client,
+    keep: bool,
+}
+
+impl ScratchDatabase {
+    /// Creates an empty database, or a copy of the template given.
+    pub fn create(env: &Environment, template: Option<&str>, keep: bool) -> Result<Self> {
+        let name = format!("jrny_test_{}", random_hex(4));
+        let mut admin = Client::try_from(env)?;
+
+        let create = match template {
+            Some(template) => format!("CREATE DATABASE {} TEMPLATE {}", name, quote_identifier(template)),
+            None => format!("CREATE DATABASE {}", name),
+        };
+        admin.batch_execute(&create)?;
+
+        let mut scratch = env.clone();
+        scratch.protected = false;
+        scratch.database.url = environment::with_dbname(&env.database.url, &name);
+        scratch.metrics = None;
+        scratch.tracing = None;
+        scratch.notifications = None;
+        scratch.backup = None;
+        scratch.freeze = vec![];
+        scratch.signing = None;
+        scratch.pacing = Default::default();
+        scratch.create_extensions = true;
+
+        Ok(Self { name, env: scratch, admin, keep })
+    }
+}
+
+impl Drop for ScratchDatabase {
+    fn drop(&mut self) {
+        if self.keep {
+            info!("\nKept scratch database {}", self.name);
+            return;
+        }
+
+        match self.admin.batch_execute(&format!("DROP DATABASE IF EXISTS {}", self.name)) {
+            Ok(()) => info!("\nDropped scratch database {}", self.name),
+            Err(e) => warn!("\nCouldn't drop scratch database {}: {}", self.name, e),
+        }
+    }
+}
+
+/// The filenames of the revisions recorded as applied to the environment,
+/// if any are.
+pub(super) fn applied(cfg: &Config, env: &Environment) -> Result<Vec<String>> {
+    let mut exec = Executor::new(cfg, env)?;
+
+    if !exec.tracking_table_exists()? {
+        return Ok(vec![]);
+    }
+
+    Ok(exec.load_revisions()?.into_iter().map(|record| record.filename).collect())
+}
+
+/// The schema of the environment's database, other than jrny's own tables.
+pub(super) fn objects(cfg: &Config, env: &Environment) -> Result<Vec<SchemaObject>> {
+    let objects = schema::objects(&mut Client::try_from(env)?)?;
+
+    Ok(schema::without_tracking_tables(objects, &cfg.table.schema, &cfg.table.name))
+}
+
+/// Reverts each revision applied since those given, most recently applied
+/// first, with its companion revert file, returning their filenames. Every
+/// one must have a revert file, which is checked before any is reverted.
+pub(super) fn revert(cfg: &Config, env: &Environment, previously: &[String]) -> Result<Vec<String>> {
+    let mut exec = Executor::new(cfg, env)?;
+    let reverts = revert_files(cfg)?;
+
+    let mut records: Vec<_> = exec
+        .load_revisions()?
+        .into_iter()
+        .filter(|record| !previously.contains(&record.filename))
+        .collect();
+    records.sort_by_key(|record| (record.applied_on, record.id));
+    records.reverse();
+
+    if let Some(missing) = records.iter().find(|record| !reverts.contains_key(&record.filename)) {
+        return Err(Error::RevertMissing(missing.filename.clone()));
+    }
+
+    info!("\nReverting {} revision(s)\n", records.len());
+
+    for record in &records {
+        exec.revert_revision(&record.filename, &statements::split(&reverts[&record.filename]))?;
+        info!("  {}", record.filename);
+    }
+
+    Ok(records.into_iter().map(|record| record.filename).collect())
+}
+
+/// The contents of each revision's companion revert file, by the revision's
+/// filename, for those that have one.
+pub(super) fn revert_files(cfg: &Config) -> Result<HashMap<String, String>> {
+    Ok(RevisionFile::all_from_disk(&cfg.revisions)?
+        .into_iter()
+        .filter_map(|file| file.revert.map(|revert| (file.filename, revert)))
+        .collect())
+}
+
+/// An assertion that failed, by the file and line of its statement.
+pub(super) struct Failure {
+    pub filename: String,
+    pub line: usize,
+    pub reason: String,
+}
+
+/// Checks every assertion in the file, each file in a transaction of its
+/// own that's rolled back afterwards, so that what one sets up or changes
+/// isn't seen by the next. A statement that errors fails like any other,
+/// and ends the file.
+pub(super) fn check(env: &Environment, files: &[AssertionFile]) -> Result<Vec<Failure>> {
+    let mut client = Client::try_from(env)?;
+    let mut failures = vec![];
+
+    for file in files {
+        let mut tx = client.transaction()?;
+        let before = failures.len();
+
+        for statement in &file.statements {
+            let failed = |reason: String| Failure {
+                filename: file.filename.clone(),
+                line: statement.line,
+                reason,
+            };
+
+            match assertions::check(&mut tx, statement) {
+                Ok(None) => {}
+                Ok(Some(reason)) => failures.push(failed(reason)),
+                Err(e) => {
+                    failures.push(failed(e.as_db_error().map_or(e.to_string(), |e| e.message().to_string())));
+                    break;
+                }
+            }
+        }
+
+        tx.rollback()?;
+
+        match failures.len() - before {
+            0 => info!("  {:40}passed", file.filename),
+            n => info!("  {:40}{} failed", file.filename, n),
+        }
+    }
+
+    Ok(failures)
+}
+
+/// Runs the pgTAP files, should the config give a directory of them and the
+/// environment not be protected, logging how each went.
+pub(super) fn pgtap(cfg: &Config, env: &Environment, events: EventStream) -> Result<Vec<TapOutcome>> {
+    let files = match &cfg.tests.pgtap {
+        Some(dir) if !env.protected => AssertionFile::all_from_disk(dir)?,
+        _ => return Ok(vec![]),
+    };
+
+    if files.is_empty() {
+        return Ok(vec![]);
+    }
+
+    info!("\nRunning {} pgTAP file(s)\n", files.len());
+
+    let mut client = Client::try_from(env)?;
+    let mut outcomes = vec![];
+
+    for file in &files {
+        let outcome = pgtap::run(&mut client, file)?;
+
+        match (outcome.is_ok(), outcome.problem()) {
+            (true, _) => info!("  {:40}{} passed", file.filename, outcome.passed),
+            (false, Some(problem)) => warn!("  {:40}{}", file.filename, problem),
+            (false, None) => warn!("  {:40}{} of {} failed", file.filename, outcome.failures.len(), outcome.ran()),
+        }
+        for line in outcome.failures.iter().flat_map(|failure| failure.lines()) {
+            warn!("    {}", line);
+        }
+
+        outcomes.push(outcome);
+    }
+
+    events.tests_done(&outcomes);
+
+    Ok(outcomes)
+}
+
+pub(super) fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
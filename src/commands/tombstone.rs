@@ -0,0 +1,103 @@
+use std::{fs, path::PathBuf};
+
+use crate::{revisions::RevisionFile, Config, Error, Result};
+
+/// A revision to be replaced by a tombstone, which stays recorded as applied
+/// wherever it already was, while environments that never applied it skip it
+/// and apply the revision superseding it instead.
+pub(super) struct Tombstone {
+    /// The revision file being replaced
+    pub path: PathBuf,
+    pub filename: String,
+    /// The revision superseding it, by id and filename
+    pub superseded_by: (i32, String),
+}
+
+impl Tombstone {
+    /// Finds the revision by its filename, id, or name, and the one that
+    /// supersedes it by its id, neither of which may already be a tombstone.
+    pub fn prepare(cfg: &Config, revision: &str, superseded_by: i32) -> Result<Self> {
+        let files = RevisionFile::all_from_disk(&cfg.revisions)?;
+
+        let matching: Vec<_> = files
+            .iter()
+            .filter(|file| {
+                file.filename == revision
+                    || file.path_name() == revision
+                    || file.name == revision
+                    || revision.parse::<i32>().ok() == Some(file.id)
+            })
+            .collect();
+
+        let found = match matching.as_slice() {
+            [found] => found,
+            _ => return Err(Error::RevisionNotFound(revision.to_string(), matching.len())),
+        };
+
+        let invalid = |reason: String| Error::TombstoneInvalid(found.filename.clone(), reason);
+
+        if let Some(id) = found.meta.superseded_by {
+            return Err(invalid(format!("it's already superseded by revision {}", id)));
+        }
+        if found.id == superseded_by {
+            return Err(invalid("it can't supersede itself".to_string()));
+        }
+
+        let replacements: Vec<_> = files.iter().filter(|file| file.id == superseded_by).collect();
+
+        let replacement = match replacements.as_slice() {
+            [replacement] => replacement,
+            _ => return Err(Error::RevisionNotFound(superseded_by.to_string(), replacements.len())),
+        };
+
+        if replacement.meta.superseded_by.is_some() {
+            return Err(invalid(format!("`{}` is itself a tombstone", replacement.filename)));
+        }
+
+        Ok(Self {
+            path: cfg.revisions.directory.join(found.path_name()),
+            filename: found.filename.clone(),
+            superseded_by: (replacement.id, replacement.filename.clone()),
+        })
+    }
+
+    /// Replaces the revision's contents with the tombstone.
+    pub fn apply(&self) -> Result<()> {
+        let (id, filename) = &self.superseded_by;
+
+        fs::write(&self.path, contents(*id, filename))?;
+
+        Ok(())
+    }
+}
+
+/// The contents of a tombstone superseded by the revision, by id and filename.
+fn contents(id: i32, filename: &str) -> String {
+    format!(
+        "-- +++\n\
+         -- superseded_by = {}\n\
+         -- +++\n\
+         \n\
+         -- Superseded by `{}`.\n\
+         --\n\
+         -- Environments that already applied this revision keep it as applied\n\
+         -- and skip the replacement, while the rest skip this one and apply the\n\
+         -- replacement instead.\n",
+        id, filename,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::meta::RevisionMeta;
+
+    #[test]
+    fn tombstones_are_superseded() {
+        let contents = contents(7, "007.1618370298.fix-users.sql");
+        let meta = RevisionMeta::parse("003.1618370000.add-users.sql", &contents).unwrap();
+
+        assert_eq!(meta.superseded_by, Some(7));
+        assert!(contents.contains("`007.1618370298.fix-users.sql`"));
+    }
+}
@@ -1,34 +1,262 @@
 use std::{
+    collections::HashMap,
     fs,
-    path::PathBuf,
+    path::{Path, PathBuf},
 };
 
 use serde::Deserialize;
 
 use crate::{
+    checksum::ChecksumSettings,
+    naming::NamingRules,
+    pg_version::{self, PgVersion},
+    policy::PolicyRules,
+    revisions::RevisionOrder,
+    settings::Problem,
     Error,
     Result,
 };
 
 #[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct RevisionsSettings {
     pub directory: PathBuf,
+    /// Rules that new and pending revision names must follow
+    #[serde(default)]
+    pub naming: NamingRules,
+    /// Names of environments that revision filenames can be limited to
+    /// with a suffix, eg. `seed-users.production.sql`
+    #[serde(default)]
+    pub environments: Vec<String>,
+    /// Rules for the SQL of pending revisions, checked by review
+    #[serde(default)]
+    pub policy: PolicyRules,
+    /// Whether revisions marked `idempotent` carry on past statements
+    /// creating tables, indexes, and types that already exist, with a warning
+    #[serde(default)]
+    pub allow_idempotent: bool,
+    /// How the contents of revisions are checksummed
+    #[serde(default)]
+    pub checksum: ChecksumSettings,
+    /// Whether each subdirectory of the revisions directory is a release
+    /// group, rather than only organizing the revisions within it
+    #[serde(default = "default_release_groups")]
+    pub release_groups: bool,
+    /// The order of revisions across the directories holding them
+    #[serde(default)]
+    pub order: RevisionOrder,
+    /// Extensions the revisions need, checked for before any is applied
+    #[serde(default)]
+    pub extensions: Vec<String>,
+    /// The oldest version of Postgres that every revision can be applied to
+    #[serde(default, deserialize_with = "pg_version::deserialize")]
+    pub requires_pg: Option<PgVersion>,
+}
+
+fn default_release_groups() -> bool {
+    true
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SeedsSettings {
+    pub directory: PathBuf,
+}
+
+impl Default for SeedsSettings {
+    fn default() -> Self {
+        Self {
+            directory: PathBuf::from("seeds"),
+        }
+    }
 }
 
 #[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FixturesSettings {
+    #[serde(default = "default_fixtures_directory")]
+    pub directory: PathBuf,
+    /// Names of the only environments that fixtures may be loaded into
+    #[serde(default)]
+    pub environments: Vec<String>,
+}
+
+fn default_fixtures_directory() -> PathBuf {
+    PathBuf::from("fixtures")
+}
+
+impl Default for FixturesSettings {
+    fn default() -> Self {
+        Self {
+            directory: default_fixtures_directory(),
+            environments: vec![],
+        }
+    }
+}
+
+/// Where to find the assertions that `jrny test` checks once every revision
+/// is applied to a scratch database, and any pgTAP files run after embarking.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TestsSettings {
+    #[serde(default = "default_tests_directory")]
+    pub directory: PathBuf,
+    /// The pgTAP files run once revisions are applied to any environment
+    /// that isn't protected, if any are
+    pub pgtap: Option<PathBuf>,
+}
+
+fn default_tests_directory() -> PathBuf {
+    PathBuf::from("tests")
+}
+
+impl Default for TestsSettings {
+    fn default() -> Self {
+        Self {
+            directory: default_tests_directory(),
+            pgtap: None,
+        }
+    }
+}
+
+/// Thresholds above which `jrny review` warns about statements that lock
+/// a table for their duration.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ReviewSettings {
+    #[serde(default = "default_large_table_rows")]
+    pub large_table_rows: i64,
+    #[serde(default = "default_large_table_megabytes")]
+    pub large_table_megabytes: i64,
+}
+
+fn default_large_table_rows() -> i64 {
+    1_000_000
+}
+
+fn default_large_table_megabytes() -> i64 {
+    1024
+}
+
+impl Default for ReviewSettings {
+    fn default() -> Self {
+        Self {
+            large_table_rows: default_large_table_rows(),
+            large_table_megabytes: default_large_table_megabytes(),
+        }
+    }
+}
+
+/// The built-in rewrites applied to statements before they're executed.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RewriteSettings {
+    /// Whether to add `IF NOT EXISTS` to statements creating tables, indexes,
+    /// and other objects that allow it
+    #[serde(default)]
+    pub if_not_exists: bool,
+    /// The schema to qualify the names of tables, views, and sequences with
+    /// when they're created or altered without one
+    #[serde(default)]
+    pub qualify_schema: Option<String>,
+    /// Whether to create views with `security_invoker` set
+    #[serde(default)]
+    pub security_invoker: bool,
+}
+
+/// The templates `jrny plan` writes new revisions from, relative to the
+/// config file, and variables they can use beyond the built-in ones.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TemplateSettings {
+    /// The template for ordinary revisions, in place of the built-in one
+    #[serde(default)]
+    pub revision: Option<PathBuf>,
+    /// The template for data revisions, planned with `--data`
+    #[serde(default)]
+    pub data: Option<PathBuf>,
+    #[serde(default)]
+    pub variables: HashMap<String, String>,
+}
+
+/// SQL run before the first revision of each embark is applied and after the
+/// last, eg. to flip a feature flag or pause job workers while revisions are
+/// being applied.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MaintenanceSettings {
+    #[serde(default)]
+    pub enable_sql: Option<String>,
+    /// Run even if a revision fails, or enabling maintenance mode did
+    #[serde(default)]
+    pub disable_sql: Option<String>,
+}
+
+/// What `jrny bootstrap` creates before applying revisions, should it not
+/// exist yet, eg. for preview environments spun up for each pull request.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BootstrapSettings {
+    /// The role to own the database, created along with it
+    pub role: Option<String>,
+    /// The database that new databases are copied from, if not `template1`
+    pub template: Option<String>,
+    /// Extensions created in the database before any revision is applied
+    #[serde(default)]
+    pub extensions: Vec<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct TableSettings {
     pub schema: String,
     pub name: String,
+    /// Whether every statement executed is recorded in a table of its own,
+    /// named after the tracking table with `_statement` appended
+    #[serde(default)]
+    pub audit: bool,
+    /// Whether the contents of each revision file are archived, compressed,
+    /// along with its record once it's applied
+    #[serde(default)]
+    pub archive: bool,
 }
 
 #[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct Config {
     pub revisions: RevisionsSettings,
+    /// Where to find reference data for `jrny seed`
+    #[serde(default)]
+    pub seeds: SeedsSettings,
+    /// Where to find sample data for `jrny fixtures`, and which environments
+    /// may have it loaded
+    #[serde(default)]
+    pub fixtures: FixturesSettings,
+    /// Where to find assertions for `jrny test`
+    #[serde(default)]
+    pub tests: TestsSettings,
+    #[serde(default)]
+    pub review: ReviewSettings,
+    /// How statements are rewritten before they're executed
+    #[serde(default)]
+    pub rewrite: RewriteSettings,
+    /// What new revisions are planned from
+    #[serde(default)]
+    pub templates: TemplateSettings,
+    /// What's run around applying revisions
+    #[serde(default)]
+    pub maintenance: MaintenanceSettings,
+    /// What's created by `jrny bootstrap` before applying revisions
+    #[serde(default)]
+    pub bootstrap: BootstrapSettings,
     pub table: TableSettings,
+    /// The directory of the config file
+    #[serde(skip)]
+    pub root: PathBuf,
 }
 
 impl Config {
-    pub fn from_filepath(confpath: &PathBuf) -> Result<Self> {
+    pub fn from_filepath(confpath: &Path) -> Result<Self> {
         if !confpath.exists() {
             return Err(Error::ConfigNotFound(confpath.display().to_string()));
         }
@@ -37,15 +265,31 @@ impl Config {
             return Err(Error::FileNotValid(confpath.display().to_string()));
         }
 
-        let contents = fs::read_to_string(&confpath)?;
-        let mut config: Self = toml::from_str(&contents)
+        let contents = fs::read_to_string(confpath)?;
+        let settings: toml::Value = toml::from_str(&contents)
             .map_err(|e| Error::TomlInvalid(e, confpath.display().to_string()))?;
+        let mut config: Self = settings
+            .try_into()
+            .map_err(|e| Problem::from_toml(&e).into_error(confpath, &[(confpath, &contents)]))?;
+
+        // The revisions, seeds, fixtures, and tests directories, and any templates, are relative
+        // to the config file itself, not the current working directory.
+        let root = confpath.parent().unwrap();
 
-        // The revisions directory is relative to the config file itself,
-        // not the current working directory.
-        config.revisions.directory = confpath
-            .parent().unwrap()
-            .join(&config.revisions.directory);
+        config.root = match root.as_os_str().is_empty() {
+            true => PathBuf::from("."),
+            false => root.to_path_buf(),
+        };
+
+        config.revisions.directory = root.join(&config.revisions.directory);
+        config.seeds.directory = root.join(&config.seeds.directory);
+        config.fixtures.directory = root.join(&config.fixtures.directory);
+        config.tests.directory = root.join(&config.tests.directory);
+        config.tests.pgtap = config.tests.pgtap.map(|pgtap| root.join(pgtap));
+
+        for template in [&mut config.templates.revision, &mut config.templates.data].into_iter().flatten() {
+            *template = root.join(&*template);
+        }
 
         Ok(config)
     }
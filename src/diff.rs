@@ -0,0 +1,154 @@
+//! Line by line differences between two versions of a file, shown as a
+//! unified diff, as `git diff` would show them:
+//!
+//! ```text
+//! --- applied
+//! +++ on disk
+//! @@ -1,3 +1,3 @@
+//!  CREATE TABLE users (
+//! -    name text
+//! +    name text NOT NULL
+//!  );
+//! ```
+//!
+//! Changes are found as the longest run of lines that both have in common,
+//! which is quadratic in the number of lines but fine for revisions.
+
+/// Lines of context shown around each change, as with `git diff`.
+const CONTEXT: usize = 3;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Line<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+impl Line<'_> {
+    fn is_change(&self) -> bool {
+        !matches!(self, Self::Same(_))
+    }
+}
+
+/// The unified diff from the old contents to the new, with the names given
+/// for each in its header, or `None` if their lines are the same.
+pub fn unified(old: &str, new: &str, old_name: &str, new_name: &str) -> Option<String> {
+    let old: Vec<_> = old.lines().collect();
+    let new: Vec<_> = new.lines().collect();
+    let lines = diff(&old, &new);
+
+    if !lines.iter().any(Line::is_change) {
+        return None;
+    }
+
+    let mut text = format!("--- {}\n+++ {}\n", old_name, new_name);
+
+    for (start, end) in hunks(&lines) {
+        let before = &lines[..start];
+        let hunk = &lines[start..end];
+        let old_start = before.iter().filter(|line| !matches!(line, Line::Added(_))).count();
+        let new_start = before.iter().filter(|line| !matches!(line, Line::Removed(_))).count();
+        let old_count = hunk.iter().filter(|line| !matches!(line, Line::Added(_))).count();
+        let new_count = hunk.iter().filter(|line| !matches!(line, Line::Removed(_))).count();
+
+        // Counting from 1, unless there are no lines, as with `git diff`
+        text.push_str(&format!(
+            "@@ -{},{} +{},{} @@\n",
+            old_start + usize::from(old_count > 0),
+            old_count,
+            new_start + usize::from(new_count > 0),
+            new_count,
+        ));
+
+        for line in hunk {
+            let (prefix, line) = match line {
+                Line::Same(line) => (' ', line),
+                Line::Removed(line) => ('-', line),
+                Line::Added(line) => ('+', line),
+            };
+
+            text.push(prefix);
+            text.push_str(line);
+            text.push('\n');
+        }
+    }
+
+    Some(text)
+}
+
+/// Every line of both, as kept, removed, or added, removals first.
+fn diff<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<Line<'a>> {
+    // The most lines in common between what's left of each from there on
+    let mut common = vec![vec![0; new.len() + 1]; old.len() + 1];
+
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = match old[i] == new[j] {
+                true => common[i + 1][j + 1] + 1,
+                false => common[i + 1][j].max(common[i][j + 1]),
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    let mut lines = vec![];
+
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            lines.push(Line::Same(old[i]));
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || common[i + 1][j] >= common[i][j + 1]) {
+            lines.push(Line::Removed(old[i]));
+            i += 1;
+        } else {
+            lines.push(Line::Added(new[j]));
+            j += 1;
+        }
+    }
+
+    lines
+}
+
+/// The range of lines in each hunk, being the changes close enough together
+/// to share their context.
+fn hunks(lines: &[Line]) -> Vec<(usize, usize)> {
+    let mut hunks: Vec<(usize, usize)> = vec![];
+
+    for (i, _) in lines.iter().enumerate().filter(|(_, line)| line.is_change()) {
+        let start = i.saturating_sub(CONTEXT);
+        let end = (i + 1 + CONTEXT).min(lines.len());
+
+        match hunks.last_mut() {
+            Some((_, last_end)) if start <= *last_end => *last_end = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+
+    hunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn changes_are_shown_with_context() {
+        let old = "CREATE TABLE users (\n    id int,\n    name text\n);\n\n\n\n\n\nSELECT 1;\nSELECT 2;\n";
+        let new = "-- Users\nCREATE TABLE users (\n    id int,\n    name text NOT NULL\n);\n\n\n\n\n\nSELECT 1;\n";
+
+        assert_eq!(
+            unified(old, new, "applied", "on disk").unwrap(),
+            "--- applied\n+++ on disk\n\
+             @@ -1,6 +1,7 @@\n+-- Users\n CREATE TABLE users (\n     id int,\n-    name text\n+    name text NOT NULL\n );\n \n \n\
+             @@ -8,4 +9,3 @@\n \n \n SELECT 1;\n-SELECT 2;\n",
+        );
+        assert_eq!(unified(old, old, "applied", "on disk"), None);
+    }
+
+    #[test]
+    fn empty_sides_count_from_zero() {
+        assert_eq!(unified("", "SELECT 1;\n", "a", "b").unwrap(), "--- a\n+++ b\n@@ -0,0 +1,1 @@\n+SELECT 1;\n");
+        assert_eq!(unified("SELECT 1;\n", "", "a", "b").unwrap(), "--- a\n+++ b\n@@ -1,1 +0,0 @@\n-SELECT 1;\n");
+    }
+}
@@ -1,47 +1,470 @@
 use std::{
+    collections::BTreeMap,
     fs,
-    path::PathBuf,
+    path::{Path, PathBuf},
+    str::FromStr,
 };
 
+use postgres::config::{Config, SslMode};
 use serde::Deserialize;
 
 use crate::{
     error::Error,
+    freeze::FreezeWindow,
+    heartbeat::HeartbeatSettings,
+    pacing::PacingSettings,
+    ownership::OwnershipSettings,
+    settings::Problem,
+    signing::SigningSettings,
     Result,
+    ENV,
 };
 
+/// Settings that can't be given together, as dotted keys.
+const EXCLUSIVE: [(&str, &str); 1] = [("backup.schema", "backup.directory")];
+
+/// How to authenticate with the database beyond what the URL gives.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum DatabaseAuth {
+    /// The password in the URL, or fetched by `password_ref`
+    #[default]
+    Password,
+    /// A short-lived token generated for RDS or Aurora at each connection
+    AwsIam,
+}
+
+/// What happens once a revision takes longer than its `max_duration`.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum MaxDurationPolicy {
+    /// Warn once the revision has been applied regardless
+    #[default]
+    Warn,
+    /// Cancel the running statement, failing the revision and the run
+    Cancel,
+    /// Fail the run once the revision has been applied, before the next
+    Fail,
+}
+
 #[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct DatabaseEnvironment {
     pub url: String,
+    /// The password to connect with instead of any in the URL or fetched by
+    /// `password_ref`, eg. given only in `jrny-env.local.toml`
+    pub password: Option<String>,
+    /// Where to fetch the password from instead of the URL, eg.
+    /// `vault:secret/data/db#password`
+    pub password_ref: Option<String>,
+    #[serde(default)]
+    pub auth: DatabaseAuth,
+    /// The region to generate IAM tokens for, if not the AWS CLI's default
+    pub aws_region: Option<String>,
+    /// Certificates to verify the server's against when connecting over TLS,
+    /// eg. the RDS bundle, without which it's verified against the system's
+    /// with IAM authentication, and otherwise encrypted but not verified
+    pub ssl_root_cert: Option<PathBuf>,
+}
+
+impl DatabaseEnvironment {
+    /// Why the database can't be connected to as configured, if it can't:
+    /// IAM tokens are only accepted over TLS, which needs the `aws-auth`
+    /// feature and mustn't be turned off by the URL's `sslmode`.
+    fn tls_problem(&self) -> Option<Problem> {
+        let reason = if self.auth != DatabaseAuth::AwsIam {
+            return None;
+        } else if !cfg!(feature = "aws-auth") {
+            "`aws-iam` needs TLS, which jrny was built without - rebuild with `--features aws-auth`"
+        } else if Config::from_str(&self.url).is_ok_and(|config| config.get_ssl_mode() == SslMode::Disable) {
+            "`aws-iam` needs TLS, which the URL's `sslmode=disable` turns off"
+        } else {
+            return None;
+        };
+
+        Some(Problem {
+            key: vec!["database".to_string(), "auth".to_string()],
+            reason: reason.to_string(),
+        })
+    }
+}
+
+/// Where to publish Prometheus metrics after embarking.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MetricsSettings {
+    /// Base URL of a Prometheus pushgateway
+    pub pushgateway: Option<String>,
+    /// File to write for the node exporter's textfile collector
+    pub textfile: Option<PathBuf>,
+    /// The job name used when pushing
+    #[serde(default = "default_job")]
+    pub job: String,
+    /// Labels added to all metrics, eg. `environment = "production"`
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
 }
 
+fn default_job() -> String {
+    "jrny".to_string()
+}
+
+/// Where to export OpenTelemetry traces after embarking.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TracingSettings {
+    /// Base URL of an OTLP/HTTP collector, eg. `http://localhost:4318`
+    pub endpoint: String,
+    #[serde(default = "default_service_name")]
+    pub service_name: String,
+    /// Extra headers sent with the export, eg. for authentication
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+}
+
+fn default_service_name() -> String {
+    "jrny".to_string()
+}
+
+/// Webhooks to notify with the run summary after embarking.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NotificationSettings {
+    /// Receives the run report as a JSON POST
+    pub webhook_url: Option<String>,
+    /// A Slack incoming webhook, which receives a formatted message
+    pub slack_webhook_url: Option<String>,
+}
+
+/// How to back up tables before revisions destroy data in them.
 #[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BackupSettings {
+    /// Whether to back up the tables that destructive statements affect
+    /// before applying their revision
+    #[serde(default)]
+    pub before_destructive: bool,
+    /// The schema to copy tables into, unless writing them to `directory`,
+    /// which can't be given along with it
+    #[serde(default = "default_backup_schema")]
+    pub schema: String,
+    /// A directory to write tables to as CSV files instead
+    pub directory: Option<PathBuf>,
+}
+
+fn default_backup_schema() -> String {
+    "jrny_backup".to_string()
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct Environment {
+    /// A human-friendly name for the environment, eg. `production`
+    pub name: Option<String>,
+    /// Marks environments such as production that must be guarded against
+    /// commands only meant for development, eg. `jrny fixtures`
+    #[serde(default)]
+    pub protected: bool,
+    /// What happens to revisions taking longer than their `max_duration`
+    #[serde(default)]
+    pub on_max_duration: MaxDurationPolicy,
     pub database: DatabaseEnvironment,
+    pub metrics: Option<MetricsSettings>,
+    pub tracing: Option<TracingSettings>,
+    pub notifications: Option<NotificationSettings>,
+    pub backup: Option<BackupSettings>,
+    /// Windows during which revisions aren't applied without overriding them
+    #[serde(default)]
+    pub freeze: Vec<FreezeWindow>,
+    /// Analyzes each table that a revision changes more than this many rows
+    /// of once it's applied, so that later revisions aren't planned against
+    /// stale statistics
+    pub analyze_after_rows: Option<u64>,
+    /// When to log heartbeats for statements that are still running
+    #[serde(default)]
+    pub heartbeat: HeartbeatSettings,
+    /// Which role should own objects created by embarking, if checked
+    pub ownership: Option<OwnershipSettings>,
+    /// The keys trusted to sign manifests of the revisions to apply
+    pub signing: Option<SigningSettings>,
+    /// How long to pause between statements and revisions, and how far
+    /// replicas may lag behind before pausing for them
+    #[serde(default)]
+    pub pacing: PacingSettings,
+    /// Whether extensions the revisions need are created where missing
+    /// before they're applied, rather than failing
+    #[serde(default)]
+    pub create_extensions: bool,
 }
 
 impl Environment {
-    pub fn from_filepath(envpath: &PathBuf) -> Result<Self> {
-        if !envpath.exists() {
+    pub fn from_filepath(envpath: &Path) -> Result<Self> {
+        Self::layered(envpath, &[])
+    }
+
+    /// Reads the environment file along with its local file alongside it, eg.
+    /// `jrny-env.local.toml`, whose settings take precedence, followed by the
+    /// overrides given as `key=value`, eg. `database.url=postgresql://...`.
+    /// Either file may be missing, as long as there's at least one layer.
+    pub fn layered(envpath: &Path, overrides: &[String]) -> Result<Self> {
+        let localpath = local_filepath(envpath);
+        let mut merged = None;
+        let mut files = vec![];
+
+        for path in [envpath, localpath.as_path()] {
+            if !path.exists() {
+                continue;
+            }
+
+            if !path.is_file() {
+                return Err(Error::FileNotValid(path.display().to_string()));
+            }
+
+            let contents = fs::read_to_string(path)?;
+            let layer: toml::Value = toml::from_str(&contents)
+                .map_err(|e| Error::TomlInvalid(e, path.display().to_string()))?;
+
+            merged = Some(match merged {
+                Some(merged) => merge(merged, layer),
+                None => layer,
+            });
+            files.push((path, contents));
+        }
+
+        if merged.is_none() && overrides.is_empty() {
             return Err(Error::EnvNotFound);
         }
 
-        if envpath.exists() && !envpath.is_file() {
-            return Err(Error::FileNotValid(envpath.display().to_string()));
+        let mut merged = merged.unwrap_or_else(|| toml::Value::Table(Default::default()));
+
+        for setting in overrides {
+            merged = merge(merged, parse_override(setting)?);
         }
 
-        let contents = fs::read_to_string(&envpath)?;
-        let env: Result<Self> = toml::from_str(&contents)
-            .map_err(|e| Error::TomlInvalid(e, envpath.display().to_string()));
+        let files: Vec<_> = files.iter().map(|(path, contents)| (*path, contents.as_str())).collect();
 
-        env
+        if let Some(problem) = Problem::conflicting(&merged, &EXCLUSIVE) {
+            return Err(problem.into_error(envpath, &files));
+        }
+
+        let env: Self = merged.try_into().map_err(|e| Problem::from_toml(&e).into_error(envpath, &files))?;
+
+        if let Some(problem) = env.database.tls_problem() {
+            return Err(problem.into_error(envpath, &files));
+        }
+
+        Ok(env)
     }
 
     pub fn from_database_url(url: &str) -> Self {
         Self {
+            name: None,
+            protected: false,
+            on_max_duration: MaxDurationPolicy::Warn,
             database: DatabaseEnvironment {
                 url: url.to_owned(),
+                password: None,
+                password_ref: None,
+                auth: DatabaseAuth::Password,
+                aws_region: None,
+                ssl_root_cert: None,
             },
+            metrics: None,
+            tracing: None,
+            notifications: None,
+            backup: None,
+            freeze: vec![],
+            analyze_after_rows: None,
+            heartbeat: HeartbeatSettings::default(),
+            ownership: None,
+            signing: None,
+            pacing: PacingSettings::default(),
+            create_extensions: false,
         }
     }
 }
+
+/// The local file for the environment file, eg. `jrny-env.local.toml` for
+/// `jrny-env.toml`, for settings that are kept out of version control.
+pub fn local_filepath(envpath: &Path) -> PathBuf {
+    let stem = envpath.file_stem().map_or_else(String::new, |stem| stem.to_string_lossy().to_string());
+
+    match envpath.extension() {
+        Some(ext) => envpath.with_file_name(format!("{}.local.{}", stem, ext.to_string_lossy())),
+        None => envpath.with_file_name(format!("{}.local", stem)),
+    }
+}
+
+/// The environment file for the named environment in the directory, eg.
+/// `jrny-env.staging.toml`.
+pub fn named_filepath(dir: &Path, name: &str) -> PathBuf {
+    dir.join(format!("jrny-env.{}.toml", name))
+}
+
+/// Whether the environment name can be used in a filename, as in
+/// `jrny-env.staging.toml`.
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// The name of the environment that the file is for, going by its filename,
+/// which is `None` for the default `jrny-env.toml`, or else whether it isn't
+/// an environment file at all, such as a local or example file.
+pub fn name_from_filepath(path: &Path) -> Option<Option<String>> {
+    let filename = path.file_name()?.to_str()?;
+
+    if filename == ENV {
+        return Some(None);
+    }
+
+    let name = filename.strip_prefix("jrny-env.")?.strip_suffix(".toml")?;
+
+    match name {
+        "local" | "example" => None,
+        name if is_valid_name(name) => Some(Some(name.to_string())),
+        _ => None,
+    }
+}
+
+/// The connection string for another database on the same server, as the
+/// same user and with the same options, given either as a URL or as
+/// `key=value` pairs, where the last `dbname` given is the one used.
+pub fn with_dbname(url: &str, dbname: &str) -> String {
+    let rest = match url.strip_prefix("postgresql://").or_else(|| url.strip_prefix("postgres://")) {
+        Some(rest) => rest,
+        None => return format!("{} dbname={}", url, dbname),
+    };
+    let scheme = &url[..url.len() - rest.len()];
+    let (rest, query) = match rest.split_once('?') {
+        Some((rest, query)) => (rest, Some(query)),
+        None => (rest, None),
+    };
+    let authority = rest.split('/').next().unwrap_or_default();
+    let query: Vec<_> = query
+        .into_iter()
+        .flat_map(|query| query.split('&'))
+        .filter(|param| !param.starts_with("dbname="))
+        .collect();
+
+    match query.is_empty() {
+        true => format!("{}{}/{}", scheme, authority, dbname),
+        false => format!("{}{}/{}?{}", scheme, authority, dbname, query.join("&")),
+    }
+}
+
+/// Merges the layer into the base, with tables merged key by key and any
+/// other value in the layer replacing that in the base.
+fn merge(base: toml::Value, layer: toml::Value) -> toml::Value {
+    match (base, layer) {
+        (toml::Value::Table(mut base), toml::Value::Table(layer)) => {
+            for (key, value) in layer {
+                let merged = match base.remove(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => value,
+                };
+
+                base.insert(key, merged);
+            }
+
+            toml::Value::Table(base)
+        }
+        (_, layer) => layer,
+    }
+}
+
+/// Parses an override such as `database.url=postgresql://...` into a layer
+/// holding only that setting, with its value read as TOML if it can be, eg.
+/// `protected=true`, and as a string otherwise.
+fn parse_override(setting: &str) -> Result<toml::Value> {
+    let invalid = || Error::EnvOverrideInvalid(setting.to_string());
+
+    let (key, value) = setting.split_once('=').ok_or_else(invalid)?;
+    let keys: Vec<_> = key.trim().split('.').map(str::trim).collect();
+
+    if keys.iter().any(|key| key.is_empty()) {
+        return Err(invalid());
+    }
+
+    let value = toml::from_str::<toml::value::Table>(&format!("value = {}", value))
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| toml::Value::String(value.to_string()));
+
+    Ok(keys.iter().rev().fold(value, |value, key| {
+        let mut table = toml::value::Table::new();
+        table.insert(key.to_string(), value);
+        toml::Value::Table(table)
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn layers_merge_in_order() {
+        let base: toml::Value = toml::from_str("name = \"dev\"\n[database]\nurl = \"postgresql://app@localhost/app\"").unwrap();
+        let local: toml::Value = toml::from_str("[database]\npassword_ref = \"vault:db#password\"").unwrap();
+
+        let merged = merge(merge(base, local), parse_override("database.url=postgresql://me@localhost/app").unwrap());
+        let env: Environment = merged.try_into().unwrap();
+
+        assert_eq!(env.name.as_deref(), Some("dev"));
+        assert_eq!(env.database.url, "postgresql://me@localhost/app");
+        assert_eq!(env.database.password_ref.as_deref(), Some("vault:db#password"));
+    }
+
+    #[test]
+    fn overrides_are_typed_where_possible() {
+        assert_eq!(parse_override("protected=true").unwrap()["protected"], toml::Value::Boolean(true));
+        assert_eq!(parse_override("name=staging").unwrap()["name"], toml::Value::String("staging".to_string()));
+        assert!(parse_override("protected").is_err());
+        assert!(parse_override("database..url=x").is_err());
+    }
+
+    #[test]
+    fn iam_auth_needs_tls() {
+        let mut database = Environment::from_database_url("postgresql://jrny_iam@db.example.com/app").database;
+
+        assert_eq!(database.tls_problem(), None);
+
+        database.auth = DatabaseAuth::AwsIam;
+        assert_eq!(database.tls_problem().is_none(), cfg!(feature = "aws-auth"));
+
+        database.url.push_str("?sslmode=disable");
+        assert_eq!(database.tls_problem().unwrap().key, ["database", "auth"]);
+    }
+
+    #[test]
+    fn local_file_sits_alongside() {
+        assert_eq!(local_filepath(Path::new("db/jrny-env.toml")), PathBuf::from("db/jrny-env.local.toml"));
+        assert_eq!(
+            local_filepath(Path::new("jrny-env.staging.toml")),
+            PathBuf::from("jrny-env.staging.local.toml"),
+        );
+    }
+
+    #[test]
+    fn environment_files_are_named() {
+        let name = |filename: &str| name_from_filepath(Path::new(filename));
+
+        assert_eq!(named_filepath(Path::new("db"), "staging"), PathBuf::from("db/jrny-env.staging.toml"));
+        assert_eq!(name("db/jrny-env.toml"), Some(None));
+        assert_eq!(name("jrny-env.staging.toml"), Some(Some("staging".to_string())));
+        assert_eq!(name("jrny-env.local.toml"), None);
+        assert_eq!(name("jrny-env.staging.local.toml"), None);
+        assert_eq!(name("jrny-env.example.toml"), None);
+        assert_eq!(name("jrny.toml"), None);
+    }
+
+    #[test]
+    fn database_is_replaced_in_urls() {
+        assert_eq!(with_dbname("postgresql://jrny@localhost:5432/app", "scratch"), "postgresql://jrny@localhost:5432/scratch");
+        assert_eq!(with_dbname("postgres://localhost?sslmode=require", "scratch"), "postgres://localhost/scratch?sslmode=require");
+        assert_eq!(
+            with_dbname("postgresql://localhost/app?dbname=other&sslmode=require", "scratch"),
+            "postgresql://localhost/scratch?sslmode=require",
+        );
+        assert_eq!(with_dbname("host=localhost dbname=app", "scratch"), "host=localhost dbname=app dbname=scratch");
+    }
+}
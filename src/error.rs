@@ -1,32 +1,134 @@
-use std::{env, fmt, io, num};
+use std::{env, fmt, io, num, time::Duration};
+use postgres::error::SqlState;
 use toml::de::Error as TomlError;
 
+use crate::progress::format_duration;
+
 // TODO This has gotten a bit unwieldy.
 // Should these just be individual structs now to avoid
 // big matches anywhere?
 #[derive(Debug)]
 pub enum Error {
+    AssertionFailed(String, String),
+    AuthTokenFailed(String),
+    BackupFailed(String, String),
     BadEnvVar(env::VarError, String),
+    BatchInvalid(String, String),
+    BundleConflict(String, String),
+    BundleInvalid(String, String),
+    ColorModeInvalid(String),
+    CommandFailed(String, io::Error),
+    CommandMissing,
+    ConfigFormatInvalid(String),
     ConfigNotFound(String),
+    ConfirmationFailed(String),
+    ConfirmationRequired(Option<String>),
+    ConstraintsNotValidated(String, String),
     DatabaseError(postgres::Error),
+    DatabaseNameMissing,
+    DatabaseUnavailable(Duration, postgres::Error),
+    DestructiveStatementsFound(Vec<String>),
     EnvNotFound,
+    EnvOverrideInvalid(String),
+    EnvironmentFrozen(Option<String>, String),
+    EnvironmentNameInvalid(String),
+    EnvironmentNotFound(String, String),
+    EnvironmentProtected(Option<String>),
+    ExtensionsMissing(Vec<String>),
     FileNotValid(String),
+    FixtureFailed(String, String),
+    FixtureInvalid(String, String),
+    FixturesNotAllowed(Option<String>),
+    GeneratorInvalid(String, String),
+    GitRefInvalid(String, String),
+    GrantsDiffer(usize, String),
+    HttpRequestFailed(String, String),
+    IfAlreadyRunningInvalid(String),
+    ImportSourceInvalid(String, String),
+    ImportToolInvalid(String),
     IoError(io::Error),
+    LockRetryInvalid(String, String),
+    MaintenanceSqlFailed(String, String),
+    ManifestInvalid(String, String),
+    ObjectsMisowned(usize, String),
+    OptionsConflict(String, String),
+    OutputFormatInvalid(String),
     PathAlreadyExists(String),
+    PathCaseConflict(String, String),
     PathInvalid(String),
     PathNotDirectory(String),
     PathNotEmptyDirectory(String),
+    PgTapFailed(usize),
+    PreparedTransactionInvalid(String, String),
+    PreparedTransactionsDisabled(String),
+    PreparedTransactionsLeft(usize),
+    PreparedTransactionsUnresolved(usize),
+    ProjectNotInWorkspace(String, String),
+    ProjectOrderInvalid(String),
+    ProjectsFailed(usize),
+    RehearsalProtected(Option<String>),
+    ReleaseGroupInvalid(String, String),
+    ReleaseGroupNotApplied(String),
+    RepeatableNameInvalid(String),
+    ReplicationLagInvalid(String),
+    ReportFormatInvalid(String),
+    RevertFailed(String, String),
+    RevertIncomplete(usize),
+    RevertMissing(String),
+    RevisionCancelled(String, Duration),
+    RevisionChangeRejected(String),
+    RevisionMetaInvalid(String, String),
     RevisionNameInvalid(String),
+    RevisionNameViolatesRules(String, Vec<String>),
+    RevisionNotFound(String, usize),
+    RevisionNotPending(String),
+    RevisionRecordedConcurrently(String),
     RevisionTimestampInvalid(num::ParseIntError, String),
     RevisionTimestampOutOfRange(String),
+    RevisionTooSlow(String, Duration, Duration),
+    RevisionsNotApproved(usize),
+    RevisionsNotSigned(usize),
+    RevisionsPending(usize),
     RevisionsFailedReview {
         changed: usize,
         duplicate_ids: usize,
+        duplicate_names: usize,
+        expired: usize,
+        invalid: usize,
+        misnamed: usize,
         missing: usize,
+        out_of_order: usize,
+        policy: usize,
         predate_applied: usize,
+        unbounded: usize,
+        unmet: usize,
+        unreplaced: usize,
+        unsupported: usize,
     },
+    SchemasDiffer(usize),
+    SecretFetchFailed(String, String),
+    SecretRefInvalid(String),
+    SettingInvalid(String, Option<usize>, String),
+    SignatureInvalid(String, String),
+    SigningFailed(String, String),
+    SigningNotConfigured,
+    SigningToolInvalid(String),
+    SnapshotInvalid(String, String),
+    SnapshotStale(String, usize),
+    SplitPointsInvalid(String),
+    SqlNotEmittable(String),
+    TemplateInvalid(String, String),
+    TerminateBlockersProtected(Option<String>),
+    TestProtected(Option<String>),
+    TestsFailed(usize),
+    TlsNotConfigured(String),
+    TombstoneInvalid(String, String),
     TomlInvalid(TomlError, String),
+    TrackingTableNewer(String, i32),
+    TrackingTableOutdated(String, Vec<String>),
     TransactionCommandFound(String),
+    ValidationInvalid(String, String),
+    WorkspaceNotFound(String),
 }
 
 impl fmt::Display for Error {
@@ -34,27 +136,174 @@ impl fmt::Display for Error {
         use Error::*;
 
         match self {
+            AssertionFailed(location, reason) => {
+                write!(f, "Assertion at `{}` failed: {}", location, reason)
+            }
+            AuthTokenFailed(reason) => {
+                write!(f, "Could not generate an IAM authentication token: {}", reason)
+            }
+            BackupFailed(table, reason) => {
+                write!(f, "Failed to back up `{}`: {}", table, reason)
+            }
             BadEnvVar(err, var_name) => {
                 write!(f, "{} - {}", err, var_name)
             }
+            BatchInvalid(location, reason) => {
+                write!(f, "Invalid batch directive at `{}`: {}", location, reason)
+            }
+            BundleConflict(filename, reason) => {
+                write!(f, "Cannot import `{}` from bundle: {}", filename, reason)
+            }
+            BundleInvalid(pathstr, reason) => {
+                write!(f, "Invalid bundle `{}`: {}", pathstr, reason)
+            }
+            ColorModeInvalid(mode) => {
+                write!(f, "Invalid color mode `{}`: expected `auto`, `always`, or `never`", mode)
+            }
+            CommandFailed(program, err) => {
+                write!(f, "Could not run `{}`: {}", program, err)
+            }
+            CommandMissing => {
+                write!(f, "No command given to run, expected eg. `jrny run -- ./my-service`")
+            }
+            ConfigFormatInvalid(format) => {
+                write!(f, "Invalid config format `{}`: expected `commented` or `minimal`", format)
+            }
             ConfigNotFound(pathstr) => {
                 write!(f, "`{}` not found - run in directory with `jrny.toml` file or specify path to config with `-c /path/to/config`", pathstr)
             }
+            ConfirmationFailed(name) => {
+                write!(f, "Confirmation did not match `{}`, so nothing was changed", name)
+            }
+            ConfirmationRequired(Some(name)) => {
+                write!(f, "`{}` is a protected environment - confirm with `--yes-i-mean-production` when not running interactively", name)
+            }
+            ConfirmationRequired(None) => {
+                write!(f, "This is a protected environment - confirm with `--yes-i-mean-production`, or give it a `name` to type in")
+            }
+            ConstraintsNotValidated(filename, reason) => {
+                write!(
+                    f,
+                    "`{}` was applied, but validating its constraints failed, leaving them `NOT VALID`: {}\n\
+                     Fix the rows breaking them and validate them with `ALTER TABLE ... VALIDATE CONSTRAINT`",
+                    filename, reason,
+                )
+            }
             DatabaseError(err) => {
                 write!(f, "{}", err)
             }
+            DatabaseNameMissing => {
+                write!(f, "The database URL names neither a database nor a user, so there's no database to create")
+            }
+            DatabaseUnavailable(timeout, err) => {
+                write!(f, "Database still unavailable after waiting {}s: {}", timeout.as_secs(), err)
+            }
+            DestructiveStatementsFound(statements) => {
+                write!(
+                    f,
+                    "Destructive statements need `-- jrny:allow-destructive` in their revision to be applied to a protected environment:"
+                )?;
+
+                for statement in statements {
+                    write!(f, "\n\t{}", statement)?;
+                }
+
+                Ok(())
+            }
             EnvNotFound => {
                 write!(f, "`jrny-env.toml` must exist within same directory as config file or `--database-url` must be provided")
             }
+            EnvOverrideInvalid(setting) => {
+                write!(f, "Invalid setting `{}`: expected `[key]=[value]`, eg. `database.url=postgresql://...`", setting)
+            }
+            EnvironmentFrozen(Some(name), window) => {
+                write!(f, "Environment `{}` is frozen{} - use `--override-freeze` to apply revisions anyway", name, window)
+            }
+            EnvironmentFrozen(None, window) => {
+                write!(f, "The environment is frozen{} - use `--override-freeze` to apply revisions anyway", window)
+            }
+            EnvironmentNameInvalid(name) => {
+                write!(f, "Invalid environment name `{}`: expected only letters, numbers, `-`, and `_`", name)
+            }
+            EnvironmentNotFound(name, pathstr) => {
+                write!(f, "No environment file for `{}` at `{}` - add one with `jrny env add {} --url [url]`", name, pathstr, name)
+            }
+            EnvironmentProtected(Some(name)) => {
+                write!(f, "Refusing to load fixtures into protected environment `{}`", name)
+            }
+            EnvironmentProtected(None) => {
+                write!(f, "Refusing to load fixtures into a protected environment")
+            }
+            ExtensionsMissing(extensions) => {
+                write!(f, "The database lacks extensions the revisions need: {}", extensions.join(", "))
+            }
             FileNotValid(pathstr) => {
                 write!(f, "`{}` is not a valid file", pathstr)
             }
+            FixtureFailed(filename, err) => {
+                write!(f, "Failed to load fixture `{}`: {}", filename, err)
+            }
+            FixtureInvalid(filename, reason) => {
+                write!(f, "Invalid fixture `{}`: {}", filename, reason)
+            }
+            FixturesNotAllowed(Some(name)) => {
+                write!(
+                    f,
+                    "Fixtures are not enabled for environment `{}` - add it to `environments` under `[fixtures]` in `jrny.toml`",
+                    name
+                )
+            }
+            FixturesNotAllowed(None) => {
+                write!(f, "Fixtures can only be loaded into environments with a `name` listed under `[fixtures]` in `jrny.toml`")
+            }
+            GeneratorInvalid(generator, reason) => {
+                write!(f, "Cannot generate revisions for `{}`: {}", generator, reason)
+            }
+            GitRefInvalid(reference, dir) => {
+                write!(f, "Could not compare `{}` against git ref `{}` - is it a repository containing the ref?", dir, reference)
+            }
+            GrantsDiffer(count, pathstr) => {
+                write!(f, "The cluster needs {} change(s) to match `{}`", count, pathstr)
+            }
+            HttpRequestFailed(url, err) => {
+                write!(f, "Request to `{}` failed: {}", url, err)
+            }
+            IfAlreadyRunningInvalid(mode) => {
+                write!(f, "Invalid action `{}`: expected `wait` or `skip`", mode)
+            }
+            ImportSourceInvalid(pathstr, reason) => {
+                write!(f, "Cannot import from `{}`: {}", pathstr, reason)
+            }
+            ImportToolInvalid(tool) => {
+                write!(f, "Invalid tool `{}`: expected `flyway`, `sqitch`, or `golang-migrate`", tool)
+            }
             IoError(err) => {
                 write!(f, "{}", err)
             }
+            LockRetryInvalid(location, reason) => {
+                write!(f, "Invalid lock-retry directive at `{}`: {}", location, reason)
+            }
+            MaintenanceSqlFailed(action, err) => {
+                write!(f, "Could not {} maintenance mode: {}", action, err)
+            }
+            ManifestInvalid(pathstr, reason) => {
+                write!(f, "Invalid manifest `{}`: {}", pathstr, reason)
+            }
+            ObjectsMisowned(count, owner) => {
+                write!(f, "{} object(s) created are not owned by `{}` or lack its default privileges", count, owner)
+            }
+            OptionsConflict(option, other) => {
+                write!(f, "`{}` can't be used with `{}`", option, other)
+            }
+            OutputFormatInvalid(format) => {
+                write!(f, "Invalid output format `{}`: expected `text` or `junit`", format)
+            }
             PathAlreadyExists(pathstr) => {
                 write!(f, "`{}` already exists", pathstr)
             }
+            PathCaseConflict(pathstr, other) => {
+                write!(f, "`{}` differs only by case from `{}`, so they can't both be checked out on Windows or macOS", pathstr, other)
+            }
             PathInvalid(pathstr) => {
                 write!(f, "`{}` is not a valid path", pathstr)
             }
@@ -64,6 +313,69 @@ impl fmt::Display for Error {
             PathNotEmptyDirectory(pathstr) => {
                 write!(f, "`{}` is not an empty directory", pathstr)
             }
+            PgTapFailed(count) => {
+                write!(f, "{} pgTAP file(s) failed", count)
+            }
+            PreparedTransactionInvalid(at, reason) => {
+                write!(f, "Cannot apply `{}` in a prepared transaction: it {}", at, reason)
+            }
+            PreparedTransactionsDisabled(project) => {
+                write!(f, "The server of `{}` doesn't allow transactions to be prepared", project)
+            }
+            PreparedTransactionsLeft(count) => {
+                write!(f, "{} prepared transaction(s) were left to be committed", count)
+            }
+            PreparedTransactionsUnresolved(count) => {
+                write!(f, "{} transaction(s) are still left prepared by earlier runs", count)
+            }
+            ProjectNotInWorkspace(project, workspace) => {
+                write!(f, "`{}` is not one of the projects listed in `{}`", project, workspace)
+            }
+            ProjectOrderInvalid(reason) => {
+                write!(f, "Cannot order revisions across projects: {}", reason)
+            }
+            ProjectsFailed(count) => {
+                write!(f, "{} project(s) could not be summarized", count)
+            }
+            RehearsalProtected(Some(name)) => {
+                write!(f, "Refusing to rehearse against protected environment `{}` - use a copy of it instead", name)
+            }
+            RehearsalProtected(None) => {
+                write!(f, "Refusing to rehearse against a protected environment - use a copy of it instead")
+            }
+            ReleaseGroupInvalid(group, reason) => {
+                write!(f, "Cannot apply release group `{}` in one transaction: {}", group, reason)
+            }
+            ReleaseGroupNotApplied(group) => {
+                write!(f, "No revisions of release group `{}` are recorded as applied", group)
+            }
+            RepeatableNameInvalid(filename) => {
+                write!(f, "Invalid file `{}`: expected a `.sql` file", filename)
+            }
+            ReplicationLagInvalid(found) => {
+                write!(f, "Cannot find replication lag: expected a number of seconds, found `{}`", found)
+            }
+            ReportFormatInvalid(pathstr) => {
+                write!(f, "Cannot write report to `{}`: expected a `.json` or `.md` file", pathstr)
+            }
+            RevertFailed(filename, reason) => {
+                write!(f, "Reverting `{}` failed - {}", filename, reason)
+            }
+            RevertIncomplete(count) => {
+                write!(f, "Reverting the revisions left {} difference(s) from the schema before they were applied", count)
+            }
+            RevertMissing(filename) => {
+                write!(f, "`{}` can't be reverted, as it has no `.revert.sql` file alongside it", filename)
+            }
+            RevisionCancelled(filename, max) => {
+                write!(f, "Cancelled `{}` for taking longer than its max_duration of {}", filename, format_duration(*max))
+            }
+            RevisionChangeRejected(filename) => {
+                write!(f, "Stopped without accepting the changes to applied revision `{}`", filename)
+            }
+            RevisionMetaInvalid(filename, reason) => {
+                write!(f, "Invalid frontmatter in `{}`: {}", filename, reason)
+            }
             RevisionNameInvalid(filename) => {
                 write!(
                     f,
@@ -71,6 +383,18 @@ impl fmt::Display for Error {
                     filename
                 )
             }
+            RevisionNotFound(revision, 0) => {
+                write!(f, "No revision `{}` found, expected its filename, id, or name", revision)
+            }
+            RevisionNotFound(revision, matches) => {
+                write!(f, "`{}` matches {} revisions, expected the filename of one", revision, matches)
+            }
+            RevisionNotPending(filename) => {
+                write!(f, "`{}` has already been applied, so it can't be changed", filename)
+            }
+            RevisionRecordedConcurrently(filename) => {
+                write!(f, "`{}` was recorded as applied by another run at the same time - review before embarking again", filename)
+            }
             RevisionTimestampInvalid(err, filename) => {
                 write!(f, "Invalid revision timestamp `{}`: {}", filename, err)
             }
@@ -81,11 +405,48 @@ impl fmt::Display for Error {
                     filename
                 )
             }
+            RevisionTooSlow(filename, took, max) => {
+                write!(
+                    f,
+                    "`{}` took {}, longer than its max_duration of {}",
+                    filename,
+                    format_duration(*took),
+                    format_duration(*max),
+                )
+            }
+            RevisionNameViolatesRules(name, violations) => {
+                write!(f, "`{}` breaks the naming rules:", name)?;
+
+                for violation in violations {
+                    write!(f, "\n\t{}", violation)?;
+                }
+
+                Ok(())
+            }
+            RevisionsNotApproved(count) => {
+                write!(f, "{} pending revision(s) are not approved", count)
+            }
+            RevisionsNotSigned(count) => {
+                write!(f, "{} pending revision(s) are not signed as they are", count)
+            }
+            RevisionsPending(count) => {
+                write!(f, "{} pending revision(s) must be applied with `jrny embark` first", count)
+            }
             RevisionsFailedReview {
                 changed,
                 duplicate_ids,
+                duplicate_names,
+                expired,
+                invalid,
+                misnamed,
                 missing,
+                out_of_order,
+                policy,
                 predate_applied,
+                unbounded,
+                unmet,
+                unreplaced,
+                unsupported,
             } => {
                 let mut errs = String::new();
 
@@ -102,10 +463,34 @@ impl fmt::Display for Error {
                     errs.push_str(&format!("\n\t{} {} duplicate {}", duplicate_ids, verb, id));
                 }
 
+                if *duplicate_names > 0 {
+                    errs.push_str(&format!("\n\t{} pending share a name with another revision", duplicate_names));
+                }
+
+                if *expired > 0 {
+                    errs.push_str(&format!("\n\t{} pending are past the date they were to be applied before", expired));
+                }
+
+                if *invalid > 0 {
+                    errs.push_str(&format!("\n\t{} not valid as revision files", invalid));
+                }
+
+                if *misnamed > 0 {
+                    errs.push_str(&format!("\n\t{} pending break naming rules", misnamed));
+                }
+
                 if *missing > 0 {
                     errs.push_str(&format!("\n\t{} applied no longer present", missing));
                 }
 
+                if *out_of_order > 0 {
+                    errs.push_str(&format!("\n\t{} applied after later revisions", out_of_order));
+                }
+
+                if *policy > 0 {
+                    errs.push_str(&format!("\n\t{} pending break policy rules", policy));
+                }
+
                 if *predate_applied > 0 {
                     errs.push_str(&format!(
                         "\n\t{} pending occur before applied revisions",
@@ -113,15 +498,369 @@ impl fmt::Display for Error {
                     ));
                 }
 
+                if *unbounded > 0 {
+                    errs.push_str(&format!("\n\t{} pending data revisions change every row of a table", unbounded));
+                }
+
+                if *unmet > 0 {
+                    errs.push_str(&format!("\n\t{} pending require revisions not applied before them", unmet));
+                }
+
+                if *unreplaced > 0 {
+                    errs.push_str(&format!("\n\t{} superseded by revisions that can't be found", unreplaced));
+                }
+
+                if *unsupported > 0 {
+                    errs.push_str(&format!("\n\t{} pending need a newer version of Postgres than the server's", unsupported));
+                }
+
+                if *duplicate_ids + *predate_applied > 0 {
+                    errs.push_str("\n\nPending revisions can be renumbered to follow those applied with `jrny rename --resequence`");
+                }
+
+                if *duplicate_names > 0 {
+                    errs.push_str("\n\nRevisions sharing a name should be renamed or combined");
+                }
+
                 write!(f, "Revisions review failed:{}", errs)
             }
+            SchemasDiffer(count) => {
+                write!(f, "The databases differ in {} object(s)", count)
+            }
+            SecretFetchFailed(reference, reason) => {
+                write!(f, "Could not fetch secret `{}`: {}", reference, reason)
+            }
+            SecretRefInvalid(reference) => {
+                write!(
+                    f,
+                    "Invalid secret reference `{}`: expected `vault:[path]#[key]`, `aws:[secret-id]`, or `gcp:[name]`, each optionally with `#[key]`",
+                    reference
+                )
+            }
+            SignatureInvalid(pathstr, reason) => {
+                write!(f, "Could not verify the signature of `{}`: {}", pathstr, reason)
+            }
+            SigningFailed(pathstr, reason) => {
+                write!(f, "Could not sign `{}`: {}", pathstr, reason)
+            }
+            SigningNotConfigured => {
+                write!(f, "Requiring a signature needs a `[signing]` section in the environment file giving the keys to trust")
+            }
+            SettingInvalid(pathstr, Some(line), reason) => {
+                write!(f, "`{}` line {} is invalid - {}", pathstr, line, reason)
+            }
+            SettingInvalid(pathstr, None, reason) => {
+                write!(f, "`{}` is invalid - {}", pathstr, reason)
+            }
+            SigningToolInvalid(tool) => {
+                write!(f, "Invalid signing tool `{}`: expected `ssh`, `gpg`, or `minisign`", tool)
+            }
+            SnapshotInvalid(pathstr, reason) => {
+                write!(f, "Invalid snapshot `{}`: {}", pathstr, reason)
+            }
+            SnapshotStale(pathstr, count) => {
+                write!(f, "The snapshot `{}` is out of date by {} object(s) - run `jrny snapshot` to update it", pathstr, count)
+            }
+            SplitPointsInvalid(reason) => {
+                write!(f, "Cannot split revision: {}", reason)
+            }
+            SqlNotEmittable(reason) => {
+                write!(f, "Cannot emit SQL to apply the revisions: {}", reason)
+            }
+            TemplateInvalid(pathstr, reason) => {
+                write!(f, "Template `{}` is invalid - {}", pathstr, reason)
+            }
+            TerminateBlockersProtected(Some(name)) => {
+                write!(f, "Refusing to terminate sessions blocking revisions in protected environment `{}`", name)
+            }
+            TerminateBlockersProtected(None) => {
+                write!(f, "Refusing to terminate sessions blocking revisions in a protected environment")
+            }
+            TestProtected(Some(name)) => {
+                write!(f, "Refusing to create a scratch database on the server of protected environment `{}`", name)
+            }
+            TestProtected(None) => {
+                write!(f, "Refusing to create a scratch database on the server of a protected environment")
+            }
+            TestsFailed(count) => {
+                write!(f, "{} assertion(s) failed", count)
+            }
+            TlsNotConfigured(reason) => {
+                write!(f, "Failed to set up TLS for connecting: {}", reason)
+            }
+            TombstoneInvalid(filename, reason) => {
+                write!(f, "Cannot make `{}` a tombstone: {}", filename, reason)
+            }
             TomlInvalid(err, pathstr) => {
                 write!(f, "`{}` is invalid - {}", pathstr, err)
             }
+            TrackingTableNewer(table, version) => {
+                write!(
+                    f,
+                    "Tracking table `{}` is at version {}, which is newer than this version of jrny supports - upgrade jrny",
+                    table, version,
+                )
+            }
+            TrackingTableOutdated(table, missing) => {
+                write!(
+                    f,
+                    "Tracking table `{}` is missing column(s) `{}` that can't be added automatically - was it created by another tool?",
+                    table,
+                    missing.join("`, `"),
+                )
+            }
             TransactionCommandFound(cmd) => {
                 write!(f, "Cannot use transaction commands: found `{}`", cmd)
             }
+            ValidationInvalid(location, reason) => {
+                write!(f, "Invalid validate-separately directive at `{}`: {}", location, reason)
+            }
+            WorkspaceNotFound(dir) => {
+                write!(f, "No `jrny-workspace.toml` found in `{}` or any directory above it", dir)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        use Error::*;
+
+        match self {
+            BadEnvVar(err, _) => Some(err),
+            CommandFailed(_, err) | IoError(err) => Some(err),
+            DatabaseError(err) | DatabaseUnavailable(_, err) => Some(err),
+            RevisionTimestampInvalid(err, _) => Some(err),
+            TomlInvalid(err, _) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// The message, followed by each of the causes and the hint, if any.
+pub fn render_report(message: String, causes: &[String], hint: Option<String>) -> String {
+    let mut report = message;
+
+    for cause in causes {
+        report.push_str(&format!("\n  caused by: {}", cause));
+    }
+
+    if let Some(hint) = hint {
+        report.push_str(&format!("\nhint: {}", hint));
+    }
+
+    report
+}
+
+/// What to suggest when the database can't be reached at all.
+const CONNECTION_HINT: &str = "is the database accepting connections at the environment's `url`? Check with `jrny env test`";
+
+impl Error {
+    /// The stable id of the error, which stays the same whatever its
+    /// message says, eg. for wrappers to tell errors apart or word them
+    /// their own way, and for JSON output.
+    pub fn id(&self) -> &'static str {
+        use Error::*;
+
+        match self {
+            AssertionFailed(..) => "assertion-failed",
+            AuthTokenFailed(..) => "auth-token-failed",
+            BackupFailed(..) => "backup-failed",
+            BadEnvVar(..) => "bad-env-var",
+            BatchInvalid(..) => "batch-invalid",
+            BundleConflict(..) => "bundle-conflict",
+            BundleInvalid(..) => "bundle-invalid",
+            ColorModeInvalid(..) => "color-mode-invalid",
+            CommandFailed(..) => "command-failed",
+            CommandMissing => "command-missing",
+            ConfigFormatInvalid(..) => "config-format-invalid",
+            ConfigNotFound(..) => "config-not-found",
+            ConfirmationFailed(..) => "confirmation-failed",
+            ConfirmationRequired(..) => "confirmation-required",
+            ConstraintsNotValidated(..) => "constraints-not-validated",
+            DatabaseError(..) => "database-error",
+            DatabaseNameMissing => "database-name-missing",
+            DatabaseUnavailable(..) => "database-unavailable",
+            DestructiveStatementsFound(..) => "destructive-statements-found",
+            EnvNotFound => "env-not-found",
+            EnvOverrideInvalid(..) => "env-override-invalid",
+            EnvironmentFrozen(..) => "environment-frozen",
+            EnvironmentNameInvalid(..) => "environment-name-invalid",
+            EnvironmentNotFound(..) => "environment-not-found",
+            EnvironmentProtected(..) => "environment-protected",
+            ExtensionsMissing(..) => "extensions-missing",
+            FileNotValid(..) => "file-not-valid",
+            FixtureFailed(..) => "fixture-failed",
+            FixtureInvalid(..) => "fixture-invalid",
+            FixturesNotAllowed(..) => "fixtures-not-allowed",
+            GeneratorInvalid(..) => "generator-invalid",
+            GitRefInvalid(..) => "git-ref-invalid",
+            GrantsDiffer(..) => "grants-differ",
+            HttpRequestFailed(..) => "http-request-failed",
+            IfAlreadyRunningInvalid(..) => "if-already-running-invalid",
+            ImportSourceInvalid(..) => "import-source-invalid",
+            ImportToolInvalid(..) => "import-tool-invalid",
+            IoError(..) => "io-error",
+            LockRetryInvalid(..) => "lock-retry-invalid",
+            MaintenanceSqlFailed(..) => "maintenance-sql-failed",
+            ManifestInvalid(..) => "manifest-invalid",
+            ObjectsMisowned(..) => "objects-misowned",
+            OptionsConflict(..) => "options-conflict",
+            OutputFormatInvalid(..) => "output-format-invalid",
+            PathAlreadyExists(..) => "path-already-exists",
+            PathCaseConflict(..) => "path-case-conflict",
+            PathInvalid(..) => "path-invalid",
+            PathNotDirectory(..) => "path-not-directory",
+            PathNotEmptyDirectory(..) => "path-not-empty-directory",
+            PgTapFailed(..) => "pgtap-failed",
+            PreparedTransactionInvalid(..) => "prepared-transaction-invalid",
+            PreparedTransactionsDisabled(..) => "prepared-transactions-disabled",
+            PreparedTransactionsLeft(..) => "prepared-transactions-left",
+            PreparedTransactionsUnresolved(..) => "prepared-transactions-unresolved",
+            ProjectNotInWorkspace(..) => "project-not-in-workspace",
+            ProjectOrderInvalid(..) => "project-order-invalid",
+            ProjectsFailed(..) => "projects-failed",
+            RehearsalProtected(..) => "rehearsal-protected",
+            ReleaseGroupInvalid(..) => "release-group-invalid",
+            ReleaseGroupNotApplied(..) => "release-group-not-applied",
+            RepeatableNameInvalid(..) => "repeatable-name-invalid",
+            ReplicationLagInvalid(..) => "replication-lag-invalid",
+            ReportFormatInvalid(..) => "report-format-invalid",
+            RevertFailed(..) => "revert-failed",
+            RevertIncomplete(..) => "revert-incomplete",
+            RevertMissing(..) => "revert-missing",
+            RevisionCancelled(..) => "revision-cancelled",
+            RevisionChangeRejected(..) => "revision-change-rejected",
+            RevisionMetaInvalid(..) => "revision-meta-invalid",
+            RevisionNameInvalid(..) => "revision-name-invalid",
+            RevisionNameViolatesRules(..) => "revision-name-violates-rules",
+            RevisionNotFound(..) => "revision-not-found",
+            RevisionNotPending(..) => "revision-not-pending",
+            RevisionRecordedConcurrently(..) => "revision-recorded-concurrently",
+            RevisionTimestampInvalid(..) => "revision-timestamp-invalid",
+            RevisionTimestampOutOfRange(..) => "revision-timestamp-out-of-range",
+            RevisionTooSlow(..) => "revision-too-slow",
+            RevisionsNotApproved(..) => "revisions-not-approved",
+            RevisionsNotSigned(..) => "revisions-not-signed",
+            RevisionsPending(..) => "revisions-pending",
+            RevisionsFailedReview { .. } => "revisions-failed-review",
+            SchemasDiffer(..) => "schemas-differ",
+            SecretFetchFailed(..) => "secret-fetch-failed",
+            SecretRefInvalid(..) => "secret-ref-invalid",
+            SettingInvalid(..) => "setting-invalid",
+            SignatureInvalid(..) => "signature-invalid",
+            SigningFailed(..) => "signing-failed",
+            SigningNotConfigured => "signing-not-configured",
+            SigningToolInvalid(..) => "signing-tool-invalid",
+            SnapshotInvalid(..) => "snapshot-invalid",
+            SnapshotStale(..) => "snapshot-stale",
+            SplitPointsInvalid(..) => "split-points-invalid",
+            SqlNotEmittable(..) => "sql-not-emittable",
+            TemplateInvalid(..) => "template-invalid",
+            TerminateBlockersProtected(..) => "terminate-blockers-protected",
+            TestProtected(..) => "test-protected",
+            TestsFailed(..) => "tests-failed",
+            TlsNotConfigured(..) => "tls-not-configured",
+            TombstoneInvalid(..) => "tombstone-invalid",
+            TomlInvalid(..) => "toml-invalid",
+            TrackingTableNewer(..) => "tracking-table-newer",
+            TrackingTableOutdated(..) => "tracking-table-outdated",
+            TransactionCommandFound(..) => "transaction-command-found",
+            ValidationInvalid(..) => "validation-invalid",
+            WorkspaceNotFound(..) => "workspace-not-found",
+        }
+    }
+
+    /// The error followed by each of its causes that it doesn't already
+    /// mention, and then a hint at what to do about it if there is one, as
+    /// shown when a command fails.
+    pub fn report(&self) -> String {
+        render_report(self.to_string(), &self.causes(), self.hint())
+    }
+
+    /// What caused the error, outermost first, leaving out causes that the
+    /// error or those before them already mention.
+    pub fn causes(&self) -> Vec<String> {
+        let mut said = self.to_string();
+        let mut causes = vec![];
+        let mut source = std::error::Error::source(self);
+
+        while let Some(cause) = source {
+            let cause_str = cause.to_string();
+
+            if !said.contains(&cause_str) {
+                said.push_str(&cause_str);
+                causes.push(cause_str);
+            }
+
+            source = cause.source();
         }
+
+        causes
+    }
+
+    /// What to do about the error, for errors that don't already say.
+    pub fn hint(&self) -> Option<String> {
+        use Error::*;
+
+        let hint = match self {
+            AuthTokenFailed(_) => "is the AWS CLI installed and signed in, eg. with `aws sso login`?".to_string(),
+            CommandFailed(program, err) if err.kind() == io::ErrorKind::NotFound => {
+                format!("is `{}` installed and on the `PATH`?", program)
+            }
+            DatabaseError(err) => return database_hint(err).map(ToString::to_string),
+            DatabaseUnavailable(..) => CONNECTION_HINT.to_string(),
+            GrantsDiffer(..) => "make them with `jrny grants apply`".to_string(),
+            IoError(err) if err.kind() == io::ErrorKind::PermissionDenied => {
+                "can the user running jrny read and write the project's files?".to_string()
+            }
+            RevisionChangeRejected(_) => "undo the changes to the file, or run `jrny embark` again to accept them".to_string(),
+            RevisionsFailedReview { .. } => "run `jrny review` to see which revisions fail and why".to_string(),
+            ExtensionsMissing(_) => {
+                "create them with `CREATE EXTENSION`, or set `create_extensions = true` in the environment file".to_string()
+            }
+            RevisionsNotSigned(_) => "sign them with `jrny sign --files`".to_string(),
+            PreparedTransactionsDisabled(_) => {
+                "set `max_prepared_transactions` above 0 in its postgresql.conf and restart it".to_string()
+            }
+            PreparedTransactionsLeft(_) => "commit them with `jrny prepared resolve` once every server is reachable".to_string(),
+            PreparedTransactionsUnresolved(_) => "list them with `jrny prepared list`, and resolve them with `jrny prepared resolve`".to_string(),
+            SecretFetchFailed(..) => {
+                "are you signed in to where the secret is kept, eg. with `VAULT_TOKEN`, `aws sso login`, or `gcloud auth login`?"
+                    .to_string()
+            }
+            _ => return None,
+        };
+
+        Some(hint)
+    }
+}
+
+/// The hint for errors from the database that don't come with one of their
+/// own, which are instead shown as part of the error.
+fn database_hint(err: &postgres::Error) -> Option<&'static str> {
+    let db = match err.as_db_error() {
+        Some(db) => db,
+        None => {
+            let connecting = std::error::Error::source(err).is_some_and(|cause| cause.is::<io::Error>());
+
+            return (connecting || err.is_closed()).then_some(CONNECTION_HINT);
+        }
+    };
+
+    if db.hint().is_some() {
+        return None;
+    }
+
+    match db.code() {
+        &SqlState::INVALID_PASSWORD | &SqlState::INVALID_AUTHORIZATION_SPECIFICATION => {
+            Some("check the user and password in the environment's `url`, or its `password` or `password_ref`")
+        }
+        &SqlState::INVALID_CATALOG_NAME => Some("does the database exist? It can be created with `jrny bootstrap --create-db`"),
+        &SqlState::INSUFFICIENT_PRIVILEGE => {
+            Some("does the role connecting own what the revision changes, or have privileges granted on it?")
+        }
+        _ => None,
     }
 }
 
@@ -136,3 +875,51 @@ impl From<io::Error> for Error {
         Self::IoError(e)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_give_causes_not_already_mentioned_and_hints() {
+        let missing = Error::CommandFailed("aws".to_string(), io::ErrorKind::NotFound.into());
+
+        assert_eq!(missing.report(), "Could not run `aws`: entity not found\nhint: is `aws` installed and on the `PATH`?");
+
+        let toml = toml::from_str::<toml::Value>("name = ").unwrap_err();
+        let invalid = Error::TomlInvalid(toml, "jrny.toml".to_string());
+
+        assert!(!invalid.report().contains("caused by"));
+        assert_eq!(invalid.hint(), None);
+
+        #[derive(Debug)]
+        struct Unreadable(io::Error);
+
+        impl fmt::Display for Unreadable {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "could not read the bundle")
+            }
+        }
+
+        impl std::error::Error for Unreadable {
+            fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+                Some(&self.0)
+            }
+        }
+
+        let unreadable = Error::IoError(io::Error::other(Unreadable(io::ErrorKind::PermissionDenied.into())));
+
+        assert_eq!(unreadable.report(), "could not read the bundle\n  caused by: permission denied");
+
+        let denied = Error::IoError(io::ErrorKind::PermissionDenied.into());
+
+        assert_eq!(denied.report(), "permission denied\nhint: can the user running jrny read and write the project's files?");
+    }
+
+    #[test]
+    fn ids_are_variant_names_in_kebab_case() {
+        assert_eq!(Error::EnvNotFound.id(), "env-not-found");
+        assert_eq!(Error::RevisionsPending(1).id(), "revisions-pending");
+        assert_eq!(Error::IoError(io::ErrorKind::NotFound.into()).id(), "io-error");
+    }
+}
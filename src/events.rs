@@ -0,0 +1,100 @@
+//! Newline-delimited JSON lifecycle events written to standard output as
+//! an embark progresses, for orchestration tools and live dashboards.
+use std::{
+    io::{self, Write},
+    time::Duration,
+};
+
+use chrono::Utc;
+
+use crate::{
+    json::Json,
+    pgtap::TapOutcome,
+    report::{RevisionOutcome, RevisionStatus, RunReport, StatementOutcome},
+};
+
+/// Emits events if enabled, otherwise does nothing, so that callers
+/// don't need to check.
+#[derive(Clone, Copy, Debug)]
+pub struct EventStream {
+    enabled: bool,
+}
+
+impl EventStream {
+    pub fn new(enabled: bool) -> Self {
+        Self { enabled }
+    }
+
+    pub fn run_start(&self, pending: usize) {
+        self.emit("run-start", vec![("pending", Json::from(pending))]);
+    }
+
+    pub fn revision_start(&self, filename: &str, statements: usize) {
+        self.emit("revision-start", vec![
+            ("revision", Json::from(filename)),
+            ("statements", Json::from(statements)),
+        ]);
+    }
+
+    pub fn statement_done(&self, filename: &str, statement: &StatementOutcome) {
+        self.emit("statement-done", vec![
+            ("revision", Json::from(filename)),
+            ("line", Json::from(statement.line)),
+            ("statement", Json::from(statement.summary.as_str())),
+            ("seconds", seconds(statement.elapsed)),
+            ("rows", Json::from(statement.rows.map(|rows| rows as i64))),
+            ("sqlstate", Json::from(statement.error.as_ref().and_then(|e| e.code.clone()))),
+            ("error", Json::from(statement.error.as_ref().map(|e| e.message.clone()))),
+        ]);
+    }
+
+    pub fn revision_done(&self, revision: &RevisionOutcome) {
+        self.emit("revision-done", vec![
+            ("revision", Json::from(revision.filename.as_str())),
+            ("status", Json::from(revision.status.as_str())),
+            ("seconds", seconds(revision.elapsed)),
+        ]);
+    }
+
+    pub fn run_done(&self, report: &RunReport) {
+        self.emit("run-done", vec![
+            ("success", Json::from(report.error.is_none())),
+            ("applied", Json::from(report.count(RevisionStatus::Applied))),
+            ("skipped", Json::from(report.count(RevisionStatus::Skipped))),
+            ("failed", Json::from(report.count(RevisionStatus::Failed))),
+            ("seconds", seconds(report.elapsed)),
+            ("error", Json::from(report.error.clone())),
+            ("error_code", Json::from(report.error_code)),
+        ]);
+    }
+
+    /// Once the pgTAP files run after the revisions are done, should there be any.
+    pub fn tests_done(&self, tests: &[TapOutcome]) {
+        self.emit("tests-done", vec![
+            ("files", Json::from(tests.len())),
+            ("passed", Json::from(tests.iter().filter(|t| t.is_ok()).count())),
+            ("failed", Json::from(tests.iter().filter(|t| !t.is_ok()).count())),
+        ]);
+    }
+
+    fn emit(&self, event: &str, fields: Vec<(&str, Json)>) {
+        if !self.enabled {
+            return;
+        }
+
+        let mut pairs = vec![
+            ("event", Json::from(event)),
+            ("timestamp", Json::from(Utc::now().to_rfc3339())),
+        ];
+        pairs.extend(fields);
+
+        // Flush per event so consumers see each as it happens, even when piped
+        let mut stdout = io::stdout().lock();
+        let _ = writeln!(stdout, "{}", Json::object(pairs));
+        let _ = stdout.flush();
+    }
+}
+
+fn seconds(d: Duration) -> Json {
+    Json::from(d.as_secs_f64())
+}
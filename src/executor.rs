@@ -1,12 +1,46 @@
-use std::convert::TryFrom;
+use std::{
+    convert::TryFrom,
+    fs,
+    io::Write,
+    thread,
+    time::{Duration, Instant},
+};
 
+use chrono::{DateTime, SecondsFormat, Utc};
 use log::info;
-use postgres::Client;
+use postgres::{error::SqlState, types::ToSql, Client, GenericClient, SimpleQueryMessage};
 
 use crate::{
-    revisions::{AnnotatedRevision, RevisionRecord},
+    analysis::{Dependent, Destroyed, Dropped, Impact, TableSize},
+    assertions::{self, AssertionFile},
+    batch::{Batch, Batched},
+    environment::BackupSettings,
+    fixtures::{Fixture, FixtureKind},
+    git::Provenance,
+    gzip,
+    import::{flyway_version_key, History, MigrationTool},
+    ownership::{DatabaseObject, OBJECTS},
+    pacing::{self, PacingSettings, LAG_INTERVAL},
+    paths,
+    pg_version::PgVersion,
+    prepared::{self, PreparedTransaction, TransactionId},
+    report::RevisionOutcome,
+    retry::LockRetry,
+    revisions::{
+        AnnotatedRepeatable,
+        AnnotatedRevision,
+        ReappliedKind,
+        RepeatableFile,
+        RepeatableRecord,
+        RevisionFile,
+        RevisionRecord,
+    },
+    rewrite,
+    statements::{self, Statement},
+    validation,
     Config,
     Environment,
+    Error,
     Result,
 };
 
@@ -21,7 +55,134 @@ CREATE TABLE $$schema$$.$$table$$ (
     applied_on  TIMESTAMPTZ  NOT NULL,
     filename    TEXT         NOT NULL UNIQUE,
     name        TEXT         NOT NULL,
-    checksum    TEXT         NOT NULL
+    checksum    TEXT         NOT NULL,
+    git_commit  TEXT,
+    git_branch  TEXT,
+    git_author  TEXT,
+    applied_by  TEXT,
+    duration_ms BIGINT,
+    release_group TEXT,
+    impact      TEXT,
+    contents    BYTEA
+)";
+
+/// The columns each tracking table must have, for checking existing tables.
+const REVISION_COLUMNS: &[&str] = &[
+    "id",
+    "created_at",
+    "applied_on",
+    "filename",
+    "name",
+    "checksum",
+    "git_commit",
+    "git_branch",
+    "git_author",
+    "applied_by",
+    "duration_ms",
+    "release_group",
+    "impact",
+    "contents",
+];
+const REAPPLIED_COLUMNS: &[&str] = &["filename", "applied_on", "checksum", "applied_by", "duration_ms"];
+
+/// Changes made to an existing tracking table to bring it up to a version,
+/// each of which can be made again should it already have been.
+struct Upgrade {
+    version: i32,
+    /// The columns added to the table tracking revisions, by which the
+    /// version of tables from before versions were recorded is judged
+    columns: &'static [&'static str],
+    /// How the table tracking revisions is altered, if at all
+    revisions: Option<&'static str>,
+    /// How the tables tracking repeatable revisions and seeds are altered
+    reapplied: Option<&'static str>,
+}
+
+/// Every change to the tracking tables since their first version, in order.
+/// New tables are created as of the latest, and older ones are upgraded
+/// through each version after their own.
+const UPGRADES: &[Upgrade] = &[
+    Upgrade {
+        version: 2,
+        columns: &["git_commit", "git_branch", "git_author"],
+        revisions: Some(
+            "ADD COLUMN IF NOT EXISTS git_commit TEXT, \
+             ADD COLUMN IF NOT EXISTS git_branch TEXT, \
+             ADD COLUMN IF NOT EXISTS git_author TEXT",
+        ),
+        reapplied: None,
+    },
+    Upgrade {
+        version: 3,
+        columns: &["applied_by", "duration_ms"],
+        revisions: Some("ADD COLUMN IF NOT EXISTS applied_by TEXT, ADD COLUMN IF NOT EXISTS duration_ms BIGINT"),
+        reapplied: Some("ADD COLUMN IF NOT EXISTS applied_by TEXT, ADD COLUMN IF NOT EXISTS duration_ms BIGINT"),
+    },
+    Upgrade {
+        version: 4,
+        columns: &["release_group"],
+        revisions: Some("ADD COLUMN IF NOT EXISTS release_group TEXT"),
+        reapplied: None,
+    },
+    Upgrade {
+        version: 5,
+        columns: &["impact"],
+        revisions: Some("ADD COLUMN IF NOT EXISTS impact TEXT"),
+        reapplied: None,
+    },
+    Upgrade {
+        version: 6,
+        columns: &["contents"],
+        revisions: Some("ADD COLUMN IF NOT EXISTS contents BYTEA"),
+        reapplied: None,
+    },
+];
+
+const CURRENT_VERSION: i32 = UPGRADES[UPGRADES.len() - 1].version;
+
+// Tables from before versions were recorded are at whichever version their
+// columns match
+const CREATE_VERSION_TABLE: &str = "
+CREATE TABLE $$schema$$.$$table$$_version (
+    version     INT          NOT NULL
+)";
+
+const SELECT_VERSION: &str = "
+SELECT max(version) AS version FROM $$schema$$.$$table$$_version
+";
+
+const DELETE_VERSION: &str = "
+DELETE FROM $$schema$$.$$table$$_version
+";
+
+const INSERT_VERSION: &str = "
+INSERT INTO $$schema$$.$$table$$_version (version) VALUES ($1)
+";
+
+const ALTER_TABLE: &str = "
+ALTER TABLE $$schema$$.$$table$$ $$changes$$
+";
+
+// Held until the upgrade commits, so a concurrent run can't use the tables
+// half-upgraded or upgrade them a second time
+const LOCK_TABLE: &str = "
+LOCK TABLE $$schema$$.$$table$$ IN ACCESS EXCLUSIVE MODE
+";
+
+// Columns already added, eg. to tables patched by hand, are only noticed
+const QUIET_UPGRADE: &str = "
+SET LOCAL client_min_messages = warning
+";
+
+// Repeatable revisions and seeds have no id and are re-applied in place, so
+// they are tracked separately by filename with only their latest application.
+const CREATE_REAPPLIED_TABLE: &str = "
+CREATE TABLE $$schema$$.$$table$$ (
+    filename    TEXT         PRIMARY KEY,
+    applied_on  TIMESTAMPTZ  NOT NULL,
+    checksum    TEXT         NOT NULL,
+    applied_by  TEXT,
+    duration_ms BIGINT
 )";
 
 const TABLE_EXISTS: &str = "
@@ -30,6 +191,21 @@ SELECT EXISTS (
    WHERE schemaname = $1 AND tablename  = $2
 )";
 
+const SELECT_COLUMNS: &str = "
+SELECT column_name::TEXT AS column_name
+FROM information_schema.columns
+WHERE table_schema = $1 AND table_name = $2
+";
+
+// Keyed by the tracking table, so projects sharing a database don't contend
+const TRY_ADVISORY_LOCK: &str = "
+SELECT pg_try_advisory_lock(hashtext($1)) AS locked
+";
+
+const ADVISORY_LOCK: &str = "
+SELECT pg_advisory_lock(hashtext($1))
+";
+
 const SCHEMA_EXISTS: &str = "
 SELECT EXISTS (
     SELECT FROM information_schema.schemata
@@ -43,12 +219,55 @@ SELECT
     checksum,
     created_at,
     filename,
-    name
+    name,
+    git_commit,
+    git_branch,
+    git_author,
+    release_group,
+    impact,
+    contents
 FROM $$schema$$.$$table$$
 ORDER BY id ASC
 ";
 
+// Claims the revision before its statements run, within the same transaction,
+// so that a concurrent run claiming it too waits on the uncommitted row and
+// then finds it taken rather than recording it twice
 const INSERT_REVISION: &str = "
+INSERT INTO $$schema$$.$$table$$ (
+    applied_on,
+    id,
+    created_at,
+    checksum,
+    filename,
+    name,
+    git_commit,
+    git_branch,
+    git_author,
+    release_group,
+    impact,
+    contents,
+    applied_by
+) VALUES (clock_timestamp(), $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, current_user)
+ON CONFLICT DO NOTHING
+";
+
+// Once claimed, the revision is only applied when its statements finish
+const UPDATE_REVISION_APPLIED: &str = "
+UPDATE $$schema$$.$$table$$ SET applied_on = clock_timestamp(), duration_ms = $2 WHERE id = $1
+";
+
+// Accepting changes to an applied revision only changes what it's checked against
+const UPDATE_REVISION_CHECKSUM: &str = "
+UPDATE $$schema$$.$$table$$ SET checksum = $2 WHERE filename = $1
+";
+
+const DELETE_REVISION: &str = "
+DELETE FROM $$schema$$.$$table$$ WHERE filename = $1
+";
+
+// Imported revisions keep when they were applied by the other tool, if known
+const INSERT_IMPORTED_REVISION: &str = "
 INSERT INTO $$schema$$.$$table$$ (
     applied_on,
     id,
@@ -56,13 +275,360 @@ INSERT INTO $$schema$$.$$table$$ (
     checksum,
     filename,
     name
-) VALUES (clock_timestamp(), $1, $2, $3, $4, $5)
+) VALUES (COALESCE($1, clock_timestamp()), $2, $3, $4, $5, $6)
+";
+
+// Bundled revisions keep everything recorded where they were exported from
+const INSERT_BUNDLED_REVISION: &str = "
+INSERT INTO $$schema$$.$$table$$ (
+    applied_on,
+    id,
+    created_at,
+    checksum,
+    filename,
+    name,
+    git_commit,
+    git_branch,
+    git_author,
+    release_group,
+    impact,
+    contents
+) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
+";
+
+const SELECT_REAPPLIED: &str = "
+SELECT
+    filename,
+    applied_on,
+    checksum
+FROM $$schema$$.$$table$$
+ORDER BY filename ASC
+";
+
+const INSERT_IMPORTED_REAPPLIED: &str = "
+INSERT INTO $$schema$$.$$table$$ (
+    applied_on,
+    checksum,
+    filename
+) VALUES (COALESCE($1, clock_timestamp()), $2, $3)
+ON CONFLICT (filename) DO NOTHING
+";
+
+const RELATION_EXISTS: &str = "
+SELECT to_regclass($1) IS NOT NULL AS exists
+";
+
+const SELECT_FLYWAY_HISTORY: &str = "
+SELECT version, script, type, installed_on::TIMESTAMPTZ AS installed_on
+FROM $$table$$
+WHERE success
+ORDER BY installed_rank ASC
+";
+
+const SELECT_SQITCH_HISTORY: &str = "
+SELECT change, committed_at
+FROM $$table$$
+ORDER BY committed_at ASC
+";
+
+const SELECT_GOLANG_MIGRATE_HISTORY: &str = "
+SELECT version, dirty
+FROM $$table$$
+LIMIT 1
+";
+
+// Estimates are negative for tables that have never been analyzed
+const TABLE_SIZE: &str = "
+SELECT
+    CASE WHEN c.reltuples < 0 THEN NULL ELSE c.reltuples::BIGINT END AS rows,
+    pg_total_relation_size(c.oid) AS bytes
+FROM pg_class c
+WHERE c.oid = to_regclass($1)
+";
+
+// Whatever depends on the object found by `$$target$$`, other than what's
+// dropped along with it anyway, such as its indexes, or everything in a
+// schema. Views are found by their rewrite rules, and whatever depends on
+// each dependent breaks too.
+const DEPENDENTS: &str = "
+WITH RECURSIVE target(classid, objid, objsubid) AS (
+    $$target$$
+),
+owned(classid, objid, objsubid) AS (
+    SELECT classid, objid, objsubid FROM target WHERE objid IS NOT NULL
+    UNION
+    SELECT d.classid, d.objid, d.objsubid
+    FROM pg_depend d
+    JOIN owned o ON d.refclassid = o.classid AND d.refobjid = o.objid AND (o.objsubid = 0 OR d.refobjsubid = o.objsubid)
+    WHERE d.deptype IN ('a', 'i') OR o.classid = 'pg_namespace'::regclass
+),
+dependents(classid, objid) AS (
+    SELECT CASE WHEN r.oid IS NULL THEN d.classid ELSE 'pg_class'::regclass::oid END, coalesce(r.ev_class, d.objid)
+    FROM pg_depend d
+    JOIN owned o ON d.refclassid = o.classid AND d.refobjid = o.objid AND (o.objsubid = 0 OR d.refobjsubid = o.objsubid)
+    LEFT JOIN pg_rewrite r ON d.classid = 'pg_rewrite'::regclass AND r.oid = d.objid
+    WHERE d.deptype = 'n'
+    UNION
+    SELECT CASE WHEN r.oid IS NULL THEN d.classid ELSE 'pg_class'::regclass::oid END, coalesce(r.ev_class, d.objid)
+    FROM pg_depend d
+    JOIN dependents b ON d.refclassid = b.classid AND d.refobjid = b.objid
+    LEFT JOIN pg_rewrite r ON d.classid = 'pg_rewrite'::regclass AND r.oid = d.objid
+    WHERE d.deptype = 'n'
+)
+SELECT classid || ':' || objid AS key, pg_describe_object(classid, objid, 0) AS description, true AS dropped
+FROM target
+WHERE objid IS NOT NULL
+UNION ALL
+SELECT DISTINCT b.classid || ':' || b.objid, pg_describe_object(b.classid, b.objid, 0), false
+FROM dependents b
+WHERE NOT EXISTS (SELECT 1 FROM owned o WHERE o.classid = b.classid AND o.objid = b.objid)
+ORDER BY dropped DESC, description
+";
+
+const COPY_FIXTURE: &str = "
+COPY $$table$$ ($$columns$$) FROM STDIN WITH (FORMAT csv, HEADER true)
+";
+
+// Where tables were backed up to before revisions destroyed data in them,
+// for finding them again in an emergency
+const CREATE_BACKUP_TABLE: &str = "
+CREATE TABLE $$schema$$.$$table$$_backup (
+    revision     TEXT         NOT NULL,
+    source       TEXT         NOT NULL,
+    location     TEXT         NOT NULL,
+    backed_up_at TIMESTAMPTZ  NOT NULL
+)";
+
+const INSERT_BACKUP: &str = "
+INSERT INTO $$schema$$.$$table$$_backup (
+    revision,
+    source,
+    location,
+    backed_up_at
+) VALUES ($1, $2, $3, clock_timestamp())
+";
+
+// Every statement executed by embarking, for a queryable audit trail
+const CREATE_STATEMENT_TABLE: &str = "
+CREATE TABLE $$schema$$.$$table$$_statement (
+    revision      TEXT         NOT NULL,
+    status        TEXT         NOT NULL,
+    line          INT          NOT NULL,
+    checksum      TEXT         NOT NULL,
+    summary       TEXT         NOT NULL,
+    started_at    TIMESTAMPTZ  NOT NULL,
+    duration_ms   BIGINT       NOT NULL,
+    rows_affected BIGINT,
+    sqlstate      TEXT,
+    error         TEXT,
+    applied_by    TEXT         NOT NULL DEFAULT current_user
+)";
+
+const INSERT_STATEMENT: &str = "
+INSERT INTO $$schema$$.$$table$$_statement (
+    revision,
+    status,
+    line,
+    checksum,
+    summary,
+    started_at,
+    duration_ms,
+    rows_affected,
+    sqlstate,
+    error
+) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+";
+
+// Resolves the name as written against the search path, so that it can be
+// backed up under the same name however it was written
+const QUALIFIED_TABLE: &str = "
+SELECT c.oid::regclass::TEXT AS name
+FROM pg_class c
+JOIN pg_namespace n ON n.oid = c.relnamespace
+WHERE c.oid = to_regclass($1) AND c.relkind IN ('r', 'p')
+";
+
+const SCHEMA_TABLES: &str = "
+SELECT quote_ident(schemaname) || '.' || quote_ident(tablename) AS name
+FROM pg_tables
+WHERE schemaname = $1
+ORDER BY tablename ASC
+";
+
+const BACKUP_TABLE: &str = "
+CREATE TABLE $$backup$$ AS TABLE $$source$$
+";
+
+const COPY_TABLE: &str = "
+COPY $$source$$ TO STDOUT WITH (FORMAT csv, HEADER true)
+";
+
+// As with revisions, claims the file before it's applied, though only while it
+// was last applied when expected, ie. not by another run in the meantime
+const UPSERT_REAPPLIED: &str = "
+INSERT INTO $$schema$$.$$table$$ AS reapplied (
+    applied_on,
+    checksum,
+    filename,
+    applied_by
+) VALUES (clock_timestamp(), $1, $2, current_user)
+ON CONFLICT (filename) DO UPDATE SET
+    applied_on = excluded.applied_on,
+    checksum = excluded.checksum,
+    applied_by = excluded.applied_by
+WHERE reapplied.applied_on IS NOT DISTINCT FROM $3
+";
+
+const UPDATE_REAPPLIED_APPLIED: &str = "
+UPDATE $$schema$$.$$table$$ SET applied_on = clock_timestamp(), duration_ms = $2 WHERE filename = $1
+";
+
+// Revisions with a maximum duration have each statement limited to the time
+// they have left
+const SET_STATEMENT_TIMEOUT: &str = "
+SET statement_timeout = $$millis$$
+";
+
+const SET_LOCAL_STATEMENT_TIMEOUT: &str = "
+SET LOCAL statement_timeout = $$millis$$
+";
+
+const RESET_STATEMENT_TIMEOUT: &str = "
+RESET statement_timeout
+";
+
+// Within a release group's transaction, the next revision may have no limit
+const RESET_LOCAL_STATEMENT_TIMEOUT: &str = "
+SET LOCAL statement_timeout TO DEFAULT
+";
+
+const SET_LOCK_TIMEOUT: &str = "
+SET lock_timeout = $$millis$$
+";
+
+const SET_LOCAL_LOCK_TIMEOUT: &str = "
+SET LOCAL lock_timeout = $$millis$$
+";
+
+const RESET_LOCK_TIMEOUT: &str = "
+RESET lock_timeout
+";
+
+const RESET_LOCAL_LOCK_TIMEOUT: &str = "
+SET LOCAL lock_timeout TO DEFAULT
+";
+
+// Statements that may fail without failing their revision, by being retried
+// or as what they create already exists, are each run from a savepoint within
+// a transaction, so that it can carry on afterwards
+const STATEMENT_SAVEPOINT: &str = "
+SAVEPOINT jrny_statement
+";
+
+const ROLLBACK_TO_STATEMENT_SAVEPOINT: &str = "
+ROLLBACK TO SAVEPOINT jrny_statement
+";
+
+const RELEASE_STATEMENT_SAVEPOINT: &str = "
+RELEASE SAVEPOINT jrny_statement
+";
+
+// Release groups are applied in a transaction spanning their revisions, so
+// one can't be borrowed from the client for as long
+const BEGIN: &str = "
+BEGIN
+";
+
+const COMMIT: &str = "
+COMMIT
+";
+
+const ANALYZE: &str = "
+ANALYZE $$table$$
+";
+
+const BACKEND_PID: &str = "
+SELECT pg_backend_pid() AS pid
+";
+
+const EXTENSIONS: &str = "
+SELECT extname FROM pg_extension
+";
+
+const SERVER_VERSION: &str = "
+SELECT current_setting('server_version_num')::int AS version
+";
+
+const ROLLBACK: &str = "
+ROLLBACK
+";
+
+// Emitted SQL has no client to notice a file couldn't be claimed, so the claim
+// fails outright instead
+const ENSURE_CLAIMED: &str = "
+WITH claimed AS ($$claim$$ RETURNING 1)
+SELECT 1 / count(*) AS claimed FROM claimed
 ";
 
+// Claims are made when files start being applied, so emitted SQL can work out
+// how long they took even without a client timing them
+const ELAPSED_MILLIS: &str = "(extract(epoch FROM clock_timestamp() - applied_on) * 1000)::BIGINT";
+
+/// Hooks for observing the execution of individual statements.
+pub trait StatementObserver {
+    fn before(&mut self, _statement: &Statement) {}
+    /// Called after the statement succeeds with the number of rows it affected
+    fn after(&mut self, _statement: &Statement, _elapsed: Duration, _rows: u64) {}
+    /// Called after each batch of a batched statement with the totals so far
+    fn batch(&mut self, _statement: &Statement, _batches: usize, _rows: u64) {}
+    /// Called before `after` with the plan of a statement that was explained
+    fn plan(&mut self, _statement: &Statement, _plan: &str) {}
+    /// Called when an attempt at a statement fails to take a lock in time,
+    /// before pausing to try it again
+    fn retrying(&mut self, _statement: &Statement, _attempt: u32, _wait: Duration) {}
+    /// Called when a statement is cancelled by one of the revision's
+    /// escalating timeouts, before trying it again with the next
+    fn timed_out(&mut self, _statement: &Statement, _timeout: Duration, _next: Duration) {}
+    /// Called when what a statement creates already exists, before `after`,
+    /// in revisions marked idempotent
+    fn existing(&mut self, _statement: &Statement, _error: &postgres::Error) {}
+    /// Called when replicas lag further behind than allowed, before pausing
+    /// until they catch up
+    fn lagging(&mut self, _lag: Duration, _max: Duration) {}
+    /// Called once replicas have caught up after pausing for them
+    fn caught_up(&mut self, _waited: Duration) {}
+    fn failed(&mut self, _statement: &Statement, _elapsed: Duration, _error: &postgres::Error) {}
+}
+
+/// Totals the rows affected by the statements it observes.
+#[derive(Default)]
+struct RowCounter {
+    rows: u64,
+}
+
+impl StatementObserver for RowCounter {
+    fn after(&mut self, _statement: &Statement, _elapsed: Duration, rows: u64) {
+        self.rows += rows;
+    }
+}
+
 pub struct Executor {
     client: Client,
     schema: String,
     table: String,
+    /// Whether a release group's transaction is open, which revisions are
+    /// then applied within rather than in their own
+    in_group: bool,
+    /// Whether statements creating what already exists succeed regardless
+    tolerate_existing: bool,
+    /// Statement timeouts to try each statement with in turn
+    timeouts: Vec<Duration>,
+    /// How long to pause between statements and revisions
+    pacing: PacingSettings,
+    /// Whether the contents of revisions are archived as they're applied
+    archive: bool,
+    /// Assertions checked once the next revision's statements have run
+    assertions: Option<AssertionFile>,
 }
 
 impl Executor {
@@ -73,6 +639,12 @@ impl Executor {
             client,
             schema: config.table.schema.clone(),
             table: config.table.name.clone(),
+            in_group: false,
+            tolerate_existing: false,
+            timeouts: vec![],
+            pacing: PacingSettings::default(),
+            archive: config.table.archive,
+            assertions: None,
         })
     }
 
@@ -80,8 +652,109 @@ impl Executor {
         if !self.schema_exists()? {
             self.create_schema()?;
         }
-        if !self.table_exists()? {
+        let (table, repeatable_table) = (self.table.clone(), self.reapplied_table(ReappliedKind::Repeatable));
+
+        if !self.table_exists(&table)? {
             self.create_table()?;
+        } else {
+            self.upgrade_tables()?;
+            self.check_columns(&table, REVISION_COLUMNS)?;
+        }
+        if !self.table_exists(&repeatable_table)? {
+            self.create_reapplied_table(ReappliedKind::Repeatable)?;
+        } else {
+            self.check_columns(&repeatable_table, REAPPLIED_COLUMNS)?;
+        }
+
+        Ok(())
+    }
+
+    /// Whether the table tracking revisions exists yet, without creating it.
+    pub fn tracking_table_exists(&mut self) -> Result<bool> {
+        let table = self.table.clone();
+
+        self.table_exists(&table)
+    }
+
+    /// Attempts to take the lock held while applying revisions, without
+    /// waiting, returning whether it was taken. The lock is held for as long
+    /// as the executor is connected.
+    pub fn try_lock(&mut self) -> Result<bool> {
+        let row = self.client.query_one(TRY_ADVISORY_LOCK, &[&self.lock_key()])?;
+
+        Ok(row.get("locked"))
+    }
+
+    /// As with `try_lock`, but waits for the lock for as long as it takes.
+    pub fn lock(&mut self) -> Result<()> {
+        self.client.execute(ADVISORY_LOCK, &[&self.lock_key()])?;
+
+        Ok(())
+    }
+
+    /// The SQL that `ensure_table_exists` would execute to create whichever
+    /// tracking tables don't exist yet, if any, for running by hand instead.
+    /// Tables needing an upgrade fail, as only jrny itself upgrades them.
+    pub fn tracking_tables_sql(&mut self) -> Result<Option<String>> {
+        let (schema, table) = (self.schema.clone(), self.table.clone());
+        let repeatable_table = self.reapplied_table(ReappliedKind::Repeatable);
+        let replace = |stmt: &str, table: &str| terminated(&stmt.replace("$$schema$$", &schema).replace("$$table$$", table));
+        let mut sql = String::new();
+
+        if !self.schema_exists()? {
+            sql.push_str(&replace(CREATE_SCHEMA, &table));
+        }
+
+        if !self.table_exists(&table)? {
+            sql.push_str(&terminated(BEGIN));
+            sql.push_str(&replace(CREATE_TABLE, &table));
+            sql.push_str(&replace(CREATE_VERSION_TABLE, &table));
+            sql.push_str(&replace(&INSERT_VERSION.replace("$1", &CURRENT_VERSION.to_string()), &table));
+            sql.push_str(&terminated(COMMIT));
+        } else {
+            let version = tables_version(&mut self.client, &schema, &table)?;
+
+            if version > CURRENT_VERSION {
+                return Err(Error::TrackingTableNewer(format!("{}.{}", schema, table), version));
+            }
+            if version < CURRENT_VERSION {
+                return Err(Error::SqlNotEmittable(format!(
+                    "the tracking tables are at version {} and must first be upgraded to {}, eg. by `jrny review`",
+                    version, CURRENT_VERSION,
+                )));
+            }
+            self.check_columns(&table, REVISION_COLUMNS)?;
+        }
+
+        if !self.table_exists(&repeatable_table)? {
+            sql.push_str(&replace(CREATE_REAPPLIED_TABLE, &repeatable_table));
+        } else {
+            self.check_columns(&repeatable_table, REAPPLIED_COLUMNS)?;
+        }
+
+        Ok(Some(sql).filter(|sql| !sql.is_empty()))
+    }
+
+    /// Whether the table tracking the given kind of repeatable revision
+    /// exists yet, without creating it.
+    pub fn reapplied_table_exists(&mut self, kind: ReappliedKind) -> Result<bool> {
+        let table = self.reapplied_table(kind);
+
+        self.table_exists(&table)
+    }
+
+    /// The SQL taking the lock that `lock` does, for running by hand instead.
+    pub fn lock_sql(&self) -> String {
+        terminated(&ADVISORY_LOCK.replace("$1", &literal(Some(&self.lock_key()))))
+    }
+
+    /// As with `ensure_table_exists` but for the table tracking seeds, which
+    /// is only created once seeds are first used.
+    pub fn ensure_seed_table_exists(&mut self) -> Result<()> {
+        self.ensure_table_exists()?;
+
+        if !self.table_exists(&self.reapplied_table(ReappliedKind::Seed))? {
+            self.create_reapplied_table(ReappliedKind::Seed)?;
         }
 
         Ok(())
@@ -103,67 +776,1611 @@ impl Executor {
                 checksum: r.get("checksum"),
                 filename: r.get("filename"),
                 name: r.get("name"),
+                provenance: Provenance {
+                    commit: r.get("git_commit"),
+                    branch: r.get("git_branch"),
+                    author: r.get("git_author"),
+                },
+                release_group: r.get("release_group"),
+                impact: r.get("impact"),
+                archived: r
+                    .get::<_, Option<Vec<u8>>>("contents")
+                    .and_then(|contents| gzip::decompress(&contents))
+                    .and_then(|contents| String::from_utf8(contents).ok()),
             })
             .collect();
 
         Ok(revisions)
     }
 
-    pub fn run_revision(&mut self, revision: &AnnotatedRevision) -> Result<()> {
-        let insert_revision = INSERT_REVISION
+    pub fn load_reapplied(&mut self, kind: ReappliedKind) -> Result<Vec<RepeatableRecord>> {
+        let stmt = SELECT_REAPPLIED
             .replace("$$schema$$", &self.schema)
-            .replace("$$table$$", &self.table);
+            .replace("$$table$$", &self.reapplied_table(kind));
 
-        let statements = revision
-            .contents
-            .as_ref()
-            .expect(format!("No content for {}", revision.filename).as_str());
+        let rows = self.client.query(stmt.as_str(), &[])?;
 
-        let _ = self.client.batch_execute(statements.as_str())?;
+        let repeatables = rows
+            .iter()
+            .map(|r| RepeatableRecord {
+                applied_on: r.get("applied_on"),
+                checksum: r.get("checksum"),
+                filename: paths::normalize(r.get("filename")),
+            })
+            .collect();
 
-        let _ = self.client.execute(
-            insert_revision.as_str(),
-            &[
-                &revision.id,
-                &revision.created_at,
-                &revision.checksum,
-                &revision.filename,
-                &revision.name,
-            ],
-        )?;
+        Ok(repeatables)
+    }
 
-        Ok(())
+    /// The estimated size of the table, given as it would be written in SQL,
+    /// or nothing if there's no such table, eg. as it's yet to be created.
+    pub fn table_size(&mut self, table: &str) -> Result<Option<TableSize>> {
+        let row = self.client.query_opt(TABLE_SIZE, &[&table])?;
+
+        Ok(row.map(|r| TableSize {
+            rows: r.get("rows"),
+            bytes: r.get("bytes"),
+        }))
     }
 
-    fn table_exists(&mut self) -> Result<bool> {
-        let row = self.client.query_one(TABLE_EXISTS, &[&self.schema, &self.table])?;
+    /// The key of the object the statement drops, along with the objects that
+    /// depend on it, or nothing if there's no such object, eg. as it's yet to
+    /// be created. Functions are found by name alone, so overloaded functions
+    /// are skipped, as are kinds of object others can't depend on.
+    pub fn dependents(&mut self, dropped: &Dropped) -> Result<Option<(String, Vec<Dependent>)>> {
+        let target = match (dropped.object.as_str(), &dropped.part) {
+            ("column", Some(_)) => {
+                "SELECT 'pg_class'::regclass::oid, a.attrelid, a.attnum::INT FROM pg_attribute a \
+                 WHERE a.attrelid = to_regclass($1) AND a.attname = $2 AND NOT a.attisdropped"
+            }
+            ("constraint", Some(_)) => {
+                "SELECT 'pg_constraint'::regclass::oid, c.oid, 0 FROM pg_constraint c \
+                 WHERE c.conrelid = to_regclass($1) AND c.conname = $2"
+            }
+            ("table" | "view" | "materialized view" | "foreign table" | "sequence" | "index", None) => {
+                "SELECT 'pg_class'::regclass::oid, to_regclass($1)::oid, 0 WHERE $2::TEXT IS NULL"
+            }
+            ("function" | "procedure", None) => {
+                "SELECT 'pg_proc'::regclass::oid, to_regproc($1)::oid, 0 WHERE $2::TEXT IS NULL"
+            }
+            ("type" | "domain", None) => "SELECT 'pg_type'::regclass::oid, to_regtype($1)::oid, 0 WHERE $2::TEXT IS NULL",
+            ("schema", None) => "SELECT 'pg_namespace'::regclass::oid, to_regnamespace($1)::oid, 0 WHERE $2::TEXT IS NULL",
+            _ => return Ok(None),
+        };
 
-        Ok(row.get("exists"))
+        // Unquoted names are folded to lowercase, as PostgreSQL does
+        let part = dropped.part.as_ref().map(|part| match part.strip_prefix('"').and_then(|p| p.strip_suffix('"')) {
+            Some(quoted) => quoted.to_string(),
+            None => part.to_lowercase(),
+        });
+
+        let rows = self.client.query(DEPENDENTS.replace("$$target$$", target).as_str(), &[&dropped.name, &part])?;
+        let (targets, dependents): (Vec<_>, Vec<_>) = rows.iter().partition(|r| r.get::<_, bool>("dropped"));
+
+        Ok(targets.first().map(|target| {
+            let dependents = dependents
+                .iter()
+                .map(|r| Dependent {
+                    key: r.get("key"),
+                    description: r.get("description"),
+                })
+                .collect();
+
+            (target.get("key"), dependents)
+        }))
     }
 
-    fn schema_exists(&mut self) -> Result<bool> {
-        let row = self.client.query_one(SCHEMA_EXISTS, &[&self.schema])?;
+    /// Copies the tables whose data is about to be destroyed into the backup
+    /// schema or directory, recording where each went, and returning the
+    /// tables and their backups. Tables that don't exist yet are skipped, eg.
+    /// as they're created by the revision itself.
+    pub fn back_up(
+        &mut self,
+        revision: &str,
+        destroyed: &[Destroyed],
+        settings: &BackupSettings,
+    ) -> Result<Vec<(String, String)>> {
+        let mut sources: Vec<String> = vec![];
 
-        Ok(row.get("exists"))
+        for destroyed in destroyed {
+            match destroyed {
+                Destroyed::Table(table) => {
+                    let row = self.client.query_opt(QUALIFIED_TABLE, &[table])?;
+                    sources.extend(row.map(|r| r.get("name")));
+                }
+                Destroyed::Schema(schema) => {
+                    // Unquoted names are folded to lowercase, as PostgreSQL does
+                    let schema = match schema.strip_prefix('"').and_then(|s| s.strip_suffix('"')) {
+                        Some(quoted) => quoted.to_string(),
+                        None => schema.to_lowercase(),
+                    };
+                    let rows = self.client.query(SCHEMA_TABLES, &[&schema])?;
+                    sources.extend(rows.iter().map(|r| r.get::<_, String>("name")));
+                }
+            }
+        }
+        // The same table may be named in several statements
+        let mut seen = vec![];
+        sources.retain(|source| {
+            let new = !seen.contains(source);
+            seen.push(source.clone());
+            new
+        });
+
+        if sources.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let backup_table = format!("{}_backup", self.table);
+
+        if !self.table_exists(&backup_table)? {
+            self.client.execute(
+                CREATE_BACKUP_TABLE
+                    .replace("$$schema$$", &self.schema)
+                    .replace("$$table$$", &self.table)
+                    .as_str(),
+                &[],
+            )?;
+        }
+        if settings.directory.is_none() {
+            let exists: bool = self.client.query_one(SCHEMA_EXISTS, &[&settings.schema])?.get("exists");
+
+            if !exists {
+                self.client.execute(CREATE_SCHEMA.replace("$$schema$$", &settings.schema).as_str(), &[])?;
+            }
+        }
+
+        let stamp = Utc::now().format("%Y%m%d%H%M%S");
+        let insert_backup = INSERT_BACKUP
+            .replace("$$schema$$", &self.schema)
+            .replace("$$table$$", &self.table);
+        let mut backups = vec![];
+
+        for source in sources {
+            let failed = |e: Error| Error::BackupFailed(source.clone(), e.to_string());
+            let name = format!("{}_{}", stamp, source.replace('"', "").replace(|c: char| !c.is_alphanumeric(), "_"));
+
+            let location = match &settings.directory {
+                Some(directory) => {
+                    let path = directory.join(format!("{}.csv", name));
+                    let copy = COPY_TABLE.replace("$$source$$", &source);
+
+                    fs::create_dir_all(directory).map_err(|e| failed(e.into()))?;
+
+                    let mut reader = self.client.copy_out(copy.as_str()).map_err(|e| failed(e.into()))?;
+                    let mut file = fs::File::create(&path).map_err(|e| failed(e.into()))?;
+                    std::io::copy(&mut reader, &mut file).map_err(|e| failed(e.into()))?;
+
+                    path.display().to_string()
+                }
+                None => {
+                    // Identifiers longer than this are truncated anyway
+                    let name: String = name.chars().take(63).collect();
+                    let backup = format!("{}.\"{}\"", settings.schema, name);
+                    let create = BACKUP_TABLE
+                        .replace("$$backup$$", &backup)
+                        .replace("$$source$$", &source);
+
+                    self.client.execute(create.as_str(), &[]).map_err(|e| failed(e.into()))?;
+
+                    backup
+                }
+            };
+
+            self.client.execute(insert_backup.as_str(), &[&revision, &source, &location])?;
+            backups.push((source, location));
+        }
+
+        Ok(backups)
     }
 
-    fn create_schema(&mut self) -> Result<()> {
-        info!("Creating schema {}", self.schema);
-        let create = CREATE_SCHEMA.replace("$$schema$$", &self.schema);
+    /// Records every statement executed for the revisions in the audit table,
+    /// creating it first should it not exist yet.
+    pub fn audit(&mut self, outcomes: &[RevisionOutcome]) -> Result<()> {
+        let mut tx = self.client.transaction()?;
 
-        self.client.execute(create.as_str(), &[])?;
+        if !table_exists(&mut tx, &self.schema, &format!("{}_statement", self.table))? {
+            tx.execute(
+                CREATE_STATEMENT_TABLE
+                    .replace("$$schema$$", &self.schema)
+                    .replace("$$table$$", &self.table)
+                    .as_str(),
+                &[],
+            )?;
+        }
+
+        let insert = INSERT_STATEMENT
+            .replace("$$schema$$", &self.schema)
+            .replace("$$table$$", &self.table);
+
+        for outcome in outcomes {
+            for statement in &outcome.statements {
+                let error = statement.error.as_ref();
+
+                tx.execute(
+                    insert.as_str(),
+                    &[
+                        &outcome.filename,
+                        &outcome.status.as_str(),
+                        &(statement.line as i32),
+                        &statement.checksum,
+                        &statement.summary,
+                        &statement.started_at,
+                        &(statement.elapsed.as_millis() as i64),
+                        &statement.rows.map(|rows| rows as i64),
+                        &error.and_then(|error| error.code.as_deref()),
+                        &error.map(|error| error.message.as_str()),
+                    ],
+                )?;
+            }
+        }
+        tx.commit()?;
 
         Ok(())
     }
 
-    fn create_table(&mut self) -> Result<()> {
-        info!("Creating table {}.{}", self.schema, self.table);
-        let create = CREATE_TABLE
+    /// Reads what the other tool's history table, given as it would be written
+    /// in SQL, says has been applied, or nothing if there's no such table.
+    pub fn load_foreign_history(&mut self, tool: MigrationTool, table: &str) -> Result<Option<History>> {
+        let exists: bool = self.client.query_one(RELATION_EXISTS, &[&table])?.get("exists");
+
+        if !exists {
+            return Ok(None);
+        }
+
+        let history = match tool {
+            MigrationTool::Flyway => {
+                let stmt = SELECT_FLYWAY_HISTORY.replace("$$table$$", table);
+                let (mut applied, mut baseline) = (vec![], None);
+
+                for row in self.client.query(stmt.as_str(), &[])? {
+                    let version: Option<String> = row.get("version");
+                    let script: String = row.get("script");
+                    let installed_on = row.get("installed_on");
+
+                    // Repeatable migrations have no version, only a script
+                    let key = match version.as_deref() {
+                        Some(version) => flyway_version_key(version),
+                        None => script.rsplit('/').next().map(str::to_string),
+                    };
+
+                    match (key, row.get::<_, String>("type").as_str()) {
+                        (Some(key), "BASELINE") => baseline = Some((key, installed_on)),
+                        (Some(key), _) => applied.push((key, installed_on)),
+                        (None, _) => continue,
+                    }
+                }
+
+                match baseline {
+                    Some((baseline, baselined_on)) => History::Baselined { applied, baseline, baselined_on },
+                    None => History::Applied(applied),
+                }
+            }
+            MigrationTool::Sqitch => {
+                let stmt = SELECT_SQITCH_HISTORY.replace("$$table$$", table);
+                let rows = self.client.query(stmt.as_str(), &[])?;
+
+                History::Applied(rows.iter().map(|r| (r.get("change"), r.get("committed_at"))).collect())
+            }
+            MigrationTool::GolangMigrate => {
+                let stmt = SELECT_GOLANG_MIGRATE_HISTORY.replace("$$table$$", table);
+
+                match self.client.query_opt(stmt.as_str(), &[])? {
+                    Some(row) => History::Version {
+                        version: row.get("version"),
+                        dirty: row.get("dirty"),
+                    },
+                    None => History::Applied(vec![]),
+                }
+            }
+        };
+
+        Ok(Some(history))
+    }
+
+    /// Records the checksum of the applied revision's file as it now is, so
+    /// that its changes are no longer taken as a problem.
+    pub fn accept_checksum(&mut self, filename: &str, checksum: &str) -> Result<()> {
+        let update = UPDATE_REVISION_CHECKSUM
             .replace("$$schema$$", &self.schema)
             .replace("$$table$$", &self.table);
 
-        self.client.execute(create.as_str(), &[])?;
+        self.client.execute(update.as_str(), &[&filename, &checksum])?;
 
         Ok(())
     }
+
+    /// Runs the statements undoing the applied revision and forgets that it
+    /// was applied, all or nothing, so that it's pending once again.
+    pub fn revert_revision(&mut self, filename: &str, statements: &[Statement]) -> Result<()> {
+        self.revert_revisions(&[(filename, statements)])
+    }
+
+    /// As with `revert_revision`, but for each of the revisions in turn, in
+    /// a single transaction, so that either all are reverted or none are.
+    pub fn revert_revisions(&mut self, reverts: &[(&str, &[Statement])]) -> Result<()> {
+        let delete = DELETE_REVISION
+            .replace("$$schema$$", &self.schema)
+            .replace("$$table$$", &self.table);
+
+        let mut tx = self.client.transaction()?;
+
+        for (filename, statements) in reverts {
+            let failed = |e: postgres::Error| {
+                Error::RevertFailed(filename.to_string(), e.as_db_error().map_or(e.to_string(), |e| e.message().to_string()))
+            };
+
+            for statement in statements.iter().filter(|statement| !statement.is_transaction_command()) {
+                tx.batch_execute(&statement.sql).map_err(failed)?;
+            }
+
+            tx.execute(delete.as_str(), &[filename])?;
+        }
+
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    /// Records a revision imported from another tool as applied, without
+    /// running it, since the other tool already has.
+    pub fn record_imported_revision(&mut self, file: &RevisionFile, applied_on: Option<DateTime<Utc>>) -> Result<()> {
+        let insert = INSERT_IMPORTED_REVISION
+            .replace("$$schema$$", &self.schema)
+            .replace("$$table$$", &self.table);
+
+        self.client.execute(
+            insert.as_str(),
+            &[&applied_on, &file.id, &file.created_at, &file.checksum, &file.filename, &file.name],
+        )?;
+
+        Ok(())
+    }
+
+    /// As with `record_imported_revision`, but for a repeatable revision.
+    pub fn record_imported_repeatable(&mut self, file: &RepeatableFile, applied_on: Option<DateTime<Utc>>) -> Result<()> {
+        let insert = INSERT_IMPORTED_REAPPLIED
+            .replace("$$schema$$", &self.schema)
+            .replace("$$table$$", &self.reapplied_table(file.kind));
+
+        self.client.execute(insert.as_str(), &[&applied_on, &file.checksum, &file.filename])?;
+
+        Ok(())
+    }
+
+    /// Records the history of another environment from a bundle as that of
+    /// this one, all or nothing, without running any of the revisions.
+    pub fn record_bundled_history(&mut self, revisions: &[&RevisionRecord], repeatables: &[&RepeatableRecord]) -> Result<()> {
+        let insert_revision = INSERT_BUNDLED_REVISION
+            .replace("$$schema$$", &self.schema)
+            .replace("$$table$$", &self.table);
+        let insert_repeatable = INSERT_IMPORTED_REAPPLIED
+            .replace("$$schema$$", &self.schema)
+            .replace("$$table$$", &self.reapplied_table(ReappliedKind::Repeatable));
+
+        let mut tx = self.client.transaction()?;
+
+        for r in revisions {
+            tx.execute(
+                insert_revision.as_str(),
+                &[
+                    &r.applied_on,
+                    &r.id,
+                    &r.created_at,
+                    &r.checksum,
+                    &r.filename,
+                    &r.name,
+                    &r.provenance.commit,
+                    &r.provenance.branch,
+                    &r.provenance.author,
+                    &r.release_group,
+                    &r.impact,
+                    &r.archived.as_ref().map(|contents| gzip::compress(contents.as_bytes())),
+                ],
+            )?;
+        }
+
+        for r in repeatables {
+            tx.execute(insert_repeatable.as_str(), &[&Some(r.applied_on), &r.checksum, &r.filename])?;
+        }
+
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    /// Executes each of the given statements for the revision, notifying the
+    /// observer around each, before recording the revision as applied along
+    /// with where it came from and a summary of what it changes. Given
+    /// a timeout, any statement still running once it passes is cancelled.
+    pub fn run_revision(
+        &mut self,
+        revision: &AnnotatedRevision,
+        provenance: &Provenance,
+        statements: &[Statement],
+        observer: &mut impl StatementObserver,
+        timeout: Option<Duration>,
+    ) -> Result<()> {
+        let insert_revision = INSERT_REVISION
+            .replace("$$schema$$", &self.schema)
+            .replace("$$table$$", &self.table);
+        let update_applied = UPDATE_REVISION_APPLIED
+            .replace("$$schema$$", &self.schema)
+            .replace("$$table$$", &self.table);
+        let impact = Impact::summary(statements);
+        let archived = self.archived(revision);
+
+        self.run_claimed(
+            &revision.filename,
+            statements,
+            observer,
+            timeout,
+            Some((
+                &insert_revision,
+                &[
+                    &revision.id,
+                    &revision.created_at,
+                    &revision.checksum,
+                    &revision.filename,
+                    &revision.name,
+                    &provenance.commit,
+                    &provenance.branch,
+                    &provenance.author,
+                    &revision.group,
+                    &impact,
+                    &archived,
+                ],
+            )),
+            Some((&update_applied, &revision.id)),
+        )
+    }
+
+    /// The revision's contents compressed for archiving, if they're archived.
+    fn archived(&self, revision: &AnnotatedRevision) -> Option<Vec<u8>> {
+        let contents = revision.contents.as_ref().filter(|_| self.archive)?;
+
+        Some(gzip::compress(contents.as_bytes()))
+    }
+
+    /// The SQL that `run_revision` would execute for the revision, with its
+    /// parameters inlined, for running by hand instead. Within a release
+    /// group, the group's transaction is left to the caller.
+    pub fn revision_sql(
+        &self,
+        revision: &AnnotatedRevision,
+        provenance: &Provenance,
+        statements: &[Statement],
+        timeout: Option<Duration>,
+        in_group: bool,
+    ) -> Result<String> {
+        let insert_revision = INSERT_REVISION
+            .replace("$$schema$$", &self.schema)
+            .replace("$$table$$", &self.table);
+        let update_applied = UPDATE_REVISION_APPLIED
+            .replace("$$schema$$", &self.schema)
+            .replace("$$table$$", &self.table);
+
+        let claim = inline(
+            &insert_revision,
+            &[
+                revision.id.to_string(),
+                literal(Some(&timestamp(&revision.created_at))),
+                literal(revision.checksum.as_deref()),
+                literal(Some(&revision.filename)),
+                literal(Some(&revision.name)),
+                literal(provenance.commit.as_deref()),
+                literal(provenance.branch.as_deref()),
+                literal(provenance.author.as_deref()),
+                literal(revision.group.as_deref()),
+                literal(Impact::summary(statements).as_deref()),
+                bytea_literal(self.archived(revision).as_deref()),
+            ],
+        );
+
+        claimed_sql(
+            &revision.filename,
+            statements,
+            timeout,
+            in_group,
+            &claim,
+            (&update_applied, &revision.id.to_string()),
+        )
+    }
+
+    /// The SQL that `run_repeatable` would execute, as with `revision_sql`.
+    pub fn repeatable_sql(
+        &self,
+        repeatable: &AnnotatedRepeatable,
+        statements: &[Statement],
+        timeout: Option<Duration>,
+    ) -> Result<String> {
+        let table = self.reapplied_table(repeatable.kind);
+        let upsert_repeatable = UPSERT_REAPPLIED
+            .replace("$$schema$$", &self.schema)
+            .replace("$$table$$", &table);
+        let update_applied = UPDATE_REAPPLIED_APPLIED
+            .replace("$$schema$$", &self.schema)
+            .replace("$$table$$", &table);
+
+        let claim = inline(
+            &upsert_repeatable,
+            &[
+                literal(Some(&repeatable.checksum)),
+                literal(Some(&repeatable.filename)),
+                literal(repeatable.applied_on.as_ref().map(timestamp).as_deref()),
+            ],
+        );
+
+        claimed_sql(
+            &repeatable.filename,
+            statements,
+            timeout,
+            false,
+            &claim,
+            (&update_applied, &literal(Some(&repeatable.filename))),
+        )
+    }
+
+    /// The SQL opening a release group's transaction, for running by hand.
+    pub fn begin_group_sql(&self) -> String {
+        terminated(BEGIN)
+    }
+
+    pub fn commit_group_sql(&self) -> String {
+        terminated(COMMIT)
+    }
+
+    /// Runs SQL outside of any transaction of jrny's own and without
+    /// recording it, eg. to enable or disable maintenance mode.
+    pub fn run_unrecorded(&mut self, sql: &str) -> Result<()> {
+        self.client.batch_execute(sql)?;
+
+        Ok(())
+    }
+
+    /// The process ID of the connection's backend, eg. for finding it in
+    /// `pg_stat_activity` from another connection.
+    pub fn backend_pid(&mut self) -> Result<i32> {
+        Ok(self.client.query_one(BACKEND_PID, &[])?.get("pid"))
+    }
+
+    pub fn server_version(&mut self) -> Result<PgVersion> {
+        Ok(PgVersion(self.client.query_one(SERVER_VERSION, &[])?.get("version")))
+    }
+
+    /// The extensions given that aren't installed in the database, each once.
+    pub fn missing_extensions(&mut self, extensions: &[String]) -> Result<Vec<String>> {
+        let installed: Vec<String> = self.client.query(EXTENSIONS, &[])?.iter().map(|row| row.get("extname")).collect();
+        let mut missing = vec![];
+
+        for extension in extensions {
+            if !installed.contains(extension) && !missing.contains(extension) {
+                missing.push(extension.clone());
+            }
+        }
+
+        Ok(missing)
+    }
+
+    /// Whether statements creating tables, indexes, and types that already
+    /// exist are to succeed regardless, eg. for revisions marked idempotent
+    /// to be run again after partly applying.
+    pub fn tolerate_existing(&mut self, tolerate: bool) {
+        self.tolerate_existing = tolerate;
+    }
+
+    /// The statement timeouts to run each statement with in turn, trying it
+    /// again with the next whenever it's cancelled by the one before, or
+    /// none to only be limited by the revision's own timeout.
+    pub fn escalate_timeouts(&mut self, timeouts: &[Duration]) {
+        self.timeouts = timeouts.to_vec();
+    }
+
+    /// Assertions the next revision must leave true, checked after its
+    /// statements and before it's recorded, within its transaction if any.
+    pub fn assert_after(&mut self, assertions: Option<AssertionFile>) {
+        self.assertions = assertions;
+    }
+
+    /// How long to pause before each statement after the first, and how far
+    /// replicas may lag behind before pausing until they catch up.
+    pub fn pace(&mut self, pacing: PacingSettings) {
+        self.pacing = pacing;
+    }
+
+    /// Pauses before applying the next revision, if pacing revisions and it
+    /// isn't the first, and for as long as replicas lag further behind than
+    /// allowed.
+    pub fn pause_before_revision(&mut self, first: bool, observer: &mut impl StatementObserver) -> Result<()> {
+        let between = self.pacing.between_revisions.filter(|_| !first);
+
+        pause(&mut self.client, between, &self.pacing, observer)
+    }
+
+    /// Every object checked for who owns it, with the default privileges of
+    /// the role given that each lacks.
+    pub fn objects(&mut self, owner: &str) -> Result<Vec<DatabaseObject>> {
+        let objects = self
+            .client
+            .query(OBJECTS, &[&owner])?
+            .iter()
+            .map(|r| DatabaseObject {
+                key: r.get("key"),
+                kind: r.get("kind"),
+                name: r.get("name"),
+                owner: r.get("owner"),
+                missing_privileges: r.get("missing_privileges"),
+            })
+            .collect();
+
+        Ok(objects)
+    }
+
+    /// Updates the planner's statistics for the table, eg. after a revision
+    /// changes much of it.
+    pub fn analyze(&mut self, table: &str) -> Result<()> {
+        self.client.batch_execute(&ANALYZE.replace("$$table$$", table))?;
+
+        Ok(())
+    }
+
+    /// Opens a transaction for a release group, within which each following
+    /// revision is applied until the group is committed or rolled back.
+    pub fn begin_group(&mut self) -> Result<()> {
+        self.client.batch_execute(BEGIN)?;
+        self.in_group = true;
+
+        Ok(())
+    }
+
+    pub fn commit_group(&mut self) -> Result<()> {
+        self.in_group = false;
+        self.client.batch_execute(COMMIT)?;
+
+        Ok(())
+    }
+
+    pub fn rollback_group(&mut self) -> Result<()> {
+        self.in_group = false;
+        self.client.batch_execute(ROLLBACK)?;
+
+        Ok(())
+    }
+
+    /// Prepares the release group's transaction for a two-phase commit under
+    /// the name, rather than committing it, after which it's no longer this
+    /// connection's but the server's until committed or rolled back.
+    pub fn prepare_group(&mut self, gid: &str) -> Result<()> {
+        self.in_group = false;
+        self.client.batch_execute(&format!("PREPARE TRANSACTION {}", literal(Some(gid))))?;
+
+        Ok(())
+    }
+
+    pub fn commit_prepared(&mut self, gid: &str) -> Result<()> {
+        self.client.batch_execute(&format!("COMMIT PREPARED {}", literal(Some(gid))))?;
+
+        Ok(())
+    }
+
+    pub fn rollback_prepared(&mut self, gid: &str) -> Result<()> {
+        self.client.batch_execute(&format!("ROLLBACK PREPARED {}", literal(Some(gid))))?;
+
+        Ok(())
+    }
+
+    /// Whether the server allows transactions to be prepared, which it
+    /// doesn't by default.
+    pub fn allows_prepared_transactions(&mut self) -> Result<bool> {
+        Ok(self.client.query_one(prepared::MAX_PREPARED_TRANSACTIONS, &[])?.get::<_, i32>("max") > 0)
+    }
+
+    /// The transactions left prepared by jrny anywhere in the cluster.
+    pub fn prepared_transactions(&mut self) -> Result<Vec<PreparedTransaction>> {
+        let rows = self.client.query(prepared::PREPARED_TRANSACTIONS, &[&prepared::PREFIX])?;
+
+        Ok(rows
+            .iter()
+            .filter_map(|r| {
+                Some(PreparedTransaction {
+                    id: TransactionId::parse(r.get("gid"))?,
+                    prepared: r.get("prepared"),
+                    owner: r.get("owner"),
+                    database: r.get("database"),
+                })
+            })
+            .collect())
+    }
+
+    /// As with `run_revision`, but recording the checksum of the repeatable
+    /// revision or seed's contents in place of any previous application.
+    pub fn run_repeatable(
+        &mut self,
+        repeatable: &AnnotatedRepeatable,
+        statements: &[Statement],
+        observer: &mut impl StatementObserver,
+        timeout: Option<Duration>,
+    ) -> Result<()> {
+        let table = self.reapplied_table(repeatable.kind);
+        let upsert_repeatable = UPSERT_REAPPLIED
+            .replace("$$schema$$", &self.schema)
+            .replace("$$table$$", &table);
+        let update_applied = UPDATE_REAPPLIED_APPLIED
+            .replace("$$schema$$", &self.schema)
+            .replace("$$table$$", &table);
+
+        self.run_claimed(
+            &repeatable.filename,
+            statements,
+            observer,
+            timeout,
+            Some((&upsert_repeatable, &[&repeatable.checksum, &repeatable.filename, &repeatable.applied_on])),
+            Some((&update_applied, &repeatable.filename)),
+        )
+    }
+
+    /// Executes the statements of an ad-hoc script as `run_revision` would,
+    /// within one transaction unless they manage their own, but without
+    /// recording anything.
+    pub fn run_script(
+        &mut self,
+        filename: &str,
+        statements: &[Statement],
+        observer: &mut impl StatementObserver,
+        timeout: Option<Duration>,
+    ) -> Result<()> {
+        self.run_claimed(filename, statements, observer, timeout, None, None)
+    }
+
+    /// Loads all of the fixtures within a single transaction, so that either all
+    /// or none are loaded, returning the number of rows each affected.
+    pub fn load_fixtures(&mut self, fixtures: &[Fixture]) -> Result<Vec<u64>> {
+        let mut tx = self.client.transaction()?;
+        let mut rows = vec![];
+
+        for fixture in fixtures {
+            let failed = |e: Error| Error::FixtureFailed(fixture.filename.clone(), e.to_string());
+
+            let affected = match &fixture.kind {
+                FixtureKind::Sql(statements) => {
+                    let mut counter = RowCounter::default();
+
+                    for statement in statements {
+                        run_statement(&mut tx, statement, &mut counter, true, false, &[], None).map_err(failed)?;
+                    }
+
+                    counter.rows
+                }
+                FixtureKind::Csv { table, columns } => {
+                    let copy = COPY_FIXTURE
+                        .replace("$$table$$", table)
+                        .replace("$$columns$$", &columns.join(", "));
+
+                    let mut writer = tx.copy_in(copy.as_str()).map_err(|e| failed(e.into()))?;
+                    writer.write_all(fixture.contents.as_bytes()).map_err(|e| failed(e.into()))?;
+                    writer.finish().map_err(|e| failed(e.into()))?
+                }
+            };
+
+            rows.push(affected);
+        }
+
+        tx.commit()?;
+
+        Ok(rows)
+    }
+
+    /// Runs the statements after claiming the file with the given statement,
+    /// all within one transaction unless the statements manage their own, and
+    /// then marks it as applied, with how long it took, by its key. Fails
+    /// without running anything if another run has claimed the file first.
+    /// Without a claim or a statement marking it applied, nothing is recorded.
+    ///
+    /// Within a release group, the group's transaction is used instead, which
+    /// is left to the caller to commit.
+    fn run_claimed(
+        &mut self,
+        filename: &str,
+        statements: &[Statement],
+        observer: &mut impl StatementObserver,
+        timeout: Option<Duration>,
+        claim: Option<(&str, &[&(dyn ToSql + Sync)])>,
+        applied: Option<(&str, &(dyn ToSql + Sync))>,
+    ) -> Result<()> {
+        let conflict = || Error::RevisionRecordedConcurrently(filename.to_string());
+        let assertions = self.assertions.take();
+        let started = Instant::now();
+        let elapsed = || started.elapsed().as_millis() as i64;
+        let deadline = timeout.map(|timeout| started + timeout);
+
+        // Without a transaction of the revision's own, constraints are
+        // validated as soon as they're added
+        let inline = statements::outside_transaction(statements)
+            || statements.iter().any(|statement| matches!(Batch::of(statement), Ok(Some(_))));
+        let (statements, validations) = validation::separate(statements, inline)
+            .map_err(|(line, reason)| Error::ValidationInvalid(format!("{}:{}", filename, line), reason))?;
+        let statements = statements.as_slice();
+
+        let batched = statements
+            .iter()
+            .map(|statement| {
+                Batch::of(statement)
+                    .and_then(|batch| batch.map(|batch| Batched::new(statement, batch)).transpose())
+                    .map_err(|reason| Error::BatchInvalid(format!("{}:{}", filename, statement.line), reason))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        // Statements may also be cancelled by the last of the escalating timeouts
+        let escalated = !self.timeouts.is_empty();
+        let cancelled = |err: Error| match (err, timeout) {
+            (Error::DatabaseError(e), Some(timeout))
+                if e.code() == Some(&SqlState::QUERY_CANCELED) && (!escalated || started.elapsed() >= timeout) =>
+            {
+                Error::RevisionCancelled(filename.to_string(), timeout)
+            }
+            (err, _) => err,
+        };
+
+        if self.in_group {
+            if let Some(validation) = validations.first() {
+                return Err(Error::ValidationInvalid(
+                    format!("{}:{}", filename, validation.line),
+                    "release groups are applied in a single transaction".to_string(),
+                ));
+            }
+            let settings = (self.tolerate_existing, self.timeouts.as_slice(), &self.pacing);
+
+            if !claim_and_run(&mut self.client, statements, observer, deadline, claim, settings).map_err(cancelled)? {
+                return Err(conflict());
+            }
+            if deadline.is_some() {
+                self.client.execute(RESET_LOCAL_STATEMENT_TIMEOUT, &[])?;
+            }
+            verify(&mut self.client, assertions.as_ref())?;
+            if let Some((applied, key)) = applied {
+                self.client.execute(applied, &[key, &elapsed()])?;
+            }
+
+            return Ok(());
+        }
+
+        // Files managing their own transactions or run without one, or with
+        // statements batched into transactions of their own, can't be claimed
+        // beforehand, so any conflict can only be found once they've been applied
+        if statements::outside_transaction(statements) || batched.iter().any(Option::is_some) {
+            let mut run = || -> Result<()> {
+                for (i, (statement, batched)) in statements.iter().zip(&batched).enumerate() {
+                    if i > 0 {
+                        pause(&mut self.client, self.pacing.between_statements, &self.pacing, observer)?;
+                    }
+
+                    match batched {
+                        Some(batched) => run_batched(&mut self.client, statement, batched, observer, deadline, &self.pacing)?,
+                        None => {
+                            limit_statement(&mut self.client, deadline, false)?;
+                            run_statement(
+                                &mut self.client,
+                                statement,
+                                observer,
+                                false,
+                                self.tolerate_existing,
+                                &self.timeouts,
+                                deadline,
+                            )?;
+                        }
+                    }
+                }
+
+                Ok(())
+            };
+            let result = run();
+
+            if deadline.is_some() {
+                self.client.execute(RESET_STATEMENT_TIMEOUT, &[])?;
+            }
+            result.map_err(cancelled)?;
+            verify(&mut self.client, assertions.as_ref())?;
+
+            if let Some((claim, claim_params)) = claim {
+                if self.client.execute(claim, claim_params)? == 0 {
+                    return Err(conflict());
+                }
+            }
+            if let Some((applied, key)) = applied {
+                self.client.execute(applied, &[key, &elapsed()])?;
+            }
+
+            return Ok(());
+        }
+
+        let mut tx = self.client.transaction()?;
+
+        let settings = (self.tolerate_existing, self.timeouts.as_slice(), &self.pacing);
+
+        if !claim_and_run(&mut tx, statements, observer, deadline, claim, settings).map_err(cancelled)? {
+            return Err(conflict());
+        }
+        verify(&mut tx, assertions.as_ref())?;
+
+        if let Some((applied, key)) = applied {
+            tx.execute(applied, &[key, &elapsed()])?;
+        }
+        tx.commit()?;
+
+        // Each validated in a transaction of its own, now that the locks of
+        // the revision's are released
+        if !validations.is_empty() {
+            let mut validate = || -> Result<()> {
+                for validation in &validations {
+                    limit_statement(&mut self.client, deadline, false)?;
+                    run_statement(&mut self.client, validation, observer, false, false, &self.timeouts, deadline)?;
+                }
+
+                Ok(())
+            };
+            let result = validate();
+
+            if deadline.is_some() {
+                self.client.execute(RESET_STATEMENT_TIMEOUT, &[])?;
+            }
+            result.map_err(|err| Error::ConstraintsNotValidated(filename.to_string(), cancelled(err).to_string()))?;
+        }
+
+        Ok(())
+    }
+
+    fn reapplied_table(&self, kind: ReappliedKind) -> String {
+        match kind {
+            ReappliedKind::Repeatable => format!("{}_repeatable", self.table),
+            ReappliedKind::Seed => format!("{}_seed", self.table),
+        }
+    }
+
+    fn lock_key(&self) -> String {
+        format!("jrny:{}.{}", self.schema, self.table)
+    }
+
+    fn table_exists(&mut self, table: &str) -> Result<bool> {
+        table_exists(&mut self.client, &self.schema, table)
+    }
+
+    fn missing_columns(&mut self, table: &str, expected: &[&str]) -> Result<Vec<String>> {
+        let rows = self.client.query(SELECT_COLUMNS, &[&self.schema, &table])?;
+        let columns: Vec<String> = rows.iter().map(|r| r.get("column_name")).collect();
+
+        Ok(expected
+            .iter()
+            .filter(|column| !columns.iter().any(|c| c == *column))
+            .map(|column| column.to_string())
+            .collect())
+    }
+
+    /// Fails clearly, rather than with an error from a later query, if the
+    /// tracking table lacks columns that can't be added automatically.
+    fn check_columns(&mut self, table: &str, expected: &[&str]) -> Result<()> {
+        let missing = self.missing_columns(table, expected)?;
+
+        if !missing.is_empty() {
+            return Err(Error::TrackingTableOutdated(format!("{}.{}", self.schema, table), missing));
+        }
+
+        Ok(())
+    }
+
+    fn schema_exists(&mut self) -> Result<bool> {
+        let row = self.client.query_one(SCHEMA_EXISTS, &[&self.schema])?;
+
+        Ok(row.get("exists"))
+    }
+
+    fn create_schema(&mut self) -> Result<()> {
+        info!("Creating schema {}", self.schema);
+        let create = CREATE_SCHEMA.replace("$$schema$$", &self.schema);
+
+        self.client.execute(create.as_str(), &[])?;
+
+        Ok(())
+    }
+
+    fn create_table(&mut self) -> Result<()> {
+        info!("Creating table {}.{}", self.schema, self.table);
+        let create = CREATE_TABLE
+            .replace("$$schema$$", &self.schema)
+            .replace("$$table$$", &self.table);
+        let create_version = CREATE_VERSION_TABLE
+            .replace("$$schema$$", &self.schema)
+            .replace("$$table$$", &self.table);
+        let insert_version = INSERT_VERSION
+            .replace("$$schema$$", &self.schema)
+            .replace("$$table$$", &self.table);
+
+        let mut tx = self.client.transaction()?;
+
+        tx.execute(create.as_str(), &[])?;
+        tx.execute(create_version.as_str(), &[])?;
+        tx.execute(insert_version.as_str(), &[&CURRENT_VERSION])?;
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    /// Brings tracking tables created by an earlier version of jrny up to
+    /// date, all in one transaction, the first time this version uses them.
+    fn upgrade_tables(&mut self) -> Result<()> {
+        let (schema, table) = (self.schema.clone(), self.table.clone());
+        let version = tables_version(&mut self.client, &schema, &table)?;
+
+        if version > CURRENT_VERSION {
+            return Err(Error::TrackingTableNewer(format!("{}.{}", schema, table), version));
+        }
+        if version == CURRENT_VERSION {
+            return Ok(());
+        }
+
+        let replace = |stmt: &str, table: &str| stmt.replace("$$schema$$", &schema).replace("$$table$$", table);
+        let reapplied = [self.reapplied_table(ReappliedKind::Repeatable), self.reapplied_table(ReappliedKind::Seed)];
+
+        let mut tx = self.client.transaction()?;
+
+        tx.execute(replace(LOCK_TABLE, &table).as_str(), &[])?;
+        tx.execute(QUIET_UPGRADE, &[])?;
+
+        // Another run may have upgraded the tables while this one waited
+        let version = tables_version(&mut tx, &schema, &table)?;
+
+        if version == CURRENT_VERSION {
+            return Ok(());
+        }
+
+        info!("Upgrading tracking tables in {} from version {} to {}", schema, version, CURRENT_VERSION);
+
+        for upgrade in UPGRADES.iter().filter(|u| u.version > version) {
+            if let Some(changes) = upgrade.revisions {
+                tx.execute(replace(ALTER_TABLE, &table).replace("$$changes$$", changes).as_str(), &[])?;
+            }
+            if let Some(changes) = upgrade.reapplied {
+                for reapplied in &reapplied {
+                    if table_exists(&mut tx, &schema, reapplied)? {
+                        tx.execute(replace(ALTER_TABLE, reapplied).replace("$$changes$$", changes).as_str(), &[])?;
+                    }
+                }
+            }
+        }
+
+        if !table_exists(&mut tx, &schema, &format!("{}_version", table))? {
+            tx.execute(replace(CREATE_VERSION_TABLE, &table).as_str(), &[])?;
+        }
+        tx.execute(replace(DELETE_VERSION, &table).as_str(), &[])?;
+        tx.execute(replace(INSERT_VERSION, &table).as_str(), &[&CURRENT_VERSION])?;
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    fn create_reapplied_table(&mut self, kind: ReappliedKind) -> Result<()> {
+        let table = self.reapplied_table(kind);
+
+        info!("Creating table {}.{}", self.schema, table);
+        let create = CREATE_REAPPLIED_TABLE
+            .replace("$$schema$$", &self.schema)
+            .replace("$$table$$", &table);
+
+        self.client.execute(create.as_str(), &[])?;
+
+        Ok(())
+    }
+}
+
+/// The version of the tracking tables, as recorded since versions have been,
+/// or otherwise judged by the columns of the table tracking revisions.
+fn tables_version(client: &mut impl GenericClient, schema: &str, table: &str) -> Result<i32> {
+    if table_exists(client, schema, &format!("{}_version", table))? {
+        let select = SELECT_VERSION.replace("$$schema$$", schema).replace("$$table$$", table);
+        let version: Option<i32> = client.query_one(select.as_str(), &[])?.get("version");
+
+        return Ok(version.unwrap_or(1));
+    }
+
+    let rows = client.query(SELECT_COLUMNS, &[&schema, &table])?;
+    let columns: Vec<String> = rows.iter().map(|r| r.get("column_name")).collect();
+
+    Ok(version_of(&columns))
+}
+
+/// The latest version whose columns, along with those of every version
+/// before it, the table tracking revisions has.
+fn version_of(columns: &[String]) -> i32 {
+    UPGRADES
+        .iter()
+        .take_while(|upgrade| upgrade.columns.iter().all(|column| columns.iter().any(|c| c == column)))
+        .last()
+        .map_or(1, |upgrade| upgrade.version)
+}
+
+fn table_exists(client: &mut impl GenericClient, schema: &str, table: &str) -> Result<bool> {
+    let row = client.query_one(TABLE_EXISTS, &[&schema, &table])?;
+
+    Ok(row.get("exists"))
+}
+
+/// Claims the file, if there's a claim to make, and then runs its statements,
+/// each limited to the time left until the deadline, within the transaction
+/// the client is in. Returns whether the file could be claimed, running
+/// nothing if not.
+fn claim_and_run(
+    client: &mut impl GenericClient,
+    statements: &[Statement],
+    observer: &mut impl StatementObserver,
+    deadline: Option<Instant>,
+    claim: Option<(&str, &[&(dyn ToSql + Sync)])>,
+    (tolerate_existing, timeouts, pacing): (bool, &[Duration], &PacingSettings),
+) -> Result<bool> {
+    if let Some((claim, claim_params)) = claim {
+        if client.execute(claim, claim_params)? == 0 {
+            return Ok(false);
+        }
+    }
+
+    for (i, statement) in statements.iter().enumerate() {
+        if i > 0 {
+            pause(client, pacing.between_statements, pacing, observer)?;
+        }
+
+        limit_statement(client, deadline, true)?;
+        run_statement(client, statement, observer, true, tolerate_existing, timeouts, deadline)?;
+    }
+
+    Ok(true)
+}
+
+/// Checks every assertion in the file, failing with the first that fails,
+/// or whose statement errors.
+fn verify(client: &mut impl GenericClient, assertions: Option<&AssertionFile>) -> Result<()> {
+    let file = match assertions {
+        Some(file) => file,
+        None => return Ok(()),
+    };
+
+    for statement in &file.statements {
+        let failed = |reason: String| Error::AssertionFailed(format!("{}:{}", file.filename, statement.line), reason);
+
+        match assertions::check(client, statement) {
+            Ok(None) => {}
+            Ok(Some(reason)) => return Err(failed(reason)),
+            Err(e) => return Err(failed(e.as_db_error().map_or(e.to_string(), |e| e.message().to_string()))),
+        }
+    }
+
+    Ok(())
+}
+
+/// The SQL that `run_claimed` would execute, with the claim and the key its
+/// applied statement takes already inlined. Without a client keeping time,
+/// each statement is limited to the whole timeout rather than what's left.
+fn claimed_sql(
+    filename: &str,
+    statements: &[Statement],
+    timeout: Option<Duration>,
+    in_group: bool,
+    claim: &str,
+    (applied, key): (&str, &str),
+) -> Result<String> {
+    if let Some(statement) = statements.iter().find(|statement| matches!(Batch::of(statement), Ok(Some(_)))) {
+        return Err(Error::SqlNotEmittable(format!(
+            "`{}:{}` is batched, which needs jrny to find each batch as it goes",
+            filename, statement.line,
+        )));
+    }
+    if let Some(statement) = statements.iter().find(|statement| matches!(LockRetry::of(statement), Ok(Some(_)))) {
+        return Err(Error::SqlNotEmittable(format!(
+            "`{}:{}` retries on lock timeouts, which needs jrny to try it again",
+            filename, statement.line,
+        )));
+    }
+
+    let outside_transaction = statements::outside_transaction(statements);
+    let (statements, validations) = validation::separate(statements, outside_transaction)
+        .map_err(|(line, reason)| Error::ValidationInvalid(format!("{}:{}", filename, line), reason))?;
+    let statements = statements.as_slice();
+
+    if let Some(validation) = validations.first().filter(|_| in_group) {
+        return Err(Error::ValidationInvalid(
+            format!("{}:{}", filename, validation.line),
+            "release groups are applied in a single transaction".to_string(),
+        ));
+    }
+
+    let ensure_claimed = terminated(&ENSURE_CLAIMED.replace("$$claim$$", claim.trim()));
+    let millis = timeout.map(|timeout| timeout.as_millis().max(1).to_string());
+    let mut sql = String::new();
+
+    // As when applied by jrny, files run outside of a transaction are only
+    // claimed once they've been applied, so there's no telling how long for
+    if outside_transaction {
+        if let Some(millis) = &millis {
+            sql.push_str(&terminated(&SET_STATEMENT_TIMEOUT.replace("$$millis$$", millis)));
+        }
+        for statement in statements {
+            sql.push_str(&terminated(&statement.sql));
+        }
+        if millis.is_some() {
+            sql.push_str(&terminated(RESET_STATEMENT_TIMEOUT));
+        }
+
+        sql.push_str(&ensure_claimed);
+        sql.push_str(&terminated(&inline(applied, &[key.to_string(), "NULL".to_string()])));
+
+        return Ok(sql);
+    }
+
+    if !in_group {
+        sql.push_str(&terminated(BEGIN));
+    }
+
+    sql.push_str(&ensure_claimed);
+
+    if let Some(millis) = &millis {
+        sql.push_str(&terminated(&SET_LOCAL_STATEMENT_TIMEOUT.replace("$$millis$$", millis)));
+    }
+    for statement in statements {
+        sql.push_str(&terminated(&statement.sql));
+    }
+    if in_group && millis.is_some() {
+        sql.push_str(&terminated(RESET_LOCAL_STATEMENT_TIMEOUT));
+    }
+
+    sql.push_str(&terminated(&inline(applied, &[key.to_string(), ELAPSED_MILLIS.to_string()])));
+
+    if !in_group {
+        sql.push_str(&terminated(COMMIT));
+    }
+
+    if !validations.is_empty() {
+        if let Some(millis) = &millis {
+            sql.push_str(&terminated(&SET_STATEMENT_TIMEOUT.replace("$$millis$$", millis)));
+        }
+        for validation in &validations {
+            sql.push_str(&terminated(&validation.sql));
+        }
+        if millis.is_some() {
+            sql.push_str(&terminated(RESET_STATEMENT_TIMEOUT));
+        }
+    }
+
+    Ok(sql)
+}
+
+/// Replaces each of the statement's parameters with the given SQL, in a
+/// single pass, so that neither `$1` matches the start of `$10` nor is a
+/// parameter found within the SQL given for another.
+fn inline(statement: &str, params: &[String]) -> String {
+    let mut inlined = String::with_capacity(statement.len());
+    let mut rest = statement;
+
+    while let Some(start) = rest.find('$') {
+        inlined.push_str(&rest[..start]);
+
+        let digits = rest[start + 1..].find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len() - start - 1);
+        let placeholder = &rest[start..start + 1 + digits];
+        let param = placeholder[1..].parse::<usize>().ok().and_then(|n| params.get(n.checked_sub(1)?));
+
+        inlined.push_str(param.map_or(placeholder, String::as_str));
+        rest = &rest[start + placeholder.len()..];
+    }
+
+    inlined.push_str(rest);
+    inlined
+}
+
+/// The bytes as a `bytea` literal, or `NULL` without them.
+fn bytea_literal(value: Option<&[u8]>) -> String {
+    match value {
+        Some(value) => format!("'\\x{}'::BYTEA", value.iter().map(|b| format!("{:02x}", b)).collect::<String>()),
+        None => "NULL".to_string(),
+    }
+}
+
+/// The value as a string literal, or `NULL` without one.
+fn literal(value: Option<&str>) -> String {
+    match value {
+        Some(value) => format!("'{}'", value.replace('\'', "''")),
+        None => "NULL".to_string(),
+    }
+}
+
+fn timestamp(value: &DateTime<Utc>) -> String {
+    value.to_rfc3339_opts(SecondsFormat::AutoSi, true)
+}
+
+/// The statement followed by a semicolon and a newline, on a line of its own
+/// if the statement ends in a comment that would otherwise swallow it.
+fn terminated(statement: &str) -> String {
+    let statement = statement.trim();
+
+    if statement.lines().last().is_some_and(|line| line.contains("--")) {
+        format!("{}\n;\n", statement)
+    } else {
+        format!("{};\n", statement)
+    }
+}
+
+/// Executes the statement one batch at a time, each committed on its own,
+/// until there are no keys left.
+fn run_batched(
+    client: &mut Client,
+    statement: &Statement,
+    batched: &Batched,
+    observer: &mut impl StatementObserver,
+    deadline: Option<Instant>,
+    pacing: &PacingSettings,
+) -> Result<()> {
+    observer.before(statement);
+    let started = Instant::now();
+
+    let (mut after, mut batches, mut rows) = (None, 0, 0);
+
+    loop {
+        if batches > 0 {
+            pause(client, pacing.between_statements, pacing, observer)?;
+        }
+
+        limit_statement(client, deadline, false)?;
+
+        let next = client
+            .query_one(batched.next_bounds(after.as_deref()).as_str(), &[])
+            .and_then(|row| row.try_get::<_, Option<String>>("last"));
+
+        let last = match next {
+            Ok(Some(last)) => last,
+            Ok(None) => break,
+            Err(e) => {
+                observer.failed(statement, started.elapsed(), &e);
+                return Err(e.into());
+            }
+        };
+
+        match client.execute(batched.statement(after.as_deref(), &last).as_str(), &[]) {
+            Ok(changed) => rows += changed,
+            Err(e) => {
+                observer.failed(statement, started.elapsed(), &e);
+                return Err(e.into());
+            }
+        }
+
+        batches += 1;
+        observer.batch(statement, batches, rows);
+        after = Some(last);
+    }
+
+    observer.after(statement, started.elapsed(), rows);
+
+    Ok(())
+}
+
+/// Whether the statement creates a table, index, or type, which revisions
+/// marked idempotent may find already exists.
+fn creates_tolerated(statement: &Statement) -> bool {
+    matches!(rewrite::created(statement), Some((object, _)) if matches!(object.as_str(), "table" | "index" | "type"))
+}
+
+/// Pauses for as long as given, if at all, and then for as long as any
+/// replica lags further behind than allowed.
+fn pause(
+    client: &mut impl GenericClient,
+    pause: Option<Duration>,
+    pacing: &PacingSettings,
+    observer: &mut impl StatementObserver,
+) -> Result<()> {
+    if let Some(pause) = pause {
+        thread::sleep(pause);
+    }
+
+    let max_lag = match pacing.max_replication_lag {
+        Some(max_lag) => max_lag,
+        None => return Ok(()),
+    };
+    let started = Instant::now();
+    let mut lagging = false;
+
+    loop {
+        // Via the simple query protocol, so the query may give the lag as any
+        // type of number
+        let found = client
+            .simple_query(pacing.lag_query())?
+            .into_iter()
+            .find_map(|message| match message {
+                SimpleQueryMessage::Row(row) => Some(row.try_get(0).map(|lag| lag.map(ToString::to_string))),
+                _ => None,
+            })
+            .transpose()?
+            .flatten();
+        let lag = pacing::parse_lag(found.as_deref()).map_err(Error::ReplicationLagInvalid)?;
+
+        if lag <= max_lag {
+            break;
+        }
+        if !lagging {
+            observer.lagging(lag, max_lag);
+            lagging = true;
+        }
+
+        thread::sleep(LAG_INTERVAL);
+    }
+
+    if lagging {
+        observer.caught_up(started.elapsed());
+    }
+
+    Ok(())
+}
+
+/// Limits the next statement to the time left until the deadline, if any,
+/// either until the end of the transaction or for the session.
+fn limit_statement(client: &mut impl GenericClient, deadline: Option<Instant>, local: bool) -> Result<()> {
+    let remaining = match deadline {
+        Some(deadline) => deadline.saturating_duration_since(Instant::now()),
+        None => return Ok(()),
+    };
+    // A timeout of zero would mean no timeout at all
+    let millis = remaining.as_millis().max(1);
+    let set = if local { SET_LOCAL_STATEMENT_TIMEOUT } else { SET_STATEMENT_TIMEOUT };
+
+    client.batch_execute(&set.replace("$$millis$$", &millis.to_string()))?;
+
+    Ok(())
+}
+
+/// Executes a single statement via the simple query protocol, as with
+/// `batch_execute`, but keeping track of the number of affected rows, and of
+/// the plan should it be explained. Given a `-- jrny:lock-retry` directive,
+/// the statement is tried again whenever it fails to take a lock in time,
+/// and if tolerating what exists, creating it anyway succeeds. Given several
+/// timeouts, it's tried with each in turn until one isn't reached, though
+/// never beyond the deadline.
+fn run_statement(
+    client: &mut impl GenericClient,
+    statement: &Statement,
+    observer: &mut impl StatementObserver,
+    in_transaction: bool,
+    tolerate_existing: bool,
+    timeouts: &[Duration],
+    deadline: Option<Instant>,
+) -> Result<()> {
+    // Directives are checked before anything is applied
+    let retry = LockRetry::of(statement).ok().flatten();
+    let tolerated = tolerate_existing && creates_tolerated(statement);
+    let savepoint = in_transaction && (retry.is_some() || tolerated || timeouts.len() > 1);
+
+    observer.before(statement);
+    let started = Instant::now();
+
+    if let Some(retry) = &retry {
+        let set = if in_transaction { SET_LOCAL_LOCK_TIMEOUT } else { SET_LOCK_TIMEOUT };
+        client.batch_execute(&set.replace("$$millis$$", &retry.wait.as_millis().max(1).to_string()))?;
+    }
+
+    let mut attempt = 1;
+    let mut escalation = 0;
+    let result = loop {
+        // Whether the attempt is limited by its timeout rather than the deadline
+        let mut escalating = false;
+
+        if let Some(timeout) = timeouts.get(escalation) {
+            let remaining = deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()));
+            let limit = remaining.map_or(*timeout, |remaining| remaining.min(*timeout));
+            let set = if in_transaction { SET_LOCAL_STATEMENT_TIMEOUT } else { SET_STATEMENT_TIMEOUT };
+
+            client.batch_execute(&set.replace("$$millis$$", &limit.as_millis().max(1).to_string()))?;
+            escalating = escalation + 1 < timeouts.len() && remaining.is_none_or(|remaining| *timeout < remaining);
+        }
+
+        if savepoint {
+            client.batch_execute(STATEMENT_SAVEPOINT)?;
+        }
+
+        match (&retry, client.simple_query(&statement.sql)) {
+            (Some(retry), Err(e)) if e.code() == Some(&SqlState::LOCK_NOT_AVAILABLE) && attempt < retry.attempts => {
+                if savepoint {
+                    client.batch_execute(ROLLBACK_TO_STATEMENT_SAVEPOINT)?;
+                }
+
+                observer.retrying(statement, attempt, retry.wait);
+                thread::sleep(retry.wait);
+                attempt += 1;
+            }
+            (_, Err(e)) if e.code() == Some(&SqlState::QUERY_CANCELED) && escalating => {
+                if savepoint {
+                    client.batch_execute(ROLLBACK_TO_STATEMENT_SAVEPOINT)?;
+                }
+
+                observer.timed_out(statement, timeouts[escalation], timeouts[escalation + 1]);
+                escalation += 1;
+            }
+            (_, result) => break result,
+        }
+    };
+
+    let result = match result {
+        Err(e) if tolerated && matches!(e.code(), Some(&SqlState::DUPLICATE_TABLE | &SqlState::DUPLICATE_OBJECT)) => {
+            if savepoint {
+                client.batch_execute(ROLLBACK_TO_STATEMENT_SAVEPOINT)?;
+            }
+
+            observer.existing(statement, &e);
+            Ok(vec![])
+        }
+        result => result,
+    };
+
+    // A transaction that failed is rolled back regardless
+    if savepoint && result.is_ok() {
+        client.batch_execute(RELEASE_STATEMENT_SAVEPOINT)?;
+    }
+    if retry.is_some() && in_transaction && result.is_ok() {
+        client.batch_execute(RESET_LOCAL_LOCK_TIMEOUT)?;
+    } else if retry.is_some() && !in_transaction {
+        client.batch_execute(RESET_LOCK_TIMEOUT)?;
+    }
+    // Given a deadline, the next statement's timeout is set from it anyway
+    if !timeouts.is_empty() && deadline.is_none() && in_transaction && result.is_ok() {
+        client.batch_execute(RESET_LOCAL_STATEMENT_TIMEOUT)?;
+    } else if !timeouts.is_empty() && deadline.is_none() && !in_transaction {
+        client.batch_execute(RESET_STATEMENT_TIMEOUT)?;
+    }
+
+    match result {
+        Ok(messages) => {
+            if statement.leading_keyword().as_deref() == Some("explain") {
+                let plan: Vec<_> = messages
+                    .iter()
+                    .filter_map(|m| match m {
+                        SimpleQueryMessage::Row(row) => row.get(0),
+                        _ => None,
+                    })
+                    .collect();
+
+                observer.plan(statement, &plan.join("\n"));
+            }
+
+            let rows = messages
+                .iter()
+                .find_map(|m| match m {
+                    SimpleQueryMessage::CommandComplete(rows) => Some(*rows),
+                    _ => None,
+                })
+                .unwrap_or(0);
+
+            observer.after(statement, started.elapsed(), rows);
+            Ok(())
+        }
+        Err(e) => {
+            observer.failed(statement, started.elapsed(), &e);
+            Err(e.into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn versions_are_judged_by_the_columns_added() {
+        let columns = |names: &[&str]| names.iter().map(|name| name.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(version_of(&columns(&["id", "checksum"])), 1);
+        assert_eq!(version_of(&columns(&["git_commit", "git_branch", "git_author"])), 2);
+        assert_eq!(version_of(&columns(&["git_commit", "git_branch", "git_author", "applied_by", "duration_ms", "impact"])), 3);
+        assert_eq!(version_of(&columns(REVISION_COLUMNS)), CURRENT_VERSION);
+    }
+
+    #[test]
+    fn parameters_are_inlined_once() {
+        let params = ["'costs $1 or $12'".to_string(), "2".to_string()];
+
+        assert_eq!(
+            inline("INSERT INTO t VALUES ($1, $2, $10, $)", &params),
+            "INSERT INTO t VALUES ('costs $1 or $12', 2, $10, $)",
+        );
+    }
 }
@@ -0,0 +1,158 @@
+//! Sample data for development and testing environments, loaded by
+//! `jrny fixtures` from either SQL files or CSV files that are copied
+//! into the table they are named after, eg. `public.users.csv`.
+//!
+//! Unlike seeds, fixtures aren't tracked: all of them are loaded every
+//! time, within a single transaction.
+use std::{fs, path::Path};
+
+use crate::{
+    paths,
+    revisions::sorted_entries,
+    statements::{self, Statement},
+    Error,
+    Result,
+};
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum FixtureKind {
+    Sql(Vec<Statement>),
+    /// Copied into the table with columns matching the CSV header
+    Csv { table: String, columns: Vec<String> },
+}
+
+#[derive(Debug)]
+pub struct Fixture {
+    /// The name of the file, relative to the fixtures directory
+    pub filename: String,
+    pub contents: String,
+    pub kind: FixtureKind,
+}
+
+impl Fixture {
+    /// Loads the fixtures in the directory, followed by those in the
+    /// subdirectory for the environment, if any.
+    pub fn all_from_disk(fixtures: &Path, env_name: Option<&str>) -> Result<Vec<Self>> {
+        if !fixtures.is_dir() {
+            return Ok(vec![]);
+        }
+
+        let mut entries: Vec<_> = sorted_entries(fixtures)?.into_iter().filter(|e| !e.is_dir()).collect();
+
+        if let Some(env_dir) = env_name.map(|name| fixtures.join(name)).filter(|dir| dir.is_dir()) {
+            entries.extend(sorted_entries(&env_dir)?);
+        }
+
+        entries.iter().map(|entry| Self::from_path(fixtures, entry)).collect()
+    }
+
+    fn from_path(root: &Path, p: &Path) -> Result<Self> {
+        let filename = paths::to_slash(p.strip_prefix(root).unwrap_or(p))
+            .ok_or_else(|| Error::FileNotValid(p.display().to_string()))?;
+        let invalid = |reason: &str| Error::FixtureInvalid(filename.clone(), reason.to_string());
+
+        let file_name = p.file_name().and_then(|f| f.to_str()).filter(|_| p.is_file());
+        let (stem, extension) = match file_name.and_then(|f| f.rsplit_once('.')) {
+            Some(parts) => parts,
+            None => return Err(invalid("expected a `.sql` or `.csv` file")),
+        };
+
+        let contents = fs::read_to_string(p)?;
+
+        let kind = match extension {
+            "sql" => {
+                let statements = statements::split(&contents);
+
+                // All fixtures being loaded in one transaction is what makes
+                // loading them all-or-nothing
+                if statements.iter().any(Statement::is_transaction_command) {
+                    return Err(invalid("cannot use transaction commands"));
+                }
+
+                FixtureKind::Sql(statements)
+            }
+            "csv" => {
+                let table = table_name(stem)
+                    .ok_or_else(|| invalid("expected a name like `[table].csv` or `[schema].[table].csv`"))?;
+                let columns = csv_header(&contents);
+
+                if columns.is_empty() {
+                    return Err(invalid("expected a header row naming the columns"));
+                }
+
+                FixtureKind::Csv { table, columns }
+            }
+            _ => return Err(invalid("expected a `.sql` or `.csv` file")),
+        };
+
+        Ok(Self { filename, contents, kind })
+    }
+}
+
+/// The quoted table name for a CSV file, ignoring any leading number used
+/// to order the files, eg. `01.public.users` is `"public"."users"`.
+fn table_name(stem: &str) -> Option<String> {
+    let mut parts: Vec<_> = stem.split('.').collect();
+
+    if parts.len() > 1 && parts[0].chars().all(|c| c.is_ascii_digit()) {
+        parts.remove(0);
+    }
+
+    if parts.len() > 2 || parts.iter().any(|part| part.is_empty()) {
+        return None;
+    }
+
+    Some(parts.iter().map(|part| quote_identifier(part)).collect::<Vec<_>>().join("."))
+}
+
+/// The quoted column names from the first line of the CSV contents.
+fn csv_header(contents: &str) -> Vec<String> {
+    let (mut columns, mut column) = (vec![], String::new());
+    let mut chars = contents.lines().next().unwrap_or_default().chars().peekable();
+    let mut quoted = false;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                column.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => columns.push(std::mem::take(&mut column)),
+            c => column.push(c),
+        }
+    }
+    columns.push(column);
+
+    if columns.iter().all(|column| column.trim().is_empty()) {
+        return vec![];
+    }
+
+    columns.iter().map(|column| quote_identifier(column.trim())).collect()
+}
+
+fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn table_name_allows_schema_and_order() {
+        assert_eq!(table_name("users"), Some("\"users\"".to_string()));
+        assert_eq!(table_name("01.public.users"), Some("\"public\".\"users\"".to_string()));
+        assert_eq!(table_name("a.b.c"), None);
+        assert_eq!(table_name("public."), None);
+    }
+
+    #[test]
+    fn csv_header_handles_quotes() {
+        assert_eq!(
+            csv_header("id, \"full, name\",\"say \"\"hi\"\"\"\n1,a,b"),
+            vec!["\"id\"", "\"full, name\"", "\"say \"\"hi\"\"\""],
+        );
+        assert!(csv_header("").is_empty());
+    }
+}
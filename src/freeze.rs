@@ -0,0 +1,304 @@
+//! Windows during which an environment's revisions are frozen, eg. around a
+//! big sales event or over weekends, given in its environment file:
+//!
+//! ```toml
+//! [[freeze]]
+//! reason = "Black Friday"
+//! from = 2021-11-25
+//! until = 2021-11-29
+//!
+//! [[freeze]]
+//! reason = "Weekend"
+//! cron = "0 16 * * fri"
+//! duration = "64h"
+//! applies_to = "destructive"
+//! ```
+//!
+//! Windows either fall between two moments, where a date alone for `until`
+//! includes the whole of that day, or start whenever a cron expression
+//! matches and last for the duration. All times are in UTC.
+use std::{convert::TryFrom, time::Duration};
+
+use chrono::{DateTime, Datelike, NaiveDate, NaiveDateTime, TimeZone, Timelike, Utc};
+use serde::{de, Deserialize, Deserializer};
+
+use crate::meta::parse_duration;
+
+/// Which revisions a freeze window holds back.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FreezeScope {
+    #[default]
+    All,
+    /// Only revisions with destructive statements, whether acknowledged or not
+    Destructive,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Schedule {
+    Between(DateTime<Utc>, DateTime<Utc>),
+    Recurring(Cron, Duration),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "FreezeWindowSettings")]
+pub struct FreezeWindow {
+    /// Why revisions are frozen, for the error refusing to apply them
+    pub reason: Option<String>,
+    pub applies_to: FreezeScope,
+    schedule: Schedule,
+}
+
+/// A freeze window as given in the environment file, before it's checked
+/// that it has one schedule or the other.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FreezeWindowSettings {
+    reason: Option<String>,
+    #[serde(default)]
+    applies_to: FreezeScope,
+    from: Option<toml::Value>,
+    until: Option<toml::Value>,
+    cron: Option<Cron>,
+    duration: Option<String>,
+}
+
+impl TryFrom<FreezeWindowSettings> for FreezeWindow {
+    type Error = String;
+
+    fn try_from(settings: FreezeWindowSettings) -> Result<Self, Self::Error> {
+        let schedule = match settings {
+            FreezeWindowSettings { from: Some(from), until: Some(until), cron: None, duration: None, .. } => {
+                Schedule::Between(moment(&from, false)?, moment(&until, true)?)
+            }
+            FreezeWindowSettings { from: None, until: None, cron: Some(ref cron), duration: Some(ref duration), .. } => {
+                let duration = parse_duration(duration)
+                    .ok_or_else(|| format!("invalid duration `{}`, expected eg. `90m`, `12h`, or `3d`", duration))?;
+
+                Schedule::Recurring(cron.clone(), duration)
+            }
+            _ => return Err("a freeze window needs either `from` and `until`, or `cron` and `duration`".to_string()),
+        };
+
+        Ok(Self {
+            reason: settings.reason,
+            applies_to: settings.applies_to,
+            schedule,
+        })
+    }
+}
+
+impl FreezeWindow {
+    /// When the window ends, if it's in effect at the moment given.
+    pub fn in_effect(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match &self.schedule {
+            Schedule::Between(from, until) => (*from <= now && now < *until).then_some(*until),
+            Schedule::Recurring(cron, duration) => {
+                let duration = chrono::Duration::from_std(*duration).ok()?;
+                let minute = now.with_second(0)?.with_nanosecond(0)?;
+
+                // The latest start is the one that ends last
+                (0..=duration.num_minutes())
+                    .map(|ago| minute - chrono::Duration::minutes(ago))
+                    .find(|start| cron.matches(start) && now < *start + duration)
+                    .map(|start| start + duration)
+            }
+        }
+    }
+
+    /// Describes the window for the error refusing to apply revisions
+    /// during it, given when it ends.
+    pub fn describe(&self, until: DateTime<Utc>) -> String {
+        let scope = match self.applies_to {
+            FreezeScope::All => "",
+            FreezeScope::Destructive => " for destructive revisions",
+        };
+        let reason = self.reason.as_ref().map(|reason| format!(" ({})", reason)).unwrap_or_default();
+
+        format!("{} until {}{}", scope, until.format("%Y-%m-%d %H:%M UTC"), reason)
+    }
+}
+
+/// The moment given by a TOML datetime or string, either of which may be
+/// a date alone, taken as the start of that day or else the end of it.
+fn moment(value: &toml::Value, end_of_day: bool) -> Result<DateTime<Utc>, String> {
+    let text = match value {
+        toml::Value::Datetime(datetime) => datetime.to_string(),
+        toml::Value::String(text) => text.clone(),
+        other => return Err(format!("expected a date or datetime, not `{}`", other)),
+    };
+
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(&text) {
+        return Ok(datetime.with_timezone(&Utc));
+    }
+    if let Ok(datetime) = NaiveDateTime::parse_from_str(&text, "%Y-%m-%dT%H:%M:%S") {
+        return Ok(Utc.from_utc_datetime(&datetime));
+    }
+
+    let date = NaiveDate::parse_from_str(&text, "%Y-%m-%d")
+        .map_err(|_| format!("invalid date `{}`, expected eg. `2021-11-25` or `2021-11-25T06:00:00Z`", text))?;
+    let date = if end_of_day { date.succ_opt().ok_or("date out of range")? } else { date };
+
+    Ok(Utc.from_utc_datetime(&date.and_hms_opt(0, 0, 0).ok_or("date out of range")?))
+}
+
+/// A cron expression of minutes, hours, days of the month, months, and
+/// days of the week, each a list of values, ranges, or `*`, optionally
+/// stepped (eg. `*/15`), with months and days of the week also by name.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Cron {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    /// Whether days of the month or of the week were left as `*`, as when
+    /// both are restricted, either matching will do
+    any_day: bool,
+    any_weekday: bool,
+}
+
+const MONTHS: &[&str] = &["jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec"];
+const WEEKDAYS: &[&str] = &["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+impl Cron {
+    pub fn parse(expr: &str) -> Result<Self, String> {
+        let fields: Vec<_> = expr.split_whitespace().collect();
+
+        let (minutes, hours, days, months, weekdays) = match fields.as_slice() {
+            [minutes, hours, days, months, weekdays] => (minutes, hours, days, months, weekdays),
+            _ => return Err(format!("`{}` should have 5 fields: minute, hour, day, month, and day of the week", expr)),
+        };
+
+        // Sunday can be either 0 or 7
+        let weekdays_mask = field(weekdays, 0, 7, WEEKDAYS, 0)?;
+
+        Ok(Self {
+            minutes: field(minutes, 0, 59, &[], 0)?,
+            hours: field(hours, 0, 23, &[], 0)?,
+            days: field(days, 1, 31, &[], 1)?,
+            months: field(months, 1, 12, MONTHS, 1)?,
+            weekdays: (weekdays_mask | (weekdays_mask >> 7)) & 0x7f,
+            any_day: *days == "*",
+            any_weekday: *weekdays == "*",
+        })
+    }
+
+    pub fn matches(&self, moment: &DateTime<Utc>) -> bool {
+        let has = |mask: u64, n: u32| mask & (1 << n) != 0;
+
+        let day = has(self.days, moment.day());
+        let weekday = has(self.weekdays, moment.weekday().num_days_from_sunday());
+        let day_matches = match (self.any_day, self.any_weekday) {
+            (false, false) => day || weekday,
+            _ => day && weekday,
+        };
+
+        has(self.minutes, moment.minute()) && has(self.hours, moment.hour()) && has(self.months, moment.month()) && day_matches
+    }
+}
+
+impl<'de> Deserialize<'de> for Cron {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let expr = String::deserialize(deserializer)?;
+
+        Self::parse(&expr).map_err(de::Error::custom)
+    }
+}
+
+/// The bitmask of the values a field of a cron expression matches, where
+/// names are numbered from `first_name`.
+fn field(field: &str, min: u32, max: u32, names: &[&str], first_name: u32) -> Result<u64, String> {
+    let invalid = || format!("invalid cron field `{}`", field);
+    let value = |text: &str| -> Result<u32, String> {
+        let lowercase = text.to_lowercase();
+
+        match names.iter().position(|name| *name == lowercase) {
+            Some(i) => Ok(i as u32 + first_name),
+            None => text.parse().ok().filter(|n| (min..=max).contains(n)).ok_or_else(invalid),
+        }
+    };
+
+    let mut mask = 0;
+
+    for item in field.split(',') {
+        let (range, step) = match item.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>().ok().filter(|step| *step > 0).ok_or_else(invalid)?),
+            None => (item, 1),
+        };
+        let (start, end) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((start, end)) => (value(start)?, value(end)?),
+            None => (value(range)?, value(range)?),
+        };
+
+        if start > end {
+            return Err(invalid());
+        }
+
+        for n in (start..=end).step_by(step as usize) {
+            mask |= 1 << n;
+        }
+    }
+
+    Ok(mask)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(y: i32, m: u32, d: u32, h: u32, min: u32) -> DateTime<Utc> {
+        Utc.ymd(y, m, d).and_hms(h, min, 0)
+    }
+
+    fn window(toml: &str) -> FreezeWindow {
+        toml::from_str(toml).unwrap()
+    }
+
+    #[test]
+    fn cron_matches_lists_ranges_steps_and_names() {
+        let cron = Cron::parse("*/15 9-17 * * mon-fri").unwrap();
+
+        // 2021-11-26 is a Friday
+        assert!(cron.matches(&at(2021, 11, 26, 9, 45)));
+        assert!(!cron.matches(&at(2021, 11, 26, 9, 50)));
+        assert!(!cron.matches(&at(2021, 11, 27, 9, 45)));
+
+        // Either day of the month or of the week, when both are restricted
+        let cron = Cron::parse("0 0 1 * 7").unwrap();
+        assert!(cron.matches(&at(2021, 12, 1, 0, 0)));
+        assert!(cron.matches(&at(2021, 11, 28, 0, 0)));
+        assert!(!cron.matches(&at(2021, 11, 29, 0, 0)));
+
+        assert!(Cron::parse("0 24 * * *").is_err());
+        assert!(Cron::parse("0 0 * *").is_err());
+        assert!(Cron::parse("0 0 * * fry").is_err());
+    }
+
+    #[test]
+    fn recurring_window_lasts_for_duration() {
+        let weekend = window("cron = \"0 16 * * fri\"\nduration = \"64h\"\napplies_to = \"destructive\"");
+
+        assert_eq!(weekend.applies_to, FreezeScope::Destructive);
+        assert_eq!(weekend.in_effect(at(2021, 11, 26, 15, 59)), None);
+        assert_eq!(weekend.in_effect(at(2021, 11, 26, 16, 0)), Some(at(2021, 11, 29, 8, 0)));
+        assert_eq!(weekend.in_effect(at(2021, 11, 29, 7, 59)), Some(at(2021, 11, 29, 8, 0)));
+        assert_eq!(weekend.in_effect(at(2021, 11, 29, 8, 0)), None);
+    }
+
+    #[test]
+    fn window_between_dates_includes_last_day() {
+        let sale = window("reason = \"Black Friday\"\nfrom = 2021-11-25\nuntil = 2021-11-29");
+
+        assert_eq!(sale.in_effect(at(2021, 11, 24, 23, 59)), None);
+        assert_eq!(sale.in_effect(at(2021, 11, 29, 23, 59)), Some(at(2021, 11, 30, 0, 0)));
+        assert_eq!(sale.describe(at(2021, 11, 30, 0, 0)), " until 2021-11-30 00:00 UTC (Black Friday)");
+
+        let exact = window("from = \"2021-11-25T06:00:00Z\"\nuntil = 2021-11-25T08:00:00Z");
+        assert_eq!(exact.in_effect(at(2021, 11, 25, 7, 0)), Some(at(2021, 11, 25, 8, 0)));
+
+        assert!(toml::from_str::<FreezeWindow>("from = 2021-11-25").is_err());
+        assert!(toml::from_str::<FreezeWindow>("cron = \"0 16 * * fri\"\nduration = \"a while\"").is_err());
+    }
+}
@@ -0,0 +1,702 @@
+//! Revisions generated for common operations that are easy to get wrong by
+//! hand, eg. `jrny plan add-column orders shipped_at timestamptz`, split
+//! across several revisions so that none holds a heavy lock for long, or
+//! breaks versions of the app still running while it's deployed.
+use crate::template::snake_case;
+
+/// Kept short, so that a statement stuck behind another's lock fails rather
+/// than blocking everything queued behind it in turn
+const LOCK_TIMEOUT: &str = "5s";
+
+// Rather than a lock timeout, which waiting on other transactions to finish
+// would count towards, failed builds are cleaned up to be run again
+const CONCURRENT_INDEX: &str = "-- jrny:no-transaction
+do $do$
+begin
+    if exists (select from pg_index where indexrelid = to_regclass('$$qualified$$') and not indisvalid) then
+        drop index $$qualified$$;
+    end if;
+end
+$do$;
+
+create index concurrently if not exists $$name$$ on $$table$$ ($$columns$$);
+";
+
+// The new column is given the old one's type, without its default or
+// constraints, and kept in sync with it whichever of them is written to
+const START_RENAME_COLUMN: &str = "begin;
+
+set local lock_timeout = '$$timeout$$';
+
+do $do$
+declare
+    data_type text := (
+        select format_type(atttypid, atttypmod)
+        from pg_attribute
+        where attrelid = '$$table$$'::regclass and attname = '$$from$$' and not attisdropped
+    );
+begin
+    if data_type is null then
+        raise exception 'column % of % does not exist', '$$from$$', '$$table$$';
+    end if;
+
+    execute format('alter table %s add column %I %s', '$$table$$', '$$to$$', data_type);
+end
+$do$;
+
+create function $$sync$$() returns trigger language plpgsql as $sync$
+begin
+    if tg_op = 'INSERT' then
+        if new.$$to$$ is null then
+            new.$$to$$ := new.$$from$$;
+        elsif new.$$from$$ is null then
+            new.$$from$$ := new.$$to$$;
+        end if;
+    elsif new.$$from$$ is distinct from old.$$from$$ then
+        new.$$to$$ := new.$$from$$;
+    elsif new.$$to$$ is distinct from old.$$to$$ then
+        new.$$from$$ := new.$$to$$;
+    end if;
+
+    return new;
+end
+$sync$;
+
+create trigger $$trigger$$ before insert or update on $$table$$
+    for each row execute procedure $$sync$$();
+
+commit;
+";
+
+/// The setting the app gives the current tenant in, eg. with `SET
+/// app.tenant_id = '42'`, which row-level security policies compare against
+const TENANT_SETTING: &str = "app.tenant_id";
+
+// The setting is compared as the column's own type, so that the policy can
+// use indexes on it, and a tenant that isn't set sees nothing
+const ROW_LEVEL_SECURITY: &str = "begin;
+
+set local lock_timeout = '$$timeout$$';
+
+alter table $$table$$ enable row level security;
+alter table $$table$$ force row level security;
+
+do $do$
+declare
+    data_type text := (
+        select format_type(atttypid, atttypmod)
+        from pg_attribute
+        where attrelid = '$$table$$'::regclass and attname = '$$column$$' and not attisdropped
+    );
+begin
+    if data_type is null then
+        raise exception 'column % of % does not exist', '$$column$$', '$$table$$';
+    end if;
+
+    execute format(
+        'create policy %I on %s using (%I = nullif(current_setting(%L, true), %L)::%s) with check (%I = nullif(current_setting(%L, true), %L)::%s)',
+        '$$policy$$', '$$table$$', '$$column$$', '$$setting$$', '', data_type, '$$column$$', '$$setting$$', '', data_type
+    );
+end
+$do$;
+
+commit;
+";
+
+/// An operation that `jrny plan` can generate revisions for.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Generator {
+    /// Adds a column that ends up `NOT NULL`: nullable first, then
+    /// backfilled, then constrained
+    AddColumn {
+        table: String,
+        column: String,
+        data_type: String,
+        /// The value for existing rows, left for filling in if not given
+        backfill: Option<String>,
+    },
+    /// Adds a foreign key or check constraint as `NOT VALID`, then
+    /// validates it in a later revision
+    AddConstraint {
+        table: String,
+        name: String,
+        /// Everything after the constraint's name, eg. `check (total > 0)`
+        definition: String,
+    },
+    /// Renames a column by adding the new one, keeping the two in sync
+    /// until the old one is dropped
+    RenameColumn { table: String, from: String, to: String },
+    /// Renames a table, leaving a view by the old name until it's dropped
+    RenameTable { from: String, to: String },
+    /// Enables row-level security on a table, with a policy limiting each
+    /// tenant to its own rows
+    Rls { table: String, tenant_column: String },
+    /// Indexes a table, optionally without blocking writes to it
+    Index {
+        table: String,
+        /// The indexed columns or expressions, as in their parentheses
+        columns: String,
+        concurrent: bool,
+    },
+}
+
+/// Options given to `jrny plan` that only some generators take.
+#[derive(Clone, Debug, Default)]
+pub struct GeneratorFlags {
+    /// The value to backfill a new column with
+    pub backfill: Option<String>,
+    /// Whether to build an index without blocking writes
+    pub concurrent: bool,
+    /// The column of a table giving the tenant each row belongs to
+    pub tenant_column: Option<String>,
+}
+
+/// A revision generated to be planned, in order with the others.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Generated {
+    /// The words of the revision's name, to be joined in the configured style
+    pub words: Vec<String>,
+    /// Whether the revision changes data rather than the schema
+    pub data: bool,
+    /// What the revision does, for the comment at the top of it
+    pub description: String,
+    /// The positions of the generated revisions that must be applied first
+    pub requires: Vec<usize>,
+    pub sql: String,
+}
+
+impl Generated {
+    /// The contents of the revision file, given its name and those of the
+    /// revisions it requires.
+    pub fn contents(&self, name: &str, requires: &[&str]) -> String {
+        const WIDTH: usize = 76;
+
+        let mut contents = String::new();
+        let mut frontmatter = vec![];
+
+        if self.data {
+            frontmatter.push("kind = \"data\"".to_string());
+        }
+        if !requires.is_empty() {
+            let names: Vec<_> = requires.iter().map(|name| format!("\"{}\"", name)).collect();
+            frontmatter.push(format!("requires = [{}]", names.join(", ")));
+        }
+        if !frontmatter.is_empty() {
+            contents.push_str("-- +++\n");
+            contents.extend(frontmatter.iter().map(|line| format!("-- {}\n", line)));
+            contents.push_str("-- +++\n");
+        }
+        contents.push_str(&format!("-- Revision: {}\n--\n", name));
+
+        let mut line = String::new();
+
+        for word in self.description.split_whitespace() {
+            if !line.is_empty() && line.len() + word.len() + 1 > WIDTH {
+                contents.push_str(&format!("-- {}\n", line));
+                line.clear();
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(word);
+        }
+
+        contents.push_str(&format!("-- {}\n\n{}", line, self.sql));
+        contents
+    }
+}
+
+impl Generator {
+    /// The generator by the name given to `jrny plan`, with its arguments,
+    /// or what's wrong with them.
+    pub fn parse(kind: &str, args: &[String], flags: &GeneratorFlags) -> Result<Self, String> {
+        match kind {
+            "add-column" => match args {
+                [table, column, data_type @ ..] if !data_type.is_empty() => Ok(Self::AddColumn {
+                    table: table.clone(),
+                    column: column.clone(),
+                    data_type: data_type.join(" "),
+                    backfill: flags.backfill.clone(),
+                }),
+                _ => Err("expected `add-column <table> <column> <type>`".to_string()),
+            },
+            "add-constraint" => match args {
+                [table, name, definition @ ..] if !definition.is_empty() => {
+                    let definition = definition.join(" ");
+                    let kind = definition.split(|c: char| !c.is_alphanumeric()).next().unwrap_or_default().to_lowercase();
+
+                    if kind != "foreign" && kind != "check" {
+                        return Err("only foreign key and check constraints can be validated separately".to_string());
+                    }
+
+                    Ok(Self::AddConstraint {
+                        table: table.clone(),
+                        name: name.clone(),
+                        definition: definition.trim().to_string(),
+                    })
+                }
+                _ => Err("expected `add-constraint <table> <name> <definition>`".to_string()),
+            },
+            "rename-column" => match args {
+                [table, from, to] => Ok(Self::RenameColumn { table: table.clone(), from: from.clone(), to: to.clone() }),
+                _ => Err("expected `rename-column <table> <column> <new name>`".to_string()),
+            },
+            "rename-table" => match args {
+                [from, to] if !to.contains('.') => Ok(Self::RenameTable { from: from.clone(), to: to.clone() }),
+                [_, _] => Err("the new name can't change the table's schema".to_string()),
+                _ => Err("expected `rename-table <table> <new name>`".to_string()),
+            },
+            "rls" => match (args, &flags.tenant_column) {
+                ([table], Some(column)) => Ok(Self::Rls { table: table.clone(), tenant_column: column.clone() }),
+                _ => Err("expected `rls <table> --tenant-column <column>`".to_string()),
+            },
+            "index" => match args {
+                [table, columns @ ..] if !columns.is_empty() => {
+                    let columns = columns.join(" ");
+                    let columns = columns.trim();
+                    // Only the parentheses around the whole list, not those of a last expression
+                    let columns = match columns.strip_prefix('(') {
+                        Some(inner) => inner.strip_suffix(')').unwrap_or(inner),
+                        None => columns,
+                    };
+
+                    Ok(Self::Index {
+                        table: table.clone(),
+                        columns: columns.trim().to_string(),
+                        concurrent: flags.concurrent,
+                    })
+                }
+                _ => Err("expected `index <table> (<columns>)`".to_string()),
+            },
+            _ => Err("not an operation that revisions can be generated for - expected `add-column`, `add-constraint`, `index`, \
+                  `rename-column`, `rename-table`, or `rls`"
+                .to_string()),
+        }
+    }
+
+    pub fn revisions(&self) -> Vec<Generated> {
+        match self {
+            Self::AddColumn { table, column, data_type, backfill } => {
+                add_column(table, column, data_type, backfill.as_deref())
+            }
+            Self::AddConstraint { table, name, definition } => add_constraint(table, name, definition),
+            Self::RenameColumn { table, from, to } => rename_column(table, from, to),
+            Self::RenameTable { from, to } => rename_table(from, to),
+            Self::Rls { table, tenant_column } => vec![rls(table, tenant_column)],
+            Self::Index { table, columns, concurrent } => vec![index(table, columns, *concurrent)],
+        }
+    }
+}
+
+fn add_column(table: &str, column: &str, data_type: &str, backfill: Option<&str>) -> Vec<Generated> {
+    let (_, unqualified) = split_schema(table);
+    let constraint = format!("{}_{}_not_null", unqualified, column);
+    let step = |n: usize, what: &str| format!("Step {} of 3 of adding `{}.{}`: {}", n, table, column, what);
+
+    let update = format!(
+        "-- jrny:batch size=10000 key=id\nupdate {} set {} = {} where {} is null;\n",
+        table,
+        column,
+        backfill.unwrap_or("<value>"),
+        column,
+    );
+
+    vec![
+        Generated {
+            words: words(&["add", unqualified, column]),
+            data: false,
+            description: step(1, "adds it as nullable and without a default, so that no rows are rewritten."),
+            requires: vec![],
+            sql: format!(
+                "begin;\n\nset local lock_timeout = '{}';\n\nalter table {} add column {} {};\n\ncommit;\n",
+                LOCK_TIMEOUT, table, column, data_type,
+            ),
+        },
+        Generated {
+            words: words(&["backfill", unqualified, column]),
+            data: true,
+            description: step(
+                2,
+                "fills it in for existing rows, in batches so as not to lock or rewrite the whole table at once.",
+            ),
+            requires: vec![0],
+            sql: match backfill {
+                Some(_) => update,
+                // Left commented out until the value is filled in, rather than backfilling nothing
+                None => format!(
+                    "-- Replace `<value>` with the value for existing rows, eg. another column,\n\
+                     -- and uncomment the statement (including its directive).\n\
+                     --\n{}",
+                    update.lines().map(|line| format!("-- {}\n", line)).collect::<String>(),
+                ),
+            },
+        },
+        Generated {
+            words: words(&["set", unqualified, column, "not", "null"]),
+            data: false,
+            description: step(
+                3,
+                "makes it `NOT NULL` without holding an exclusive lock while every row is checked, \
+                 by first validating a check constraint, which PostgreSQL 12 and later use to skip the check.",
+            ),
+            requires: vec![1],
+            sql: format!(
+                "begin;\n\
+                 set local lock_timeout = '{timeout}';\n\
+                 alter table {table} add constraint {constraint} check ({column} is not null) not valid;\n\
+                 commit;\n\n\
+                 alter table {table} validate constraint {constraint};\n\n\
+                 begin;\n\
+                 set local lock_timeout = '{timeout}';\n\
+                 alter table {table} alter column {column} set not null;\n\
+                 alter table {table} drop constraint {constraint};\n\
+                 commit;\n",
+                timeout = LOCK_TIMEOUT,
+                table = table,
+                column = column,
+                constraint = constraint,
+            ),
+        },
+    ]
+}
+
+fn add_constraint(table: &str, name: &str, definition: &str) -> Vec<Generated> {
+    let step = |n: usize, what: &str| format!("Step {} of 2 of adding `{}` to `{}`: {}", n, name, table, what);
+
+    vec![
+        Generated {
+            words: words(&["add", name]),
+            data: false,
+            description: step(
+                1,
+                "adds it as `NOT VALID`, so that only rows written from now on are checked and its lock is only held briefly.",
+            ),
+            requires: vec![],
+            sql: format!(
+                "begin;\n\nset local lock_timeout = '{}';\n\nalter table {} add constraint {} {} not valid;\n\ncommit;\n",
+                LOCK_TIMEOUT, table, name, definition,
+            ),
+        },
+        Generated {
+            words: words(&["validate", name]),
+            data: false,
+            description: step(
+                2,
+                "checks that existing rows meet it, without blocking writes to the table. Should any not, \
+                 fix them and apply this again.",
+            ),
+            requires: vec![0],
+            sql: format!("alter table {} validate constraint {};\n", table, name),
+        },
+    ]
+}
+
+fn rename_column(table: &str, from: &str, to: &str) -> Vec<Generated> {
+    let (schema, unqualified) = split_schema(table);
+    let sync = format!("{}_sync_{}_{}", unqualified, from, to);
+    let qualified_sync = schema.map_or_else(|| sync.clone(), |schema| format!("{}.{}", schema, sync));
+    let step = |n: usize, what: &str| format!("Step {} of 3 of renaming `{}.{}` to `{}`: {}", n, table, from, to, what);
+
+    vec![
+        Generated {
+            words: words(&["start", "rename", unqualified, from]),
+            data: false,
+            description: step(
+                1,
+                "adds the new column with the same type and a trigger keeping the two in sync, so that versions of \
+                 the app using either name keep working. Defaults, constraints, and indexes aren't copied.",
+            ),
+            requires: vec![],
+            sql: START_RENAME_COLUMN
+                .replace("$$timeout$$", LOCK_TIMEOUT)
+                .replace("$$table$$", table)
+                .replace("$$from$$", from)
+                .replace("$$to$$", to)
+                .replace("$$sync$$", &qualified_sync)
+                .replace("$$trigger$$", &sync),
+        },
+        Generated {
+            words: words(&["backfill", unqualified, to]),
+            data: true,
+            description: step(2, "copies the old column into the new one for existing rows, in batches."),
+            requires: vec![0],
+            sql: format!(
+                "-- jrny:batch size=10000 key=id\nupdate {} set {} = {} where {} is distinct from {};\n",
+                table, to, from, to, from,
+            ),
+        },
+        Generated {
+            words: words(&["finish", "rename", unqualified, from]),
+            data: false,
+            description: step(
+                3,
+                &format!(
+                    "drops the trigger and the old column. Only apply this once no running version of the app \
+                     uses `{}`, after recreating any defaults, constraints, and indexes on `{}`.",
+                    from, to,
+                ),
+            ),
+            requires: vec![1],
+            sql: format!(
+                "-- jrny:allow-destructive\n\
+                 begin;\n\n\
+                 set local lock_timeout = '{timeout}';\n\n\
+                 drop trigger {trigger} on {table};\n\
+                 drop function {sync}();\n\
+                 alter table {table} drop column {from};\n\n\
+                 commit;\n",
+                timeout = LOCK_TIMEOUT,
+                trigger = sync,
+                table = table,
+                sync = qualified_sync,
+                from = from,
+            ),
+        },
+    ]
+}
+
+fn rename_table(from: &str, to: &str) -> Vec<Generated> {
+    let (schema, unqualified) = split_schema(from);
+    let renamed = schema.map_or_else(|| to.to_string(), |schema| format!("{}.{}", schema, to));
+    let step = |n: usize, what: &str| format!("Step {} of 2 of renaming `{}` to `{}`: {}", n, from, renamed, what);
+
+    vec![
+        Generated {
+            words: words(&["start", "rename", unqualified]),
+            data: false,
+            description: step(
+                1,
+                "renames it, leaving a view by the old name in its place, which versions of the app using the old \
+                 name can keep reading from and writing to as PostgreSQL passes changes to simple views through.",
+            ),
+            requires: vec![],
+            sql: format!(
+                "begin;\n\n\
+                 set local lock_timeout = '{}';\n\n\
+                 alter table {} rename to {};\n\
+                 create view {} as select * from {};\n\n\
+                 commit;\n",
+                LOCK_TIMEOUT, from, to, from, renamed,
+            ),
+        },
+        Generated {
+            words: words(&["finish", "rename", unqualified]),
+            data: false,
+            description: step(
+                2,
+                &format!("drops the view. Only apply this once no running version of the app uses `{}`.", from),
+            ),
+            requires: vec![0],
+            sql: format!("-- jrny:allow-destructive\ndrop view {};\n", from),
+        },
+    ]
+}
+
+fn rls(table: &str, column: &str) -> Generated {
+    let (_, unqualified) = split_schema(table);
+
+    Generated {
+        words: words(&["enable", unqualified, "rls"]),
+        data: false,
+        description: format!(
+            "Limits `{}` to the rows of the tenant whose `{}` is given in the `{}` setting, eg. with \
+             `SET {} = '42'`, including for the table's owner. Roles with `BYPASSRLS`, such as superusers, \
+             still see every row.",
+            table, column, TENANT_SETTING, TENANT_SETTING,
+        ),
+        requires: vec![],
+        sql: ROW_LEVEL_SECURITY
+            .replace("$$timeout$$", LOCK_TIMEOUT)
+            .replace("$$table$$", table)
+            .replace("$$column$$", column)
+            .replace("$$policy$$", &format!("{}_tenant_isolation", unqualified))
+            .replace("$$setting$$", TENANT_SETTING),
+    }
+}
+
+fn index(table: &str, columns: &str, concurrent: bool) -> Generated {
+    let (schema, unqualified) = split_schema(table);
+    let name = format!("{}_{}_idx", unqualified, snake_case(columns));
+    // Indexes are always in their table's schema
+    let qualified = schema.map_or_else(|| name.clone(), |schema| format!("{}.{}", schema, name));
+    let words = words(&["add", unqualified, &snake_case(columns), "index"]);
+
+    if !concurrent {
+        return Generated {
+            words,
+            data: false,
+            description: format!(
+                "Indexes `{}` on `{}`, blocking writes to it while the index is built - \
+                 consider `--concurrent` for large or busy tables.",
+                table, columns,
+            ),
+            requires: vec![],
+            sql: format!(
+                "begin;\n\nset local lock_timeout = '{}';\n\ncreate index if not exists {} on {} ({});\n\ncommit;\n",
+                LOCK_TIMEOUT, name, table, columns,
+            ),
+        };
+    }
+
+    Generated {
+        words,
+        data: false,
+        description: format!(
+            "Indexes `{}` on `{}` without blocking writes to it, which can't be done in a transaction. \
+             A build that fails partway leaves an invalid index behind, which `IF NOT EXISTS` would then \
+             skip, so any is dropped first and the revision can simply be run again.",
+            table, columns,
+        ),
+        requires: vec![],
+        sql: CONCURRENT_INDEX
+            .replace("$$qualified$$", &qualified)
+            .replace("$$name$$", &name)
+            .replace("$$table$$", table)
+            .replace("$$columns$$", columns),
+    }
+}
+
+/// The schema of the name, if it's qualified, and the name without it.
+fn split_schema(name: &str) -> (Option<&str>, &str) {
+    match name.rsplit_once('.') {
+        Some((schema, name)) => (Some(schema), name),
+        None => (None, name),
+    }
+}
+
+fn words(words: &[&str]) -> Vec<String> {
+    words.iter().map(|word| word.to_string()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::statements::split;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn add_column_is_nullable_then_backfilled_then_not_null() {
+        let args = args(&["app.orders", "shipped_at", "timestamp", "with", "time", "zone"]);
+        let generator = Generator::parse("add-column", &args, &GeneratorFlags { backfill: Some("created_at".to_string()), ..Default::default() }).unwrap();
+        let revisions = generator.revisions();
+
+        let names: Vec<_> = revisions.iter().map(|r| r.words.join("_")).collect();
+        assert_eq!(names, vec!["add_orders_shipped_at", "backfill_orders_shipped_at", "set_orders_shipped_at_not_null"]);
+        assert_eq!(revisions.iter().map(|r| r.data).collect::<Vec<_>>(), vec![false, true, false]);
+
+        assert!(revisions[0].sql.contains("alter table app.orders add column shipped_at timestamp with time zone;"));
+        assert!(revisions[1].sql.starts_with("-- jrny:batch"));
+        assert!(revisions[1].sql.contains("set shipped_at = created_at where shipped_at is null;"));
+        assert_eq!(split(&revisions[2].sql).iter().filter(|s| s.leading_keyword().as_deref() == Some("alter")).count(), 4);
+    }
+
+    #[test]
+    fn add_constraint_is_not_valid_then_validated() {
+        let given = args(&["app.orders", "orders_user_id_fkey", "foreign", "key", "(user_id)", "references", "app.users", "(id)"]);
+        let revisions = Generator::parse("add-constraint", &given, &GeneratorFlags::default()).unwrap().revisions();
+
+        let names: Vec<_> = revisions.iter().map(|r| r.words.join("_")).collect();
+        assert_eq!(names, vec!["add_orders_user_id_fkey", "validate_orders_user_id_fkey"]);
+        assert!(revisions[0].sql.contains(
+            "alter table app.orders add constraint orders_user_id_fkey foreign key (user_id) references app.users (id) not valid;"
+        ));
+        assert_eq!(revisions[1].sql, "alter table app.orders validate constraint orders_user_id_fkey;\n");
+        assert_eq!(revisions[1].requires, vec![0]);
+        assert!(Generator::parse("add-constraint", &args(&["orders", "orders_pkey", "primary", "key", "(id)"]), &GeneratorFlags::default()).is_err());
+    }
+
+    #[test]
+    fn concurrent_index_runs_without_transaction_and_drops_invalid_index() {
+        let flags = GeneratorFlags { concurrent: true, ..Default::default() };
+        let generated = Generator::parse("index", &args(&["app.orders", "(customer_id,", "created_at)"]), &flags)
+            .unwrap()
+            .revisions()
+            .remove(0);
+        let statements = split(&generated.sql);
+
+        assert_eq!(generated.words.join("_"), "add_orders_customer_id_created_at_index");
+        assert!(crate::statements::outside_transaction(&statements));
+        assert!(statements[0].sql.contains("to_regclass('app.orders_customer_id_created_at_idx') and not indisvalid"));
+        assert!(statements[0].sql.contains("drop index app.orders_customer_id_created_at_idx;"));
+        assert_eq!(
+            statements[1].sql,
+            "create index concurrently if not exists orders_customer_id_created_at_idx on app.orders (customer_id, created_at)",
+        );
+    }
+
+    #[test]
+    fn index_without_concurrent_is_in_transaction() {
+        let generated = Generator::parse("index", &args(&["orders", "lower(email)"]), &GeneratorFlags::default())
+            .unwrap()
+            .revisions()
+            .remove(0);
+
+        assert!(generated.sql.contains("create index if not exists orders_lower_email_idx on orders (lower(email));"));
+        assert!(split(&generated.sql)[0].is_transaction_command());
+        assert!(Generator::parse("index", &args(&["orders"]), &GeneratorFlags::default()).is_err());
+    }
+
+    #[test]
+    fn renames_keep_old_name_working_until_finished() {
+        let columns = Generator::parse("rename-column", &args(&["app.users", "email", "email_address"]), &GeneratorFlags::default())
+            .unwrap()
+            .revisions();
+
+        assert_eq!(columns.iter().map(|r| r.requires.clone()).collect::<Vec<_>>(), vec![vec![], vec![0], vec![1]]);
+        assert!(columns[0].sql.contains("create trigger users_sync_email_email_address before insert or update on app.users"));
+        assert!(columns[0].sql.contains("execute procedure app.users_sync_email_email_address();"));
+        assert!(columns[1].sql.contains("update app.users set email_address = email where email_address is distinct from email;"));
+        assert!(columns[2].sql.starts_with("-- jrny:allow-destructive"));
+        assert!(columns[2].sql.contains("alter table app.users drop column email;"));
+
+        let tables = Generator::parse("rename-table", &args(&["app.orders", "purchases"]), &GeneratorFlags::default())
+            .unwrap()
+            .revisions();
+
+        assert_eq!(tables[0].words.join("_"), "start_rename_orders");
+        assert!(tables[0].sql.contains("alter table app.orders rename to purchases;\ncreate view app.orders as select * from app.purchases;"));
+        assert_eq!(tables[1].requires, vec![0]);
+        assert!(Generator::parse("rename-table", &args(&["app.orders", "sales.purchases"]), &GeneratorFlags::default()).is_err());
+    }
+
+    #[test]
+    fn rls_enables_security_and_adds_tenant_policy() {
+        let flags = GeneratorFlags { tenant_column: Some("org_id".to_string()), ..Default::default() };
+        let generated = Generator::parse("rls", &args(&["app.orders"]), &flags).unwrap().revisions().remove(0);
+        let statements = split(&generated.sql);
+
+        assert_eq!(generated.words.join("_"), "enable_orders_rls");
+        assert_eq!(statements[2].sql, "alter table app.orders enable row level security");
+        assert_eq!(statements[3].sql, "alter table app.orders force row level security");
+        assert!(statements[4].sql.contains("'orders_tenant_isolation', 'app.orders', 'org_id', 'app.tenant_id'"));
+        assert!(Generator::parse("rls", &args(&["app.orders"]), &GeneratorFlags::default()).is_err());
+    }
+
+    #[test]
+    fn contents_wrap_description_after_name() {
+        let generated = Generated {
+            words: vec![],
+            data: true,
+            description: "a ".repeat(40),
+            requires: vec![0],
+            sql: "select 1;\n".to_string(),
+        };
+
+        assert_eq!(
+            generated.contents("x", &["y"]),
+            format!("-- +++\n-- kind = \"data\"\n-- requires = [\"y\"]\n-- +++\n-- Revision: x\n--\n-- {}\n-- a a\n\nselect 1;\n", "a ".repeat(38).trim()),
+        );
+    }
+
+    #[test]
+    fn add_column_leaves_backfill_to_fill_in() {
+        let generator = Generator::parse("add-column", &args(&["orders", "shipped_at", "timestamptz"]), &GeneratorFlags::default()).unwrap();
+
+        assert!(split(&generator.revisions()[1].sql).is_empty());
+        assert!(Generator::parse("add-column", &args(&["orders", "shipped_at"]), &GeneratorFlags::default()).is_err());
+        assert!(Generator::parse("add-table", &args(&["orders"]), &GeneratorFlags::default()).is_err());
+    }
+}
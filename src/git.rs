@@ -0,0 +1,98 @@
+//! Details from git about where revisions being applied came from, and
+//! which have changed on a branch.
+//!
+//! As with HTTP requests, this shells out to `git` rather than linking
+//! against libgit2, and since this is mostly supplementary, anything
+//! that goes wrong (not a repository, `git` not installed, etc.) simply
+//! results in the details being absent.
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// The git commit and author recorded alongside an applied revision.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Provenance {
+    /// Hash of the commit checked out when the revision was applied
+    pub commit: Option<String>,
+    /// Branch checked out when the revision was applied, unless detached
+    pub branch: Option<String>,
+    /// Author of the last commit to change the revision file
+    pub author: Option<String>,
+}
+
+/// A git working tree containing the revisions directory.
+#[derive(Debug)]
+pub struct Repository {
+    /// The directory that commands are run from and paths are relative to
+    dir: PathBuf,
+    commit: String,
+    branch: Option<String>,
+}
+
+impl Repository {
+    /// Finds the repository containing the directory, if any.
+    pub fn discover(dir: &Path) -> Option<Self> {
+        let commit = git(dir, &["rev-parse", "HEAD"])?;
+        let branch = git(dir, &["rev-parse", "--abbrev-ref", "HEAD"]).filter(|b| b != "HEAD");
+
+        Some(Self {
+            dir: dir.to_path_buf(),
+            commit,
+            branch,
+        })
+    }
+
+    /// The provenance for the file, given relative to the directory the
+    /// repository was discovered from.
+    pub fn provenance(&self, filename: &str) -> Provenance {
+        Provenance {
+            commit: Some(self.commit.clone()),
+            branch: self.branch.clone(),
+            author: git(&self.dir, &["log", "-1", "--format=%an <%ae>", "--", filename]),
+        }
+    }
+}
+
+/// The paths, relative to the directory, of files within it that are
+/// untracked or differ from the commit that HEAD has in common with the
+/// ref, eg. those added or changed on a branch to be merged into `main`.
+/// Renamed files are given by both their old and new paths.
+pub fn changed_since(dir: &Path, reference: &str) -> Option<Vec<PathBuf>> {
+    let base = git(dir, &["merge-base", reference, "HEAD"])?;
+    let changed = lines(dir, &["diff", "--name-only", "--no-renames", "--relative", &base, "--", "."])?;
+    let untracked = lines(dir, &["ls-files", "--others", "--exclude-standard", "--", "."])?;
+
+    Some(changed.into_iter().chain(untracked).map(PathBuf::from).collect())
+}
+
+/// The contents of the file, given relative to the directory, as of the
+/// commit, if it was committed by then.
+pub fn show(dir: &Path, commit: &str, filename: &str) -> Option<String> {
+    output(dir, &["show", &format!("{}:./{}", commit, filename)])
+}
+
+/// Runs git in the directory, returning its trimmed output if successful
+/// and non-empty.
+fn git(dir: &Path, args: &[&str]) -> Option<String> {
+    let stdout = output(dir, args)?;
+    let stdout = stdout.trim();
+
+    (!stdout.is_empty()).then(|| stdout.to_string())
+}
+
+/// Runs git in the directory, returning the lines of its output if
+/// successful, of which there may be none.
+fn lines(dir: &Path, args: &[&str]) -> Option<Vec<String>> {
+    Some(output(dir, args)?.lines().filter(|line| !line.is_empty()).map(str::to_string).collect())
+}
+
+fn output(dir: &Path, args: &[&str]) -> Option<String> {
+    let output = Command::new("git").arg("-C").arg(dir).args(args).output().ok()?;
+
+    if !output.status.success() {
+        return None;
+    }
+
+    String::from_utf8(output.stdout).ok()
+}
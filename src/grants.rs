@@ -0,0 +1,541 @@
+//! Roles, their memberships, and the default privileges they grant, declared
+//! in `roles.toml` alongside the config rather than created by revisions, as
+//! roles belong to the whole cluster rather than any one database and often
+//! differ between environments:
+//!
+//! ```toml
+//! [roles.app_owner]
+//!
+//! [roles.readonly]
+//!
+//! [roles.reporting]
+//! login = true
+//! environments = ["production"]
+//!
+//! [[memberships]]
+//! role = "readonly"
+//! member = "reporting"
+//!
+//! [[default_privileges]]
+//! owner = "app_owner"
+//! schema = "public"
+//! on = "tables"
+//! privileges = ["SELECT"]
+//! grantee = "readonly"
+//! ```
+//!
+//! `jrny grants diff` lists what the cluster lacks or has beyond the file,
+//! and `jrny grants apply` changes it to match. Only the roles declared are
+//! managed: no role is ever dropped, and memberships and default privileges
+//! are only revoked between roles that are both declared.
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fs,
+    path::Path,
+};
+
+use postgres::Client;
+use serde::Deserialize;
+
+use crate::{revisions::is_applicable, settings::Problem, Error, Result};
+
+const ROLES: &str = "
+SELECT rolname, rolcanlogin, rolcreatedb, rolcreaterole, rolinherit, rolconnlimit
+FROM pg_roles
+";
+
+const MEMBERSHIPS: &str = "
+SELECT r.rolname AS role, m.rolname AS member
+FROM pg_auth_members a
+JOIN pg_roles r ON r.oid = a.roleid
+JOIN pg_roles m ON m.oid = a.member
+";
+
+/// Each privilege granted by default, other than to the role creating the
+/// objects itself, which it has regardless, or to `PUBLIC`.
+const DEFAULT_PRIVILEGES: &str = "
+SELECT
+    pg_get_userbyid(d.defaclrole) AS owner,
+    n.nspname AS schema,
+    d.defaclobjtype::text AS kind,
+    pg_get_userbyid(a.grantee) AS grantee,
+    a.privilege_type AS privilege
+FROM pg_default_acl d
+LEFT JOIN pg_namespace n ON n.oid = d.defaclnamespace
+CROSS JOIN LATERAL aclexplode(d.defaclacl) a
+WHERE a.grantee <> 0 AND a.grantee <> d.defaclrole
+";
+
+/// A role's attributes, which are those of `CREATE ROLE` other than
+/// `SUPERUSER`, `REPLICATION`, `BYPASSRLS`, and its password, which are
+/// left to be managed by hand.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct RoleSpec {
+    #[serde(default)]
+    pub login: bool,
+    #[serde(default)]
+    pub createdb: bool,
+    #[serde(default)]
+    pub createrole: bool,
+    #[serde(default = "default_inherit")]
+    pub inherit: bool,
+    /// How many connections the role can make at once, if limited
+    pub connection_limit: Option<i32>,
+    /// Environments the role is limited to, if any
+    #[serde(default)]
+    pub environments: Vec<String>,
+}
+
+fn default_inherit() -> bool {
+    true
+}
+
+impl Default for RoleSpec {
+    fn default() -> Self {
+        Self {
+            login: false,
+            createdb: false,
+            createrole: false,
+            inherit: true,
+            connection_limit: None,
+            environments: vec![],
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct Membership {
+    /// The role granted
+    pub role: String,
+    /// The role it's granted to
+    pub member: String,
+    #[serde(default)]
+    pub environments: Vec<String>,
+}
+
+/// The kinds of objects that default privileges are given for.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum ObjectKind {
+    Tables,
+    Sequences,
+    Functions,
+    Types,
+    Schemas,
+}
+
+impl ObjectKind {
+    /// The kind as `pg_default_acl.defaclobjtype` gives it.
+    fn from_code(code: &str) -> Option<Self> {
+        match code {
+            "r" => Some(Self::Tables),
+            "S" => Some(Self::Sequences),
+            "f" => Some(Self::Functions),
+            "T" => Some(Self::Types),
+            "n" => Some(Self::Schemas),
+            _ => None,
+        }
+    }
+
+    fn keyword(self) -> &'static str {
+        match self {
+            Self::Tables => "TABLES",
+            Self::Sequences => "SEQUENCES",
+            Self::Functions => "FUNCTIONS",
+            Self::Types => "TYPES",
+            Self::Schemas => "SCHEMAS",
+        }
+    }
+
+    /// The privileges that can be granted on objects of the kind.
+    fn privileges(self) -> &'static [&'static str] {
+        match self {
+            Self::Tables => &["SELECT", "INSERT", "UPDATE", "DELETE", "TRUNCATE", "REFERENCES", "TRIGGER"],
+            Self::Sequences => &["USAGE", "SELECT", "UPDATE"],
+            Self::Functions => &["EXECUTE"],
+            Self::Types => &["USAGE"],
+            Self::Schemas => &["USAGE", "CREATE"],
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct DefaultPrivilege {
+    /// The role whose new objects are granted on, which is whoever creates
+    /// them rather than who ends up owning them
+    pub owner: String,
+    /// The schema the objects are created in, or any if not given
+    pub schema: Option<String>,
+    pub on: ObjectKind,
+    pub privileges: Vec<String>,
+    pub grantee: String,
+    #[serde(default)]
+    pub environments: Vec<String>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct Roles {
+    #[serde(default)]
+    pub roles: BTreeMap<String, RoleSpec>,
+    #[serde(default)]
+    pub memberships: Vec<Membership>,
+    #[serde(default)]
+    pub default_privileges: Vec<DefaultPrivilege>,
+}
+
+impl Roles {
+    pub fn from_path(path: &Path) -> Result<Self> {
+        if !path.is_file() {
+            return Err(Error::FileNotValid(path.display().to_string()));
+        }
+
+        let contents = fs::read_to_string(path)?;
+        let settings: toml::Value =
+            toml::from_str(&contents).map_err(|e| Error::TomlInvalid(e, path.display().to_string()))?;
+        let mut roles: Self = settings
+            .try_into()
+            .map_err(|e| Problem::from_toml(&e).into_error(path, &[(path, &contents)]))?;
+
+        roles.check().map_err(|reason| Error::SettingInvalid(path.display().to_string(), None, reason))?;
+
+        Ok(roles)
+    }
+
+    /// Checks that each default privilege can be granted, giving them in
+    /// uppercase as Postgres names them.
+    fn check(&mut self) -> std::result::Result<(), String> {
+        for privilege in &mut self.default_privileges {
+            if privilege.on == ObjectKind::Schemas && privilege.schema.is_some() {
+                return Err(format!("default privileges on schemas for `{}` can't be limited to a schema", privilege.grantee));
+            }
+
+            for name in &mut privilege.privileges {
+                *name = name.to_uppercase();
+
+                if !privilege.on.privileges().contains(&name.as_str()) {
+                    return Err(format!(
+                        "`{}` can't be granted on {}, expected one of {}",
+                        name,
+                        privilege.on.keyword().to_lowercase(),
+                        privilege.on.privileges().join(", "),
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Only what applies to the environment.
+    pub fn for_environment(mut self, env_name: Option<&str>) -> Self {
+        self.roles.retain(|_, role| is_applicable(&role.environments, env_name));
+        self.memberships.retain(|membership| is_applicable(&membership.environments, env_name));
+        self.default_privileges.retain(|privilege| is_applicable(&privilege.environments, env_name));
+        self
+    }
+}
+
+/// A single privilege granted by default, as it is in the cluster.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DefaultGrant {
+    pub owner: String,
+    pub schema: Option<String>,
+    pub on: ObjectKind,
+    pub grantee: String,
+    pub privilege: String,
+}
+
+/// The roles, memberships, and default privileges as they are in the
+/// cluster, the last of these being those of the connected database.
+#[derive(Debug, Default)]
+pub struct Cluster {
+    pub roles: HashMap<String, RoleSpec>,
+    /// Each role granted, and the role it's granted to
+    pub memberships: BTreeSet<(String, String)>,
+    pub default_privileges: BTreeSet<DefaultGrant>,
+}
+
+impl Cluster {
+    pub fn load(client: &mut Client) -> Result<Self> {
+        let roles = client
+            .query(ROLES, &[])?
+            .iter()
+            .map(|row| {
+                let limit: i32 = row.get("rolconnlimit");
+                let role = RoleSpec {
+                    login: row.get("rolcanlogin"),
+                    createdb: row.get("rolcreatedb"),
+                    createrole: row.get("rolcreaterole"),
+                    inherit: row.get("rolinherit"),
+                    connection_limit: Some(limit).filter(|limit| *limit >= 0),
+                    environments: vec![],
+                };
+
+                (row.get("rolname"), role)
+            })
+            .collect();
+
+        let memberships = client
+            .query(MEMBERSHIPS, &[])?
+            .iter()
+            .map(|row| (row.get("role"), row.get("member")))
+            .collect();
+
+        let default_privileges = client
+            .query(DEFAULT_PRIVILEGES, &[])?
+            .iter()
+            .filter_map(|row| {
+                Some(DefaultGrant {
+                    owner: row.get("owner"),
+                    schema: row.get("schema"),
+                    on: ObjectKind::from_code(row.get("kind"))?,
+                    grantee: row.get("grantee"),
+                    privilege: row.get("privilege"),
+                })
+            })
+            .collect();
+
+        Ok(Self {
+            roles,
+            memberships,
+            default_privileges,
+        })
+    }
+}
+
+/// A statement bringing the cluster closer to what's declared.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Change {
+    /// Creates or grants what the cluster lacks
+    Add(String),
+    /// Changes a role's attributes
+    Alter(String),
+    /// Revokes what isn't declared
+    Remove(String),
+}
+
+impl Change {
+    pub fn sql(&self) -> &str {
+        match self {
+            Self::Add(sql) | Self::Alter(sql) | Self::Remove(sql) => sql,
+        }
+    }
+}
+
+/// The statements making the cluster match the declarations, in the order
+/// they're to be run: roles first, so that they exist to be granted, then
+/// memberships, then default privileges, each granting before revoking.
+pub fn changes(declared: &Roles, cluster: &Cluster) -> Vec<Change> {
+    let mut changes = vec![];
+
+    for (name, role) in &declared.roles {
+        match cluster.roles.get(name) {
+            None => changes.push(Change::Add(format!("CREATE ROLE {}{}", quote_identifier(name), attributes(role, None)))),
+            Some(live) if !same_attributes(role, live) => {
+                changes.push(Change::Alter(format!("ALTER ROLE {}{}", quote_identifier(name), attributes(role, Some(live)))));
+            }
+            Some(_) => {}
+        }
+    }
+
+    let managed = |role: &str| declared.roles.contains_key(role);
+    let memberships: BTreeSet<_> = declared
+        .memberships
+        .iter()
+        .map(|membership| (membership.role.clone(), membership.member.clone()))
+        .collect();
+
+    for (role, member) in memberships.difference(&cluster.memberships) {
+        changes.push(Change::Add(format!("GRANT {} TO {}", quote_identifier(role), quote_identifier(member))));
+    }
+    for (role, member) in cluster.memberships.difference(&memberships) {
+        if managed(role) && managed(member) {
+            changes.push(Change::Remove(format!("REVOKE {} FROM {}", quote_identifier(role), quote_identifier(member))));
+        }
+    }
+
+    let grants: BTreeSet<_> = declared
+        .default_privileges
+        .iter()
+        .flat_map(|declared| {
+            declared.privileges.iter().map(move |privilege| DefaultGrant {
+                owner: declared.owner.clone(),
+                schema: declared.schema.clone(),
+                on: declared.on,
+                grantee: declared.grantee.clone(),
+                privilege: privilege.clone(),
+            })
+        })
+        .collect();
+    let revoked = cluster
+        .default_privileges
+        .difference(&grants)
+        .filter(|grant| managed(&grant.owner) && managed(&grant.grantee));
+
+    changes.extend(grouped(grants.difference(&cluster.default_privileges)).map(|(grant, privileges)| {
+        Change::Add(format!(
+            "ALTER DEFAULT PRIVILEGES {}GRANT {} ON {} TO {}",
+            target(grant),
+            privileges.join(", "),
+            grant.on.keyword(),
+            quote_identifier(&grant.grantee),
+        ))
+    }));
+    changes.extend(grouped(revoked).map(|(grant, privileges)| {
+        Change::Remove(format!(
+            "ALTER DEFAULT PRIVILEGES {}REVOKE {} ON {} FROM {}",
+            target(grant),
+            privileges.join(", "),
+            grant.on.keyword(),
+            quote_identifier(&grant.grantee),
+        ))
+    }));
+
+    changes
+}
+
+fn same_attributes(declared: &RoleSpec, live: &RoleSpec) -> bool {
+    (declared.login, declared.createdb, declared.createrole, declared.inherit, declared.connection_limit)
+        == (live.login, live.createdb, live.createrole, live.inherit, live.connection_limit)
+}
+
+/// The role's attributes as given to `CREATE ROLE`, or to `ALTER ROLE` for
+/// those differing from the role as it is, each with a space before it.
+/// Creating a role, only those other than the defaults are given.
+fn attributes(role: &RoleSpec, live: Option<&RoleSpec>) -> String {
+    let current = live.cloned().unwrap_or_default();
+    let mut attributes = String::new();
+    let mut flag = |wanted: bool, current: bool, name: &str| {
+        if wanted != current {
+            attributes.push_str(if wanted { " " } else { " NO" });
+            attributes.push_str(name);
+        }
+    };
+
+    flag(role.login, current.login, "LOGIN");
+    flag(role.createdb, current.createdb, "CREATEDB");
+    flag(role.createrole, current.createrole, "CREATEROLE");
+    flag(role.inherit, current.inherit, "INHERIT");
+
+    if role.connection_limit != current.connection_limit {
+        attributes.push_str(&format!(" CONNECTION LIMIT {}", role.connection_limit.unwrap_or(-1)));
+    }
+
+    attributes
+}
+
+/// The privileges of each grant alike but for them, as granted together.
+fn grouped<'a>(grants: impl Iterator<Item = &'a DefaultGrant>) -> impl Iterator<Item = (&'a DefaultGrant, Vec<&'a str>)> {
+    let mut grouped: Vec<(&DefaultGrant, Vec<&str>)> = vec![];
+
+    for grant in grants {
+        match grouped.iter_mut().find(|(first, _)| {
+            (&first.owner, &first.schema, first.on, &first.grantee) == (&grant.owner, &grant.schema, grant.on, &grant.grantee)
+        }) {
+            Some((_, privileges)) => privileges.push(&grant.privilege),
+            None => grouped.push((grant, vec![&grant.privilege])),
+        }
+    }
+
+    grouped.into_iter()
+}
+
+/// What `ALTER DEFAULT PRIVILEGES` applies to, with a space after it.
+fn target(grant: &DefaultGrant) -> String {
+    match &grant.schema {
+        Some(schema) => format!("FOR ROLE {} IN SCHEMA {} ", quote_identifier(&grant.owner), quote_identifier(schema)),
+        None => format!("FOR ROLE {} ", quote_identifier(&grant.owner)),
+    }
+}
+
+fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roles(toml: &str) -> Roles {
+        let mut roles: Roles = toml::from_str(toml).unwrap();
+        roles.check().unwrap();
+        roles
+    }
+
+    fn sql(changes: &[Change]) -> Vec<&str> {
+        changes.iter().map(Change::sql).collect()
+    }
+
+    #[test]
+    fn missing_roles_are_created_and_changed_ones_altered() {
+        let declared = roles(
+            "[roles.app]\nlogin = true\nconnection_limit = 10\n\
+             [roles.readonly]\n\
+             [roles.reporting]\nlogin = true\ninherit = false\n",
+        );
+        let cluster = Cluster {
+            roles: HashMap::from([
+                ("readonly".to_string(), RoleSpec::default()),
+                ("reporting".to_string(), RoleSpec::default()),
+            ]),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            sql(&changes(&declared, &cluster)),
+            vec!["CREATE ROLE \"app\" LOGIN CONNECTION LIMIT 10", "ALTER ROLE \"reporting\" LOGIN NOINHERIT"],
+        );
+    }
+
+    #[test]
+    fn grants_are_reconciled_between_declared_roles() {
+        let declared = roles(
+            "[roles.app_owner]\n[roles.readonly]\n[roles.reporting]\n\
+             [[memberships]]\nrole = \"readonly\"\nmember = \"reporting\"\n\
+             [[default_privileges]]\nowner = \"app_owner\"\nschema = \"public\"\non = \"tables\"\n\
+             privileges = [\"select\", \"insert\"]\ngrantee = \"readonly\"\n",
+        );
+        let grant = |grantee: &str, privilege: &str| DefaultGrant {
+            owner: "app_owner".to_string(),
+            schema: Some("public".to_string()),
+            on: ObjectKind::Tables,
+            grantee: grantee.to_string(),
+            privilege: privilege.to_string(),
+        };
+        let cluster = Cluster {
+            roles: ["app_owner", "readonly", "reporting"].iter().map(|name| (name.to_string(), RoleSpec::default())).collect(),
+            memberships: BTreeSet::from([
+                ("readonly".to_string(), "app_owner".to_string()),
+                ("pg_monitor".to_string(), "reporting".to_string()),
+            ]),
+            default_privileges: BTreeSet::from([
+                grant("readonly", "SELECT"),
+                grant("reporting", "UPDATE"),
+                grant("reporting", "DELETE"),
+                grant("someone_else", "SELECT"),
+            ]),
+        };
+
+        assert_eq!(
+            sql(&changes(&declared, &cluster)),
+            vec![
+                "GRANT \"readonly\" TO \"reporting\"",
+                "REVOKE \"readonly\" FROM \"app_owner\"",
+                "ALTER DEFAULT PRIVILEGES FOR ROLE \"app_owner\" IN SCHEMA \"public\" GRANT INSERT ON TABLES TO \"readonly\"",
+                "ALTER DEFAULT PRIVILEGES FOR ROLE \"app_owner\" IN SCHEMA \"public\" REVOKE DELETE, UPDATE ON TABLES FROM \"reporting\"",
+            ],
+        );
+    }
+
+    #[test]
+    fn only_grantable_privileges_can_be_declared() {
+        let mut declared: Roles =
+            toml::from_str("[[default_privileges]]\nowner = \"a\"\non = \"functions\"\nprivileges = [\"select\"]\ngrantee = \"b\"\n").unwrap();
+
+        assert_eq!(declared.check().unwrap_err(), "`SELECT` can't be granted on functions, expected one of EXECUTE");
+    }
+}
@@ -0,0 +1,409 @@
+//! Compresses revisions archived in the tracking schema as gzip, written by
+//! hand rather than pulling in a crate for it, so that archived copies can
+//! still be read by anything else that reads gzip, eg. `gunzip`.
+//!
+//! Compressing finds repeated runs within the last 32 KiB and codes them
+//! with DEFLATE's fixed Huffman codes, which is plenty for SQL. Decompressing
+//! reads any gzip, whichever codes it was compressed with.
+
+/// How far back repeated runs are looked for, and longest and shortest runs.
+const WINDOW: usize = 32 * 1024;
+const MAX_RUN: usize = 258;
+const MIN_RUN: usize = 3;
+
+/// How many earlier runs starting with the same bytes are tried, at most.
+const MAX_CHAIN: usize = 64;
+
+const LENGTH_BASES: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DISTANCE_BASES: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145,
+    8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+
+/// The order that the lengths of the codes for code lengths are given in.
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+const END_OF_BLOCK: u16 = 256;
+
+/// The bytes compressed as a gzip member.
+pub fn compress(bytes: &[u8]) -> Vec<u8> {
+    // No file name or modification time, so the same bytes always compress
+    // to the same archive
+    let mut out = BitWriter { bytes: vec![0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 0xff], bit: 0 };
+
+    // A single final block of fixed codes
+    out.write(1, 1);
+    out.write(1, 2);
+
+    let mut heads = vec![usize::MAX; 1 << 15];
+    let mut previous = vec![usize::MAX; bytes.len()];
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let (length, distance) = longest_run(bytes, i, &heads, &previous);
+
+        if length >= MIN_RUN {
+            out.write_run(length, distance);
+        } else {
+            out.write_literal(bytes[i]);
+        }
+
+        for (start, previous) in previous.iter_mut().enumerate().skip(i).take(length.max(1)) {
+            if let Some(hash) = hash(bytes, start) {
+                *previous = heads[hash];
+                heads[hash] = start;
+            }
+        }
+
+        i += length.max(1);
+    }
+
+    out.write_symbol(END_OF_BLOCK);
+
+    let mut bytes_out = out.bytes;
+
+    bytes_out.extend(crc32(bytes).to_le_bytes());
+    bytes_out.extend((bytes.len() as u32).to_le_bytes());
+    bytes_out
+}
+
+/// The bytes compressed in the gzip member, or `None` if it isn't one or
+/// is corrupt.
+pub fn decompress(gzip: &[u8]) -> Option<Vec<u8>> {
+    let (flags, mut start) = match gzip {
+        [0x1f, 0x8b, 8, flags, ..] if gzip.len() >= 18 => (*flags, 10),
+        _ => return None,
+    };
+
+    // An extra field, file name, comment, and header checksum, if flagged
+    if flags & 4 != 0 {
+        let length = u16::from_le_bytes([*gzip.get(start)?, *gzip.get(start + 1)?]) as usize;
+        start += 2 + length;
+    }
+    for flag in [8, 16] {
+        if flags & flag != 0 {
+            start += gzip.get(start..)?.iter().position(|&b| b == 0)? + 1;
+        }
+    }
+    if flags & 2 != 0 {
+        start += 2;
+    }
+
+    let trailer = gzip.len().checked_sub(8)?;
+    let bytes = inflate(gzip.get(start..trailer)?)?;
+    let crc = u32::from_le_bytes(gzip[trailer..trailer + 4].try_into().ok()?);
+    let size = u32::from_le_bytes(gzip[trailer + 4..].try_into().ok()?);
+
+    (crc == crc32(&bytes) && size == bytes.len() as u32).then_some(bytes)
+}
+
+/// Three bytes from the position hashed, if there are that many.
+fn hash(bytes: &[u8], i: usize) -> Option<usize> {
+    let run = bytes.get(i..i + MIN_RUN)?;
+
+    Some(((run[0] as usize) << 10 ^ (run[1] as usize) << 5 ^ run[2] as usize) & ((1 << 15) - 1))
+}
+
+/// The length and distance back of the longest earlier run that the bytes
+/// from the position repeat, if any.
+fn longest_run(bytes: &[u8], i: usize, heads: &[usize], previous: &[usize]) -> (usize, usize) {
+    let mut candidate = match hash(bytes, i) {
+        Some(hash) => heads[hash],
+        None => return (0, 0),
+    };
+    let max = (bytes.len() - i).min(MAX_RUN);
+    let mut best = (0, 0);
+
+    for _ in 0..MAX_CHAIN {
+        if candidate == usize::MAX || i - candidate > WINDOW {
+            break;
+        }
+
+        let length = (0..max).take_while(|&j| bytes[candidate + j] == bytes[i + j]).count();
+
+        if length > best.0 {
+            best = (length, i - candidate);
+        }
+        if length == max {
+            break;
+        }
+
+        candidate = previous[candidate];
+    }
+
+    best
+}
+
+struct BitWriter {
+    bytes: Vec<u8>,
+    /// How many bits of the last byte are written
+    bit: u8,
+}
+
+impl BitWriter {
+    /// Writes the value's lowest bits, lowest first.
+    fn write(&mut self, value: u32, bits: u8) {
+        for i in 0..bits {
+            if self.bit == 0 {
+                self.bytes.push(0);
+            }
+
+            *self.bytes.last_mut().unwrap() |= (((value >> i) & 1) as u8) << self.bit;
+            self.bit = (self.bit + 1) % 8;
+        }
+    }
+
+    /// Writes the Huffman code, highest bit first.
+    fn write_code(&mut self, code: u32, bits: u8) {
+        for i in (0..bits).rev() {
+            self.write((code >> i) & 1, 1);
+        }
+    }
+
+    fn write_symbol(&mut self, symbol: u16) {
+        let symbol = symbol as u32;
+
+        match symbol {
+            0..=143 => self.write_code(0x30 + symbol, 8),
+            144..=255 => self.write_code(0x190 + symbol - 144, 9),
+            256..=279 => self.write_code(symbol - 256, 7),
+            _ => self.write_code(0xc0 + symbol - 280, 8),
+        }
+    }
+
+    fn write_literal(&mut self, byte: u8) {
+        self.write_symbol(byte as u16);
+    }
+
+    fn write_run(&mut self, length: usize, distance: usize) {
+        let code = LENGTH_BASES.iter().rposition(|&base| base as usize <= length).unwrap();
+        self.write_symbol(257 + code as u16);
+        self.write((length - LENGTH_BASES[code] as usize) as u32, LENGTH_EXTRA[code]);
+
+        let code = DISTANCE_BASES.iter().rposition(|&base| base as usize <= distance).unwrap();
+        self.write_code(code as u32, 5);
+        self.write((distance - DISTANCE_BASES[code] as usize) as u32, DISTANCE_EXTRA[code]);
+    }
+}
+
+struct BitReader<'a> {
+    bytes: &'a [u8],
+    /// How many bits have been read
+    position: usize,
+}
+
+impl BitReader<'_> {
+    /// Reads that many bits as a value, lowest first.
+    fn read(&mut self, bits: u8) -> Option<u32> {
+        let mut value = 0;
+
+        for i in 0..bits {
+            let byte = self.bytes.get(self.position / 8)?;
+            value |= (((byte >> (self.position % 8)) & 1) as u32) << i;
+            self.position += 1;
+        }
+
+        Some(value)
+    }
+
+    /// Reads the next symbol coded by the Huffman codes.
+    fn decode(&mut self, codes: &Huffman) -> Option<u16> {
+        let (mut code, mut first, mut index) = (0, 0, 0);
+
+        for count in &codes.counts[1..] {
+            code |= self.read(1)? as usize;
+
+            if code < first + count {
+                return codes.symbols.get(index + code - first).copied();
+            }
+
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+
+        None
+    }
+}
+
+/// Canonical Huffman codes, by how many codes there are of each length and
+/// the symbols they code in order.
+struct Huffman {
+    counts: [usize; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Self {
+        let mut counts = [0; 16];
+
+        for &length in lengths {
+            counts[length as usize] += 1;
+        }
+        counts[0] = 0;
+
+        let mut symbols: Vec<_> = (0..lengths.len() as u16).filter(|&s| lengths[s as usize] != 0).collect();
+        symbols.sort_by_key(|&s| lengths[s as usize]);
+
+        Self { counts, symbols }
+    }
+
+    fn fixed() -> (Self, Self) {
+        let lengths: Vec<u8> = (0..288).map(|s| match s {
+            0..=143 => 8,
+            144..=255 => 9,
+            256..=279 => 7,
+            _ => 8,
+        }).collect();
+
+        (Self::new(&lengths), Self::new(&[5; 30]))
+    }
+}
+
+fn inflate(deflated: &[u8]) -> Option<Vec<u8>> {
+    let mut reader = BitReader { bytes: deflated, position: 0 };
+    let mut out = vec![];
+
+    loop {
+        let last = reader.read(1)? == 1;
+
+        match reader.read(2)? {
+            0 => {
+                reader.position = reader.position.div_ceil(8) * 8;
+
+                let start = reader.position / 8;
+                let length = u16::from_le_bytes([*deflated.get(start)?, *deflated.get(start + 1)?]) as usize;
+
+                out.extend(deflated.get(start + 4..start + 4 + length)?);
+                reader.position += (4 + length) * 8;
+            }
+            1 => {
+                let (literals, distances) = Huffman::fixed();
+                inflate_block(&mut reader, &mut out, &literals, &distances)?;
+            }
+            2 => {
+                let (literals, distances) = dynamic_codes(&mut reader)?;
+                inflate_block(&mut reader, &mut out, &literals, &distances)?;
+            }
+            _ => return None,
+        }
+
+        if last {
+            return Some(out);
+        }
+    }
+}
+
+/// Reads the codes that a block with dynamic codes gives before its data.
+fn dynamic_codes(reader: &mut BitReader) -> Option<(Huffman, Huffman)> {
+    let literals = reader.read(5)? as usize + 257;
+    let distances = reader.read(5)? as usize + 1;
+    let code_lengths = reader.read(4)? as usize + 4;
+
+    let mut lengths = [0; 19];
+    for &i in &CODE_LENGTH_ORDER[..code_lengths] {
+        lengths[i] = reader.read(3)? as u8;
+    }
+    let code_length_codes = Huffman::new(&lengths);
+
+    let mut lengths: Vec<u8> = vec![];
+
+    while lengths.len() < literals + distances {
+        let (length, repeat) = match reader.decode(&code_length_codes)? {
+            symbol @ 0..=15 => (symbol as u8, 1),
+            16 => (*lengths.last()?, 3 + reader.read(2)?),
+            17 => (0, 3 + reader.read(3)?),
+            _ => (0, 11 + reader.read(7)?),
+        };
+
+        lengths.extend(std::iter::repeat_n(length, repeat as usize));
+    }
+
+    if lengths.len() != literals + distances {
+        return None;
+    }
+
+    Some((Huffman::new(&lengths[..literals]), Huffman::new(&lengths[literals..])))
+}
+
+fn inflate_block(reader: &mut BitReader, out: &mut Vec<u8>, literals: &Huffman, distances: &Huffman) -> Option<()> {
+    loop {
+        let symbol = reader.decode(literals)?;
+
+        match symbol {
+            0..=255 => out.push(symbol as u8),
+            END_OF_BLOCK => return Some(()),
+            _ => {
+                let code = symbol as usize - 257;
+                let length = *LENGTH_BASES.get(code)? as usize + reader.read(*LENGTH_EXTRA.get(code)?)? as usize;
+                let code = reader.decode(distances)? as usize;
+                let distance = *DISTANCE_BASES.get(code)? as usize + reader.read(*DISTANCE_EXTRA.get(code)?)? as usize;
+                let start = out.len().checked_sub(distance)?;
+
+                // Runs may repeat bytes of their own, so are copied one by one
+                for i in 0..length {
+                    out.push(out[start + i]);
+                }
+            }
+        }
+    }
+}
+
+/// The CRC-32 of the bytes, as gzip checks them with.
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+
+    for &byte in bytes {
+        crc ^= byte as u32;
+
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+        }
+    }
+
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compressed_bytes_round_trip() {
+        let sql = "CREATE TABLE users (id int);\nCREATE TABLE orders (id int);\n".repeat(50);
+
+        for bytes in [sql.as_bytes(), b"", b"a", b"aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa"] {
+            let compressed = compress(bytes);
+
+            assert_eq!(decompress(&compressed).as_deref(), Some(bytes));
+        }
+
+        assert!(compress(sql.as_bytes()).len() < sql.len() / 10);
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn gzip_from_elsewhere_is_read() {
+        // `printf 'CREATE TABLE a ();\nCREATE TABLE a ();\n' | gzip -n`, with fixed codes
+        let fixed = [
+            0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0x73, 0x0e, 0x72, 0x75, 0x0c, 0x71, 0x55, 0x08,
+            0x71, 0x74, 0xf2, 0x71, 0x55, 0x48, 0x54, 0xd0, 0xd0, 0xb4, 0xe6, 0x72, 0xc6, 0x14, 0x02, 0x00, 0x63, 0x0f,
+            0xa2, 0x30, 0x26, 0x00, 0x00, 0x00,
+        ];
+        // Python's `gzip.compress`, which chose dynamic codes
+        let dynamic = [
+            0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0x1d, 0x87, 0xb1, 0x09, 0x00, 0x00, 0x0c, 0x83,
+            0x76, 0xbf, 0xd4, 0xff, 0x8f, 0x68, 0x09, 0x04, 0x35, 0x62, 0x56, 0xe4, 0x02, 0x19, 0xfe, 0x28, 0xaf, 0x0d,
+            0x0f, 0x85, 0x5a, 0x70, 0xdc, 0x28, 0x00, 0x00, 0x00,
+        ];
+
+        assert_eq!(decompress(&fixed).as_deref(), Some(&b"CREATE TABLE a ();\nCREATE TABLE a ();\n"[..]));
+        assert_eq!(decompress(&dynamic).as_deref(), Some(&b"a\nababbb\nbaababb\na\nbb\na\nabaaa\n\nab\nab\na\na"[..]));
+        assert_eq!(decompress(&fixed[..20]), None);
+        assert_eq!(decompress(b"CREATE TABLE a ();"), None);
+    }
+}
@@ -0,0 +1,293 @@
+//! Heartbeats for statements that run for a long time, so that one that's
+//! still working can be told apart from one that's hung. Once a statement
+//! has run for a while, a second connection looks it up in
+//! `pg_stat_activity` every so often and logs what it's waiting on, if
+//! anything, as well as whatever's blocking it from taking a lock. Settings
+//! are given in the environment file:
+//!
+//! ```toml
+//! [heartbeat]
+//! after = "30m"
+//! interval = "5m"
+//! blocked_after = "10s"
+//! ```
+use std::{
+    convert::TryFrom,
+    sync::{mpsc, Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use log::Level;
+use serde::Deserialize;
+
+use crate::{
+    client::Client,
+    meta::deserialize_duration,
+    progress::{format_duration, ProgressLog},
+    statements::Statement,
+    Environment,
+};
+
+/// How long statements run before heartbeats start, unless given.
+const DEFAULT_AFTER: Duration = Duration::from_secs(30 * 60);
+
+/// How often heartbeats are logged, unless given.
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// How long statements run before what's blocking them is looked for, unless
+/// given.
+const DEFAULT_BLOCKED_AFTER: Duration = Duration::from_secs(10);
+
+/// How often the current statement is checked on.
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+const ACTIVITY: &str = "
+SELECT state, wait_event_type, wait_event FROM pg_stat_activity WHERE pid = $1
+";
+
+const BLOCKERS: &str = "
+SELECT
+    pid,
+    state,
+    query,
+    extract(epoch FROM now() - coalesce(xact_start, query_start))::FLOAT8 AS age
+FROM pg_stat_activity
+WHERE pid = ANY(pg_blocking_pids($1))
+ORDER BY pid
+";
+
+const TERMINATE: &str = "
+SELECT pg_terminate_backend($1)
+";
+
+/// When to log heartbeats for statements that are still running, which is
+/// after half an hour and every five minutes from then on unless given.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HeartbeatSettings {
+    /// Whether to log heartbeats at all
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// How long a statement runs before heartbeats start
+    #[serde(default, deserialize_with = "deserialize_duration")]
+    pub after: Option<Duration>,
+    /// How often heartbeats are logged once they've started
+    #[serde(default, deserialize_with = "deserialize_duration")]
+    pub interval: Option<Duration>,
+    /// How long a statement runs before whatever's blocking it from taking
+    /// a lock is looked for
+    #[serde(default, deserialize_with = "deserialize_duration")]
+    pub blocked_after: Option<Duration>,
+}
+
+impl Default for HeartbeatSettings {
+    fn default() -> Self {
+        Self { enabled: true, after: None, interval: None, blocked_after: None }
+    }
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+/// The statement currently running, and when it started.
+type Current = Arc<Mutex<Option<(Instant, String)>>>;
+
+/// Watches the statements running on one connection from a thread of its
+/// own, which connects to the environment's database for the first time
+/// only once a heartbeat or a look for blockers is due.
+pub struct Heartbeat {
+    current: Current,
+    ticker: Option<(mpsc::Sender<()>, thread::JoinHandle<()>)>,
+}
+
+impl Heartbeat {
+    /// Starts watching the connection with the given backend process ID,
+    /// terminating any sessions found blocking it if asked to.
+    pub fn start(env: &Environment, pid: i32, terminate_blockers: bool, log: ProgressLog) -> Self {
+        let current: Current = Arc::new(Mutex::new(None));
+        let watched = Arc::clone(&current);
+
+        let env = env.clone();
+        let after = env.heartbeat.after.unwrap_or(DEFAULT_AFTER);
+        let interval = env.heartbeat.interval.unwrap_or(DEFAULT_INTERVAL);
+        let blocked_after = env.heartbeat.blocked_after.unwrap_or(DEFAULT_BLOCKED_AFTER);
+
+        let (stop, stopped) = mpsc::channel();
+
+        let handle = thread::spawn(move || {
+            let mut monitor: Option<Client> = None;
+            // The statement last given a heartbeat, by when it started, and when
+            let mut last: Option<(Instant, Instant)> = None;
+            // The statement last found blocked, and the sessions blocking it
+            let mut blocked: Option<(Instant, Vec<i32>)> = None;
+
+            // Either a message or a disconnect means it's time to stop
+            while let Err(mpsc::RecvTimeoutError::Timeout) = stopped.recv_timeout(CHECK_INTERVAL) {
+                let (started, summary) = match watched.lock().unwrap().clone() {
+                    Some(current) => current,
+                    None => continue,
+                };
+
+                if started.elapsed() >= blocked_after {
+                    let reported = blocked.as_ref().filter(|(statement, _)| *statement == started).map(|(_, pids)| pids.as_slice());
+                    let pids = check_blockers(&mut monitor, &env, pid, reported.unwrap_or_default(), terminate_blockers, &log);
+
+                    blocked = Some((started, pids));
+                }
+
+                let due = match last {
+                    Some((statement, beat)) if statement == started => beat.elapsed() >= interval,
+                    _ => started.elapsed() >= after,
+                };
+
+                if !due {
+                    continue;
+                }
+
+                last = Some((started, Instant::now()));
+
+                let activity = describe(&mut monitor, &env, pid);
+                log.log(
+                    Level::Info,
+                    &format!("    still running `{}` after {}, {}", summary, format_duration(started.elapsed()), activity),
+                );
+            }
+        });
+
+        Self {
+            current,
+            ticker: Some((stop, handle)),
+        }
+    }
+
+    /// Marks the start of the next statement.
+    pub fn statement(&self, statement: &Statement) {
+        *self.current.lock().unwrap() = Some((Instant::now(), statement.summary()));
+    }
+
+    /// Marks the end of the current statement, whether it succeeded or not.
+    pub fn done(&self) {
+        *self.current.lock().unwrap() = None;
+    }
+}
+
+impl Drop for Heartbeat {
+    fn drop(&mut self) {
+        if let Some((stop, handle)) = self.ticker.take() {
+            let _ = stop.send(());
+            let _ = handle.join();
+        }
+    }
+}
+
+/// The monitoring connection, connecting first if need be.
+fn connect<'a>(monitor: &'a mut Option<Client>, env: &Environment) -> Result<&'a mut Client, crate::Error> {
+    match monitor {
+        Some(client) => Ok(client),
+        None => Ok(monitor.insert(Client::try_from(env)?)),
+    }
+}
+
+/// Logs the sessions blocking the backend from taking a lock, besides those
+/// already reported, terminating them if asked to. Returns the sessions
+/// blocking it, or those already reported should they not be found, as
+/// with the connection failing, which is then dropped to be made again.
+fn check_blockers(
+    monitor: &mut Option<Client>,
+    env: &Environment,
+    pid: i32,
+    reported: &[i32],
+    terminate: bool,
+    log: &ProgressLog,
+) -> Vec<i32> {
+    let rows = match connect(monitor, env).and_then(|client| Ok(client.query(BLOCKERS, &[&pid])?)) {
+        Ok(rows) => rows,
+        Err(_) => {
+            *monitor = None;
+            return reported.to_vec();
+        }
+    };
+
+    let mut pids = vec![];
+
+    for row in rows {
+        let blocker: i32 = row.get("pid");
+        pids.push(blocker);
+
+        if reported.contains(&blocker) {
+            continue;
+        }
+
+        let state = row.get::<_, Option<String>>("state").unwrap_or_else(|| "unknown".to_string());
+        let age = row.get::<_, Option<f64>>("age").map_or(String::new(), |age| {
+            format!(" for {}", format_duration(Duration::from_secs_f64(age.max(0.0))))
+        });
+        let query = Statement {
+            sql: row.get::<_, Option<String>>("query").unwrap_or_default(),
+            line: 1,
+        };
+
+        log.log(Level::Warn, &format!("    blocked by pid {} ({}{}): {}", blocker, state, age, query.summary()));
+
+        if let Some(client) = monitor.as_mut().filter(|_| terminate) {
+            match client.query_one(TERMINATE, &[&blocker]) {
+                Ok(_) => log.log(Level::Warn, &format!("    terminated pid {}", blocker)),
+                Err(e) => log.log(Level::Warn, &format!("    could not terminate pid {}: {}", blocker, e)),
+            }
+        }
+    }
+
+    pids
+}
+
+/// What the backend is doing according to `pg_stat_activity`, connecting
+/// first if need be. The connection is dropped should it fail, to be made
+/// again for the next heartbeat.
+fn describe(monitor: &mut Option<Client>, env: &Environment, pid: i32) -> String {
+    let client = match connect(monitor, env) {
+        Ok(client) => client,
+        Err(e) => return format!("could not connect to check on it: {}", e),
+    };
+
+    let row = match client.query_opt(ACTIVITY, &[&pid]) {
+        Ok(row) => row,
+        Err(e) => {
+            *monitor = None;
+            return format!("could not check on it: {}", e);
+        }
+    };
+
+    let (state, wait_type, wait_event) = match row {
+        Some(row) => (
+            row.get::<_, Option<String>>("state"),
+            row.get::<_, Option<String>>("wait_event_type"),
+            row.get::<_, Option<String>>("wait_event"),
+        ),
+        None => return "but its connection is no longer in pg_stat_activity".to_string(),
+    };
+    let state = state.unwrap_or_else(|| "unknown".to_string());
+
+    match (wait_type, wait_event) {
+        (Some(wait_type), Some(wait_event)) => format!("{}, waiting on {}: {}", state, wait_type, wait_event),
+        _ => format!("{}, not waiting", state),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn settings_default_to_enabled() {
+        let settings: HeartbeatSettings = toml::from_str("after = \"10m\"\nblocked_after = \"30s\"").unwrap();
+
+        assert!(settings.enabled);
+        assert_eq!(settings.after, Some(Duration::from_secs(600)));
+        assert_eq!(settings.interval, None);
+        assert_eq!(settings.blocked_after, Some(Duration::from_secs(30)));
+        assert!(toml::from_str::<HeartbeatSettings>("after = \"soon\"").is_err());
+        assert!(toml::from_str::<HeartbeatSettings>("every = \"5m\"").is_err());
+    }
+}
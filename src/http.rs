@@ -0,0 +1,94 @@
+//! Bare-bones HTTP requests for integrations like metrics and notifications.
+//!
+//! Rather than pulling in an HTTP client and TLS stack for the handful of
+//! fire-and-forget requests `jrny` makes, these are delegated to `curl`,
+//! which is almost universally available wherever migrations are run.
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+};
+
+use crate::{Error, Result};
+
+/// Percent-encodes a value for use as a single URL path segment.
+pub fn encode_segment(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// Sends the body to the URL with the given method and content type,
+/// failing if the server does not respond with a successful status.
+pub fn send(method: &str, url: &str, content_type: &str, body: &str) -> Result<String> {
+    send_with_headers(method, url, &[("Content-Type", content_type)], body)
+}
+
+/// Like [`send`], with any headers. The URL, headers and body are given to
+/// curl as a config file on its stdin rather than as arguments, since webhook
+/// URLs and headers may carry tokens that other local users could otherwise
+/// see in `ps`.
+pub fn send_with_headers(method: &str, url: &str, headers: &[(&str, &str)], body: &str) -> Result<String> {
+    let mut config = format!("url = {}\n", quoted(url));
+
+    for (name, value) in headers {
+        config.push_str(&format!("header = {}\n", quoted(&format!("{}: {}", name, value))));
+    }
+    // Unlike `data-binary`, taken literally even if it starts with `@`
+    config.push_str(&format!("data-raw = {}\n", quoted(body)));
+
+    let mut child = Command::new("curl")
+        .args(["--silent", "--show-error", "--fail", "--max-time", "30"])
+        .args(["--request", method])
+        .args(["--config", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| Error::HttpRequestFailed(url.to_string(), format!("could not run curl: {}", e)))?;
+
+    child.stdin.take().unwrap().write_all(config.as_bytes())?;
+
+    let output = child.wait_with_output()?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(Error::HttpRequestFailed(url.to_string(), stderr.trim().to_string()));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// Quotes a value for a curl config file, where backslash escapes are the
+/// only special sequences within double quotes.
+fn quoted(value: &str) -> String {
+    let mut quoted = String::from('"');
+
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            _ => quoted.push(c),
+        }
+    }
+
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quoted_escapes_curl_config_values() {
+        assert_eq!(quoted("X-Vault-Token: s.abc"), "\"X-Vault-Token: s.abc\"");
+        assert_eq!(quoted("{\"a\":\"b\\c\"}\n"), "\"{\\\"a\\\":\\\"b\\\\c\\\"}\\n\"");
+    }
+}
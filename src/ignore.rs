@@ -0,0 +1,191 @@
+//! Files in the revisions directory that aren't revisions at all, eg. editor
+//! temp files, a `README.md`, or work in progress, listed in a `.jrnyignore`
+//! file at the top of it in the syntax of `.gitignore`:
+//!
+//! ```text
+//! # Notes on the revisions
+//! README.md
+//! *.draft.sql
+//! *~
+//! /scratch/
+//! !keep.draft.sql
+//! ```
+//!
+//! Patterns without a `/`, other than one at the end, match names at any
+//! depth, while those with one match paths relative to the revisions
+//! directory. A `*` matches anything but a `/`, `**` matches anything at all,
+//! a trailing `/` matches only directories, and a leading `!` re-includes
+//! what an earlier pattern ignored, unless its directory is ignored.
+use std::{fs, path::Path};
+
+use crate::Result;
+
+/// The file listing what to ignore in the revisions directory.
+pub const IGNORE_FILE: &str = ".jrnyignore";
+
+#[derive(Debug, PartialEq, Eq)]
+struct Rule {
+    pattern: Vec<char>,
+    negated: bool,
+    dir_only: bool,
+    /// Whether the pattern matches the whole path rather than just the name
+    anchored: bool,
+}
+
+/// The patterns of an ignore file, in the order given.
+#[derive(Debug, Default)]
+pub struct IgnoreRules {
+    rules: Vec<Rule>,
+}
+
+impl IgnoreRules {
+    /// The rules in the directory's ignore file, if it has one.
+    pub fn from_dir(dir: &Path) -> Result<Self> {
+        let path = dir.join(IGNORE_FILE);
+
+        if !path.is_file() {
+            return Ok(Self::default());
+        }
+
+        Ok(Self::parse(&fs::read_to_string(path)?))
+    }
+
+    pub fn parse(contents: &str) -> Self {
+        let rules = contents
+            .lines()
+            .map(|line| line.trim_end())
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| {
+                let (line, negated) = match line.strip_prefix('!') {
+                    Some(line) => (line, true),
+                    None => (line, false),
+                };
+                let (line, dir_only) = match line.strip_suffix('/') {
+                    Some(line) => (line, true),
+                    None => (line, false),
+                };
+
+                Rule {
+                    pattern: line.trim_start_matches('/').chars().collect(),
+                    negated,
+                    dir_only,
+                    anchored: line.contains('/'),
+                }
+            })
+            .collect();
+
+        Self { rules }
+    }
+
+    /// Whether the entry, by its path relative to the revisions directory
+    /// and separated by `/`, is ignored, which is up to the last pattern
+    /// matching it. Ignore files themselves always are.
+    pub fn is_ignored(&self, path: &str, is_dir: bool) -> bool {
+        let name = path.rsplit('/').next().unwrap_or(path);
+
+        if name == IGNORE_FILE && !is_dir {
+            return true;
+        }
+
+        let path: Vec<_> = path.chars().collect();
+        let name: Vec<_> = name.chars().collect();
+
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| (is_dir || !rule.dir_only) && matches(&rule.pattern, if rule.anchored { &path } else { &name }))
+            .is_some_and(|rule| !rule.negated)
+    }
+}
+
+/// Whether the whole of the text matches the pattern.
+fn matches(pattern: &[char], text: &[char]) -> bool {
+    match pattern {
+        [] => text.is_empty(),
+        // Any number of whole directories, including none
+        ['*', '*', '/', rest @ ..] => {
+            matches(rest, text) || (0..text.len()).any(|i| text[i] == '/' && matches(rest, &text[i + 1..]))
+        }
+        ['*', '*', rest @ ..] => (0..=text.len()).any(|i| matches(rest, &text[i..])),
+        ['*', rest @ ..] => (0..=text.len())
+            .take_while(|&i| i == 0 || text[i - 1] != '/')
+            .any(|i| matches(rest, &text[i..])),
+        ['?', rest @ ..] => text.first().is_some_and(|&c| c != '/') && matches(rest, &text[1..]),
+        ['[', class @ ..] => match class_matches(class, text.first().copied()) {
+            Some((true, rest)) => matches(rest, &text[1..]),
+            Some((false, _)) => false,
+            // Without a closing `]`, it's just a `[`
+            None => text.first() == Some(&'[') && matches(class, &text[1..]),
+        },
+        ['\\', c, rest @ ..] | [c, rest @ ..] => text.first() == Some(c) && matches(rest, &text[1..]),
+    }
+}
+
+/// Whether the character is in the class, eg. `a-z]` or `!.]`, along with
+/// what follows it, or `None` if the class isn't closed.
+fn class_matches(class: &[char], c: Option<char>) -> Option<(bool, &[char])> {
+    let (negated, class) = match class {
+        ['!' | '^', class @ ..] => (true, class),
+        _ => (false, class),
+    };
+    let end = class.iter().skip(1).position(|&c| c == ']')? + 1;
+    let (members, rest) = (&class[..end], &class[end + 1..]);
+
+    let c = match c {
+        Some(c) if c != '/' => c,
+        _ => return Some((false, rest)),
+    };
+    let mut found = false;
+    let mut i = 0;
+
+    while i < members.len() {
+        found |= match members.get(i + 1..i + 3) {
+            Some(['-', to]) => {
+                i += 2;
+                (members[i - 2]..=*to).contains(&c)
+            }
+            _ => members[i] == c,
+        };
+        i += 1;
+    }
+
+    Some((found != negated, rest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_match_at_any_depth() {
+        let rules = IgnoreRules::parse("# Not revisions\nREADME.md\n*.draft.sql\n*~\n.*.swp\n\n!keep.draft.sql\n");
+
+        assert!(rules.is_ignored("README.md", false));
+        assert!(rules.is_ignored("2021/billing/002.1618449763.add-invoices.draft.sql", false));
+        assert!(rules.is_ignored("001.1618370298.add-users.sql~", false));
+        assert!(rules.is_ignored(".001.1618370298.add-users.sql.swp", false));
+        assert!(!rules.is_ignored("001.1618370298.add-users.sql", false));
+        assert!(!rules.is_ignored("2021/keep.draft.sql", false));
+        assert!(rules.is_ignored(".jrnyignore", false));
+        assert!(rules.is_ignored("2021/.jrnyignore", false));
+    }
+
+    #[test]
+    fn paths_match_from_the_top() {
+        let rules = IgnoreRules::parse("/scratch/\ndrafts/**/*.sql\n**/wip\nnotes/\n2021/00[1-3].*\n2021/00[!1-3].*\\*\n");
+
+        assert!(rules.is_ignored("scratch", true));
+        assert!(!rules.is_ignored("scratch", false));
+        assert!(!rules.is_ignored("2021/scratch", true));
+        assert!(rules.is_ignored("drafts/001.a.sql", false));
+        assert!(rules.is_ignored("drafts/2021/04/001.a.sql", false));
+        assert!(!rules.is_ignored("drafts/001.a.txt", false));
+        assert!(rules.is_ignored("wip", true));
+        assert!(rules.is_ignored("2021/billing/wip", false));
+        assert!(rules.is_ignored("2021/notes", true));
+        assert!(rules.is_ignored("2021/002.a.sql", false));
+        assert!(!rules.is_ignored("2021/004.a.sql", false));
+        assert!(rules.is_ignored("2021/004.*", false));
+        assert!(!rules.is_ignored("2022/002.a.sql", false));
+    }
+}
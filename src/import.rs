@@ -0,0 +1,416 @@
+//! Converting revisions from other migration tools, so that projects can
+//! switch to `jrny` without re-applying anything.
+//!
+//! Converted revisions start with a comment naming what they were converted
+//! from, eg. `-- Imported from flyway: V1_1__add_users.sql`, which is what
+//! allows environments still tracked by the other tool to be matched up
+//! with them later on, without needing the original files.
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+
+use crate::{revisions::sorted_entries, Error, Result};
+
+const MARKER_PREFIX: &str = "-- Imported from ";
+
+/// The migration tools that revisions and history can be imported from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MigrationTool {
+    Flyway,
+    Sqitch,
+    GolangMigrate,
+}
+
+impl FromStr for MigrationTool {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "flyway" => Ok(Self::Flyway),
+            "sqitch" => Ok(Self::Sqitch),
+            "golang-migrate" => Ok(Self::GolangMigrate),
+            _ => Err(Error::ImportToolInvalid(s.to_string())),
+        }
+    }
+}
+
+impl MigrationTool {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Flyway => "flyway",
+            Self::Sqitch => "sqitch",
+            Self::GolangMigrate => "golang-migrate",
+        }
+    }
+
+    /// The table the tool records its history in by default.
+    pub fn default_history_table(&self) -> &'static str {
+        match self {
+            Self::Flyway => "flyway_schema_history",
+            Self::Sqitch => "sqitch.changes",
+            Self::GolangMigrate => "schema_migrations",
+        }
+    }
+
+    /// Reads the revisions from the tool's layout within the directory, in
+    /// the order the tool would apply them: for flyway, the migrations
+    /// directory (eg. `db/migration`); for sqitch, the project directory
+    /// containing `sqitch.plan`; and for golang-migrate, the directory
+    /// containing the `.up.sql` files.
+    pub fn read_sources(&self, dir: &Path) -> Result<Vec<Source>> {
+        if !dir.is_dir() {
+            return Err(Error::PathNotDirectory(dir.display().to_string()));
+        }
+
+        match self {
+            Self::Flyway => flyway_sources(dir),
+            Self::Sqitch => sqitch_sources(dir),
+            Self::GolangMigrate => golang_migrate_sources(dir),
+        }
+    }
+
+    /// How the tool's history identifies the revision converted from the
+    /// original, if it is one the tool applies.
+    pub fn key(&self, original: &str) -> Option<String> {
+        match self {
+            Self::Flyway => match parse_flyway(original)? {
+                FlywayFile::Versioned { version, .. } => Some(version),
+                FlywayFile::Repeatable { .. } => Some(original.to_string()),
+            },
+            Self::Sqitch => Some(original.to_string()),
+            Self::GolangMigrate => parse_golang_migrate(original).map(|(version, _)| version.to_string()),
+        }
+    }
+
+    /// The comment that a revision converted from the original starts with.
+    pub fn marker(&self, original: &str) -> String {
+        format!("{}{}: {}", MARKER_PREFIX, self.as_str(), original)
+    }
+
+    /// What the revision was converted from by this tool, if it was.
+    pub fn original<'a>(&self, contents: &'a str) -> Option<&'a str> {
+        let marker = contents.lines().next()?.strip_prefix(MARKER_PREFIX)?;
+        let (tool, original) = marker.split_once(": ")?;
+
+        (tool == self.as_str()).then(|| original.trim())
+    }
+}
+
+/// A revision as defined for another tool.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Source {
+    /// What the tool identifies the revision by: the filename for flyway
+    /// and golang-migrate, or the change name for sqitch
+    pub original: String,
+    /// The name to give the converted revision
+    pub name: String,
+    pub contents: String,
+    /// When the revision was planned, if that's known
+    pub created_at: Option<DateTime<Utc>>,
+    /// Whether the revision is re-applied whenever it changes
+    pub repeatable: bool,
+}
+
+/// What the history of the other tool says has been applied.
+#[derive(Debug)]
+pub enum History {
+    /// Each applied revision by key, along with when it was applied if known
+    Applied(Vec<(String, Option<DateTime<Utc>>)>),
+    /// As with `Applied`, but also every flyway version up to and including a
+    /// baseline, which flyway considers applied
+    Baselined {
+        applied: Vec<(String, Option<DateTime<Utc>>)>,
+        baseline: String,
+        baselined_on: Option<DateTime<Utc>>,
+    },
+    /// Every revision up to and including the version is applied, unless
+    /// dirty, in which case the version itself failed part-way
+    Version { version: i64, dirty: bool },
+}
+
+impl History {
+    /// Whether the revision with the key has been applied and, if so, when
+    /// (if known).
+    pub fn applied(&self, key: &str) -> Option<Option<DateTime<Utc>>> {
+        let find = |applied: &[(String, Option<DateTime<Utc>>)]| {
+            applied.iter().rev().find(|(k, _)| k == key).map(|(_, at)| *at)
+        };
+
+        match self {
+            Self::Applied(applied) => find(applied),
+            Self::Baselined { applied, baseline, baselined_on } => find(applied).or_else(|| {
+                let (key, baseline) = (version_parts(key)?, version_parts(baseline)?);
+
+                (key <= baseline).then_some(*baselined_on)
+            }),
+            Self::Version { version, dirty } => {
+                let key: i64 = key.parse().ok()?;
+                let applied = key < *version || (key == *version && !dirty);
+
+                applied.then_some(None)
+            }
+        }
+    }
+}
+
+/// Normalizes a version from flyway's history, so that it matches the key
+/// for the revision converted from its file.
+pub fn flyway_version_key(version: &str) -> Option<String> {
+    match parse_flyway(&format!("V{}__.sql", version))? {
+        FlywayFile::Versioned { version, .. } => Some(version),
+        FlywayFile::Repeatable { .. } => None,
+    }
+}
+
+fn version_parts(version: &str) -> Option<Vec<u64>> {
+    version.split('.').map(|part| part.parse().ok()).collect()
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum FlywayFile {
+    Versioned { version: String, description: String },
+    Repeatable { description: String },
+}
+
+/// Parses flyway's `V1_1__description.sql` and `R__description.sql` filenames,
+/// normalizing versions as flyway compares them, eg. `V01_10` is `1.10`.
+fn parse_flyway(filename: &str) -> Option<FlywayFile> {
+    let stem = filename.strip_suffix(".sql")?;
+
+    if let Some(description) = stem.strip_prefix("R__") {
+        return Some(FlywayFile::Repeatable {
+            description: description.to_string(),
+        });
+    }
+
+    let (version, description) = stem.strip_prefix('V')?.split_once("__")?;
+    let mut parts = version
+        .split(['.', '_'])
+        .map(|part| part.parse::<u64>().ok())
+        .collect::<Option<Vec<_>>>()?;
+
+    // Trailing zeros make no difference to the version, eg. `1.0` is `1`
+    while parts.len() > 1 && parts.last() == Some(&0) {
+        parts.pop();
+    }
+
+    Some(FlywayFile::Versioned {
+        version: parts.iter().map(u64::to_string).collect::<Vec<_>>().join("."),
+        description: description.to_string(),
+    })
+}
+
+/// Parses golang-migrate's `{version}_{title}.up.sql` filenames.
+fn parse_golang_migrate(filename: &str) -> Option<(i64, String)> {
+    let (version, title) = filename.strip_suffix(".up.sql")?.split_once('_')?;
+
+    Some((version.parse().ok()?, title.to_string()))
+}
+
+/// Versions are often timestamps, eg. `20211025120000` or `1635163200`,
+/// which make for the most faithful creation times.
+fn version_timestamp(version: &str) -> Option<DateTime<Utc>> {
+    match version.len() {
+        14 => NaiveDateTime::parse_from_str(version, "%Y%m%d%H%M%S")
+            .ok()
+            .map(|dt| Utc.from_utc_datetime(&dt)),
+        10 => Utc.timestamp_opt(version.parse().ok()?, 0).single(),
+        _ => None,
+    }
+}
+
+fn flyway_sources(dir: &Path) -> Result<Vec<Source>> {
+    let mut versioned = vec![];
+    let mut repeatable = vec![];
+
+    // Flyway finds migrations in subdirectories too
+    for path in files_within(dir)? {
+        let filename = file_name(&path)?;
+        let contents = || fs::read_to_string(&path);
+
+        match parse_flyway(&filename) {
+            Some(FlywayFile::Versioned { version, description }) => {
+                let order = version_parts(&version).unwrap_or_default();
+                let first = version.split('.').next().unwrap_or_default();
+
+                versioned.push((order, Source {
+                    created_at: version_timestamp(first),
+                    original: filename,
+                    name: description,
+                    contents: contents()?,
+                    repeatable: false,
+                }));
+            }
+            Some(FlywayFile::Repeatable { description }) => repeatable.push(Source {
+                original: filename,
+                name: description,
+                contents: contents()?,
+                created_at: None,
+                repeatable: true,
+            }),
+            // Undo and baseline migrations, as well as callbacks, aren't revisions
+            None => continue,
+        }
+    }
+
+    versioned.sort_by(|(a, _), (b, _)| a.cmp(b));
+    repeatable.sort_by(|a, b| a.original.cmp(&b.original));
+
+    Ok(versioned.into_iter().map(|(_, source)| source).chain(repeatable).collect())
+}
+
+fn golang_migrate_sources(dir: &Path) -> Result<Vec<Source>> {
+    let mut sources = vec![];
+
+    for path in sorted_entries(dir)? {
+        let filename = file_name(&path)?;
+
+        if let Some((version, title)) = parse_golang_migrate(&filename) {
+            sources.push((version, Source {
+                created_at: version_timestamp(&version.to_string()),
+                original: filename,
+                name: title,
+                contents: fs::read_to_string(&path)?,
+                repeatable: false,
+            }));
+        }
+    }
+
+    sources.sort_by_key(|(version, _)| *version);
+
+    Ok(sources.into_iter().map(|(_, source)| source).collect())
+}
+
+fn sqitch_sources(dir: &Path) -> Result<Vec<Source>> {
+    let plan_path = dir.join("sqitch.plan");
+    let plan = fs::read_to_string(&plan_path)?;
+    let invalid = |reason: String| Error::ImportSourceInvalid(plan_path.display().to_string(), reason);
+
+    let mut sources = vec![];
+    let mut seen = HashSet::new();
+
+    for line in plan.lines().map(str::trim) {
+        // Skip pragmas, tags, comments, and blank lines
+        if line.is_empty() || line.starts_with(['%', '@', '#']) {
+            continue;
+        }
+
+        let mut words = line.split_whitespace();
+        let change = words.next().unwrap_or_default().to_string();
+
+        // Reworked changes keep earlier versions of their scripts elsewhere,
+        // which isn't something that translates to revisions
+        if !seen.insert(change.clone()) {
+            return Err(invalid(format!("change `{}` is reworked, which cannot be imported", change)));
+        }
+
+        // Dependencies are in brackets between the name and timestamp
+        let created_at = words
+            .find(|w| !w.starts_with('[') && !w.ends_with(']'))
+            .and_then(|w| DateTime::parse_from_rfc3339(w).ok())
+            .map(|dt| dt.with_timezone(&Utc));
+
+        let deploy = dir.join("deploy").join(format!("{}.sql", change));
+        let contents = fs::read_to_string(&deploy)
+            .map_err(|e| invalid(format!("could not read `{}`: {}", deploy.display(), e)))?;
+
+        sources.push(Source {
+            name: change.clone(),
+            original: change,
+            contents,
+            created_at,
+            repeatable: false,
+        });
+    }
+
+    Ok(sources)
+}
+
+/// All files within the directory or any of its subdirectories.
+fn files_within(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = vec![];
+
+    for entry in sorted_entries(dir)? {
+        if entry.is_dir() {
+            files.extend(files_within(&entry)?);
+        } else {
+            files.push(entry);
+        }
+    }
+
+    Ok(files)
+}
+
+fn file_name(path: &Path) -> Result<String> {
+    path.file_name()
+        .and_then(|f| f.to_str())
+        .map(str::to_string)
+        .ok_or_else(|| Error::FileNotValid(path.display().to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_flyway_normalizes_versions() {
+        assert_eq!(
+            parse_flyway("V01_10.0__add_users.sql"),
+            Some(FlywayFile::Versioned {
+                version: "1.10".to_string(),
+                description: "add_users".to_string(),
+            })
+        );
+        assert_eq!(
+            parse_flyway("R__views.sql"),
+            Some(FlywayFile::Repeatable {
+                description: "views".to_string(),
+            })
+        );
+        assert_eq!(parse_flyway("U1__undo.sql"), None);
+        assert_eq!(parse_flyway("V1_x__bad.sql"), None);
+    }
+
+    #[test]
+    fn key_matches_history_identifiers() {
+        assert_eq!(MigrationTool::Flyway.key("V2_1__things.sql"), Some("2.1".to_string()));
+        assert_eq!(MigrationTool::GolangMigrate.key("000012_things.up.sql"), Some("12".to_string()));
+        assert_eq!(MigrationTool::GolangMigrate.key("000012_things.down.sql"), None);
+    }
+
+    #[test]
+    fn original_reads_marker_for_tool() {
+        let contents = format!("{}\n\ncreate table users ();", MigrationTool::Flyway.marker("V1__users.sql"));
+
+        assert_eq!(MigrationTool::Flyway.original(&contents), Some("V1__users.sql"));
+        assert_eq!(MigrationTool::Sqitch.original(&contents), None);
+    }
+
+    #[test]
+    fn history_baselined_includes_earlier_versions() {
+        let history = History::Baselined {
+            applied: vec![("3".to_string(), None)],
+            baseline: "2".to_string(),
+            baselined_on: None,
+        };
+
+        assert_eq!(history.applied("1.5"), Some(None));
+        assert_eq!(history.applied("3"), Some(None));
+        assert_eq!(history.applied("2.1"), None);
+        assert_eq!(flyway_version_key("01.2.0"), Some("1.2".to_string()));
+    }
+
+    #[test]
+    fn history_version_excludes_dirty() {
+        let history = History::Version { version: 3, dirty: true };
+
+        assert_eq!(history.applied("2"), Some(None));
+        assert_eq!(history.applied("3"), None);
+        assert_eq!(history.applied("4"), None);
+    }
+}
@@ -0,0 +1,329 @@
+//! Minimal JSON serialization for reports and machine-readable output,
+//! along with parsing for reading back bundles, neither of which warrants
+//! pulling in `serde_json`.
+use std::{fmt, iter::Peekable, str::Chars};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Str(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    /// Builds an object from key-value pairs, preserving their order.
+    pub fn object<K: Into<String>>(pairs: impl IntoIterator<Item = (K, Json)>) -> Self {
+        Self::Object(pairs.into_iter().map(|(k, v)| (k.into(), v)).collect())
+    }
+
+    /// Parses a single JSON value, describing what was wrong if it isn't one.
+    pub fn parse(input: &str) -> Result<Self, String> {
+        let mut chars = input.chars().peekable();
+        let value = parse_value(&mut chars)?;
+
+        skip_whitespace(&mut chars);
+
+        match chars.next() {
+            None => Ok(value),
+            Some(c) => Err(format!("unexpected `{}` after value", c)),
+        }
+    }
+
+    /// The value of the key, if this is an object with it.
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(pairs) => pairs.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::Str(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Json::Int(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+}
+
+impl From<&str> for Json {
+    fn from(s: &str) -> Self {
+        Self::Str(s.to_string())
+    }
+}
+
+impl From<String> for Json {
+    fn from(s: String) -> Self {
+        Self::Str(s)
+    }
+}
+
+impl From<bool> for Json {
+    fn from(b: bool) -> Self {
+        Self::Bool(b)
+    }
+}
+
+impl From<i64> for Json {
+    fn from(n: i64) -> Self {
+        Self::Int(n)
+    }
+}
+
+impl From<i32> for Json {
+    fn from(n: i32) -> Self {
+        Self::Int(n.into())
+    }
+}
+
+impl From<usize> for Json {
+    fn from(n: usize) -> Self {
+        Self::Int(n as i64)
+    }
+}
+
+impl From<f64> for Json {
+    fn from(n: f64) -> Self {
+        Self::Float(n)
+    }
+}
+
+impl<T: Into<Json>> From<Option<T>> for Json {
+    fn from(opt: Option<T>) -> Self {
+        opt.map_or(Self::Null, Into::into)
+    }
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(b) => write!(f, "{}", b),
+            Json::Int(n) => write!(f, "{}", n),
+            Json::Float(n) if n.is_finite() => write!(f, "{}", n),
+            Json::Float(_) => write!(f, "null"),
+            Json::Str(s) => write_str(f, s),
+            Json::Array(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            }
+            Json::Object(pairs) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in pairs.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_str(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+fn write_str(f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+    write!(f, "\"")?;
+
+    for c in s.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+
+    write!(f, "\"")
+}
+
+type Input<'a> = Peekable<Chars<'a>>;
+
+fn skip_whitespace(chars: &mut Input) {
+    while chars.next_if(|c| c.is_whitespace()).is_some() {}
+}
+
+fn expect(chars: &mut Input, expected: &str) -> Result<(), String> {
+    for c in expected.chars() {
+        if chars.next() != Some(c) {
+            return Err(format!("expected `{}`", expected));
+        }
+    }
+
+    Ok(())
+}
+
+fn parse_value(chars: &mut Input) -> Result<Json, String> {
+    skip_whitespace(chars);
+
+    match chars.peek().copied() {
+        Some('n') => expect(chars, "null").map(|_| Json::Null),
+        Some('t') => expect(chars, "true").map(|_| Json::Bool(true)),
+        Some('f') => expect(chars, "false").map(|_| Json::Bool(false)),
+        Some('"') => parse_str(chars).map(Json::Str),
+        Some('[') => {
+            chars.next();
+            let mut items = vec![];
+
+            skip_whitespace(chars);
+            if chars.next_if_eq(&']').is_some() {
+                return Ok(Json::Array(items));
+            }
+
+            loop {
+                items.push(parse_value(chars)?);
+                skip_whitespace(chars);
+
+                match chars.next() {
+                    Some(',') => continue,
+                    Some(']') => return Ok(Json::Array(items)),
+                    _ => return Err("expected `,` or `]` in array".to_string()),
+                }
+            }
+        }
+        Some('{') => {
+            chars.next();
+            let mut pairs = vec![];
+
+            skip_whitespace(chars);
+            if chars.next_if_eq(&'}').is_some() {
+                return Ok(Json::Object(pairs));
+            }
+
+            loop {
+                skip_whitespace(chars);
+                let key = parse_str(chars)?;
+
+                skip_whitespace(chars);
+                expect(chars, ":")?;
+                pairs.push((key, parse_value(chars)?));
+                skip_whitespace(chars);
+
+                match chars.next() {
+                    Some(',') => continue,
+                    Some('}') => return Ok(Json::Object(pairs)),
+                    _ => return Err("expected `,` or `}` in object".to_string()),
+                }
+            }
+        }
+        Some(c) if c == '-' || c.is_ascii_digit() => {
+            let mut number = String::new();
+
+            while let Some(c) = chars.next_if(|c| matches!(c, '-' | '+' | '.' | 'e' | 'E' | '0'..='9')) {
+                number.push(c);
+            }
+
+            number
+                .parse()
+                .map(Json::Int)
+                .or_else(|_| number.parse().map(Json::Float))
+                .map_err(|_| format!("invalid number `{}`", number))
+        }
+        Some(c) => Err(format!("unexpected `{}`", c)),
+        None => Err("unexpected end of input".to_string()),
+    }
+}
+
+fn parse_str(chars: &mut Input) -> Result<String, String> {
+    expect(chars, "\"")?;
+    let mut s = String::new();
+
+    loop {
+        match chars.next() {
+            Some('"') => return Ok(s),
+            Some('\\') => match chars.next() {
+                Some('n') => s.push('\n'),
+                Some('r') => s.push('\r'),
+                Some('t') => s.push('\t'),
+                Some('b') => s.push('\u{8}'),
+                Some('f') => s.push('\u{c}'),
+                Some('u') => {
+                    let mut code = parse_hex(chars)?;
+
+                    // Characters outside the basic plane are escaped as surrogate pairs
+                    if (0xd800..0xdc00).contains(&code) {
+                        expect(chars, "\\u")?;
+                        code = 0x10000 + ((code - 0xd800) << 10) + (parse_hex(chars)? - 0xdc00);
+                    }
+
+                    s.push(char::from_u32(code).ok_or("invalid unicode escape")?);
+                }
+                Some(c) => s.push(c),
+                None => return Err("unterminated string".to_string()),
+            },
+            Some(c) => s.push(c),
+            None => return Err("unterminated string".to_string()),
+        }
+    }
+}
+
+fn parse_hex(chars: &mut Input) -> Result<u32, String> {
+    let hex: String = chars.take(4).collect();
+
+    u32::from_str_radix(&hex, 16).map_err(|_| format!("invalid unicode escape `{}`", hex))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_serializes_nested_values() {
+        let json = Json::object([
+            ("name", Json::from("a \"quoted\"\nvalue")),
+            ("count", Json::from(3)),
+            ("missing", Json::from(None::<i64>)),
+            ("items", Json::Array(vec![Json::from(true), Json::from(1.5)])),
+        ]);
+
+        assert_eq!(
+            json.to_string(),
+            r#"{"name":"a \"quoted\"\nvalue","count":3,"missing":null,"items":[true,1.5]}"#,
+        );
+    }
+
+    #[test]
+    fn json_parses_what_it_serializes() {
+        let json = Json::object([
+            ("name", Json::from("a \"quoted\"\n\u{1}value ✓")),
+            ("count", Json::from(-3)),
+            ("ratio", Json::from(1.5)),
+            ("missing", Json::Null),
+            ("items", Json::Array(vec![Json::from(true), Json::Array(vec![]), Json::object::<&str>([])])),
+        ]);
+
+        assert_eq!(Json::parse(&json.to_string()), Ok(json));
+        assert_eq!(
+            Json::parse(" { \"a\" : [ 1 , \"\\ud83d\\ude00\" ] } "),
+            Ok(Json::object([("a", Json::Array(vec![Json::from(1), Json::from("😀")]))])),
+        );
+        assert!(Json::parse("{\"a\": 1,}").is_err());
+        assert!(Json::parse("[1] 2").is_err());
+    }
+}
@@ -0,0 +1,72 @@
+//! JUnit XML output, which most CI systems can render natively.
+use std::fmt;
+
+/// A single test case with any number of failures.
+#[derive(Debug)]
+pub struct TestCase {
+    pub name: String,
+    /// Pairs of failure type and message
+    pub failures: Vec<(String, String)>,
+}
+
+#[derive(Debug)]
+pub struct TestSuite {
+    pub name: String,
+    pub cases: Vec<TestCase>,
+}
+
+impl TestSuite {
+    pub fn failures(&self) -> usize {
+        self.cases.iter().filter(|c| !c.failures.is_empty()).count()
+    }
+}
+
+impl fmt::Display for TestSuite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (tests, failures) = (self.cases.len(), self.failures());
+
+        writeln!(f, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(
+            f,
+            r#"<testsuites name="jrny" tests="{}" failures="{}">"#,
+            tests, failures,
+        )?;
+        writeln!(
+            f,
+            r#"  <testsuite name="{}" tests="{}" failures="{}">"#,
+            escape(&self.name), tests, failures,
+        )?;
+
+        for case in &self.cases {
+            let name = escape(&case.name);
+
+            if case.failures.is_empty() {
+                writeln!(f, r#"    <testcase classname="revisions" name="{}"/>"#, name)?;
+                continue;
+            }
+
+            writeln!(f, r#"    <testcase classname="revisions" name="{}">"#, name)?;
+
+            for (kind, message) in &case.failures {
+                writeln!(
+                    f,
+                    r#"      <failure type="{}" message="{}"/>"#,
+                    escape(kind), escape(message),
+                )?;
+            }
+
+            writeln!(f, "    </testcase>")?;
+        }
+
+        writeln!(f, "  </testsuite>")?;
+        write!(f, "</testsuites>")
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
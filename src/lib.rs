@@ -1,18 +1,74 @@
 pub mod commands;
 
+mod analysis;
+mod approvals;
+mod assertions;
+mod batch;
+mod bundle;
+mod catalog;
+mod checksum;
 mod client;
 mod config;
+mod diff;
 mod environment;
 mod error;
+mod events;
 mod executor;
+mod fixtures;
+mod freeze;
+mod generators;
+mod git;
+mod grants;
+mod gzip;
+mod heartbeat;
+mod http;
+mod ignore;
+mod import;
 mod logger;
+mod meta;
+mod metrics;
+mod naming;
+mod notify;
+mod ownership;
+mod pacing;
+mod paths;
+mod pg_version;
+mod pgtap;
+mod policy;
+mod prepared;
+mod json;
+mod junit;
+mod progress;
+mod report;
+mod retry;
 mod revisions;
+mod rewrite;
+mod schedule;
+mod schema;
+mod secrets;
+mod settings;
+mod signing;
+mod statements;
+mod telemetry;
+mod template;
+mod validation;
+mod workspace;
 
+pub use catalog::{Catalog, MESSAGES};
 pub use commands::*;
 pub use config::Config;
 pub use environment::Environment;
+pub use generators::GeneratorFlags;
 pub use error::Error;
-pub use logger::Logger;
+pub use import::MigrationTool;
+pub use logger::{ColorMode, Logger, TimeStyle};
+pub use meta::parse_duration;
+pub use rewrite::Rewriter;
+pub use schedule::start_time;
+pub use schema::SNAPSHOT;
+pub use signing::{SigningTool, MANIFEST};
+pub use statements::Statement;
+pub use workspace::{Workspace, WORKSPACE};
 
 // Crate result type
 pub type Result<T> = std::result::Result<T, error::Error>;
@@ -25,3 +81,7 @@ pub const ENV: &str = "jrny-env.toml";
 
 /// The default name of the example environment file
 pub const ENV_EX: &str = "jrny-env.example.toml";
+
+/// The default name of the file declaring roles and grants, alongside the
+/// config file
+pub const ROLES: &str = "roles.toml";
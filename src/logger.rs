@@ -1,12 +1,88 @@
 //! Basic implementation of a Log, as none of the complexity of
 //! common crates is particularly necessary here.
 //! See: https://docs.rs/log/0.4.11/log/#implementing-a-logger
+use chrono::{DateTime, SecondsFormat, Utc};
 use log::{Level, Log, Metadata, Record};
-use std::io::Write;
-use termcolor::{Color, ColorChoice, ColorSpec, StandardStream, WriteColor};
+use std::{
+    env,
+    io::{self, IsTerminal, Write},
+    str::FromStr,
+    sync::atomic::{AtomicBool, AtomicU8, Ordering},
+};
+use termcolor::{Ansi, Color, ColorChoice, ColorSpec, StandardStream, WriteColor};
+
+use crate::Error;
+
+static STDOUT_COLOR: AtomicBool = AtomicBool::new(false);
+static STDERR_COLOR: AtomicBool = AtomicBool::new(false);
+static TIME_STYLE: AtomicU8 = AtomicU8::new(TimeStyle::Relative as u8);
+
+/// When to colorize output
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorMode {
+    /// Colorize when writing to a terminal and `NO_COLOR` is not set
+    Auto,
+    Always,
+    Never,
+}
+
+impl FromStr for ColorMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Self::Auto),
+            "always" => Ok(Self::Always),
+            "never" => Ok(Self::Never),
+            _ => Err(Error::ColorModeInvalid(s.to_string())),
+        }
+    }
+}
+
+/// How moments are shown in output
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimeStyle {
+    /// How long ago, along with when in UTC, eg. `3 days ago (2024-06-01 14:02 UTC)`
+    Relative,
+    /// When in UTC alone, eg. `2024-06-01 14:02:00 UTC`
+    Utc,
+    /// As RFC 3339 in UTC, eg. `2024-06-01T14:02:00Z`, for scripts
+    Iso,
+}
+
+impl TimeStyle {
+    fn load() -> Self {
+        match TIME_STYLE.load(Ordering::Relaxed) {
+            style if style == Self::Utc as u8 => Self::Utc,
+            style if style == Self::Iso as u8 => Self::Iso,
+            _ => Self::Relative,
+        }
+    }
+}
 
 pub struct Logger;
 
+impl Logger {
+    /// Resolves the color mode against the environment and each output stream.
+    pub fn set_color(mode: ColorMode) {
+        let no_color = env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+
+        let (stdout, stderr) = match mode {
+            ColorMode::Always => (true, true),
+            ColorMode::Never => (false, false),
+            ColorMode::Auto if no_color => (false, false),
+            ColorMode::Auto => (io::stdout().is_terminal(), io::stderr().is_terminal()),
+        };
+
+        STDOUT_COLOR.store(stdout, Ordering::Relaxed);
+        STDERR_COLOR.store(stderr, Ordering::Relaxed);
+    }
+
+    pub fn set_time_style(style: TimeStyle) {
+        TIME_STYLE.store(style as u8, Ordering::Relaxed);
+    }
+}
+
 impl Log for Logger {
     fn enabled(&self, metadata: &Metadata) -> bool {
         metadata.level() <= Level::Info
@@ -18,7 +94,12 @@ impl Log for Logger {
         }
 
         if record.metadata().level() == Level::Warn {
-            let mut stderr = StandardStream::stderr(ColorChoice::Always);
+            let choice = if STDERR_COLOR.load(Ordering::Relaxed) {
+                ColorChoice::Always
+            } else {
+                ColorChoice::Never
+            };
+            let mut stderr = StandardStream::stderr(choice);
 
             stderr
                 .set_color(ColorSpec::new().set_fg(Some(Color::Red)))
@@ -36,3 +117,86 @@ impl Log for Logger {
 
     fn flush(&self) {}
 }
+
+/// Wraps the text in the escape codes for the given color, if standard output
+/// is being colorized.
+pub(crate) fn paint(text: &str, color: Color) -> String {
+    if !STDOUT_COLOR.load(Ordering::Relaxed) {
+        return text.to_string();
+    }
+
+    let mut ansi = Ansi::new(vec![]);
+
+    ansi.set_color(ColorSpec::new().set_fg(Some(color))).unwrap();
+    write!(&mut ansi, "{}", text).unwrap();
+    ansi.reset().unwrap();
+
+    String::from_utf8(ansi.into_inner()).unwrap()
+}
+
+/// Formats the moment as output is to show them.
+pub(crate) fn format_time(moment: DateTime<Utc>) -> String {
+    format_time_at(moment, Utc::now(), TimeStyle::load())
+}
+
+/// How wide a column of moments formatted as output is to show them should
+/// be, to line up whatever follows.
+pub(crate) fn time_width() -> usize {
+    match TimeStyle::load() {
+        TimeStyle::Relative => 38,
+        TimeStyle::Utc => 25,
+        TimeStyle::Iso => 22,
+    }
+}
+
+fn format_time_at(moment: DateTime<Utc>, now: DateTime<Utc>, style: TimeStyle) -> String {
+    match style {
+        TimeStyle::Relative => format!("{} ({})", ago(moment, now), moment.format("%Y-%m-%d %H:%M UTC")),
+        TimeStyle::Utc => moment.format("%Y-%m-%d %H:%M:%S UTC").to_string(),
+        TimeStyle::Iso => moment.to_rfc3339_opts(SecondsFormat::Secs, true),
+    }
+}
+
+/// How long before `now` the moment was, in the largest whole unit.
+fn ago(moment: DateTime<Utc>, now: DateTime<Utc>) -> String {
+    let seconds = (now - moment).num_seconds();
+    let (amount, unit) = match seconds.abs() {
+        s if s < 60 => return "just now".to_string(),
+        s if s < 60 * 60 => (s / 60, "minute"),
+        s if s < 24 * 60 * 60 => (s / (60 * 60), "hour"),
+        s if s < 30 * 24 * 60 * 60 => (s / (24 * 60 * 60), "day"),
+        s if s < 365 * 24 * 60 * 60 => (s / (30 * 24 * 60 * 60), "month"),
+        s => (s / (365 * 24 * 60 * 60), "year"),
+    };
+    let plural = if amount == 1 { "" } else { "s" };
+
+    if seconds < 0 {
+        format!("in {} {}{}", amount, unit, plural)
+    } else {
+        format!("{} {}{} ago", amount, unit, plural)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone};
+
+    use super::*;
+
+    #[test]
+    fn times_are_formatted_relative_to_now_or_absolute() {
+        let now = Utc.ymd(2024, 6, 4).and_hms(9, 30, 0);
+        let applied = Utc.ymd(2024, 6, 1).and_hms(14, 2, 0);
+
+        assert_eq!(format_time_at(applied, now, TimeStyle::Relative), "2 days ago (2024-06-01 14:02 UTC)");
+        assert_eq!(format_time_at(applied, now, TimeStyle::Utc), "2024-06-01 14:02:00 UTC");
+        assert_eq!(format_time_at(applied, now, TimeStyle::Iso), "2024-06-01T14:02:00Z");
+
+        assert_eq!(ago(now - Duration::seconds(59), now), "just now");
+        assert_eq!(ago(now - Duration::minutes(1), now), "1 minute ago");
+        assert_eq!(ago(now - Duration::hours(23), now), "23 hours ago");
+        assert_eq!(ago(now - Duration::days(45), now), "1 month ago");
+        assert_eq!(ago(now - Duration::days(800), now), "2 years ago");
+        assert_eq!(ago(now + Duration::hours(2), now), "in 2 hours");
+    }
+}
@@ -1,22 +1,78 @@
-use std::path::PathBuf;
+use std::{
+    env,
+    path::{Path, PathBuf},
+    process,
+    time::Duration,
+};
 
-use clap::{Parser, crate_version};
+use chrono::{DateTime, Local, Utc};
+use clap::{ArgGroup, Parser, crate_version};
 use log::{warn, LevelFilter};
 
 use jrny::{
+    BeginOptions,
+    BootstrapOptions,
     CONF,
+    Catalog,
     ENV,
+    CloneStateOptions,
+    ColorMode,
     Config,
+    ConfigFormat,
+    DiffOptions,
+    EmbarkOptions,
+    EnvAddOptions,
     Environment,
     Error as JrnyError,
+    ExecOptions,
+    ExportOptions,
+    GeneratorFlags,
+    GrantsOptions,
+    PreparedOptions,
+    IfAlreadyRunning,
+    MESSAGES,
+    ImportOptions,
     Logger,
+    MigrationTool,
+    OutputFormat,
+    PlanOptions,
     Result as JrnyResult,
+    RevertOptions,
+    ReviewOptions,
+    RunOptions,
+    SeedOptions,
+    SignOptions,
+    SnapshotOptions,
+    SigningTool,
+    SplitOptions,
+    TestOptions,
+    TimeStyle,
+    TombstoneOptions,
+    VerifyOptions,
+    Workspace,
 };
 
 /// PostgreSQL schema revisions made easy - just add SQL!
 #[derive(Parser, Debug)]
 #[clap(version = crate_version!())]
 struct Opts {
+    /// When to colorize output: `auto`, `always`, or `never`
+    #[clap(long = "color", name = "WHEN", default_value = "auto", global = true)]
+    color: ColorMode,
+
+    /// Show times in UTC alone, rather than how long ago along with when
+    #[clap(long = "utc", global = true)]
+    utc: bool,
+
+    /// Show times as RFC 3339 in UTC, eg. for scripts
+    #[clap(long = "iso", global = true, conflicts_with = "utc")]
+    iso: bool,
+
+    /// Run as if from within the project's directory, which must be one of
+    /// those listed in the workspace file if there is one
+    #[clap(long = "project", name = "DIR", global = true)]
+    project: Option<PathBuf>,
+
     #[clap(subcommand)]
     subcmd: SubCommand,
 }
@@ -26,7 +82,30 @@ enum SubCommand {
     Begin(Begin),
     Plan(Plan),
     Review(Review),
+    Verify(Verify),
+    Sign(Sign),
+    Diff(Diff),
+    Snapshot(Snapshot),
+    Status(Status),
+    Env(Env),
+    Grants(Grants),
+    Bootstrap(Bootstrap),
     Embark(Embark),
+    Prepared(Prepared),
+    Test(Test),
+    Exec(Exec),
+    History(History),
+    Search(Search),
+    Rename(Rename),
+    Split(Split),
+    Tombstone(Tombstone),
+    Seed(Seed),
+    Fixtures(Fixtures),
+    Import(Import),
+    Export(Export),
+    CloneState(CloneState),
+    Revert(Revert),
+    Run(Run),
 }
 
 /// Sets up relevant files and directories for a new revision timeline
@@ -34,6 +113,33 @@ enum SubCommand {
 struct Begin {
     /// The directory in which to set up new project files - will be created if does not exist
     dirpath: PathBuf,
+
+    /// The revisions directory, relative to the project directory
+    #[clap(long = "revisions-dir", name = "REVISIONS", default_value = "revisions")]
+    revisions_dir: PathBuf,
+
+    /// Create only the revisions directory, which is the default
+    #[clap(long = "flat", conflicts_with = "grouped")]
+    flat: bool,
+
+    /// Also create directories for repeatable revisions, seeds, and fixtures
+    #[clap(long = "grouped")]
+    grouped: bool,
+
+    /// How much of the config file to write out: `commented` for every
+    /// option, or `minimal` for only those required
+    #[clap(long = "config-format", name = "FORMAT", default_value = "commented")]
+    config_format: ConfigFormat,
+
+    /// Names of environments to create environment files for, eg.
+    /// `staging,production`, which revisions can then be limited to
+    #[clap(long = "environments", name = "NAMES", value_delimiter = ',')]
+    environments: Vec<String>,
+
+    /// Add entries keeping environment files out of version control to the
+    /// project's `.gitignore`
+    #[clap(long = "gitignore")]
+    gitignore: bool,
 }
 
 /// Generates a new SQL revision file
@@ -42,8 +148,32 @@ struct Plan {
     #[clap(flatten)]
     cfg: CliConfig,
 
-    /// Name for the new revision file
+    /// Name for the new revision file, or the operation to generate revisions
+    /// for when given arguments, eg. `add-column orders shipped_at timestamptz`
     name: String,
+
+    /// Arguments for generating revisions
+    #[clap(name = "ARGS")]
+    args: Vec<String>,
+
+    /// Mark the revision as changing data rather than the schema, which
+    /// review then holds to stricter checks
+    #[clap(long = "data")]
+    data: bool,
+
+    /// The value to backfill a generated column with for existing rows, eg.
+    /// another column
+    #[clap(long = "backfill", name = "VALUE")]
+    backfill: Option<String>,
+
+    /// Build a generated index without blocking writes to the table
+    #[clap(long = "concurrent")]
+    concurrent: bool,
+
+    /// The column giving the tenant of each row, for generated row-level
+    /// security policies
+    #[clap(long = "tenant-column", name = "COLUMN")]
+    tenant_column: Option<String>,
 }
 
 /// Summarizes the state of revisions on disk and in database
@@ -53,7 +183,464 @@ struct Review {
     cfg: CliConfig,
 
     #[clap(flatten)]
-    env: CliEnvironment
+    env: CliEnvironment,
+
+    /// Output format: `text` or `junit`
+    #[clap(long = "format", name = "FORMAT", default_value = "text")]
+    format: OutputFormat,
+
+    /// Also list the locks each pending statement takes, and on which relations
+    #[clap(long = "locks")]
+    locks: bool,
+
+    /// Review against the state file written by `embark --write-state`
+    /// instead of connecting to the database
+    #[clap(long = "state", name = "PATH")]
+    state: Option<PathBuf>,
+
+    /// Review only revisions added or changed since the git ref, eg. `origin/main`,
+    /// failing if any don't pass or were already applied
+    #[clap(long = "against", name = "REF")]
+    against: Option<String>,
+
+    /// List only revisions yet to be applied
+    #[clap(long = "pending-only")]
+    pending_only: bool,
+
+    /// List only revisions whose files changed since they were applied
+    #[clap(long = "changed-only")]
+    changed_only: bool,
+
+    /// List only revisions whose names or paths contain the text, or match
+    /// it as a pattern with `*` and `?`, eg. `2021-04/*`
+    #[clap(long = "revision", name = "PATTERN")]
+    revision: Option<String>,
+
+    /// List revisions under the release group they're in
+    #[clap(long = "group-by-release")]
+    group_by_release: bool,
+}
+
+/// Checks revisions as with review, reporting only failures and exiting with an error if any
+#[derive(Parser, Debug)]
+struct Verify {
+    #[clap(flatten)]
+    cfg: CliConfig,
+
+    #[clap(flatten)]
+    env: CliEnvironment,
+
+    /// Output format: `text` or `junit`
+    #[clap(long = "format", name = "FORMAT", default_value = "text")]
+    format: OutputFormat,
+
+    /// Also require every pending revision to be approved, as it is now and
+    /// by one of its owners (if any), in the given TOML file
+    #[clap(long = "require-approval-file", name = "PATH")]
+    require_approval_file: Option<PathBuf>,
+
+    /// Also require applied revisions to have been applied in the order
+    /// they're in on disk, with none applied after later ones
+    #[clap(long = "strict-order")]
+    strict_order: bool,
+}
+
+/// Writes a manifest of every revision file's checksum and signs it, for embarking to require
+#[derive(Parser, Debug)]
+struct Sign {
+    #[clap(flatten)]
+    cfg: CliConfig,
+
+    /// The private key to sign with, or for `gpg` its id
+    #[clap(long = "key", name = "KEY")]
+    key: PathBuf,
+
+    /// What to sign with: `ssh` (with `ssh-keygen`), `gpg`, or `minisign`
+    #[clap(long = "tool", name = "TOOL", default_value = "ssh")]
+    tool: SigningTool,
+
+    /// Where to write the manifest, with its signature alongside it as `[path].sig`
+    #[clap(long = "manifest", name = "MANIFEST", default_value = jrny::MANIFEST)]
+    manifest: PathBuf,
+
+    /// Instead of a manifest, sign each revision file yet to be signed, with
+    /// its signature alongside it
+    #[clap(long = "files", conflicts_with = "MANIFEST")]
+    files: bool,
+}
+
+/// Compares the schemas of two live databases, eg. staging and production
+#[derive(Parser, Debug)]
+struct Diff {
+    /// The database to compare from, as a connection string or an environment file
+    #[clap(long = "from-db", name = "FROM")]
+    from_db: String,
+
+    /// The database to compare to, as a connection string or an environment file
+    #[clap(long = "to-db", name = "TO")]
+    to_db: String,
+}
+
+/// Writes a snapshot of the environment's schema in a stable text format, or checks that it's up to date
+#[derive(Parser, Debug)]
+struct Snapshot {
+    #[clap(flatten)]
+    cfg: CliConfig,
+
+    #[clap(flatten)]
+    env: CliEnvironment,
+
+    /// Where to write the snapshot, or read it from with `--check`
+    #[clap(name = "SNAPSHOT", default_value = jrny::SNAPSHOT)]
+    path: PathBuf,
+
+    /// Fail, listing what changed, if the snapshot is out of date rather
+    /// than writing it
+    #[clap(long = "check")]
+    check: bool,
+}
+
+/// Summarizes how many revisions are applied and pending, for one project or every project in the workspace
+#[derive(Parser, Debug)]
+struct Status {
+    #[clap(flatten)]
+    cfg: CliConfig,
+
+    #[clap(flatten)]
+    env: CliEnvironment,
+
+    /// Summarize every project listed in the workspace file, each with the
+    /// config and environment files in its directory
+    #[clap(long = "all-projects", conflicts_with_all = &["CFG", "ENV", "URL"])]
+    all_projects: bool,
+}
+
+/// Lists, adds, and tests the project's environments
+#[derive(Parser, Debug)]
+struct Env {
+    #[clap(subcommand)]
+    subcmd: EnvCommand,
+}
+
+#[derive(Parser, Debug)]
+enum EnvCommand {
+    List(EnvList),
+    Add(EnvAdd),
+    Test(EnvTest),
+}
+
+/// Lists the environment files alongside the config file, each with its database
+#[derive(Parser, Debug)]
+struct EnvList {
+    #[clap(flatten)]
+    cfg: CliConfig,
+}
+
+/// Shows or applies the changes making the cluster's roles and grants match `roles.toml`
+#[derive(Parser, Debug)]
+struct Grants {
+    #[clap(subcommand)]
+    subcmd: GrantsCommand,
+}
+
+#[derive(Parser, Debug)]
+enum GrantsCommand {
+    Diff(GrantsDiff),
+    Apply(GrantsApply),
+}
+
+/// Lists the changes needed for the cluster to match `roles.toml`, exiting with an error if there are any
+#[derive(Parser, Debug)]
+struct GrantsDiff {
+    #[clap(flatten)]
+    cfg: CliConfig,
+
+    #[clap(flatten)]
+    env: CliEnvironment,
+
+    /// The file declaring roles and grants, if not `roles.toml` alongside the config file
+    #[clap(long = "roles", name = "ROLES")]
+    roles: Option<PathBuf>,
+}
+
+/// Creates and alters roles, and grants and revokes memberships and default privileges, to match `roles.toml`
+#[derive(Parser, Debug)]
+struct GrantsApply {
+    #[clap(flatten)]
+    cfg: CliConfig,
+
+    #[clap(flatten)]
+    env: CliEnvironment,
+
+    /// The file declaring roles and grants, if not `roles.toml` alongside the config file
+    #[clap(long = "roles", name = "ROLES")]
+    roles: Option<PathBuf>,
+
+    /// Skip typing in the name of a protected environment to confirm
+    #[clap(long = "yes-i-mean-production")]
+    yes_i_mean_production: bool,
+}
+
+/// Writes the environment file for a new environment, eg. `jrny-env.staging.toml`
+#[derive(Parser, Debug)]
+struct EnvAdd {
+    #[clap(flatten)]
+    cfg: CliConfig,
+
+    /// The name of the environment, eg. `staging`
+    #[clap(name = "NAME")]
+    name: String,
+
+    /// The database connection string
+    #[clap(long = "url", name = "URL")]
+    url: String,
+
+    /// Guard the environment against commands only meant for development
+    #[clap(long = "protected")]
+    protected: bool,
+
+    /// Where to fetch the password from instead of the URL, eg.
+    /// `vault:secret/data/db#password`
+    #[clap(long = "password-ref", name = "REF")]
+    password_ref: Option<String>,
+}
+
+/// Checks that an environment's files are valid and its database can be connected to
+#[derive(Parser, Debug)]
+struct EnvTest {
+    #[clap(flatten)]
+    cfg: CliConfig,
+
+    /// The name of the environment, or `jrny-env.toml` if not given
+    #[clap(name = "NAME")]
+    name: Option<String>,
+}
+
+/// Lists applied revisions along with the git commit, branch, and author recorded for each
+#[derive(Parser, Debug)]
+struct History {
+    #[clap(flatten)]
+    cfg: CliConfig,
+
+    #[clap(flatten)]
+    env: CliEnvironment,
+}
+
+/// Finds revisions whose files or recorded impact mention the term, and when each was applied
+#[derive(Parser, Debug)]
+struct Search {
+    #[clap(flatten)]
+    cfg: CliConfig,
+
+    #[clap(flatten)]
+    env: CliEnvironment,
+
+    /// The text to search for, ignoring case, eg. a table name
+    term: String,
+}
+
+/// Splits a pending revision into several at statement boundaries
+#[derive(Parser, Debug)]
+struct Split {
+    #[clap(flatten)]
+    cfg: CliConfig,
+
+    #[clap(flatten)]
+    env: CliEnvironment,
+
+    /// The filename, id, or name of the revision to split
+    #[clap(name = "REVISION")]
+    revision: String,
+
+    /// The statements to split before, numbered from 1, eg. `2,5`, rather
+    /// than choosing them interactively
+    #[clap(long = "at", name = "STATEMENTS", value_delimiter = ',')]
+    at: Vec<usize>,
+}
+
+/// Replaces a revision with a tombstone superseded by another, which only
+/// environments that didn't apply it apply instead
+#[derive(Parser, Debug)]
+struct Tombstone {
+    #[clap(flatten)]
+    cfg: CliConfig,
+
+    /// The filename, id, or name of the revision to replace
+    #[clap(name = "REVISION")]
+    revision: String,
+
+    /// The id of the revision superseding it
+    #[clap(long = "superseded-by", name = "ID")]
+    superseded_by: i32,
+}
+
+/// Renames pending revision files
+#[derive(Parser, Debug)]
+#[clap(group = ArgGroup::new("action").required(true))]
+struct Rename {
+    #[clap(flatten)]
+    cfg: CliConfig,
+
+    #[clap(flatten)]
+    env: CliEnvironment,
+
+    /// Renumber pending revisions so that each has a unique id following
+    /// all applied revisions, eg. after merging branches that both added one
+    #[clap(long = "resequence", group = "action")]
+    resequence: bool,
+}
+
+/// Applies reference data from seed files that are new or changed since last applied
+#[derive(Parser, Debug)]
+struct Seed {
+    #[clap(flatten)]
+    cfg: CliConfig,
+
+    #[clap(flatten)]
+    env: CliEnvironment,
+
+    /// Apply all seeds, even those unchanged since last applied
+    #[clap(long = "force")]
+    force: bool,
+
+    /// Skip typing in the name of a protected environment to confirm
+    #[clap(long = "yes-i-mean-production")]
+    yes_i_mean_production: bool,
+}
+
+/// Loads sample data from fixture files into a designated, unprotected environment
+#[derive(Parser, Debug)]
+struct Fixtures {
+    #[clap(flatten)]
+    cfg: CliConfig,
+
+    #[clap(flatten)]
+    env: CliEnvironment,
+}
+
+/// Converts revisions from another migration tool and records those it has applied,
+/// or imports a bundle written by `jrny export`
+#[derive(Parser, Debug)]
+#[clap(group = ArgGroup::new("origin").required(true))]
+struct Import {
+    #[clap(flatten)]
+    cfg: CliConfig,
+
+    #[clap(flatten)]
+    env: CliEnvironment,
+
+    /// The tool to import from: `flyway`, `sqitch`, or `golang-migrate`
+    #[clap(long = "from", name = "TOOL", group = "origin")]
+    from: Option<MigrationTool>,
+
+    /// Where the tool's revisions are, to convert them into the revisions
+    /// directory - omit if already converted, eg. for another environment
+    #[clap(long = "source", name = "DIR", requires = "TOOL")]
+    source: Option<PathBuf>,
+
+    /// The tool's history table, if not its default
+    #[clap(long = "history-table", name = "TABLE", requires = "TOOL")]
+    history_table: Option<String>,
+
+    /// A bundle written by `jrny export`, whose missing revision files are
+    /// written to the revisions directory
+    #[clap(long = "bundle", name = "BUNDLE", group = "origin")]
+    bundle: Option<PathBuf>,
+
+    /// Also record the bundle's history of applied revisions as this
+    /// environment's, eg. for a database restored from the exported one
+    #[clap(long = "with-history", requires = "BUNDLE")]
+    with_history: bool,
+}
+
+/// Writes the revision files and the environment's history of applying them to a bundle
+#[derive(Parser, Debug)]
+struct Export {
+    #[clap(flatten)]
+    cfg: CliConfig,
+
+    #[clap(flatten)]
+    env: CliEnvironment,
+
+    /// Where to write the bundle (as JSON)
+    output: PathBuf,
+}
+
+/// Applies every revision to a scratch database on the environment's server and checks the assertions in `tests`
+#[derive(Parser, Debug)]
+struct Test {
+    #[clap(flatten)]
+    cfg: CliConfig,
+
+    #[clap(flatten)]
+    env: CliEnvironment,
+
+    /// A database to copy as the scratch database, rather than starting from an empty one
+    #[clap(long = "template", name = "DATABASE")]
+    template: Option<String>,
+
+    /// Keep the scratch database afterwards rather than dropping it
+    #[clap(long = "keep")]
+    keep: bool,
+
+    /// Revert the revisions once applied, with the `.revert.sql` file alongside each, and apply them again
+    #[clap(long = "round-trip")]
+    round_trip: bool,
+}
+
+/// Records the revisions applied to one environment as applied to another, eg. a database restored from its dump
+#[derive(Parser, Debug)]
+struct CloneState {
+    #[clap(flatten)]
+    cfg: CliConfig,
+
+    /// The name of the environment whose state is copied
+    #[clap(long = "from", name = "FROM")]
+    from: String,
+
+    /// The name of the environment to record it for
+    #[clap(long = "to", name = "TO")]
+    to: String,
+
+    /// Skip typing in the name of a protected environment to confirm
+    #[clap(long = "yes-i-mean-production")]
+    yes_i_mean_production: bool,
+}
+
+/// Reverts the revisions of a release group, most recently applied first, with the `.revert.sql` file alongside each
+#[derive(Parser, Debug)]
+struct Revert {
+    #[clap(flatten)]
+    cfg: CliConfig,
+
+    #[clap(flatten)]
+    env: CliEnvironment,
+
+    /// The release group to revert, by the name of its directory
+    #[clap(long = "group", name = "GROUP")]
+    group: String,
+
+    /// Skip typing in the name of a protected environment to confirm
+    #[clap(long = "yes-i-mean-production")]
+    yes_i_mean_production: bool,
+}
+
+/// Creates the database's role and extensions from the config, and with `--create-db` the database, then embarks
+#[derive(Parser, Debug)]
+struct Bootstrap {
+    #[clap(flatten)]
+    cfg: CliConfig,
+
+    #[clap(flatten)]
+    env: CliEnvironment,
+
+    /// Create the database, along with the role to own it, should they not exist yet
+    #[clap(long = "create-db")]
+    create_db: bool,
+
+    /// Skip typing in the name of a protected environment to confirm
+    #[clap(long = "yes-i-mean-production")]
+    yes_i_mean_production: bool,
 }
 
 /// Applies pending revisions upon successful review
@@ -64,6 +651,172 @@ struct Embark {
 
     #[clap(flatten)]
     env: CliEnvironment,
+
+    /// Also write the run summary to the given `.json` or `.md` file
+    #[clap(long = "report", name = "PATH")]
+    report: Option<PathBuf>,
+
+    /// Stream lifecycle events to stdout as newline-delimited JSON
+    /// instead of the usual output
+    #[clap(long = "events")]
+    events: bool,
+
+    /// What to do if revisions are already being applied elsewhere, eg. by
+    /// another replica: `wait` for them to finish, or `skip` applying any
+    #[clap(long = "if-already-running", name = "ACTION", default_value = "wait")]
+    if_already_running: IfAlreadyRunning,
+
+    /// Skip typing in the name of a protected environment to confirm
+    #[clap(long = "yes-i-mean-production")]
+    yes_i_mean_production: bool,
+
+    /// Apply revisions even during one of the environment's freeze windows
+    #[clap(long = "override-freeze")]
+    override_freeze: bool,
+
+    /// Wait until the given time before connecting, eg. `02:00` for the next
+    /// 02:00 in local time, `02:00Z` in UTC, or `2021-11-25T02:00`
+    #[clap(
+        long = "at",
+        visible_alias = "not-before",
+        name = "TIME",
+        parse(try_from_str = parse_start_time),
+        conflicts_with = "SQL",
+    )]
+    at: Option<DateTime<Utc>>,
+
+    /// Afterwards, write the applied revisions to the given state file for
+    /// reviewing against offline
+    #[clap(long = "write-state", name = "STATE")]
+    write_state: Option<PathBuf>,
+
+    /// Write the SQL that applying the revisions would execute to the given
+    /// file, for running without jrny, instead of applying them
+    #[clap(long = "emit-sql", name = "SQL")]
+    emit_sql: Option<PathBuf>,
+
+    /// Rehearse applying the revisions against a copy of another environment,
+    /// refusing to if this one is protected
+    #[clap(long = "rehearse", conflicts_with = "SQL")]
+    rehearse: bool,
+
+    /// While rehearsing, run statements changing data with `EXPLAIN (ANALYZE,
+    /// BUFFERS)` and save their plans to the given directory
+    #[clap(long = "explain", name = "DIR", requires = "rehearse")]
+    explain: Option<PathBuf>,
+
+    /// Terminate sessions found blocking statements from taking locks, unless
+    /// the environment is protected
+    #[clap(long = "terminate-blockers")]
+    terminate_blockers: bool,
+
+    /// Only apply revisions that are in a manifest signed by a key the
+    /// environment trusts, as they are
+    #[clap(long = "require-signature")]
+    require_signature: bool,
+
+    /// The signed manifest, defaulting to `jrny-manifest.txt`
+    #[clap(long = "manifest", name = "MANIFEST")]
+    manifest: Option<PathBuf>,
+
+    /// Pause between statements and revisions while replicas lag further
+    /// behind than this, eg. `10s`, overriding the environment's pacing
+    #[clap(
+        long = "max-replication-lag",
+        name = "LAG",
+        parse(try_from_str = parse_duration),
+        conflicts_with = "SQL",
+    )]
+    max_replication_lag: Option<Duration>,
+
+    /// Apply the revisions of every project listed in the workspace file, in
+    /// an order combining them, each with the config and environment files
+    /// in its directory
+    #[clap(
+        long = "all-projects",
+        conflicts_with_all = &["CFG", "ENV", "URL", "PATH", "events", "STATE", "SQL", "rehearse", "MANIFEST"],
+    )]
+    all_projects: bool,
+
+    /// Prepare each project's transaction with a two-phase commit, committing
+    /// them only once every one is prepared, and rolling them back if any
+    /// fails
+    #[clap(long = "two-phase", requires = "all-projects")]
+    two_phase: bool,
+}
+
+/// Lists or resolves the transactions left prepared by `embark --two-phase` runs that didn't finish
+#[derive(Parser, Debug)]
+struct Prepared {
+    #[clap(subcommand)]
+    subcmd: PreparedCommand,
+}
+
+#[derive(Parser, Debug)]
+enum PreparedCommand {
+    List(PreparedList),
+    Resolve(PreparedResolve),
+}
+
+/// Lists the transactions left prepared on the servers of every project in the workspace, by run
+#[derive(Parser, Debug)]
+struct PreparedList {}
+
+/// Commits each run's transactions left prepared if committing them had started, or rolls them back if not
+#[derive(Parser, Debug)]
+struct PreparedResolve {
+    /// Skip typing in the name of a protected environment to confirm
+    #[clap(long = "yes-i-mean-production")]
+    yes_i_mean_production: bool,
+}
+
+/// Runs an ad-hoc SQL file as a revision would be applied, but without recording it
+#[derive(Parser, Debug)]
+struct Exec {
+    #[clap(flatten)]
+    cfg: CliConfig,
+
+    #[clap(flatten)]
+    env: CliEnvironment,
+
+    /// The SQL file to run, or `-` to read it from stdin
+    path: String,
+
+    /// Skip typing in the name of a protected environment to confirm
+    #[clap(long = "yes-i-mean-production")]
+    yes_i_mean_production: bool,
+}
+
+/// Applies pending revisions, optionally once the database is up, and then runs
+/// the given command in place of jrny, eg. as a container entrypoint
+#[derive(Parser, Debug)]
+struct Run {
+    #[clap(flatten)]
+    cfg: CliConfig,
+
+    #[clap(flatten)]
+    env: CliEnvironment,
+
+    /// How long to wait for the database to accept connections, eg. `60s`
+    #[clap(long = "wait-for-db", name = "DURATION", parse(try_from_str = parse_duration))]
+    wait_for_db: Option<Duration>,
+
+    /// What to do if revisions are already being applied elsewhere, eg. by
+    /// another replica: `wait` for them to finish, or `skip` applying any
+    #[clap(long = "if-already-running", name = "ACTION", default_value = "wait")]
+    if_already_running: IfAlreadyRunning,
+
+    /// Skip typing in the name of a protected environment to confirm
+    #[clap(long = "yes-i-mean-production")]
+    yes_i_mean_production: bool,
+
+    /// Apply revisions even during one of the environment's freeze windows
+    #[clap(long = "override-freeze")]
+    override_freeze: bool,
+
+    /// The command to run, following `--`
+    #[clap(last = true, required = true, name = "COMMAND")]
+    command: Vec<String>,
 }
 
 #[derive(Parser, Debug)]
@@ -75,7 +828,7 @@ struct CliConfig {
 }
 
 impl CliConfig {
-    fn to_cfg(self) -> JrnyResult<Config> {
+    fn into_cfg(self) -> JrnyResult<Config> {
         let confpath = self.filepath.unwrap_or_else(|| PathBuf::from(CONF));
         Config::from_filepath(&confpath)
     }
@@ -92,11 +845,16 @@ struct CliEnvironment {
     /// in directory relative to config file
     #[clap(short = 'e', long = "environment", name = "ENV")]
     filepath: Option<PathBuf>,
+
+    /// Override a setting of the environment file, eg.
+    /// `database.password=secret`, taking precedence over its local file
+    #[clap(long = "set", name = "KEY=VALUE", multiple_occurrences = true)]
+    set: Vec<String>,
 }
 
 // Can't implement from/into traits if `Config` is involved, since it's technically foreign
 impl CliEnvironment {
-    fn to_env(self, cfg: &Config) -> JrnyResult<Environment> {
+    fn into_env(self, cfg: &Config) -> JrnyResult<Environment> {
         let envpath = self.filepath
             .unwrap_or_else(|| cfg.revisions.directory
                 .parent()
@@ -106,8 +864,9 @@ impl CliEnvironment {
 
         // This validates the env file, even if someone overrides it with the
         // database url flag. The file itself is optional as long as the
-        // database url is supplied.
-        let env_file = (match Environment::from_filepath(&envpath) {
+        // database url is supplied. Settings in its local file and then
+        // those set by flag take precedence over those in the file.
+        let env_file = (match Environment::layered(&envpath, &self.set) {
             Ok(env) => Ok(Some(env)),
             Err(err) => match err {
                 JrnyError::EnvNotFound => Ok(None),
@@ -115,12 +874,16 @@ impl CliEnvironment {
             }
         })?;
 
-        match self.database_url {
-            Some(url) => Ok(Environment::from_database_url(&url)),
-            None => match env_file {
-                Some(env) => Ok(env),
-                None => Err(JrnyError::EnvNotFound),
+        // The url flag only overrides the database url, keeping any other
+        // settings from the environment file if there is one.
+        match (self.database_url, env_file) {
+            (Some(url), Some(mut env)) => {
+                env.database.url = url;
+                Ok(env)
             }
+            (Some(url), None) => Ok(Environment::from_database_url(&url)),
+            (None, Some(env)) => Ok(env),
+            (None, None) => Err(JrnyError::EnvNotFound),
         }
     }
 }
@@ -133,38 +896,454 @@ fn main() {
     
     let opts: Opts = Opts::parse();
 
+    Logger::set_color(opts.color);
+
+    if opts.iso {
+        Logger::set_time_style(TimeStyle::Iso);
+    } else if opts.utc {
+        Logger::set_time_style(TimeStyle::Utc);
+    }
+
+    // Wrappers can word errors their own way with a catalog of messages
+    let catalog = match env::var_os(MESSAGES) {
+        Some(path) => Catalog::from_filepath(Path::new(&path)).unwrap_or_else(|e| {
+            warn!("Error: {}", e.report());
+            process::exit(1);
+        }),
+        None => Catalog::default(),
+    };
+
+    if let Some(project) = &opts.project {
+        if let Err(e) = enter_project(project) {
+            warn!("Error: {}", catalog.report(&e));
+            process::exit(1);
+        }
+    }
+
     let result = match opts.subcmd {
-        SubCommand::Begin(cmd)  => begin(cmd),
-        SubCommand::Plan(cmd)   => plan(cmd),
-        SubCommand::Review(cmd) => review(cmd),
-        SubCommand::Embark(cmd) => embark(cmd),
+        SubCommand::Begin(cmd)    => begin(cmd),
+        SubCommand::Plan(cmd)     => plan(cmd),
+        SubCommand::Review(cmd)   => review(cmd),
+        SubCommand::Verify(cmd)   => verify(cmd),
+        SubCommand::Sign(cmd)     => sign(cmd),
+        SubCommand::Diff(cmd)     => diff(cmd),
+        SubCommand::Snapshot(cmd) => snapshot(cmd),
+        SubCommand::Status(cmd)   => status(cmd),
+        SubCommand::Env(cmd)      => environments(cmd),
+        SubCommand::Grants(cmd)   => grants(cmd),
+        SubCommand::Bootstrap(cmd) => bootstrap(cmd),
+        SubCommand::Embark(cmd)   => embark(cmd),
+        SubCommand::Prepared(cmd) => prepared(cmd),
+        SubCommand::Test(cmd)     => test(cmd),
+        SubCommand::Exec(cmd)     => exec(cmd),
+        SubCommand::History(cmd)  => history(cmd),
+        SubCommand::Search(cmd)   => search(cmd),
+        SubCommand::Rename(cmd)   => rename(cmd),
+        SubCommand::Split(cmd)    => split(cmd),
+        SubCommand::Tombstone(cmd) => tombstone(cmd),
+        SubCommand::Seed(cmd)     => seed(cmd),
+        SubCommand::Fixtures(cmd) => fixtures(cmd),
+        SubCommand::Import(cmd)   => import(cmd),
+        SubCommand::Export(cmd)   => export(cmd),
+        SubCommand::CloneState(cmd) => clone_state(cmd),
+        SubCommand::Revert(cmd)   => revert(cmd),
+        SubCommand::Run(cmd)      => run(cmd),
     };
 
     if let Err(e) = result {
-        warn!("Error: {}", e);
+        warn!("Error: {}", catalog.report(&e));
+        process::exit(1);
     }
 }
 
 fn begin(cmd: Begin) -> JrnyResult<()> {
-    jrny::begin(&cmd.dirpath)
+    let opts = BeginOptions {
+        revisions_dir: cmd.revisions_dir,
+        grouped: cmd.grouped && !cmd.flat,
+        config_format: cmd.config_format,
+        environments: cmd.environments,
+        gitignore: cmd.gitignore,
+    };
+
+    jrny::begin(&cmd.dirpath, &opts)
 }
 
 fn plan(cmd: Plan) -> JrnyResult<()> {
-    let cfg = cmd.cfg.to_cfg()?;
+    let cfg = cmd.cfg.into_cfg()?;
 
-    jrny::plan(&cfg, &cmd.name)
+    let opts = PlanOptions {
+        data: cmd.data,
+        args: cmd.args,
+        flags: GeneratorFlags {
+            backfill: cmd.backfill,
+            concurrent: cmd.concurrent,
+            tenant_column: cmd.tenant_column,
+        },
+    };
+
+    jrny::plan(&cfg, &cmd.name, &opts)
 }
 
 fn review(cmd: Review) -> JrnyResult<()> {
-    let cfg = cmd.cfg.to_cfg()?;
-    let env = cmd.env.to_env(&cfg)?;
+    let cfg = cmd.cfg.into_cfg()?;
+
+    // Reviewing against a state file needs neither an environment file nor a
+    // database url, as the database is never connected to
+    let env = match cmd.env.into_env(&cfg) {
+        Err(JrnyError::EnvNotFound) if cmd.state.is_some() => Environment::from_database_url(""),
+        env => env?,
+    };
 
-    jrny::review(&cfg, &env)
+    let opts = ReviewOptions {
+        format: cmd.format,
+        locks: cmd.locks,
+        state: cmd.state,
+        against: cmd.against,
+        pending_only: cmd.pending_only,
+        changed_only: cmd.changed_only,
+        revision: cmd.revision,
+        group_by_release: cmd.group_by_release,
+    };
+
+    jrny::review(&cfg, &env, &opts)
+}
+
+fn verify(cmd: Verify) -> JrnyResult<()> {
+    let cfg = cmd.cfg.into_cfg()?;
+    let env = cmd.env.into_env(&cfg)?;
+    let opts = VerifyOptions {
+        format: cmd.format,
+        approvals: cmd.require_approval_file,
+        strict_order: cmd.strict_order,
+    };
+
+    jrny::verify(&cfg, &env, &opts)
+}
+
+fn sign(cmd: Sign) -> JrnyResult<()> {
+    let cfg = cmd.cfg.into_cfg()?;
+    let opts = SignOptions {
+        key: cmd.key,
+        tool: cmd.tool,
+        manifest: cmd.manifest,
+        files: cmd.files,
+    };
+
+    jrny::sign(&cfg, &opts)
+}
+
+fn diff(cmd: Diff) -> JrnyResult<()> {
+    let opts = DiffOptions {
+        from: cmd.from_db,
+        to: cmd.to_db,
+    };
+
+    jrny::diff(&opts)
+}
+
+fn snapshot(cmd: Snapshot) -> JrnyResult<()> {
+    let cfg = cmd.cfg.into_cfg()?;
+    let env = cmd.env.into_env(&cfg)?;
+    let opts = SnapshotOptions {
+        path: cmd.path,
+        check: cmd.check,
+    };
+
+    jrny::snapshot(&cfg, &env, &opts)
+}
+
+fn bootstrap(cmd: Bootstrap) -> JrnyResult<()> {
+    let cfg = cmd.cfg.into_cfg()?;
+    let env = cmd.env.into_env(&cfg)?;
+    let opts = BootstrapOptions {
+        create_db: cmd.create_db,
+        confirmed: cmd.yes_i_mean_production,
+    };
+
+    jrny::bootstrap(&cfg, &env, &opts)
 }
 
 fn embark(cmd: Embark) -> JrnyResult<()> {
-    let cfg = cmd.cfg.to_cfg()?;
-    let env = cmd.env.to_env(&cfg)?;
+    let opts = EmbarkOptions {
+        report: cmd.report,
+        events: cmd.events,
+        confirmed: cmd.yes_i_mean_production,
+        if_already_running: cmd.if_already_running,
+        state: cmd.write_state,
+        emit_sql: cmd.emit_sql,
+        override_freeze: cmd.override_freeze,
+        not_before: cmd.at,
+        rehearse: cmd.rehearse,
+        explain: cmd.explain,
+        terminate_blockers: cmd.terminate_blockers,
+        rewriters: vec![],
+        require_signature: cmd.require_signature,
+        manifest: cmd.manifest,
+        max_replication_lag: cmd.max_replication_lag,
+        two_phase: cmd.two_phase,
+    };
+
+    if cmd.all_projects {
+        return jrny::embark_all_projects(&current_workspace()?, &opts);
+    }
+
+    let cfg = cmd.cfg.into_cfg()?;
+    let env = cmd.env.into_env(&cfg)?;
+
+    // Human-friendly output would only get in the way of the event stream
+    if opts.events {
+        log::set_max_level(LevelFilter::Warn);
+    }
+
+    jrny::embark(&cfg, &env, &opts)
+}
+
+fn prepared(cmd: Prepared) -> JrnyResult<()> {
+    match cmd.subcmd {
+        PreparedCommand::List(_) => jrny::prepared_list(&current_workspace()?),
+        PreparedCommand::Resolve(cmd) => {
+            let opts = PreparedOptions {
+                confirmed: cmd.yes_i_mean_production,
+            };
+
+            jrny::prepared_resolve(&current_workspace()?, &opts)
+        }
+    }
+}
+
+fn exec(cmd: Exec) -> JrnyResult<()> {
+    let cfg = cmd.cfg.into_cfg()?;
+    let env = cmd.env.into_env(&cfg)?;
+    let opts = ExecOptions {
+        path: cmd.path,
+        confirmed: cmd.yes_i_mean_production,
+    };
+
+    jrny::exec(&cfg, &env, &opts)
+}
+
+/// Changes to the project's directory, found through the workspace file in
+/// the current directory or above it if there is one.
+fn enter_project(project: &Path) -> JrnyResult<()> {
+    let dir = match Workspace::find(&env::current_dir()?)? {
+        Some(workspace) => workspace.project_dir(project).ok_or_else(|| {
+            JrnyError::ProjectNotInWorkspace(project.display().to_string(), workspace.filepath().display().to_string())
+        })?,
+        None => project.to_path_buf(),
+    };
+
+    if !dir.is_dir() {
+        return Err(JrnyError::PathNotDirectory(dir.display().to_string()));
+    }
+
+    Ok(env::set_current_dir(dir)?)
+}
+
+/// The workspace file in the current directory or the nearest above it.
+fn current_workspace() -> JrnyResult<Workspace> {
+    let cwd = env::current_dir()?;
+
+    Workspace::find(&cwd)?.ok_or_else(|| JrnyError::WorkspaceNotFound(cwd.display().to_string()))
+}
+
+fn status(cmd: Status) -> JrnyResult<()> {
+    if cmd.all_projects {
+        return jrny::status_all_projects(&current_workspace()?);
+    }
+
+    let cfg = cmd.cfg.into_cfg()?;
+    let env = cmd.env.into_env(&cfg)?;
+
+    jrny::status(&cfg, &env)
+}
+
+fn environments(cmd: Env) -> JrnyResult<()> {
+    match cmd.subcmd {
+        EnvCommand::List(cmd) => jrny::env_list(&cmd.cfg.into_cfg()?),
+        EnvCommand::Add(cmd) => {
+            let cfg = cmd.cfg.into_cfg()?;
+            let opts = EnvAddOptions {
+                name: cmd.name,
+                url: cmd.url,
+                protected: cmd.protected,
+                password_ref: cmd.password_ref,
+            };
+
+            jrny::env_add(&cfg, &opts)
+        }
+        EnvCommand::Test(cmd) => {
+            let cfg = cmd.cfg.into_cfg()?;
+
+            jrny::env_test(&cfg, cmd.name.as_deref())
+        }
+    }
+}
+
+fn grants(cmd: Grants) -> JrnyResult<()> {
+    match cmd.subcmd {
+        GrantsCommand::Diff(cmd) => {
+            let cfg = cmd.cfg.into_cfg()?;
+            let env = cmd.env.into_env(&cfg)?;
+            let opts = GrantsOptions {
+                roles: cmd.roles,
+                confirmed: false,
+            };
+
+            jrny::grants_diff(&cfg, &env, &opts)
+        }
+        GrantsCommand::Apply(cmd) => {
+            let cfg = cmd.cfg.into_cfg()?;
+            let env = cmd.env.into_env(&cfg)?;
+            let opts = GrantsOptions {
+                roles: cmd.roles,
+                confirmed: cmd.yes_i_mean_production,
+            };
+
+            jrny::grants_apply(&cfg, &env, &opts)
+        }
+    }
+}
+
+fn history(cmd: History) -> JrnyResult<()> {
+    let cfg = cmd.cfg.into_cfg()?;
+    let env = cmd.env.into_env(&cfg)?;
+
+    jrny::history(&cfg, &env)
+}
+
+fn search(cmd: Search) -> JrnyResult<()> {
+    let cfg = cmd.cfg.into_cfg()?;
+    let env = cmd.env.into_env(&cfg)?;
+
+    jrny::search(&cfg, &env, &cmd.term)
+}
+
+fn rename(cmd: Rename) -> JrnyResult<()> {
+    let cfg = cmd.cfg.into_cfg()?;
+    let env = cmd.env.into_env(&cfg)?;
+
+    // Resequencing is currently the only kind of renaming
+    debug_assert!(cmd.resequence);
+
+    jrny::resequence(&cfg, &env)
+}
+
+fn split(cmd: Split) -> JrnyResult<()> {
+    let cfg = cmd.cfg.into_cfg()?;
+    let env = cmd.env.into_env(&cfg)?;
+    let opts = SplitOptions {
+        revision: cmd.revision,
+        at: cmd.at,
+    };
+
+    jrny::split(&cfg, &env, &opts)
+}
+
+fn tombstone(cmd: Tombstone) -> JrnyResult<()> {
+    let cfg = cmd.cfg.into_cfg()?;
+    let opts = TombstoneOptions {
+        revision: cmd.revision,
+        superseded_by: cmd.superseded_by,
+    };
+
+    jrny::tombstone(&cfg, &opts)
+}
+
+fn seed(cmd: Seed) -> JrnyResult<()> {
+    let cfg = cmd.cfg.into_cfg()?;
+    let env = cmd.env.into_env(&cfg)?;
+    let opts = SeedOptions {
+        force: cmd.force,
+        confirmed: cmd.yes_i_mean_production,
+    };
+
+    jrny::seed(&cfg, &env, &opts)
+}
+
+fn fixtures(cmd: Fixtures) -> JrnyResult<()> {
+    let cfg = cmd.cfg.into_cfg()?;
+    let env = cmd.env.into_env(&cfg)?;
+
+    jrny::fixtures(&cfg, &env)
+}
+
+fn import(cmd: Import) -> JrnyResult<()> {
+    let cfg = cmd.cfg.into_cfg()?;
+    let env = cmd.env.into_env(&cfg)?;
+
+    let opts = ImportOptions {
+        from: cmd.from,
+        source: cmd.source,
+        history_table: cmd.history_table,
+        bundle: cmd.bundle,
+        with_history: cmd.with_history,
+    };
+
+    jrny::import(&cfg, &env, &opts)
+}
+
+fn run(cmd: Run) -> JrnyResult<()> {
+    let cfg = cmd.cfg.into_cfg()?;
+    let env = cmd.env.into_env(&cfg)?;
+
+    let opts = RunOptions {
+        wait_for_db: cmd.wait_for_db,
+        confirmed: cmd.yes_i_mean_production,
+        if_already_running: cmd.if_already_running,
+        override_freeze: cmd.override_freeze,
+        command: cmd.command,
+    };
+
+    jrny::run(&cfg, &env, &opts)
+}
+
+fn parse_duration(s: &str) -> Result<Duration, String> {
+    jrny::parse_duration(s).ok_or_else(|| format!("invalid duration `{}`, expected eg. `30s` or `2m`", s))
+}
+
+fn parse_start_time(s: &str) -> Result<DateTime<Utc>, String> {
+    jrny::start_time(s, &Local::now())
+}
+
+fn export(cmd: Export) -> JrnyResult<()> {
+    let cfg = cmd.cfg.into_cfg()?;
+    let env = cmd.env.into_env(&cfg)?;
+
+    let opts = ExportOptions { output: cmd.output };
+
+    jrny::export(&cfg, &env, &opts)
+}
+
+fn test(cmd: Test) -> JrnyResult<()> {
+    let cfg = cmd.cfg.into_cfg()?;
+    let env = cmd.env.into_env(&cfg)?;
+    let opts = TestOptions {
+        template: cmd.template,
+        keep: cmd.keep,
+        round_trip: cmd.round_trip,
+    };
+
+    jrny::test(&cfg, &env, &opts)
+}
+
+fn clone_state(cmd: CloneState) -> JrnyResult<()> {
+    let cfg = cmd.cfg.into_cfg()?;
+    let opts = CloneStateOptions {
+        from: cmd.from,
+        to: cmd.to,
+        confirmed: cmd.yes_i_mean_production,
+    };
+
+    jrny::clone_state(&cfg, &opts)
+}
+
+fn revert(cmd: Revert) -> JrnyResult<()> {
+    let cfg = cmd.cfg.into_cfg()?;
+    let env = cmd.env.into_env(&cfg)?;
+    let opts = RevertOptions {
+        group: cmd.group,
+        confirmed: cmd.yes_i_mean_production,
+    };
 
-    jrny::embark(&cfg, &env)
+    jrny::revert(&cfg, &env, &opts)
 }
@@ -0,0 +1,482 @@
+//! Optional metadata for a revision, declared as TOML in a comment block
+//! at the very start of the file:
+//!
+//! ```sql
+//! -- +++
+//! -- author = "Jane Doe"
+//! -- ticket = "https://tracker.example.com/DB-123"
+//! -- description = "Adds the users table"
+//! -- kind = "schema"
+//! -- estimated_duration = "5m"
+//! -- max_duration = "15m"
+//! -- timeout = ["30s", "2m", "10m"]
+//! -- directives = ["allow-destructive"]
+//! -- environments = ["staging", "production"]
+//! -- owners = ["@dba-team"]
+//! -- requires = ["add_users_table"]
+//! -- after = ["services/accounts:add_accounts_table"]
+//! -- idempotent = true
+//! -- draft = true
+//! -- apply_before = "2024-09-01"
+//! -- superseded_by = 7
+//! -- requires_pg = ">= 15"
+//! -- +++
+//! ```
+//!
+//! Directives can also be given by comments anywhere in the file, eg.
+//! `-- jrny:allow-destructive`, to keep them next to what they apply to.
+use std::time::Duration;
+
+use chrono::NaiveDate;
+use serde::{de, Deserialize, Deserializer};
+
+use crate::{
+    pg_version::{self, PgVersion},
+    progress::format_duration,
+    Error,
+    Result,
+};
+
+/// Marks the start and end of the frontmatter block
+const DELIMITER: &str = "+++";
+
+/// Precedes a directive given by a comment rather than in frontmatter
+const DIRECTIVE_PREFIX: &str = "jrny:";
+
+/// Acknowledges that a revision's destructive statements are intended, which
+/// is required to apply them to protected environments.
+pub const ALLOW_DESTRUCTIVE: &str = "allow-destructive";
+
+/// Runs a revision's statements as they are rather than in a transaction,
+/// for those that can't be run in one, given by a comment in the file.
+pub const NO_TRANSACTION: &str = "no-transaction";
+
+/// How many days before a pending revision's `apply_before` date review
+/// starts warning about it.
+pub const APPLY_BEFORE_WARNING_DAYS: i64 = 7;
+
+/// How a pending revision stands against its `apply_before` date.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Deadline {
+    /// The date is only the given number of days away
+    Approaching(i64),
+    /// The date has come, so it's too late to apply the revision
+    Passed,
+}
+
+/// What a revision changes, as data changes are held to stricter review.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RevisionKind {
+    #[default]
+    Schema,
+    /// Changes rows rather than the schema, eg. a backfill or cleanup
+    Data,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct RevisionMeta {
+    pub author: Option<String>,
+    /// Link to the ticket or issue the revision is for
+    pub ticket: Option<String>,
+    pub description: Option<String>,
+    #[serde(default)]
+    pub kind: RevisionKind,
+    /// Roughly how long the revision is expected to take to apply
+    #[serde(default, deserialize_with = "deserialize_duration")]
+    pub estimated_duration: Option<Duration>,
+    /// The longest the revision may take to apply, with what happens beyond
+    /// that depending on the environment's `on_max_duration`
+    #[serde(default, deserialize_with = "deserialize_duration")]
+    pub max_duration: Option<Duration>,
+    /// Statement timeouts to run each statement with in turn, trying it
+    /// again with the next whenever it's cancelled by the one before
+    #[serde(default, deserialize_with = "deserialize_timeouts")]
+    pub timeout: Vec<Duration>,
+    /// Instructions altering how `jrny` treats the revision
+    #[serde(default)]
+    pub directives: Vec<String>,
+    /// Environments the revision is limited to, if any
+    #[serde(default)]
+    pub environments: Vec<String>,
+    /// Who must approve the revision, instead of the owners of its directory
+    #[serde(default)]
+    pub owners: Vec<String>,
+    /// Names of revisions that must be applied before this one, eg. earlier
+    /// steps of a rename
+    #[serde(default)]
+    pub requires: Vec<String>,
+    /// Revisions of other projects in the workspace that must be applied
+    /// before this one, as `project:name`, eg. as they share a database
+    #[serde(default)]
+    pub after: Vec<String>,
+    /// Whether the revision is safe to run again after partly applying, so
+    /// that tables, indexes, and types it creates may already exist, if the
+    /// config allows it
+    #[serde(default)]
+    pub idempotent: bool,
+    /// Whether the revision is still being worked on, so that it can be
+    /// reviewed but not applied until this is removed
+    #[serde(default)]
+    pub draft: bool,
+    /// The date by which the revision must be applied, if it's pointless or
+    /// harmful to apply later, eg. a time-sensitive data correction
+    #[serde(default, deserialize_with = "deserialize_date")]
+    pub apply_before: Option<NaiveDate>,
+    /// The id of the revision replacing this one, which makes this one a
+    /// tombstone, kept only for the environments that already applied it
+    pub superseded_by: Option<i32>,
+    /// The oldest version of Postgres the revision can be applied to, eg.
+    /// for syntax that older versions lack
+    #[serde(default, deserialize_with = "pg_version::deserialize")]
+    pub requires_pg: Option<PgVersion>,
+}
+
+impl RevisionMeta {
+    /// Parses the frontmatter of the revision contents, if any, defaulting
+    /// to empty metadata for files without it, along with any directives
+    /// given by comments.
+    pub fn parse(filename: &str, contents: &str) -> Result<Self> {
+        let mut meta = Self::parse_frontmatter(filename, contents)?;
+
+        for directive in contents.lines().filter_map(inline_directive) {
+            if !meta.has_directive(directive) {
+                meta.directives.push(directive.to_string());
+            }
+        }
+
+        Ok(meta)
+    }
+
+    pub fn has_directive(&self, directive: &str) -> bool {
+        self.directives.iter().any(|d| d == directive)
+    }
+
+    /// How the revision, were it pending, would stand against its
+    /// `apply_before` date as of the given day, if it's near or past.
+    pub fn deadline(&self, today: NaiveDate) -> Option<Deadline> {
+        let days = (self.apply_before? - today).num_days();
+
+        match days {
+            _ if days <= 0 => Some(Deadline::Passed),
+            _ if days <= APPLY_BEFORE_WARNING_DAYS => Some(Deadline::Approaching(days)),
+            _ => None,
+        }
+    }
+
+    fn parse_frontmatter(filename: &str, contents: &str) -> Result<Self> {
+        let invalid = |reason: String| Error::RevisionMetaInvalid(filename.to_string(), reason);
+        let mut lines = contents.trim_start().lines();
+
+        if lines.next().and_then(comment_text).map(str::trim) != Some(DELIMITER) {
+            return Ok(Self::default());
+        }
+
+        let mut toml = String::new();
+
+        for line in lines {
+            let text = match comment_text(line) {
+                Some(text) => text,
+                None => break,
+            };
+
+            if text.trim() == DELIMITER {
+                return toml::from_str(&toml).map_err(|e| invalid(e.to_string()));
+            }
+
+            toml.push_str(text);
+            toml.push('\n');
+        }
+
+        Err(invalid(format!("expected frontmatter to end with `-- {}`", DELIMITER)))
+    }
+
+    /// A single line describing the revision for listings, if there is
+    /// any metadata at all.
+    pub fn summary(&self) -> Option<String> {
+        let mut details = vec![];
+
+        if let Some(author) = &self.author {
+            details.push(format!("by {}", author));
+        }
+        if let Some(ticket) = &self.ticket {
+            details.push(ticket.clone());
+        }
+        if self.kind == RevisionKind::Data {
+            details.push("data change".to_string());
+        }
+        if let Some(estimate) = self.estimated_duration {
+            details.push(format!("est. {}", format_duration(estimate)));
+        }
+        if let Some(max) = self.max_duration {
+            details.push(format!("max {}", format_duration(max)));
+        }
+        if !self.timeout.is_empty() {
+            let timeouts: Vec<_> = self.timeout.iter().copied().map(format_duration).collect();
+            details.push(format!("timeout {}", timeouts.join(" then ")));
+        }
+        if !self.directives.is_empty() {
+            details.push(format!("directives: {}", self.directives.join(", ")));
+        }
+        if !self.environments.is_empty() {
+            details.push(format!("only in {}", self.environments.join(", ")));
+        }
+        if !self.owners.is_empty() {
+            details.push(format!("owned by {}", self.owners.join(", ")));
+        }
+        if let Some(date) = self.apply_before {
+            details.push(format!("apply before {}", date));
+        }
+
+        match (&self.description, details.is_empty()) {
+            (None, true) => None,
+            (None, false) => Some(details.join(", ")),
+            (Some(description), true) => Some(description.clone()),
+            (Some(description), false) => Some(format!("{} ({})", description, details.join(", "))),
+        }
+    }
+}
+
+/// The frontmatter block at the start of the revision contents, including
+/// its delimiters, if it has one.
+pub fn frontmatter(contents: &str) -> Option<&str> {
+    let start = contents.len() - contents.trim_start().len();
+    let mut lines = contents[start..].split_inclusive('\n');
+    let mut end = start + lines.next().filter(|line| comment_text(line).map(str::trim) == Some(DELIMITER))?.len();
+
+    for line in lines {
+        comment_text(line)?;
+        end += line.len();
+
+        if comment_text(line).map(str::trim) == Some(DELIMITER) {
+            return Some(&contents[start..end]);
+        }
+    }
+
+    None
+}
+
+/// The text of a `--` comment line without the marker and a single following
+/// space, or nothing if the line is not a comment.
+fn comment_text(line: &str) -> Option<&str> {
+    let text = line.trim_start().strip_prefix("--")?;
+
+    Some(text.strip_prefix(' ').unwrap_or(text))
+}
+
+/// The directive given by a `-- jrny:<directive>` comment line, if it is one.
+pub fn inline_directive(line: &str) -> Option<&str> {
+    comment_text(line)?.trim().strip_prefix(DIRECTIVE_PREFIX).map(str::trim)
+}
+
+/// The settings following the first `-- jrny:<name>` directive among the
+/// lines, eg. `size=10000` for `-- jrny:batch size=10000`, or an empty string
+/// if the directive has none.
+pub fn directive_settings<'a>(sql: &'a str, name: &str) -> Option<&'a str> {
+    sql.lines()
+        .filter_map(inline_directive)
+        .find_map(|directive| match directive.split_once(char::is_whitespace) {
+            Some((directive, settings)) if directive == name => Some(settings),
+            None if directive == name => Some(""),
+            _ => None,
+        })
+}
+
+pub(crate) fn deserialize_duration<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Option<Duration>, D::Error> {
+    let s = String::deserialize(deserializer)?;
+
+    parse_duration(&s)
+        .map(Some)
+        .ok_or_else(|| de::Error::custom(format!("invalid duration `{}`, expected eg. `90s`, `5m`, or `1h30m`", s)))
+}
+
+fn deserialize_date<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Option<NaiveDate>, D::Error> {
+    let s = String::deserialize(deserializer)?;
+
+    NaiveDate::parse_from_str(s.trim(), "%Y-%m-%d")
+        .map(Some)
+        .map_err(|_| de::Error::custom(format!("invalid date `{}`, expected eg. `2024-09-01`", s)))
+}
+
+/// Either a single timeout or a list of them, each longer than the last.
+fn deserialize_timeouts<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Vec<Duration>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Timeouts {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    let given = match Timeouts::deserialize(deserializer)? {
+        Timeouts::One(timeout) => vec![timeout],
+        Timeouts::Many(timeouts) => timeouts,
+    };
+    let timeouts = given
+        .iter()
+        .map(|s| {
+            parse_duration(s)
+                .filter(|timeout| !timeout.is_zero())
+                .ok_or_else(|| de::Error::custom(format!("invalid timeout `{}`, expected eg. `30s` or `2m`", s)))
+        })
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+
+    if timeouts.windows(2).any(|pair| pair[1] <= pair[0]) {
+        return Err(de::Error::custom("expected each timeout to be longer than the one before"));
+    }
+
+    Ok(timeouts)
+}
+
+/// Parses durations as people tend to write them, eg. `500ms`, `90s`, `5m`, `1h 30m`, or `3d`.
+pub fn parse_duration(s: &str) -> Option<Duration> {
+    let mut rest = s.trim();
+    let mut total = Duration::ZERO;
+
+    if rest.is_empty() {
+        return None;
+    }
+
+    while !rest.is_empty() {
+        let digits = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+        let n: u64 = rest[..digits].parse().ok()?;
+        rest = &rest[digits..];
+
+        let unit_len = rest.find(|c: char| c.is_ascii_digit()).unwrap_or(rest.len());
+        let unit = rest[..unit_len].trim();
+        rest = &rest[unit_len..];
+
+        total += match unit {
+            "ms" => Duration::from_millis(n),
+            "s" => Duration::from_secs(n),
+            "m" => Duration::from_secs(n * 60),
+            "h" => Duration::from_secs(n * 3600),
+            "d" => Duration::from_secs(n * 86400),
+            _ => return None,
+        };
+    }
+
+    Some(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_reads_frontmatter() {
+        let contents = "
+-- +++
+-- author = \"Jane Doe\"
+-- description = \"Adds the users table\"
+-- kind = \"data\"
+-- estimated_duration = \"1h 30m\"
+-- max_duration = \"2h\"
+-- timeout = [\"30s\", \"2m\"]
+-- directives = [\"allow-destructive\"]
+-- owners = [\"@dba-team\"]
+-- requires = [\"add_accounts\"]
+-- after = [\"services/accounts:add_accounts\"]
+-- idempotent = true
+-- draft = true
+-- apply_before = \"2024-09-01\"
+-- superseded_by = 7
+-- requires_pg = 15
+-- +++
+
+create table users ();
+";
+
+        assert_eq!(
+            RevisionMeta::parse("001.1577836800.users.sql", contents).unwrap(),
+            RevisionMeta {
+                author: Some("Jane Doe".to_string()),
+                ticket: None,
+                description: Some("Adds the users table".to_string()),
+                kind: RevisionKind::Data,
+                estimated_duration: Some(Duration::from_secs(5400)),
+                max_duration: Some(Duration::from_secs(7200)),
+                timeout: vec![Duration::from_secs(30), Duration::from_secs(120)],
+                directives: vec!["allow-destructive".to_string()],
+                environments: vec![],
+                owners: vec!["@dba-team".to_string()],
+                requires: vec!["add_accounts".to_string()],
+                after: vec!["services/accounts:add_accounts".to_string()],
+                idempotent: true,
+                draft: true,
+                apply_before: Some(NaiveDate::from_ymd(2024, 9, 1)),
+                superseded_by: Some(7),
+                requires_pg: Some(PgVersion(150_000)),
+            }
+        );
+    }
+
+    #[test]
+    fn parse_defaults_without_frontmatter() {
+        assert_eq!(
+            RevisionMeta::parse("001.1577836800.users.sql", "-- Revision: users\ncreate table users ();").unwrap(),
+            RevisionMeta::default(),
+        );
+    }
+
+    #[test]
+    fn parse_fails_unclosed_or_unknown_fields() {
+        assert!(RevisionMeta::parse("a.sql", "-- +++\n-- author = \"x\"\ncreate table users ();").is_err());
+        assert!(RevisionMeta::parse("a.sql", "-- +++\n-- auther = \"x\"\n-- +++").is_err());
+        assert!(RevisionMeta::parse("a.sql", "-- +++\n-- timeout = [\"2m\", \"30s\"]\n-- +++").is_err());
+        assert_eq!(
+            RevisionMeta::parse("a.sql", "-- +++\n-- timeout = \"30s\"\n-- +++").unwrap().timeout,
+            vec![Duration::from_secs(30)],
+        );
+    }
+
+    #[test]
+    fn frontmatter_is_the_whole_block() {
+        let contents = "\n-- +++\n-- author = \"x\"\n-- +++\ncreate table users ();";
+
+        assert_eq!(frontmatter(contents), Some("-- +++\n-- author = \"x\"\n-- +++\n"));
+        assert_eq!(frontmatter("-- +++\n-- author = \"x\"\ncreate table users ();"), None);
+        assert_eq!(frontmatter("create table users ();"), None);
+    }
+
+    #[test]
+    fn directive_settings_follow_the_name() {
+        let sql = "-- jrny:batched\n-- jrny:batch size=10 key=id\nupdate users set a = 1;";
+
+        assert_eq!(directive_settings(sql, "batch"), Some("size=10 key=id"));
+        assert_eq!(directive_settings(sql, "batched"), Some(""));
+        assert_eq!(directive_settings(sql, "retry"), None);
+    }
+
+    #[test]
+    fn parse_collects_inline_directives() {
+        let contents = "-- +++\n-- directives = [\"a\"]\n-- +++\n\n-- jrny:allow-destructive\ndrop table users;\n--jrny:a\n";
+        let meta = RevisionMeta::parse("a.sql", contents).unwrap();
+
+        assert_eq!(meta.directives, vec!["a".to_string(), ALLOW_DESTRUCTIVE.to_string()]);
+        assert!(RevisionMeta::parse("a.sql", "-- jrny:allow-destructive").unwrap().has_directive(ALLOW_DESTRUCTIVE));
+    }
+
+    #[test]
+    fn parse_duration_accepts_units() {
+        assert_eq!(parse_duration("500ms"), Some(Duration::from_millis(500)));
+        assert_eq!(parse_duration("90s"), Some(Duration::from_secs(90)));
+        assert_eq!(parse_duration("1h30m"), Some(Duration::from_secs(5400)));
+        assert_eq!(parse_duration("5 minutes"), None);
+        assert_eq!(parse_duration(""), None);
+    }
+
+    #[test]
+    fn deadlines_warn_then_pass() {
+        let meta = RevisionMeta { apply_before: Some(NaiveDate::from_ymd(2024, 9, 1)), ..Default::default() };
+
+        assert_eq!(meta.deadline(NaiveDate::from_ymd(2024, 8, 1)), None);
+        assert_eq!(meta.deadline(NaiveDate::from_ymd(2024, 8, 25)), Some(Deadline::Approaching(7)));
+        assert_eq!(meta.deadline(NaiveDate::from_ymd(2024, 8, 31)), Some(Deadline::Approaching(1)));
+        assert_eq!(meta.deadline(NaiveDate::from_ymd(2024, 9, 1)), Some(Deadline::Passed));
+        assert_eq!(RevisionMeta::default().deadline(NaiveDate::from_ymd(2024, 9, 1)), None);
+
+        let invalid = RevisionMeta::parse("a.sql", "-- +++\n-- apply_before = \"next week\"\n-- +++\n");
+        assert!(invalid.unwrap_err().to_string().contains("expected eg. `2024-09-01`"));
+    }
+}
@@ -0,0 +1,127 @@
+//! Prometheus metrics for embark runs, either pushed to a pushgateway or
+//! written to a file for the node exporter's textfile collector.
+use std::{collections::BTreeMap, fs, path::Path};
+
+use log::info;
+
+use crate::{
+    environment::MetricsSettings,
+    http,
+    report::{RevisionStatus, RunReport},
+    Result,
+};
+
+/// Renders the run as Prometheus text exposition format, with the given
+/// labels added to every sample.
+pub fn render(report: &RunReport, labels: &BTreeMap<String, String>) -> String {
+    let mut out = String::new();
+
+    let mut gauge = |name: &str, help: &str, samples: Vec<(Option<(&str, &str)>, f64)>| {
+        out.push_str(&format!("# HELP {} {}\n# TYPE {} gauge\n", name, help, name));
+
+        for (extra, value) in samples {
+            let mut all: Vec<(&str, &str)> = labels
+                .iter()
+                .map(|(k, v)| (k.as_str(), v.as_str()))
+                .collect();
+            all.extend(extra);
+
+            let labels = all
+                .iter()
+                .map(|(k, v)| format!("{}=\"{}\"", k, escape(v)))
+                .collect::<Vec<_>>()
+                .join(",");
+
+            if labels.is_empty() {
+                out.push_str(&format!("{} {}\n", name, value));
+            } else {
+                out.push_str(&format!("{}{{{}}} {}\n", name, labels, value));
+            }
+        }
+    };
+
+    let count = |status| report.count(status) as f64;
+
+    gauge(
+        "jrny_revisions_applied",
+        "Revisions applied by the last embark run",
+        vec![(None, count(RevisionStatus::Applied))],
+    );
+    gauge(
+        "jrny_revisions_failed",
+        "Revisions that failed during the last embark run",
+        vec![(None, count(RevisionStatus::Failed))],
+    );
+    gauge(
+        "jrny_revisions_skipped",
+        "Revisions skipped after a failure during the last embark run",
+        vec![(None, count(RevisionStatus::Skipped))],
+    );
+    gauge(
+        "jrny_embark_duration_seconds",
+        "Duration of the last embark run",
+        vec![(None, report.elapsed.as_secs_f64())],
+    );
+    gauge(
+        "jrny_embark_last_run_timestamp_seconds",
+        "When the last embark run started",
+        vec![(None, report.started_at.timestamp() as f64)],
+    );
+    gauge(
+        "jrny_revision_duration_seconds",
+        "Duration of each revision attempted by the last embark run",
+        report
+            .revisions
+            .iter()
+            .filter(|r| r.status != RevisionStatus::Skipped)
+            .map(|r| (Some(("revision", r.filename.as_str())), r.elapsed.as_secs_f64()))
+            .collect(),
+    );
+
+    out
+}
+
+/// Publishes metrics for the run to each destination in the settings.
+pub fn publish(settings: &MetricsSettings, report: &RunReport) -> Result<()> {
+    if let Some(path) = &settings.textfile {
+        write_textfile(path, &render(report, &settings.labels))?;
+        info!("Wrote metrics to {}", path.display());
+    }
+
+    if let Some(url) = &settings.pushgateway {
+        // The labels form the grouping key, so they must not be repeated
+        // on the samples themselves.
+        let mut push_url = format!(
+            "{}/metrics/job/{}",
+            url.trim_end_matches('/'),
+            http::encode_segment(&settings.job),
+        );
+
+        for (k, v) in &settings.labels {
+            push_url.push_str(&format!("/{}/{}", k, http::encode_segment(v)));
+        }
+
+        http::send("PUT", &push_url, "text/plain; version=0.0.4", &render(report, &BTreeMap::new()))?;
+        info!("Pushed metrics to {}", url);
+    }
+
+    Ok(())
+}
+
+/// Writes via a temporary file and rename so the collector never reads
+/// a partially-written file.
+fn write_textfile(path: &Path, contents: &str) -> Result<()> {
+    let tmp = path.with_extension("prom.tmp");
+
+    fs::write(&tmp, contents)?;
+    fs::rename(&tmp, path)?;
+
+    Ok(())
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
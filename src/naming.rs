@@ -0,0 +1,142 @@
+//! Conventions that revision names can be required to follow, checked
+//! when planning new revisions and when reviewing pending ones.
+//!
+//! These are built-in rules rather than arbitrary patterns, which covers
+//! the usual conventions without needing a regex dependency.
+use serde::Deserialize;
+
+/// Casing styles that revision names can be required to use.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+pub enum NameStyle {
+    /// Lowercase letters, digits, and underscores, eg. `add_users_table`
+    #[serde(rename = "lower_snake_case")]
+    LowerSnakeCase,
+    /// Lowercase letters, digits, and hyphens, eg. `add-users-table`
+    #[serde(rename = "kebab-case")]
+    KebabCase,
+}
+
+impl NameStyle {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::LowerSnakeCase => "lower_snake_case",
+            Self::KebabCase => "kebab-case",
+        }
+    }
+
+    /// The character separating the words of a name.
+    pub fn separator(&self) -> char {
+        match self {
+            Self::LowerSnakeCase => '_',
+            Self::KebabCase => '-',
+        }
+    }
+
+    fn matches(&self, name: &str) -> bool {
+        let separator = self.separator();
+
+        !name.is_empty()
+            && !name.starts_with(separator)
+            && !name.ends_with(separator)
+            && name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == separator)
+    }
+}
+
+/// Rules for the name portion of revision filenames, ie. excluding the id,
+/// timestamp, and extension. Every rule is optional.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct NamingRules {
+    pub style: Option<NameStyle>,
+    /// Names must start with one of these, if any are given
+    #[serde(default)]
+    pub prefixes: Vec<String>,
+    pub max_length: Option<usize>,
+}
+
+impl NamingRules {
+    /// Describes each rule the name breaks, if any.
+    pub fn violations(&self, name: &str) -> Vec<String> {
+        let mut violations = vec![];
+
+        if let Some(style) = self.style {
+            if !style.matches(name) {
+                violations.push(format!("must be {}", style.as_str()));
+            }
+        }
+
+        if !self.prefixes.is_empty() && !self.prefixes.iter().any(|prefix| name.starts_with(prefix.as_str())) {
+            let prefixes: Vec<_> = self.prefixes.iter().map(|p| format!("`{}`", p)).collect();
+            violations.push(format!("must start with one of {}", prefixes.join(", ")));
+        }
+
+        if let Some(max) = self.max_length {
+            let length = name.chars().count();
+
+            if length > max {
+                violations.push(format!("must be at most {} characters (is {})", max, length));
+            }
+        }
+
+        violations
+    }
+
+    /// The words of a generated name joined in the required style, or in
+    /// lower_snake_case if there's none.
+    pub fn join(&self, words: &[String]) -> String {
+        let name = words.join("_").to_lowercase();
+
+        match self.style {
+            Some(NameStyle::KebabCase) => name.replace('_', "-"),
+            Some(NameStyle::LowerSnakeCase) | None => name,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules() -> NamingRules {
+        NamingRules {
+            style: Some(NameStyle::LowerSnakeCase),
+            prefixes: vec!["add_".to_string(), "drop_".to_string()],
+            max_length: Some(20),
+        }
+    }
+
+    #[test]
+    fn violations_empty_when_following_rules() {
+        assert!(rules().violations("add_users").is_empty());
+        assert!(NamingRules::default().violations("Anything Goes!").is_empty());
+    }
+
+    #[test]
+    fn violations_lists_each_rule_broken() {
+        assert_eq!(
+            rules().violations("Create-Users-And-Other-Things"),
+            vec![
+                "must be lower_snake_case".to_string(),
+                "must start with one of `add_`, `drop_`".to_string(),
+                "must be at most 20 characters (is 29)".to_string(),
+            ],
+        );
+    }
+
+    #[test]
+    fn join_uses_required_style() {
+        let words = vec!["add".to_string(), "Orders".to_string(), "shipped_at".to_string()];
+        let kebab = NamingRules { style: Some(NameStyle::KebabCase), ..Default::default() };
+
+        assert_eq!(NamingRules::default().join(&words), "add_orders_shipped_at");
+        assert_eq!(kebab.join(&words), "add-orders-shipped-at");
+    }
+
+    #[test]
+    fn style_rejects_leading_or_trailing_separators() {
+        assert!(!NameStyle::KebabCase.matches("-users"));
+        assert!(!NameStyle::KebabCase.matches("users-"));
+        assert!(!NameStyle::KebabCase.matches("add_users"));
+        assert!(NameStyle::KebabCase.matches("add-users-2"));
+    }
+}
@@ -0,0 +1,85 @@
+//! Notifications sent with the run summary once an embark completes or fails.
+use log::info;
+
+use crate::{
+    environment::NotificationSettings,
+    http,
+    json::Json,
+    progress::format_duration,
+    report::{RevisionStatus, RunReport},
+    Result,
+};
+
+/// Sends the run summary to each configured webhook, attempting all of
+/// them even if one fails.
+pub fn send(settings: &NotificationSettings, env_name: Option<&str>, report: &RunReport) -> Result<()> {
+    let mut result = Ok(());
+
+    if let Some(url) = &settings.webhook_url {
+        let payload = Json::object([
+            ("success", Json::from(report.error.is_none())),
+            ("environment", Json::from(env_name)),
+            ("report", report.to_json()),
+        ]);
+
+        match http::send("POST", url, "application/json", &payload.to_string()) {
+            Ok(_) => info!("Sent notification to webhook"),
+            Err(e) => result = Err(e),
+        }
+    }
+
+    if let Some(url) = &settings.slack_webhook_url {
+        let payload = Json::object([("text", Json::from(slack_text(env_name, report)))]);
+
+        match http::send("POST", url, "application/json", &payload.to_string()) {
+            Ok(_) => info!("Sent notification to Slack"),
+            Err(e) => result = Err(e),
+        }
+    }
+
+    result
+}
+
+/// Formats the summary using Slack's `mrkdwn` syntax.
+fn slack_text(env_name: Option<&str>, report: &RunReport) -> String {
+    let target = env_name.map(|name| format!(" on *{}*", name)).unwrap_or_default();
+    let applied = report.count(RevisionStatus::Applied);
+
+    let mut text = match &report.error {
+        None => format!(
+            ":white_check_mark: jrny embark{} applied {} revision(s) in {}",
+            target,
+            applied,
+            format_duration(report.elapsed),
+        ),
+        Some(_) => format!(
+            ":x: jrny embark{} failed after applying {} revision(s) in {} ({} skipped)",
+            target,
+            applied,
+            format_duration(report.elapsed),
+            report.count(RevisionStatus::Skipped),
+        ),
+    };
+
+    for revision in &report.revisions {
+        let icon = match revision.status {
+            RevisionStatus::Applied => ":large_green_circle:",
+            RevisionStatus::Failed => ":red_circle:",
+            RevisionStatus::Skipped => ":white_circle:",
+            RevisionStatus::RolledBack => ":leftwards_arrow_with_hook:",
+        };
+        text.push_str(&format!(
+            "\n{} `{}` {} ({})",
+            icon,
+            revision.filename,
+            revision.status.as_str(),
+            format_duration(revision.elapsed),
+        ));
+    }
+
+    if let Some(error) = &report.error {
+        text.push_str(&format!("\n```{}```", error));
+    }
+
+    text
+}
@@ -0,0 +1,170 @@
+//! Checks after embarking that whatever the run created is owned by the role
+//! it should be, rather than eg. the personal superuser account someone ran
+//! jrny as, and has the default privileges that role grants on new objects.
+//! The role is given in the environment file:
+//!
+//! ```toml
+//! [ownership]
+//! owner = "app_owner"
+//! fail = true
+//! ```
+//!
+//! Tables, views, sequences, functions, types, and schemas outside of the
+//! system schemas are checked, other than those belonging to extensions.
+//! Objects are found by what exists before the run and after it, so any
+//! created concurrently by others are checked too.
+use std::collections::HashSet;
+
+use serde::Deserialize;
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OwnershipSettings {
+    /// The role that should own every object created
+    pub owner: String,
+    /// Whether objects owned by another role, or lacking the owner's default
+    /// privileges, fail the run rather than only being warned about
+    #[serde(default)]
+    pub fail: bool,
+}
+
+/// Every object that's checked, with the default privileges of the role
+/// given, as for the object's type and schema, that it doesn't have.
+pub const OBJECTS: &str = "
+WITH objects AS (
+    SELECT
+        CASE c.relkind WHEN 'S' THEN 'sequence' ELSE 'table' END AS kind,
+        c.oid,
+        c.relnamespace AS namespace,
+        format('%I.%I', n.nspname, c.relname) AS name,
+        c.relowner AS owner,
+        c.relacl AS acl,
+        CASE c.relkind WHEN 'S' THEN 's' ELSE 'r' END AS acl_type,
+        CASE c.relkind WHEN 'S' THEN 'S' ELSE 'r' END AS defaults_type
+    FROM pg_class c JOIN pg_namespace n ON n.oid = c.relnamespace
+    WHERE c.relkind IN ('r', 'p', 'v', 'm', 'S', 'f')
+    UNION ALL
+    SELECT
+        'function',
+        p.oid,
+        p.pronamespace,
+        format('%I.%I(%s)', n.nspname, p.proname, pg_get_function_identity_arguments(p.oid)),
+        p.proowner,
+        p.proacl,
+        'f',
+        'f'
+    FROM pg_proc p JOIN pg_namespace n ON n.oid = p.pronamespace
+    UNION ALL
+    SELECT 'type', t.oid, t.typnamespace, format('%I.%I', n.nspname, t.typname), t.typowner, t.typacl, 'T', 'T'
+    FROM pg_type t JOIN pg_namespace n ON n.oid = t.typnamespace
+    WHERE t.typtype IN ('e', 'd', 'r', 'm')
+        OR (t.typtype = 'c' AND EXISTS (SELECT 1 FROM pg_class c WHERE c.oid = t.typrelid AND c.relkind = 'c'))
+    UNION ALL
+    SELECT 'schema', n.oid, n.oid, format('%I', n.nspname), n.nspowner, n.nspacl, 'n', 'n'
+    FROM pg_namespace n
+)
+SELECT
+    o.kind || ':' || o.oid AS key,
+    o.kind,
+    o.name,
+    pg_get_userbyid(o.owner) AS owner,
+    ARRAY(
+        SELECT DISTINCT format('%s to %s', g.privilege_type, CASE g.grantee WHEN 0 THEN 'PUBLIC' ELSE pg_get_userbyid(g.grantee) END)
+        FROM pg_default_acl d, aclexplode(d.defaclacl) AS g
+        WHERE d.defaclrole = (SELECT oid FROM pg_roles WHERE rolname = $1)
+            AND (d.defaclnamespace = 0 OR (d.defaclnamespace = o.namespace AND o.kind <> 'schema'))
+            AND d.defaclobjtype::TEXT = o.defaults_type
+            AND g.grantee <> d.defaclrole
+            AND NOT EXISTS (
+                SELECT 1
+                FROM aclexplode(coalesce(o.acl, acldefault(o.acl_type::\"char\", o.owner))) AS a
+                WHERE a.grantee = g.grantee AND a.privilege_type = g.privilege_type
+            )
+        ORDER BY 1
+    ) AS missing_privileges
+FROM objects o JOIN pg_namespace n ON n.oid = o.namespace
+WHERE n.nspname NOT IN ('pg_catalog', 'information_schema')
+    AND n.nspname NOT LIKE 'pg\\_toast%'
+    AND n.nspname NOT LIKE 'pg\\_temp\\_%'
+    AND NOT EXISTS (SELECT 1 FROM pg_depend e WHERE e.objid = o.oid AND e.deptype = 'e')
+ORDER BY o.name
+";
+
+/// An object in the database, identified by its catalog and `oid`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct DatabaseObject {
+    pub key: String,
+    /// eg. `table`, `function`, or `schema`
+    pub kind: String,
+    /// The qualified name of the object
+    pub name: String,
+    /// The role that owns it
+    pub owner: String,
+    /// The default privileges of the expected owner it doesn't have, eg.
+    /// `SELECT to app_reader`
+    pub missing_privileges: Vec<String>,
+}
+
+impl DatabaseObject {
+    /// Describes what's wrong with the object, if anything.
+    pub fn problems(&self, owner: &str) -> Vec<String> {
+        let mut problems = vec![];
+
+        if self.owner != owner {
+            problems.push(format!("owned by `{}` rather than `{}`", self.owner, owner));
+        }
+        if !self.missing_privileges.is_empty() {
+            problems.push(format!("missing default privileges {}", self.missing_privileges.join(", ")));
+        }
+
+        problems
+    }
+}
+
+/// The objects that weren't there before, each with its problems, for those
+/// that have any.
+pub fn misowned<'a>(
+    before: &HashSet<String>,
+    after: &'a [DatabaseObject],
+    owner: &str,
+) -> Vec<(&'a DatabaseObject, Vec<String>)> {
+    after
+        .iter()
+        .filter(|object| !before.contains(&object.key))
+        .map(|object| (object, object.problems(owner)))
+        .filter(|(_, problems)| !problems.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn object(key: &str, owner: &str, missing: &[&str]) -> DatabaseObject {
+        DatabaseObject {
+            key: key.to_string(),
+            kind: "table".to_string(),
+            name: format!("public.{}", key),
+            owner: owner.to_string(),
+            missing_privileges: missing.iter().map(ToString::to_string).collect(),
+        }
+    }
+
+    #[test]
+    fn only_new_objects_with_problems_are_misowned() {
+        let before: HashSet<_> = ["old".to_string()].into_iter().collect();
+        let after = [
+            object("old", "jane", &[]),
+            object("fine", "app_owner", &[]),
+            object("mine", "jane", &[]),
+            object("bare", "app_owner", &["SELECT to app_reader"]),
+        ];
+
+        let misowned = misowned(&before, &after, "app_owner");
+
+        assert_eq!(misowned.len(), 2);
+        assert_eq!(misowned[0].0.key, "mine");
+        assert_eq!(misowned[0].1, vec!["owned by `jane` rather than `app_owner`"]);
+        assert_eq!(misowned[1].1, vec!["missing default privileges SELECT to app_reader"]);
+    }
+}
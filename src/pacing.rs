@@ -0,0 +1,97 @@
+//! Pauses between statements and between revisions, for fragile primaries
+//! where back-to-back DDL spikes replication lag, given in the environment
+//! file:
+//!
+//! ```toml
+//! [pacing]
+//! between_statements = "200ms"
+//! between_revisions = "5s"
+//! max_replication_lag = "10s"
+//! ```
+//!
+//! With a `max_replication_lag`, or `jrny embark --max-replication-lag`, the
+//! replay lag of every replica in `pg_stat_replication` is also checked
+//! before the first revision and at each pause, waiting for as long as any
+//! lags further behind than that. A `lag_query` can find the lag instead, eg.
+//! from a monitoring table, as a number of seconds in its first column.
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use crate::meta::deserialize_duration;
+
+/// How often replication lag is checked again while it's too high.
+pub const LAG_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How far behind the replica furthest behind is, in seconds, which is null
+/// once they've all caught up or without any replicas.
+pub const REPLICATION_LAG: &str = "
+SELECT extract(epoch FROM max(replay_lag))::FLOAT8 AS lag FROM pg_stat_replication
+";
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PacingSettings {
+    /// How long to pause before each statement after the first, including
+    /// each batch of a batched statement
+    #[serde(default, deserialize_with = "deserialize_duration")]
+    pub between_statements: Option<Duration>,
+    /// How long to pause before each revision after the first
+    #[serde(default, deserialize_with = "deserialize_duration")]
+    pub between_revisions: Option<Duration>,
+    /// How far replicas may lag behind before pausing until they catch up
+    #[serde(default, deserialize_with = "deserialize_duration")]
+    pub max_replication_lag: Option<Duration>,
+    /// Finds how far replicas lag behind, in seconds, rather than the
+    /// replay lag in `pg_stat_replication`
+    pub lag_query: Option<String>,
+}
+
+impl PacingSettings {
+    /// The query finding replication lag.
+    pub fn lag_query(&self) -> &str {
+        self.lag_query.as_deref().unwrap_or(REPLICATION_LAG)
+    }
+}
+
+/// The lag given as the text of a number of seconds, with none for null.
+pub fn parse_lag(lag: Option<&str>) -> Result<Duration, String> {
+    let seconds = match lag.map(str::trim) {
+        Some(lag) => lag.parse::<f64>().map_err(|_| lag.to_string())?,
+        None => return Ok(Duration::default()),
+    };
+
+    if !seconds.is_finite() {
+        return Err(seconds.to_string());
+    }
+
+    Ok(Duration::from_secs_f64(seconds.max(0.0)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pacing_settings_parse_durations() {
+        let settings: PacingSettings = toml::from_str("between_statements = \"200ms\"\nmax_replication_lag = \"10s\"").unwrap();
+
+        assert_eq!(settings.between_statements, Some(Duration::from_millis(200)));
+        assert_eq!(settings.between_revisions, None);
+        assert_eq!(settings.max_replication_lag, Some(Duration::from_secs(10)));
+
+        assert_eq!(settings.lag_query(), REPLICATION_LAG);
+        assert!(toml::from_str::<PacingSettings>("between_statements = 200").is_err());
+        assert!(toml::from_str::<PacingSettings>("between = \"1s\"").is_err());
+    }
+
+    #[test]
+    fn lag_is_parsed_from_seconds() {
+        assert_eq!(parse_lag(None), Ok(Duration::default()));
+        assert_eq!(parse_lag(Some("2.5")), Ok(Duration::from_millis(2500)));
+        assert_eq!(parse_lag(Some(" 12 ")), Ok(Duration::from_secs(12)));
+        assert_eq!(parse_lag(Some("-0.001")), Ok(Duration::default()));
+        assert!(parse_lag(Some("00:00:05")).is_err());
+        assert!(parse_lag(Some("NaN")).is_err());
+    }
+}
@@ -0,0 +1,89 @@
+//! Names of files relative to the revisions directory, as they're shown,
+//! recorded, and signed. These are separated by `/` on every platform, even
+//! where the filesystem uses `\`, so that eg. a repeatable revision applied
+//! from Windows isn't taken to be a new one when next applied from Linux.
+//!
+//! As the revisions directory may well be checked out onto case-insensitive
+//! filesystems, such as those of Windows and macOS, files whose names differ
+//! only by case are also found so they can be rejected.
+use std::{
+    collections::HashMap,
+    path::{Component, Path},
+};
+
+/// The path with its parts separated by `/`, or `None` if it's not UTF-8.
+pub fn to_slash(path: &Path) -> Option<String> {
+    let parts = path
+        .components()
+        .filter(|component| !matches!(component, Component::CurDir))
+        .map(|component| component.as_os_str().to_str())
+        .collect::<Option<Vec<_>>>()?;
+
+    Some(parts.join("/"))
+}
+
+/// The name of the path relative to the root, or within it if it's not
+/// under the root, separated by `/` and lossily converted to UTF-8.
+pub fn relative(root: &Path, path: &Path) -> String {
+    let relative = path.strip_prefix(root).unwrap_or(path);
+
+    to_slash(relative).unwrap_or_else(|| relative.to_string_lossy().replace('\\', "/"))
+}
+
+/// The name given or recorded, with any `\` separating its parts, eg. as
+/// recorded from Windows by earlier versions, replaced by `/`.
+pub fn normalize(name: &str) -> String {
+    name.replace('\\', "/")
+}
+
+/// Each name differing only by case from one before it, along with that one.
+pub fn case_conflicts<'a>(names: impl IntoIterator<Item = &'a str>) -> Vec<(&'a str, &'a str)> {
+    let mut seen: HashMap<String, &str> = HashMap::new();
+    let mut conflicts = vec![];
+
+    for name in names {
+        match seen.get(&name.to_lowercase()) {
+            Some(earlier) if *earlier != name => conflicts.push((name, *earlier)),
+            Some(_) => {}
+            None => {
+                seen.insert(name.to_lowercase(), name);
+            }
+        }
+    }
+
+    conflicts
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    #[test]
+    fn names_are_separated_by_slashes() {
+        let revisions = Path::new("project").join("revisions");
+        let grouped = revisions.join("2021-04").join("002.1617000000.users.sql");
+
+        assert_eq!(relative(&revisions, &grouped), "2021-04/002.1617000000.users.sql");
+        assert_eq!(relative(Path::new("elsewhere"), Path::new("./revisions/001.a.sql")), "revisions/001.a.sql");
+        assert_eq!(to_slash(&PathBuf::from("repeatable").join("views.sql")).as_deref(), Some("repeatable/views.sql"));
+        assert_eq!(normalize("repeatable\\views.sql"), "repeatable/views.sql");
+        assert_eq!(normalize("2021-04/002.a.sql"), "2021-04/002.a.sql");
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn windows_separators_are_replaced() {
+        assert_eq!(relative(Path::new("revisions"), Path::new(r"revisions\2021-04\002.a.sql")), "2021-04/002.a.sql");
+        assert_eq!(to_slash(Path::new(r"repeatable\views.sql")).as_deref(), Some("repeatable/views.sql"));
+    }
+
+    #[test]
+    fn names_differing_only_by_case_conflict() {
+        let names = ["001.a.sql", "2021-04/002.Users.sql", "2021-04/002.users.sql", "001.a.sql", "R__Views.sql"];
+
+        assert_eq!(case_conflicts(names), vec![("2021-04/002.users.sql", "2021-04/002.Users.sql")]);
+        assert!(case_conflicts(["001.a.sql", "002.b.sql"]).is_empty());
+    }
+}
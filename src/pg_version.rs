@@ -0,0 +1,83 @@
+//! Versions of Postgres, of which revisions can need a minimum, either every
+//! revision by `requires_pg` under `[revisions]` in the config, or a single
+//! revision by the same in its frontmatter:
+//!
+//! ```toml
+//! requires_pg = ">= 14"
+//! ```
+//!
+//! Versions are given by their major version, optionally followed by the
+//! minor, eg. `14` or `"15.2"`, with or without `>=` before them.
+use std::fmt;
+
+use serde::{de, Deserialize, Deserializer};
+
+/// A version of Postgres as `server_version_num` gives it, eg. `140005` for
+/// 14.5, which only covers Postgres 10 and later.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct PgVersion(pub i32);
+
+impl PgVersion {
+    pub fn parse(version: &str) -> Option<Self> {
+        let version = version.trim();
+        let version = version.strip_prefix(">=").unwrap_or(version).trim();
+        let mut parts = version.split('.');
+
+        let major: i32 = parts.next()?.parse().ok()?;
+        let minor: i32 = match parts.next() {
+            Some(minor) => minor.parse().ok()?,
+            None => 0,
+        };
+
+        if parts.next().is_some() || !(10..=999).contains(&major) || !(0..10_000).contains(&minor) {
+            return None;
+        }
+
+        Some(Self(major * 10_000 + minor))
+    }
+}
+
+impl fmt::Display for PgVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.0 / 10_000, self.0 % 10_000) {
+            (major, 0) => write!(f, "{}", major),
+            (major, minor) => write!(f, "{}.{}", major, minor),
+        }
+    }
+}
+
+/// Either the major version alone as a number, or any version as a string.
+pub(crate) fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Option<PgVersion>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Given {
+        Major(i64),
+        Version(String),
+    }
+
+    let given = match Given::deserialize(deserializer)? {
+        Given::Major(major) => major.to_string(),
+        Given::Version(version) => version,
+    };
+
+    PgVersion::parse(&given)
+        .map(Some)
+        .ok_or_else(|| de::Error::custom(format!("invalid Postgres version `{}`, expected eg. `14`, `\"15.2\"`, or `\">= 14\"`", given)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn versions_parse_as_server_version_num() {
+        assert_eq!(PgVersion::parse("14"), Some(PgVersion(140_000)));
+        assert_eq!(PgVersion::parse(">= 15.2"), Some(PgVersion(150_002)));
+        assert_eq!(PgVersion::parse(">=16"), Some(PgVersion(160_000)));
+        assert_eq!(PgVersion::parse("9.6"), None);
+        assert_eq!(PgVersion::parse("14.1.2"), None);
+        assert_eq!(PgVersion::parse("> 14"), None);
+        assert_eq!(PgVersion(140_005).to_string(), "14.5");
+        assert_eq!(PgVersion(160_000).to_string(), "16");
+    }
+}
@@ -0,0 +1,172 @@
+//! pgTAP test files run once revisions are applied, on environments that
+//! aren't protected, when the config gives a directory for them:
+//!
+//! ```toml
+//! [tests]
+//! pgtap = "pgtap"
+//! ```
+//!
+//! Each file is run as `pg_prove` would, reading the TAP its queries return:
+//!
+//! ```sql
+//! SELECT plan(2);
+//!
+//! SELECT has_table('users');
+//! SELECT col_not_null('users', 'email');
+//!
+//! SELECT * FROM finish();
+//! ```
+//!
+//! The pgTAP extension itself must already be installed in the database.
+use postgres::{Client, SimpleQueryMessage};
+
+use crate::{assertions::AssertionFile, Result};
+
+/// The outcome of running a single pgTAP file.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct TapOutcome {
+    pub filename: String,
+    /// How many tests the file planned to run, if it said
+    pub planned: Option<usize>,
+    pub passed: usize,
+    /// Each failing test, eg. `not ok 2 - users have emails`, followed by
+    /// any diagnostics pgTAP gave for it
+    pub failures: Vec<String>,
+    /// The error that ended the file early, if any
+    pub error: Option<String>,
+}
+
+impl TapOutcome {
+    pub fn ran(&self) -> usize {
+        self.passed + self.failures.len()
+    }
+
+    /// Whether every test passed, without error, and as many ran as planned.
+    pub fn is_ok(&self) -> bool {
+        self.failures.is_empty() && self.problem().is_none()
+    }
+
+    /// Why the file failed, short of its failing tests, if it did.
+    pub fn problem(&self) -> Option<String> {
+        if let Some(error) = &self.error {
+            return Some(error.clone());
+        }
+
+        match self.planned {
+            Some(planned) if planned != self.ran() => Some(format!("planned {} test(s) but ran {}", planned, self.ran())),
+            _ => None,
+        }
+    }
+}
+
+/// Runs the file in a transaction that's rolled back afterwards, so that
+/// what its tests create or change isn't kept. Transaction commands in the
+/// file are skipped, as it's already in one.
+pub fn run(client: &mut Client, file: &AssertionFile) -> Result<TapOutcome> {
+    let mut tx = client.transaction()?;
+    let mut output = String::new();
+    let mut error = None;
+
+    for statement in file.statements.iter().filter(|statement| !statement.is_transaction_command()) {
+        let messages = match tx.simple_query(&statement.sql) {
+            Ok(messages) => messages,
+            Err(e) => {
+                let message = e.as_db_error().map_or(e.to_string(), |e| e.message().to_string());
+                error = Some(format!("line {}: {}", statement.line, message));
+                break;
+            }
+        };
+
+        for message in &messages {
+            if let SimpleQueryMessage::Row(row) = message {
+                for line in (0..row.len()).filter_map(|i| row.get(i)) {
+                    output.push_str(line);
+                    output.push('\n');
+                }
+            }
+        }
+    }
+
+    tx.rollback()?;
+
+    Ok(TapOutcome {
+        error,
+        ..parse(&file.filename, &output)
+    })
+}
+
+/// Reads the TAP output of a file, counting each test that passed, or that
+/// failed but is marked `# TODO`, and keeping each other that failed along
+/// with the diagnostics after it.
+pub fn parse(filename: &str, output: &str) -> TapOutcome {
+    let mut outcome = TapOutcome {
+        filename: filename.to_string(),
+        ..Default::default()
+    };
+    // Whether the diagnostics that follow belong to a failing test
+    let mut failing = false;
+
+    for line in output.lines().map(str::trim_end) {
+        if let Some(plan) = line.strip_prefix("1..") {
+            outcome.planned = plan.split_whitespace().next().and_then(|planned| planned.parse().ok());
+            failing = false;
+        } else if is_test(line, "not ok") && !line.to_lowercase().contains("# todo") {
+            outcome.failures.push(line.to_string());
+            failing = true;
+        } else if is_test(line, "ok") || is_test(line, "not ok") {
+            outcome.passed += 1;
+            failing = false;
+        } else if line.starts_with("# Looks like") {
+            // A summary of what's counted already
+            failing = false;
+        } else if let Some(failure) = outcome.failures.last_mut().filter(|_| failing && line.starts_with('#')) {
+            failure.push('\n');
+            failure.push_str(line);
+        }
+    }
+
+    outcome
+}
+
+fn is_test(line: &str, status: &str) -> bool {
+    line.strip_prefix(status).is_some_and(|rest| rest.is_empty() || rest.starts_with(' '))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tap_output_is_counted() {
+        let output = "1..4\n\
+                      ok 1 - has users\n\
+                      not ok 2 - emails are required\n\
+                      # Failed test 2: \"emails are required\"\n\
+                      #   Column users.email should be NOT NULL\n\
+                      not ok 3 - soon # TODO not yet\n\
+                      ok 4 # SKIP no orders\n\
+                      # Looks like you failed 1 test of 4\n";
+        let outcome = parse("users.sql", output);
+
+        assert_eq!(outcome.planned, Some(4));
+        assert_eq!(outcome.passed, 3);
+        assert_eq!(
+            outcome.failures,
+            vec!["not ok 2 - emails are required\n\
+                  # Failed test 2: \"emails are required\"\n\
+                  #   Column users.email should be NOT NULL"],
+        );
+        assert!(!outcome.is_ok());
+        assert_eq!(outcome.problem(), None);
+    }
+
+    #[test]
+    fn files_must_run_as_many_tests_as_planned() {
+        let outcome = parse("users.sql", "1..3\nok 1\nok 2\n# Looks like you planned 3 tests but ran 2\n");
+
+        assert!(!outcome.is_ok());
+        assert_eq!(outcome.problem().unwrap(), "planned 3 test(s) but ran 2");
+        assert!(parse("users.sql", "1..2\nok 1\nok 2\n").is_ok());
+        assert!(parse("users.sql", "").is_ok());
+    }
+}
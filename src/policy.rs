@@ -0,0 +1,397 @@
+//! Organization-wide rules for the SQL in pending revisions, checked by
+//! review with a severity for each, which environments can override.
+//!
+//! As with naming rules, these are built in rather than arbitrary patterns:
+//!
+//! ```toml
+//! [revisions.policy]
+//! varchar_without_length = "error"
+//! table_without_primary_key = "warning"
+//!
+//! [revisions.policy.environments.development]
+//! table_without_primary_key = "off"
+//! ```
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+use crate::{
+    analysis::{created_table, TableOperation},
+    statements::{self, strip_comments, Statement},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rule {
+    /// `varchar` or `character varying` columns must be given a length
+    VarcharWithoutLength,
+    /// Tables must be given a primary key in the revision creating them
+    TableWithoutPrimaryKey,
+    /// Views must list their columns rather than selecting `*`
+    SelectStarInView,
+    /// Tables in multi-tenant schemas must be given a row-level security
+    /// policy in the revision creating them
+    TableWithoutRlsPolicy,
+}
+
+impl Rule {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::VarcharWithoutLength => "varchar_without_length",
+            Self::TableWithoutPrimaryKey => "table_without_primary_key",
+            Self::SelectStarInView => "select_star_in_view",
+            Self::TableWithoutRlsPolicy => "table_without_rls_policy",
+        }
+    }
+
+    fn description(&self) -> &'static str {
+        match self {
+            Self::VarcharWithoutLength => "`varchar` without a length",
+            Self::TableWithoutPrimaryKey => "table created without a primary key",
+            Self::SelectStarInView => "view selecting `*`",
+            Self::TableWithoutRlsPolicy => "table in a multi-tenant schema created without a row-level security policy",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Off,
+    /// Reported by review without failing it
+    Warning,
+    /// Fails review, and so embark
+    Error,
+}
+
+/// The severity of each rule, if given.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct Severities {
+    pub varchar_without_length: Option<Severity>,
+    pub table_without_primary_key: Option<Severity>,
+    pub select_star_in_view: Option<Severity>,
+    pub table_without_rls_policy: Option<Severity>,
+}
+
+impl Severities {
+    fn get(&self, rule: Rule) -> Option<Severity> {
+        match rule {
+            Rule::VarcharWithoutLength => self.varchar_without_length,
+            Rule::TableWithoutPrimaryKey => self.table_without_primary_key,
+            Rule::SelectStarInView => self.select_star_in_view,
+            Rule::TableWithoutRlsPolicy => self.table_without_rls_policy,
+        }
+    }
+}
+
+/// The severity of each rule, every one of which is off unless given, along
+/// with any that differ by environment name. Tables without a row-level
+/// security policy are warned about unless given, once there are tenant
+/// schemas to check them in.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct PolicyRules {
+    pub varchar_without_length: Option<Severity>,
+    pub table_without_primary_key: Option<Severity>,
+    pub select_star_in_view: Option<Severity>,
+    pub table_without_rls_policy: Option<Severity>,
+    /// The schemas holding every tenant's rows, in which tables need a
+    /// row-level security policy, where unqualified names are in `public`
+    #[serde(default)]
+    pub tenant_schemas: Vec<String>,
+    #[serde(default)]
+    pub environments: HashMap<String, Severities>,
+}
+
+/// A statement breaking a rule that isn't off.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Violation {
+    pub rule: Rule,
+    pub severity: Severity,
+    /// The line on which the statement begins
+    pub line: usize,
+}
+
+impl Violation {
+    pub fn message(&self) -> String {
+        format!("Line {}: {} ({})", self.line, self.rule.description(), self.rule.as_str())
+    }
+}
+
+impl PolicyRules {
+    /// The severity of the rule in the environment, if it has a name.
+    pub fn severity(&self, rule: Rule, env: Option<&str>) -> Severity {
+        env.and_then(|env| self.environments.get(env))
+            .and_then(|rules| rules.get(rule))
+            .or(match rule {
+                Rule::VarcharWithoutLength => self.varchar_without_length,
+                Rule::TableWithoutPrimaryKey => self.table_without_primary_key,
+                Rule::SelectStarInView => self.select_star_in_view,
+                Rule::TableWithoutRlsPolicy => self.table_without_rls_policy,
+            })
+            .unwrap_or(match rule {
+                Rule::TableWithoutRlsPolicy if !self.tenant_schemas.is_empty() => Severity::Warning,
+                _ => Severity::Off,
+            })
+    }
+
+    /// Checks the contents of a revision against each rule that isn't off in
+    /// the environment, in the order the violations appear.
+    pub fn violations(&self, contents: &str, env: Option<&str>) -> Vec<Violation> {
+        let statements = statements::split(contents);
+        let mut violations = vec![];
+
+        let mut check = |rule: Rule, lines: Vec<usize>| {
+            let severity = self.severity(rule, env);
+
+            if severity != Severity::Off {
+                violations.extend(lines.into_iter().map(|line| Violation { rule, severity, line }));
+            }
+        };
+
+        check(Rule::VarcharWithoutLength, lines_where(&statements, varchar_without_length));
+        check(Rule::TableWithoutPrimaryKey, tables_without_primary_key(&statements));
+        check(Rule::SelectStarInView, lines_where(&statements, selects_star_in_view));
+        check(Rule::TableWithoutRlsPolicy, tables_without_rls_policy(&statements, &self.tenant_schemas));
+
+        violations.sort_by_key(|violation| violation.line);
+        violations
+    }
+}
+
+fn lines_where(statements: &[Statement], pred: fn(&Statement) -> bool) -> Vec<usize> {
+    statements.iter().filter(|statement| pred(statement)).map(|statement| statement.line).collect()
+}
+
+/// Whether the statement defines a column, or changes one's type, as
+/// `varchar` without giving its maximum length.
+fn varchar_without_length(statement: &Statement) -> bool {
+    if !matches!(statement.leading_keyword().as_deref(), Some("create" | "alter")) {
+        return false;
+    }
+
+    let sql = strip_comments(&statement.sql).to_lowercase();
+    let is_ident = |c: char| c.is_alphanumeric() || c == '_';
+
+    ["varchar", "character varying"].iter().any(|ty| {
+        sql.match_indices(ty).any(|(i, _)| {
+            let rest = &sql[i + ty.len()..];
+            let preceded = sql[..i].chars().next_back().is_some_and(is_ident);
+            let followed = rest.chars().next().is_some_and(is_ident);
+
+            !preceded && !followed && !rest.trim_start().starts_with('(')
+        })
+    })
+}
+
+/// The lines of statements creating tables without a primary key, unless one
+/// is added by a later statement in the same revision.
+fn tables_without_primary_key(statements: &[Statement]) -> Vec<usize> {
+    let has_primary_key = |statement: &Statement| {
+        let words: Vec<_> = statement.words().collect();
+        words.windows(2).any(|pair| pair[0] == "primary" && pair[1] == "key")
+    };
+
+    statements
+        .iter()
+        .enumerate()
+        .filter_map(|(i, statement)| {
+            let table = created_table(statement)?;
+
+            let added_later = statements[i + 1..].iter().any(|later| {
+                matches!(TableOperation::of(later), Some((TableOperation::AlterTable, altered)) if same_table(&altered, &table))
+                    && has_primary_key(later)
+            });
+
+            (!has_primary_key(statement) && !added_later).then_some(statement.line)
+        })
+        .collect()
+}
+
+/// The lines of statements creating tables in any of the tenant schemas
+/// without a row-level security policy being created on them in the same
+/// revision, including within a `DO` block.
+fn tables_without_rls_policy(statements: &[Statement], tenant_schemas: &[String]) -> Vec<usize> {
+    let unquoted = |name: &str| if name.starts_with('"') { name.trim_matches('"').to_string() } else { name.to_lowercase() };
+
+    statements
+        .iter()
+        .filter_map(|statement| {
+            let table = created_table(statement)?;
+            let (schema, name) = match table.rsplit_once('.') {
+                Some((schema, name)) => (unquoted(schema), name),
+                None => ("public".to_string(), table.as_str()),
+            };
+
+            if !tenant_schemas.contains(&schema) {
+                return None;
+            }
+
+            // Policies are matched by the words they're written with, which
+            // split names at their quotes and dots
+            let name = unquoted(name).to_lowercase();
+            let has_policy = statements.iter().any(|other| {
+                let words: Vec<_> = other.words().collect();
+
+                words.windows(2).any(|pair| pair[0] == "create" && pair[1] == "policy") && words.contains(&name)
+            });
+
+            (!has_policy).then_some(statement.line)
+        })
+        .collect()
+}
+
+/// Whether the names are of the same table, where either may leave out the
+/// schema, and unquoted names differ only in case.
+fn same_table(a: &str, b: &str) -> bool {
+    let unqualified = |name: &str| {
+        let name = name.rsplit_once('.').map_or(name, |(_, name)| name);
+        if name.starts_with('"') { name.to_string() } else { name.to_lowercase() }
+    };
+
+    unqualified(a) == unqualified(b)
+}
+
+/// Whether the statement creates a view selecting `*` or `table.*`.
+fn selects_star_in_view(statement: &Statement) -> bool {
+    let words: Vec<_> = statement.words().take_while(|word| word != "as").collect();
+
+    if words.first().map(String::as_str) != Some("create") || !words.iter().any(|word| word == "view") {
+        return false;
+    }
+
+    let sql = strip_comments(&statement.sql).to_lowercase().replace(',', " , ");
+    let tokens: Vec<_> = sql.split_whitespace().collect();
+
+    tokens.iter().enumerate().any(|(i, token)| {
+        *token == "select"
+            && tokens[i + 1..]
+                .iter()
+                .find(|next| !matches!(**next, "distinct" | "all"))
+                .is_some_and(|next| *next == "*" || next.ends_with(".*"))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(varchar: Option<Severity>, primary_key: Option<Severity>, select_star: Option<Severity>) -> PolicyRules {
+        PolicyRules {
+            varchar_without_length: varchar,
+            table_without_primary_key: primary_key,
+            select_star_in_view: select_star,
+            table_without_rls_policy: None,
+            tenant_schemas: vec![],
+            environments: HashMap::new(),
+        }
+    }
+
+    fn lines(policy: &PolicyRules, contents: &str) -> Vec<(Rule, usize)> {
+        policy.violations(contents, None).into_iter().map(|v| (v.rule, v.line)).collect()
+    }
+
+    #[test]
+    fn varchar_needs_a_length() {
+        let policy = rules(Some(Severity::Error), None, None);
+
+        assert_eq!(
+            lines(
+                &policy,
+                "create table a (name varchar(50), email varchar);\n\
+                 alter table a alter column name type character varying;\n\
+                 create table b (name varchar (10), nickname varchar_name);\n\
+                 select 'x'::varchar;",
+            ),
+            vec![(Rule::VarcharWithoutLength, 1), (Rule::VarcharWithoutLength, 2)],
+        );
+    }
+
+    #[test]
+    fn tables_need_a_primary_key_in_the_same_revision() {
+        let policy = rules(None, Some(Severity::Warning), None);
+
+        assert_eq!(
+            lines(
+                &policy,
+                "create table a (id int primary key);\n\
+                 create table b (id int);\n\
+                 alter table public.b add primary key (id);\n\
+                 create table if not exists c (id int);\n\
+                 create table c_1 partition of c for values in (1);",
+            ),
+            vec![(Rule::TableWithoutPrimaryKey, 4)],
+        );
+    }
+
+    #[test]
+    fn views_cant_select_star() {
+        let policy = rules(None, None, Some(Severity::Error));
+
+        assert_eq!(
+            lines(
+                &policy,
+                "create view a as select * from users;\n\
+                 create or replace view b as select distinct u.* from users u;\n\
+                 create view c as select id, count(*) from users group by id;\n\
+                 select * from users;",
+            ),
+            vec![(Rule::SelectStarInView, 1), (Rule::SelectStarInView, 2)],
+        );
+    }
+
+    #[test]
+    fn tenant_tables_need_a_policy_in_the_same_revision() {
+        let policy = PolicyRules {
+            tenant_schemas: vec!["app".to_string(), "public".to_string()],
+            ..Default::default()
+        };
+
+        assert_eq!(policy.severity(Rule::TableWithoutRlsPolicy, None), Severity::Warning);
+        assert_eq!(PolicyRules::default().severity(Rule::TableWithoutRlsPolicy, None), Severity::Off);
+        assert_eq!(
+            lines(
+                &policy,
+                "create table app.orders (id int primary key, org_id int);\n\
+                 alter table app.orders enable row level security;\n\
+                 create policy orders_tenant_isolation on app.orders using (org_id = 1);\n\
+                 create table app.invoices (id int);\n\
+                 create table audit.events (id int);\n\
+                 create table notes (id int);",
+            ),
+            vec![(Rule::TableWithoutRlsPolicy, 4), (Rule::TableWithoutRlsPolicy, 6)],
+        );
+    }
+
+    #[test]
+    fn environments_override_severities() {
+        let mut policy = rules(None, None, Some(Severity::Error));
+        policy.environments.insert(
+            "development".to_string(),
+            Severities {
+                varchar_without_length: Some(Severity::Warning),
+                select_star_in_view: Some(Severity::Off),
+                ..Default::default()
+            },
+        );
+
+        assert_eq!(policy.severity(Rule::SelectStarInView, None), Severity::Error);
+        assert_eq!(policy.severity(Rule::SelectStarInView, Some("development")), Severity::Off);
+        assert_eq!(policy.severity(Rule::VarcharWithoutLength, Some("development")), Severity::Warning);
+        assert_eq!(policy.severity(Rule::VarcharWithoutLength, Some("production")), Severity::Off);
+    }
+
+    #[test]
+    fn rules_parse_from_toml() {
+        let policy: PolicyRules = toml::from_str(
+            "varchar_without_length = \"error\"\n\
+             [environments.development]\n\
+             varchar_without_length = \"off\"\n",
+        )
+        .unwrap();
+
+        assert_eq!(policy.severity(Rule::VarcharWithoutLength, None), Severity::Error);
+        assert_eq!(policy.severity(Rule::VarcharWithoutLength, Some("development")), Severity::Off);
+        assert!(toml::from_str::<PolicyRules>("varchar_without_lenght = \"error\"").is_err());
+    }
+}
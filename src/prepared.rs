@@ -0,0 +1,144 @@
+//! Two-phase commit of the revisions of every project in a workspace, eg.
+//! one per shard, with `jrny embark --all-projects --two-phase`. Each
+//! project's pending revisions are applied in a transaction of its own that's
+//! prepared with `PREPARE TRANSACTION` rather than committed, and only once
+//! every project's is prepared are they committed with `COMMIT PREPARED`, so
+//! that the run lands on every database or on none of them.
+//!
+//! Each transaction is named for the run and its place among the run's
+//! transactions, eg. `jrny:20261014T153000-4242:2/3:shards/eu`. They're
+//! prepared in that order and committed in the same order, while those
+//! prepared before a failure are rolled back in reverse, so which of a run's
+//! transactions are left prepared after it was interrupted shows how far it
+//! got: should the first be left, nothing was committed, and they're only
+//! committed if every one of them was prepared; should it not, committing had
+//! started, and the rest must be committed too.
+use std::fmt;
+
+use chrono::{DateTime, Utc};
+
+/// What the names of jrny's prepared transactions start with.
+pub const PREFIX: &str = "jrny:";
+
+/// The longest name Postgres allows for a prepared transaction.
+const MAX_LENGTH: usize = 199;
+
+/// Whether the server allows transactions to be prepared at all.
+pub const MAX_PREPARED_TRANSACTIONS: &str = "
+SELECT current_setting('max_prepared_transactions')::INT AS max
+";
+
+/// The transactions prepared by jrny, in any database of the cluster.
+pub const PREPARED_TRANSACTIONS: &str = "
+SELECT gid, prepared, owner, database
+FROM pg_prepared_xacts
+WHERE left(gid, length($1)) = $1
+ORDER BY prepared, gid
+";
+
+/// The name of one of a run's prepared transactions.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TransactionId {
+    /// When the run started and the process it was, eg. `20261014T153000-4242`
+    pub run: String,
+    /// Where the transaction comes among the run's, from 1
+    pub position: usize,
+    /// How many transactions the run prepares
+    pub total: usize,
+    /// The project whose revisions it applies, as listed in the workspace file
+    pub project: String,
+}
+
+impl TransactionId {
+    /// Names a run that starts at the moment.
+    pub fn run(started_at: DateTime<Utc>) -> String {
+        format!("{}-{}", started_at.format("%Y%m%dT%H%M%S"), std::process::id())
+    }
+
+    /// The transaction's name, if it isn't too long for Postgres, as it
+    /// might be for a project with a long enough path.
+    pub fn gid(&self) -> Option<String> {
+        Some(self.to_string()).filter(|gid| gid.len() <= MAX_LENGTH)
+    }
+
+    /// Reads the name of a transaction prepared by jrny.
+    pub fn parse(gid: &str) -> Option<Self> {
+        let mut parts = gid.strip_prefix(PREFIX)?.splitn(3, ':');
+        let run = parts.next()?.to_string();
+        let (position, total) = parts.next()?.split_once('/')?;
+        let (position, total) = (position.parse().ok()?, total.parse().ok()?);
+        let project = parts.next()?.to_string();
+
+        (1..=total).contains(&position).then_some(Self { run, position, total, project })
+    }
+}
+
+impl fmt::Display for TransactionId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}:{}/{}:{}", PREFIX, self.run, self.position, self.total, self.project)
+    }
+}
+
+/// A transaction left prepared, as found in `pg_prepared_xacts`.
+#[derive(Debug)]
+pub struct PreparedTransaction {
+    pub id: TransactionId,
+    pub prepared: DateTime<Utc>,
+    pub owner: String,
+    pub database: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Resolution {
+    Commit,
+    RollBack,
+}
+
+impl Resolution {
+    /// How the run's transactions left prepared, given by their positions,
+    /// are resolved, which is to commit them unless the first is among them
+    /// without every other.
+    pub fn of(total: usize, left: &[usize]) -> Self {
+        match left.contains(&1) && left.len() < total {
+            true => Self::RollBack,
+            false => Self::Commit,
+        }
+    }
+
+    pub fn describe(&self) -> &'static str {
+        match self {
+            Self::Commit => "to commit",
+            Self::RollBack => "to roll back",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ids_read_back_as_written() {
+        let id = TransactionId {
+            run: "20261014T153000-4242".to_string(),
+            position: 2,
+            total: 3,
+            project: "shards/eu:west".to_string(),
+        };
+
+        assert_eq!(id.gid().as_deref(), Some("jrny:20261014T153000-4242:2/3:shards/eu:west"));
+        assert_eq!(TransactionId::parse(&id.to_string()), Some(id.clone()));
+        assert_eq!(TransactionId::parse("jrny:20261014T153000-4242:4/3:shards/eu"), None);
+        assert_eq!(TransactionId::parse("other:20261014T153000-4242:1/3:shards/eu"), None);
+        assert_eq!(TransactionId { project: "a".repeat(200), ..id }.gid(), None);
+    }
+
+    #[test]
+    fn runs_are_committed_once_committing_started_or_all_were_prepared() {
+        assert_eq!(Resolution::of(3, &[1, 2, 3]), Resolution::Commit);
+        assert_eq!(Resolution::of(3, &[2, 3]), Resolution::Commit);
+        assert_eq!(Resolution::of(3, &[3]), Resolution::Commit);
+        assert_eq!(Resolution::of(3, &[1, 2]), Resolution::RollBack);
+        assert_eq!(Resolution::of(3, &[1]), Resolution::RollBack);
+    }
+}
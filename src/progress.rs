@@ -0,0 +1,201 @@
+//! Progress reporting while applying revisions.
+//!
+//! On a terminal this keeps a single status line updated in place; when
+//! output is piped it instead logs a status line every so often, so that
+//! long-running statements still show signs of life in CI logs.
+use std::{
+    io::{self, IsTerminal, Write},
+    sync::{mpsc, Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use log::{info, log, log_enabled, Level};
+
+/// How often the in-place status line is redrawn on a terminal
+const REDRAW_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often a status line is logged when output is not a terminal
+const LOG_INTERVAL: Duration = Duration::from_secs(30);
+
+struct State {
+    interactive: bool,
+    started: Instant,
+    revision: usize,
+    revisions: usize,
+    statement: usize,
+    statements: usize,
+    /// Batches of the current statement done so far, if it's batched
+    batches: usize,
+    batched_rows: u64,
+}
+
+impl State {
+    fn status(&self) -> String {
+        let batches = if self.batches > 0 {
+            format!(", {} rows in {} batch(es)", self.batched_rows, self.batches)
+        } else {
+            String::new()
+        };
+
+        format!(
+            "  revision {} of {}, statement {} of {}{} ({} elapsed)",
+            self.revision,
+            self.revisions,
+            self.statement,
+            self.statements,
+            batches,
+            format_duration(self.started.elapsed()),
+        )
+    }
+
+    fn draw(&self) {
+        if self.interactive {
+            print!("\r\x1b[2K{}", self.status());
+            let _ = io::stdout().flush();
+        }
+    }
+
+    fn clear(&self) {
+        if self.interactive {
+            print!("\r\x1b[2K");
+            let _ = io::stdout().flush();
+        }
+    }
+}
+
+/// Logs lines without disturbing the status line, from any thread.
+#[derive(Clone)]
+pub struct ProgressLog {
+    state: Arc<Mutex<State>>,
+}
+
+impl ProgressLog {
+    pub fn log(&self, level: Level, message: &str) {
+        let state = self.state.lock().unwrap();
+
+        state.clear();
+        log!(level, "{}", message);
+        state.draw();
+    }
+}
+
+pub struct Progress {
+    state: Arc<Mutex<State>>,
+    ticker: Option<(mpsc::Sender<()>, thread::JoinHandle<()>)>,
+}
+
+impl Progress {
+    /// Starts reporting progress for applying the given number of revisions.
+    pub fn start(revisions: usize) -> Self {
+        // Stay quiet on a terminal too if informational output is disabled
+        let interactive = io::stdout().is_terminal() && log_enabled!(Level::Info);
+
+        let state = Arc::new(Mutex::new(State {
+            interactive,
+            started: Instant::now(),
+            revision: 0,
+            revisions,
+            statement: 0,
+            statements: 0,
+            batches: 0,
+            batched_rows: 0,
+        }));
+
+        let (stop, stopped) = mpsc::channel();
+        let ticker_state = Arc::clone(&state);
+        let interval = if interactive { REDRAW_INTERVAL } else { LOG_INTERVAL };
+
+        let handle = thread::spawn(move || {
+            // Either a message or a disconnect means it's time to stop
+            while let Err(mpsc::RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                let state = ticker_state.lock().unwrap();
+
+                if state.interactive {
+                    state.draw();
+                } else if state.revision > 0 {
+                    info!("{}", state.status());
+                }
+            }
+        });
+
+        Self {
+            state,
+            ticker: Some((stop, handle)),
+        }
+    }
+
+    /// Marks the start of the next revision, logging its filename.
+    pub fn revision(&self, filename: &str, statements: usize) {
+        let mut state = self.state.lock().unwrap();
+
+        state.clear();
+        info!("  {}", filename);
+
+        state.revision += 1;
+        state.statement = 0;
+        state.statements = statements;
+        state.draw();
+    }
+
+    /// Logs a line about the current revision without disturbing the status line.
+    pub fn log(&self, level: Level, message: &str) {
+        self.log_handle().log(level, message);
+    }
+
+    /// A handle for logging from another thread, eg. heartbeats.
+    pub fn log_handle(&self) -> ProgressLog {
+        ProgressLog { state: Arc::clone(&self.state) }
+    }
+
+    /// Marks the start of the next statement within the current revision.
+    pub fn statement(&self) {
+        let mut state = self.state.lock().unwrap();
+
+        state.statement += 1;
+        state.batches = 0;
+        state.batched_rows = 0;
+        state.draw();
+    }
+
+    /// Updates the totals for the current statement as it's run in batches.
+    pub fn batch(&self, batches: usize, rows: u64) {
+        let mut state = self.state.lock().unwrap();
+
+        state.batches = batches;
+        state.batched_rows = rows;
+        state.draw();
+    }
+
+    /// Time elapsed since progress reporting started
+    pub fn elapsed(&self) -> Duration {
+        self.state.lock().unwrap().started.elapsed()
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        if let Some((stop, handle)) = self.ticker.take() {
+            let _ = stop.send(());
+            let _ = handle.join();
+        }
+
+        self.state.lock().unwrap().clear();
+    }
+}
+
+/// Formats a duration as eg. `350ms`, `42s`, `1m 42s`, or `2h 03m 42s`.
+pub fn format_duration(d: Duration) -> String {
+    let secs = d.as_secs();
+    let (h, m, s) = (secs / 3600, secs % 3600 / 60, secs % 60);
+
+    if h > 0 {
+        format!("{}h {:02}m {:02}s", h, m, s)
+    } else if m > 0 {
+        format!("{}m {:02}s", m, s)
+    } else if s > 0 {
+        format!("{}s", s)
+    } else {
+        format!("{}ms", d.as_millis())
+    }
+}
@@ -0,0 +1,290 @@
+//! Summary of an embark run, logged at the end of the run and optionally
+//! written to disk for attaching to deploy tickets and the like.
+use std::{fs, path::Path, time::Duration};
+
+use chrono::{DateTime, Utc};
+use log::info;
+
+use crate::{json::Json, logger::format_time, pgtap::TapOutcome, progress::format_duration, Error, Result};
+
+/// How many of the slowest statements to include in the summary
+const SLOWEST_COUNT: usize = 5;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RevisionStatus {
+    Applied,
+    Failed,
+    /// Not attempted because an earlier revision failed
+    Skipped,
+    /// Applied, but then rolled back as a later revision in the same release
+    /// group failed, or another project's prepared transaction did
+    RolledBack,
+}
+
+impl RevisionStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Applied => "applied",
+            Self::Failed => "failed",
+            Self::Skipped => "skipped",
+            Self::RolledBack => "rolled-back",
+        }
+    }
+}
+
+/// Details of a statement that failed to execute.
+#[derive(Debug)]
+pub struct StatementError {
+    /// The SQLSTATE code, if the error came from the database
+    pub code: Option<String>,
+    pub message: String,
+}
+
+/// The timing and result of a single executed statement.
+#[derive(Debug)]
+pub struct StatementOutcome {
+    /// The line within the revision file on which the statement begins
+    pub line: usize,
+    /// A condensed, truncated version of the statement text
+    pub summary: String,
+    /// The SHA-256 of the statement text as executed
+    pub checksum: String,
+    pub started_at: DateTime<Utc>,
+    pub elapsed: Duration,
+    /// Rows affected, if the statement succeeded
+    pub rows: Option<u64>,
+    pub error: Option<StatementError>,
+}
+
+/// The outcome of a single pending revision.
+#[derive(Debug)]
+pub struct RevisionOutcome {
+    pub filename: String,
+    pub status: RevisionStatus,
+    pub started_at: DateTime<Utc>,
+    pub elapsed: Duration,
+    pub statements: Vec<StatementOutcome>,
+}
+
+/// The file formats a report can be written in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReportFormat {
+    Json,
+    Markdown,
+}
+
+impl ReportFormat {
+    /// Determines the format from the file extension.
+    pub fn from_path(path: &Path) -> Result<Self> {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => Ok(Self::Json),
+            Some("md") => Ok(Self::Markdown),
+            _ => Err(Error::ReportFormatInvalid(path.display().to_string())),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct RunReport {
+    pub started_at: DateTime<Utc>,
+    pub elapsed: Duration,
+    pub revisions: Vec<RevisionOutcome>,
+    /// The error that stopped the run, if any
+    pub error: Option<String>,
+    /// The stable id of that error, eg. `revision-cancelled`
+    pub error_code: Option<&'static str>,
+    /// The pgTAP files run once the revisions were applied, if any were
+    pub tests: Vec<TapOutcome>,
+}
+
+impl RunReport {
+    /// A report for a run with nothing to apply
+    pub fn empty() -> Self {
+        Self {
+            started_at: Utc::now(),
+            elapsed: Duration::default(),
+            revisions: vec![],
+            error: None,
+            error_code: None,
+            tests: vec![],
+        }
+    }
+
+    pub fn count(&self, status: RevisionStatus) -> usize {
+        self.revisions.iter().filter(|r| r.status == status).count()
+    }
+
+    /// Returns the slowest statements across all revisions, slowest first,
+    /// along with the filename of the revision each belongs to.
+    pub fn slowest(&self, n: usize) -> Vec<(&str, &StatementOutcome)> {
+        let mut timings: Vec<_> = self
+            .revisions
+            .iter()
+            .flat_map(|r| r.statements.iter().map(move |s| (r.filename.as_str(), s)))
+            .collect();
+
+        timings.sort_by_key(|(_, s)| std::cmp::Reverse(s.elapsed));
+        timings.truncate(n);
+        timings
+    }
+
+    pub fn log_summary(&self) {
+        info!("\nSummary\n");
+        info!("  {:10}{}", "Applied", self.count(RevisionStatus::Applied));
+        info!("  {:10}{}", "Skipped", self.count(RevisionStatus::Skipped));
+        info!("  {:10}{}", "Failed", self.count(RevisionStatus::Failed));
+
+        // Only release groups and prepared transactions are ever rolled back
+        if self.count(RevisionStatus::RolledBack) > 0 {
+            info!("  {:10}{}", "Undone", self.count(RevisionStatus::RolledBack));
+        }
+
+        if !self.tests.is_empty() {
+            let passed = self.tests.iter().filter(|t| t.is_ok()).count();
+            info!("  {:10}{} of {} file(s) passed", "Tests", passed, self.tests.len());
+        }
+
+        info!("  {:10}{}", "Started", format_time(self.started_at));
+        info!("  {:10}{}", "Elapsed", format_duration(self.elapsed));
+
+        let slowest = self.slowest(SLOWEST_COUNT);
+
+        if slowest.is_empty() {
+            return;
+        }
+
+        info!("\nSlowest statements\n");
+
+        for (filename, timing) in slowest {
+            info!(
+                "  {:>10}  {}:{}  {}",
+                format_duration(timing.elapsed),
+                filename,
+                timing.line,
+                timing.summary,
+            );
+        }
+    }
+
+    pub fn to_json(&self) -> Json {
+        let revisions = self.revisions.iter().map(|r| {
+            let statements = r.statements.iter().map(|s| {
+                Json::object([
+                    ("line", Json::from(s.line)),
+                    ("statement", Json::from(s.summary.as_str())),
+                    ("seconds", Json::from(s.elapsed.as_secs_f64())),
+                    ("rows", Json::from(s.rows.map(|rows| rows as i64))),
+                    ("sqlstate", Json::from(s.error.as_ref().and_then(|e| e.code.clone()))),
+                    ("error", Json::from(s.error.as_ref().map(|e| e.message.clone()))),
+                ])
+            });
+
+            Json::object([
+                ("filename", Json::from(r.filename.as_str())),
+                ("status", Json::from(r.status.as_str())),
+                ("started_at", Json::from(r.started_at.to_rfc3339())),
+                ("seconds", Json::from(r.elapsed.as_secs_f64())),
+                ("statements", Json::Array(statements.collect())),
+            ])
+        });
+
+        let tests = self.tests.iter().map(|t| {
+            Json::object([
+                ("filename", Json::from(t.filename.as_str())),
+                ("passed", Json::from(t.passed)),
+                ("failed", Json::from(t.failures.len())),
+                ("planned", Json::from(t.planned)),
+                ("problem", Json::from(t.problem())),
+                ("failures", Json::Array(t.failures.iter().map(|f| Json::from(f.as_str())).collect())),
+            ])
+        });
+
+        Json::object([
+            ("started_at", Json::from(self.started_at.to_rfc3339())),
+            ("seconds", Json::from(self.elapsed.as_secs_f64())),
+            ("applied", Json::from(self.count(RevisionStatus::Applied))),
+            ("skipped", Json::from(self.count(RevisionStatus::Skipped))),
+            ("failed", Json::from(self.count(RevisionStatus::Failed))),
+            ("rolled_back", Json::from(self.count(RevisionStatus::RolledBack))),
+            ("error", Json::from(self.error.clone())),
+            ("error_code", Json::from(self.error_code)),
+            ("revisions", Json::Array(revisions.collect())),
+            ("tests", Json::Array(tests.collect())),
+        ])
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let mut md = format!(
+            "# jrny embark\n\n\
+             Started {} and took {}.\n\n\
+             | Applied | Skipped | Failed |\n\
+             |---------|---------|--------|\n\
+             | {} | {} | {} |\n",
+            self.started_at.to_rfc3339(),
+            format_duration(self.elapsed),
+            self.count(RevisionStatus::Applied),
+            self.count(RevisionStatus::Skipped),
+            self.count(RevisionStatus::Failed),
+        );
+
+        if let Some(error) = &self.error {
+            md.push_str(&format!("\n**Error:** {}\n", error));
+        }
+
+        md.push_str("\n## Revisions\n\n| Revision | Status | Duration |\n|----------|--------|----------|\n");
+
+        for r in &self.revisions {
+            md.push_str(&format!(
+                "| `{}` | {} | {} |\n",
+                r.filename,
+                r.status.as_str(),
+                format_duration(r.elapsed),
+            ));
+        }
+
+        if !self.tests.is_empty() {
+            md.push_str("\n## pgTAP tests\n\n| File | Passed | Failed | Problem |\n|------|--------|--------|---------|\n");
+
+            for t in &self.tests {
+                md.push_str(&format!(
+                    "| `{}` | {} | {} | {} |\n",
+                    t.filename,
+                    t.passed,
+                    t.failures.len(),
+                    t.problem().unwrap_or_default().replace('|', "\\|"),
+                ));
+            }
+        }
+
+        let slowest = self.slowest(SLOWEST_COUNT);
+
+        if !slowest.is_empty() {
+            md.push_str("\n## Slowest statements\n\n| Duration | Location | Statement |\n|----------|----------|-----------|\n");
+
+            for (filename, timing) in slowest {
+                md.push_str(&format!(
+                    "| {} | `{}:{}` | `{}` |\n",
+                    format_duration(timing.elapsed),
+                    filename,
+                    timing.line,
+                    timing.summary.replace('|', "\\|"),
+                ));
+            }
+        }
+
+        md
+    }
+
+    /// Writes the report as JSON or Markdown, depending on the file extension.
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let contents = match ReportFormat::from_path(path)? {
+            ReportFormat::Json => format!("{}\n", self.to_json()),
+            ReportFormat::Markdown => self.to_markdown(),
+        };
+
+        fs::write(path, contents)?;
+        info!("\nWrote report to {}", path.display());
+
+        Ok(())
+    }
+}
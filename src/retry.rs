@@ -0,0 +1,99 @@
+//! Retrying a statement that can't take its lock straight away, as marked by
+//! a directive comment directly before it:
+//!
+//! ```sql
+//! -- jrny:lock-retry attempts=10 wait=5s
+//! alter table users add column nickname text;
+//! ```
+//!
+//! Each attempt waits at most `wait` for the lock, by way of `lock_timeout`,
+//! rather than queueing behind a long-running transaction with every query
+//! on the table then queueing behind it in turn. Between attempts, jrny
+//! pauses for as long again, letting those queries through.
+use std::time::Duration;
+
+use crate::{
+    meta::{directive_settings, parse_duration},
+    statements::Statement,
+};
+
+/// Names the directive, which is followed by its settings
+pub const DIRECTIVE: &str = "lock-retry";
+
+const DEFAULT_ATTEMPTS: u32 = 10;
+const DEFAULT_WAIT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct LockRetry {
+    /// The most times the statement is tried, including the first
+    pub attempts: u32,
+    /// How long each attempt waits for the lock, and the pause after it
+    pub wait: Duration,
+}
+
+impl LockRetry {
+    /// The retrying asked for by a directive in the statement's comments,
+    /// if any.
+    pub fn of(statement: &Statement) -> Result<Option<Self>, String> {
+        directive_settings(&statement.sql, DIRECTIVE).map(Self::parse).transpose()
+    }
+
+    /// Parses settings such as `attempts=10 wait=5s`, either of which may be
+    /// left out for its default.
+    fn parse(settings: &str) -> Result<Self, String> {
+        let mut retry = Self {
+            attempts: DEFAULT_ATTEMPTS,
+            wait: DEFAULT_WAIT,
+        };
+
+        for setting in settings.split_whitespace() {
+            match setting.split_once('=') {
+                Some(("attempts", attempts)) => {
+                    retry.attempts = attempts
+                        .parse()
+                        .ok()
+                        .filter(|attempts| *attempts > 0)
+                        .ok_or(format!("expected a positive number of attempts, found `{}`", attempts))?;
+                }
+                Some(("wait", wait)) => {
+                    retry.wait = parse_duration(wait)
+                        .filter(|wait| !wait.is_zero())
+                        .ok_or(format!("expected a duration such as `5s`, found `{}`", wait))?;
+                }
+                _ => return Err(format!("unexpected `{}`, expected `attempts=[count]` or `wait=[duration]`", setting)),
+            }
+        }
+
+        Ok(retry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::statements::split;
+
+    fn retry(sql: &str) -> Result<Option<LockRetry>, String> {
+        LockRetry::of(&split(sql)[0])
+    }
+
+    #[test]
+    fn directive_gives_settings_or_defaults() {
+        assert_eq!(
+            retry("-- jrny:lock-retry attempts=3 wait=500ms\nalter table users add column name text").unwrap(),
+            Some(LockRetry { attempts: 3, wait: Duration::from_millis(500) }),
+        );
+        assert_eq!(
+            retry("-- jrny:lock-retry\nalter table users add column name text").unwrap(),
+            Some(LockRetry { attempts: DEFAULT_ATTEMPTS, wait: DEFAULT_WAIT }),
+        );
+        assert_eq!(retry("alter table users add column name text").unwrap(), None);
+    }
+
+    #[test]
+    fn invalid_settings_are_rejected() {
+        assert!(retry("-- jrny:lock-retry attempts=0\nalter table users add column name text").is_err());
+        assert!(retry("-- jrny:lock-retry wait=soon\nalter table users add column name text").is_err());
+        assert!(retry("-- jrny:lock-retry tries=3\nalter table users add column name text").is_err());
+    }
+}
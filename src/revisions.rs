@@ -1,11 +1,41 @@
 use chrono::{DateTime, TimeZone, Utc};
-use sha2::{Digest, Sha256};
+use serde::Deserialize;
 use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
 use std::convert::TryFrom;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use crate::{Error, Result};
+use crate::{
+    approvals::OWNERS_FILE,
+    checksum::ChecksumSettings,
+    config::RevisionsSettings,
+    git::Provenance,
+    ignore::IgnoreRules,
+    meta::RevisionMeta,
+    paths,
+    signing::is_signature,
+    Error,
+    Result,
+};
+
+/// Prefix marking a file in the revisions directory as a repeatable revision.
+pub const REPEATABLE_PREFIX: &str = "R__";
+
+/// Subdirectory of the revisions directory in which all files are repeatable revisions.
+pub const REPEATABLE_DIR: &str = "repeatable";
+
+/// Suffix of the name of a revision marking it as a draft, as with `draft`
+/// in its frontmatter, eg. `003.1618450954.backfill-orders.draft.sql`.
+pub const DRAFT_SUFFIX: &str = ".draft";
+
+/// Suffix of the companion file that undoes a revision, alongside it, eg.
+/// `003.1618450954.add-orders.revert.sql`.
+pub const REVERT_SUFFIX: &str = ".revert.sql";
+
+/// Suffix of the companion file of assertions checked once a revision is
+/// applied, alongside it, eg. `003.1618450954.add-orders.verify.sql`.
+pub const VERIFY_SUFFIX: &str = ".verify.sql";
 
 /// Metadata and contents for a revision loaded from disk.
 #[derive(Debug)]
@@ -22,43 +52,424 @@ pub struct RevisionFile {
     pub filename: String,
     /// The name of the file, excluding id, timestamp, and extension
     pub name: String,
+    /// Metadata from the frontmatter of the file
+    pub meta: RevisionMeta,
+    /// The release group of the file, if any, whose pending revisions are
+    /// applied together in one transaction
+    pub group: Option<String>,
+    /// The subdirectory of the revisions directory holding the file, if any,
+    /// at any depth
+    pub directory: Option<String>,
+    /// The contents of the companion file undoing the revision, if it has one
+    pub revert: Option<String>,
+    /// The contents of its companion file of assertions, if it has one
+    pub verify: Option<String>,
 }
 
 impl RevisionFile {
     /// Attempts to read revision directory to convert all entries (assumed to be SQL files)
-    /// into metadata objects with contents stored.
-    pub fn all_from_disk(revisions: &PathBuf) -> Result<Vec<Self>> {
-        let mut entries = fs::read_dir(revisions.as_path())?
-            .map(|res| res.map(|e| e.path()).map_err(Error::IoError))
-            .collect::<Result<Vec<_>>>()?;
+    /// into metadata objects with contents stored, checksummed by the given scheme.
+    pub fn all_from_disk(settings: &RevisionsSettings) -> Result<Vec<Self>> {
+        let (files, invalid) = Self::partition_from_disk(settings)?;
 
-        entries.sort();
-        entries.iter().map(Self::try_from).collect()
+        match invalid.into_iter().next() {
+            Some(invalid) => Err(invalid.error),
+            None => Ok(files),
+        }
     }
-}
 
-impl TryFrom<&PathBuf> for RevisionFile {
-    type Error = crate::Error;
+    /// Like `all_from_disk` but, rather than failing on the first entry that is
+    /// not a valid revision, collects the error for each alongside its filename.
+    ///
+    /// Subdirectories are read at any depth, including those linked to, and
+    /// their revisions flattened into the one list in order of path. Each
+    /// subdirectory of the revisions directory is a release group, taking in
+    /// those within it, unless release groups are turned off.
+    pub fn partition_from_disk(settings: &RevisionsSettings) -> Result<(Vec<Self>, Vec<InvalidRevision>)> {
+        let mut files = vec![];
+        let mut invalid = vec![];
+
+        let ignored = IgnoreRules::from_dir(&settings.directory)?;
+
+        Self::load_dir(settings, &ignored, &settings.directory, None, &mut HashSet::new(), &mut files, &mut invalid)?;
+
+        Ok((files, invalid))
+    }
+
+    /// Loads the revisions in the directory, and those in its subdirectories,
+    /// as within the release group if there is one. Directories already
+    /// read, eg. as a link leads back to one, are skipped.
+    fn load_dir(
+        settings: &RevisionsSettings,
+        ignored: &IgnoreRules,
+        dir: &Path,
+        group: Option<&str>,
+        visited: &mut HashSet<PathBuf>,
+        files: &mut Vec<Self>,
+        invalid: &mut Vec<InvalidRevision>,
+    ) -> Result<()> {
+        let revisions = &settings.directory;
+
+        if !visited.insert(fs::canonicalize(dir)?) {
+            return Ok(());
+        }
+
+        let top_level = dir == revisions.as_path();
+        let directory = paths::to_slash(dir.strip_prefix(revisions).unwrap_or(dir)).filter(|d| !d.is_empty());
+        let entries: Vec<_> = sorted_entries(dir)?
+            .into_iter()
+            .filter(|entry| !(is_owners(entry) || is_signature(entry) || is_companion(entry)))
+            .filter(|entry| !(top_level && is_repeatable(revisions, entry)))
+            .filter(|entry| !is_ignored(ignored, revisions, entry))
+            .collect();
+        let mut conflicts = case_conflicts(revisions, &entries);
+
+        for entry in &entries {
+            if let Some(error) = conflicts.remove(entry) {
+                invalid.push(InvalidRevision::new(revisions, entry, error));
+                continue;
+            }
+
+            if !entry.is_dir() {
+                match Self::from_path(entry, &settings.checksum) {
+                    Ok(file) => files.push(Self {
+                        group: group.map(ToString::to_string),
+                        directory: directory.clone(),
+                        ..file
+                    }),
+                    Err(error) => invalid.push(InvalidRevision::new(revisions, entry, error)),
+                }
+                continue;
+            }
 
-    /// Attempts to gather appropriate metadata for and read contents of given path buf.
-    fn try_from(p: &PathBuf) -> std::result::Result<Self, Self::Error> {
+            let name = match paths::to_slash(entry.strip_prefix(revisions).unwrap_or(entry)) {
+                Some(name) => name,
+                None => {
+                    invalid.push(InvalidRevision::new(revisions, entry, Error::FileNotValid(entry.display().to_string())));
+                    continue;
+                }
+            };
+            let group = match group {
+                Some(group) => Some(group),
+                None if top_level && settings.release_groups => Some(name.as_str()),
+                None => None,
+            };
+
+            Self::load_dir(settings, ignored, entry, group, visited, files, invalid)?;
+        }
+
+        Ok(())
+    }
+
+    /// The path of the file relative to the revisions directory, as it's
+    /// shown and signed, separated by `/`.
+    pub fn path_name(&self) -> String {
+        path_name(self.directory.as_deref(), &self.filename)
+    }
+
+    /// Attempts to gather appropriate metadata for and read contents of given path.
+    fn from_path(p: &Path, checksum: &ChecksumSettings) -> Result<Self> {
         let filename = p
             .file_name()
-            .map(|os_str| os_str.to_str())
-            .flatten()
+            .and_then(|os_str| os_str.to_str())
             .ok_or_else(|| Error::FileNotValid(p.display().to_string()))?;
 
         let title = RevisionTitle::try_from(filename)?;
+        let (name, draft) = strip_draft(&title.name);
         let contents = fs::read_to_string(p)?;
+        let mut meta = RevisionMeta::parse(filename, &contents)?;
+        meta.draft |= draft;
+
+        let companion = |suffix| -> Result<Option<String>> {
+            let path = p.with_file_name(companion_name(filename, suffix));
+
+            match path.is_file() {
+                true => Ok(Some(fs::read_to_string(path)?)),
+                false => Ok(None),
+            }
+        };
 
         Ok(Self {
             id: title.id,
-            checksum: to_checksum(&contents),
+            checksum: checksum.checksum(&contents),
             contents,
             created_at: title.created_at,
             filename: filename.to_string(),
-            name: title.name,
+            name: name.to_string(),
+            meta,
+            group: None,
+            directory: None,
+            revert: companion(REVERT_SUFFIX)?,
+            verify: companion(VERIFY_SUFFIX)?,
+        })
+    }
+}
+
+/// An entry in the revisions directory that could not be loaded as a revision.
+#[derive(Debug)]
+pub struct InvalidRevision {
+    pub filename: String,
+    pub error: Error,
+}
+
+impl InvalidRevision {
+    /// Identifies the entry by its path relative to the revisions directory.
+    fn new(revisions: &Path, entry: &Path, error: Error) -> Self {
+        Self {
+            filename: paths::relative(revisions, entry),
+            error,
+        }
+    }
+}
+
+/// Whether a revision limited to the given environments, if any, applies to the
+/// one named. Such revisions never apply to environments without a name, since
+/// there's no telling which it is.
+pub fn is_applicable(environments: &[String], env_name: Option<&str>) -> bool {
+    environments.is_empty() || env_name.is_some_and(|name| environments.iter().any(|env| env == name))
+}
+
+/// The paths of all entries in the directory, sorted by name.
+pub fn sorted_entries(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut entries = fs::read_dir(dir)?
+        .map(|res| res.map(|e| e.path()).map_err(Error::IoError))
+        .collect::<Result<Vec<_>>>()?;
+
+    entries.sort();
+    Ok(entries)
+}
+
+/// The path of a file in the group, if any, separated by `/`.
+fn path_name(group: Option<&str>, filename: &str) -> String {
+    match group {
+        Some(group) => format!("{}/{}", group, filename),
+        None => filename.to_string(),
+    }
+}
+
+/// The error for each of the entries whose name differs only by case from
+/// that of one before it, which couldn't both be checked out on
+/// case-insensitive filesystems.
+fn case_conflicts(root: &Path, entries: &[PathBuf]) -> HashMap<PathBuf, Error> {
+    let names: Vec<_> = entries.iter().map(|entry| paths::relative(root, entry)).collect();
+
+    paths::case_conflicts(names.iter().map(String::as_str))
+        .into_iter()
+        .filter_map(|(name, earlier)| {
+            let (entry, _) = entries.iter().zip(&names).find(|(_, n)| n.as_str() == name)?;
+
+            Some((entry.clone(), Error::PathCaseConflict(name.to_string(), earlier.to_string())))
         })
+        .collect()
+}
+
+/// Whether the entry is, or is the directory of, repeatable revisions rather
+/// than a versioned revision.
+fn is_repeatable(revisions: &Path, entry: &Path) -> bool {
+    let filename = entry.file_name().and_then(|f| f.to_str()).unwrap_or_default();
+
+    if entry.is_dir() {
+        return entry == revisions.join(REPEATABLE_DIR);
+    }
+
+    filename.starts_with(REPEATABLE_PREFIX) && filename.ends_with(".sql")
+}
+
+/// The name without any draft suffix, and whether it had one.
+fn strip_draft(name: &str) -> (&str, bool) {
+    match name.strip_suffix(DRAFT_SUFFIX) {
+        Some(stripped) if !stripped.is_empty() => (stripped, true),
+        _ => (name, false),
+    }
+}
+
+/// The name of the revision's companion file with the suffix, eg.
+/// `003.1618450954.add-orders.verify.sql`.
+pub fn companion_name(filename: &str, suffix: &str) -> String {
+    format!("{}{}", filename.strip_suffix(".sql").unwrap_or(filename), suffix)
+}
+
+/// Whether the entry is a companion file of a revision, undoing it or with
+/// its assertions, rather than a revision itself.
+fn is_companion(entry: &Path) -> bool {
+    let filename = entry.file_name().and_then(|f| f.to_str()).unwrap_or_default();
+
+    entry.is_file() && (filename.ends_with(REVERT_SUFFIX) || filename.ends_with(VERIFY_SUFFIX))
+}
+
+/// Whether the entry is ignored by the revisions directory's ignore file.
+fn is_ignored(ignored: &IgnoreRules, revisions: &Path, entry: &Path) -> bool {
+    ignored.is_ignored(&paths::relative(revisions, entry), entry.is_dir())
+}
+
+/// Whether the entry lists the owners of its directory's revisions.
+fn is_owners(entry: &Path) -> bool {
+    entry.is_file() && entry.file_name().and_then(|f| f.to_str()) == Some(OWNERS_FILE)
+}
+
+/// The kinds of file that are re-applied whenever their contents change,
+/// rather than only ever being applied once, each tracked separately.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReappliedKind {
+    Repeatable,
+    /// Reference data, applied on demand by `jrny seed` rather than by embark
+    Seed,
+}
+
+/// Contents for a repeatable revision loaded from disk, which is applied
+/// after all versioned revisions and again whenever its contents change,
+/// eg. for views, functions, and grants.
+#[derive(Debug)]
+pub struct RepeatableFile {
+    /// The hash of the contents
+    pub checksum: String,
+    /// Contents of revision file
+    pub contents: String,
+    /// The name of the file, relative to the revisions directory
+    pub filename: String,
+    /// The name of the file, excluding prefix, directory, and extension
+    pub name: String,
+    pub kind: ReappliedKind,
+    /// Metadata from the frontmatter of the file
+    pub meta: RevisionMeta,
+}
+
+impl RepeatableFile {
+    /// Reads all repeatable revisions, being `R__`-prefixed SQL files in the revisions
+    /// directory followed by those in its `repeatable` subdirectory, each in order of
+    /// filename. This is also the order in which they are applied.
+    ///
+    /// As with `RevisionFile::partition_from_disk`, entries that cannot be loaded
+    /// are collected alongside the error for each.
+    pub fn partition_from_disk(revisions: &Path, checksum: &ChecksumSettings) -> Result<(Vec<Self>, Vec<InvalidRevision>)> {
+        let mut files = vec![];
+        let mut invalid = vec![];
+        let ignored = IgnoreRules::from_dir(revisions)?;
+
+        let top_level = sorted_entries(revisions)?
+            .into_iter()
+            .filter(|entry| entry.is_file() && is_repeatable(revisions, entry));
+
+        let nested_dir = revisions.join(REPEATABLE_DIR);
+        let nested = match nested_dir.is_dir() && !is_ignored(&ignored, revisions, &nested_dir) {
+            true => sorted_entries(&nested_dir)?,
+            false => vec![],
+        };
+
+        let entries: Vec<_> = top_level
+            .chain(nested.into_iter().filter(|entry| !is_owners(entry) && !is_signature(entry)))
+            .filter(|entry| !is_ignored(&ignored, revisions, entry))
+            .collect();
+        let mut conflicts = case_conflicts(revisions, &entries);
+
+        for entry in entries {
+            if let Some(error) = conflicts.remove(&entry) {
+                invalid.push(InvalidRevision::new(revisions, &entry, error));
+                continue;
+            }
+
+            match Self::from_path(revisions, &entry, ReappliedKind::Repeatable, checksum) {
+                Ok(file) => files.push(file),
+                Err(error) => invalid.push(InvalidRevision::new(revisions, &entry, error)),
+            }
+        }
+
+        Ok((files, invalid))
+    }
+
+    /// Reads all seeds, being SQL files in the seeds directory followed by those
+    /// in the subdirectory for the environment, if it has a name, each in order of
+    /// filename. Subdirectories for other environments are ignored.
+    pub fn seeds_from_disk(seeds: &Path, env_name: Option<&str>, checksum: &ChecksumSettings) -> Result<Vec<Self>> {
+        if !seeds.is_dir() {
+            return Ok(vec![]);
+        }
+
+        let mut entries: Vec<_> = sorted_entries(seeds)?.into_iter().filter(|e| !e.is_dir()).collect();
+
+        if let Some(env_dir) = env_name.map(|name| seeds.join(name)).filter(|dir| dir.is_dir()) {
+            entries.extend(sorted_entries(&env_dir)?);
+        }
+
+        entries
+            .iter()
+            .map(|entry| Self::from_path(seeds, entry, ReappliedKind::Seed, checksum))
+            .collect()
+    }
+
+    fn from_path(root: &Path, p: &Path, kind: ReappliedKind, checksum: &ChecksumSettings) -> Result<Self> {
+        let filename = paths::to_slash(p.strip_prefix(root).unwrap_or(p))
+            .ok_or_else(|| Error::FileNotValid(p.display().to_string()))?;
+
+        let name = p
+            .file_name()
+            .and_then(|f| f.to_str())
+            .and_then(|f| f.strip_suffix(".sql"))
+            .filter(|_| p.is_file())
+            .map(|f| f.strip_prefix(REPEATABLE_PREFIX).unwrap_or(f).to_string())
+            .ok_or_else(|| Error::RepeatableNameInvalid(filename.clone()))?;
+        let (name, draft) = strip_draft(&name);
+        let name = name.to_string();
+
+        let contents = fs::read_to_string(p)?;
+        let mut meta = RevisionMeta::parse(&filename, &contents)?;
+        meta.draft |= draft;
+
+        Ok(Self {
+            checksum: checksum.checksum(&contents),
+            contents,
+            filename,
+            name,
+            kind,
+            meta,
+        })
+    }
+}
+
+/// Metadata stored for the most recent application of a repeatable revision.
+#[derive(Debug)]
+pub struct RepeatableRecord {
+    /// Moment the revision was last applied to the database
+    pub applied_on: DateTime<Utc>,
+    /// The hash of the contents when last applied
+    pub checksum: String,
+    /// The name of the file, relative to the revisions directory
+    pub filename: String,
+}
+
+/// A repeatable revision on disk along with its most recent application, if any.
+#[derive(Debug, PartialEq, Eq)]
+pub struct AnnotatedRepeatable {
+    /// Moment the revision was last applied to the database
+    pub applied_on: Option<DateTime<Utc>>,
+    /// The hash of the contents on disk
+    pub checksum: String,
+    /// Whether or not checksums for file and record match, if the latter is present
+    pub checksums_match: Option<bool>,
+    /// Contents of revision file
+    pub contents: String,
+    /// The name of the file, relative to the revisions directory
+    pub filename: String,
+    /// The name of the file, excluding prefix, directory, and extension
+    pub name: String,
+    pub kind: ReappliedKind,
+    /// Metadata from the frontmatter of the file
+    pub meta: RevisionMeta,
+    /// Environments the revision is limited to, if any
+    pub environments: Vec<String>,
+    /// Whether the revision applies to the current environment
+    pub applicable: bool,
+}
+
+impl AnnotatedRepeatable {
+    /// Whether the revision has never been applied or has changed since it last was.
+    pub fn is_pending(&self) -> bool {
+        self.checksums_match != Some(true)
+    }
+
+    /// Whether the revision is a draft, which embark doesn't apply.
+    pub fn is_draft(&self) -> bool {
+        self.meta.draft
     }
 }
 
@@ -77,6 +488,14 @@ pub struct RevisionRecord {
     pub filename: String,
     /// The name of the file, excluding timestamp and extension
     pub name: String,
+    /// Where the revision came from when applied, if known
+    pub provenance: Provenance,
+    /// The release group the revision was applied as part of, if any
+    pub release_group: Option<String>,
+    /// A summary of the objects the revision changed, if recorded
+    pub impact: Option<String>,
+    /// The contents of the file as it was applied, if archived
+    pub archived: Option<String>,
 }
 
 /// Comprehensive metadata for a revision detected on disk or in the database.
@@ -100,6 +519,72 @@ pub struct AnnotatedRevision {
     pub name: String,
     /// Whether or not the file for an applied revision is found on disk
     pub on_disk: bool,
+    /// Metadata from the frontmatter of the revision file, if present
+    pub meta: Option<RevisionMeta>,
+    /// Environments the revision is limited to, if any
+    pub environments: Vec<String>,
+    /// Whether the revision applies to the current environment
+    pub applicable: bool,
+    /// The release group of the revision file, if it's on disk and has one
+    pub group: Option<String>,
+    /// The subdirectory holding the revision file, if it's on disk and in one
+    pub directory: Option<String>,
+    /// The assertions checked once the revision is applied, if it's on disk
+    /// and has a companion file of them
+    pub verify: Option<String>,
+}
+
+impl AnnotatedRevision {
+    /// Whether the revision is a draft, which embark doesn't apply, nor any
+    /// revision after it.
+    pub fn is_draft(&self) -> bool {
+        self.meta.as_ref().is_some_and(|meta| meta.draft)
+    }
+
+    /// The id of the revision superseding this one, if it's a tombstone.
+    pub fn superseded_by(&self) -> Option<i32> {
+        self.meta.as_ref().and_then(|meta| meta.superseded_by)
+    }
+
+    /// The path of the revision file relative to the revisions directory.
+    pub fn path(&self) -> PathBuf {
+        match &self.directory {
+            Some(directory) => Path::new(directory).join(&self.filename),
+            None => PathBuf::from(&self.filename),
+        }
+    }
+
+    /// As with `RevisionFile::path_name`, the path as it's shown, separated
+    /// by `/`.
+    pub fn path_name(&self) -> String {
+        path_name(self.directory.as_deref(), &self.filename)
+    }
+}
+
+/// The order of revisions across the directories holding them.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RevisionOrder {
+    /// By id, whichever directories they're in
+    #[default]
+    Id,
+    /// By the path of the directory holding them and then by id, so that
+    /// those in the revisions directory itself come first, and all of those
+    /// in each subdirectory come before any in the next
+    Directory,
+}
+
+impl RevisionOrder {
+    pub fn sort(&self, revisions: &mut [AnnotatedRevision]) {
+        revisions.sort();
+
+        if *self == Self::Directory {
+            let parts = |revision: &AnnotatedRevision| revision.directory.clone().unwrap_or_default();
+
+            // Being stable, those in each directory stay in order of id
+            revisions.sort_by(|a, b| parts(a).split('/').cmp(parts(b).split('/')));
+        }
+    }
 }
 
 impl Ord for AnnotatedRevision {
@@ -169,9 +654,9 @@ impl TryFrom<&str> for RevisionTitle {
     }
 }
 
-fn to_checksum(s: &str) -> String {
-    // See: https://users.rust-lang.org/t/sha256-result-to-string/49391/3
-    format!("{:x}", Sha256::digest(s.as_bytes()))
+/// Checksums by the default scheme, as for the contents of bundles.
+pub fn to_checksum(s: &str) -> String {
+    ChecksumSettings::default().checksum(s)
 }
 
 #[cfg(test)]
@@ -227,4 +712,48 @@ mod tests {
             result => panic!("received {:?}", result),
         }
     }
+
+    #[test]
+    fn draft_suffixes_are_stripped_from_names() {
+        assert_eq!(strip_draft("backfill-orders.draft"), ("backfill-orders", true));
+        assert_eq!(strip_draft("seed-users.production.draft"), ("seed-users.production", true));
+        assert_eq!(strip_draft("backfill-orders"), ("backfill-orders", false));
+        assert_eq!(strip_draft("draft"), ("draft", false));
+    }
+
+    #[test]
+    fn revisions_are_ordered_by_id_or_directory() {
+        let revision = |id, directory: Option<&str>| AnnotatedRevision {
+            id,
+            applied_on: None,
+            checksum: None,
+            checksums_match: None,
+            contents: None,
+            created_at: Utc.ymd(2021, 1, 1).and_hms(0, 0, 0),
+            filename: format!("{:03}.1609459200.r.sql", id),
+            name: "r".to_string(),
+            on_disk: true,
+            meta: None,
+            environments: vec![],
+            applicable: true,
+            group: None,
+            directory: directory.map(ToString::to_string),
+            verify: None,
+        };
+        let mut revisions = vec![
+            revision(4, Some("2021/billing")),
+            revision(1, Some("2021-archive")),
+            revision(2, Some("2021")),
+            revision(5, None),
+            revision(3, Some("2021/billing")),
+        ];
+        let ids = |revisions: &[AnnotatedRevision]| revisions.iter().map(|r| r.id).collect::<Vec<_>>();
+
+        RevisionOrder::Id.sort(&mut revisions);
+        assert_eq!(ids(&revisions), vec![1, 2, 3, 4, 5]);
+
+        RevisionOrder::Directory.sort(&mut revisions);
+        assert_eq!(ids(&revisions), vec![5, 2, 3, 4, 1]);
+        assert_eq!(revisions[1].path_name(), "2021/002.1609459200.r.sql");
+    }
 }
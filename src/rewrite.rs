@@ -0,0 +1,427 @@
+//! Rewriting statements before they're executed, so that conventions such as
+//! always qualifying names with a schema are applied rather than only being
+//! caught in code review.
+//!
+//! The built-in rewriters are enabled in the `[rewrite]` section of the
+//! config, and embedding jrny as a library allows adding others by
+//! implementing `Rewriter`. Only what's executed is rewritten - revisions are
+//! reviewed and checksummed as they're written.
+//!
+//! Statements marked with a directive comment are also wrapped in a block
+//! ignoring the error raised should what they create already exist, which
+//! `CREATE TYPE` and `CREATE ROLE` can't do for themselves:
+//!
+//! ```sql
+//! -- jrny:ignore-duplicate
+//! CREATE TYPE mood AS ENUM ('happy', 'sad');
+//! ```
+use std::{fmt, sync::Arc};
+
+use crate::{
+    batch::Batch,
+    config::RewriteSettings,
+    statements::{find_top_level_keyword, Statement},
+};
+
+/// Transforms statements before they're executed.
+pub trait Rewriter: fmt::Debug + Send + Sync {
+    /// The SQL to execute in place of the statement's, if it's to be rewritten.
+    fn rewrite(&self, statement: &Statement) -> Option<String>;
+}
+
+/// The rewriters to apply to each statement, in order.
+#[derive(Clone, Debug, Default)]
+pub struct Rewrites {
+    rewriters: Vec<Arc<dyn Rewriter>>,
+}
+
+impl Rewrites {
+    /// The built-in rewriters enabled by the settings.
+    pub fn from_settings(settings: &RewriteSettings) -> Self {
+        let mut rewriters: Vec<Arc<dyn Rewriter>> = vec![];
+
+        if settings.if_not_exists {
+            rewriters.push(Arc::new(IfNotExists));
+        }
+        if let Some(schema) = &settings.qualify_schema {
+            rewriters.push(Arc::new(QualifySchema { schema: schema.clone() }));
+        }
+        if settings.security_invoker {
+            rewriters.push(Arc::new(SecurityInvoker));
+        }
+
+        Self { rewriters }
+    }
+
+    /// Adds the rewriters to apply after those already added.
+    pub fn extend(&mut self, rewriters: &[Arc<dyn Rewriter>]) {
+        self.rewriters.extend(rewriters.iter().cloned());
+    }
+
+    /// The statements with each rewriter applied in turn, and then wrapped
+    /// should they ignore duplicates, so that every rewriter sees the
+    /// statement as written rather than the block around it.
+    pub fn apply(&self, mut statements: Vec<Statement>) -> Vec<Statement> {
+        for statement in &mut statements {
+            for rewriter in self.rewriters.iter().map(AsRef::as_ref).chain([&IgnoreDuplicate as &dyn Rewriter]) {
+                if let Some(sql) = rewriter.rewrite(statement) {
+                    statement.sql = sql;
+                }
+            }
+        }
+
+        statements
+    }
+}
+
+/// Names the directive wrapping a statement to ignore duplicates
+pub const IGNORE_DUPLICATE: &str = "ignore-duplicate";
+
+/// Wraps statements marked with the directive in a `DO` block that ignores
+/// `duplicate_object` errors, keeping any comments before them outside it.
+#[derive(Debug)]
+pub struct IgnoreDuplicate;
+
+impl Rewriter for IgnoreDuplicate {
+    fn rewrite(&self, statement: &Statement) -> Option<String> {
+        if !statement.has_directive(IGNORE_DUPLICATE) {
+            return None;
+        }
+
+        let sql = &statement.sql;
+        let at = find_top_level_keyword(sql, &statement.leading_keyword()?)?;
+        let (comments, body) = (&sql[..at], sql[at..].trim_end());
+        // The body's own dollar quotes, eg. of a function, mustn't end the block
+        let tag = (0..)
+            .map(|i| if i == 0 { "$$".to_string() } else { format!("$jrny{}$", i) })
+            .find(|tag| !sql.contains(tag.as_str()))?;
+        // A trailing comment would otherwise swallow the semicolon
+        let end = if body.lines().last().is_some_and(|line| line.contains("--")) { "\n;" } else { ";" };
+
+        Some(format!(
+            "{}{} {}\n{}\n{}{}\n{}\n{} {}",
+            comments,
+            cased(body, "DO"),
+            tag,
+            cased(body, "BEGIN"),
+            body,
+            end,
+            cased(body, "EXCEPTION WHEN duplicate_object THEN NULL;"),
+            cased(body, "END"),
+            tag,
+        ))
+    }
+}
+
+/// Keywords that may come between `CREATE` and the kind of object created.
+const CREATE_MODIFIERS: &[&str] = &["or", "replace", "unique", "temp", "temporary", "unlogged", "global", "local", "recursive"];
+
+/// Adds `IF NOT EXISTS` to statements creating tables, indexes, schemas,
+/// sequences, extensions, and materialized views, so that they can be rerun.
+#[derive(Debug)]
+pub struct IfNotExists;
+
+impl Rewriter for IfNotExists {
+    fn rewrite(&self, statement: &Statement) -> Option<String> {
+        let (object, at) = created(statement)?;
+
+        if !matches!(object.as_str(), "table" | "index" | "schema" | "sequence" | "extension" | "materialized view") {
+            return None;
+        }
+
+        let sql = &statement.sql;
+        let at = skip_keywords(sql, at, &["concurrently"]);
+        let next = next_word(sql, at);
+
+        // Indexes without a name can't be created only if they don't exist
+        if next == "if" || (object == "index" && next == "on") {
+            return None;
+        }
+
+        Some(insert(sql, at, &cased(sql, "IF NOT EXISTS "), ""))
+    }
+}
+
+/// Qualifies the names of tables, views, and sequences that are created or
+/// altered, and of tables that are indexed, with the schema if they have none.
+#[derive(Debug)]
+pub struct QualifySchema {
+    pub schema: String,
+}
+
+impl Rewriter for QualifySchema {
+    fn rewrite(&self, statement: &Statement) -> Option<String> {
+        let sql = &statement.sql;
+
+        let at = match created(statement) {
+            Some((object, _)) if object == "index" => {
+                let on = find_top_level_keyword(sql, "on")?;
+                skip_keywords(sql, on + "on".len(), &["only"])
+            }
+            Some((object, at)) if matches!(object.as_str(), "table" | "view" | "materialized view" | "sequence") => {
+                skip_keywords(sql, at, &["if", "not", "exists"])
+            }
+            Some(_) => return None,
+            None => {
+                let words: Vec<_> = statement.words().take(2).collect();
+
+                if words.first().map(String::as_str) != Some("alter") {
+                    return None;
+                }
+
+                let object = words.get(1)?;
+
+                if !matches!(object.as_str(), "table" | "view" | "sequence") {
+                    return None;
+                }
+
+                let at = find_top_level_keyword(sql, object)? + object.len();
+                skip_keywords(sql, at, &["if", "exists", "only"])
+            }
+        };
+
+        let name = &sql[at..name_end(sql, at)];
+
+        if name.is_empty() || is_qualified(name) {
+            return None;
+        }
+
+        Some(insert(sql, at, &self.schema, "."))
+    }
+}
+
+/// Creates views with `security_invoker` set, so that they check permissions
+/// as the user querying them rather than as their owner.
+#[derive(Debug)]
+pub struct SecurityInvoker;
+
+impl Rewriter for SecurityInvoker {
+    fn rewrite(&self, statement: &Statement) -> Option<String> {
+        let (object, _) = created(statement)?;
+
+        if object != "view" || statement.words().any(|word| word == "security_invoker") {
+            return None;
+        }
+
+        let sql = &statement.sql;
+        let as_at = find_top_level_keyword(sql, "as")?;
+
+        // Adding to any options already given rather than giving them twice
+        match find_top_level_keyword(sql, "with").filter(|with| *with < as_at) {
+            Some(with) => {
+                let open = with + sql[with..].find('(')? + 1;
+                Some(insert(sql, open, "security_invoker = true", ", "))
+            }
+            None => Some(insert(sql, as_at, &cased(sql, "WITH (security_invoker = true) "), "")),
+        }
+    }
+}
+
+/// Explains statements changing data as they're run, with `EXPLAIN (ANALYZE,
+/// BUFFERS)`, so that rehearsals can save their plans. Batched statements are
+/// left as they are, since each batch runs a statement of its own.
+#[derive(Debug)]
+pub struct ExplainAnalyze;
+
+impl Rewriter for ExplainAnalyze {
+    fn rewrite(&self, statement: &Statement) -> Option<String> {
+        let keyword = statement.leading_keyword()?;
+
+        if !matches!(keyword.as_str(), "insert" | "update" | "delete" | "merge") || !matches!(Batch::of(statement), Ok(None)) {
+            return None;
+        }
+
+        let sql = &statement.sql;
+        let at = find_top_level_keyword(sql, &keyword)?;
+
+        Some(insert(sql, at, &cased(&sql[at..], "EXPLAIN (ANALYZE, BUFFERS) "), ""))
+    }
+}
+
+/// The kind of object a `CREATE` statement creates, eg. `materialized view`,
+/// and the byte offset just past its keyword(s).
+pub(crate) fn created(statement: &Statement) -> Option<(String, usize)> {
+    if statement.leading_keyword().as_deref() != Some("create") {
+        return None;
+    }
+
+    let sql = &statement.sql;
+    let create = find_top_level_keyword(sql, "create")?;
+    let at = skip_keywords(sql, create + "create".len(), CREATE_MODIFIERS);
+    let object = next_word(sql, at);
+
+    if object == "materialized" {
+        let at = skip_keywords(sql, at + object.len(), &[]);
+        let object = next_word(sql, at);
+
+        return Some((format!("materialized {}", object), at + object.len()));
+    }
+
+    let end = at + object.len();
+
+    Some((object, end))
+}
+
+/// The byte offset of the next word from `at`, after any of the optional
+/// keywords and the whitespace around them.
+fn skip_keywords(sql: &str, mut at: usize, optional: &[&str]) -> usize {
+    loop {
+        let start = at + (sql[at..].len() - sql[at..].trim_start().len());
+        let word = next_word(sql, start);
+
+        if word.is_empty() || !optional.contains(&word.as_str()) {
+            return start;
+        }
+
+        at = start + word.len();
+    }
+}
+
+/// The lowercased word starting at or after `at`, ignoring whitespace.
+fn next_word(sql: &str, at: usize) -> String {
+    sql[at..]
+        .trim_start()
+        .chars()
+        .take_while(|c| c.is_alphanumeric() || *c == '_')
+        .collect::<String>()
+        .to_lowercase()
+}
+
+/// The byte offset just past the name starting at `at`, which may be quoted.
+fn name_end(sql: &str, at: usize) -> usize {
+    let mut quoted = false;
+
+    for (i, c) in sql[at..].char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            c if !quoted && (c.is_whitespace() || matches!(c, '(' | ')' | ',' | ';')) => return at + i,
+            _ => {}
+        }
+    }
+
+    sql.len()
+}
+
+/// Whether the name includes a schema, ie. has a dot outside of any quotes.
+fn is_qualified(name: &str) -> bool {
+    let mut quoted = false;
+
+    name.chars().any(|c| {
+        if c == '"' {
+            quoted = !quoted;
+        }
+
+        !quoted && c == '.'
+    })
+}
+
+/// The SQL with the text and then the separator inserted at the offset.
+fn insert(sql: &str, at: usize, text: &str, separator: &str) -> String {
+    format!("{}{}{}{}", &sql[..at], text, separator, &sql[at..])
+}
+
+/// The keywords in lowercase if the statement is written in lowercase.
+pub(crate) fn cased(sql: &str, keywords: &str) -> String {
+    let leading = sql.trim_start().split(|c: char| !c.is_alphanumeric()).next().unwrap_or_default();
+
+    if leading.chars().any(char::is_lowercase) {
+        keywords.to_lowercase()
+    } else {
+        keywords.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::statements::split;
+
+    fn rewrite(rewriter: &impl Rewriter, sql: &str) -> Option<String> {
+        rewriter.rewrite(&split(sql)[0])
+    }
+
+    #[test]
+    fn if_not_exists_is_added_where_missing() {
+        assert_eq!(rewrite(&IfNotExists, "create table users (id int)").unwrap(), "create table if not exists users (id int)");
+        assert_eq!(
+            rewrite(&IfNotExists, "CREATE UNIQUE INDEX CONCURRENTLY users_email ON users (email)").unwrap(),
+            "CREATE UNIQUE INDEX CONCURRENTLY IF NOT EXISTS users_email ON users (email)",
+        );
+        assert_eq!(
+            rewrite(&IfNotExists, "CREATE MATERIALIZED VIEW totals AS SELECT 1").unwrap(),
+            "CREATE MATERIALIZED VIEW IF NOT EXISTS totals AS SELECT 1",
+        );
+        assert_eq!(rewrite(&IfNotExists, "CREATE TABLE IF NOT EXISTS users (id int)"), None);
+        assert_eq!(rewrite(&IfNotExists, "CREATE INDEX ON users (email)"), None);
+        assert_eq!(rewrite(&IfNotExists, "CREATE VIEW active AS SELECT 1"), None);
+    }
+
+    #[test]
+    fn qualify_schema_leaves_qualified_names() {
+        let qualify = QualifySchema { schema: "app".to_string() };
+
+        assert_eq!(rewrite(&qualify, "CREATE TABLE users(id int)").unwrap(), "CREATE TABLE app.users(id int)");
+        assert_eq!(
+            rewrite(&qualify, "create table if not exists \"Users\" (id int)").unwrap(),
+            "create table if not exists app.\"Users\" (id int)",
+        );
+        assert_eq!(
+            rewrite(&qualify, "ALTER TABLE IF EXISTS ONLY users ADD COLUMN name text").unwrap(),
+            "ALTER TABLE IF EXISTS ONLY app.users ADD COLUMN name text",
+        );
+        assert_eq!(
+            rewrite(&qualify, "CREATE INDEX users_name ON users (name)").unwrap(),
+            "CREATE INDEX users_name ON app.users (name)",
+        );
+        assert_eq!(rewrite(&qualify, "CREATE TABLE public.users (id int)"), None);
+        assert_eq!(rewrite(&qualify, "CREATE TABLE \"a.b\".users (id int)"), None);
+        assert_eq!(rewrite(&qualify, "INSERT INTO users VALUES (1)"), None);
+    }
+
+    #[test]
+    fn security_invoker_is_added_to_views() {
+        assert_eq!(
+            rewrite(&SecurityInvoker, "CREATE OR REPLACE VIEW active AS SELECT * FROM users").unwrap(),
+            "CREATE OR REPLACE VIEW active WITH (security_invoker = true) AS SELECT * FROM users",
+        );
+        assert_eq!(
+            rewrite(&SecurityInvoker, "create view active with (check_option = local) as select 1").unwrap(),
+            "create view active with (security_invoker = true, check_option = local) as select 1",
+        );
+        assert_eq!(rewrite(&SecurityInvoker, "CREATE VIEW v WITH (security_invoker) AS SELECT 1"), None);
+        assert_eq!(rewrite(&SecurityInvoker, "CREATE MATERIALIZED VIEW v AS SELECT 1"), None);
+    }
+
+    #[test]
+    fn ignore_duplicate_wraps_marked_statements() {
+        assert_eq!(
+            rewrite(&IgnoreDuplicate, "-- jrny:ignore-duplicate\nCREATE TYPE mood AS ENUM ('happy', 'sad')").unwrap(),
+            "-- jrny:ignore-duplicate\nDO $$\nBEGIN\nCREATE TYPE mood AS ENUM ('happy', 'sad');\n\
+             EXCEPTION WHEN duplicate_object THEN NULL;\nEND $$",
+        );
+        assert_eq!(
+            rewrite(&IgnoreDuplicate, "-- jrny:ignore-duplicate\ncreate role reporting -- read only\n").unwrap(),
+            "-- jrny:ignore-duplicate\ndo $$\nbegin\ncreate role reporting -- read only\n;\n\
+             exception when duplicate_object then null;\nend $$",
+        );
+        assert!(rewrite(&IgnoreDuplicate, "-- jrny:ignore-duplicate\nCREATE FUNCTION f() RETURNS int AS $$ SELECT 1 $$ LANGUAGE sql")
+            .unwrap()
+            .starts_with("-- jrny:ignore-duplicate\nDO $jrny1$\n"));
+        assert_eq!(rewrite(&IgnoreDuplicate, "CREATE TYPE mood AS ENUM ('happy', 'sad')"), None);
+    }
+
+    #[test]
+    fn explain_analyze_wraps_data_changes() {
+        assert_eq!(
+            rewrite(&ExplainAnalyze, "-- Backfill\nupdate users set active = true where id < 10").unwrap(),
+            "-- Backfill\nexplain (analyze, buffers) update users set active = true where id < 10",
+        );
+        assert_eq!(
+            rewrite(&ExplainAnalyze, "DELETE FROM users WHERE active").unwrap(),
+            "EXPLAIN (ANALYZE, BUFFERS) DELETE FROM users WHERE active",
+        );
+        assert_eq!(rewrite(&ExplainAnalyze, "-- jrny:batch size=100\nupdate users set active = true"), None);
+        assert_eq!(rewrite(&ExplainAnalyze, "create table users (id int)"), None);
+        assert_eq!(rewrite(&ExplainAnalyze, "select * from users"), None);
+    }
+}
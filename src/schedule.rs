@@ -0,0 +1,89 @@
+//! When embark is to start applying revisions, given by `--at`, so that a run
+//! launched eg. from an afternoon deploy pipeline waits for the low-traffic
+//! window instead. Either a time of day, taken as the next time it comes
+//! round, or a date and time, each in local time unless given in UTC:
+//!
+//! ```text
+//! 02:00
+//! 02:00Z
+//! 2021-11-25T02:00
+//! 2021-11-25T02:00:00+01:00
+//! ```
+use chrono::{DateTime, Duration, NaiveDateTime, NaiveTime, TimeZone, Utc};
+
+/// The moment given, as of the moment `now`, in whose time zone those without
+/// one are taken to be.
+pub fn start_time<Tz: TimeZone>(s: &str, now: &DateTime<Tz>) -> Result<DateTime<Utc>, String> {
+    let text = s.trim();
+
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(text) {
+        return Ok(datetime.with_timezone(&Utc));
+    }
+
+    let (text, utc) = match text.strip_suffix('Z').or_else(|| text.strip_suffix(" UTC")) {
+        Some(text) => (text.trim_end(), true),
+        None => (text, false),
+    };
+    let zone = now.timezone();
+    let moment = |naive: NaiveDateTime| match utc {
+        true => Ok(Utc.from_utc_datetime(&naive)),
+        // Times skipped when the clocks go forward don't exist at all
+        false => zone
+            .from_local_datetime(&naive)
+            .earliest()
+            .map(|datetime| datetime.with_timezone(&Utc))
+            .ok_or_else(|| format!("`{}` doesn't exist in local time", s)),
+    };
+
+    for format in ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%dT%H:%M", "%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M"] {
+        if let Ok(naive) = NaiveDateTime::parse_from_str(text, format) {
+            return moment(naive);
+        }
+    }
+
+    let time = NaiveTime::parse_from_str(text, "%H:%M:%S")
+        .or_else(|_| NaiveTime::parse_from_str(text, "%H:%M"))
+        .map_err(|_| format!("invalid time `{}`, expected eg. `02:00`, `02:00Z`, or `2021-11-25T02:00`", s))?;
+    let today = if utc { now.naive_utc().date() } else { now.naive_local().date() };
+    let start = moment(today.and_time(time))?;
+
+    if start >= now.with_timezone(&Utc) {
+        Ok(start)
+    } else {
+        moment((today + Duration::days(1)).and_time(time))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::FixedOffset;
+
+    use super::*;
+
+    #[test]
+    fn times_of_day_are_next_in_local_time_or_utc() {
+        let zone = FixedOffset::east(3600);
+        let now = zone.ymd(2021, 11, 25).and_hms(15, 30, 0);
+        let utc = |d, h, m| Utc.ymd(2021, 11, d).and_hms(h, m, 0);
+
+        assert_eq!(start_time("02:00", &now), Ok(utc(26, 1, 0)));
+        assert_eq!(start_time("16:00", &now), Ok(utc(25, 15, 0)));
+        assert_eq!(start_time("15:30", &now), Ok(utc(25, 14, 30)));
+        assert_eq!(start_time("14:45Z", &now), Ok(utc(25, 14, 45)));
+        assert_eq!(start_time("14:15 UTC", &now), Ok(utc(26, 14, 15)));
+        assert!(start_time("25:00", &now).is_err());
+        assert!(start_time("tonight", &now).is_err());
+    }
+
+    #[test]
+    fn datetimes_are_taken_as_given() {
+        let now = FixedOffset::east(3600).ymd(2021, 11, 25).and_hms(15, 30, 0);
+        let utc = |d, h, m| Utc.ymd(2021, 11, d).and_hms(h, m, 0);
+
+        assert_eq!(start_time("2021-11-26T02:00", &now), Ok(utc(26, 1, 0)));
+        assert_eq!(start_time("2021-11-26 02:00:00Z", &now), Ok(utc(26, 2, 0)));
+        assert_eq!(start_time("2021-11-26T02:00:00-05:00", &now), Ok(utc(26, 7, 0)));
+        // Already past, so not waited for at all
+        assert_eq!(start_time("2021-11-24T02:00", &now), Ok(utc(24, 1, 0)));
+    }
+}
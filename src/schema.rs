@@ -0,0 +1,446 @@
+//! Introspection of a live database's schema, as a list of objects each with
+//! a definition in a canonical form, so that two databases can be compared,
+//! eg. staging against production with `jrny diff`, or a database against a
+//! snapshot of its schema committed alongside the revisions.
+//!
+//! Schemas, extensions, tables and their columns, constraints, indexes,
+//! triggers, and row-level security policies, views, sequences, functions,
+//! and types are introspected, outside of the system schemas and other than
+//! those belonging to extensions. Owners and privileges are left out, as
+//! they usually differ between environments by design.
+use std::collections::BTreeMap;
+
+use postgres::GenericClient;
+
+use crate::Result;
+
+/// Every object introspected, with its kind, qualified name, and definition.
+const OBJECTS: &str = "
+WITH namespaces AS (
+    SELECT n.oid, n.nspname
+    FROM pg_namespace n
+    WHERE n.nspname NOT IN ('pg_catalog', 'information_schema')
+        AND n.nspname NOT LIKE 'pg\\_toast%'
+        AND n.nspname NOT LIKE 'pg\\_temp\\_%'
+        AND NOT EXISTS (SELECT 1 FROM pg_depend e WHERE e.objid = n.oid AND e.deptype = 'e')
+),
+relations AS (
+    SELECT c.oid, c.relname, c.relkind, c.relpersistence, n.nspname, format('%I.%I', n.nspname, c.relname) AS name
+    FROM pg_class c JOIN namespaces n ON n.oid = c.relnamespace
+    WHERE NOT EXISTS (SELECT 1 FROM pg_depend e WHERE e.objid = c.oid AND e.deptype = 'e')
+)
+SELECT 'schema' AS kind, format('%I', nspname) AS name, '' AS definition
+FROM namespaces
+UNION ALL
+SELECT 'extension', format('%I', extname), 'version ' || extversion
+FROM pg_extension
+WHERE extname <> 'plpgsql'
+UNION ALL
+SELECT
+    'table',
+    name,
+    concat_ws(
+        ' ',
+        CASE relpersistence WHEN 'u' THEN 'unlogged' END,
+        CASE relkind WHEN 'f' THEN 'foreign' END,
+        CASE relkind WHEN 'p' THEN 'partitioned by ' || pg_get_partkeydef(oid) END
+    )
+FROM relations
+WHERE relkind IN ('r', 'p', 'f')
+UNION ALL
+SELECT
+    'column',
+    r.name || '.' || quote_ident(a.attname),
+    concat_ws(
+        ' ',
+        format_type(a.atttypid, a.atttypmod),
+        CASE WHEN a.attnotnull THEN 'not null' END,
+        CASE a.attidentity WHEN 'a' THEN 'generated always as identity' WHEN 'd' THEN 'generated by default as identity' END,
+        CASE WHEN a.attgenerated = 's' THEN 'generated always as (' || pg_get_expr(d.adbin, d.adrelid) || ') stored' END,
+        CASE WHEN a.attgenerated = '' AND d.adbin IS NOT NULL THEN 'default ' || pg_get_expr(d.adbin, d.adrelid) END
+    )
+FROM relations r
+JOIN pg_attribute a ON a.attrelid = r.oid AND a.attnum > 0 AND NOT a.attisdropped
+LEFT JOIN pg_attrdef d ON d.adrelid = a.attrelid AND d.adnum = a.attnum
+WHERE r.relkind IN ('r', 'p', 'f')
+UNION ALL
+SELECT 'constraint', r.name || '.' || quote_ident(c.conname), pg_get_constraintdef(c.oid)
+FROM pg_constraint c JOIN relations r ON r.oid = c.conrelid
+UNION ALL
+SELECT 'index', format('%I.%I', r.nspname, i.relname), pg_get_indexdef(x.indexrelid)
+FROM pg_index x
+JOIN relations r ON r.oid = x.indrelid
+JOIN pg_class i ON i.oid = x.indexrelid
+WHERE NOT EXISTS (SELECT 1 FROM pg_constraint c WHERE c.conindid = x.indexrelid AND c.contype IN ('p', 'u', 'x'))
+UNION ALL
+SELECT 'trigger', r.name || '.' || quote_ident(t.tgname), pg_get_triggerdef(t.oid)
+FROM pg_trigger t JOIN relations r ON r.oid = t.tgrelid
+WHERE NOT t.tgisinternal
+UNION ALL
+SELECT
+    'policy',
+    r.name || '.' || quote_ident(p.polname),
+    concat_ws(
+        ' ',
+        CASE WHEN p.polpermissive THEN 'permissive' ELSE 'restrictive' END,
+        'for',
+        CASE p.polcmd WHEN 'r' THEN 'select' WHEN 'a' THEN 'insert' WHEN 'w' THEN 'update' WHEN 'd' THEN 'delete' ELSE 'all' END,
+        'using (' || pg_get_expr(p.polqual, p.polrelid) || ')',
+        'with check (' || pg_get_expr(p.polwithcheck, p.polrelid) || ')'
+    )
+FROM pg_policy p JOIN relations r ON r.oid = p.polrelid
+UNION ALL
+SELECT CASE relkind WHEN 'm' THEN 'materialized view' ELSE 'view' END, name, pg_get_viewdef(oid, true)
+FROM relations
+WHERE relkind IN ('v', 'm')
+UNION ALL
+SELECT
+    'sequence',
+    r.name,
+    format(
+        '%s start %s increment %s minvalue %s maxvalue %s%s',
+        format_type(s.seqtypid, NULL),
+        s.seqstart,
+        s.seqincrement,
+        s.seqmin,
+        s.seqmax,
+        CASE WHEN s.seqcycle THEN ' cycle' ELSE '' END
+    )
+FROM pg_sequence s JOIN relations r ON r.oid = s.seqrelid
+UNION ALL
+SELECT
+    CASE p.prokind WHEN 'p' THEN 'procedure' ELSE 'function' END,
+    format('%I.%I(%s)', n.nspname, p.proname, pg_get_function_identity_arguments(p.oid)),
+    pg_get_functiondef(p.oid)
+FROM pg_proc p JOIN namespaces n ON n.oid = p.pronamespace
+WHERE p.prokind IN ('f', 'p')
+    AND NOT EXISTS (SELECT 1 FROM pg_depend e WHERE e.objid = p.oid AND e.deptype = 'e')
+UNION ALL
+SELECT
+    'type',
+    format('%I.%I', n.nspname, t.typname),
+    CASE t.typtype
+        WHEN 'e' THEN 'enum (' || (
+            SELECT string_agg(quote_literal(e.enumlabel), ', ' ORDER BY e.enumsortorder)
+            FROM pg_enum e
+            WHERE e.enumtypid = t.oid
+        ) || ')'
+        WHEN 'd' THEN concat_ws(
+            ' ',
+            'domain ' || format_type(t.typbasetype, t.typtypmod),
+            CASE WHEN t.typnotnull THEN 'not null' END,
+            'default ' || t.typdefault,
+            (
+                SELECT string_agg(pg_get_constraintdef(c.oid), ' ' ORDER BY c.conname)
+                FROM pg_constraint c
+                WHERE c.contypid = t.oid
+            )
+        )
+        WHEN 'r' THEN 'range of ' || (SELECT format_type(r.rngsubtype, NULL) FROM pg_range r WHERE r.rngtypid = t.oid)
+        ELSE 'composite (' || (
+            SELECT string_agg(quote_ident(a.attname) || ' ' || format_type(a.atttypid, a.atttypmod), ', ' ORDER BY a.attnum)
+            FROM pg_attribute a
+            WHERE a.attrelid = t.typrelid AND a.attnum > 0 AND NOT a.attisdropped
+        ) || ')'
+    END
+FROM pg_type t JOIN namespaces n ON n.oid = t.typnamespace
+WHERE (t.typtype IN ('e', 'd', 'r')
+        OR (t.typtype = 'c' AND EXISTS (SELECT 1 FROM pg_class c WHERE c.oid = t.typrelid AND c.relkind = 'c')))
+    AND NOT EXISTS (SELECT 1 FROM pg_depend e WHERE e.objid = t.oid AND e.deptype = 'e')
+";
+
+/// The default name of the snapshot file
+pub const SNAPSHOT: &str = "jrny-schema.txt";
+
+/// The kinds of object introspected
+const KINDS: &[&str] = &[
+    "column",
+    "constraint",
+    "extension",
+    "function",
+    "index",
+    "materialized view",
+    "policy",
+    "procedure",
+    "schema",
+    "sequence",
+    "table",
+    "trigger",
+    "type",
+    "view",
+];
+
+/// Heads each snapshot, so that it's recognized as one
+const SNAPSHOT_HEADER: &str = "-- jrny schema snapshot";
+
+/// What each line of an object's definition is indented by in a snapshot
+const SNAPSHOT_INDENT: &str = "    ";
+
+/// An object in the database's schema.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SchemaObject {
+    /// eg. `table`, `column`, or `index`
+    pub kind: String,
+    /// The qualified name of the object, eg. `public.users.email` for a
+    /// column
+    pub name: String,
+    /// What the object is, in a canonical form, eg. `text not null` for a
+    /// column
+    pub definition: String,
+}
+
+/// How an object differs between two databases.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Difference<'a> {
+    /// The object is only in the database compared from
+    OnlyInFrom(&'a SchemaObject),
+    /// The object is only in the database compared to
+    OnlyInTo(&'a SchemaObject),
+    /// The object is in both but defined differently, as in each in turn
+    Changed(&'a SchemaObject, &'a SchemaObject),
+}
+
+/// Introspects every object in the database's schema, ordered by kind and
+/// then name. Whitespace at the end of each line of a definition is left
+/// out, as are blank lines at the end.
+pub fn objects(client: &mut impl GenericClient) -> Result<Vec<SchemaObject>> {
+    let mut objects: Vec<_> = client
+        .query(OBJECTS, &[])?
+        .iter()
+        .map(|r| {
+            let definition = r.get::<_, Option<String>>("definition").unwrap_or_default();
+            let lines: Vec<_> = definition.lines().map(str::trim_end).collect();
+
+            SchemaObject {
+                kind: r.get("kind"),
+                name: r.get("name"),
+                definition: lines.join("\n").trim_end().to_string(),
+            }
+        })
+        .collect();
+
+    // Sorted here rather than by the database, whose collation may differ
+    objects.sort_by(|a, b| (&a.kind, &a.name).cmp(&(&b.kind, &b.name)));
+
+    Ok(objects)
+}
+
+/// Leaves out jrny's own tracking tables, given the schema and name of the
+/// table tracking revisions, along with everything belonging to them.
+pub fn without_tracking_tables(objects: Vec<SchemaObject>, schema: &str, table: &str) -> Vec<SchemaObject> {
+    let tracking = format!("{}.{}", schema, table);
+
+    objects
+        .into_iter()
+        .filter(|object| {
+            let rest = match object.name.strip_prefix(&tracking) {
+                Some(rest) => rest,
+                None => return true,
+            };
+
+            // eg. `jrny_revision_repeatable`, or the index of `jrny_revision`
+            !(rest.is_empty() || rest.starts_with('.') || rest.starts_with('_'))
+        })
+        .collect()
+}
+
+/// Renders the objects as a snapshot, with a block for each in order, headed
+/// by its kind and name and followed by its definition indented. Being sorted
+/// and one object to a block, snapshots change only where the schema does.
+pub fn render_snapshot(objects: &[SchemaObject]) -> String {
+    let mut rendered = format!("{}\n", SNAPSHOT_HEADER);
+
+    for object in objects {
+        rendered.push_str(&format!("\n{} {}\n", object.kind, object.name));
+
+        for line in object.definition.lines() {
+            if line.is_empty() {
+                rendered.push('\n');
+            } else {
+                rendered.push_str(&format!("{}{}\n", SNAPSHOT_INDENT, line));
+            }
+        }
+    }
+
+    rendered
+}
+
+/// Reads the objects back from a snapshot.
+pub fn parse_snapshot(contents: &str) -> std::result::Result<Vec<SchemaObject>, String> {
+    let mut lines = contents.lines().enumerate();
+
+    if lines.next().map(|(_, line)| line.trim_end()) != Some(SNAPSHOT_HEADER) {
+        return Err(format!("expected it to start with `{}`", SNAPSHOT_HEADER));
+    }
+
+    let mut objects: Vec<SchemaObject> = vec![];
+
+    for (i, line) in lines {
+        if let Some(definition) = line.strip_prefix(SNAPSHOT_INDENT) {
+            let object = objects.last_mut().ok_or(format!("expected an object before line {}", i + 1))?;
+
+            object.definition.push_str(&format!("\n{}", definition));
+            continue;
+        }
+        if line.trim().is_empty() {
+            if let Some(object) = objects.last_mut() {
+                object.definition.push('\n');
+            }
+            continue;
+        }
+
+        let (kind, name) = KINDS
+            .iter()
+            .find_map(|kind| Some((*kind, line.strip_prefix(kind)?.strip_prefix(' ')?)))
+            .ok_or(format!("expected an object's kind and name on line {}", i + 1))?;
+
+        objects.push(SchemaObject {
+            kind: kind.to_string(),
+            name: name.to_string(),
+            definition: String::new(),
+        });
+    }
+
+    for object in &mut objects {
+        // Each definition was started with a line break, and followed by the
+        // blank line before the next
+        object.definition = object.definition.trim_start_matches('\n').trim_end().to_string();
+    }
+
+    Ok(objects)
+}
+
+/// The differences between the objects of two databases, ordered by kind
+/// and then name. The columns, constraints, triggers, and policies of tables
+/// only in one of them go without saying, so are left out.
+pub fn diff<'a>(from: &'a [SchemaObject], to: &'a [SchemaObject]) -> Vec<Difference<'a>> {
+    let key = |object: &'a SchemaObject| ((object.kind.as_str(), object.name.as_str()), object);
+    let from: BTreeMap<_, _> = from.iter().map(key).collect();
+    let to: BTreeMap<_, _> = to.iter().map(key).collect();
+
+    let mut keys: Vec<_> = from.keys().chain(to.keys()).collect();
+    keys.sort();
+    keys.dedup();
+
+    let mut differences: Vec<_> = keys
+        .into_iter()
+        .filter_map(|key| match (from.get(key), to.get(key)) {
+            (Some(from), Some(to)) if from.definition != to.definition => Some(Difference::Changed(from, to)),
+            (Some(from), None) => Some(Difference::OnlyInFrom(from)),
+            (None, Some(to)) => Some(Difference::OnlyInTo(to)),
+            _ => None,
+        })
+        .collect();
+
+    let only_in = |difference: &Difference<'a>| match difference {
+        Difference::OnlyInFrom(object) => Some((true, *object)),
+        Difference::OnlyInTo(object) => Some((false, *object)),
+        Difference::Changed(_, _) => None,
+    };
+    let tables: Vec<_> = differences
+        .iter()
+        .filter_map(only_in)
+        .filter(|(_, object)| object.kind == "table")
+        .map(|(side, object)| (side, format!("{}.", object.name)))
+        .collect();
+
+    differences.retain(|difference| match only_in(difference) {
+        Some((side, object)) if object.kind != "table" => {
+            !tables.iter().any(|(table_side, prefix)| *table_side == side && object.name.starts_with(prefix.as_str()))
+        }
+        _ => true,
+    });
+
+    differences
+}
+
+/// The lines of each definition that aren't in the other, for showing how
+/// an object changed without repeating what didn't.
+pub fn changed_lines<'a>(from: &'a str, to: &'a str) -> (Vec<&'a str>, Vec<&'a str>) {
+    let (from, to): (Vec<_>, Vec<_>) = (from.lines().collect(), to.lines().collect());
+
+    (
+        from.iter().copied().filter(|line| !to.contains(line)).collect(),
+        to.iter().copied().filter(|line| !from.contains(line)).collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn object(kind: &str, name: &str, definition: &str) -> SchemaObject {
+        SchemaObject {
+            kind: kind.to_string(),
+            name: name.to_string(),
+            definition: definition.to_string(),
+        }
+    }
+
+    #[test]
+    fn differences_are_found_by_kind_and_name() {
+        let from = [
+            object("column", "public.users.email", "text"),
+            object("column", "public.users.id", "integer not null"),
+            object("index", "public.users_email_idx", "CREATE INDEX ..."),
+        ];
+        let to = [
+            object("column", "public.orders.id", "integer not null"),
+            object("column", "public.users.email", "text not null"),
+            object("column", "public.users.id", "integer not null"),
+            object("table", "public.orders", ""),
+        ];
+
+        assert_eq!(
+            diff(&from, &to),
+            vec![
+                Difference::Changed(&from[0], &to[1]),
+                Difference::OnlyInFrom(&from[2]),
+                Difference::OnlyInTo(&to[3]),
+            ],
+        );
+        assert!(diff(&from, &from).is_empty());
+    }
+
+    #[test]
+    fn snapshots_round_trip() {
+        let objects = [
+            object("column", "public.users.email", "text not null"),
+            object("function", "public.f()", "CREATE FUNCTION public.f()\n\nAS $$\n  SELECT 1\n$$"),
+            object("materialized view", "public.totals", " SELECT 1;"),
+            object("schema", "public", ""),
+        ];
+        let rendered = render_snapshot(&objects);
+
+        assert!(rendered.contains("\nfunction public.f()\n    CREATE FUNCTION public.f()\n\n    AS $$\n      SELECT 1\n"));
+        assert_eq!(parse_snapshot(&rendered), Ok(objects.to_vec()));
+        assert!(parse_snapshot("table public.users\n").is_err());
+        assert!(parse_snapshot(&format!("{}\nrelation public.users\n", SNAPSHOT_HEADER)).is_err());
+    }
+
+    #[test]
+    fn tracking_tables_are_left_out() {
+        let objects = vec![
+            object("table", "public.jrny_revision", ""),
+            object("column", "public.jrny_revision.id", "integer not null"),
+            object("table", "public.jrny_revision_repeatable", ""),
+            object("table", "public.jrny_revisions_archive", ""),
+            object("table", "app.jrny_revision", ""),
+        ];
+
+        let kept = without_tracking_tables(objects, "public", "jrny_revision");
+
+        assert_eq!(
+            kept.iter().map(|object| object.name.as_str()).collect::<Vec<_>>(),
+            vec!["public.jrny_revisions_archive", "app.jrny_revision"],
+        );
+    }
+
+    #[test]
+    fn changed_lines_leave_out_those_in_both() {
+        let from = "BEGIN\n  RETURN 1;\nEND";
+        let to = "BEGIN\n  RETURN 2;\nEND";
+
+        assert_eq!(changed_lines(from, to), (vec!["  RETURN 1;"], vec!["  RETURN 2;"]));
+    }
+}
@@ -0,0 +1,313 @@
+//! Fetching the database password from a secrets manager by reference, eg.
+//! `password_ref = "vault:secret/data/db#password"`, rather than keeping it
+//! in the environment file.
+//!
+//! Each backend is behind its own feature (`vault`, `aws-secrets`, and
+//! `gcp-secrets`) and, as with other integrations, is reached through a
+//! command-line client rather than an SDK: `curl` for Vault, and the `aws`
+//! or `gcloud` CLIs otherwise, relying on whatever credentials they're
+//! configured with. The same goes for generating IAM tokens for RDS, behind
+//! the `aws-auth` feature.
+#[cfg(any(feature = "aws-secrets", feature = "gcp-secrets", feature = "aws-auth"))]
+use std::process::Command;
+use std::{
+    collections::HashMap,
+    str::FromStr,
+    sync::{Mutex, OnceLock},
+};
+
+use crate::{Error, Result};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backend {
+    Vault,
+    AwsSecretsManager,
+    GcpSecretManager,
+}
+
+impl Backend {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Vault => "vault",
+            Self::AwsSecretsManager => "aws",
+            Self::GcpSecretManager => "gcp",
+        }
+    }
+
+    /// The feature that must be enabled to use the backend.
+    pub fn feature(&self) -> &'static str {
+        match self {
+            Self::Vault => "vault",
+            Self::AwsSecretsManager => "aws-secrets",
+            Self::GcpSecretManager => "gcp-secrets",
+        }
+    }
+}
+
+/// A reference to a secret, given as `[backend]:[path]` optionally followed
+/// by `#[key]` to pick a single value from a secret holding several.
+#[derive(Debug, PartialEq, Eq)]
+pub struct SecretRef {
+    pub backend: Backend,
+    /// The path in Vault, or the name, ARN, or resource name of the secret
+    pub path: String,
+    pub key: Option<String>,
+}
+
+impl FromStr for SecretRef {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || Error::SecretRefInvalid(s.to_string());
+        let (backend, rest) = s.split_once(':').ok_or_else(invalid)?;
+
+        let backend = match backend {
+            "vault" => Backend::Vault,
+            "aws" => Backend::AwsSecretsManager,
+            "gcp" => Backend::GcpSecretManager,
+            _ => return Err(invalid()),
+        };
+
+        let (path, key) = match rest.rsplit_once('#') {
+            Some((path, key)) => (path, Some(key.to_string())),
+            None => (rest, None),
+        };
+
+        // Vault's values are always keyed, unlike other backends' secrets
+        if path.is_empty() || key.as_deref() == Some("") || (backend == Backend::Vault && key.is_none()) {
+            return Err(invalid());
+        }
+
+        Ok(Self { backend, path: path.to_string(), key })
+    }
+}
+
+/// Fetches the referenced secret, only once for however many connections are
+/// made during the run.
+pub fn resolve(reference: &str) -> Result<String> {
+    static RESOLVED: OnceLock<Mutex<HashMap<String, String>>> = OnceLock::new();
+
+    let mut resolved = RESOLVED.get_or_init(Default::default).lock().unwrap_or_else(|e| e.into_inner());
+
+    if let Some(value) = resolved.get(reference) {
+        return Ok(value.clone());
+    }
+
+    let secret: SecretRef = reference.parse()?;
+    let value = fetch(&secret).map_err(|reason| Error::SecretFetchFailed(reference.to_string(), reason))?;
+
+    resolved.insert(reference.to_string(), value.clone());
+
+    Ok(value)
+}
+
+fn fetch(secret: &SecretRef) -> std::result::Result<String, String> {
+    match secret.backend {
+        #[cfg(feature = "vault")]
+        Backend::Vault => vault::fetch(secret),
+        #[cfg(feature = "aws-secrets")]
+        Backend::AwsSecretsManager => {
+            let output = run(Command::new("aws").args([
+                "secretsmanager",
+                "get-secret-value",
+                "--secret-id",
+                &secret.path,
+                "--query",
+                "SecretString",
+                "--output",
+                "text",
+            ]))?;
+
+            select_key(output.trim_end_matches('\n'), secret.key.as_deref())
+        }
+        #[cfg(feature = "gcp-secrets")]
+        Backend::GcpSecretManager => {
+            // Resource names may leave out the version, meaning the latest
+            let version = if secret.path.contains("/versions/") {
+                secret.path.clone()
+            } else {
+                format!("{}/versions/latest", secret.path)
+            };
+
+            let output = run(Command::new("gcloud").args(["secrets", "versions", "access", &version]))?;
+
+            select_key(&output, secret.key.as_deref())
+        }
+        #[allow(unreachable_patterns)]
+        backend => Err(format!(
+            "jrny was built without the `{}` backend - rebuild with `--features {}`",
+            backend.as_str(),
+            backend.feature(),
+        )),
+    }
+}
+
+/// Generates a token to connect to RDS or Aurora as the URL's user with IAM
+/// authentication, which is valid for 15 minutes.
+#[cfg(feature = "aws-auth")]
+pub fn rds_auth_token(config: &postgres::Config, region: Option<&str>) -> Result<String> {
+    let failed = |reason: &str| Error::AuthTokenFailed(reason.to_string());
+
+    let host = match config.get_hosts().first() {
+        Some(postgres::config::Host::Tcp(host)) => host.clone(),
+        _ => return Err(failed("the database URL must have a host name")),
+    };
+    let port = config.get_ports().first().copied().unwrap_or(5432).to_string();
+    let user = config.get_user().ok_or_else(|| failed("the database URL must have a user"))?;
+
+    let mut cmd = Command::new("aws");
+
+    cmd.args(["rds", "generate-db-auth-token", "--hostname", &host, "--port", &port, "--username", user]);
+
+    if let Some(region) = region {
+        cmd.args(["--region", region]);
+    }
+
+    run(&mut cmd).map(|token| token.trim().to_string()).map_err(Error::AuthTokenFailed)
+}
+
+#[cfg(not(feature = "aws-auth"))]
+pub fn rds_auth_token(_config: &postgres::Config, _region: Option<&str>) -> Result<String> {
+    Err(Error::AuthTokenFailed(
+        "jrny was built without IAM authentication - rebuild with `--features aws-auth`".to_string(),
+    ))
+}
+
+/// Runs a backend's CLI, returning what it wrote to stdout.
+#[cfg(any(feature = "aws-secrets", feature = "gcp-secrets", feature = "aws-auth"))]
+fn run(cmd: &mut Command) -> std::result::Result<String, String> {
+    let program = cmd.get_program().to_string_lossy().to_string();
+    let output = cmd.output().map_err(|e| format!("could not run {}: {}", program, e))?;
+
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// The secret itself, or given a key, that key's value from the secret as
+/// a JSON object, eg. as AWS stores database credentials.
+#[cfg(any(feature = "aws-secrets", feature = "gcp-secrets"))]
+fn select_key(secret: &str, key: Option<&str>) -> std::result::Result<String, String> {
+    let key = match key {
+        Some(key) => key,
+        None => return Ok(secret.to_string()),
+    };
+
+    let json = crate::json::Json::parse(secret).map_err(|e| format!("expected a JSON object to pick `{}` from: {}", key, e))?;
+
+    json.get(key)
+        .and_then(|value| value.as_str())
+        .map(str::to_string)
+        .ok_or(format!("no string `{}` in secret", key))
+}
+
+/// Reads secrets over Vault's HTTP API, using `VAULT_ADDR` and either
+/// `VAULT_TOKEN` or the token saved by `vault login`, as the Vault CLI does.
+#[cfg(feature = "vault")]
+mod vault {
+    use std::{env, fs, thread, time::Duration};
+
+    use log::warn;
+
+    use super::SecretRef;
+    use crate::{http, json::Json};
+
+    pub(super) fn fetch(secret: &SecretRef) -> Result<String, String> {
+        let addr = env::var("VAULT_ADDR").map_err(|_| "`VAULT_ADDR` must be set".to_string())?;
+        let addr = addr.trim_end_matches('/').to_string();
+        let token = token()?;
+        let url = format!("{}/v1/{}", addr, secret.path.trim_start_matches('/'));
+
+        let body = http::send_with_headers("GET", &url, &[("X-Vault-Token", &token)], "").map_err(|e| e.to_string())?;
+        let json = Json::parse(&body).map_err(|e| format!("unexpected response from Vault: {}", e))?;
+        let key = secret.key.as_deref().unwrap_or_default();
+
+        // Version 2 of the KV engine nests values a level deeper than others
+        let data = json.get("data");
+        let value = data
+            .and_then(|data| data.get("data"))
+            .and_then(|data| data.get(key))
+            .or_else(|| data.and_then(|data| data.get(key)))
+            .and_then(Json::as_str)
+            .ok_or(format!("no string `{}` in secret", key))?;
+
+        let lease_id = json.get("lease_id").and_then(Json::as_str).filter(|id| !id.is_empty());
+        let lease_duration = json.get("lease_duration").and_then(Json::as_i64).filter(|secs| *secs > 0);
+
+        // Dynamic credentials expire with their lease, so keep both it and
+        // the token alive for however long the run takes
+        if let (Some(lease_id), Some(secs)) = (lease_id, lease_duration) {
+            let (lease_id, renewing) = (lease_id.to_string(), Duration::from_secs(secs as u64));
+
+            thread::spawn(move || loop {
+                thread::sleep(renewing / 2);
+
+                if let Err(e) = renew(&addr, &token, &lease_id, renewing) {
+                    warn!("Could not renew Vault lease: {}", e);
+                }
+            });
+        }
+
+        Ok(value.to_string())
+    }
+
+    fn token() -> Result<String, String> {
+        if let Ok(token) = env::var("VAULT_TOKEN") {
+            return Ok(token);
+        }
+
+        env::var("HOME")
+            .ok()
+            .and_then(|home| fs::read_to_string(format!("{}/.vault-token", home)).ok())
+            .map(|token| token.trim().to_string())
+            .ok_or_else(|| "`VAULT_TOKEN` must be set, or a token saved with `vault login`".to_string())
+    }
+
+    fn renew(addr: &str, token: &str, lease_id: &str, increment: Duration) -> crate::Result<()> {
+        let headers = [("X-Vault-Token", token), ("Content-Type", "application/json")];
+        let lease = Json::object([
+            ("lease_id", Json::from(lease_id)),
+            ("increment", Json::from(increment.as_secs() as i64)),
+        ]);
+
+        http::send_with_headers("PUT", &format!("{}/v1/auth/token/renew-self", addr), &headers, "{}")?;
+        http::send_with_headers("PUT", &format!("{}/v1/sys/leases/renew", addr), &headers, &lease.to_string())?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn secret_ref_parses_backend_path_and_key() {
+        assert_eq!(
+            "vault:secret/data/db#password".parse::<SecretRef>().unwrap(),
+            SecretRef {
+                backend: Backend::Vault,
+                path: "secret/data/db".to_string(),
+                key: Some("password".to_string()),
+            },
+        );
+        assert_eq!(
+            "aws:arn:aws:secretsmanager:eu-west-1:123:secret:db".parse::<SecretRef>().unwrap().path,
+            "arn:aws:secretsmanager:eu-west-1:123:secret:db",
+        );
+        assert_eq!("gcp:projects/p/secrets/db".parse::<SecretRef>().unwrap().key, None);
+        assert!("vault:secret/data/db".parse::<SecretRef>().is_err());
+        assert!("aws:".parse::<SecretRef>().is_err());
+        assert!("keychain:db#password".parse::<SecretRef>().is_err());
+    }
+
+    #[cfg(any(feature = "aws-secrets", feature = "gcp-secrets"))]
+    #[test]
+    fn select_key_reads_json_objects() {
+        assert_eq!(select_key("hunter2", None), Ok("hunter2".to_string()));
+        assert_eq!(select_key(r#"{"username":"app","password":"hunter2"}"#, Some("password")), Ok("hunter2".to_string()));
+        assert!(select_key("hunter2", Some("password")).is_err());
+    }
+}
@@ -0,0 +1,267 @@
+//! Explains what's wrong with the settings in config and environment files,
+//! as found when deserializing them, by the file and line of the setting at
+//! fault rather than only its key. Unknown settings, which are rejected
+//! instead of being ignored, are matched against those expected so that
+//! typos like `enviroments` come with a suggestion.
+use std::path::Path;
+
+use toml::de::Error as TomlError;
+
+use crate::Error;
+
+/// A setting that's wrong, by its key, or that of the table it's missing
+/// from, and why.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Problem {
+    pub key: Vec<String>,
+    pub reason: String,
+}
+
+impl Problem {
+    /// The problem described by the error deserializing the settings.
+    pub fn from_toml(err: &TomlError) -> Self {
+        let message = err.to_string();
+        let (message, mut key) = match message.rsplit_once(" for key `") {
+            Some((message, key)) => {
+                let key = key.trim_end_matches('`').split('.').map(ToString::to_string).collect();
+
+                (message.to_string(), key)
+            }
+            None => (message, vec![]),
+        };
+        let dotted = |key: &[String], field: &str| {
+            key.iter().map(String::as_str).chain(Some(field).filter(|f| !f.is_empty())).collect::<Vec<_>>().join(".")
+        };
+
+        if let Some((field, expected)) = quoted_after(&message, "unknown field `") {
+            let reason = match closest(field, &quoted(expected)) {
+                Some(suggestion) => {
+                    format!("unknown setting `{}`, did you mean `{}`?", dotted(&key, field), suggestion)
+                }
+                None => format!("unknown setting `{}`{}", dotted(&key, field), expected),
+            };
+            key.push(field.to_string());
+
+            return Self { key, reason };
+        }
+
+        if let Some((value, expected)) = quoted_after(&message, "unknown variant `") {
+            let reason = match closest(value, &quoted(expected)) {
+                Some(suggestion) => {
+                    format!("unknown value `{}` for `{}`, did you mean `{}`?", value, dotted(&key, ""), suggestion)
+                }
+                None => format!("unknown value `{}` for `{}`{}", value, dotted(&key, ""), expected),
+            };
+
+            return Self { key, reason };
+        }
+
+        if let Some((field, _)) = quoted_after(&message, "missing field `") {
+            let reason = format!("missing setting `{}`", dotted(&key, field));
+
+            return Self { key, reason };
+        }
+
+        let reason = match key.is_empty() {
+            true => message,
+            false => format!("{} for `{}`", message, dotted(&key, "")),
+        };
+
+        Self { key, reason }
+    }
+
+    /// The first of the keys given as dotted keys, eg. `backup.schema`,
+    /// that are set along with another of them, if any are.
+    pub fn conflicting(settings: &toml::Value, exclusive: &[(&str, &str)]) -> Option<Self> {
+        let is_set = |key: &str| key.split('.').try_fold(settings, |value, part| value.get(part)).is_some();
+
+        exclusive.iter().find(|(a, b)| is_set(a) && is_set(b)).map(|(a, b)| Self {
+            key: b.split('.').map(ToString::to_string).collect(),
+            reason: format!("`{}` and `{}` can't both be given", a, b),
+        })
+    }
+
+    /// The error for the problem, at the line setting the key in the last of
+    /// the files, given along with their contents, that sets it, or else in
+    /// the file at the path.
+    pub fn into_error(self, path: &Path, files: &[(&Path, &str)]) -> Error {
+        let (path, line) = files
+            .iter()
+            .rev()
+            .find_map(|(path, contents)| locate(contents, &self.key).map(|line| (*path, Some(line))))
+            .unwrap_or((path, None));
+
+        Error::SettingInvalid(path.display().to_string(), line, self.reason)
+    }
+}
+
+/// The text between the prefix and the next backtick, and whatever follows.
+fn quoted_after<'a>(message: &'a str, prefix: &str) -> Option<(&'a str, &'a str)> {
+    message.strip_prefix(prefix)?.split_once('`')
+}
+
+/// Each of the words in backticks, eg. those that were expected.
+fn quoted(text: &str) -> Vec<&str> {
+    text.split('`').skip(1).step_by(2).collect()
+}
+
+/// The candidate closest to the name, if any is close enough to be a typo.
+fn closest<'a>(name: &str, candidates: &[&'a str]) -> Option<&'a str> {
+    let max = (name.chars().count() / 3).max(1);
+
+    candidates
+        .iter()
+        .map(|candidate| (edit_distance(name, candidate), *candidate))
+        .filter(|(distance, _)| *distance <= max)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// How many characters must be inserted, removed, or replaced to turn one
+/// into the other.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<_> = b.chars().collect();
+    let mut row: Vec<_> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+
+        for (j, cb) in b.iter().enumerate() {
+            let replaced = diagonal + usize::from(ca != *cb);
+            diagonal = row[j + 1];
+            row[j + 1] = replaced.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+
+    row[b.len()]
+}
+
+/// The line, counting from 1, that sets the key, or else that of the
+/// closest table it's in, eg. `[revisions]` for `revisions.enviroments`.
+pub fn locate(contents: &str, key: &[String]) -> Option<usize> {
+    if key.is_empty() {
+        return None;
+    }
+
+    let mut table: Vec<String> = vec![];
+    let mut closest: Option<(usize, usize)> = None;
+    let mut in_string = false;
+
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        let delimiters = line.matches("\"\"\"").count() + line.matches("'''").count();
+
+        if in_string {
+            in_string = delimiters % 2 == 0;
+            continue;
+        }
+        in_string = delimiters % 2 == 1;
+
+        let full = if line.starts_with('[') {
+            table = split_key(line.trim_start_matches('[').split(']').next().unwrap_or_default());
+            table.clone()
+        } else {
+            match line.split_once('=') {
+                Some((name, _)) if !line.starts_with('#') => table.iter().cloned().chain(split_key(name)).collect(),
+                _ => continue,
+            }
+        };
+
+        if full.starts_with(key) {
+            return Some(i + 1);
+        }
+        if key.starts_with(&full) && closest.is_none_or(|(length, _)| full.len() > length) {
+            closest = Some((full.len(), i + 1));
+        }
+    }
+
+    closest.map(|(_, line)| line)
+}
+
+/// The parts of a dotted key, without quotes.
+fn split_key(key: &str) -> Vec<String> {
+    let mut parts = vec![];
+    let mut part = String::new();
+    let mut quote = None;
+
+    for c in key.chars() {
+        match (c, quote) {
+            ('"' | '\'', None) => quote = Some(c),
+            (c, Some(q)) if c == q => quote = None,
+            ('.', None) => parts.push(std::mem::take(&mut part)),
+            (c, _) => part.push(c),
+        }
+    }
+    parts.push(part);
+
+    parts.into_iter().map(|part| part.trim().to_string()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[derive(Debug, Deserialize)]
+    #[serde(deny_unknown_fields)]
+    #[allow(dead_code)]
+    struct Settings {
+        revisions: Revisions,
+    }
+
+    #[derive(Debug, Deserialize)]
+    #[serde(deny_unknown_fields)]
+    #[allow(dead_code)]
+    struct Revisions {
+        directory: String,
+        #[serde(default)]
+        environments: Vec<String>,
+        #[serde(default)]
+        max_length: usize,
+    }
+
+    fn problem(contents: &str) -> Problem {
+        let settings: toml::Value = toml::from_str(contents).unwrap();
+
+        Problem::from_toml(&settings.try_into::<Settings>().unwrap_err())
+    }
+
+    fn key(key: &str) -> Vec<String> {
+        key.split('.').map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn problems_are_explained_with_suggestions() {
+        assert_eq!(
+            problem("[revisions]\ndirectory = \"revisions\"\nenviroments = []"),
+            Problem {
+                key: key("revisions.enviroments"),
+                reason: "unknown setting `revisions.enviroments`, did you mean `environments`?".to_string(),
+            },
+        );
+        assert!(problem("[revisions]\ndirectory = \"revisions\"\nsomething = 1").reason.ends_with("`max_length`"));
+        assert_eq!(problem("[revisions]").reason, "missing setting `revisions.directory`");
+        assert_eq!(problem("[revisions]\ndirectory = 1").key, key("revisions.directory"));
+
+        let settings: toml::Value = toml::from_str("[backup]\nschema = \"b\"\ndirectory = \"d\"").unwrap();
+        let conflict = Problem::conflicting(&settings, &[("backup.schema", "backup.directory")]).unwrap();
+
+        assert_eq!(conflict.key, key("backup.directory"));
+        assert!(Problem::conflicting(&settings, &[("backup.schema", "backup.other")]).is_none());
+    }
+
+    #[test]
+    fn keys_are_located_by_line() {
+        let contents = "directory = \"revisions\"\n\n[revisions]\nsql = \"\"\"\nmax_length = 3\n\"\"\"\nmax_length = 50\n\
+                        policy.\"varchar\" = \"off\"\n\n[revisions.naming]\nstyle = \"kebab-case\"\n";
+
+        assert_eq!(locate(contents, &key("directory")), Some(1));
+        assert_eq!(locate(contents, &key("revisions.max_length")), Some(7));
+        assert_eq!(locate(contents, &key("revisions.policy.varchar")), Some(8));
+        assert_eq!(locate(contents, &key("revisions.naming")), Some(10));
+        assert_eq!(locate(contents, &key("revisions.enviroments")), Some(3));
+        assert_eq!(locate(contents, &key("table.name")), None);
+    }
+}
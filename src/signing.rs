@@ -0,0 +1,343 @@
+//! Manifests of the checksums of every revision file, signed by the release
+//! pipeline with `jrny sign` and checked before embarking, so that an
+//! environment only ever applies revisions that passed through it. Revision
+//! files can also each be required to carry a signature of their own. The
+//! keys to trust are given in the environment file:
+//!
+//! ```toml
+//! [signing]
+//! # `ssh` (the default), with an allowed signers file, `gpg`, with a
+//! # keyring, or `minisign`, with its public key
+//! tool = "ssh"
+//! trusted = "allowed_signers"
+//! # Require a signed manifest without `--require-signature`
+//! required = true
+//! # Require each pending revision file to be signed
+//! files = true
+//! ```
+//!
+//! Signatures are made and verified by `ssh-keygen -Y`, `gpg`, or
+//! `minisign`, which must be installed, and are written alongside what's
+//! signed, eg. `jrny-manifest.txt.sig`.
+use std::{
+    env, fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process::{self, Command, Stdio},
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::Deserialize;
+
+use crate::{checksum, Error, Result};
+
+/// The default name of the manifest file
+pub const MANIFEST: &str = "jrny-manifest.txt";
+
+/// What signatures made with SSH keys are for, so that they can't be passed
+/// off as signatures for anything else
+const SSH_NAMESPACE: &str = "jrny";
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum SigningTool {
+    #[default]
+    Ssh,
+    Gpg,
+    Minisign,
+}
+
+impl FromStr for SigningTool {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "ssh" => Ok(Self::Ssh),
+            "gpg" => Ok(Self::Gpg),
+            "minisign" => Ok(Self::Minisign),
+            _ => Err(Error::SigningToolInvalid(s.to_string())),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SigningSettings {
+    #[serde(default)]
+    pub tool: SigningTool,
+    /// The allowed signers file for `ssh`, the keyring for `gpg`, or the
+    /// public key for `minisign`
+    pub trusted: PathBuf,
+    /// Whether embarking always requires a signed manifest
+    #[serde(default)]
+    pub required: bool,
+    /// Whether embarking requires each pending revision file to be signed
+    /// by a trusted key, with its signature alongside it
+    #[serde(default)]
+    pub files: bool,
+}
+
+/// The checksum of each revision file, by its path relative to the
+/// revisions directory.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Manifest {
+    pub files: Vec<(String, String)>,
+}
+
+impl Manifest {
+    pub fn render(&self) -> String {
+        let mut rendered = String::from("# jrny manifest\n");
+
+        for (filename, checksum) in &self.files {
+            rendered.push_str(&format!("{}  {}\n", checksum, filename));
+        }
+
+        rendered
+    }
+
+    pub fn parse(contents: &str) -> std::result::Result<Self, String> {
+        let files = contents
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty() && !line.starts_with('#'))
+            .map(|(i, line)| match line.split_once("  ") {
+                Some((checksum, filename)) if !checksum.is_empty() && !filename.is_empty() => {
+                    Ok((filename.to_string(), checksum.to_string()))
+                }
+                _ => Err(format!("expected `checksum  filename` on line {}", i + 1)),
+            })
+            .collect::<std::result::Result<_, _>>()?;
+
+        Ok(Self { files })
+    }
+
+    /// What's wrong with the file as far as the manifest is concerned, if
+    /// anything.
+    pub fn check(&self, filename: &str, contents: &str) -> Option<String> {
+        match self.files.iter().find(|(listed, _)| listed == filename) {
+            None => Some("isn't in the signed manifest".to_string()),
+            Some((_, recorded)) if !checksum::matches(contents, recorded) => {
+                Some("has changed since the manifest was signed".to_string())
+            }
+            Some(_) => None,
+        }
+    }
+}
+
+/// Where the signature of the file is kept.
+pub fn signature_path(path: &Path) -> PathBuf {
+    let mut signature = path.as_os_str().to_owned();
+    signature.push(".sig");
+
+    PathBuf::from(signature)
+}
+
+/// Whether the entry is the signature of another file.
+pub fn is_signature(entry: &Path) -> bool {
+    entry.is_file() && entry.extension().is_some_and(|extension| extension == "sig")
+}
+
+/// Signs the file with the private key, given by its id for `gpg`,
+/// returning where the signature was written. The tool may prompt for the
+/// key's passphrase.
+pub fn sign(path: &Path, key: &Path, tool: SigningTool) -> Result<PathBuf> {
+    let signature = signature_path(path);
+    let failed = |reason: String| Error::SigningFailed(path.display().to_string(), reason);
+
+    // None of the tools replace an existing signature without asking
+    if signature.exists() {
+        fs::remove_file(&signature)?;
+    }
+
+    let mut cmd = match tool {
+        SigningTool::Ssh => {
+            let mut cmd = Command::new("ssh-keygen");
+            cmd.args(["-Y", "sign", "-n", SSH_NAMESPACE, "-f"]).arg(key).arg(path);
+            cmd
+        }
+        SigningTool::Gpg => {
+            let mut cmd = Command::new("gpg");
+            cmd.args(["--detach-sign", "--local-user"]).arg(key).arg("--output").arg(&signature).arg(path);
+            cmd
+        }
+        SigningTool::Minisign => {
+            let mut cmd = Command::new("minisign");
+            cmd.arg("-S").arg("-s").arg(key).arg("-m").arg(path).arg("-x").arg(&signature);
+            cmd
+        }
+    };
+
+    run(cmd.stdout(Stdio::null())).map_err(failed)?;
+
+    Ok(signature)
+}
+
+/// Verifies the manifest's signature against the keys trusted, returning
+/// what it lists.
+pub fn verify(manifest: &Path, settings: &SigningSettings) -> Result<Manifest> {
+    let invalid = |reason: String| Error::ManifestInvalid(manifest.display().to_string(), reason);
+
+    // Read only the once, so that what's parsed is exactly what was verified
+    // rather than whatever's in the file by then
+    let contents = fs::read(manifest)?;
+
+    verify_contents(&contents, manifest, settings)
+        .map_err(|reason| Error::SignatureInvalid(manifest.display().to_string(), reason))?;
+
+    let contents = String::from_utf8(contents).map_err(|_| invalid("it isn't valid UTF-8".to_string()))?;
+
+    Manifest::parse(&contents).map_err(invalid)
+}
+
+/// Verifies the signature alongside the file against the keys trusted, as the
+/// signature of the contents given, which are what's given to the tool rather
+/// than the file itself, giving the reason should it be missing or invalid.
+pub fn verify_contents(contents: &[u8], path: &Path, settings: &SigningSettings) -> std::result::Result<(), String> {
+    let signature = signature_path(path);
+
+    if !signature.is_file() {
+        return Err(format!("no signature at {}", signature.display()));
+    }
+
+    match settings.tool {
+        SigningTool::Ssh => {
+            let principals = run(Command::new("ssh-keygen")
+                .args(["-Y", "find-principals", "-s"])
+                .arg(&signature)
+                .arg("-f")
+                .arg(&settings.trusted))
+            .map_err(|_| "it wasn't made by an allowed signer".to_string())?;
+            let principal = principals.lines().next().unwrap_or_default().to_string();
+
+            run_with_input(
+                Command::new("ssh-keygen")
+                    .args(["-Y", "verify", "-n", SSH_NAMESPACE, "-I", &principal, "-s"])
+                    .arg(&signature)
+                    .arg("-f")
+                    .arg(&settings.trusted),
+                contents,
+            )?;
+        }
+        SigningTool::Gpg => {
+            // Keyrings given without a directory are otherwise looked for in
+            // gpg's own home directory
+            let keyring = fs::canonicalize(&settings.trusted)
+                .map_err(|e| format!("could not find keyring {}: {}", settings.trusted.display(), e))?;
+
+            run_with_input(Command::new("gpgv").arg("--keyring").arg(keyring).arg(&signature).arg("-"), contents)?;
+        }
+        SigningTool::Minisign => {
+            // minisign only reads what's signed from a file
+            let copy = PrivateCopy::new(contents).map_err(|e| format!("could not copy {}: {}", path.display(), e))?;
+
+            run(Command::new("minisign")
+                .arg("-V")
+                .arg("-p")
+                .arg(&settings.trusted)
+                .arg("-m")
+                .arg(&copy.path)
+                .arg("-x")
+                .arg(&signature))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// A copy of contents in a directory of its own that only this user can
+/// read, removed along with the directory once dropped.
+struct PrivateCopy {
+    dir: PathBuf,
+    path: PathBuf,
+}
+
+impl PrivateCopy {
+    fn new(contents: &[u8]) -> io::Result<Self> {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_nanos());
+        let dir = env::temp_dir().join(format!("jrny-verify-{}-{}", process::id(), nanos));
+        let mut builder = fs::DirBuilder::new();
+
+        #[cfg(unix)]
+        std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+
+        // Fails rather than using a directory someone else made first
+        builder.create(&dir)?;
+
+        let copy = Self { path: dir.join("contents"), dir };
+        fs::write(&copy.path, contents)?;
+
+        Ok(copy)
+    }
+}
+
+impl Drop for PrivateCopy {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// As with `run`, writing the input to the tool's stdin.
+fn run_with_input(cmd: &mut Command, input: &[u8]) -> std::result::Result<String, String> {
+    let program = cmd.get_program().to_string_lossy().to_string();
+    let mut child = cmd
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("could not run {}: {}", program, e))?;
+
+    child.stdin.take().unwrap().write_all(input).map_err(|e| e.to_string())?;
+
+    let output = child.wait_with_output().map_err(|e| e.to_string())?;
+
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// Runs a signing tool, returning what it wrote to stdout.
+fn run(cmd: &mut Command) -> std::result::Result<String, String> {
+    let program = cmd.get_program().to_string_lossy().to_string();
+    let output = cmd.output().map_err(|e| format!("could not run {}: {}", program, e))?;
+
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::revisions::to_checksum;
+
+    #[test]
+    fn manifest_round_trips_and_checks_files() {
+        let manifest = Manifest {
+            files: vec![
+                ("001.1618370298.add-users.sql".to_string(), to_checksum("CREATE TABLE users ();\n")),
+                ("2021-04/002.1618449763.add orders.sql".to_string(), to_checksum("CREATE TABLE orders ();\n")),
+            ],
+        };
+
+        assert_eq!(Manifest::parse(&manifest.render()), Ok(Manifest { files: manifest.files.clone() }));
+        assert_eq!(manifest.check("001.1618370298.add-users.sql", "CREATE TABLE users ();\n"), None);
+        assert!(manifest.check("001.1618370298.add-users.sql", "DROP TABLE users;\n").is_some());
+        assert!(manifest.check("003.1618450954.other.sql", "").is_some());
+        assert!(Manifest::parse("abc123 001.sql\n").is_err());
+    }
+
+    #[test]
+    fn private_copies_are_removed_once_dropped() {
+        let copy = PrivateCopy::new(b"abc").unwrap();
+        let dir = copy.dir.clone();
+
+        assert_eq!(fs::read(&copy.path).unwrap(), b"abc");
+        drop(copy);
+        assert!(!dir.exists());
+    }
+}
@@ -0,0 +1,522 @@
+//! Splits the contents of a revision file into individual statements.
+//!
+//! This is nowhere near a full SQL parser, but it understands enough of
+//! PostgreSQL's lexical structure (quoted strings and identifiers,
+//! dollar-quoting, and both comment styles) to know which semicolons
+//! actually terminate statements.
+
+use crate::meta::{inline_directive, NO_TRANSACTION};
+
+/// A single statement from a revision file.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Statement {
+    /// The statement text, excluding the terminating semicolon
+    pub sql: String,
+    /// The 1-based line on which the statement begins
+    pub line: usize,
+}
+
+impl Statement {
+    /// Returns the first keyword of the statement, lowercased and ignoring
+    /// any leading comments.
+    pub fn leading_keyword(&self) -> Option<String> {
+        self.words().next()
+    }
+
+    /// Iterates over the lowercased words of the statement, skipping comments
+    /// but not the contents of quoted strings.
+    pub fn words(&self) -> impl Iterator<Item = String> + '_ {
+        strip_comments(&self.sql)
+            .split(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .filter(|w| !w.is_empty())
+            .map(str::to_lowercase)
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    /// Returns the statement with comments removed and whitespace collapsed,
+    /// truncated for display.
+    pub fn summary(&self) -> String {
+        const MAX_CHARS: usize = 60;
+
+        let collapsed = strip_comments(&self.sql)
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        if collapsed.chars().count() > MAX_CHARS {
+            format!("{}...", collapsed.chars().take(MAX_CHARS).collect::<String>())
+        } else {
+            collapsed
+        }
+    }
+
+    /// Whether or not the statement begins, ends, or otherwise manages
+    /// a transaction block.
+    pub fn is_transaction_command(&self) -> bool {
+        matches!(
+            self.leading_keyword().as_deref(),
+            Some("begin" | "start" | "commit" | "end" | "rollback" | "abort")
+        )
+    }
+
+    /// Whether the comments before the statement include a `-- jrny:` one
+    /// giving the directive.
+    pub fn has_directive(&self, directive: &str) -> bool {
+        self.sql.lines().filter_map(inline_directive).any(|d| d == directive)
+    }
+
+    /// Describes the statement if it's one that destroys data wholesale, ie.
+    /// dropping a table or schema with everything in it, truncating, or
+    /// deleting from or updating a table without any `WHERE` clause of its
+    /// own, rather than within a string or subquery.
+    pub fn destructive_operation(&self) -> Option<&'static str> {
+        let words: Vec<_> = self.words().collect();
+        let has = |word: &str| words.iter().any(|w| w == word);
+        let bounded = find_top_level_keyword(&self.sql, "where").is_some();
+
+        match (words.first().map(String::as_str), words.get(1).map(String::as_str)) {
+            (Some("drop"), Some("table")) => Some("DROP TABLE"),
+            (Some("drop"), Some("schema")) if has("cascade") => Some("DROP SCHEMA ... CASCADE"),
+            (Some("truncate"), _) => Some("TRUNCATE"),
+            (Some("delete"), _) if !bounded => Some("DELETE without WHERE"),
+            (Some("update"), _) if !bounded => Some("UPDATE without WHERE"),
+            _ => None,
+        }
+    }
+}
+
+/// Whether the statements are run as they are rather than in a transaction,
+/// as they manage their own or are marked by a `-- jrny:no-transaction`
+/// comment, eg. to `CREATE INDEX CONCURRENTLY`.
+pub fn outside_transaction(statements: &[Statement]) -> bool {
+    statements.iter().any(|statement| statement.is_transaction_command() || statement.has_directive(NO_TRANSACTION))
+}
+
+/// Splits the given SQL into statements, discarding any that consist
+/// solely of whitespace and comments.
+pub fn split(sql: &str) -> Vec<Statement> {
+    split_with_ends(sql).into_iter().map(|(statement, _)| statement).collect()
+}
+
+/// As with `split`, along with the byte offset just past the end of each
+/// statement, including its terminating semicolon if it has one.
+pub fn split_with_ends(sql: &str) -> Vec<(Statement, usize)> {
+    let chars: Vec<char> = sql.chars().collect();
+    // The byte offset of each char, so that ends needn't be counted anew
+    let offsets: Vec<usize> = sql.char_indices().map(|(offset, _)| offset).collect();
+    let mut statements = vec![];
+
+    let mut current = String::new();
+    let mut has_content = false;
+    let mut start_line = 1;
+    let mut line = 1;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        let starts_comment = (c == '-' && next == Some('-')) || (c == '/' && next == Some('*'));
+
+        if !has_content && !c.is_whitespace() && c != ';' && !starts_comment {
+            has_content = true;
+            start_line = line;
+        }
+
+        match c {
+            ';' => {
+                if has_content {
+                    let end = offsets[i] + ';'.len_utf8();
+
+                    statements.push((
+                        Statement {
+                            sql: current.trim().to_string(),
+                            line: start_line,
+                        },
+                        end,
+                    ));
+                }
+                current.clear();
+                has_content = false;
+                i += 1;
+                continue;
+            }
+            '-' if next == Some('-') => {
+                let end = find(&chars, i, |j| chars[j] == '\n').unwrap_or(chars.len());
+                i = push_span(&mut current, &chars, i, end, &mut line);
+                continue;
+            }
+            '/' if next == Some('*') => {
+                let end = block_comment_end(&chars, i);
+                i = push_span(&mut current, &chars, i, end, &mut line);
+                continue;
+            }
+            _ => {
+                if let Some(end) = literal_end(&chars, i) {
+                    i = push_span(&mut current, &chars, i, end, &mut line);
+                    continue;
+                }
+            }
+        }
+
+        if c == '\n' {
+            line += 1;
+        }
+        current.push(c);
+        i += 1;
+    }
+
+    if has_content {
+        statements.push((
+            Statement {
+                sql: current.trim().to_string(),
+                line: start_line,
+            },
+            sql.len(),
+        ));
+    }
+
+    statements
+}
+
+/// Removes line and block comments from the given SQL, leaving quoted
+/// strings and identifiers intact.
+pub fn strip_comments(sql: &str) -> String {
+    let chars: Vec<char> = sql.chars().collect();
+    let mut stripped = String::new();
+    let mut lines = 0;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+
+        let end = match c {
+            '-' if next == Some('-') => {
+                i = find(&chars, i, |j| chars[j] == '\n').unwrap_or(chars.len());
+                stripped.push(' ');
+                continue;
+            }
+            '/' if next == Some('*') => {
+                i = block_comment_end(&chars, i);
+                stripped.push(' ');
+                continue;
+            }
+            _ => literal_end(&chars, i).unwrap_or(i + 1),
+        };
+
+        i = push_span(&mut stripped, &chars, i, end, &mut lines);
+    }
+
+    stripped
+}
+
+/// Returns the byte offset of the first occurrence of the keyword as a whole
+/// word outside of any parentheses, quotes, or comments, eg. to find the
+/// `WHERE` clause of a statement rather than of a subquery within it.
+pub fn find_top_level_keyword(sql: &str, keyword: &str) -> Option<usize> {
+    let chars: Vec<char> = sql.chars().collect();
+    let keyword: Vec<char> = keyword.chars().collect();
+    let mut depth = 0;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let next = chars.get(i + 1).copied();
+
+        match chars[i] {
+            '-' if next == Some('-') => {
+                i = find(&chars, i, |j| chars[j] == '\n').unwrap_or(chars.len());
+                continue;
+            }
+            '/' if next == Some('*') => {
+                i = block_comment_end(&chars, i);
+                continue;
+            }
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            _ => {
+                if let Some(end) = literal_end(&chars, i) {
+                    i = end;
+                    continue;
+                }
+            }
+        }
+
+        let matches = chars
+            .get(i..i + keyword.len())
+            .is_some_and(|word| word.iter().zip(&keyword).all(|(a, b)| a.eq_ignore_ascii_case(b)));
+        let preceded = i > 0 && is_ident_char(chars[i - 1]);
+        let followed = chars.get(i + keyword.len()).copied().is_some_and(is_ident_char);
+
+        if depth == 0 && matches && !preceded && !followed {
+            return Some(chars[..i].iter().map(|c| c.len_utf8()).sum());
+        }
+
+        i += 1;
+    }
+
+    None
+}
+
+/// Returns the index just past the end of the quoted string, quoted identifier,
+/// or dollar-quoted string starting at `from`, if one starts there.
+fn literal_end(chars: &[char], from: usize) -> Option<usize> {
+    let preceded_by_ident = from > 0 && is_ident_char(chars[from - 1]);
+
+    match chars[from] {
+        '\'' => {
+            let escapes = preceded_by_ident
+                && (chars[from - 1] == 'e' || chars[from - 1] == 'E')
+                && !(from > 1 && is_ident_char(chars[from - 2]));
+            Some(quoted_end(chars, from, '\'', escapes))
+        }
+        '"' => Some(quoted_end(chars, from, '"', false)),
+        '$' if !preceded_by_ident => {
+            dollar_tag(chars, from).map(|tag| dollar_quoted_end(chars, from, &tag))
+        }
+        _ => None,
+    }
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Finds the first index at or after `from` satisfying the predicate.
+fn find(chars: &[char], from: usize, pred: impl Fn(usize) -> bool) -> Option<usize> {
+    (from..chars.len()).find(|&j| pred(j))
+}
+
+/// Appends `chars[from..to]` to the buffer, counting newlines, and returns `to`.
+fn push_span(buf: &mut String, chars: &[char], from: usize, to: usize, line: &mut usize) -> usize {
+    for &c in &chars[from..to] {
+        if c == '\n' {
+            *line += 1;
+        }
+        buf.push(c);
+    }
+    to
+}
+
+/// Returns the index just past the end of the (possibly nested) block comment
+/// starting at `from`.
+fn block_comment_end(chars: &[char], from: usize) -> usize {
+    let mut depth = 0;
+    let mut i = from;
+
+    while i < chars.len() {
+        match (chars[i], chars.get(i + 1)) {
+            ('/', Some('*')) => {
+                depth += 1;
+                i += 2;
+            }
+            ('*', Some('/')) => {
+                depth -= 1;
+                i += 2;
+                if depth == 0 {
+                    return i;
+                }
+            }
+            _ => i += 1,
+        }
+    }
+
+    chars.len()
+}
+
+/// Returns the index just past the closing quote of the string or identifier
+/// starting at `from`, treating doubled quotes (and backslashes, for `E''`
+/// strings) as escapes.
+fn quoted_end(chars: &[char], from: usize, quote: char, escapes: bool) -> usize {
+    let mut i = from + 1;
+
+    while i < chars.len() {
+        if escapes && chars[i] == '\\' {
+            i += 2;
+            continue;
+        }
+        if chars[i] == quote {
+            if chars.get(i + 1) == Some(&quote) {
+                i += 2;
+                continue;
+            }
+            return i + 1;
+        }
+        i += 1;
+    }
+
+    chars.len()
+}
+
+/// Returns the full dollar-quote tag (eg. `$$` or `$body$`) starting at `from`,
+/// if there is one; `$1` and the like are positional parameters instead.
+fn dollar_tag(chars: &[char], from: usize) -> Option<String> {
+    let mut tag = String::from("$");
+    let mut i = from + 1;
+
+    while let Some(&c) = chars.get(i) {
+        if c == '$' {
+            tag.push(c);
+            return Some(tag);
+        }
+        if !is_ident_char(c) || (i == from + 1 && c.is_ascii_digit()) {
+            return None;
+        }
+        tag.push(c);
+        i += 1;
+    }
+
+    None
+}
+
+/// Returns the index just past the closing tag of the dollar-quoted string
+/// starting at `from`.
+fn dollar_quoted_end(chars: &[char], from: usize, tag: &str) -> usize {
+    let tag: Vec<char> = tag.chars().collect();
+    let mut i = from + tag.len();
+
+    while i + tag.len() <= chars.len() {
+        if chars[i..i + tag.len()] == tag[..] {
+            return i + tag.len();
+        }
+        i += 1;
+    }
+
+    chars.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sqls(sql: &str) -> Vec<String> {
+        split(sql).into_iter().map(|s| s.sql).collect()
+    }
+
+    #[test]
+    fn split_separates_statements() {
+        assert_eq!(
+            sqls("create table a (id int);\n\ninsert into a values (1);"),
+            vec!["create table a (id int)", "insert into a values (1)"],
+        );
+    }
+
+    #[test]
+    fn split_ignores_comment_only_statements() {
+        assert_eq!(
+            sqls("-- Revision: thing\n--\n-- Add SQL here;\n\n/* nothing; here */;\nselect 1;"),
+            vec!["select 1"],
+        );
+    }
+
+    #[test]
+    fn split_respects_quotes() {
+        assert_eq!(
+            sqls("select 'a;b', \"weird;name\", E'it\\'s;';select 'it''s;'"),
+            vec!["select 'a;b', \"weird;name\", E'it\\'s;'", "select 'it''s;'"],
+        );
+    }
+
+    #[test]
+    fn split_respects_dollar_quotes() {
+        let sql = "create function f() returns int as $body$ begin; return $1; end; $body$ language plpgsql;\n\
+                   do $$ begin perform 1; end $$;";
+
+        assert_eq!(split(sql).len(), 2);
+        assert_eq!(split(sql)[1].sql, "do $$ begin perform 1; end $$");
+    }
+
+    #[test]
+    fn split_tracks_starting_lines() {
+        let lines: Vec<usize> = split("-- header\n\nbegin;\n\ncreate table a (\n  id int\n);\ncommit;\n")
+            .iter()
+            .map(|s| s.line)
+            .collect();
+
+        assert_eq!(lines, vec![3, 5, 8]);
+    }
+
+    #[test]
+    fn split_with_ends_gives_byte_offsets() {
+        let sql = "insert into a values ('é');\n-- next\nselect 1";
+        let ends: Vec<usize> = split_with_ends(sql).iter().map(|(_, end)| *end).collect();
+
+        assert_eq!(ends, vec![sql.find(';').unwrap() + 1, sql.len()]);
+    }
+
+    #[test]
+    fn split_ends_large_revisions_at_each_statement() {
+        // Large enough that splitting in quadratic time wouldn't finish
+        let statement = "insert into notes values ('é', 'a;b');\n";
+        let sql = statement.repeat(100_000);
+        let split = split_with_ends(&sql);
+
+        assert_eq!(split.len(), 100_000);
+        assert!(split.iter().enumerate().all(|(i, (_, end))| *end == (i + 1) * statement.len() - 1));
+    }
+
+    #[test]
+    fn outside_transaction_when_managed_or_marked() {
+        assert!(outside_transaction(&split("select 1;\nbegin;\nselect 2;\ncommit;")));
+        assert!(outside_transaction(&split("-- jrny:no-transaction\ncreate index concurrently a on b (c);")));
+        assert!(!outside_transaction(&split("-- no-transaction\nselect 1;")));
+    }
+
+    #[test]
+    fn statement_detects_transaction_commands() {
+        let statements = split("-- leading comment\nBEGIN;\nselect 1;\nCOMMIT;");
+
+        assert!(statements[0].is_transaction_command());
+        assert!(!statements[1].is_transaction_command());
+        assert!(statements[2].is_transaction_command());
+    }
+
+    #[test]
+    fn find_top_level_keyword_skips_nested_and_quoted() {
+        let sql = "update t set note = 'where' -- where\n\
+                   where id in (select id from u where x) and \"where\" = 1";
+
+        let found = find_top_level_keyword(sql, "where").unwrap();
+
+        assert!(sql[found..].starts_with("where id in"));
+        assert_eq!(find_top_level_keyword("delete from nowhere", "where"), None);
+        assert_eq!(find_top_level_keyword("delete from t where (a) returning *", "RETURNING"), Some(24));
+    }
+
+    #[test]
+    fn statement_detects_destructive_operations() {
+        let operations: Vec<_> = split(
+            "DROP TABLE users;
+             drop schema app cascade;
+             drop schema app;
+             truncate users;
+             delete from users;
+             delete from users where id = 1;
+             update users set name = 'x';
+             update users set note = 'where';
+             update users set plan = (select plan from plans where plans.id = 1);
+             delete from users where id in (select id from banned);
+             drop index users_name;",
+        )
+        .iter()
+        .map(Statement::destructive_operation)
+        .collect();
+
+        assert_eq!(
+            operations,
+            vec![
+                Some("DROP TABLE"),
+                Some("DROP SCHEMA ... CASCADE"),
+                None,
+                Some("TRUNCATE"),
+                Some("DELETE without WHERE"),
+                None,
+                Some("UPDATE without WHERE"),
+                Some("UPDATE without WHERE"),
+                Some("UPDATE without WHERE"),
+                None,
+                None,
+            ],
+        );
+    }
+}
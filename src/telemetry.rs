@@ -0,0 +1,256 @@
+//! OpenTelemetry traces for embark runs, exported as OTLP/HTTP JSON.
+//!
+//! The run is the root span, each attempted revision a child span, and each
+//! executed statement a grandchild. If a W3C `TRACEPARENT` is present in the
+//! environment, the run joins that trace, eg. an enclosing deploy.
+use std::{
+    collections::hash_map::RandomState,
+    env,
+    hash::{BuildHasher, Hasher},
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use log::info;
+
+use crate::{
+    environment::TracingSettings,
+    http,
+    json::Json,
+    report::{RevisionStatus, RunReport},
+    Result,
+};
+
+const SPAN_KIND_INTERNAL: i64 = 1;
+const SPAN_KIND_CLIENT: i64 = 3;
+const STATUS_OK: i64 = 1;
+const STATUS_ERROR: i64 = 2;
+
+struct Span {
+    span_id: String,
+    parent_id: Option<String>,
+    name: String,
+    kind: i64,
+    started_at: DateTime<Utc>,
+    elapsed: Duration,
+    attributes: Vec<(&'static str, Json)>,
+    error: Option<String>,
+}
+
+impl Span {
+    fn to_json(&self, trace_id: &str) -> Json {
+        let start = self.started_at.timestamp_nanos();
+        let end = start + self.elapsed.as_nanos() as i64;
+
+        let attributes = self.attributes.iter().map(|(key, value)| {
+            let value = match value {
+                Json::Int(n) => Json::object([("intValue", Json::from(n.to_string()))]),
+                Json::Bool(b) => Json::object([("boolValue", Json::from(*b))]),
+                Json::Str(s) => Json::object([("stringValue", Json::from(s.as_str()))]),
+                other => Json::object([("stringValue", Json::from(other.to_string()))]),
+            };
+            Json::object([("key", Json::from(*key)), ("value", value)])
+        });
+
+        let status = match &self.error {
+            Some(message) => Json::object([
+                ("code", Json::from(STATUS_ERROR)),
+                ("message", Json::from(message.as_str())),
+            ]),
+            None => Json::object([("code", Json::from(STATUS_OK))]),
+        };
+
+        Json::object([
+            ("traceId", Json::from(trace_id)),
+            ("spanId", Json::from(self.span_id.as_str())),
+            ("parentSpanId", Json::from(self.parent_id.clone().unwrap_or_default())),
+            ("name", Json::from(self.name.as_str())),
+            ("kind", Json::from(self.kind)),
+            // 64-bit integers are encoded as strings in OTLP JSON
+            ("startTimeUnixNano", Json::from(start.to_string())),
+            ("endTimeUnixNano", Json::from(end.to_string())),
+            ("attributes", Json::Array(attributes.collect())),
+            ("status", status),
+        ])
+    }
+}
+
+/// Builds the OTLP JSON payload for the run, returning it along with the trace id.
+fn build(settings: &TracingSettings, report: &RunReport) -> (String, Json) {
+    let (trace_id, parent_id) = match env::var("TRACEPARENT").ok().as_deref().and_then(parse_traceparent) {
+        Some((trace_id, parent_id)) => (trace_id, Some(parent_id)),
+        None => (random_hex(16), None),
+    };
+
+    let root_id = random_hex(8);
+    let mut spans = vec![Span {
+        span_id: root_id.clone(),
+        parent_id,
+        name: "jrny embark".to_string(),
+        kind: SPAN_KIND_INTERNAL,
+        started_at: report.started_at,
+        elapsed: report.elapsed,
+        attributes: vec![
+            ("jrny.revisions.applied", Json::from(report.count(RevisionStatus::Applied))),
+            ("jrny.revisions.failed", Json::from(report.count(RevisionStatus::Failed))),
+            ("jrny.revisions.skipped", Json::from(report.count(RevisionStatus::Skipped))),
+        ]
+        .into_iter()
+        .chain(report.error_code.map(|code| ("jrny.error.code", Json::from(code))))
+        .collect(),
+        error: report.error.clone(),
+    }];
+
+    for revision in &report.revisions {
+        if revision.status == RevisionStatus::Skipped {
+            continue;
+        }
+
+        let revision_id = random_hex(8);
+        let failed = revision.status == RevisionStatus::Failed;
+
+        spans.push(Span {
+            span_id: revision_id.clone(),
+            parent_id: Some(root_id.clone()),
+            name: revision.filename.clone(),
+            kind: SPAN_KIND_INTERNAL,
+            started_at: revision.started_at,
+            elapsed: revision.elapsed,
+            attributes: vec![
+                ("jrny.revision", Json::from(revision.filename.as_str())),
+                ("jrny.statements", Json::from(revision.statements.len())),
+            ],
+            error: if failed { report.error.clone() } else { None },
+        });
+
+        for statement in &revision.statements {
+            let mut attributes = vec![
+                ("db.system", Json::from("postgresql")),
+                ("db.statement", Json::from(statement.summary.as_str())),
+                ("code.filepath", Json::from(revision.filename.as_str())),
+                ("code.lineno", Json::from(statement.line)),
+            ];
+
+            if let Some(rows) = statement.rows {
+                attributes.push(("db.rows_affected", Json::from(rows as i64)));
+            }
+
+            if let Some(code) = statement.error.as_ref().and_then(|e| e.code.clone()) {
+                attributes.push(("db.response.status_code", Json::from(code)));
+            }
+
+            spans.push(Span {
+                span_id: random_hex(8),
+                parent_id: Some(revision_id.clone()),
+                name: statement.summary.clone(),
+                kind: SPAN_KIND_CLIENT,
+                started_at: statement.started_at,
+                elapsed: statement.elapsed,
+                attributes,
+                error: statement.error.as_ref().map(|e| e.message.clone()),
+            });
+        }
+    }
+
+    let resource = Json::object([(
+        "attributes",
+        Json::Array(vec![Json::object([
+            ("key", Json::from("service.name")),
+            ("value", Json::object([("stringValue", Json::from(settings.service_name.as_str()))])),
+        ])]),
+    )]);
+
+    let scope = Json::object([
+        ("name", Json::from("jrny")),
+        ("version", Json::from(env!("CARGO_PKG_VERSION"))),
+    ]);
+
+    let payload = Json::object([(
+        "resourceSpans",
+        Json::Array(vec![Json::object([
+            ("resource", resource),
+            ("scopeSpans", Json::Array(vec![Json::object([
+                ("scope", scope),
+                ("spans", Json::Array(spans.iter().map(|s| s.to_json(&trace_id)).collect())),
+            ])])),
+        ])]),
+    )]);
+
+    (trace_id, payload)
+}
+
+/// Exports the trace for the run to the configured OTLP endpoint.
+pub fn export(settings: &TracingSettings, report: &RunReport) -> Result<()> {
+    let (trace_id, payload) = build(settings, report);
+
+    let endpoint = settings.endpoint.trim_end_matches('/');
+    let url = if endpoint.ends_with("/v1/traces") {
+        endpoint.to_string()
+    } else {
+        format!("{}/v1/traces", endpoint)
+    };
+
+    let mut headers: Vec<(&str, &str)> = vec![("Content-Type", "application/json")];
+    headers.extend(settings.headers.iter().map(|(k, v)| (k.as_str(), v.as_str())));
+
+    http::send_with_headers("POST", &url, &headers, &payload.to_string())?;
+    info!("Exported trace {}", trace_id);
+
+    Ok(())
+}
+
+/// Parses a W3C trace context header, eg. `00-<trace id>-<parent id>-01`.
+fn parse_traceparent(header: &str) -> Option<(String, String)> {
+    let parts: Vec<&str> = header.trim().split('-').collect();
+
+    match parts.as_slice() {
+        [_, trace_id, parent_id, _]
+            if trace_id.len() == 32
+                && parent_id.len() == 16
+                && trace_id.chars().chain(parent_id.chars()).all(|c| c.is_ascii_hexdigit()) =>
+        {
+            Some((trace_id.to_lowercase(), parent_id.to_lowercase()))
+        }
+        _ => None,
+    }
+}
+
+/// Generates a random id of the given number of bytes, hex-encoded.
+///
+/// This leans on the randomly-seeded hasher from the standard library,
+/// which is plenty for span ids, or naming scratch databases, and avoids
+/// depending on `rand`.
+pub fn random_hex(bytes: usize) -> String {
+    let mut hex = String::new();
+
+    while hex.len() < bytes * 2 {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_usize(hex.len());
+        hex.push_str(&format!("{:016x}", hasher.finish()));
+    }
+
+    hex.truncate(bytes * 2);
+    hex
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_traceparent_accepts_valid_header() {
+        assert_eq!(
+            parse_traceparent("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"),
+            Some((
+                "4bf92f3577b34da6a3ce929d0e0e4736".to_string(),
+                "00f067aa0ba902b7".to_string(),
+            )),
+        );
+    }
+
+    #[test]
+    fn parse_traceparent_rejects_invalid_header() {
+        assert_eq!(parse_traceparent("00-abc-def-01"), None);
+        assert_eq!(parse_traceparent("not a header"), None);
+    }
+}
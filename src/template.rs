@@ -0,0 +1,201 @@
+//! A minimal engine for the templates new revisions are planned from, eg.
+//!
+//! ```sql
+//! -- Revision: {{ name }}
+//! -- Planned {{ now "%Y-%m-%d" }} by {{ env "USER" }} for {{ team }}
+//!
+//! create table {{ snake_case name }} ();
+//! ```
+//!
+//! Each `{{ ... }}` is either a variable or a helper applied to arguments,
+//! which are themselves variables or `"quoted"` text. Anything unknown fails
+//! rather than being left in place, so mistakes don't end up in revisions.
+use std::{collections::HashMap, env, fmt::Write};
+
+use chrono::{DateTime, SecondsFormat, Utc};
+
+/// The variables a template can refer to, and the moment `now` gives.
+#[derive(Clone, Debug)]
+pub struct Context {
+    variables: HashMap<String, String>,
+    now: DateTime<Utc>,
+}
+
+impl Context {
+    pub fn new(now: DateTime<Utc>) -> Self {
+        Self { variables: HashMap::new(), now }
+    }
+
+    /// Adds the variable, replacing any already given by that name.
+    pub fn set(&mut self, name: &str, value: &str) -> &mut Self {
+        self.variables.insert(name.to_string(), value.to_string());
+        self
+    }
+
+    /// The template with each expression replaced by its value, or what's
+    /// wrong with the first that can't be.
+    pub fn render(&self, template: &str) -> Result<String, String> {
+        let mut rendered = String::new();
+        let mut rest = template;
+
+        while let Some(open) = rest.find("{{") {
+            rendered.push_str(&rest[..open]);
+
+            let line = template[..template.len() - rest.len() + open].matches('\n').count() + 1;
+            let close = rest[open..].find("}}").ok_or(format!("line {}: `{{{{` is never closed", line))?;
+            let expr = &rest[open + 2..open + close];
+
+            rendered.push_str(&self.evaluate(expr).map_err(|e| format!("line {}: {}", line, e))?);
+            rest = &rest[open + close + 2..];
+        }
+
+        rendered.push_str(rest);
+
+        Ok(rendered)
+    }
+
+    fn evaluate(&self, expr: &str) -> Result<String, String> {
+        let words = words(expr)?;
+
+        let (first, args) = match words.split_first() {
+            Some((Word::Name(first), args)) => (first.as_str(), args),
+            Some((Word::Text(text), [])) => return Ok(text.clone()),
+            _ => return Err(format!("`{{{{{}}}}}` should be a variable or helper", expr)),
+        };
+
+        let args = args.iter().map(|arg| self.value(arg)).collect::<Result<Vec<_>, _>>()?;
+        let arg = || match args.as_slice() {
+            [arg] => Ok(arg.as_str()),
+            _ => Err(format!("`{}` takes 1 argument, not {}", first, args.len())),
+        };
+
+        match first {
+            "now" => match args.as_slice() {
+                [] => Ok(self.now.to_rfc3339_opts(SecondsFormat::Secs, true)),
+                [format] => {
+                    let mut formatted = String::new();
+
+                    write!(formatted, "{}", self.now.format(format))
+                        .map_err(|_| format!("`{}` is not a valid format for `now`", format))?;
+
+                    Ok(formatted)
+                }
+                _ => Err(format!("`now` takes at most 1 argument, not {}", args.len())),
+            },
+            "env" => {
+                let name = arg()?;
+
+                env::var(name).map_err(|_| format!("environment variable `{}` is not set", name))
+            }
+            "snake_case" => Ok(snake_case(arg()?)),
+            "upper" => Ok(arg()?.to_uppercase()),
+            "lower" => Ok(arg()?.to_lowercase()),
+            name if args.is_empty() => self.value(&Word::Name(name.to_string())),
+            name => Err(format!("unknown helper `{}`", name)),
+        }
+    }
+
+    fn value(&self, word: &Word) -> Result<String, String> {
+        match word {
+            Word::Text(text) => Ok(text.clone()),
+            Word::Name(name) => self.variables.get(name).cloned().ok_or(format!("unknown variable `{}`", name)),
+        }
+    }
+}
+
+enum Word {
+    Name(String),
+    Text(String),
+}
+
+/// Splits an expression into names and quoted text.
+fn words(expr: &str) -> Result<Vec<Word>, String> {
+    let mut words = vec![];
+    let mut chars = expr.trim().chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            '"' => {
+                let text: String = chars.by_ref().take_while(|c| *c != '"').collect();
+                words.push(Word::Text(text));
+            }
+            c => {
+                let mut name = c.to_string();
+
+                while let Some(c) = chars.next_if(|c| !c.is_whitespace() && *c != '"') {
+                    name.push(c);
+                }
+                words.push(Word::Name(name));
+            }
+        }
+    }
+
+    if !expr.matches('"').count().is_multiple_of(2) {
+        return Err(format!("`{{{{{}}}}}` has an unclosed quote", expr));
+    }
+
+    Ok(words)
+}
+
+/// The text in lowercase with words separated by underscores, splitting on
+/// anything other than letters and digits as well as between `camelCase`.
+pub fn snake_case(text: &str) -> String {
+    let mut snake = String::new();
+    let mut previous: Option<char> = None;
+
+    for c in text.chars() {
+        if c.is_alphanumeric() {
+            let boundary = previous.is_some_and(|p| !p.is_alphanumeric() || (p.is_lowercase() && c.is_uppercase()));
+
+            if boundary && !snake.is_empty() {
+                snake.push('_');
+            }
+            snake.extend(c.to_lowercase());
+        }
+        previous = Some(c);
+    }
+
+    snake
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn context() -> Context {
+        let mut context = Context::new(Utc.timestamp(1_618_370_298, 0));
+        context.set("name", "addOrders-table").set("team", "payments");
+        context
+    }
+
+    #[test]
+    fn render_evaluates_variables_and_helpers() {
+        assert_eq!(
+            context()
+                .render("-- {{ name }} for {{team}} on {{ now \"%Y-%m-%d\" }}\n{{ upper team }} {{ snake_case name }} {{ \"{{\" }}")
+                .unwrap(),
+            "-- addOrders-table for payments on 2021-04-14\nPAYMENTS add_orders_table {{",
+        );
+        assert_eq!(context().render("{{ now }}").unwrap(), "2021-04-14T03:18:18Z");
+    }
+
+    #[test]
+    fn render_fails_on_anything_unknown() {
+        assert_eq!(context().render("ok\n{{ nmae }}").unwrap_err(), "line 2: unknown variable `nmae`");
+        assert_eq!(context().render("{{ shout name }}").unwrap_err(), "line 1: unknown helper `shout`");
+        assert_eq!(context().render("{{ upper }}").unwrap_err(), "line 1: `upper` takes 1 argument, not 0");
+        assert!(context().render("{{ name ").unwrap_err().contains("never closed"));
+        assert!(context().render("{{ \"name }}").unwrap_err().contains("unclosed quote"));
+        assert!(context().render("{{ now \"%Q\" }}").unwrap_err().contains("not a valid format"));
+    }
+
+    #[test]
+    fn snake_case_splits_words() {
+        assert_eq!(snake_case("AddOrders"), "add_orders");
+        assert_eq!(snake_case("add-orders table"), "add_orders_table");
+        assert_eq!(snake_case("ordersByUser_id"), "orders_by_user_id");
+    }
+}
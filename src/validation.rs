@@ -0,0 +1,153 @@
+//! Adding a foreign key or check constraint without holding a heavy lock on
+//! its table while every existing row is checked, as marked by a directive
+//! comment directly before it:
+//!
+//! ```sql
+//! -- jrny:validate-separately
+//! alter table orders add constraint orders_user_id_fkey foreign key (user_id) references users (id);
+//! ```
+//!
+//! The constraint is added as `NOT VALID`, which only checks rows written
+//! from then on and so only briefly holds its lock, and then validated with
+//! `VALIDATE CONSTRAINT` once the revision's transaction is committed, which
+//! checks existing rows without blocking writes. In a revision run without a
+//! transaction, it's validated as soon as it's added.
+use crate::{
+    analysis::added_constraint,
+    rewrite::cased,
+    statements::{strip_comments, Statement},
+};
+
+/// Names the directive
+pub const DIRECTIVE: &str = "validate-separately";
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct SeparateValidation {
+    /// The table the constraint is added to, as written
+    pub table: String,
+    pub constraint: String,
+}
+
+impl SeparateValidation {
+    /// The separate validation asked for by a directive in the statement's
+    /// comments, if any.
+    pub fn of(statement: &Statement) -> Result<Option<Self>, String> {
+        if !statement.has_directive(DIRECTIVE) {
+            return Ok(None);
+        }
+
+        let (table, constraint) = added_constraint(statement)?;
+
+        Ok(Some(Self { table, constraint }))
+    }
+
+    /// The statement adding the constraint without validating it. `NOT
+    /// VALID` goes on a line of its own, after any trailing comment.
+    pub fn add(&self, statement: &Statement) -> Statement {
+        Statement {
+            sql: format!("{}\n{}", statement.sql.trim_end(), cased(&strip_comments(&statement.sql), "NOT VALID")),
+            line: statement.line,
+        }
+    }
+
+    /// The statement validating the constraint once it's been added.
+    pub fn validate(&self, statement: &Statement) -> Statement {
+        let sql = strip_comments(&statement.sql);
+
+        Statement {
+            sql: format!(
+                "{} {} {} {}",
+                cased(&sql, "ALTER TABLE"),
+                self.table,
+                cased(&sql, "VALIDATE CONSTRAINT"),
+                self.constraint,
+            ),
+            line: statement.line,
+        }
+    }
+}
+
+/// The statements with any constraints to be validated separately added as
+/// `NOT VALID` instead, along with the statements validating them, in order.
+/// Inline, each is validated directly after being added rather than left to
+/// run after the rest. Gives the line of any invalid directive, and why.
+pub fn separate(statements: &[Statement], inline: bool) -> Result<(Vec<Statement>, Vec<Statement>), (usize, String)> {
+    let mut run = vec![];
+    let mut validations = vec![];
+
+    for statement in statements {
+        let separate = SeparateValidation::of(statement).map_err(|reason| (statement.line, reason))?;
+
+        match separate {
+            Some(separate) if inline => {
+                run.push(separate.add(statement));
+                run.push(separate.validate(statement));
+            }
+            Some(separate) => {
+                run.push(separate.add(statement));
+                validations.push(separate.validate(statement));
+            }
+            None => run.push(Statement {
+                sql: statement.sql.clone(),
+                line: statement.line,
+            }),
+        }
+    }
+
+    Ok((run, validations))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::statements::split;
+
+    #[test]
+    fn constraints_are_added_as_not_valid_and_validated_after() {
+        let statements = split(
+            "-- jrny:validate-separately\n\
+             alter table only orders add constraint \"Orders_user\" foreign key (user_id) references users (id);\n\
+             alter table orders add column note text;\n\
+             -- jrny:validate-separately\n\
+             ALTER TABLE orders ADD CONSTRAINT \"Positive\" CHECK (total > 0) -- no refunds\n;",
+        );
+
+        let (run, validations) = separate(&statements, false).unwrap();
+        let sql = |statements: &[Statement]| statements.iter().map(|s| s.sql.clone()).collect::<Vec<_>>();
+
+        assert_eq!(
+            sql(&run),
+            vec![
+                "-- jrny:validate-separately\nalter table only orders add constraint \"Orders_user\" foreign key (user_id) references users (id)\nnot valid",
+                "alter table orders add column note text",
+                "-- jrny:validate-separately\nALTER TABLE orders ADD CONSTRAINT \"Positive\" CHECK (total > 0) -- no refunds\nNOT VALID",
+            ],
+        );
+        assert_eq!(
+            sql(&validations),
+            vec![
+                "alter table orders validate constraint \"Orders_user\"",
+                "ALTER TABLE orders VALIDATE CONSTRAINT \"Positive\"",
+            ],
+        );
+        assert_eq!(separate(&statements, true).unwrap().0.len(), 5);
+    }
+
+    #[test]
+    fn only_single_named_constraints_are_validated_separately() {
+        let invalid = [
+            "alter table orders add foreign key (user_id) references users (id)",
+            "alter table orders add constraint orders_positive check (total > 0) not valid",
+            "alter table orders add constraint orders_pkey primary key (id)",
+            "alter table orders add constraint a check (x > 0), add constraint b check (y > 0)",
+            "create index on orders (user_id)",
+        ];
+
+        for sql in invalid {
+            let statement = &split(&format!("-- jrny:validate-separately\n{}", sql))[0];
+            assert!(SeparateValidation::of(statement).is_err(), "{}", sql);
+        }
+
+        assert_eq!(SeparateValidation::of(&split("alter table orders add constraint a check (x > 0)")[0]), Ok(None));
+    }
+}
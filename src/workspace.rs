@@ -0,0 +1,220 @@
+//! Workspaces, for repositories holding more than one jrny project, each in
+//! a directory of its own with its own config and environment files. They're
+//! listed in a workspace file at the root of the repository:
+//!
+//! ```toml
+//! # jrny-workspace.toml
+//! projects = ["services/billing", "services/accounts"]
+//! ```
+//!
+//! Where projects share a database, a revision can declare that it must be
+//! applied after those of other projects, as `project:name`, with `after` in
+//! its metadata. Applying every project's revisions at once then orders them
+//! all accordingly.
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
+use serde::Deserialize;
+
+use crate::{settings::Problem, Error, Result};
+
+/// The name of the workspace file
+pub const WORKSPACE: &str = "jrny-workspace.toml";
+
+/// The pending revisions of a project by name, in order, each along with the
+/// revisions it must be applied after, as `project:name`.
+pub type Pending<'a> = Vec<(&'a str, &'a [String])>;
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Workspace {
+    /// The directory holding the workspace file, which projects are relative to
+    #[serde(skip)]
+    pub root: PathBuf,
+    /// The directory of each project, relative to the workspace file
+    pub projects: Vec<PathBuf>,
+}
+
+impl Workspace {
+    /// Finds the workspace file in the directory or the nearest above it,
+    /// if there is one.
+    pub fn find(dir: &Path) -> Result<Option<Self>> {
+        match dir.ancestors().map(|dir| dir.join(WORKSPACE)).find(|path| path.is_file()) {
+            Some(path) => Self::from_filepath(&path).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    pub fn from_filepath(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)?;
+        let settings: toml::Value = toml::from_str(&contents)
+            .map_err(|e| Error::TomlInvalid(e, path.display().to_string()))?;
+        let mut workspace: Self = settings
+            .try_into()
+            .map_err(|e| Problem::from_toml(&e).into_error(path, &[(path, &contents)]))?;
+
+        workspace.root = path.parent().unwrap().to_path_buf();
+
+        Ok(workspace)
+    }
+
+    /// The path of the workspace file.
+    pub fn filepath(&self) -> PathBuf {
+        self.root.join(WORKSPACE)
+    }
+
+    /// The directory of the project, given as it's listed in the workspace
+    /// file, if it's listed.
+    pub fn project_dir(&self, project: &Path) -> Option<PathBuf> {
+        self.projects
+            .iter()
+            .find(|listed| same_path(listed, project))
+            .map(|listed| self.root.join(listed))
+    }
+
+    /// Orders the pending revisions of every project, given in the order the
+    /// projects are listed along with the names of those already applied, so
+    /// that each follows those before it in its own project as well as those
+    /// it must be applied after. Each revision in turn is taken from the first
+    /// project listed with one ready to apply. Returns which project each
+    /// revision in the order belongs to.
+    pub fn order(&self, pending: &[Pending], applied: &[HashSet<String>]) -> std::result::Result<Vec<usize>, String> {
+        let mut waits = vec![];
+
+        for (project, revisions) in self.projects.iter().zip(pending) {
+            let mut project_waits = vec![];
+
+            for (name, after) in revisions {
+                let revision = format!("{}:{}", project.display(), name);
+                let resolved = after
+                    .iter()
+                    .map(|after| {
+                        let (required_project, required) = after
+                            .rsplit_once(':')
+                            .ok_or_else(|| format!("`{}` must be after `project:name` rather than `{}`", revision, after))?;
+                        let q = self
+                            .projects
+                            .iter()
+                            .position(|listed| same_path(listed, Path::new(required_project)))
+                            .ok_or_else(|| format!("`{}` must be after `{}`, whose project isn't listed", revision, after))?;
+
+                        if !applied[q].contains(required) && !pending[q].iter().any(|(pending, _)| *pending == required) {
+                            return Err(format!("`{}` must be after `{}`, which is neither applied nor pending", revision, after));
+                        }
+
+                        Ok((q, required))
+                    })
+                    .collect::<std::result::Result<Vec<_>, String>>()?;
+
+                project_waits.push(resolved);
+            }
+
+            waits.push(project_waits);
+        }
+
+        let total = pending.iter().map(Vec::len).sum();
+        let mut next = vec![0; pending.len()];
+        let mut order = vec![];
+
+        while order.len() < total {
+            let done = |q: usize, name: &str| {
+                applied[q].contains(name) || pending[q][..next[q]].iter().any(|(pending, _)| *pending == name)
+            };
+            let ready = (0..pending.len())
+                .find(|&p| next[p] < pending[p].len() && waits[p][next[p]].iter().all(|&(q, name)| done(q, name)));
+
+            match ready {
+                Some(p) => {
+                    order.push(p);
+                    next[p] += 1;
+                }
+                None => {
+                    let blocked: Vec<_> = (0..pending.len())
+                        .filter(|&p| next[p] < pending[p].len())
+                        .map(|p| format!("`{}:{}`", self.projects[p].display(), pending[p][next[p]].0))
+                        .collect();
+
+                    return Err(format!("{} must each be after another", blocked.join(", ")));
+                }
+            }
+        }
+
+        Ok(order)
+    }
+}
+
+/// Whether the relative paths are the same, ignoring any `.` in either.
+fn same_path(a: &Path, b: &Path) -> bool {
+    let a = a.components().filter(|component| *component != Component::CurDir);
+    let b = b.components().filter(|component| *component != Component::CurDir);
+
+    a.eq(b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn workspace() -> Workspace {
+        Workspace {
+            root: PathBuf::from("/repo"),
+            projects: vec![PathBuf::from("billing"), PathBuf::from("accounts")],
+        }
+    }
+
+    fn after(revisions: &[&str]) -> Vec<String> {
+        revisions.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn revisions_are_ordered_after_other_projects() {
+        let (none, invoices, payments) = (after(&[]), after(&["accounts:add_users"]), after(&["accounts:add_cards"]));
+        let pending = [
+            vec![("add_invoices", &invoices[..]), ("add_payments", &payments[..])],
+            vec![("add_users", &none[..]), ("add_cards", &none[..])],
+        ];
+        let applied = [HashSet::new(), HashSet::new()];
+
+        assert_eq!(workspace().order(&pending, &applied), Ok(vec![1, 0, 1, 0]));
+
+        let applied = [HashSet::new(), ["add_users".to_string(), "add_cards".to_string()].into_iter().collect()];
+        let pending = [pending[0].clone(), vec![]];
+
+        assert_eq!(workspace().order(&pending, &applied), Ok(vec![0, 0]));
+    }
+
+    #[test]
+    fn unknown_or_circular_revisions_are_rejected() {
+        let (users, invoices, unknown) = (after(&["billing:add_invoices"]), after(&["accounts:add_users"]), after(&["search:add_index"]));
+        let applied = [HashSet::new(), HashSet::new()];
+
+        let circular = [vec![("add_invoices", &invoices[..])], vec![("add_users", &users[..])]];
+        assert!(workspace().order(&circular, &applied).is_err());
+
+        let missing = [vec![("add_invoices", &invoices[..])], vec![]];
+        assert!(workspace().order(&missing, &applied).is_err());
+
+        let unlisted = [vec![("add_invoices", &unknown[..])], vec![]];
+        assert!(workspace().order(&unlisted, &applied).is_err());
+    }
+
+    #[test]
+    fn projects_are_found_as_listed() {
+        let mut workspace: Workspace = toml::from_str("projects = [\"services/billing\", \"./services/accounts/\"]").unwrap();
+        workspace.root = PathBuf::from("/repo");
+
+        assert_eq!(
+            workspace.project_dir(Path::new("./services/billing/")),
+            Some(PathBuf::from("/repo/services/billing")),
+        );
+        assert_eq!(
+            workspace.project_dir(Path::new("services/accounts")),
+            Some(PathBuf::from("/repo/services/accounts")),
+        );
+        assert_eq!(workspace.project_dir(Path::new("services")), None);
+        assert!(toml::from_str::<Workspace>("project = [\"services/billing\"]").is_err());
+    }
+}